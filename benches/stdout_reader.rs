@@ -0,0 +1,76 @@
+//! Benchmark for reading CLI stdout.
+//!
+//! Compares the buffer-reusing `JsonLineReader` against the previous
+//! `lines()` + `serde_json::from_str` approach on a stream dominated by
+//! partial-message events.
+//!
+//! Run with `cargo bench --bench stdout_reader`.
+
+use claude_agents_sdk::_internal::transport::JsonLineReader;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use tokio::io::AsyncBufReadExt;
+
+const EVENTS: usize = 10_000;
+
+fn partial_message_stream() -> Vec<u8> {
+    let mut data = Vec::new();
+    for i in 0..EVENTS {
+        let event = serde_json::json!({
+            "type": "stream_event",
+            "uuid": format!("evt-{}", i),
+            "session_id": "bench-session",
+            "event": {
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": {"type": "text_delta", "text": "The quick brown fox jumps over the lazy dog. "}
+            }
+        });
+        serde_json::to_writer(&mut data, &event).unwrap();
+        data.push(b'\n');
+    }
+    data
+}
+
+fn bench_stdout_reader(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let data = partial_message_stream();
+
+    let mut group = c.benchmark_group("stdout_reader");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+
+    group.bench_function("lines_from_str", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let mut lines = tokio::io::BufReader::new(data.as_slice()).lines();
+                let mut count = 0;
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+                    black_box(value);
+                    count += 1;
+                }
+                assert_eq!(count, EVENTS);
+            })
+        })
+    });
+
+    group.bench_function("json_line_reader", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let mut reader = JsonLineReader::new(data.as_slice(), 64 * 1024);
+                let mut count = 0;
+                while let Some(value) = reader.next_value().await {
+                    black_box(value.unwrap());
+                    count += 1;
+                }
+                assert_eq!(count, EVENTS);
+            })
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_stdout_reader);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+//! Example demonstrating the code reviewer preset with structured output.
+//!
+//! Run with: cargo run --example code_review -- [repo_path]
+
+use claude_agents_sdk::presets::{self, ReviewReport, ReviewSeverity};
+use claude_agents_sdk::query_result;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let repo_path = std::env::args().nth(1).unwrap_or_else(|| ".".to_string());
+    println!("=== Reviewing {} ===", repo_path);
+
+    let options = presets::code_reviewer(&repo_path)
+        .with_max_turns(20)
+        .with_timeout_secs(600);
+
+    let (_, result) = query_result(
+        "Review the Rust sources in this repository and report your findings.",
+        Some(options),
+    )
+    .await?;
+
+    let report = ReviewReport::from_result(&result)?;
+    println!("Summary: {}", report.summary);
+
+    for finding in report.at_least(ReviewSeverity::Minor) {
+        let location = match finding.line {
+            Some(line) => format!("{}:{}", finding.file, line),
+            None => finding.file.clone(),
+        };
+        println!("[{:?}] {} - {}", finding.severity, location, finding.title);
+        if let Some(ref suggestion) = finding.suggestion {
+            println!("    Suggestion: {}", suggestion);
+        }
+    }
+
+    if let Some(cost) = result.total_cost_usd {
+        println!("\nCost: ${:.4}", cost);
+    }
+
+    Ok(())
+}
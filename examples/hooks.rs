@@ -115,19 +115,11 @@ async fn example_basic_hooks() -> Result<(), Box<dyn std::error::Error>> {
     let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
     hooks.insert(
         HookEvent::PreToolUse,
-        vec![HookMatcher {
-            matcher: None, // Match all tools
-            hooks: vec![logging_hook()],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![logging_hook()])],
     );
     hooks.insert(
         HookEvent::PostToolUse,
-        vec![HookMatcher {
-            matcher: None,
-            hooks: vec![logging_hook()],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![logging_hook()])],
     );
 
     let mut options = ClaudeAgentOptions::new();
@@ -158,11 +150,7 @@ async fn example_security_hooks() -> Result<(), Box<dyn std::error::Error>> {
     let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
     hooks.insert(
         HookEvent::PreToolUse,
-        vec![HookMatcher {
-            matcher: Some("Bash".to_string()),
-            hooks: vec![security_hook()],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![security_hook()]).with_matcher("Bash")],
     );
 
     let mut options = ClaudeAgentOptions::new();
@@ -3,8 +3,7 @@
 //!
 //! Run with: cargo run --example max_budget_usd
 
-use claude_agents_sdk::{query, ClaudeAgentOptions, ContentBlock, Message};
-use tokio_stream::StreamExt;
+use claude_agents_sdk::prelude::*;
 
 /// Example without budget limit.
 async fn without_budget() -> Result<(), Box<dyn std::error::Error>> {
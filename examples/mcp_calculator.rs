@@ -42,7 +42,7 @@ fn display_message(msg: &Message) {
                                 .content
                                 .as_ref()
                                 .map(|c| {
-                                    let s = c.to_string();
+                                    let s = c.as_text().unwrap_or_else(|| c.to_value().to_string());
                                     if s.len() > 100 {
                                         format!("{}...", &s[..100])
                                     } else {
@@ -7,8 +7,7 @@
 //!
 //! Run with: cargo run --example quick_start
 
-use claude_agents_sdk::{query, ClaudeAgentOptions, ContentBlock, Message};
-use tokio_stream::StreamExt;
+use claude_agents_sdk::prelude::*;
 
 /// Basic example - simple question.
 async fn basic_example() -> Result<(), Box<dyn std::error::Error>> {
@@ -5,8 +5,7 @@
 //!
 //! Run with: cargo run --example simple_query
 
-use claude_agents_sdk::{query, ClaudeAgentOptions, Message};
-use tokio_stream::StreamExt;
+use claude_agents_sdk::prelude::*;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -5,8 +5,7 @@
 //!
 //! Run with: cargo run --example streaming_client
 
-use claude_agents_sdk::{ClaudeAgentOptions, ClaudeClient, Message, PermissionMode};
-use tokio_stream::StreamExt;
+use claude_agents_sdk::prelude::*;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -248,7 +248,8 @@ async fn example_bash_command() -> Result<(), Box<dyn std::error::Error>> {
                                     .content
                                     .as_ref()
                                     .map(|c| {
-                                        let s = c.to_string();
+                                        let s =
+                                            c.as_text().unwrap_or_else(|| c.to_value().to_string());
                                         if s.len() > 100 {
                                             format!("{}...", &s[..100])
                                         } else {
@@ -0,0 +1,38 @@
+//! Example of reviewing tool results before they reach the model.
+//!
+//! Redacts a secret from every tool result and truncates long Bash output.
+//!
+//! Run with: cargo run --example tool_result_filter
+
+use claude_agents_sdk::hooks::{
+    redact_tool_result, truncate_tool_result, HookRegistry, ToolResultDecision,
+};
+use claude_agents_sdk::prelude::*;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let secret = std::env::var("DEMO_SECRET").unwrap_or_else(|_| "s3cr3t-demo-value".to_string());
+
+    let hooks = HookRegistry::new()
+        .filter_tool_results(move |input| {
+            let decision = redact_tool_result(&input.tool_response, &[&secret]);
+            if decision != ToolResultDecision::Pass {
+                println!("[filter] Redacted a secret from {} output", input.tool_name);
+            }
+            decision
+        })
+        .filter_tool_results_matching("Bash", |input| {
+            truncate_tool_result(&input.tool_response, 2_000)
+        });
+
+    let options = ClaudeAgentOptions::new()
+        .with_hooks(hooks)
+        .with_allowed_tools(vec!["Bash".to_string()])
+        .with_max_turns(3);
+
+    let (response, _) =
+        query_result("Run `env | sort` and summarize the output.", Some(options)).await?;
+    println!("{}", response);
+
+    Ok(())
+}
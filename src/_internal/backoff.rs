@@ -0,0 +1,52 @@
+//! Exponential backoff between retries.
+
+use std::time::Duration;
+
+/// The delay before retry number `attempt` (starting at 1): `initial`,
+/// doubling with each retry up to `max`.
+pub(crate) fn exponential(initial: Duration, max: Duration, attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(31);
+    initial.saturating_mul(1 << exponent).min(max)
+}
+
+/// `delay` with a random `jitter` fraction of it taken off: between
+/// `(1 - jitter) * delay` and `delay`.
+pub(crate) fn jittered(delay: Duration, jitter: f64) -> Duration {
+    delay.mul_f64(1.0 - jitter.clamp(0.0, 1.0) * random_fraction())
+}
+
+/// A random number in `[0, 1)`, without a dependency on `rand`.
+fn random_fraction() -> f64 {
+    // The first 48 bits of a v4 UUID are random; the version and variant
+    // bits come later
+    let bits = (uuid::Uuid::new_v4().as_u128() >> 80) as u64;
+    bits as f64 / (1u64 << 48) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let delays = (1..=5)
+            .map(|n| exponential(Duration::from_secs(1), Duration::from_secs(5), n).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+        assert_eq!(
+            exponential(Duration::from_secs(1), Duration::MAX, u32::MAX),
+            Duration::from_secs(1 << 31)
+        );
+
+        // Spread over the whole range, not a slice of it
+        let fractions = (0..2000).map(|_| random_fraction()).collect::<Vec<_>>();
+        assert!(fractions.iter().all(|f| (0.0..1.0).contains(f)));
+        assert!(fractions.iter().any(|&f| f < 0.25));
+        assert!(fractions.iter().any(|&f| f >= 0.75));
+
+        let delay = Duration::from_secs(10);
+        assert_eq!(jittered(delay, 0.0), delay);
+        let jittered = jittered(delay, 0.5);
+        assert!(jittered > Duration::from_secs(5) && jittered <= delay);
+    }
+}
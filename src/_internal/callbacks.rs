@@ -0,0 +1,303 @@
+//! Execution of user callbacks off the reader task.
+//!
+//! Permission, hook and MCP callbacks can take arbitrarily long. Each
+//! control request is answered on its own task in a [`CallbackPool`], and
+//! the response goes over a channel to a task writing it to the CLI, so
+//! messages keep flowing while a callback runs or the CLI is slow to read
+//! its answers. Requests sharing an ordering key — the
+//! callbacks of one tool use, the hooks not tied to a tool use, or the
+//! messages of one MCP server — still run one at a time, in the order they
+//! arrived.
+//!
+//! Prompts are written from the caller's task, so a [`ResponseQueue`]
+//! orders them against the responses: a response queued before a prompt is
+//! sent is written before it. The CLI then sees the answers it was given
+//! before the next prompt, as it would if both were written by one task.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinSet;
+use tracing::Instrument;
+
+use crate::errors::Result;
+use crate::protocol::ControlRequestPayload;
+
+/// The answer to a control request, ready to be sent to the CLI.
+pub(crate) struct Completion {
+    pub(crate) request_id: String,
+    pub(crate) response: Result<Value>,
+}
+
+/// Responses waiting to be written to the CLI, in the order they completed.
+pub(crate) struct ResponseQueue {
+    /// Number of responses queued, locked with the sender so the channel
+    /// holds them in that order.
+    queued: Mutex<(u64, mpsc::UnboundedSender<Completion>)>,
+    /// Number of responses the writer is done with.
+    written: watch::Receiver<u64>,
+}
+
+/// The receiving end of a [`ResponseQueue`], owned by the task writing the
+/// responses.
+pub(crate) struct ResponseWriter {
+    rx: mpsc::UnboundedReceiver<Completion>,
+    written: watch::Sender<u64>,
+}
+
+impl ResponseQueue {
+    /// Create a queue and the writer its responses go to.
+    pub(crate) fn new() -> (Arc<Self>, ResponseWriter) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (written_tx, written) = watch::channel(0);
+        let queue = Self {
+            queued: Mutex::new((0, tx)),
+            written,
+        };
+        let writer = ResponseWriter {
+            rx,
+            written: written_tx,
+        };
+        (Arc::new(queue), writer)
+    }
+
+    /// Queue `completion` to be written.
+    pub(crate) fn push(&self, completion: Completion) {
+        let mut queued = self.queued.lock().unwrap_or_else(|e| e.into_inner());
+        if queued.1.send(completion).is_ok() {
+            queued.0 += 1;
+        }
+    }
+
+    /// Wait until the responses queued so far are written, or the writer
+    /// is gone. Responses queued after the call aren't waited for.
+    pub(crate) fn flushed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let target = self.queued.lock().unwrap_or_else(|e| e.into_inner()).0;
+        let mut written = self.written.clone();
+        async move {
+            // Fails once the writer is gone: nothing will be written then
+            let _ = written.wait_for(|written| *written >= target).await;
+        }
+    }
+}
+
+impl ResponseWriter {
+    /// The next response to write, or `None` once the queue is gone and
+    /// empty.
+    pub(crate) async fn next(&mut self) -> Option<Completion> {
+        self.rx.recv().await
+    }
+
+    /// Count the last response from [`next`](Self::next) as written,
+    /// whether or not writing it succeeded.
+    pub(crate) fn written(&mut self) {
+        self.written.send_modify(|written| *written += 1);
+    }
+}
+
+/// Signals the end of the last job queued for an ordering key.
+struct Tail {
+    job: u64,
+    finished: oneshot::Receiver<()>,
+}
+
+/// Tasks answering control requests.
+///
+/// Dropping the pool aborts the callbacks still running.
+pub(crate) struct CallbackPool {
+    tasks: JoinSet<()>,
+    tails: Arc<Mutex<HashMap<String, Tail>>>,
+    next_job: u64,
+    responses: Arc<ResponseQueue>,
+}
+
+/// Key of the requests that must be answered in arrival order, if any.
+pub(crate) fn ordering_key(request: &ControlRequestPayload) -> Option<String> {
+    match request {
+        ControlRequestPayload::CanUseTool {
+            tool_use_id: Some(id),
+            ..
+        }
+        | ControlRequestPayload::HookCallback {
+            tool_use_id: Some(id),
+            ..
+        } => Some(format!("tool_use:{}", id)),
+        // Session-level hooks, such as `UserPromptSubmit` or `Stop`
+        ControlRequestPayload::HookCallback {
+            tool_use_id: None, ..
+        } => Some("session_hooks".to_string()),
+        ControlRequestPayload::McpMessage { server_name, .. } => {
+            Some(format!("mcp:{}", server_name))
+        }
+        _ => None,
+    }
+}
+
+impl CallbackPool {
+    /// Create a pool queueing its responses on `responses`.
+    pub(crate) fn new(responses: Arc<ResponseQueue>) -> Self {
+        Self {
+            tasks: JoinSet::new(),
+            tails: Arc::new(Mutex::new(HashMap::new())),
+            next_job: 0,
+            responses,
+        }
+    }
+
+    /// Answer `request_id` with the output of `job` on a new task, after
+    /// the jobs queued earlier under the same `key` have finished.
+    pub(crate) fn spawn<F>(&mut self, request_id: String, key: Option<String>, job: F)
+    where
+        F: Future<Output = Result<Value>> + Send + 'static,
+    {
+        // Reap finished tasks so the set doesn't grow with the session
+        while self.tasks.try_join_next().is_some() {}
+
+        let order = key.map(|key| {
+            self.next_job += 1;
+            let (finished_tx, finished) = oneshot::channel();
+            let tail = Tail {
+                job: self.next_job,
+                finished,
+            };
+            let previous = self
+                .tails
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.clone(), tail);
+            (key, self.next_job, previous, finished_tx)
+        });
+
+        let tails = Arc::clone(&self.tails);
+        let responses = Arc::clone(&self.responses);
+        self.tasks.spawn(
+            async move {
+                let (key, job_id, previous, finished_tx) = match order {
+                    Some((key, job_id, previous, finished_tx)) => {
+                        (Some(key), job_id, previous, Some(finished_tx))
+                    }
+                    None => (None, 0, None, None),
+                };
+                if let Some(previous) = previous {
+                    // An aborted predecessor drops its sender, which also counts
+                    let _ = previous.finished.await;
+                }
+
+                let response = job.await;
+                responses.push(Completion {
+                    request_id,
+                    response,
+                });
+
+                if let (Some(key), Some(finished_tx)) = (key, finished_tx) {
+                    let _ = finished_tx.send(());
+                    let mut tails = tails.lock().unwrap_or_else(|e| e.into_inner());
+                    if tails.get(&key).is_some_and(|tail| tail.job == job_id) {
+                        tails.remove(&key);
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// Whether a control request is still being answered.
+    pub(crate) fn is_busy(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+
+    /// Wait for the next answer to finish.
+    pub(crate) async fn finished(&mut self) {
+        self.tasks.join_next().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn answer(ms: u64, value: &str) -> Result<Value> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        Ok(Value::from(value))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_jobs_run_concurrently_except_within_a_key() {
+        let (responses, mut writer) = ResponseQueue::new();
+        let mut pool = CallbackPool::new(responses);
+        let key = || Some("tool_use:toolu_1".to_string());
+        pool.spawn("slow".to_string(), key(), answer(100, "slow"));
+        pool.spawn("after_slow".to_string(), key(), answer(1, "after_slow"));
+        pool.spawn("free".to_string(), None, answer(10, "free"));
+
+        let mut order = Vec::new();
+        for _ in 0..3 {
+            order.push(writer.next().await.unwrap().request_id);
+        }
+        // The unkeyed job overtakes the slow one; the keyed one waits for it
+        assert_eq!(order, ["free", "slow", "after_slow"]);
+        assert!(pool.tails.lock().unwrap().is_empty());
+    }
+
+    fn completion(request_id: &str) -> Completion {
+        Completion {
+            request_id: request_id.to_string(),
+            response: Ok(Value::Null),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flushed_waits_for_responses_queued_before() {
+        use futures::FutureExt;
+
+        let (queue, mut writer) = ResponseQueue::new();
+        queue.flushed().await;
+
+        queue.push(completion("a"));
+        queue.push(completion("b"));
+        let mut flushed = Box::pin(queue.flushed());
+        // Not waited for
+        queue.push(completion("c"));
+
+        for id in ["a", "b"] {
+            assert!((&mut flushed).now_or_never().is_none());
+            assert_eq!(writer.next().await.unwrap().request_id, id);
+            writer.written();
+        }
+        assert!(flushed.now_or_never().is_some());
+        assert!(queue.flushed().now_or_never().is_none());
+
+        // A writer that is gone holds nothing up
+        drop(writer);
+        queue.flushed().await;
+    }
+
+    #[test]
+    fn test_ordering_key() {
+        let hook = ControlRequestPayload::HookCallback {
+            callback_id: "hook_0".to_string(),
+            input: Value::Null,
+            tool_use_id: Some("toolu_1".to_string()),
+        };
+        assert_eq!(ordering_key(&hook).as_deref(), Some("tool_use:toolu_1"));
+        let session_hook = ControlRequestPayload::HookCallback {
+            callback_id: "hook_1".to_string(),
+            input: Value::Null,
+            tool_use_id: None,
+        };
+        assert_eq!(
+            ordering_key(&session_hook).as_deref(),
+            Some("session_hooks")
+        );
+        let mcp = ControlRequestPayload::McpMessage {
+            server_name: "calc".to_string(),
+            message: Value::Null,
+        };
+        assert_eq!(ordering_key(&mcp).as_deref(), Some("mcp:calc"));
+        assert_eq!(ordering_key(&ControlRequestPayload::Interrupt), None);
+    }
+}
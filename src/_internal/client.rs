@@ -3,17 +3,205 @@
 //! This module provides the core query processing logic used by both
 //! the one-shot `query()` function and the streaming `ClaudeClient`.
 
+#[cfg(feature = "subprocess")]
+use std::borrow::Cow;
+use std::path::Path;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use tokio::sync::mpsc;
+use std::time::Duration;
+
+use tokio::io::AsyncRead;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::Instant;
 use tokio_stream::Stream;
-use tracing::{debug, info};
+use tracing::{debug, info, warn, Instrument};
 
-use super::query::Query;
-use super::transport::{SubprocessTransport, Transport};
-use crate::errors::{ClaudeSDKError, Result};
+use super::escalation::EscalationSlot;
+use super::query::{ControlSlot, Query};
+use super::tmp_dir::SessionTmpDir;
+#[cfg(feature = "subprocess")]
+use super::transport::SubprocessTransport;
+use super::transport::{Transport, TransportHandle};
+use super::{with_deadline, AUDIT_TARGET};
+use crate::diagnostics::{
+    Diagnostic, DiagnosticReceiver, DiagnosticSeverity, DIAGNOSTICS_CAPACITY,
+};
+#[cfg(feature = "subprocess")]
+use crate::errors::{CLILaunchError, CLILaunchErrorKind};
+use crate::errors::{ClaudeSDKError, Result, TimeoutPhase};
+use crate::progress::Progress;
+use crate::testing::MockTransport;
 use crate::types::*;
 
+/// Code of the diagnostic published when falling back on another transport.
+#[cfg(feature = "subprocess")]
+const TRANSPORT_FAILOVER: &str = "transport_failover";
+
+/// When connecting must be done by: the earlier of the overall deadline and
+/// the connect timeout from now.
+fn connect_deadline(options: &ClaudeAgentOptions) -> Option<Instant> {
+    let timeout = options
+        .timeouts
+        .connect
+        .map(|timeout| Instant::now() + timeout);
+    match (options.deadline.map(Instant::from_std), timeout) {
+        (Some(deadline), Some(timeout)) => Some(deadline.min(timeout)),
+        (deadline, timeout) => deadline.or(timeout),
+    }
+}
+
+/// Create and connect the transport for `options`: the echo model's for
+/// [`Model::mock`], or else the CLI's.
+async fn connect_transport(
+    options: &ClaudeAgentOptions,
+    initial_prompt: Option<&str>,
+    diagnostics: Option<&broadcast::Sender<Arc<Diagnostic>>>,
+    deadline: Option<Instant>,
+) -> Result<TransportHandle> {
+    if Model::is_mock(options.model.as_deref()) {
+        debug!("Starting echo model");
+        let mut transport = MockTransport::echo_model(initial_prompt);
+        transport.connect().await?;
+        return Ok(TransportHandle::other(
+            Box::new(transport),
+            options.outbound_frame_limit,
+        ));
+    }
+    connect_cli(options, initial_prompt, diagnostics, deadline).await
+}
+
+/// Start the CLI and connect to it, falling back on each of the
+/// [`fallback_transports`](ClaudeAgentOptions::fallback_transports) of
+/// `options` in turn while the CLI can't be used, and reporting each
+/// failover to `diagnostics`.
+#[cfg(feature = "subprocess")]
+async fn connect_cli(
+    options: &ClaudeAgentOptions,
+    initial_prompt: Option<&str>,
+    diagnostics: Option<&broadcast::Sender<Arc<Diagnostic>>>,
+    deadline: Option<Instant>,
+) -> Result<TransportHandle> {
+    let mut fallbacks = options.fallback_transports.iter();
+    let mut candidate = Cow::Borrowed(options);
+    loop {
+        let attempt = async {
+            let mut transport =
+                SubprocessTransport::new(&candidate, initial_prompt.map(String::from))?;
+            if let Some(diagnostics) = diagnostics {
+                transport = transport.with_diagnostics(diagnostics.clone());
+            }
+            if !options.fallback_transports.is_empty() {
+                transport = transport.with_min_version_check();
+            }
+            with_deadline(deadline, TimeoutPhase::Spawn, transport.connect()).await?;
+            Ok::<_, ClaudeSDKError>(TransportHandle::subprocess(transport))
+        };
+        let error = match attempt.await {
+            Err(e) if e.is_cli_unavailable() => e,
+            connected => return connected,
+        };
+        let Some(fallback) = fallbacks.next() else {
+            return Err(error);
+        };
+        let unapplied = unapplied_options(fallback, options);
+        if !unapplied.is_empty() {
+            return Err(ClaudeSDKError::configuration(format!(
+                "Not falling back on the {}, which can't apply {}: {}",
+                fallback,
+                unapplied.join(", "),
+                error
+            )));
+        }
+        let message = format!("Falling back on the {}: {}", fallback, error);
+        warn!(tags = ?options.session_tags, "{}", message);
+        if let Some(diagnostics) = diagnostics {
+            // Never waits: lagging receivers lose the oldest
+            let _ = diagnostics.send(Arc::new(Diagnostic::sdk(
+                DiagnosticSeverity::Warning,
+                TRANSPORT_FAILOVER,
+                message,
+            )));
+        }
+        candidate = Cow::Owned(match fallback {
+            FallbackTransport::Cli(path) => {
+                let mut options = options.clone();
+                options.cli_path = Some(path.clone());
+                options
+            }
+            // The last resort: a remote CLI can't be checked for anything
+            #[cfg(feature = "websocket")]
+            FallbackTransport::WebSocket(config) => {
+                let mut transport = super::transport::WebSocketTransport::new(config.clone());
+                with_deadline(deadline, TimeoutPhase::Spawn, transport.connect()).await?;
+                return Ok(TransportHandle::other(
+                    Box::new(transport),
+                    options.outbound_frame_limit,
+                ));
+            }
+        });
+    }
+}
+
+/// Without the `subprocess` feature, only user-provided transports connect.
+#[cfg(not(feature = "subprocess"))]
+async fn connect_cli(
+    _options: &ClaudeAgentOptions,
+    _initial_prompt: Option<&str>,
+    _diagnostics: Option<&broadcast::Sender<Arc<Diagnostic>>>,
+    _deadline: Option<Instant>,
+) -> Result<TransportHandle> {
+    Err(ClaudeSDKError::configuration(
+        "Starting the CLI requires the `subprocess` feature; connect over a transport of your own instead",
+    ))
+}
+
+/// Options `fallback` can't apply, because they are given to the CLI as
+/// flags and a remote CLI is started by its bridge.
+#[cfg(feature = "subprocess")]
+fn unapplied_options(
+    fallback: &FallbackTransport,
+    options: &ClaudeAgentOptions,
+) -> Vec<&'static str> {
+    if !fallback.is_remote() {
+        return Vec::new();
+    }
+    [
+        ("model", options.model.is_some()),
+        ("permission_mode", options.permission_mode.is_some()),
+        ("allowed_tools", !options.allowed_tools.is_empty()),
+        ("disallowed_tools", !options.disallowed_tools.is_empty()),
+        ("system_prompt", options.system_prompt.is_some()),
+        ("sandbox", options.sandbox.is_some()),
+        ("settings", options.settings.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, set)| set.then_some(name))
+    .collect()
+}
+
+/// Values of the running session that can be changed at runtime, which
+/// the client's options don't follow.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SessionSettings {
+    /// The model, `None` for the CLI's default.
+    pub(crate) model: Option<String>,
+    pub(crate) permission_mode: PermissionMode,
+    /// The thinking budget, `None` for the CLI's default.
+    pub(crate) max_thinking_tokens: Option<u32>,
+}
+
+impl SessionSettings {
+    /// The values a session started with `options` runs with.
+    fn from_options(options: &ClaudeAgentOptions) -> Self {
+        Self {
+            model: options.model.clone(),
+            permission_mode: options.permission_mode.unwrap_or_default(),
+            max_thinking_tokens: options.max_thinking_tokens,
+        }
+    }
+}
+
 /// A stream that keeps the Query alive while consuming messages.
 ///
 /// This wrapper ensures the Query (and its background reader task) stays alive
@@ -89,6 +277,24 @@ pub struct InternalClient {
     options: ClaudeAgentOptions,
     /// Whether the client is connected.
     connected: bool,
+    /// Diagnostics parsed from CLI stderr, across reconnects.
+    diagnostics: broadcast::Sender<Arc<Diagnostic>>,
+    /// Progress of the session, across reconnects.
+    progress: watch::Sender<Progress>,
+    /// The session's temp directory, once created.
+    tmp_dir: Option<SessionTmpDir>,
+    /// Transport given to run over instead of the CLI, until connecting
+    /// takes it.
+    transport: Option<Box<dyn Transport>>,
+    /// Whether the client was given a transport of its own.
+    custom_transport: bool,
+    /// Values of the running session, as changed at runtime.
+    settings: Arc<std::sync::Mutex<SessionSettings>>,
+    /// Active permission escalation, across reconnects.
+    escalation: EscalationSlot,
+    /// Sender of control requests to the current session, across
+    /// reconnects.
+    control: ControlSlot,
 }
 
 impl InternalClient {
@@ -99,50 +305,234 @@ impl InternalClient {
             message_rx: None,
             options,
             connected: false,
+            diagnostics: broadcast::channel(DIAGNOSTICS_CAPACITY).0,
+            progress: watch::channel(Progress::Idle).0,
+            tmp_dir: None,
+            transport: None,
+            custom_transport: false,
+            settings: Arc::default(),
+            escalation: EscalationSlot::default(),
+            control: ControlSlot::default(),
         }
     }
 
-    /// Validate options before connecting.
-    fn validate_options(&self) -> Result<()> {
-        // Check for mutually exclusive options
-        if self.options.can_use_tool.is_some() && self.options.permission_prompt_tool_name.is_some()
-        {
+    /// Run over `transport` instead of starting the CLI.
+    ///
+    /// The transport is connected first if it isn't ready. It only serves
+    /// one session: once it has been used, connecting again fails.
+    pub fn with_transport(mut self, transport: Box<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self.custom_transport = true;
+        self
+    }
+
+    /// Connect the transport given to the client, if it was given one.
+    async fn connect_custom_transport(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<Option<TransportHandle>> {
+        if !self.custom_transport {
+            return Ok(None);
+        }
+        let Some(mut transport) = self.transport.take() else {
             return Err(ClaudeSDKError::configuration(
-                "Cannot specify both 'can_use_tool' and 'permission_prompt_tool_name'",
+                "The client's transport has already been used and can't be connected again",
             ));
+        };
+        if !transport.is_ready() {
+            with_deadline(deadline, TimeoutPhase::Spawn, transport.connect()).await?;
         }
+        Ok(Some(TransportHandle::new(
+            transport,
+            self.options.outbound_frame_limit,
+        )))
+    }
 
-        Ok(())
+    /// Validate options before connecting.
+    fn validate_options(&self) -> Result<()> {
+        self.options.validate()
     }
 
     /// Connect to the CLI in streaming mode.
     pub async fn connect(&mut self) -> Result<()> {
+        self.connect_with_progress(|_| {}).await
+    }
+
+    /// Connect to the CLI in streaming mode, reporting each phase as it completes.
+    ///
+    /// The client only becomes connected once every phase has succeeded. If
+    /// a phase fails, the CLI process is shut down before the error is
+    /// returned; if the future is dropped mid-connect, the process is killed.
+    pub async fn connect_with_progress(
+        &mut self,
+        mut on_phase: impl FnMut(ConnectPhase),
+    ) -> Result<()> {
         if self.connected {
-            return Ok(());
+            if self.failure().is_none() {
+                return Ok(());
+            }
+            debug!("Reconnecting after session failure");
+            if let Err(e) = self.disconnect().await {
+                warn!("Failed to clean up failed session: {}", e);
+            }
+            self.query = None;
+            self.control.set(None);
+            self.message_rx = None;
+            self.connected = false;
         }
 
         self.validate_options()?;
 
-        // Create transport in streaming mode
-        let mut transport = SubprocessTransport::new(&self.options, None)?;
-        transport.connect().await?;
+        self.progress.send_replace(Progress::Connecting);
+        let started = match self.start(&mut on_phase).await {
+            Err(ClaudeSDKError::SessionNotFound { session_id })
+                if self.options.resume_fallback_new =>
+            {
+                warn!("Session {} not found, starting a new session", session_id);
+                self.options.resume = None;
+                self.options.resume_session_at = None;
+                self.options.fork_session = false;
+                self.start(&mut on_phase).await
+            }
+            started => started,
+        };
+        self.progress.send_replace(Progress::Idle);
+        started
+    }
 
-        // Create query handler
-        let (query, message_rx) = Query::new(transport, &self.options);
-        self.message_rx = Some(message_rx);
-        self.query = Some(query);
+    /// Spawn the CLI and start a session, storing it on `self`.
+    async fn start(&mut self, on_phase: &mut impl FnMut(ConnectPhase)) -> Result<()> {
+        // Subscribed before the CLI starts, so no line telling why a
+        // resume failed is missed
+        let mut stderr = self
+            .options
+            .resume
+            .is_some()
+            .then(|| self.diagnostics.subscribe());
 
-        // Start the query handler
-        if let Some(ref mut q) = self.query {
-            q.start().await?;
+        // Create transport in streaming mode. Until the session is stored on
+        // `self`, dropping these locals (e.g. on cancellation) kills the
+        // child process.
+        let deadline = connect_deadline(&self.options);
+        let transport = match self.connect_custom_transport(deadline).await? {
+            Some(transport) => transport,
+            None => {
+                let with_tmp_dir;
+                let spawn_options = match self.tmp_dir {
+                    Some(ref dir) => {
+                        let mut options = self.options.clone();
+                        options.add_dirs.push(dir.path().to_path_buf());
+                        with_tmp_dir = options;
+                        &with_tmp_dir
+                    }
+                    None => &self.options,
+                };
+                connect_transport(spawn_options, None, Some(&self.diagnostics), deadline)
+                    .instrument(super::session_span(&self.options.session_tags))
+                    .await?
+            }
+        };
+        on_phase(ConnectPhase::Spawned);
 
-            // Initialize the streaming session
-            let response = q.initialize().await?;
-            debug!("CLI initialized: {:?}", response);
+        let (query, message_rx) = Query::with_handle(transport, &self.options);
+        let mut query = query
+            .with_progress(self.progress.clone())
+            .with_escalation(self.escalation.clone(), Arc::clone(&self.settings));
+        let mut started = with_deadline(
+            deadline,
+            TimeoutPhase::Control,
+            Self::start_session(&mut query, on_phase),
+        )
+        .await;
+        if started.is_ok() && self.options.require_mcp_servers {
+            started = Self::check_mcp_servers(&query).await;
         }
+        // A new process starts in the configured mode
+        let escalated = self.escalation.modes().map(|(mode, _)| mode);
+        if let (Ok(()), Some(mode)) = (&started, escalated) {
+            started = query.set_permission_mode(mode).await;
+        }
+        if let Err(e) = started {
+            debug!(
+                tags = ?self.options.session_tags,
+                "Connect failed, shutting down CLI process: {}",
+                e
+            );
+            if let Err(stop_err) = query.stop().await {
+                warn!("Failed to stop CLI after failed connect: {}", stop_err);
+            }
+            // Stopping joined the stderr reader, so everything is in
+            if let (Some(session_id), Some(stderr)) = (&self.options.resume, stderr.as_mut()) {
+                if reported_missing_session(stderr) {
+                    return Err(ClaudeSDKError::session_not_found(session_id.clone()));
+                }
+            }
+            return Err(e);
+        }
+
+        let manifest = query.session_manifest().await;
+        info!(
+            target: AUDIT_TARGET,
+            model = manifest.model.as_deref().unwrap_or_default(),
+            permission_mode = ?manifest.permission_mode,
+            allowed_tools = ?manifest.allowed_tools,
+            cwd = ?manifest.cwd,
+            fingerprint = %manifest.fingerprint(),
+            tags = ?self.options.session_tags,
+            "Session started"
+        );
 
+        self.control.set(Some(query.control_sender()));
+        self.query = Some(query);
+        self.message_rx = Some(message_rx);
         self.connected = true;
-        info!("Connected to Claude CLI");
+        let mut settings = SessionSettings::from_options(&self.options);
+        if let Some(mode) = escalated {
+            settings.permission_mode = mode;
+        }
+        *self.settings_mut() = settings;
+        info!(tags = ?self.options.session_tags, "Connected to Claude CLI");
+        Ok(())
+    }
+
+    /// Start the control protocol and initialize the streaming session.
+    async fn start_session(
+        query: &mut Query,
+        on_phase: &mut impl FnMut(ConnectPhase),
+    ) -> Result<()> {
+        query.start().await?;
+        on_phase(ConnectPhase::ProtocolReady);
+
+        let response = query.initialize().await?;
+        debug!("CLI initialized: {:?}", response);
+        on_phase(ConnectPhase::Initialized);
+        Ok(())
+    }
+
+    /// Fail with the first MCP server the CLI reports as failed.
+    ///
+    /// Servers still starting up aren't waited for.
+    async fn check_mcp_servers(query: &Query) -> Result<()> {
+        let status = query.get_mcp_status().await?;
+        let servers = status
+            .get("mcpServers")
+            .and_then(serde_json::Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for server in servers {
+            if server.get("status").and_then(serde_json::Value::as_str) != Some("failed") {
+                continue;
+            }
+            let name = server
+                .get("name")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("unknown");
+            let message = server
+                .get("error")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("no details reported");
+            return Err(ClaudeSDKError::mcp_server_failed(name, message));
+        }
         Ok(())
     }
 
@@ -153,16 +543,21 @@ impl InternalClient {
         options: ClaudeAgentOptions,
         prompt: &str,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<Message>> + Send>>> {
-        // Validate options
-        if options.can_use_tool.is_some() && options.permission_prompt_tool_name.is_some() {
-            return Err(ClaudeSDKError::configuration(
-                "Cannot specify both 'can_use_tool' and 'permission_prompt_tool_name'",
-            ));
-        }
+        options.validate()?;
 
-        // For one-shot queries with callbacks, we need streaming mode
-        if options.can_use_tool.is_some() || options.hooks.is_some() {
-            // Use streaming mode for callbacks
+        // For one-shot queries with callbacks, we need streaming mode,
+        // resumed sessions use it to tell a missing session at connect, and
+        // remote fallbacks only take prompts written to them
+        if options.can_use_tool.is_some()
+            || options.hooks.is_some()
+            || options.workspace_guard.is_some()
+            || !options.permission_policies.is_empty()
+            || options.resume.is_some()
+            || options
+                .fallback_transports
+                .iter()
+                .any(FallbackTransport::is_remote)
+        {
             let mut client = InternalClient::new(options);
             client.connect().await?;
             client.send_message(prompt).await?;
@@ -175,17 +570,69 @@ impl InternalClient {
         }
 
         // Create transport in non-streaming mode
-        let mut transport = SubprocessTransport::new(&options, Some(prompt.to_string()))?;
-        transport.connect().await?;
+        let transport =
+            connect_transport(&options, Some(prompt), None, connect_deadline(&options)).await?;
 
         // Create query handler
-        let (mut query, message_rx) = Query::new(transport, &options);
+        let (mut query, message_rx) = Query::with_handle(transport, &options);
         query.start().await?;
 
         // Return stream that keeps query alive until fully consumed
         Ok(Box::pin(QueryStream::new(query, message_rx)))
     }
 
+    /// Process a one-shot query over `transport` instead of the CLI.
+    ///
+    /// Runs in streaming mode, since the prompt can only be written to the
+    /// transport.
+    pub async fn process_query_with_transport(
+        options: ClaudeAgentOptions,
+        prompt: &str,
+        transport: Box<dyn Transport>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Message>> + Send>>> {
+        options.validate()?;
+
+        let mut client = InternalClient::new(options).with_transport(transport);
+        client.connect().await?;
+        client.send_message(prompt).await?;
+        let rx = client
+            .take_message_rx()
+            .ok_or_else(|| ClaudeSDKError::internal("Message receiver not available"))?;
+        Ok(Box::pin(ClientStream::new(client, rx)))
+    }
+
+    /// Process a query whose prompt is read from `reader`, in streaming
+    /// mode.
+    pub async fn process_query_from_reader<R>(
+        options: ClaudeAgentOptions,
+        reader: R,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Message>> + Send>>>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        options.validate()?;
+
+        let mut client = InternalClient::new(options);
+        client.connect().await?;
+        client.send_message_from(reader).await?;
+        let rx = client
+            .take_message_rx()
+            .ok_or_else(|| ClaudeSDKError::internal("Message receiver not available"))?;
+        Ok(Box::pin(ClientStream::new(client, rx)))
+    }
+
+    /// Get the options used for this client.
+    pub fn options(&self) -> &ClaudeAgentOptions {
+        &self.options
+    }
+
+    /// Get mutable access to the options used for this client.
+    ///
+    /// Changes take effect on the next connect.
+    pub fn options_mut(&mut self) -> &mut ClaudeAgentOptions {
+        &mut self.options
+    }
+
     /// Send a message to the CLI.
     pub async fn send_message(&mut self, message: &str) -> Result<()> {
         let query = self
@@ -193,7 +640,34 @@ impl InternalClient {
             .as_ref()
             .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?;
 
-        query.send_message(message).await
+        query.send_message(message).await?;
+        self.progress.send_replace(Progress::WaitingForModel);
+        Ok(())
+    }
+
+    /// Send a message whose turn's messages aren't delivered.
+    pub(crate) async fn send_hidden_message(&mut self, message: &str) -> Result<()> {
+        let query = self
+            .query
+            .as_ref()
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?;
+
+        query.send_hidden_message(message).await
+    }
+
+    /// Send a message read from `reader` to the CLI.
+    pub async fn send_message_from<R>(&mut self, reader: R) -> Result<()>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let query = self
+            .query
+            .as_ref()
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?;
+
+        query.send_message_from(reader).await?;
+        self.progress.send_replace(Progress::WaitingForModel);
+        Ok(())
     }
 
     /// Get the message receiver.
@@ -218,7 +692,9 @@ impl InternalClient {
             .as_ref()
             .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?;
 
-        query.set_permission_mode(mode).await
+        query.set_permission_mode(mode).await?;
+        self.settings_mut().permission_mode = mode;
+        Ok(())
     }
 
     /// Set the model.
@@ -228,7 +704,41 @@ impl InternalClient {
             .as_ref()
             .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?;
 
-        query.set_model(model).await
+        let model = model.into();
+        query.set_model(model.clone()).await?;
+        self.settings_mut().model = Some(model);
+        Ok(())
+    }
+
+    /// Set the maximum thinking tokens.
+    pub async fn set_max_thinking_tokens(&self, max_thinking_tokens: Option<u32>) -> Result<()> {
+        let query = self
+            .query
+            .as_ref()
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?;
+
+        query.set_max_thinking_tokens(max_thinking_tokens).await?;
+        self.settings_mut().max_thinking_tokens = max_thinking_tokens;
+        Ok(())
+    }
+
+    /// The sender of control requests to the current session.
+    pub(crate) fn control(&self) -> &ControlSlot {
+        &self.control
+    }
+
+    /// The client's permission escalation.
+    pub(crate) fn escalation(&self) -> &EscalationSlot {
+        &self.escalation
+    }
+
+    /// Values of the running session, as changed at runtime.
+    pub(crate) fn settings(&self) -> SessionSettings {
+        self.settings_mut().clone()
+    }
+
+    fn settings_mut(&self) -> std::sync::MutexGuard<'_, SessionSettings> {
+        self.settings.lock().unwrap_or_else(|e| e.into_inner())
     }
 
     /// Rewind files to a specific user message.
@@ -250,6 +760,111 @@ impl InternalClient {
         query.get_server_info().await
     }
 
+    /// Get up to `n` of the most recently received messages.
+    pub async fn recent_messages(&self, n: usize) -> Vec<Message> {
+        match self.query.as_ref() {
+            Some(query) => query.recent_messages(n).await,
+            None => Vec::new(),
+        }
+    }
+
+    /// Subscribe to the messages received from now on.
+    pub fn subscribe(&self) -> Result<broadcast::Receiver<Arc<Message>>> {
+        self.query
+            .as_ref()
+            .map(Query::subscribe)
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))
+    }
+
+    /// Watch the progress of the session.
+    pub fn progress(&self) -> watch::Receiver<Progress> {
+        self.progress.subscribe()
+    }
+
+    /// How full the context window of the session is.
+    pub fn context_usage(&self) -> Option<ContextUsage> {
+        self.query.as_ref().map(Query::context_usage)
+    }
+
+    /// Count the context as empty until the next API call.
+    pub(crate) fn reset_context_usage(&self) {
+        if let Some(ref query) = self.query {
+            query.reset_context_usage();
+        }
+    }
+
+    /// Whether the last compaction run for the context policy didn't
+    /// happen, and none has since.
+    pub(crate) fn compaction_blocked(&self) -> bool {
+        self.query.as_ref().is_some_and(Query::compaction_blocked)
+    }
+
+    /// Receive the diagnostics at or above `min_severity`.
+    pub fn diagnostics(&self, min_severity: DiagnosticSeverity) -> DiagnosticReceiver {
+        DiagnosticReceiver::new(self.diagnostics.subscribe(), min_severity)
+    }
+
+    /// Hold messages back from the consumer until resumed.
+    pub fn pause(&self) -> Result<()> {
+        self.connected_query().map(Query::pause)
+    }
+
+    /// Deliver held messages and continue reading.
+    pub fn resume(&self) -> Result<()> {
+        self.connected_query().map(Query::resume)
+    }
+
+    /// Whether message delivery is paused.
+    pub fn is_paused(&self) -> bool {
+        self.query.as_ref().is_some_and(Query::is_paused)
+    }
+
+    fn connected_query(&self) -> Result<&Query> {
+        self.query
+            .as_ref()
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))
+    }
+
+    /// Get the tool uses whose results haven't arrived, oldest first.
+    pub async fn pending_tool_uses(&self) -> Vec<PendingToolUse> {
+        match self.query.as_ref() {
+            Some(query) => query.pending_tool_uses().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// Get the session ID reported by the most recent result message.
+    pub async fn session_id(&self) -> Option<String> {
+        match self.query.as_ref() {
+            Some(query) => query.session_id().await,
+            None => None,
+        }
+    }
+
+    /// Get the configuration the current session runs with.
+    pub async fn session_manifest(&self) -> Option<SessionManifest> {
+        let mut manifest = self.query.as_ref()?.session_manifest().await;
+        // Tags can change mid-session
+        manifest.tags = self.options.session_tags.clone();
+        Some(manifest)
+    }
+
+    /// Get a summary of the protocol anomalies observed so far.
+    pub async fn protocol_anomalies(&self) -> ProtocolAnomalyReport {
+        match self.query.as_ref() {
+            Some(query) => query.protocol_anomalies().await,
+            None => ProtocolAnomalyReport::default(),
+        }
+    }
+
+    /// Get the sizes of the frames written to the CLI so far.
+    pub fn outbound_frame_stats(&self) -> OutboundFrameStats {
+        self.query
+            .as_ref()
+            .map(Query::outbound_frame_stats)
+            .unwrap_or_default()
+    }
+
     /// Get current MCP server connection status.
     pub async fn get_mcp_status(&self) -> Result<serde_json::Value> {
         let query = self
@@ -260,27 +875,85 @@ impl InternalClient {
         query.get_mcp_status().await
     }
 
+    /// Enable or disable an MCP server in the running session.
+    pub async fn toggle_mcp_server(&self, server_name: &str, enabled: bool) -> Result<()> {
+        self.connected_query()?
+            .toggle_mcp_server(server_name, enabled)
+            .await
+    }
+
+    /// Check that the CLI answers control requests, returning the round-trip
+    /// latency.
+    ///
+    /// Fails with a [`Timeout`](ClaudeSDKError::Timeout) in the
+    /// [`Control`](TimeoutPhase::Control) phase if no answer arrives within
+    /// `timeout`.
+    pub async fn ping(&self, timeout: Duration) -> Result<Duration> {
+        let query = self
+            .query
+            .as_ref()
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?;
+
+        let started = Instant::now();
+        query.ping(timeout).await?;
+        Ok(started.elapsed())
+    }
+
     /// Disconnect from the CLI.
     pub async fn disconnect(&mut self) -> Result<()> {
+        self.shutdown().await;
+        Ok(())
+    }
+
+    /// Disconnect from the CLI, reporting each shutdown stage.
+    ///
+    /// Returns an empty report when not connected.
+    pub async fn shutdown(&mut self) -> ShutdownReport {
         if !self.connected {
-            return Ok(());
+            return ShutdownReport::default();
         }
 
-        if let Some(ref mut query) = self.query {
-            query.stop().await?;
-        }
+        let report = match self.query {
+            Some(ref mut query) => query.shutdown().await,
+            None => ShutdownReport::default(),
+        };
 
         self.query = None;
+        self.control.set(None);
         self.message_rx = None;
         self.connected = false;
+        self.progress.send_replace(Progress::Idle);
 
-        info!("Disconnected from Claude CLI");
-        Ok(())
+        info!(
+            forced_kill = report.forced_kill,
+            "Disconnected from Claude CLI"
+        );
+        report
+    }
+
+    /// Get the session's temp directory, creating it on first use.
+    pub fn session_tmp_dir(&mut self) -> Result<&Path> {
+        let dir = match self.tmp_dir.take() {
+            Some(dir) => dir,
+            None => SessionTmpDir::create(self.options.keep_session_tmp_dir)?,
+        };
+        Ok(self.tmp_dir.insert(dir).path())
+    }
+
+    /// Remove the session's temp directory, unless it is to be kept.
+    pub fn discard_session_tmp_dir(&mut self) {
+        self.tmp_dir = None;
     }
 
     /// Check if connected.
     pub fn is_connected(&self) -> bool {
-        self.connected
+        self.connected && self.failure().is_none()
+    }
+
+    /// Get the reason the session failed, if the CLI process died or its
+    /// output could no longer be read.
+    pub fn failure(&self) -> Option<&str> {
+        self.query.as_ref().and_then(Query::failure)
     }
 }
 
@@ -291,7 +964,23 @@ impl Drop for InternalClient {
     }
 }
 
+/// What the CLI writes to stderr when the session to resume doesn't exist.
+const MISSING_SESSION: &str = "No conversation found with session ID";
+
+/// Whether the CLI said on `stderr` that the session to resume doesn't
+/// exist.
+fn reported_missing_session(stderr: &mut broadcast::Receiver<Arc<Diagnostic>>) -> bool {
+    loop {
+        match stderr.try_recv() {
+            Ok(diagnostic) if diagnostic.line.contains(MISSING_SESSION) => return true,
+            Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+            Err(_) => return false,
+        }
+    }
+}
+
 /// Check CLI version and warn if outdated.
+#[cfg(feature = "subprocess")]
 pub async fn check_cli_version(cli_path: Option<&std::path::Path>) -> Result<String> {
     use std::process::Stdio;
     use tokio::process::Command;
@@ -314,23 +1003,27 @@ pub async fn check_cli_version(cli_path: Option<&std::path::Path>) -> Result<Str
         if e.kind() == std::io::ErrorKind::NotFound {
             ClaudeSDKError::cli_not_found(format!("CLI not found at {}", path.display()))
         } else {
-            ClaudeSDKError::cli_connection_with_source("Failed to run CLI version check", e)
+            ClaudeSDKError::cli_launch(CLILaunchError {
+                kind: CLILaunchErrorKind::VersionCheckFailed,
+                message: format!("Failed to run CLI version check: {}", e),
+                cli_path: path.clone(),
+                argv: vec!["--version".to_string()],
+                cwd: None,
+                path_env: std::env::var("PATH").ok(),
+                source: Some(e),
+            })
         }
     })?;
 
     let version_str = String::from_utf8_lossy(&output.stdout);
-    let version = version_str
-        .lines()
-        .next()
-        .and_then(|line| line.split_whitespace().last())
-        .unwrap_or("unknown")
-        .to_string();
+    let parsed = super::transport::parse_cli_version(&version_str);
+    let version = parsed
+        .as_ref()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "unknown".to_string());
 
     // Check against minimum version
-    if let (Ok(found), Ok(required)) = (
-        semver::Version::parse(&version),
-        semver::Version::parse(crate::MIN_CLI_VERSION),
-    ) {
+    if let (Some(found), Ok(required)) = (parsed, semver::Version::parse(crate::MIN_CLI_VERSION)) {
         if found < required {
             tracing::warn!(
                 "CLI version {} is below minimum required version {}",
@@ -366,5 +1059,17 @@ mod tests {
 
         let client = InternalClient::new(options);
         assert!(client.validate_options().is_err());
+
+        let policy = crate::PermissionPolicy::new().max_concurrent("Bash", 1);
+        let options = ClaudeAgentOptions::new().with_permission_policy(policy.clone());
+        assert!(options.validate().is_ok());
+        let options = options.with_permission_policy(policy.max_concurrent("Bash", 0));
+        assert!(options.validate().is_err());
+
+        let registry = crate::ToolSchemaRegistry::builtin();
+        let options = ClaudeAgentOptions::new().with_tool_schemas(registry.clone());
+        assert!(options.validate().is_err());
+        let options = options.with_tool_schemas(registry.deny_invalid());
+        assert!(options.validate().is_ok());
     }
 }
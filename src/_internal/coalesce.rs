@@ -0,0 +1,167 @@
+//! Coalescing of partial-message text deltas.
+//!
+//! With `include_partial_messages`, the CLI emits a stream event for every
+//! few tokens of text. [`DeltaCoalescer`] merges consecutive `text_delta`
+//! events of the same content block into one, held back until the
+//! configured interval elapses, the merged text reaches the size limit, or a
+//! message that can't be merged arrives.
+
+use tokio::time::Instant;
+
+use serde_json::Value;
+
+use crate::types::{Message, PartialCoalescing, StreamEvent};
+
+/// Merges consecutive text deltas into fewer stream events.
+pub(crate) struct DeltaCoalescer {
+    config: PartialCoalescing,
+    /// Merged event waiting to be delivered.
+    pending: Option<StreamEvent>,
+    /// When `pending` must be delivered.
+    deadline: Option<Instant>,
+}
+
+/// Content block index of a `text_delta` event.
+fn text_delta_index(event: &StreamEvent) -> Option<u64> {
+    let is_text = event.event.get("type").and_then(|v| v.as_str()) == Some("content_block_delta")
+        && event.event.pointer("/delta/type").and_then(|v| v.as_str()) == Some("text_delta");
+    is_text
+        .then(|| event.event.get("index").and_then(|v| v.as_u64()))
+        .flatten()
+}
+
+/// Text of a `text_delta` event.
+fn delta_text(event: &mut StreamEvent) -> Option<&mut String> {
+    match event.event.pointer_mut("/delta/text") {
+        Some(Value::String(text)) => Some(text),
+        _ => None,
+    }
+}
+
+impl DeltaCoalescer {
+    /// Create a coalescer merging deltas per `config`.
+    pub(crate) fn new(config: PartialCoalescing) -> Self {
+        Self {
+            config,
+            pending: None,
+            deadline: None,
+        }
+    }
+
+    /// When the held-back event must be delivered, if there is one.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Take `message`, returning the messages ready for delivery, in order.
+    pub(crate) fn push(&mut self, message: Message) -> Vec<Message> {
+        let Message::StreamEvent(mut event) = message else {
+            return self.flush().into_iter().chain([message]).collect();
+        };
+        let Some(index) = text_delta_index(&event) else {
+            return self
+                .flush()
+                .into_iter()
+                .chain([Message::StreamEvent(event)])
+                .collect();
+        };
+
+        let mut ready = Vec::new();
+        let mergeable = self.pending.as_ref().is_some_and(|pending| {
+            text_delta_index(pending) == Some(index)
+                && pending.parent_tool_use_id == event.parent_tool_use_id
+                && pending.session_id == event.session_id
+        });
+        if mergeable {
+            let text = delta_text(&mut event)
+                .map(std::mem::take)
+                .unwrap_or_default();
+            if let Some(merged) = self.pending.as_mut().and_then(delta_text) {
+                merged.push_str(&text);
+            }
+        } else {
+            ready.extend(self.flush());
+            event.raw = None;
+            self.pending = Some(event);
+            self.deadline = Some(Instant::now() + self.config.interval);
+        }
+
+        let size = self
+            .pending
+            .as_mut()
+            .and_then(delta_text)
+            .map_or(0, |text| text.len());
+        if size >= self.config.max_bytes {
+            ready.extend(self.flush());
+        }
+        ready
+    }
+
+    /// Take the held-back event, if any.
+    pub(crate) fn flush(&mut self) -> Option<Message> {
+        self.deadline = None;
+        self.pending.take().map(Message::StreamEvent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn delta(index: u64, text: &str) -> Message {
+        Message::StreamEvent(StreamEvent::new(
+            format!("ev_{}", text),
+            "s",
+            json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": {"type": "text_delta", "text": text}
+            }),
+        ))
+    }
+
+    fn text(message: &Message) -> &str {
+        match message {
+            Message::StreamEvent(event) => event.event["delta"]["text"].as_str().unwrap(),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_merges_deltas_of_one_block() {
+        let mut coalescer = DeltaCoalescer::new(PartialCoalescing {
+            interval: Duration::from_secs(60),
+            max_bytes: 8,
+        });
+        assert!(coalescer.push(delta(0, "Hel")).is_empty());
+        assert!(coalescer.deadline().is_some());
+        assert!(coalescer.push(delta(0, "lo")).is_empty());
+
+        // A different block flushes the merged delta
+        let ready = coalescer.push(delta(1, "wor"));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(text(&ready[0]), "Hello");
+
+        // Reaching the size limit flushes immediately
+        let ready = coalescer.push(delta(1, "ld!!!"));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(text(&ready[0]), "world!!!");
+        assert!(coalescer.deadline().is_none());
+
+        // Other messages flush first and pass through
+        coalescer.push(delta(1, "?"));
+        let stop = Message::StreamEvent(StreamEvent {
+            event: json!({"type": "content_block_stop", "index": 1}),
+            ..match delta(1, "") {
+                Message::StreamEvent(event) => event,
+                _ => unreachable!(),
+            }
+        });
+        let ready = coalescer.push(stop);
+        assert_eq!(ready.len(), 2);
+        assert_eq!(text(&ready[0]), "?");
+        assert!(coalescer.flush().is_none());
+    }
+}
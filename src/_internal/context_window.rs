@@ -0,0 +1,89 @@
+//! How full the context window is.
+//!
+//! The usage of an API call counts everything the model was sent, so the
+//! latest call of the main conversation tells how full its context is: the
+//! call's input, cached or not, plus the output it added. Subagents have
+//! contexts of their own and are left out. A compaction boundary starts the
+//! count over, as the next call reports the compacted context.
+
+use serde_json::Value;
+
+use crate::protocol::wire;
+
+/// Subtype of the system message marking a compaction.
+const COMPACT_BOUNDARY: &str = "compact_boundary";
+
+/// Tokens in the context as of `frame`, if it tells.
+pub(crate) fn context_tokens(frame: &Value) -> Option<u64> {
+    let of_subagent = !frame.get("parent_tool_use_id").map_or(true, Value::is_null);
+    let usage = match frame.get("type").and_then(Value::as_str)? {
+        wire::SYSTEM => {
+            return (frame.get("subtype").and_then(Value::as_str) == Some(COMPACT_BOUNDARY))
+                .then_some(0);
+        }
+        _ if of_subagent => return None,
+        wire::ASSISTANT => frame["message"].get("usage")?,
+        wire::STREAM_EVENT => {
+            let event = &frame["event"];
+            if event.get("type").and_then(Value::as_str) != Some("message_start") {
+                return None;
+            }
+            event["message"].get("usage")?
+        }
+        _ => return None,
+    };
+    let tokens = |field: &str| usage.get(field).and_then(Value::as_u64).unwrap_or(0);
+    let total = tokens("input_tokens")
+        + tokens("cache_creation_input_tokens")
+        + tokens("cache_read_input_tokens")
+        + tokens("output_tokens");
+    // Messages without usage report zeros
+    (total > 0).then_some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_context_tokens() {
+        let assistant = |parent: Option<&str>| {
+            json!({
+                "type": "assistant",
+                "message": {
+                    "content": [],
+                    "model": "m",
+                    "usage": {"input_tokens": 5, "cache_read_input_tokens": 90_000, "cache_creation_input_tokens": 1_000, "output_tokens": 200}
+                },
+                "parent_tool_use_id": parent
+            })
+        };
+        assert_eq!(context_tokens(&assistant(None)), Some(91_205));
+        assert_eq!(context_tokens(&assistant(Some("task_1"))), None);
+        assert_eq!(
+            context_tokens(&json!({
+                "type": "stream_event",
+                "event": {"type": "message_start", "message": {"usage": {"input_tokens": 120_000, "output_tokens": 1}}},
+                "parent_tool_use_id": null
+            })),
+            Some(120_001)
+        );
+        assert_eq!(
+            context_tokens(
+                &json!({"type": "system", "subtype": "compact_boundary", "compact_metadata": {"trigger": "auto", "pre_tokens": 180_000}})
+            ),
+            Some(0)
+        );
+        assert_eq!(
+            context_tokens(&json!({"type": "system", "subtype": "init"})),
+            None
+        );
+        assert_eq!(
+            context_tokens(
+                &json!({"type": "assistant", "message": {"content": [], "model": "m", "usage": {"input_tokens": 0}}})
+            ),
+            None
+        );
+    }
+}
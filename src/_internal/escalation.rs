@@ -0,0 +1,136 @@
+//! Temporary permission mode changes.
+//!
+//! An escalation made by
+//! [`ClaudeClient::escalate_permissions`](crate::ClaudeClient::escalate_permissions)
+//! is shared with the reader of the session's messages, which counts the
+//! responses and reverts it as the one that expires it ends, however the
+//! messages are read.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::time::Instant;
+use tracing::info;
+
+use super::AUDIT_TARGET;
+use crate::types::{EscalationRevert, PermissionMode};
+
+/// A temporary permission mode change.
+#[derive(Debug)]
+pub(crate) struct Escalation {
+    /// The mode escalated to.
+    pub(crate) mode: PermissionMode,
+    /// The mode to revert to.
+    pub(crate) previous: PermissionMode,
+    pub(crate) revert: EscalationRevert,
+    pub(crate) started: Instant,
+    /// Responses received since the escalation.
+    pub(crate) turns: u32,
+    /// Session tags at the time, for the audit log.
+    pub(crate) tags: HashMap<String, String>,
+}
+
+impl Escalation {
+    pub(crate) fn expired(&self) -> bool {
+        match self.revert {
+            EscalationRevert::Manual => false,
+            EscalationRevert::AfterTurns(turns) => self.turns >= turns,
+            EscalationRevert::After(duration) => self.started.elapsed() >= duration,
+        }
+    }
+
+    /// Record the revert as an audit event, with what `trigger`ed it.
+    pub(crate) fn log_reverted(&self, trigger: &str) {
+        info!(
+            target: AUDIT_TARGET,
+            to = ?self.previous,
+            turns = self.turns,
+            elapsed_ms = self.started.elapsed().as_millis() as u64,
+            trigger,
+            tags = ?self.tags,
+            "Permission mode reverted"
+        );
+    }
+}
+
+/// The active escalation of a client, if any, shared with its reader.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EscalationSlot(Arc<Mutex<Option<Escalation>>>);
+
+impl EscalationSlot {
+    fn lock(&self) -> MutexGuard<'_, Option<Escalation>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    /// The mode escalated to and the one to revert to, if escalated.
+    pub(crate) fn modes(&self) -> Option<(PermissionMode, PermissionMode)> {
+        self.lock().as_ref().map(|e| (e.mode, e.previous))
+    }
+
+    pub(crate) fn set(&self, escalation: Escalation) {
+        *self.lock() = Some(escalation);
+    }
+
+    pub(crate) fn take(&self) -> Option<Escalation> {
+        self.lock().take()
+    }
+
+    /// Put back an escalation that couldn't be reverted, unless another
+    /// took its place.
+    pub(crate) fn restore(&self, escalation: Escalation) {
+        self.lock().get_or_insert(escalation);
+    }
+
+    /// Take the escalation if it has expired.
+    pub(crate) fn take_expired(&self) -> Option<Escalation> {
+        let mut slot = self.lock();
+        slot.as_ref().filter(|e| e.expired())?;
+        slot.take()
+    }
+
+    /// Count a response, taking the escalation if that expired it.
+    pub(crate) fn response_received(&self) -> Option<Escalation> {
+        if let Some(ref mut escalation) = *self.lock() {
+            escalation.turns += 1;
+        }
+        self.take_expired()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_escalation_expiry() {
+        let slot = EscalationSlot::default();
+        slot.set(Escalation {
+            mode: PermissionMode::AcceptEdits,
+            previous: PermissionMode::Default,
+            revert: EscalationRevert::AfterTurns(2),
+            started: Instant::now(),
+            turns: 0,
+            tags: HashMap::new(),
+        });
+        assert!(slot.take_expired().is_none());
+        assert!(slot.response_received().is_none());
+        let mut escalation = slot.response_received().unwrap();
+        assert_eq!(escalation.turns, 2);
+        assert!(!slot.is_active());
+
+        escalation.revert = EscalationRevert::After(Duration::ZERO);
+        assert!(escalation.expired());
+        escalation.revert = EscalationRevert::Manual;
+        assert!(!escalation.expired());
+        slot.restore(escalation);
+        assert_eq!(
+            slot.modes(),
+            Some((PermissionMode::AcceptEdits, PermissionMode::Default))
+        );
+    }
+}
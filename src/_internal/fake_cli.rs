@@ -0,0 +1,49 @@
+//! Stand-in CLIs for tests that need a real process.
+//!
+//! Spawning, command lines, stderr, exit codes and restarts can only be
+//! tested against a process; everything above the transport is tested with
+//! [`MockTransport`](crate::testing::MockTransport) instead.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Write an executable shell script running `body` to `dir/name`.
+pub(crate) fn write_script(dir: &Path, name: &str, body: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// Write a stand-in CLI to `dir` that answers control requests and runs
+/// `on_prompt` (shell code) for each prompt, followed by a successful
+/// result. It appends its arguments to `claude.args` in `dir` when it
+/// starts, and the control requests it receives to `claude.requests`.
+pub(crate) fn scripted_cli(dir: &Path, on_prompt: &str) -> PathBuf {
+    scripted_cli_with(dir, "", on_prompt)
+}
+
+/// Like [`scripted_cli`], with `on_control` holding `case` arms tried on
+/// each input line before the default answer to control requests. The
+/// line is in `$line` and its request ID in `$id`.
+pub(crate) fn scripted_cli_with(dir: &Path, on_control: &str, on_prompt: &str) -> PathBuf {
+    let script = format!(
+        r#"echo "$@" >> "$0.args"
+while IFS= read -r line; do
+  id=$(printf '%s\n' "$line" | sed 's/.*"request_id":"\([^"]*\)".*/\1/')
+  case "$line" in
+{}
+    *'"control_request"'*)
+      printf '%s\n' "$line" >> "$0.requests"
+      echo '{{"type":"control_response","response":{{"subtype":"success","request_id":"'"$id"'","response":{{}}}}}}'
+      ;;
+    *'"type":"user"'*)
+{}
+      echo '{{"type":"result","subtype":"success","duration_ms":1,"duration_api_ms":1,"is_error":false,"num_turns":1,"session_id":"s"}}'
+      ;;
+  esac
+done"#,
+        on_control, on_prompt
+    );
+    write_script(dir, "claude", &script)
+}
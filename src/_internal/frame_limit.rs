@@ -0,0 +1,218 @@
+//! Size limit on the frames written to the CLI.
+//!
+//! Each frame goes to the CLI's stdin as a single line, and a permission or
+//! hook callback can make its response arbitrarily large by handing back
+//! an updated tool input. The meter checks every frame against the
+//! configured [`OutboundFrameLimit`] and tallies the sizes written.
+//! Control responses get a chance to shrink first, and are replaced by an
+//! error response if they don't, since the CLI waits for an answer.
+
+use std::sync::Mutex;
+
+use serde_json::Value;
+use tracing::warn;
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::protocol::{encode_frame, ControlResponse, ControlResponsePayload};
+use crate::types::{OutboundFrameLimit, OutboundFrameStats, OversizedInput};
+
+/// Field of permission and hook responses holding the updated tool input.
+const UPDATED_INPUT: &str = "updatedInput";
+
+/// Checks and counts the frames written to the CLI.
+#[derive(Debug, Default)]
+pub(crate) struct FrameMeter {
+    limit: Option<OutboundFrameLimit>,
+    stats: Mutex<OutboundFrameStats>,
+}
+
+impl FrameMeter {
+    pub(crate) fn new(limit: Option<OutboundFrameLimit>) -> Self {
+        Self {
+            limit,
+            stats: Mutex::default(),
+        }
+    }
+
+    fn stats_mut(&self) -> std::sync::MutexGuard<'_, OutboundFrameStats> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether frames have a size limit.
+    #[cfg(feature = "subprocess")]
+    pub(crate) fn is_limited(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    /// Sizes of the frames written so far.
+    pub(crate) fn stats(&self) -> OutboundFrameStats {
+        self.stats_mut().clone()
+    }
+
+    /// Let a frame of `size` bytes through if it's within the limit,
+    /// counting it as written.
+    pub(crate) fn admit(&self, size: usize) -> Result<()> {
+        self.check(size)?;
+        self.record(size);
+        Ok(())
+    }
+
+    /// Fail if a frame of `size` bytes, or one growing to that size as it
+    /// is written, is over the limit.
+    pub(crate) fn check(&self, size: usize) -> Result<()> {
+        match self.limit.filter(|limit| size > limit.max_bytes) {
+            Some(limit) => {
+                self.stats_mut().rejected += 1;
+                Err(ClaudeSDKError::frame_too_large(size, limit.max_bytes))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Count a frame of `size` bytes as written.
+    pub(crate) fn record(&self, size: usize) {
+        let mut stats = self.stats_mut();
+        stats.frames += 1;
+        stats.bytes += size as u64;
+        stats.largest = stats.largest.max(size);
+    }
+
+    /// Encode the response to control request `request_id`, shrinking its
+    /// updated input to fit the limit if the strategy allows, or answering
+    /// with an error if it doesn't fit.
+    pub(crate) fn encode_response(
+        &self,
+        request_id: String,
+        response: Result<Value>,
+    ) -> Result<String> {
+        let frame = ControlResponse::from_result(request_id.as_str(), response);
+        let line = encode_frame(&frame)?;
+        let Some(limit) = self.limit.filter(|limit| line.len() > limit.max_bytes) else {
+            return Ok(line);
+        };
+        let encode =
+            |body: &Value| encode_frame(&ControlResponse::success(&request_id, body.clone()));
+        if let ControlResponsePayload::Success {
+            response: Some(mut body),
+            ..
+        } = frame.response
+        {
+            if shrink(&mut body, &limit, encode) {
+                self.stats_mut().shrunk += 1;
+                return encode(&body);
+            }
+        }
+        self.stats_mut().rejected += 1;
+        let error = ClaudeSDKError::frame_too_large(line.len(), limit.max_bytes);
+        warn!(
+            "Answering control request {} with an error: {}",
+            request_id, error
+        );
+        encode_frame(&ControlResponse::from_result(request_id, Err(error)))
+    }
+}
+
+/// Shrink the updated input of the response `body` per `limit`, until
+/// `encode` makes it fit. Returns whether it does.
+fn shrink(
+    body: &mut Value,
+    limit: &OutboundFrameLimit,
+    encode: impl Fn(&Value) -> Result<String>,
+) -> bool {
+    // Permission responses hold it at the top, PreToolUse hook outputs
+    // in their specific output
+    let holder = if body.get(UPDATED_INPUT).is_some() {
+        &mut *body
+    } else {
+        match body.get_mut("hookSpecificOutput") {
+            Some(output) if output.get(UPDATED_INPUT).is_some() => output,
+            _ => return false,
+        }
+    };
+    match limit.oversized_input {
+        OversizedInput::Reject => return false,
+        OversizedInput::KeepOriginal => {
+            if let Some(fields) = holder.as_object_mut() {
+                fields.remove(UPDATED_INPUT);
+            }
+        }
+    }
+    encode(body).is_ok_and(|line| line.len() <= limit.max_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn allow(content: &str) -> Value {
+        json!({
+            "behavior": "allow",
+            "updatedInput": {"file_path": "/tmp/out.txt", "content": content}
+        })
+    }
+
+    fn response_body(line: &str) -> Value {
+        let frame: Value = serde_json::from_str(line).unwrap();
+        frame["response"]["response"].clone()
+    }
+
+    #[test]
+    fn test_meter_counts_and_rejects() {
+        let meter = FrameMeter::new(Some(OutboundFrameLimit::new(100)));
+        meter.admit(40).unwrap();
+        meter.admit(100).unwrap();
+        let err = meter.admit(101).unwrap_err();
+        assert!(err.is_frame_too_large());
+        assert_eq!(
+            meter.stats(),
+            OutboundFrameStats {
+                frames: 2,
+                bytes: 140,
+                largest: 100,
+                rejected: 1,
+                shrunk: 0,
+            }
+        );
+        assert!(FrameMeter::default().admit(usize::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_oversized_responses_are_shrunk_or_rejected() {
+        let content = "é".repeat(500);
+        let limit = OutboundFrameLimit::new(400);
+
+        let meter = FrameMeter::new(Some(limit));
+        let line = meter
+            .encode_response("req_1".to_string(), Ok(allow(&content)))
+            .unwrap();
+        assert!(line.contains("\"error\""), "{}", line);
+        assert!(line.contains("exceeds the outbound limit"), "{}", line);
+
+        let meter = FrameMeter::new(Some(
+            limit.with_oversized_input(OversizedInput::KeepOriginal),
+        ));
+        let line = meter
+            .encode_response("req_2".to_string(), Ok(allow(&content)))
+            .unwrap();
+        assert_eq!(response_body(&line), json!({"behavior": "allow"}));
+
+        // Hook outputs hold it in their specific output
+        let hook = json!({"hookSpecificOutput": {"hookEventName": "PreToolUse", "updatedInput": {"command": content}}});
+        let line = meter
+            .encode_response("req_4".to_string(), Ok(hook))
+            .unwrap();
+        assert_eq!(
+            response_body(&line),
+            json!({"hookSpecificOutput": {"hookEventName": "PreToolUse"}})
+        );
+        assert_eq!(meter.stats().shrunk, 2);
+
+        // Nothing to shrink
+        let line = meter
+            .encode_response("req_5".to_string(), Ok(json!({"text": content})))
+            .unwrap();
+        assert!(line.contains("exceeds the outbound limit"), "{}", line);
+        assert_eq!(meter.stats().rejected, 1);
+    }
+}
@@ -2,13 +2,94 @@
 //!
 //! This module contains internal types and functions that are not part of the public API.
 //! While exposed for advanced use cases, the API here may change between versions.
+//! Parsing of the CLI's output has a stable home in [`wire`](crate::wire).
 
+pub(crate) mod backoff;
+pub(crate) mod callbacks;
 pub mod client;
-pub mod message_parser;
+pub(crate) mod coalesce;
+pub(crate) mod context_window;
+pub(crate) mod escalation;
+#[cfg(all(test, unix, feature = "subprocess"))]
+pub(crate) mod fake_cli;
+pub(crate) mod frame_limit;
+pub(crate) mod partial;
+pub(crate) mod prompt_stream;
 pub mod query;
+pub(crate) mod spool;
+pub(crate) mod tmp_dir;
 pub mod transport;
+pub(crate) mod watchdog;
 
+use std::collections::HashMap;
+use std::future::Future;
+use tokio::time::Instant;
+use tracing::Span;
+
+use crate::errors::{ClaudeSDKError, Result, TimeoutPhase};
+
+pub use crate::protocol::parser as message_parser;
 pub use client::InternalClient;
-pub use message_parser::parse_message;
+pub use message_parser::{parse_message, parse_message_with_raw};
 pub use query::Query;
-pub use transport::{SubprocessTransport, Transport};
+#[cfg(feature = "subprocess")]
+pub use transport::SubprocessTransport;
+pub use transport::Transport;
+
+/// Tracing target for security-relevant events such as permission changes.
+pub(crate) const AUDIT_TARGET: &str = "claude_agents_sdk::audit";
+
+/// Span carrying the session tags, entered by the tasks of a session so
+/// everything they log (errors, callback logs, shutdown reports) is
+/// attributed to it. Disabled when there are no tags.
+pub(crate) fn session_span(tags: &HashMap<String, String>) -> Span {
+    if tags.is_empty() {
+        return Span::none();
+    }
+    tracing::info_span!("claude_session", tags = ?tags)
+}
+
+/// Encode `bytes` as lowercase hex, e.g. a SHA-256 digest.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Run `fut` to completion unless `deadline` passes first.
+///
+/// On expiry, returns a [`ClaudeSDKError::Timeout`] tagged with `phase`.
+pub(crate) async fn with_deadline<T>(
+    deadline: Option<Instant>,
+    phase: TimeoutPhase,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(deadline) = deadline else {
+        return fut.await;
+    };
+
+    let budget = deadline.saturating_duration_since(Instant::now());
+    match tokio::time::timeout_at(deadline, fut).await {
+        Ok(result) => result,
+        Err(_) => Err(ClaudeSDKError::timeout_in(phase, budget.as_millis() as u64)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_with_deadline() {
+        let ok = with_deadline(None, TimeoutPhase::Spawn, async { Ok(1) }).await;
+        assert_eq!(ok.unwrap(), 1);
+
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let err = with_deadline(Some(deadline), TimeoutPhase::Control, async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.timeout_phase(), Some(TimeoutPhase::Control));
+    }
+}
@@ -0,0 +1,256 @@
+//! Reconstruction of assistant messages from partial stream events.
+//!
+//! With `include_partial_messages`, the CLI streams the raw API events of
+//! each assistant message before delivering the complete message. If the
+//! turn is interrupted or the CLI exits mid-message, the complete message
+//! never arrives and only the deltas seen so far describe what the model
+//! produced. [`PartialMessageAccumulator`] keeps those deltas and rebuilds a
+//! best-effort [`AssistantMessage`] from them, flagged as `synthesized`.
+
+use serde_json::Value;
+
+use crate::types::{AssistantMessage, ContentBlock, TextBlock, ThinkingBlock, ToolUseBlock};
+
+/// A content block being streamed.
+enum PartialBlock {
+    Text(String),
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input_json: String,
+        /// Whether the block's stop event arrived, so the input is whole.
+        complete: bool,
+    },
+    /// A block type this accumulator doesn't reconstruct.
+    Other,
+}
+
+impl PartialBlock {
+    fn start(block: &Value) -> Self {
+        let text = |key: &str| {
+            block
+                .get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        match block.get("type").and_then(|v| v.as_str()) {
+            Some("text") => PartialBlock::Text(text("text")),
+            Some("thinking") => PartialBlock::Thinking {
+                thinking: text("thinking"),
+                signature: text("signature"),
+            },
+            Some("tool_use") => PartialBlock::ToolUse {
+                id: text("id"),
+                name: text("name"),
+                input_json: String::new(),
+                complete: false,
+            },
+            _ => PartialBlock::Other,
+        }
+    }
+
+    fn apply(&mut self, delta: &Value) {
+        let text = |key: &str| delta.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+        match (self, delta.get("type").and_then(|v| v.as_str())) {
+            (PartialBlock::Text(buffer), Some("text_delta")) => buffer.push_str(text("text")),
+            (PartialBlock::Thinking { thinking, .. }, Some("thinking_delta")) => {
+                thinking.push_str(text("thinking"))
+            }
+            (PartialBlock::Thinking { signature, .. }, Some("signature_delta")) => {
+                signature.push_str(text("signature"))
+            }
+            (PartialBlock::ToolUse { input_json, .. }, Some("input_json_delta")) => {
+                input_json.push_str(text("partial_json"))
+            }
+            _ => {}
+        }
+    }
+
+    fn into_content_block(self) -> Option<ContentBlock> {
+        match self {
+            PartialBlock::Text(text) if !text.is_empty() => {
+                Some(ContentBlock::Text(TextBlock { text }))
+            }
+            PartialBlock::Thinking {
+                thinking,
+                signature,
+            } if !thinking.is_empty() => Some(ContentBlock::Thinking(ThinkingBlock {
+                thinking,
+                signature,
+            })),
+            // A tool call cut off mid-stream was never made; presenting it
+            // with a guessed input would misreport what the model asked for
+            PartialBlock::ToolUse {
+                id,
+                name,
+                input_json,
+                complete: true,
+            } => {
+                let input = if input_json.trim().is_empty() {
+                    Value::Object(Default::default())
+                } else {
+                    serde_json::from_str(&input_json).ok()?
+                };
+                Some(ContentBlock::ToolUse(ToolUseBlock { id, name, input }))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Accumulates the stream events of the assistant message in progress.
+#[derive(Default)]
+pub(crate) struct PartialMessageAccumulator {
+    model: Option<String>,
+    parent_tool_use_id: Option<String>,
+    /// Blocks streamed since the last delivered assistant message.
+    blocks: Vec<(u64, PartialBlock)>,
+}
+
+impl PartialMessageAccumulator {
+    /// Record a stream event.
+    ///
+    /// Returns the synthesized remainder of the previous message if `event`
+    /// starts a new one before it was delivered.
+    pub(crate) fn observe(
+        &mut self,
+        event: &Value,
+        parent_tool_use_id: Option<&str>,
+    ) -> Option<AssistantMessage> {
+        match event.get("type").and_then(|v| v.as_str()) {
+            Some("message_start") => {
+                let pending = self.flush();
+                self.model = event
+                    .pointer("/message/model")
+                    .and_then(|v| v.as_str())
+                    .map(String::from);
+                self.parent_tool_use_id = parent_tool_use_id.map(String::from);
+                return pending;
+            }
+            Some("content_block_start") => {
+                if let (Some(index), Some(block)) = (
+                    event.get("index").and_then(|v| v.as_u64()),
+                    event.get("content_block"),
+                ) {
+                    self.blocks.push((index, PartialBlock::start(block)));
+                }
+            }
+            Some("content_block_delta") => {
+                let index = event.get("index").and_then(|v| v.as_u64());
+                if let (Some((_, block)), Some(delta)) = (
+                    self.blocks
+                        .iter_mut()
+                        .rev()
+                        .find(|(i, _)| Some(*i) == index),
+                    event.get("delta"),
+                ) {
+                    block.apply(delta);
+                }
+            }
+            Some("content_block_stop") => {
+                let index = event.get("index").and_then(|v| v.as_u64());
+                if let Some((_, PartialBlock::ToolUse { complete, .. })) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|(i, _)| Some(*i) == index)
+                {
+                    *complete = true;
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Forget the blocks streamed so far; the CLI delivered them as a
+    /// complete assistant message.
+    pub(crate) fn delivered(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Build a message from blocks that were streamed but never delivered.
+    pub(crate) fn flush(&mut self) -> Option<AssistantMessage> {
+        let content: Vec<ContentBlock> = self
+            .blocks
+            .drain(..)
+            .filter_map(|(_, block)| block.into_content_block())
+            .collect();
+        if content.is_empty() {
+            return None;
+        }
+        Some(AssistantMessage {
+            content,
+            model: self.model.clone().unwrap_or_else(|| "unknown".to_string()),
+            parent_tool_use_id: self.parent_tool_use_id.clone(),
+            uuid: None,
+            error: None,
+            synthesized: true,
+            meta: None,
+            raw: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_flush_rebuilds_undelivered_blocks() {
+        let mut acc = PartialMessageAccumulator::default();
+        let events = [
+            json!({"type": "message_start", "message": {"model": "claude-sonnet-4-5"}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "check."}}),
+            json!({"type": "content_block_stop", "index": 0}),
+        ];
+        for event in &events {
+            assert!(acc.observe(event, None).is_none());
+        }
+        acc.delivered();
+        assert!(acc.flush().is_none());
+
+        acc.observe(
+            &json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "Bash"}}),
+            None,
+        );
+        acc.observe(
+            &json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"command\": \"ls"}}),
+            None,
+        );
+
+        // The call was cut off before its input was whole, so it isn't
+        // reported at all
+        assert!(acc.flush().is_none());
+
+        acc.observe(
+            &json!({"type": "content_block_start", "index": 2, "content_block": {"type": "tool_use", "id": "toolu_2", "name": "Bash"}}),
+            None,
+        );
+        acc.observe(
+            &json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "{\"command\": \"ls\"}"}}),
+            None,
+        );
+        acc.observe(&json!({"type": "content_block_stop", "index": 2}), None);
+
+        let message = acc.flush().unwrap();
+        assert!(message.synthesized);
+        assert_eq!(message.model, "claude-sonnet-4-5");
+        match &message.content[..] {
+            [ContentBlock::ToolUse(tool)] => {
+                assert_eq!(tool.name, "Bash");
+                assert_eq!(tool.input, json!({"command": "ls"}));
+            }
+            other => panic!("unexpected content: {:?}", other),
+        }
+        assert!(acc.flush().is_none());
+    }
+}
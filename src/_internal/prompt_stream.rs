@@ -0,0 +1,159 @@
+//! Prompts streamed to the CLI from a reader.
+//!
+//! A prompt frame is one line of JSON, so a prompt too large to hold in
+//! memory is written in pieces: the frame up to the opening quote of its
+//! content, the content escaped chunk by chunk as it is read, and the rest
+//! of the frame. Escaped JSON never contains a line break, so the pieces
+//! still make up a single line.
+
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::protocol::{encode_frame, UserInputMessage};
+
+/// Bytes read from the prompt reader at a time.
+pub(crate) const PROMPT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The text of a prompt frame before and after its content string, quotes
+/// excluded.
+pub(crate) fn frame_parts() -> Result<(String, String)> {
+    const EMPTY_CONTENT: &str = r#""content":"""#;
+
+    let frame = encode_frame(&UserInputMessage::text(""))?;
+    let at = frame.find(EMPTY_CONTENT).ok_or_else(|| {
+        ClaudeSDKError::internal("Prompt frame has no content string to stream into")
+    })?;
+    let split = at + EMPTY_CONTENT.len() - 1;
+    Ok((frame[..split].to_string(), frame[split..].to_string()))
+}
+
+/// Reads a prompt and hands it out as escaped JSON string content.
+///
+/// Invalid UTF-8 is replaced with U+FFFD, as
+/// [`String::from_utf8_lossy`] would; characters split between reads are
+/// kept whole.
+pub(crate) struct PromptChunks<R> {
+    reader: R,
+    buf: Vec<u8>,
+    /// Start of a character the last read cut short.
+    carry: Vec<u8>,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> PromptChunks<R> {
+    pub(crate) fn new(reader: R, chunk_size: usize) -> Self {
+        Self {
+            reader,
+            buf: vec![0; chunk_size.max(4)],
+            carry: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// The next piece of escaped content, or `None` after the end of the
+    /// prompt.
+    pub(crate) async fn next(&mut self) -> Option<Result<String>> {
+        while !self.done {
+            let read = match self.reader.read(&mut self.buf).await {
+                Ok(read) => read,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            };
+            let mut bytes = std::mem::take(&mut self.carry);
+            bytes.extend_from_slice(&self.buf[..read]);
+            if read == 0 {
+                self.done = true;
+                if bytes.is_empty() {
+                    break;
+                }
+            } else {
+                // Hold back a character the read ended in the middle of
+                let keep = incomplete_tail(&bytes);
+                self.carry = bytes.split_off(bytes.len() - keep);
+                if bytes.is_empty() {
+                    continue;
+                }
+            }
+            return Some(escape(&String::from_utf8_lossy(&bytes)));
+        }
+        None
+    }
+}
+
+/// Length of the UTF-8 sequence `bytes` ends in the middle of, if any.
+fn incomplete_tail(bytes: &[u8]) -> usize {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => 0,
+        // No error length means the input ended mid-character
+        Err(e) if e.error_len().is_none() => bytes.len() - e.valid_up_to(),
+        Err(_) => {
+            // Only the last three bytes can start an unfinished character
+            let start = bytes.len().saturating_sub(3);
+            (start..bytes.len())
+                .find(|&i| {
+                    std::str::from_utf8(&bytes[i..])
+                        .is_err_and(|e| e.valid_up_to() == 0 && e.error_len().is_none())
+                })
+                .map_or(0, |i| bytes.len() - i)
+        }
+    }
+}
+
+/// Escape `text` as the inside of a JSON string.
+fn escape(text: &str) -> Result<String> {
+    match encode_frame(&Value::from(text))? {
+        quoted if quoted.len() >= 2 => Ok(quoted[1..quoted.len() - 1].to_string()),
+        _ => Err(ClaudeSDKError::internal("Failed to escape prompt text")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn stream(input: &[u8], chunk_size: usize) -> String {
+        let (prefix, suffix) = frame_parts().unwrap();
+        let mut chunks = PromptChunks::new(input, chunk_size);
+        let mut line = prefix;
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.unwrap();
+            assert!(!chunk.contains('\n'));
+            line.push_str(&chunk);
+        }
+        line.push_str(&suffix);
+        line
+    }
+
+    #[tokio::test]
+    async fn test_streamed_frame_matches_encoded_frame() {
+        let prompt = "Summarize these logs:\n\"GET /\" 200\t€ 🦀\u{0}\n".repeat(50);
+        for chunk_size in [4, 5, 7, 1024] {
+            assert_eq!(
+                stream(prompt.as_bytes(), chunk_size).await,
+                encode_frame(&UserInputMessage::text(prompt.clone())).unwrap(),
+                "chunk size {}",
+                chunk_size
+            );
+        }
+        assert_eq!(
+            stream(b"", 8).await,
+            encode_frame(&UserInputMessage::text("")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utf8_is_replaced() {
+        let input = b"ok \xff\xfe and a cut-off \xe2\x82";
+        for chunk_size in [4, 64] {
+            let line = stream(input, chunk_size).await;
+            let frame: Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(
+                frame["message"]["content"],
+                String::from_utf8_lossy(input).as_ref()
+            );
+        }
+    }
+}
@@ -1,46 +1,396 @@
 //! Query control protocol handler.
 //!
-//! This module implements the bidirectional control protocol for communicating
-//! with the Claude CLI. It handles:
-//! - Message routing (regular messages vs control requests/responses)
-//! - Tool permission callbacks
-//! - Hook callback invocation
-//! - MCP server message routing
-//! - Control request/response lifecycle
-
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
-use tracing::{debug, error, trace, warn};
-use uuid::Uuid;
-
-use super::message_parser::{
-    is_control_request, is_control_response, parse_control_request, parse_control_response,
-    parse_message,
-};
-use super::transport::{SubprocessTransport, Transport};
-use crate::errors::{ClaudeSDKError, Result};
+//! This module runs the bidirectional control protocol over the subprocess
+//! transport. The protocol state — frame routing, control request
+//! correlation, permission and hook callbacks — lives in the I/O-free
+//! [`ProtocolEngine`]; this module adds the I/O around it:
+//! - The reader task feeding CLI output to the engine, with callbacks
+//!   answered on separate tasks so slow ones don't hold up messages
+//! - Message delivery policies (history, spooling, partial messages)
+//! - Writing control requests and responses to the transport
+//! - Timeouts, deadlines and shutdown
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio::sync::{broadcast, mpsc, watch, RwLock};
+use tokio::time::Instant;
+use tracing::{debug, error, info, trace, warn, Instrument, Span};
+
+use super::callbacks::{ordering_key, CallbackPool, ResponseQueue, ResponseWriter};
+use super::client::SessionSettings;
+use super::coalesce::DeltaCoalescer;
+use super::context_window;
+use super::escalation::{Escalation, EscalationSlot};
+use super::frame_limit::FrameMeter;
+use super::partial::PartialMessageAccumulator;
+use super::prompt_stream::{self, PromptChunks};
+use super::spool::Spooler;
+#[cfg(feature = "subprocess")]
+use super::transport::SubprocessTransport;
+use super::transport::TransportHandle;
+use super::watchdog::{StreamTimer, TurnWatchdog};
+use super::AUDIT_TARGET;
+use crate::errors::{ClaudeSDKError, Result, TimeoutPhase};
+use crate::paths::PathResolver;
+use crate::policy::PermissionPolicy;
+use crate::progress::Progress;
+use crate::protocol::engine::{Incoming, ProtocolEngine};
+use crate::protocol::parser::{parse_message, parse_message_with_raw};
+use crate::protocol::wire::{self, HookMatcherConfig};
+use crate::protocol::{encode_frame, ControlRequest, ControlRequestPayload, UserInputMessage};
 use crate::types::*;
 
-/// Counter for generating unique request IDs.
-static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// Why the reader task stopped.
+struct ReaderExit {
+    reason: String,
+    /// Whether the reader stopped on its own rather than on shutdown or
+    /// for nothing reading its messages any more.
+    failed: bool,
+}
+
+impl ReaderExit {
+    fn failed(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+            failed: true,
+        }
+    }
+
+    fn stopped(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+            failed: false,
+        }
+    }
+}
+
+/// Messages buffered per subscriber unless configured otherwise.
+pub(crate) const DEFAULT_SUBSCRIBER_CAPACITY: usize = 256;
+
+/// Messages held back while paused before the reader stops reading from
+/// the CLI.
+const PAUSE_BUFFER_SIZE: usize = 64;
+
+/// How long the reader task gets to stop on shutdown.
+const READER_STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the CLI gets to answer a request the reader makes, such as
+/// interrupting a turn over budget.
+const OFF_READER_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bounded ring of the most recently received messages.
+struct MessageHistory {
+    capacity: usize,
+    messages: VecDeque<Message>,
+}
+
+impl MessageHistory {
+    fn new(capacity: usize) -> Self {
+        // Grown as messages arrive: the capacity is a limit, which may be
+        // far more than a session ever receives
+        Self {
+            capacity,
+            messages: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, message: &Message) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message.clone());
+    }
+
+    fn recent(&self, n: usize) -> Vec<Message> {
+        let skip = self.messages.len().saturating_sub(n);
+        self.messages.iter().skip(skip).cloned().collect()
+    }
+}
 
-/// Generate a unique request ID.
-fn generate_request_id() -> String {
-    let count = REQUEST_COUNTER.fetch_add(1, Ordering::SeqCst);
-    let uuid = Uuid::new_v4();
-    let uuid_str = uuid.to_string();
-    format!("req_{}_{}", count, &uuid_str[..8])
+/// Tool uses seen in assistant messages whose results haven't arrived.
+#[derive(Default)]
+struct ToolUseTracker {
+    pending: Vec<PendingToolUse>,
 }
 
-/// Pending control request waiting for response.
-struct PendingRequest {
-    sender: oneshot::Sender<Result<serde_json::Value>>,
+impl ToolUseTracker {
+    fn observe(&mut self, message: &Message) {
+        match message {
+            Message::Assistant(assistant) => {
+                for block in &assistant.content {
+                    if let ContentBlock::ToolUse(tool_use) = block {
+                        self.pending.push(PendingToolUse {
+                            id: tool_use.id.clone(),
+                            name: tool_use.name.clone(),
+                            parent_tool_use_id: assistant.parent_tool_use_id.clone(),
+                            observed_at: Instant::now().into_std(),
+                        });
+                    }
+                }
+            }
+            Message::User(user) => {
+                if let UserMessageContent::Blocks(ref blocks) = user.content {
+                    for block in blocks {
+                        if let ContentBlock::ToolResult(result) = block {
+                            self.pending.retain(|t| t.id != result.tool_use_id);
+                        }
+                    }
+                }
+            }
+            // Nothing runs past the end of a turn
+            Message::Result(_) => self.pending.clear(),
+            _ => {}
+        }
+    }
+}
+
+/// Prompts sent whose turns haven't ended, oldest first, each marked
+/// whether its messages are kept from the consumer.
+#[derive(Default)]
+struct TurnQueue(std::sync::Mutex<VecDeque<bool>>);
+
+impl TurnQueue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<bool>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record a prompt about to be written.
+    fn sent(&self, hidden: bool) {
+        self.lock().push_back(hidden);
+    }
+
+    /// Forget the last prompt recorded, which couldn't be written.
+    fn unsent(&self) {
+        self.lock().pop_back();
+    }
+
+    /// Whether the turn in progress is hidden.
+    fn is_hidden(&self) -> bool {
+        self.lock().front().copied().unwrap_or(false)
+    }
+
+    /// End the turn in progress, returning whether it was hidden.
+    fn ended(&self) -> bool {
+        self.lock().pop_front().unwrap_or(false)
+    }
+}
+
+/// Records protocol anomalies and applies the configured strictness.
+#[derive(Clone)]
+struct AnomalyTracker {
+    strictness: ProtocolStrictness,
+    report: Arc<RwLock<ProtocolAnomalyReport>>,
+}
+
+impl AnomalyTracker {
+    /// Record an anomaly, returning the error to surface in strict mode.
+    async fn record(&self, anomaly: ProtocolAnomaly) -> Option<ClaudeSDKError> {
+        self.report.write().await.record(&anomaly);
+        match &self.strictness {
+            ProtocolStrictness::Warn => {
+                warn!("Protocol anomaly: {}", anomaly);
+                None
+            }
+            ProtocolStrictness::Error => Some(ClaudeSDKError::ControlProtocol {
+                message: format!("Protocol anomaly: {}", anomaly),
+                request_id: anomaly.request_id,
+            }),
+            ProtocolStrictness::Callback(callback) => {
+                callback(&anomaly);
+                None
+            }
+        }
+    }
+}
+
+/// Sleep until `deadline`, or forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The consumer end of the reader, holding messages back while paused.
+struct Outbox {
+    tx: mpsc::Sender<Result<Message>>,
+    paused: watch::Receiver<bool>,
+    held: std::sync::Mutex<VecDeque<Result<Message>>>,
+    /// How many stream events may wait for a full channel, if they may be
+    /// dropped at all.
+    max_pending_partials: Option<usize>,
+    /// Stream events waiting for room in the channel, oldest first.
+    pending_partials: std::sync::Mutex<VecDeque<Result<Message>>>,
+}
+
+impl Outbox {
+    /// Send `item` to the consumer, or hold it while paused.
+    ///
+    /// Returns `false` if the message receiver was dropped.
+    async fn send(&self, item: Result<Message>) -> bool {
+        {
+            let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+            // Once anything is held, keep holding to preserve the order
+            if *self.paused.borrow() || !held.is_empty() {
+                held.push_back(item);
+                return true;
+            }
+        }
+        if let Some(max) = self.max_pending_partials {
+            let earlier = {
+                let mut pending = self
+                    .pending_partials
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                if !self.try_send_pending(&mut pending) {
+                    return false;
+                }
+                if matches!(item, Ok(Message::StreamEvent(_))) {
+                    // Never waits: a stream event that doesn't fit queues up,
+                    // pushing out the oldest
+                    let item = match pending.is_empty() {
+                        true => match self.tx.try_send(item) {
+                            Ok(()) => return true,
+                            Err(mpsc::error::TrySendError::Full(item)) => item,
+                            Err(mpsc::error::TrySendError::Closed(_)) => return false,
+                        },
+                        false => item,
+                    };
+                    pending.push_back(item);
+                    if pending.len() > max {
+                        pending.pop_front();
+                        trace!("Dropped a stream event the consumer fell behind on");
+                    }
+                    return true;
+                }
+                std::mem::take(&mut *pending)
+            };
+            // Other messages wait, after the stream events before them
+            for partial in earlier {
+                if self.tx.send(partial).await.is_err() {
+                    return false;
+                }
+            }
+        }
+        self.tx.send(item).await.is_ok()
+    }
+
+    /// Move waiting stream events into the channel while it has room.
+    ///
+    /// Returns `false` if the message receiver was dropped.
+    fn try_send_pending(&self, pending: &mut VecDeque<Result<Message>>) -> bool {
+        while let Some(partial) = pending.pop_front() {
+            match self.tx.try_send(partial) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(partial)) => {
+                    pending.push_front(partial);
+                    break;
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => return false,
+            }
+        }
+        true
+    }
+
+    /// Whether stream events wait that could be sent now.
+    fn has_pending_partials(&self) -> bool {
+        !*self.paused.borrow()
+            && !self
+                .pending_partials
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .is_empty()
+    }
+
+    /// Send the oldest waiting stream event with `permit`.
+    fn send_pending_partial(&self, permit: mpsc::Permit<'_, Result<Message>>) {
+        let partial = self
+            .pending_partials
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front();
+        if let Some(partial) = partial {
+            permit.send(partial);
+        }
+    }
+
+    /// Whether the reader should stop reading until resumed.
+    fn is_full(&self) -> bool {
+        self.held.lock().unwrap_or_else(|e| e.into_inner()).len() >= PAUSE_BUFFER_SIZE
+    }
+
+    /// Send the held messages, stopping early if paused again.
+    ///
+    /// Returns `false` if the message receiver was dropped.
+    async fn release(&self, ignore_pause: bool) -> bool {
+        // Stream events waiting since before the pause go first
+        let earlier = std::mem::take(
+            &mut *self
+                .pending_partials
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+        for partial in earlier {
+            if self.tx.send(partial).await.is_err() {
+                return false;
+            }
+        }
+        loop {
+            if !ignore_pause && *self.paused.borrow() {
+                return true;
+            }
+            let item = self
+                .held
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .pop_front();
+            let Some(item) = item else {
+                return true;
+            };
+            if self.tx.send(item).await.is_err() {
+                return false;
+            }
+        }
+    }
 }
 
-/// Default timeout for CLI operations in seconds (5 minutes).
-const DEFAULT_TIMEOUT_SECS: u64 = 300;
+/// Shared state handed to the background reader task.
+struct ReaderContext {
+    transport: Arc<TransportHandle>,
+    outbox: Outbox,
+    responses: Arc<ResponseQueue>,
+    subscribers: broadcast::Sender<Arc<Message>>,
+    engine: Arc<ProtocolEngine>,
+    history: Arc<RwLock<MessageHistory>>,
+    tool_uses: Arc<RwLock<ToolUseTracker>>,
+    session_id: Arc<RwLock<Option<String>>>,
+    keep_raw_messages: bool,
+    synthesize_partial_messages: bool,
+    partial_coalescing: Option<PartialCoalescing>,
+    spooler: Option<Spooler>,
+    turn_budget: Option<TurnBudget>,
+    timeouts: Timeouts,
+    prompt_sent: watch::Receiver<Option<Instant>>,
+    progress: Option<watch::Sender<Progress>>,
+    context_usage: watch::Sender<ContextUsage>,
+    context_policy: Option<ContextPolicy>,
+    context_full: AtomicBool,
+    compacted: AtomicBool,
+    compaction_blocked: Arc<AtomicBool>,
+    anomalies: AnomalyTracker,
+    deadline: Option<Instant>,
+    exit: Arc<OnceLock<ReaderExit>>,
+    manifest: Arc<RwLock<SessionManifest>>,
+    turns: Arc<TurnQueue>,
+    escalation: Option<(EscalationSlot, Arc<std::sync::Mutex<SessionSettings>>)>,
+}
 
 /// Query handler for the control protocol.
 ///
@@ -49,17 +399,26 @@ const DEFAULT_TIMEOUT_SECS: u64 = 300;
 /// of control requests.
 pub struct Query {
     /// Transport for CLI communication.
-    transport: Arc<Mutex<SubprocessTransport>>,
+    transport: Arc<TransportHandle>,
+    /// Size limit and tally of the frames the transport writes, readable
+    /// without locking it.
+    frame_meter: Arc<FrameMeter>,
     /// Channel for sending messages to the user (taken when start() is called).
     message_tx: Option<mpsc::Sender<Result<Message>>>,
-    /// Pending control requests awaiting responses.
-    pending_requests: Arc<RwLock<HashMap<String, PendingRequest>>>,
-    /// Tool permission callback.
-    can_use_tool: Option<CanUseTool>,
+    /// Sender for subscribers (taken when start() is called, like `message_tx`).
+    subscribers_tx: Option<broadcast::Sender<Arc<Message>>>,
+    /// Handle for new subscriptions that doesn't keep the channel open.
+    subscribers: broadcast::WeakSender<Arc<Message>>,
+    /// Whether message delivery is paused.
+    paused: watch::Sender<bool>,
+    /// Control protocol state: pending requests, permission and hook callbacks.
+    engine: Arc<ProtocolEngine>,
+    /// Answers to control requests waiting to be written.
+    responses: Arc<ResponseQueue>,
+    /// Writer of `responses` (taken when start() is called).
+    response_writer: Option<ResponseWriter>,
     /// Hook configurations.
     hooks: Option<HashMap<HookEvent, Vec<HookMatcher>>>,
-    /// Hook callback registry (callback_id -> callback function).
-    hook_callbacks: Arc<RwLock<HashMap<String, HookCallback>>>,
     /// Whether the query has been started.
     started: bool,
     /// Background task handle.
@@ -68,35 +427,314 @@ pub struct Query {
     shutdown_tx: Option<mpsc::Sender<()>>,
     /// Server initialization result (stored after initialize()).
     initialization_result: Arc<RwLock<Option<serde_json::Value>>>,
-    /// Timeout for CLI operations in seconds (0 = no timeout).
-    timeout_secs: u64,
+    /// Timeouts of connecting, messages and control requests.
+    timeouts: Timeouts,
+    /// When the last prompt was sent, if one was.
+    prompt_sent: watch::Sender<Option<Instant>>,
+    /// Recently received messages (disabled when capacity is 0).
+    history: Arc<RwLock<MessageHistory>>,
+    /// Tool uses awaiting their results.
+    tool_uses: Arc<RwLock<ToolUseTracker>>,
+    /// Session ID reported by the most recent result message.
+    session_id: Arc<RwLock<Option<String>>>,
+    /// Policies whose quota counts to drop when the session ends.
+    permission_policies: Vec<PermissionPolicy>,
+    /// Span carrying the session tags, entered by the session's tasks.
+    span: Span,
+    /// Whether to attach raw JSON to parsed messages.
+    keep_raw_messages: bool,
+    /// Whether to rebuild undelivered assistant messages from stream events.
+    synthesize_partial_messages: bool,
+    /// How to merge text deltas, if at all.
+    partial_coalescing: Option<PartialCoalescing>,
+    /// How many stream events may wait for a slow consumer before the
+    /// oldest are dropped, if any may be.
+    max_pending_partials: Option<usize>,
+    /// Where to spool large tool results, if anywhere.
+    spooler: Option<Spooler>,
+    /// Caps on a single turn, if any.
+    turn_budget: Option<TurnBudget>,
+    /// Where to report the progress of the session, if anywhere.
+    progress: Option<watch::Sender<Progress>>,
+    /// How full the context window is.
+    context_usage: watch::Sender<ContextUsage>,
+    /// What to do as the context fills up, if anything.
+    context_policy: Option<ContextPolicy>,
+    /// Whether the last compaction the policy ran didn't happen, as when a
+    /// `PreCompact` hook blocks it.
+    compaction_blocked: Arc<AtomicBool>,
+    /// Protocol anomaly tracking.
+    anomalies: AnomalyTracker,
+    /// Overall deadline bounding control requests, prompts and the
+    /// message stream.
+    deadline: Option<Instant>,
+    /// Set once the reader task has stopped.
+    exit: Arc<OnceLock<ReaderExit>>,
+    /// Configuration the session runs with.
+    manifest: Arc<RwLock<SessionManifest>>,
+    /// Prompts whose turns are in progress.
+    turns: Arc<TurnQueue>,
+    /// Permission escalation to revert as responses end, and the session
+    /// values to update when it is.
+    escalation: Option<(EscalationSlot, Arc<std::sync::Mutex<SessionSettings>>)>,
+}
+
+/// Clonable sender of control requests to a session, usable without the
+/// [`Query`] it was taken from.
+#[derive(Clone)]
+pub(crate) struct ControlSender {
+    transport: Arc<TransportHandle>,
+    engine: Arc<ProtocolEngine>,
+    exit: Arc<OnceLock<ReaderExit>>,
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+}
+
+impl ControlSender {
+    /// Send a control request, waiting at most `limit` for the response
+    /// as well as the configured timeouts.
+    async fn send(
+        &self,
+        payload: ControlRequestPayload,
+        limit: Option<Duration>,
+    ) -> Result<serde_json::Value> {
+        // Register pending request
+        let (request, pending) = self.engine.request(payload).await;
+        let request_id = request.request_id.clone();
+        if let Err(e) = ensure_running(&self.exit) {
+            self.engine.cancel(&request_id).await;
+            return Err(e);
+        }
+
+        // Send request
+        let written = async { self.transport.write(&encode_frame(&request)?).await }.await;
+        if let Err(e) = written {
+            self.engine.cancel(&request_id).await;
+            return Err(e);
+        }
+
+        // Wait for response with timeout (effectively none if not set)
+        let timeout_duration = self
+            .timeouts
+            .control_request
+            .unwrap_or(Duration::from_secs(86400 * 365));
+        // The overall deadline wins if it expires first
+        let timeout_duration = match self.deadline {
+            Some(deadline) => {
+                timeout_duration.min(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => timeout_duration,
+        };
+        let timeout_duration = limit.map_or(timeout_duration, |l| l.min(timeout_duration));
+
+        match tokio::time::timeout(timeout_duration, pending.wait()).await {
+            Ok(result) => result,
+            Err(_) => {
+                // Remove from pending
+                self.engine.cancel(&request_id).await;
+                Err(ClaudeSDKError::timeout_in(
+                    TimeoutPhase::Control,
+                    timeout_duration.as_millis() as u64,
+                ))
+            }
+        }
+    }
+
+    /// Send an interrupt request.
+    pub(crate) async fn interrupt(&self) -> Result<()> {
+        self.send(ControlRequestPayload::Interrupt, None).await?;
+        Ok(())
+    }
+
+    /// Get the sizes of the frames written to the CLI so far.
+    pub(crate) fn outbound_frame_stats(&self) -> OutboundFrameStats {
+        self.transport.frame_meter().stats()
+    }
+}
+
+impl std::fmt::Debug for ControlSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ControlSender").finish_non_exhaustive()
+    }
+}
+
+/// The control sender of a client's current session, if it has one,
+/// shared with its split handles.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ControlSlot(Arc<std::sync::Mutex<Option<ControlSender>>>);
+
+impl ControlSlot {
+    /// Get the sender of the current session, if connected.
+    pub(crate) fn get(&self) -> Option<ControlSender> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the sender as a session starts or ends.
+    pub(crate) fn set(&self, sender: Option<ControlSender>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = sender;
+    }
+}
+
+/// Fail fast if the reader task has stopped.
+fn ensure_running(exit: &OnceLock<ReaderExit>) -> Result<()> {
+    match exit.get() {
+        Some(exit) => Err(ClaudeSDKError::cli_connection(exit.reason.clone())),
+        None => Ok(()),
+    }
 }
 
 impl Query {
     /// Create a new Query handler.
+    #[cfg(feature = "subprocess")]
     pub fn new(
         transport: SubprocessTransport,
         options: &ClaudeAgentOptions,
+    ) -> (Self, mpsc::Receiver<Result<Message>>) {
+        Self::with_handle(TransportHandle::subprocess(transport), options)
+    }
+
+    /// The hooks of `options`, with those enforcing its workspace guard
+    /// and tool schemas first.
+    fn hooks(options: &ClaudeAgentOptions) -> Option<HashMap<HookEvent, Vec<HookMatcher>>> {
+        let guard = options
+            .workspace_guard
+            .as_ref()
+            .map(|guard| guard.hooks(PathResolver::from_options(options)));
+        let policies = options.permission_policies.iter().map(|p| p.hooks());
+        let schemas = options
+            .tool_schemas
+            .as_ref()
+            .filter(|registry| registry.denies_invalid())
+            .map(|registry| registry.hooks());
+        let mut enforced = guard.into_iter().chain(policies).chain(schemas).peekable();
+        if enforced.peek().is_none() {
+            return options.hooks.clone();
+        }
+        let mut hooks = HashMap::<HookEvent, Vec<HookMatcher>>::new();
+        for (event, matchers) in enforced
+            .flat_map(|r| r.into_hooks())
+            .chain(options.hooks.clone().into_iter().flatten())
+        {
+            hooks.entry(event).or_default().extend(matchers);
+        }
+        Some(hooks)
+    }
+
+    pub(crate) fn with_handle(
+        transport: TransportHandle,
+        options: &ClaudeAgentOptions,
     ) -> (Self, mpsc::Receiver<Result<Message>>) {
         let (message_tx, message_rx) = mpsc::channel(256);
+        let (subscribers_tx, _) = broadcast::channel(
+            options
+                .subscriber_capacity
+                .unwrap_or(DEFAULT_SUBSCRIBER_CAPACITY)
+                .max(1),
+        );
+        let mut engine = ProtocolEngine::new(options.can_use_tool.clone());
+        if let Some(ref registry) = options.tool_schemas {
+            engine = engine.with_tool_schemas(registry.clone());
+        }
+        let (responses, response_writer) = ResponseQueue::new();
 
         let query = Self {
-            transport: Arc::new(Mutex::new(transport)),
+            frame_meter: Arc::clone(transport.frame_meter()),
+            transport: Arc::new(transport),
             message_tx: Some(message_tx),
-            pending_requests: Arc::new(RwLock::new(HashMap::new())),
-            can_use_tool: options.can_use_tool.clone(),
-            hooks: options.hooks.clone(),
-            hook_callbacks: Arc::new(RwLock::new(HashMap::new())),
+            subscribers: subscribers_tx.downgrade(),
+            subscribers_tx: Some(subscribers_tx),
+            paused: watch::channel(false).0,
+            engine: Arc::new(engine),
+            responses,
+            response_writer: Some(response_writer),
+            hooks: Self::hooks(options),
             started: false,
             reader_task: None,
             shutdown_tx: None,
             initialization_result: Arc::new(RwLock::new(None)),
-            timeout_secs: options.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS),
+            timeouts: options.timeouts,
+            prompt_sent: watch::channel(None).0,
+            history: Arc::new(RwLock::new(MessageHistory::new(
+                options.message_history_size,
+            ))),
+            tool_uses: Arc::new(RwLock::new(ToolUseTracker::default())),
+            session_id: Arc::new(RwLock::new(None)),
+            permission_policies: options.permission_policies.clone(),
+            span: super::session_span(&options.session_tags),
+            keep_raw_messages: options.keep_raw_messages,
+            synthesize_partial_messages: options.include_partial_messages
+                && options.synthesize_partial_messages,
+            partial_coalescing: options
+                .partial_coalescing
+                .filter(|_| options.include_partial_messages),
+            max_pending_partials: options
+                .max_pending_partial_messages
+                .filter(|_| options.include_partial_messages)
+                .map(|max| max.max(1)),
+            spooler: options.tool_output_spool.clone().map(Spooler::new),
+            turn_budget: options.turn_budget,
+            progress: None,
+            context_usage: watch::channel(ContextUsage {
+                used_tokens: 0,
+                context_window: options
+                    .context_policy
+                    .as_ref()
+                    .map_or(ContextPolicy::DEFAULT_CONTEXT_WINDOW, |policy| {
+                        policy.context_window
+                    }),
+            })
+            .0,
+            context_policy: options.context_policy.clone(),
+            compaction_blocked: Arc::default(),
+            anomalies: AnomalyTracker {
+                strictness: options.protocol_strictness.clone(),
+                report: Arc::new(RwLock::new(ProtocolAnomalyReport::default())),
+            },
+            deadline: options.deadline.map(Instant::from_std),
+            exit: Arc::new(OnceLock::new()),
+            manifest: Arc::new(RwLock::new(SessionManifest::from_options(options))),
+            turns: Arc::default(),
+            escalation: None,
         };
 
         (query, message_rx)
     }
 
+    /// Report the progress of the session, derived from its messages, to
+    /// `progress`.
+    pub(crate) fn with_progress(mut self, progress: watch::Sender<Progress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Revert the permission escalation in `slot` as the response that
+    /// expires it ends, recording the mode in `settings`.
+    pub(crate) fn with_escalation(
+        mut self,
+        slot: EscalationSlot,
+        settings: Arc<std::sync::Mutex<SessionSettings>>,
+    ) -> Self {
+        self.escalation = Some((slot, settings));
+        self
+    }
+
+    /// How full the context window is, as of the last API call.
+    pub fn context_usage(&self) -> ContextUsage {
+        *self.context_usage.borrow()
+    }
+
+    /// Count the context as empty until the next API call.
+    pub(crate) fn reset_context_usage(&self) {
+        self.context_usage
+            .send_modify(|usage| usage.used_tokens = 0);
+    }
+
+    /// Whether the last compaction run for the context policy didn't
+    /// happen, and none has since.
+    pub(crate) fn compaction_blocked(&self) -> bool {
+        self.compaction_blocked.load(Ordering::Relaxed)
+    }
+
     /// Start the query handler.
     ///
     /// This spawns a background task that reads messages from the transport
@@ -107,40 +745,71 @@ impl Query {
         }
 
         // Get the stdout receiver from transport
-        let stdout_rx = {
-            let mut transport = self.transport.lock().await;
-            transport.take_stdout_rx().ok_or_else(|| {
+        // In non-streaming mode, the prompt went with the arguments
+        if !self.transport.is_streaming_mode().await {
+            self.prompt_sent.send_replace(Some(Instant::now()));
+        }
+        let stdout_rx =
+            self.transport.take_messages().await.ok_or_else(|| {
                 ClaudeSDKError::internal("Transport stdout receiver already taken")
-            })?
-        };
+            })?;
 
+        let response_writer = self.response_writer.take().ok_or_else(|| {
+            ClaudeSDKError::internal("Query already started (response writer already taken)")
+        })?;
         let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
         self.shutdown_tx = Some(shutdown_tx);
 
         // Clone references for the background task
-        let transport = Arc::clone(&self.transport);
-        // Take ownership of message_tx so that when the reader task finishes,
-        // the channel closes and the consumer stream ends
-        let message_tx = self.message_tx.take().ok_or_else(|| {
-            ClaudeSDKError::internal("Query already started (message_tx already taken)")
-        })?;
-        let pending_requests = Arc::clone(&self.pending_requests);
-        let can_use_tool = self.can_use_tool.clone();
-        let hook_callbacks = Arc::clone(&self.hook_callbacks);
+        let ctx = ReaderContext {
+            transport: Arc::clone(&self.transport),
+            outbox: Outbox {
+                // Take ownership of message_tx so that when the reader task finishes,
+                // the channel closes and the consumer stream ends
+                tx: self.message_tx.take().ok_or_else(|| {
+                    ClaudeSDKError::internal("Query already started (message_tx already taken)")
+                })?,
+                paused: self.paused.subscribe(),
+                held: Default::default(),
+                max_pending_partials: self.max_pending_partials,
+                pending_partials: Default::default(),
+            },
+            responses: Arc::clone(&self.responses),
+            subscribers: self.subscribers_tx.take().ok_or_else(|| {
+                ClaudeSDKError::internal("Query already started (subscribers_tx already taken)")
+            })?,
+            engine: Arc::clone(&self.engine),
+            history: Arc::clone(&self.history),
+            tool_uses: Arc::clone(&self.tool_uses),
+            session_id: Arc::clone(&self.session_id),
+            keep_raw_messages: self.keep_raw_messages,
+            synthesize_partial_messages: self.synthesize_partial_messages,
+            partial_coalescing: self.partial_coalescing,
+            spooler: self.spooler.clone(),
+            turn_budget: self.turn_budget,
+            timeouts: self.timeouts,
+            prompt_sent: self.prompt_sent.subscribe(),
+            progress: self.progress.clone(),
+            context_usage: self.context_usage.clone(),
+            context_policy: self.context_policy.clone(),
+            context_full: AtomicBool::new(false),
+            compacted: AtomicBool::new(false),
+            compaction_blocked: Arc::clone(&self.compaction_blocked),
+            anomalies: self.anomalies.clone(),
+            deadline: self.deadline,
+            exit: Arc::clone(&self.exit),
+            manifest: Arc::clone(&self.manifest),
+            turns: Arc::clone(&self.turns),
+            escalation: self.escalation.clone(),
+        };
 
         // Spawn background reader task
-        let reader_task = tokio::spawn(async move {
-            Self::read_messages(
-                stdout_rx,
-                transport,
-                message_tx,
-                pending_requests,
-                can_use_tool,
-                hook_callbacks,
-                &mut shutdown_rx,
-            )
-            .await;
-        });
+        let reader_task = tokio::spawn(
+            async move {
+                Self::read_messages(stdout_rx, ctx, response_writer, &mut shutdown_rx).await;
+            }
+            .instrument(self.span.clone()),
+        );
 
         self.reader_task = Some(reader_task);
         self.started = true;
@@ -152,300 +821,561 @@ impl Query {
     /// Background task that reads and routes messages.
     async fn read_messages(
         mut stdout_rx: mpsc::Receiver<Result<serde_json::Value>>,
-        transport: Arc<Mutex<SubprocessTransport>>,
-        message_tx: mpsc::Sender<Result<Message>>,
-        pending_requests: Arc<RwLock<HashMap<String, PendingRequest>>>,
-        can_use_tool: Option<CanUseTool>,
-        hook_callbacks: Arc<RwLock<HashMap<String, HookCallback>>>,
+        ctx: ReaderContext,
+        response_writer: ResponseWriter,
         shutdown_rx: &mut mpsc::Receiver<()>,
     ) {
-        loop {
+        let stream_budget = ctx
+            .deadline
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        // The deadline expires once; the session outlives it
+        let mut expired = false;
+        let mut partial = ctx
+            .synthesize_partial_messages
+            .then(PartialMessageAccumulator::default);
+        let mut coalescer = ctx.partial_coalescing.map(DeltaCoalescer::new);
+        let mut watchdog = ctx.turn_budget.map(TurnWatchdog::new);
+        let mut stream_timer = StreamTimer::new(&ctx.timeouts);
+        let mut prompt_sent = ctx.prompt_sent.clone();
+        if let Some(at) = *prompt_sent.borrow_and_update() {
+            stream_timer.prompt_sent(at);
+        }
+        let mut seq = 0;
+        // Result fields already warned about in this session
+        let mut unmodeled = HashSet::new();
+        // Dropped with the reader, aborting callbacks still running
+        let mut callbacks = CallbackPool::new(Arc::clone(&ctx.responses));
+        // Responses are written on a task of their own: a CLI slow to read
+        // its stdin must not hold up the messages it is still sending
+        let responder = tokio::spawn(
+            Self::write_control_responses(Arc::clone(&ctx.transport), response_writer)
+                .in_current_span(),
+        );
+        let mut paused = ctx.outbox.paused.clone();
+
+        let exit = 'reader: loop {
+            // The CLI isn't expected to send anything while it waits for
+            // answers, or while it can't send for the reader being paused
+            stream_timer.hold(callbacks.is_busy() || ctx.outbox.is_full());
+            let flush_at = coalescer.as_ref().and_then(|c| c.deadline());
+            let query_deadline = ctx.deadline.filter(|_| !expired);
             tokio::select! {
                 biased;
 
                 _ = shutdown_rx.recv() => {
                     debug!("Query reader received shutdown signal");
-                    break;
+                    break ReaderExit::stopped("Client disconnected");
                 }
 
-                msg = stdout_rx.recv() => {
+                _ = sleep_until(query_deadline) => {
+                    warn!("Query deadline expired while streaming, interrupting");
+                    expired = true;
+                    let budget = stream_budget.unwrap_or_default();
+                    Self::interrupt_turn(&ctx, "Failed to interrupt query past its deadline");
+                    if !ctx
+                        .outbox
+                        .send(Err(ClaudeSDKError::timeout_in(
+                            TimeoutPhase::Stream,
+                            budget.as_millis() as u64,
+                        )))
+                        .await
+                    {
+                        break ReaderExit::stopped("Message receiver dropped");
+                    }
+                }
+
+                _ = sleep_until(stream_timer.deadline()) => {
+                    let timeout = stream_timer.expire();
+                    warn!("No message from the CLI within {:?}", timeout);
+                    ctx.outbox
+                        .send(Err(ClaudeSDKError::timeout_in(
+                            TimeoutPhase::Stream,
+                            timeout.as_millis() as u64,
+                        )))
+                        .await;
+                    break ReaderExit::failed(format!("No message from the CLI within {:?}", timeout));
+                }
+
+                Ok(()) = prompt_sent.changed() => {
+                    if let Some(at) = *prompt_sent.borrow_and_update() {
+                        stream_timer.prompt_sent(at);
+                    }
+                }
+
+                _ = callbacks.finished(), if callbacks.is_busy() => {}
+
+                Ok(permit) = ctx.outbox.tx.reserve(), if ctx.outbox.has_pending_partials() => {
+                    ctx.outbox.send_pending_partial(permit);
+                }
+
+                Ok(()) = paused.changed() => {
+                    if !ctx.outbox.release(false).await {
+                        break ReaderExit::stopped("Message receiver dropped");
+                    }
+                }
+
+                _ = sleep_until(flush_at) => {
+                    if let Some(msg) = coalescer.as_mut().and_then(|c| c.flush()) {
+                        if !Self::deliver(&ctx, &mut partial, &mut seq, msg).await {
+                            break ReaderExit::stopped("Message receiver dropped");
+                        }
+                    }
+                }
+
+                // While paused with a full buffer, stop reading so the pipe
+                // pushes back on the CLI
+                msg = stdout_rx.recv(), if !ctx.outbox.is_full() => {
                     match msg {
                         Some(Ok(raw)) => {
-                            let msg_type = raw.get("type").and_then(|v| v.as_str()).unwrap_or("unknown");
+                            let msg_type = raw.get("type").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
                             trace!("Query received raw message of type: {}", msg_type);
+                            stream_timer.frame_received(msg_type == wire::RESULT);
 
                             // Route by message type
-                            if is_control_response(&raw) {
-                                debug!("Routing control response");
-                                Self::handle_control_response(raw, &pending_requests).await;
-                            } else if is_control_request(&raw) {
-                                debug!("Routing control request");
-                                Self::handle_control_request(
-                                    raw,
-                                    &transport,
-                                    &can_use_tool,
-                                    &hook_callbacks,
-                                ).await;
-                            } else {
-                                // Regular message
-                                debug!("Routing regular message of type: {}", msg_type);
-                                match parse_message(raw) {
-                                    Ok(msg) => {
-                                        if message_tx.send(Ok(msg)).await.is_err() {
-                                            debug!("Message receiver dropped");
-                                            break;
+                            let anomaly = match ctx.engine.receive(raw).await {
+                                Incoming::ControlResponse => None,
+                                Incoming::Anomaly(anomaly) => {
+                                    if let Some((request_id, response)) = ctx.engine.rejection(&anomaly) {
+                                        callbacks.spawn(request_id, None, async move { response });
+                                    }
+                                    Some(anomaly)
+                                }
+                                Incoming::ControlRequest { request, anomaly } => {
+                                    Self::handle_control_request(request, &mut callbacks, &ctx.engine);
+                                    anomaly
+                                }
+                                Incoming::Message(mut raw) => {
+                                    debug!("Routing regular message of type: {}", msg_type);
+                                    if let Some(ref mut watchdog) = watchdog {
+                                        if watchdog.observe(&mut raw) {
+                                            warn!("Turn over budget after {:?}, interrupting", watchdog.usage());
+                                            Self::interrupt_turn(&ctx, "Failed to interrupt turn over budget");
+                                        }
+                                    }
+                                    if let Some(tokens) = context_window::context_tokens(&raw) {
+                                        ctx.context_usage.send_modify(|usage| usage.used_tokens = tokens);
+                                        // A compaction boundary
+                                        if tokens == 0 {
+                                            ctx.compacted.store(true, Ordering::Relaxed);
+                                            ctx.compaction_blocked.store(false, Ordering::Relaxed);
                                         }
                                     }
-                                    Err(e) => {
-                                        warn!("Failed to parse message: {}", e);
-                                        if message_tx.send(Err(e)).await.is_err() {
-                                            break;
+                                    let unknown_type = !wire::MESSAGE_TYPES.contains(&msg_type.as_str());
+                                    let anomaly = unknown_type.then(|| ProtocolAnomaly {
+                                        kind: ProtocolAnomalyKind::UnknownMessageType,
+                                        subtype: raw.get("type").and_then(|v| v.as_str()).map(String::from),
+                                        request_id: None,
+                                        raw: raw.clone(),
+                                    });
+                                    if msg_type == wire::SYSTEM
+                                        && raw.get("subtype").and_then(|v| v.as_str()) == Some(wire::INIT)
+                                    {
+                                        Self::record_init(&ctx.manifest, &raw).await;
+                                    }
+                                    if let Some(ref spooler) = ctx.spooler {
+                                        spooler.spool_tool_results(&mut raw).await;
+                                    }
+                                    let parsed = if ctx.keep_raw_messages {
+                                        parse_message_with_raw(raw)
+                                    } else {
+                                        parse_message(raw)
+                                    };
+                                    match parsed {
+                                        Ok(msg) => {
+                                            warn_unmodeled_fields(&mut unmodeled, &msg);
+                                            ctx.tool_uses.write().await.observe(&msg);
+                                            let ready = match coalescer.as_mut() {
+                                                Some(coalescer) => coalescer.push(msg),
+                                                None => vec![msg],
+                                            };
+                                            for msg in ready {
+                                                if !Self::deliver(&ctx, &mut partial, &mut seq, msg).await {
+                                                    break 'reader ReaderExit::stopped("Message receiver dropped");
+                                                }
+                                            }
                                         }
+                                        Err(e) => {
+                                            warn!("Failed to parse message: {}", e);
+                                            if let Some(msg) = coalescer.as_mut().and_then(|c| c.flush()) {
+                                                if !Self::deliver(&ctx, &mut partial, &mut seq, msg).await {
+                                                    break ReaderExit::stopped("Message receiver dropped");
+                                                }
+                                            }
+                                            // Leave a gap in the sequence for the dropped frame
+                                            seq += 1;
+                                            if !ctx.outbox.send(Err(e)).await {
+                                                break ReaderExit::stopped("Message receiver dropped");
+                                            }
+                                        }
+                                    }
+
+                                    // The parse error has already been surfaced
+                                    if let Some(anomaly) = anomaly {
+                                        ctx.anomalies.record(anomaly).await;
+                                    }
+                                    None
+                                }
+                            };
+
+                            if let Some(anomaly) = anomaly {
+                                if let Some(e) = ctx.anomalies.record(anomaly).await {
+                                    if !ctx.outbox.send(Err(e)).await {
+                                        break ReaderExit::stopped("Message receiver dropped");
                                     }
                                 }
                             }
                         }
                         Some(Err(e)) => {
                             error!("Error reading from transport: {}", e);
-                            let _ = message_tx.send(Err(e)).await;
-                            break;
+                            let reason = format!("Error reading from CLI: {}", e);
+                            ctx.outbox.send(Err(e)).await;
+                            break ReaderExit::failed(reason);
                         }
                         None => {
                             debug!("Transport stream ended");
-                            break;
+                            if let Some(msg) = coalescer.as_mut().and_then(|c| c.flush()) {
+                                Self::deliver(&ctx, &mut partial, &mut seq, msg).await;
+                            }
+                            if let Some(message) = partial.as_mut().and_then(|acc| acc.flush()) {
+                                Self::send_synthesized(&ctx, message, MessageMeta::next(&mut seq)).await;
+                            }
+                            break ReaderExit::failed("CLI process exited");
                         }
                     }
                 }
             }
-        }
+        };
 
+        // Release the transport before the reader counts as stopped
+        responder.abort();
+        let _ = responder.await;
+
+        let disconnected = !exit.failed;
+        Self::fail_pending_requests(&ctx.engine, &ctx.exit, exit).await;
+        if let Some(ref progress) = ctx.progress {
+            progress.send_replace(Progress::Idle);
+        }
+        // Nothing more will arrive: hand over what was held back, even if
+        // still paused, so the stream ends with it
+        if !disconnected {
+            ctx.outbox.release(true).await;
+        }
         debug!("Query reader task finished");
     }
 
-    /// Handle a control response from the CLI.
-    async fn handle_control_response(
-        raw: serde_json::Value,
-        pending_requests: &RwLock<HashMap<String, PendingRequest>>,
-    ) {
-        match parse_control_response(raw) {
-            Ok(response) => {
-                let request_id = response.request_id().to_string();
-                let mut pending = pending_requests.write().await;
-
-                if let Some(request) = pending.remove(&request_id) {
-                    let result = if response.is_success() {
-                        Ok(response.data().cloned().unwrap_or(serde_json::Value::Null))
-                    } else {
-                        Err(ClaudeSDKError::control_protocol_with_id(
-                            response.error().unwrap_or("Unknown error"),
-                            request_id,
-                        ))
-                    };
+    /// Interrupt the turn, off the reader, which has to read the answer.
+    fn interrupt_turn(ctx: &ReaderContext, failure: &'static str) {
+        let request = Self::request_off_reader(ctx, ControlRequestPayload::Interrupt);
+        tokio::spawn(
+            async move {
+                if let Err(e) = request.await {
+                    warn!("{}: {}", failure, e);
+                }
+            }
+            .in_current_span(),
+        );
+    }
 
-                    let _ = request.sender.send(result);
-                } else {
-                    warn!("Received response for unknown request: {}", request_id);
+    /// Revert an expired permission escalation, off the reader, putting it
+    /// back if that fails so the next query tries again.
+    fn revert_escalation(
+        ctx: &ReaderContext,
+        slot: EscalationSlot,
+        settings: Arc<std::sync::Mutex<SessionSettings>>,
+        escalation: Escalation,
+    ) {
+        let payload = set_permission_mode_payload(escalation.previous);
+        let request = Self::request_off_reader(ctx, payload);
+        tokio::spawn(
+            async move {
+                match request.await {
+                    Ok(_) => {
+                        settings
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .permission_mode = escalation.previous;
+                        escalation.log_reverted("expired");
+                    }
+                    Err(e) => {
+                        warn!("Failed to revert permission escalation: {}", e);
+                        slot.restore(escalation);
+                    }
                 }
             }
-            Err(e) => {
-                error!("Failed to parse control response: {}", e);
+            .in_current_span(),
+        );
+    }
+
+    /// Send a control request for a task other than the reader, which has
+    /// to read the answer.
+    fn request_off_reader(
+        ctx: &ReaderContext,
+        payload: ControlRequestPayload,
+    ) -> impl Future<Output = Result<serde_json::Value>> + Send + 'static {
+        let engine = Arc::clone(&ctx.engine);
+        let transport = Arc::clone(&ctx.transport);
+        async move {
+            let (request, pending) = engine.request(payload).await;
+            let request_id = request.request_id.clone();
+            let written = async { transport.write(&encode_frame(&request)?).await };
+            let answered = match written.await {
+                Ok(()) => tokio::time::timeout(OFF_READER_REQUEST_TIMEOUT, pending.wait())
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(ClaudeSDKError::timeout_in(
+                            TimeoutPhase::Control,
+                            OFF_READER_REQUEST_TIMEOUT.as_millis() as u64,
+                        ))
+                    }),
+                Err(e) => Err(e),
+            };
+            if answered.is_err() {
+                engine.cancel(&request_id).await;
             }
+            answered
         }
     }
 
-    /// Handle a control request from the CLI.
-    async fn handle_control_request(
-        raw: serde_json::Value,
-        transport: &Arc<Mutex<SubprocessTransport>>,
-        can_use_tool: &Option<CanUseTool>,
-        hook_callbacks: &RwLock<HashMap<String, HookCallback>>,
-    ) {
-        let request = match parse_control_request(raw.clone()) {
-            Ok(r) => r,
-            Err(e) => {
-                error!("Failed to parse control request: {}", e);
-                return;
+    /// Deliver a message to the consumer, synthesizing any assistant
+    /// message it shows was never delivered.
+    ///
+    /// Returns `false` if the message receiver was dropped.
+    async fn deliver(
+        ctx: &ReaderContext,
+        partial: &mut Option<PartialMessageAccumulator>,
+        seq: &mut u64,
+        mut msg: Message,
+    ) -> bool {
+        let hidden = ctx.turns.is_hidden();
+        let undelivered = match (partial.as_mut(), &msg) {
+            (Some(acc), Message::StreamEvent(event)) => {
+                acc.observe(&event.event, event.parent_tool_use_id.as_deref())
+            }
+            (Some(acc), Message::Assistant(_)) => {
+                acc.delivered();
+                None
             }
+            (Some(acc), Message::Result(_)) => acc.flush(),
+            _ => None,
         };
-
-        let request_id = request.request_id.clone();
-        let response = Self::process_control_request(request, can_use_tool, hook_callbacks).await;
-
-        // Send response back to CLI
-        let response_msg = match response {
-            Ok(data) => serde_json::json!({
-                "type": "control_response",
-                "response": {
-                    "subtype": "success",
-                    "request_id": request_id,
-                    "response": data
+        if let Some(message) = undelivered.filter(|_| !hidden) {
+            if !Self::send_synthesized(ctx, message, MessageMeta::next(seq)).await {
+                return false;
+            }
+        }
+        msg.set_meta(MessageMeta::next(seq));
+        ctx.history.write().await.push(&msg);
+        Self::publish(ctx, &msg);
+        if let Message::Result(ref result) = msg {
+            if !result.session_id.is_empty() {
+                *ctx.session_id.write().await = Some(result.session_id.clone());
+            }
+            let compacted = ctx.compacted.swap(false, Ordering::Relaxed);
+            if ctx.turns.ended() {
+                // A compaction: not to compact again if the CLI didn't
+                // mark it
+                ctx.context_usage.send_modify(|usage| usage.used_tokens = 0);
+                if result.is_error {
+                    warn!("Compaction failed: {:?}", result.result);
+                } else if !compacted {
+                    // Nor until the context is compacted some other way,
+                    // as the hook would block each compaction
+                    warn!("Compaction didn't happen, a PreCompact hook may have blocked it; not compacting again until the context is compacted");
+                    ctx.compaction_blocked.store(true, Ordering::Relaxed);
                 }
-            }),
-            Err(e) => serde_json::json!({
-                "type": "control_response",
-                "response": {
-                    "subtype": "error",
-                    "request_id": request_id,
-                    "error": e.to_string()
+            } else if let Some((ref slot, ref settings)) = ctx.escalation {
+                if let Some(escalation) = slot.response_received() {
+                    Self::revert_escalation(ctx, slot.clone(), Arc::clone(settings), escalation);
                 }
-            }),
-        };
-
-        let transport = transport.lock().await;
-        if let Err(e) = transport.write(&response_msg.to_string()).await {
-            error!("Failed to send control response: {}", e);
+            }
+        }
+        if hidden {
+            debug!("Not delivering message of a hidden turn");
+            return true;
         }
+        if !ctx.outbox.send(Ok(msg)).await {
+            debug!("Message receiver dropped");
+            return false;
+        }
+        true
     }
 
-    /// Process a control request and return the response.
-    async fn process_control_request(
-        request: ControlRequest,
-        can_use_tool: &Option<CanUseTool>,
-        hook_callbacks: &RwLock<HashMap<String, HookCallback>>,
-    ) -> Result<serde_json::Value> {
-        match request.request {
-            ControlRequestPayload::CanUseTool {
-                tool_name,
-                input,
-                permission_suggestions,
-                ..
-            } => {
-                if let Some(callback) = can_use_tool {
-                    let context = ToolPermissionContext {
-                        suggestions: permission_suggestions
-                            .map(|s| {
-                                s.into_iter()
-                                    .filter_map(|v| serde_json::from_value(v).ok())
-                                    .collect()
-                            })
-                            .unwrap_or_default(),
-                    };
+    /// Emit an assistant message rebuilt from stream events.
+    ///
+    /// Returns `false` if the message receiver was dropped.
+    async fn send_synthesized(
+        ctx: &ReaderContext,
+        message: AssistantMessage,
+        meta: MessageMeta,
+    ) -> bool {
+        debug!("Synthesized undelivered assistant message from stream events");
+        let mut message = Message::Assistant(message);
+        message.set_meta(meta);
+        ctx.history.write().await.push(&message);
+        Self::publish(ctx, &message);
+        ctx.outbox.send(Ok(message)).await
+    }
 
-                    let result = callback(tool_name, input, context).await;
-                    serde_json::to_value(result).map_err(|e| {
-                        ClaudeSDKError::internal(format!(
-                            "Failed to serialize PermissionResult: {}",
-                            e
-                        ))
-                    })
-                } else {
-                    // No callback - default to allow
-                    Ok(serde_json::json!({"behavior": "allow"}))
+    /// Hand a copy of `message` to the subscribers, if there are any, and
+    /// report the progress it shows.
+    fn publish(ctx: &ReaderContext, message: &Message) {
+        if ctx.subscribers.receiver_count() > 0 {
+            // Only fails when every subscriber is gone
+            let _ = ctx.subscribers.send(Arc::new(message.clone()));
+        }
+        let phase = Progress::from_message(message);
+        let phase = match ctx.context_policy {
+            Some(ref policy) => {
+                let usage = *ctx.context_usage.borrow();
+                let full = policy.is_exceeded_by(usage.used_tokens);
+                let crossed = full && !ctx.context_full.swap(full, Ordering::Relaxed);
+                if crossed {
+                    warn!(
+                        used_tokens = usage.used_tokens,
+                        context_window = usage.context_window,
+                        "Context nearly full"
+                    );
                 }
-            }
-
-            ControlRequestPayload::HookCallback {
-                callback_id,
-                input,
-                tool_use_id,
-            } => {
-                let callbacks = hook_callbacks.read().await;
-                if let Some(callback) = callbacks.get(&callback_id) {
-                    // Parse the hook input
-                    let hook_input: HookInput = serde_json::from_value(input).map_err(|e| {
-                        ClaudeSDKError::message_parse(format!("Failed to parse hook input: {}", e))
-                    })?;
-
-                    let context = HookContext::default();
-                    let output = callback(hook_input, tool_use_id, context).await;
-
-                    // Convert output for CLI (handle field renaming)
-                    let mut output_value = serde_json::to_value(&output).map_err(|e| {
-                        ClaudeSDKError::internal(format!("Failed to serialize HookOutput: {}", e))
-                    })?;
-
-                    // Rename async_ to async and continue_ to continue
-                    if let serde_json::Value::Object(ref mut map) = output_value {
-                        if let Some(v) = map.remove("async_") {
-                            map.insert("async".to_string(), v);
-                        }
-                        if let Some(v) = map.remove("continue_") {
-                            map.insert("continue".to_string(), v);
-                        }
-                    }
-
-                    Ok(output_value)
+                if crossed || (full && phase == Some(Progress::Idle)) {
+                    Some(Progress::ContextNearlyFull(usage))
                 } else {
-                    warn!("Unknown hook callback ID: {}", callback_id);
-                    Ok(serde_json::json!({}))
+                    phase
                 }
             }
+            None => phase,
+        };
+        if let (Some(progress), Some(phase)) = (&ctx.progress, phase) {
+            progress.send_if_modified(|current| {
+                let changed = *current != phase;
+                *current = phase;
+                changed
+            });
+        }
+    }
 
-            ControlRequestPayload::Initialize { .. } => {
-                // CLI is initializing - acknowledge
-                debug!("Received initialize request from CLI");
-                Ok(serde_json::json!({"initialized": true}))
-            }
+    /// Record why the reader stopped and fail every pending control request,
+    /// since no response can arrive any more.
+    async fn fail_pending_requests(
+        engine: &ProtocolEngine,
+        exit_slot: &OnceLock<ReaderExit>,
+        exit: ReaderExit,
+    ) {
+        if exit.failed {
+            warn!("Query reader stopped: {}", exit.reason);
+        }
+        // Set before draining so requests registered afterwards see it
+        let reason = exit.reason.clone();
+        let _ = exit_slot.set(exit);
+        engine.fail_pending(&reason).await;
+    }
 
-            ControlRequestPayload::McpMessage {
-                server_name,
-                message: _,
-            } => {
-                // MCP message routing - this would be implemented with full MCP support
-                debug!("Received MCP message for server: {}", server_name);
-                Err(ClaudeSDKError::internal(format!(
-                    "MCP server '{}' not found (SDK MCP not yet implemented)",
-                    server_name
-                )))
-            }
+    /// Fail fast if the reader task has stopped.
+    fn ensure_running(&self) -> Result<()> {
+        ensure_running(&self.exit)
+    }
 
-            _ => {
-                warn!("Unhandled control request type");
-                Ok(serde_json::Value::Null)
-            }
-        }
+    /// Get the configuration the session runs with.
+    pub async fn session_manifest(&self) -> SessionManifest {
+        self.manifest.read().await.clone()
     }
 
-    /// Send a control request to the CLI and wait for response.
-    pub async fn send_control_request(
-        &self,
-        payload: ControlRequestPayload,
-    ) -> Result<serde_json::Value> {
-        let request_id = generate_request_id();
+    /// Update the manifest from the CLI's `init` message and log it.
+    async fn record_init(manifest: &RwLock<SessionManifest>, raw: &serde_json::Value) {
+        let mut manifest = manifest.write().await;
+        manifest.apply_init(raw);
+        info!(
+            target: AUDIT_TARGET,
+            session_id = manifest.session_id.as_deref().unwrap_or_default(),
+            model = manifest.model.as_deref().unwrap_or_default(),
+            cli_version = manifest.cli_version.as_deref().unwrap_or_default(),
+            fingerprint = %manifest.fingerprint(),
+            manifest = %serde_json::to_string(&*manifest).unwrap_or_default(),
+            "Session initialized"
+        );
+    }
 
-        let request = serde_json::json!({
-            "type": "control_request",
-            "request_id": request_id,
-            "request": payload
+    /// Get the reason the session failed, if the reader task stopped
+    /// without being shut down.
+    pub fn failure(&self) -> Option<&str> {
+        self.exit
+            .get()
+            .filter(|exit| exit.failed)
+            .map(|exit| exit.reason.as_str())
+    }
+
+    /// Answer a control request from the CLI on a callback task.
+    fn handle_control_request(
+        request: ControlRequest,
+        callbacks: &mut CallbackPool,
+        engine: &Arc<ProtocolEngine>,
+    ) {
+        let request_id = request.request_id.clone();
+        let key = ordering_key(&request.request);
+        let engine = Arc::clone(engine);
+        callbacks.spawn(request_id, key, async move {
+            engine.answer(request).await.resolve().await
         });
+    }
 
-        // Register pending request
-        let (tx, rx) = oneshot::channel();
-        {
-            let mut pending = self.pending_requests.write().await;
-            pending.insert(request_id.clone(), PendingRequest { sender: tx });
+    /// Write the answers to control requests to the CLI as they complete.
+    async fn write_control_responses(transport: Arc<TransportHandle>, mut writer: ResponseWriter) {
+        while let Some(done) = writer.next().await {
+            Self::send_control_response(&transport, done.request_id, done.response).await;
+            writer.written();
         }
+    }
 
-        // Send request
+    /// Send the response to a control request back to the CLI.
+    async fn send_control_response(
+        transport: &TransportHandle,
+        request_id: String,
+        response: Result<serde_json::Value>,
+    ) {
+        let result = match transport
+            .frame_meter()
+            .encode_response(request_id, response)
         {
-            let transport = self.transport.lock().await;
-            transport.write(&request.to_string()).await?;
+            Ok(line) => transport.write(&line).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Failed to send control response: {}", e);
         }
+    }
 
-        // Wait for response with timeout (use configured timeout, or no timeout if 0)
-        let timeout_duration = if self.timeout_secs == 0 {
-            // Use a very long timeout (effectively no timeout)
-            std::time::Duration::from_secs(86400 * 365) // 1 year
-        } else {
-            std::time::Duration::from_secs(self.timeout_secs)
-        };
+    /// Send a control request to the CLI and wait for response.
+    pub async fn send_control_request(
+        &self,
+        payload: ControlRequestPayload,
+    ) -> Result<serde_json::Value> {
+        self.send_control_request_within(payload, None).await
+    }
 
-        match tokio::time::timeout(timeout_duration, rx).await {
-            Ok(Ok(result)) => result,
-            Ok(Err(_)) => Err(ClaudeSDKError::internal("Control request receiver dropped")),
-            Err(_) => {
-                // Remove from pending
-                let mut pending = self.pending_requests.write().await;
-                pending.remove(&request_id);
-                Err(ClaudeSDKError::timeout(self.timeout_secs * 1000))
-            }
+    /// Send a control request, waiting at most `limit` for the response
+    /// as well as the configured timeouts.
+    async fn send_control_request_within(
+        &self,
+        payload: ControlRequestPayload,
+        limit: Option<Duration>,
+    ) -> Result<serde_json::Value> {
+        self.control_sender().send(payload, limit).await
+    }
+
+    /// Get a sender of control requests that doesn't borrow the query.
+    pub(crate) fn control_sender(&self) -> ControlSender {
+        ControlSender {
+            transport: Arc::clone(&self.transport),
+            engine: Arc::clone(&self.engine),
+            exit: Arc::clone(&self.exit),
+            timeouts: self.timeouts,
+            deadline: self.deadline,
         }
     }
 
     /// Initialize the streaming session with the CLI.
     pub async fn initialize(&self) -> Result<serde_json::Value> {
         // Build hooks configuration for initialization
-        let hooks_config = self.build_hooks_config().await;
+        let hooks_config = self.build_hooks_config().await?;
 
         let result = self
             .send_control_request(ControlRequestPayload::Initialize {
@@ -471,28 +1401,80 @@ impl Query {
         init_result.clone()
     }
 
+    /// Subscribe to the messages received from now on.
+    ///
+    /// The receiver is closed once the reader stops.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Message>> {
+        match self.subscribers.upgrade() {
+            Some(sender) => sender.subscribe(),
+            None => broadcast::channel(1).1,
+        }
+    }
+
+    /// Hold messages back from the consumer until [`resume`](Self::resume).
+    ///
+    /// Control requests and responses keep being handled until
+    /// [`PAUSE_BUFFER_SIZE`] messages are held; then the reader stops
+    /// reading from the CLI altogether.
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Deliver the held messages and continue reading.
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    /// Whether message delivery is paused.
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Get up to `n` of the most recently received messages, oldest first.
+    ///
+    /// Returns an empty vector when message history is disabled.
+    pub async fn recent_messages(&self, n: usize) -> Vec<Message> {
+        self.history.read().await.recent(n)
+    }
+
+    /// Get the tool uses whose results haven't arrived, oldest first.
+    pub async fn pending_tool_uses(&self) -> Vec<PendingToolUse> {
+        self.tool_uses.read().await.pending.clone()
+    }
+
+    /// Get the session ID reported by the most recent result message.
+    pub async fn session_id(&self) -> Option<String> {
+        self.session_id.read().await.clone()
+    }
+
+    /// Get a summary of the protocol anomalies observed so far.
+    pub async fn protocol_anomalies(&self) -> ProtocolAnomalyReport {
+        self.anomalies.report.read().await.clone()
+    }
+
+    /// Get the sizes of the frames written to the CLI so far.
+    pub fn outbound_frame_stats(&self) -> OutboundFrameStats {
+        self.frame_meter.stats()
+    }
+
     /// Build hooks configuration for the initialize request.
-    async fn build_hooks_config(&self) -> Option<serde_json::Value> {
-        let hooks = self.hooks.as_ref()?;
-        let mut config = serde_json::Map::new();
+    async fn build_hooks_config(&self) -> Result<Option<serde_json::Value>> {
+        let Some(ref hooks) = self.hooks else {
+            return Ok(None);
+        };
+        let mut config: HashMap<HookEvent, Vec<HookMatcherConfig>> = HashMap::new();
 
         for (event, matchers) in hooks {
             let mut event_config = Vec::new();
 
             for (matcher_idx, matcher) in matchers.iter().enumerate() {
-                let mut matcher_config = serde_json::Map::new();
-
-                if let Some(ref pattern) = matcher.matcher {
-                    matcher_config.insert("matcher".to_string(), serde_json::json!(pattern));
-                }
-
-                if let Some(timeout) = matcher.timeout {
-                    matcher_config.insert("timeout".to_string(), serde_json::json!(timeout));
-                }
-
                 // Register callbacks with unique IDs across all matchers for this event
                 let mut callback_ids = Vec::new();
                 for (callback_idx, callback) in matcher.hooks.iter().enumerate() {
+                    let callback = match matcher.tool_matcher {
+                        Some(ref tool_matcher) => tool_matcher.filter(callback.clone()),
+                        None => callback.clone(),
+                    };
                     // Include matcher index to ensure uniqueness across matchers
                     let callback_id = format!(
                         "{}_{}_{}",
@@ -502,35 +1484,22 @@ impl Query {
                     );
                     callback_ids.push(callback_id.clone());
 
-                    let mut callbacks = self.hook_callbacks.write().await;
-                    callbacks.insert(callback_id, callback.clone());
+                    self.engine
+                        .register_hook_callback(callback_id, callback)
+                        .await;
                 }
 
-                if !callback_ids.is_empty() {
-                    matcher_config
-                        .insert("callbackIds".to_string(), serde_json::json!(callback_ids));
-                }
-
-                event_config.push(serde_json::Value::Object(matcher_config));
+                event_config.push(HookMatcherConfig {
+                    matcher: matcher.matcher.clone(),
+                    timeout: matcher.timeout,
+                    callback_ids,
+                });
             }
 
-            let event_name = match event {
-                HookEvent::PreToolUse => "PreToolUse",
-                HookEvent::PostToolUse => "PostToolUse",
-                HookEvent::PostToolUseFailure => "PostToolUseFailure",
-                HookEvent::UserPromptSubmit => "UserPromptSubmit",
-                HookEvent::Stop => "Stop",
-                HookEvent::SubagentStop => "SubagentStop",
-                HookEvent::PreCompact => "PreCompact",
-            };
-
-            config.insert(
-                event_name.to_string(),
-                serde_json::Value::Array(event_config),
-            );
+            config.insert(*event, event_config);
         }
 
-        Some(serde_json::Value::Object(config))
+        wire::body(&config).map(Some)
     }
 
     /// Send an interrupt request.
@@ -542,17 +1511,8 @@ impl Query {
 
     /// Set the permission mode.
     pub async fn set_permission_mode(&self, mode: PermissionMode) -> Result<()> {
-        let mode_str = match mode {
-            PermissionMode::Default => "default",
-            PermissionMode::AcceptEdits => "acceptEdits",
-            PermissionMode::Plan => "plan",
-            PermissionMode::BypassPermissions => "bypassPermissions",
-        };
-
-        self.send_control_request(ControlRequestPayload::SetPermissionMode {
-            mode: mode_str.to_string(),
-        })
-        .await?;
+        self.send_control_request(set_permission_mode_payload(mode))
+            .await?;
         Ok(())
     }
 
@@ -565,6 +1525,15 @@ impl Query {
         Ok(())
     }
 
+    /// Set the maximum thinking tokens, or restore the default with `None`.
+    pub async fn set_max_thinking_tokens(&self, max_thinking_tokens: Option<u32>) -> Result<()> {
+        self.send_control_request(ControlRequestPayload::SetMaxThinkingTokens {
+            max_thinking_tokens,
+        })
+        .await?;
+        Ok(())
+    }
+
     /// Rewind files to a specific user message.
     pub async fn rewind_files(&self, user_message_id: impl Into<String>) -> Result<()> {
         self.send_control_request(ControlRequestPayload::RewindFiles {
@@ -583,40 +1552,167 @@ impl Query {
             .await
     }
 
+    /// Check that the CLI answers control requests within `timeout`.
+    pub async fn ping(&self, timeout: Duration) -> Result<()> {
+        self.send_control_request_within(ControlRequestPayload::McpStatus, Some(timeout))
+            .await?;
+        Ok(())
+    }
+
+    /// Enable or disable the MCP server `server_name`.
+    pub async fn toggle_mcp_server(&self, server_name: &str, enabled: bool) -> Result<()> {
+        self.send_control_request(ControlRequestPayload::McpToggle {
+            server_name: server_name.to_string(),
+            enabled,
+        })
+        .await?;
+        Ok(())
+    }
+
     /// Send a user message to the CLI.
+    ///
+    /// Answers to control requests whose callbacks finished before the
+    /// call are written first, so the CLI never gets a prompt ahead of a
+    /// response it was already given.
     pub async fn send_message(&self, message: &str) -> Result<()> {
-        let msg = serde_json::json!({
-            "type": "user",
-            "message": {
-                "role": "user",
-                "content": message
-            },
-            "parent_tool_use_id": serde_json::Value::Null,
-            "session_id": "default"
-        });
+        self.ensure_running()?;
+        self.ensure_before_deadline()?;
+        let msg = UserInputMessage::text(message);
 
-        let transport = self.transport.lock().await;
-        transport.write(&msg.to_string()).await
+        // Answers already given go out first
+        self.responses.flushed().await;
+        self.write_prompt(&encode_frame(&msg)?, false).await
+    }
+
+    /// Send a user message whose turn's messages are kept from the
+    /// consumer, such as a compaction run before the next prompt.
+    ///
+    /// The turn only starts once those before it have ended, so its
+    /// messages are told apart in order and no earlier message is lost.
+    pub(crate) async fn send_hidden_message(&self, message: &str) -> Result<()> {
+        self.ensure_running()?;
+        self.ensure_before_deadline()?;
+        let msg = UserInputMessage::text(message);
+        self.responses.flushed().await;
+        self.write_prompt(&encode_frame(&msg)?, true).await
+    }
+
+    /// Write a prompt frame, recording its turn.
+    async fn write_prompt(&self, frame: &str, hidden: bool) -> Result<()> {
+        self.turns.sent(hidden);
+        if let Err(e) = self.transport.write(frame).await {
+            self.turns.unsent();
+            return Err(e);
+        }
+        self.prompt_sent.send_replace(Some(Instant::now()));
+        Ok(())
+    }
+
+    /// Fail a prompt sent past the deadline, which would stream unbounded.
+    fn ensure_before_deadline(&self) -> Result<()> {
+        match self.deadline {
+            Some(deadline) if deadline <= Instant::now() => {
+                Err(ClaudeSDKError::timeout_in(TimeoutPhase::Stream, 0))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Send a user message read from `reader`, without holding all of it
+    /// in memory (see [`query_from_reader`](crate::query_from_reader)).
+    pub async fn send_message_from<R>(&self, reader: R) -> Result<()>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        use futures::StreamExt;
+
+        self.ensure_running()?;
+        self.ensure_before_deadline()?;
+        let (prefix, suffix) = prompt_stream::frame_parts()?;
+        let chunks = PromptChunks::new(reader, prompt_stream::PROMPT_CHUNK_SIZE);
+        let content = futures::stream::unfold(chunks, |mut chunks| async move {
+            chunks.next().await.map(|chunk| (chunk, chunks))
+        });
+        let parts = futures::stream::once(async { Ok(prefix) })
+            .chain(content)
+            .chain(futures::stream::once(async { Ok(suffix) }));
+
+        self.responses.flushed().await;
+        self.turns.sent(false);
+        if let Err(e) = self.transport.write_parts(Box::pin(parts)).await {
+            self.turns.unsent();
+            return Err(e);
+        }
+        self.prompt_sent.send_replace(Some(Instant::now()));
+        Ok(())
     }
 
     /// Stop the query handler.
     pub async fn stop(&mut self) -> Result<()> {
-        // Send shutdown signal
-        if let Some(tx) = self.shutdown_tx.take() {
-            let _ = tx.send(()).await;
+        self.shutdown().await;
+        Ok(())
+    }
+
+    /// Delete the session's spool files and drop its quota counts from the
+    /// permission policies.
+    fn end_session(&self) {
+        if let Some(ref spooler) = self.spooler {
+            spooler.remove_files();
+        }
+        if self.permission_policies.is_empty() {
+            return;
         }
+        let Ok(session_id) = self.session_id.try_read() else {
+            return;
+        };
+        if let Some(ref session_id) = *session_id {
+            for policy in &self.permission_policies {
+                policy.end_session(session_id);
+            }
+        }
+    }
+
+    /// Stop the query handler and close the transport, reporting each
+    /// shutdown stage.
+    ///
+    /// The reader task is joined before the transport closes, so no control
+    /// response is written to a closing process; if it doesn't stop in
+    /// time it is aborted.
+    pub async fn shutdown(&mut self) -> ShutdownReport {
+        let span = self.span.clone();
+        self.shut_down_stages().instrument(span).await
+    }
+
+    async fn shut_down_stages(&mut self) -> ShutdownReport {
+        let mut report = ShutdownReport::default();
 
-        // Wait for reader task
         if let Some(task) = self.reader_task.take() {
-            let _ = tokio::time::timeout(std::time::Duration::from_secs(2), task).await;
+            let abort = task.abort_handle();
+            let shutdown_tx = self.shutdown_tx.take();
+            let stopped = report
+                .run_stage(ShutdownStage::StopReader, READER_STOP_TIMEOUT, async {
+                    if let Some(tx) = shutdown_tx {
+                        let _ = tx.send(()).await;
+                    }
+                    let _ = task.await;
+                })
+                .await;
+            if stopped.is_none() {
+                warn!("Query reader did not stop in time, aborting");
+                abort.abort();
+            }
         }
 
-        // Close transport
-        let mut transport = self.transport.lock().await;
-        transport.close().await?;
+        self.transport.shutdown(&mut report).await;
+        self.end_session();
 
         self.started = false;
-        Ok(())
+        debug!(
+            forced_kill = report.forced_kill,
+            duration_ms = report.duration().as_millis() as u64,
+            "Query shut down"
+        );
+        report
     }
 
     /// Check if the query is running.
@@ -631,20 +1727,735 @@ impl Drop for Query {
         if let Some(task) = self.reader_task.take() {
             task.abort();
         }
+        self.end_session();
+    }
+}
+
+/// The request setting the permission mode to `mode`.
+fn set_permission_mode_payload(mode: PermissionMode) -> ControlRequestPayload {
+    let mode = match mode {
+        PermissionMode::Default => "default",
+        PermissionMode::AcceptEdits => "acceptEdits",
+        PermissionMode::Plan => "plan",
+        PermissionMode::BypassPermissions => "bypassPermissions",
+    };
+    ControlRequestPayload::SetPermissionMode {
+        mode: mode.to_string(),
+    }
+}
+
+/// Log the fields of a result `message` this SDK version doesn't model,
+/// unless already `warned` about.
+fn warn_unmodeled_fields(warned: &mut HashSet<String>, message: &Message) {
+    let Message::Result(result) = message else {
+        return;
+    };
+    let mut new_fields: Vec<&str> = result
+        .extras
+        .keys()
+        .filter(|key| !warned.contains(*key))
+        .map(String::as_str)
+        .collect();
+    if new_fields.is_empty() {
+        return;
     }
+    new_fields.sort_unstable();
+    warn!(
+        fields = ?new_fields,
+        "Result message has fields this SDK version doesn't model; they are kept in ResultMessage::extras"
+    );
+    warned.extend(new_fields.into_iter().map(String::from));
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::_internal::transport::Transport;
+    use crate::protocol::engine::control_anomaly;
+    use crate::testing::MockTransport;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+    #[tokio::test]
+    async fn test_session_manifest_records_init() {
+        let options = ClaudeAgentOptions::new()
+            .with_allowed_tools(vec!["Read".to_string(), "Bash".to_string()])
+            .with_permission_mode(PermissionMode::AcceptEdits);
+        let manifest = RwLock::new(SessionManifest::from_options(&options));
+        let before = manifest.read().await.fingerprint();
+
+        Query::record_init(
+            &manifest,
+            &serde_json::json!({
+                "type": "system",
+                "subtype": "init",
+                "session_id": "sess_1",
+                "model": "claude-sonnet-4-5",
+                "permissionMode": "acceptEdits",
+                "cwd": "/work",
+                "tools": ["Read", "Bash"],
+                "claude_code_version": "2.0.1"
+            }),
+        )
+        .await;
+
+        let manifest = manifest.into_inner();
+        assert_eq!(manifest.session_id.as_deref(), Some("sess_1"));
+        assert_eq!(manifest.model.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(manifest.cli_version.as_deref(), Some("2.0.1"));
+        assert_eq!(manifest.cwd, Some(std::path::PathBuf::from("/work")));
+        assert_eq!(manifest.permission_mode, Some(PermissionMode::AcceptEdits));
+        assert_ne!(manifest.fingerprint(), before);
+
+        // Tool order, session identity and tags don't change the fingerprint
+        let mut other = manifest.clone();
+        other.session_id = Some("sess_2".to_string());
+        other.tags.insert("tenant".to_string(), "acme".to_string());
+        other.tools.reverse();
+        other.allowed_tools.reverse();
+        assert_eq!(other.fingerprint(), manifest.fingerprint());
+    }
+
+    const RESULT_FRAME: &str = r#"{"type":"result","subtype":"success","duration_ms":1,"duration_api_ms":1,"is_error":false,"num_turns":1,"session_id":"s"}"#;
+
+    /// A mock transport delivering `frames`.
+    fn mock(frames: &[&str]) -> MockTransport {
+        MockTransport::new(
+            frames
+                .iter()
+                .map(|frame| serde_json::from_str(frame).unwrap())
+                .collect(),
+        )
+    }
+
+    /// Start a query over `transport`.
+    async fn start_mock(
+        mut transport: MockTransport,
+        options: &ClaudeAgentOptions,
+    ) -> (Query, mpsc::Receiver<Result<Message>>) {
+        transport.connect().await.unwrap();
+        let (mut query, rx) =
+            Query::with_handle(TransportHandle::other(Box::new(transport), None), options);
+        query.start().await.unwrap();
+        (query, rx)
+    }
+
+    /// Run a query over `transport`, collecting the first `n` items of the
+    /// message stream.
+    async fn run_mock(
+        transport: MockTransport,
+        options: ClaudeAgentOptions,
+        n: usize,
+    ) -> Vec<Result<Message>> {
+        use tokio_stream::StreamExt;
+
+        let (mut query, rx) = start_mock(transport, &options).await;
+        let received = tokio_stream::wrappers::ReceiverStream::new(rx)
+            .take(n)
+            .collect()
+            .await;
+        query.shutdown().await;
+        received
+    }
+
+    #[tokio::test]
+    async fn test_ping_timeout_forgets_the_request() {
+        let transport = mock(&[])
+            .respond_to_control_requests()
+            .with_control_response_delay(Duration::from_millis(100));
+        let (mut query, _rx) = start_mock(transport, &ClaudeAgentOptions::default()).await;
+
+        let err = query.ping(Duration::from_millis(10)).await.unwrap_err();
+        assert!(matches!(err, ClaudeSDKError::Timeout { .. }));
+
+        // The late answer no longer has a request waiting for it
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while query.protocol_anomalies().await.counts.is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let report = query.protocol_anomalies().await;
+        assert_eq!(
+            report
+                .counts
+                .get(&ProtocolAnomalyKind::UnmatchedControlResponse),
+            Some(&1)
+        );
+        query.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_dropped_receiver_is_not_a_failure() {
+        let mut transport = mock(&[RESULT_FRAME]);
+        transport.connect().await.unwrap();
+        let (mut query, rx) = Query::with_handle(
+            TransportHandle::other(Box::new(transport), None),
+            &ClaudeAgentOptions::default(),
+        );
+        drop(rx);
+        query.start().await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while query.exit.get().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(query.exit.get().unwrap().reason, "Message receiver dropped");
+        assert!(query.failure().is_none());
+        query.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_spool_files_go_with_the_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ClaudeAgentOptions::new().with_tool_output_spool(dir.path(), 4);
+        let frame = r#"{"type":"user","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"toolu_1","content":"a long tool output"}]}}"#;
+
+        let (mut query, mut rx) = start_mock(mock(&[frame]), &options).await;
+        let Some(Ok(Message::User(user))) = rx.recv().await else {
+            panic!("expected the tool result");
+        };
+        let UserMessageContent::Blocks(ref blocks) = user.content else {
+            panic!("expected content blocks");
+        };
+        let ContentBlock::ToolResult(ref result) = blocks[0] else {
+            panic!("expected a tool result");
+        };
+        let content_ref = result.content_ref.clone().unwrap();
+        assert!(result.content.is_none());
+        assert_eq!(content_ref.read().unwrap(), b"a long tool output");
+
+        query.shutdown().await;
+        assert!(!content_ref.path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drops_policy_quota_counts() {
+        let policy = PermissionPolicy::new().max_tool_calls("Bash", 5);
+        policy.charge("s", "Bash", &serde_json::json!({"command": "ls"}));
+        policy.charge("other", "Bash", &serde_json::json!({"command": "ls"}));
+        let options = ClaudeAgentOptions::new().with_permission_policy(policy.clone());
+
+        let received = run_mock(mock(&[RESULT_FRAME]), options, 1).await;
+        assert!(matches!(received[0], Ok(Message::Result(_))));
+        assert_eq!(policy.quota_usage("s")[0].used, 0);
+        assert_eq!(policy.quota_usage("other")[0].used, 1);
+    }
+
+    #[tokio::test]
+    async fn test_messages_carry_sequence_numbers() {
+        let frames = [
+            r#"{"type":"system","subtype":"init","data":{}}"#,
+            r#"{"type":"brand_new_frame"}"#,
+            RESULT_FRAME,
+        ];
+        let received = run_mock(mock(&frames), ClaudeAgentOptions::new(), 3).await;
+
+        let first = received[0].as_ref().unwrap().meta().copied().unwrap();
+        assert_eq!(first.seq, 0);
+        assert!(received[1].is_err());
+        let last = received[2].as_ref().unwrap().meta().copied().unwrap();
+        assert_eq!(last.seq, 2);
+        assert!(last.follows_gap(&first));
+        assert!(last.received_at >= first.received_at);
+    }
+
+    #[tokio::test]
+    async fn test_text_deltas_are_coalesced() {
+        let delta = |text: &str| {
+            format!(
+                r#"{{"type":"stream_event","uuid":"u","session_id":"s","event":{{"type":"content_block_delta","index":0,"delta":{{"type":"text_delta","text":"{}"}}}}}}"#,
+                text
+            )
+        };
+        let frames = [
+            delta("Hel"),
+            delta("lo"),
+            delta(" there"),
+            RESULT_FRAME.to_string(),
+        ];
+        let frames: Vec<&str> = frames.iter().map(String::as_str).collect();
+        let options = ClaudeAgentOptions::new().with_partial_coalescing(PartialCoalescing {
+            interval: Duration::from_secs(60),
+            max_bytes: 1024,
+        });
+
+        let received = run_mock(mock(&frames), options, 2).await;
+        match received[0].as_ref().unwrap() {
+            Message::StreamEvent(event) => {
+                assert_eq!(event.event["delta"]["text"], "Hello there");
+                assert_eq!(event.meta.unwrap().seq, 0);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+        let result = received[1].as_ref().unwrap();
+        assert!(result.is_result());
+        assert_eq!(result.meta().unwrap().seq, 1);
+    }
+
+    #[tokio::test]
+    async fn test_slow_consumer_loses_oldest_stream_events() {
+        use tokio_stream::StreamExt;
+
+        const EVENTS: usize = 1000;
+        const MAX_PENDING: usize = 8;
+        let delta = |i: usize| {
+            format!(
+                r#"{{"type":"stream_event","uuid":"u","session_id":"s","event":{{"type":"content_block_delta","index":0,"delta":{{"type":"text_delta","text":"{}"}}}}}}"#,
+                i
+            )
+        };
+        let assistant = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Done"}],"model":"m"}}"#;
+        let mut frames: Vec<String> = (0..EVENTS).map(delta).collect();
+        frames.push(assistant.to_string());
+        frames.extend((EVENTS..2 * EVENTS).map(delta));
+        frames.push(RESULT_FRAME.to_string());
+        let frames: Vec<&str> = frames.iter().map(String::as_str).collect();
+
+        let options = ClaudeAgentOptions::new().with_max_pending_partial_messages(MAX_PENDING);
+        let (mut query, rx) = start_mock(mock(&frames), &options).await;
+
+        // Fall behind while the CLI streams everything
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let received: Vec<Message> = tokio::time::timeout(
+            Duration::from_secs(10),
+            tokio_stream::wrappers::ReceiverStream::new(rx)
+                .map(|message| message.unwrap())
+                .take_while(|message| !message.is_result())
+                .collect(),
+        )
+        .await
+        .expect("result never delivered");
+        query.shutdown().await;
+
+        let texts: Vec<String> = received
+            .iter()
+            .filter_map(|message| match message {
+                Message::StreamEvent(event) => {
+                    event.event["delta"]["text"].as_str().map(String::from)
+                }
+                _ => None,
+            })
+            .collect();
+        // Some events were dropped, leaving gaps in the sequence numbers
+        assert!(texts.len() < 2 * EVENTS);
+        assert!(received
+            .windows(2)
+            .any(|pair| pair[1].meta().unwrap().follows_gap(pair[0].meta().unwrap())));
+        // The assistant message made it, and so did the newest events
+        assert_eq!(received.iter().filter(|m| m.is_assistant()).count(), 1);
+        assert_eq!(texts.last().map(String::as_str), Some("1999"));
+        // Whatever was delivered is in order
+        let numbers: Vec<usize> = texts.iter().map(|t| t.parse().unwrap()).collect();
+        assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[tokio::test]
+    async fn test_slow_callbacks_do_not_block_messages() {
+        let frames = [
+            r#"{"type":"control_request","request_id":"req_1","request":{"subtype":"can_use_tool","tool_name":"Bash","input":{}}}"#,
+            RESULT_FRAME,
+        ];
+        let options = ClaudeAgentOptions::new().with_can_use_tool(|_, _, _| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            PermissionResult::allow()
+        });
+
+        let received =
+            tokio::time::timeout(Duration::from_secs(10), run_mock(mock(&frames), options, 1))
+                .await
+                .expect("reader blocked on the permission callback");
+        assert!(received[0].as_ref().unwrap().is_result());
+    }
+
+    #[tokio::test]
+    async fn test_control_traffic_does_not_starve_messages() {
+        use tokio_stream::StreamExt;
+
+        // Thousands of permission prompts interleaved with messages, from a
+        // CLI that never reads its input, so no response can be written
+        const ROUNDS: usize = 2000;
+        let mut transport = MockTransport::new(vec![]).with_blocked_input();
+        for i in 0..ROUNDS {
+            transport = transport
+                .then_frame(serde_json::json!({
+                    "type": "control_request",
+                    "request_id": format!("req_{}", i),
+                    "request": {"subtype": "can_use_tool", "tool_name": "Bash", "input": {}}
+                }))
+                .then_frame(serde_json::json!({"type": "system", "subtype": "status", "data": {}}));
+        }
+        let transport = transport.then_frame(serde_json::from_str(RESULT_FRAME).unwrap());
+
+        let options = ClaudeAgentOptions::new()
+            .with_can_use_tool(|_, _, _| async { PermissionResult::allow() });
+        let (mut query, rx) = start_mock(transport, &options).await;
+
+        let received: Vec<_> = tokio::time::timeout(
+            Duration::from_secs(10),
+            tokio_stream::wrappers::ReceiverStream::new(rx)
+                .take(ROUNDS + 1)
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .expect("messages starved by control responses");
+        assert!(received.last().unwrap().as_ref().unwrap().is_result());
+
+        let report = tokio::time::timeout(Duration::from_secs(10), query.shutdown())
+            .await
+            .expect("shutdown blocked on a control response");
+        assert!(report.duration() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_pause_holds_messages_but_answers_control_requests() {
+        use tokio_stream::StreamExt;
+
+        let system = r#"{"type":"system","subtype":"status","data":{}}"#;
+        let mut frames = vec![
+            system,
+            r#"{"type":"control_request","request_id":"req_1","request":{"subtype":"can_use_tool","tool_name":"Bash","input":{}}}"#,
+        ];
+        frames.extend(std::iter::repeat(system).take(PAUSE_BUFFER_SIZE + 5));
+        frames.push(RESULT_FRAME);
+
+        let (asked_tx, asked_rx) = tokio::sync::oneshot::channel();
+        let asked_tx = std::sync::Mutex::new(Some(asked_tx));
+        let options = ClaudeAgentOptions::new().with_can_use_tool(move |_, _, _| {
+            if let Some(tx) = asked_tx.lock().unwrap().take() {
+                let _ = tx.send(());
+            }
+            async { PermissionResult::allow() }
+        });
+        let mut transport = mock(&frames);
+        transport.connect().await.unwrap();
+        let (mut query, mut rx) =
+            Query::with_handle(TransportHandle::other(Box::new(transport), None), &options);
+        query.pause();
+        query.start().await.unwrap();
+
+        // The permission prompt is answered while nothing is delivered
+        tokio::time::timeout(Duration::from_secs(10), asked_rx)
+            .await
+            .expect("control request not handled while paused")
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+        assert!(query.is_paused());
+
+        query.resume();
+        let received: Vec<_> = tokio::time::timeout(
+            Duration::from_secs(10),
+            tokio_stream::wrappers::ReceiverStream::new(rx)
+                .take(PAUSE_BUFFER_SIZE + 7)
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .expect("held messages not delivered after resuming");
+        for (seq, message) in received.iter().enumerate() {
+            assert_eq!(message.as_ref().unwrap().meta().unwrap().seq, seq as u64);
+        }
+        assert!(received.last().unwrap().as_ref().unwrap().is_result());
+        query.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeouts_follow_paused_time() {
+        // A CLI that never answers
+        let silent = || MockTransport::new(vec![]).with_control_responder(|_| None);
+        let options = ClaudeAgentOptions::new().with_timeout_secs(3600);
+        let (mut query, _rx) = start_mock(silent(), &options).await;
+
+        // The CLI never answers; paused time skips the hour instantly
+        let started = std::time::Instant::now();
+        let err = query.initialize().await.unwrap_err();
+        assert_eq!(err.timeout_phase(), Some(TimeoutPhase::Control));
+        query.shutdown().await;
+
+        // Deadlines measured on tokio's clock expire the same way
+        let options = ClaudeAgentOptions::new()
+            .with_deadline((Instant::now() + Duration::from_secs(3600)).into_std());
+        let received = run_mock(silent(), options, 1).await;
+        let err = received[0].as_ref().unwrap_err();
+        assert_eq!(err.timeout_phase(), Some(TimeoutPhase::Stream));
+        assert!(started.elapsed() < Duration::from_secs(60));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline_interrupts_and_bounds_later_queries() {
+        use crate::testing::MockTransport;
+
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        let mut transport = MockTransport::new(vec![])
+            .then_delay(Duration::from_secs(90))
+            .then_frame(serde_json::json!({
+                "type": "result",
+                "subtype": "error_during_execution",
+                "is_error": true,
+                "duration_ms": 90000,
+                "duration_api_ms": 0,
+                "num_turns": 1,
+                "session_id": "sess_1"
+            }))
+            .with_control_responder(move |request| {
+                seen.lock().unwrap().push(request["subtype"].clone());
+                Some(serde_json::json!({}))
+            });
+        transport.connect().await.unwrap();
+        let options = ClaudeAgentOptions::new()
+            .with_deadline((Instant::now() + Duration::from_secs(60)).into_std());
+        let (mut query, mut rx) =
+            Query::with_handle(TransportHandle::other(Box::new(transport), None), &options);
+        query.start().await.unwrap();
+        query.send_message("first").await.unwrap();
+
+        // Past the deadline the turn is interrupted, and the session lives on
+        let err = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(err.timeout_phase(), Some(TimeoutPhase::Stream));
+        assert!(rx.recv().await.unwrap().unwrap().is_result());
+        assert_eq!(*requests.lock().unwrap(), vec!["interrupt"]);
+
+        // The deadline holds for the session, not each query
+        let err = query.send_message("second").await.unwrap_err();
+        assert_eq!(err.timeout_phase(), Some(TimeoutPhase::Stream));
+        query.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_first_message_and_idle_timeouts() {
+        // Answers the prompt once, then hangs
+        let answered = AtomicBool::new(false);
+        let transport = MockTransport::new(vec![]).with_prompt_responder(move |_| {
+            if answered.swap(true, Ordering::SeqCst) {
+                return vec![];
+            }
+            vec![serde_json::json!({
+                "type": "assistant",
+                "message": {"content": [{"type": "text", "text": "Hi"}], "model": "m"}
+            })]
+        });
+        let options = ClaudeAgentOptions::new().with_timeouts(Timeouts {
+            first_message: Some(Duration::from_secs(5)),
+            idle: Some(Duration::from_millis(200)),
+            ..Timeouts::default()
+        });
+        let (mut query, mut rx) = start_mock(transport, &options).await;
+
+        // Nothing is timed before a prompt
+        let quiet = tokio::time::timeout(Duration::from_millis(400), rx.recv()).await;
+        assert!(quiet.is_err());
+
+        query.send_message("Hello").await.unwrap();
+        assert!(matches!(rx.recv().await, Some(Ok(Message::Assistant(_)))));
+        let err = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(err.timeout_phase(), Some(TimeoutPhase::Stream));
+        assert!(err.to_string().contains("200"), "{}", err);
+        assert!(rx.recv().await.is_none());
+        query.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_see_every_message() {
+        use tokio::sync::broadcast::error::RecvError;
+        use tokio_stream::StreamExt;
+
+        let system = r#"{"type":"system","subtype":"status","data":{}}"#;
+        let options = ClaudeAgentOptions::new().with_subscriber_capacity(2);
+        let mut transport = mock(&[system, system, system, RESULT_FRAME]);
+        transport.connect().await.unwrap();
+        let (mut query, rx) =
+            Query::with_handle(TransportHandle::other(Box::new(transport), None), &options);
+        let mut logger = query.subscribe();
+        let mut laggard = query.subscribe();
+        query.start().await.unwrap();
+
+        let received: Vec<_> = tokio_stream::wrappers::ReceiverStream::new(rx)
+            .take(4)
+            .collect()
+            .await;
+        assert!(received[3].as_ref().unwrap().is_result());
+
+        // Both receivers were filled while the main stream was read; the
+        // oldest messages didn't fit
+        assert!(matches!(logger.recv().await, Err(RecvError::Lagged(2))));
+        let third = logger.recv().await.unwrap();
+        assert_eq!(third.meta().unwrap().seq, 2);
+        assert!(logger.recv().await.unwrap().is_result());
+        assert!(matches!(laggard.recv().await, Err(RecvError::Lagged(2))));
+
+        query.shutdown().await;
+        assert!(matches!(logger.recv().await, Err(RecvError::Closed)));
+        assert!(matches!(
+            query.subscribe().recv().await,
+            Err(RecvError::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reader_exit_fails_pending_requests() {
+        let engine = ProtocolEngine::new(None);
+        let (_, pending) = engine.request(ControlRequestPayload::Interrupt).await;
+
+        let exit = OnceLock::new();
+        Query::fail_pending_requests(
+            &engine,
+            &exit,
+            ReaderExit {
+                reason: "CLI process exited".to_string(),
+                failed: true,
+            },
+        )
+        .await;
+
+        let err = pending.wait().await.unwrap_err();
+        assert!(matches!(err, ClaudeSDKError::CLIConnection { .. }));
+        assert!(err.to_string().contains("CLI process exited"));
+        assert!(exit.get().unwrap().failed);
+    }
+
+    #[test]
+    fn test_unmodeled_fields_are_remembered() {
+        let mut result = ResultMessage::new("success", "s");
+        result
+            .extras
+            .insert("modelUsage".to_string(), serde_json::json!({}));
+        let mut warned = HashSet::new();
+        warn_unmodeled_fields(&mut warned, &Message::Result(result.clone()));
+        assert!(warned.contains("modelUsage"));
+
+        result
+            .extras
+            .insert("newField".to_string(), serde_json::json!(1));
+        warn_unmodeled_fields(&mut warned, &Message::Result(result));
+        assert_eq!(warned.len(), 2);
+    }
+
+    fn system_message(subtype: &str) -> Message {
+        Message::System(SystemMessage::new(subtype, serde_json::Value::Null))
+    }
+
+    #[test]
+    fn test_message_history_drops_oldest() {
+        let mut history = MessageHistory::new(2);
+        history.push(&system_message("a"));
+        history.push(&system_message("b"));
+        history.push(&system_message("c"));
+
+        let recent = history.recent(10);
+        assert_eq!(recent.len(), 2);
+        assert!(matches!(&recent[0], Message::System(m) if m.subtype == "b"));
+        assert!(matches!(&recent[1], Message::System(m) if m.subtype == "c"));
+
+        let last = history.recent(1);
+        assert!(matches!(&last[0], Message::System(m) if m.subtype == "c"));
+    }
 
     #[test]
-    fn test_generate_request_id() {
-        let id1 = generate_request_id();
-        let id2 = generate_request_id();
+    fn test_message_history_allocates_lazily() {
+        let mut history = MessageHistory::new(usize::MAX);
+        assert_eq!(history.messages.capacity(), 0);
+        history.push(&system_message("a"));
+        assert_eq!(history.recent(5).len(), 1);
+    }
+
+    #[test]
+    fn test_message_history_disabled() {
+        let mut history = MessageHistory::new(0);
+        history.push(&system_message("a"));
+        assert!(history.recent(5).is_empty());
+    }
 
-        assert!(id1.starts_with("req_"));
-        assert!(id2.starts_with("req_"));
-        assert_ne!(id1, id2);
+    #[test]
+    fn test_tool_use_tracker() {
+        let tool_use = |id: &str, name: &str| {
+            ContentBlock::ToolUse(ToolUseBlock {
+                id: id.to_string(),
+                name: name.to_string(),
+                input: serde_json::json!({}),
+            })
+        };
+        let mut tracker = ToolUseTracker::default();
+        tracker.observe(&Message::Assistant(AssistantMessage::new(
+            vec![tool_use("toolu_1", "Bash"), tool_use("toolu_2", "Read")],
+            "test",
+        )));
+        tracker.observe(&Message::User(UserMessage::new(
+            UserMessageContent::Blocks(vec![ContentBlock::ToolResult(ToolResultBlock {
+                tool_use_id: "toolu_2".to_string(),
+                content: None,
+                is_error: None,
+                content_ref: None,
+            })]),
+        )));
+
+        let names: Vec<&str> = tracker.pending.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["Bash"]);
+        assert!(tracker.pending[0].age() < Duration::from_secs(5));
+
+        tracker.observe(&system_message("status"));
+        assert_eq!(tracker.pending.len(), 1);
+    }
+
+    fn tracker(strictness: ProtocolStrictness) -> AnomalyTracker {
+        AnomalyTracker {
+            strictness,
+            report: Arc::new(RwLock::new(ProtocolAnomalyReport::default())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_control_response_is_reported() {
+        let engine = ProtocolEngine::new(None);
+        let raw = serde_json::json!({
+            "type": "control_response",
+            "response": {"subtype": "success", "request_id": "req_missing", "response": {}}
+        });
+
+        let Incoming::Anomaly(anomaly) = engine.receive(raw).await else {
+            panic!("expected an anomaly");
+        };
+        assert_eq!(anomaly.kind, ProtocolAnomalyKind::UnmatchedControlResponse);
+        assert_eq!(anomaly.request_id.as_deref(), Some("req_missing"));
+        assert_eq!(anomaly.subtype.as_deref(), Some("success"));
+    }
+
+    #[tokio::test]
+    async fn test_anomaly_tracker_strictness() {
+        let raw = serde_json::json!({
+            "type": "control_request",
+            "request_id": "req_1",
+            "request": {"subtype": "brand_new_thing"}
+        });
+        let anomaly = control_anomaly(ProtocolAnomalyKind::UnknownControlRequest, raw);
+        assert_eq!(anomaly.subtype.as_deref(), Some("brand_new_thing"));
+
+        let warn = tracker(ProtocolStrictness::Warn);
+        assert!(warn.record(anomaly.clone()).await.is_none());
+        assert!(warn.record(anomaly.clone()).await.is_none());
+        let report = warn.report.read().await.clone();
+        assert_eq!(report.total(), 2);
+        assert_eq!(
+            report.subtypes[&(
+                ProtocolAnomalyKind::UnknownControlRequest,
+                "brand_new_thing".to_string()
+            )],
+            2
+        );
+
+        let strict = tracker(ProtocolStrictness::Error);
+        let err = strict.record(anomaly.clone()).await.unwrap();
+        assert!(err.to_string().contains("brand_new_thing"));
+
+        let seen = Arc::new(AtomicU64::new(0));
+        let seen_clone = Arc::clone(&seen);
+        let callback = tracker(ProtocolStrictness::Callback(Arc::new(move |_| {
+            seen_clone.fetch_add(1, Ordering::SeqCst);
+        })));
+        assert!(callback.record(anomaly).await.is_none());
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
     }
 }
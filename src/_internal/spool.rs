@@ -0,0 +1,261 @@
+//! Spooling of large tool results to disk.
+//!
+//! Tool results are spooled from the frames as they are read, before the
+//! messages are parsed, so the content isn't copied into the parsed message
+//! first. The spool files are deleted when the session ends.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use super::to_hex;
+use crate::types::{ContentRef, ToolOutputSpool};
+
+/// Spools the tool results of a session, keeping track of its files.
+#[derive(Debug, Clone)]
+pub(crate) struct Spooler {
+    spool: ToolOutputSpool,
+    files: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl Spooler {
+    pub(crate) fn new(spool: ToolOutputSpool) -> Self {
+        Self {
+            spool,
+            files: Arc::default(),
+        }
+    }
+
+    /// Move tool result content above the spool threshold out of the raw
+    /// frame of a user or assistant message, leaving a `content_ref`.
+    ///
+    /// Content that can't be written is left in place, with a warning.
+    pub(crate) async fn spool_tool_results(&self, raw: &mut Value) {
+        let Some(blocks) = raw
+            .pointer_mut("/message/content")
+            .and_then(Value::as_array_mut)
+        else {
+            return;
+        };
+
+        for block in blocks.iter_mut() {
+            if block.get("type").and_then(Value::as_str) != Some("tool_result") {
+                continue;
+            }
+            let Some(content) = block.get("content") else {
+                continue;
+            };
+            if encoded_len(content) <= self.spool.threshold_bytes as u64 {
+                continue;
+            }
+
+            let Some(content) = block.as_object_mut().and_then(|b| b.remove("content")) else {
+                continue;
+            };
+            let tool_use_id = block
+                .get("tool_use_id")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let dir = self.spool.dir.clone();
+            let written = tokio::task::spawn_blocking(move || {
+                let written = write_spool_file(&dir, &tool_use_id, &content);
+                (written, content)
+            })
+            .await;
+
+            match written {
+                Ok((Ok(content_ref), _)) => {
+                    debug!(
+                        "Spooled {} bytes of tool output to {}",
+                        content_ref.bytes,
+                        content_ref.path.display()
+                    );
+                    self.files
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push(content_ref.path.clone());
+                    block["content_ref"] = serde_json::to_value(content_ref).unwrap_or_default();
+                }
+                Ok((Err(e), content)) => {
+                    warn!("Failed to spool tool output, keeping it in memory: {}", e);
+                    block["content"] = content;
+                }
+                Err(e) => warn!("Failed to spool tool output: {}", e),
+            }
+        }
+    }
+
+    /// Delete the spool files written so far.
+    pub(crate) fn remove_files(&self) {
+        let files = std::mem::take(&mut *self.files.lock().unwrap_or_else(|e| e.into_inner()));
+        for path in files {
+            if let Err(e) = std::fs::remove_file(&path) {
+                debug!("Failed to remove spool file {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Bytes `content` takes in a spool file: strings as-is, anything else as
+/// JSON.
+fn encoded_len(content: &Value) -> u64 {
+    match content {
+        Value::String(text) => text.len() as u64,
+        other => {
+            let mut counter = Counter::default();
+            let _ = serde_json::to_writer(&mut counter, other);
+            counter.0
+        }
+    }
+}
+
+/// A writer counting the bytes written to it.
+#[derive(Default)]
+struct Counter(u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A file writer hashing what is written to it.
+struct HashingWriter {
+    file: BufWriter<File>,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn write_spool_file(dir: &Path, tool_use_id: &str, content: &Value) -> io::Result<ContentRef> {
+    // Tool use IDs come from the CLI; keep only filename-safe characters
+    let name: String = tool_use_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .collect();
+
+    std::fs::create_dir_all(dir)?;
+    // Named after the content's hash once it is written
+    let partial = tempfile::Builder::new()
+        .prefix(&format!("{}-", name))
+        .suffix(".partial")
+        .tempfile_in(dir)?;
+    let mut writer = HashingWriter {
+        file: BufWriter::new(partial.reopen()?),
+        hasher: Sha256::new(),
+        bytes: 0,
+    };
+    match content {
+        Value::String(text) => writer.write_all(text.as_bytes())?,
+        other => serde_json::to_writer(&mut writer, other)?,
+    }
+    writer.flush()?;
+
+    let sha256 = to_hex(&writer.hasher.finalize());
+    let path = dir.join(format!("{}-{}.out", name, &sha256[..12]));
+    partial.persist(&path).map_err(|e| e.error)?;
+
+    Ok(ContentRef {
+        path,
+        bytes: writer.bytes,
+        sha256,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::parser::parse_message;
+    use crate::types::{ContentBlock, Message, ToolResultBlock, UserMessageContent};
+
+    fn tool_result(content: Value) -> Value {
+        serde_json::json!({
+            "type": "user",
+            "message": {
+                "role": "user",
+                "content": [{"type": "tool_result", "tool_use_id": "toolu_01", "content": content}]
+            }
+        })
+    }
+
+    fn result_block(raw: Value) -> ToolResultBlock {
+        match parse_message(raw).unwrap() {
+            Message::User(user) => match user.content {
+                UserMessageContent::Blocks(mut blocks) => match blocks.remove(0) {
+                    ContentBlock::ToolResult(result) => result,
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spools_large_tool_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let spooler = Spooler::new(ToolOutputSpool {
+            dir: dir.path().join("spool"),
+            threshold_bytes: 8,
+        });
+
+        let mut small = tool_result(serde_json::json!("short"));
+        spooler.spool_tool_results(&mut small).await;
+        assert!(result_block(small).content_ref.is_none());
+
+        let mut large = tool_result(serde_json::json!("a long tool output"));
+        spooler.spool_tool_results(&mut large).await;
+        let block = result_block(large);
+        assert!(block.content.is_none());
+
+        let content_ref = block.content_ref.unwrap();
+        assert_eq!(content_ref.bytes, 18);
+        assert_eq!(content_ref.sha256.len(), 64);
+        assert_eq!(content_ref.read().unwrap(), b"a long tool output");
+
+        let blocks = serde_json::json!([{"type": "text", "text": "a long tool output"}]);
+        let mut large = tool_result(blocks.clone());
+        spooler.spool_tool_results(&mut large).await;
+        let content_ref = result_block(large).content_ref.unwrap();
+        assert_eq!(content_ref.bytes, blocks.to_string().len() as u64);
+        assert_eq!(
+            serde_json::from_slice::<Value>(&content_ref.read().unwrap()).unwrap(),
+            blocks
+        );
+
+        // Only the spool files are left, and they go with the session
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("spool")).unwrap().count(),
+            2
+        );
+        spooler.remove_files();
+        assert!(!content_ref.path.exists());
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("spool")).unwrap().count(),
+            0
+        );
+    }
+}
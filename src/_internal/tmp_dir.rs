@@ -0,0 +1,51 @@
+//! The per-session temporary directory of a client.
+
+use std::path::{Path, PathBuf};
+
+use tracing::{debug, info, warn};
+
+use crate::config_dir::create_private_dir;
+use crate::errors::Result;
+
+/// Prefix of the directory names, followed by a UUID.
+const PREFIX: &str = "claude-session-";
+
+/// A directory under the system's temporary directory, accessible to the
+/// current user only, removed on drop unless kept.
+#[derive(Debug)]
+pub(crate) struct SessionTmpDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl SessionTmpDir {
+    /// Create a new directory.
+    pub(crate) fn create(keep: bool) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("{}{}", PREFIX, uuid::Uuid::new_v4()));
+        create_private_dir(&path)?;
+        debug!("Created session temp directory {}", path.display());
+        Ok(Self { path, keep })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SessionTmpDir {
+    fn drop(&mut self) {
+        if self.keep {
+            info!("Keeping session temp directory {}", self.path.display());
+            return;
+        }
+        match std::fs::remove_dir_all(&self.path) {
+            Ok(()) => debug!("Removed session temp directory {}", self.path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!(
+                "Failed to remove session temp directory {}: {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+}
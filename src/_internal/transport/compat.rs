@@ -0,0 +1,287 @@
+//! CLI flag compatibility.
+//!
+//! Older CLIs reject flags they don't know with an "unknown option" error
+//! that doesn't say which SDK option caused it. Before spawning, the
+//! arguments are checked against [`FLAG_SUPPORT`] for the installed CLI
+//! version, and unsupported flags are dropped or reported according to
+//! [`UnsupportedFlags`].
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use tracing::{debug, warn};
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::UnsupportedFlags;
+
+/// A CLI flag that only newer CLIs accept.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FlagSupport {
+    /// The flag, e.g. `--sandbox`.
+    pub(crate) flag: &'static str,
+    /// The option of [`ClaudeAgentOptions`](crate::ClaudeAgentOptions)
+    /// that sets it.
+    pub(crate) option: &'static str,
+    /// First CLI version accepting the flag.
+    pub(crate) since: &'static str,
+    /// Whether the flag is followed by a value.
+    pub(crate) takes_value: bool,
+}
+
+/// Flags introduced after [`MIN_CLI_VERSION`](crate::MIN_CLI_VERSION).
+pub(crate) const FLAG_SUPPORT: &[FlagSupport] = &[
+    FlagSupport {
+        flag: "--fork-session",
+        option: "fork_session",
+        since: "2.0.5",
+        takes_value: false,
+    },
+    FlagSupport {
+        flag: "--max-budget-usd",
+        option: "max_budget_usd",
+        since: "2.0.15",
+        takes_value: true,
+    },
+    FlagSupport {
+        flag: "--beta",
+        option: "betas",
+        since: "2.0.21",
+        takes_value: true,
+    },
+    FlagSupport {
+        flag: "--sandbox",
+        option: "sandbox",
+        since: "2.0.24",
+        takes_value: true,
+    },
+    FlagSupport {
+        flag: "--enable-file-checkpointing",
+        option: "enable_file_checkpointing",
+        since: "2.0.30",
+        takes_value: false,
+    },
+    FlagSupport {
+        flag: "--tools",
+        option: "tools",
+        since: "2.0.35",
+        takes_value: true,
+    },
+    FlagSupport {
+        flag: "--output-format-schema",
+        option: "output_format",
+        since: "2.0.40",
+        takes_value: true,
+    },
+];
+
+/// Parse the version from `claude --version` output, e.g.
+/// `2.0.14 (Claude Code)`.
+pub(crate) fn parse_cli_version(output: &str) -> Option<semver::Version> {
+    output
+        .lines()
+        .next()?
+        .split_whitespace()
+        .find_map(|word| semver::Version::parse(word.trim_start_matches('v')).ok())
+}
+
+/// Get the version of the CLI at `cli_path`, running it once per path.
+async fn cli_version(cli_path: &Path) -> Option<semver::Version> {
+    static VERSIONS: OnceLock<Mutex<HashMap<PathBuf, Option<semver::Version>>>> = OnceLock::new();
+    let versions = VERSIONS.get_or_init(Default::default);
+
+    if let Some(version) = versions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(cli_path)
+    {
+        return version.clone();
+    }
+    let version = match crate::_internal::client::check_cli_version(Some(cli_path)).await {
+        Ok(output) => parse_cli_version(&output),
+        Err(e) => {
+            debug!("Could not determine the CLI version: {}", e);
+            None
+        }
+    };
+    versions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(cli_path.to_path_buf(), version.clone());
+    version
+}
+
+/// Fail with [`VersionMismatch`](ClaudeSDKError::VersionMismatch) if the
+/// CLI at `cli_path` is older than [`MIN_CLI_VERSION`](crate::MIN_CLI_VERSION).
+///
+/// A CLI whose version can't be determined passes.
+pub(crate) async fn check_min_version(cli_path: &Path) -> Result<()> {
+    let (Some(found), Ok(required)) = (
+        cli_version(cli_path).await,
+        semver::Version::parse(crate::MIN_CLI_VERSION),
+    ) else {
+        return Ok(());
+    };
+    if found < required {
+        return Err(ClaudeSDKError::version_mismatch(
+            found.to_string(),
+            required.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check `args` against the CLI at `cli_path`, dropping or rejecting the
+/// flags it doesn't support.
+///
+/// The CLI is only asked for its version when `args` use a flag from
+/// [`FLAG_SUPPORT`]; if the version can't be determined, `args` are left
+/// as they are.
+pub(crate) async fn check_args(
+    cli_path: &Path,
+    args: Vec<String>,
+    policy: UnsupportedFlags,
+) -> Result<Vec<String>> {
+    if policy == UnsupportedFlags::Pass || !args.iter().any(|arg| support(arg).is_some()) {
+        return Ok(args);
+    }
+    match cli_version(cli_path).await {
+        Some(version) => apply(args, &version, policy),
+        None => Ok(args),
+    }
+}
+
+/// Drop or reject the flags in `args` that CLI `version` doesn't support.
+pub(crate) fn apply(
+    args: Vec<String>,
+    version: &semver::Version,
+    policy: UnsupportedFlags,
+) -> Result<Vec<String>> {
+    let mut kept = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Whatever follows `--` is the prompt
+        if arg == "--" {
+            kept.push(arg);
+            kept.extend(args);
+            break;
+        }
+        let unsupported = support(&arg).filter(|support| {
+            semver::Version::parse(support.since).is_ok_and(|since| *version < since)
+        });
+        let Some(support) = unsupported else {
+            kept.push(arg);
+            continue;
+        };
+        match policy {
+            UnsupportedFlags::Error => {
+                return Err(ClaudeSDKError::configuration(format!(
+                    "Option '{}' needs Claude CLI {} or later for {}, but version {} is installed",
+                    support.option, support.since, support.flag, version
+                )));
+            }
+            UnsupportedFlags::Omit | UnsupportedFlags::Pass => {
+                warn!(
+                    "Omitting {} (option '{}'): Claude CLI {} doesn't support it, {} or later does",
+                    support.flag, support.option, version, support.since
+                );
+                if support.takes_value {
+                    args.next();
+                }
+            }
+        }
+    }
+    Ok(kept)
+}
+
+fn support(arg: &str) -> Option<&'static FlagSupport> {
+    FLAG_SUPPORT.iter().find(|support| support.flag == arg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_cli_version() {
+        assert_eq!(
+            parse_cli_version("2.0.14 (Claude Code)\n"),
+            Some(semver::Version::new(2, 0, 14))
+        );
+        assert_eq!(
+            parse_cli_version("claude v2.1.0"),
+            Some(semver::Version::new(2, 1, 0))
+        );
+        assert_eq!(parse_cli_version("unknown"), None);
+    }
+
+    #[test]
+    fn test_unsupported_flags_are_omitted() {
+        let input = args(&[
+            "--verbose",
+            "--sandbox",
+            "{}",
+            "--fork-session",
+            "--max-budget-usd",
+            "1.5",
+            "--model",
+            "sonnet",
+            "--",
+            "--sandbox",
+        ]);
+        let old = semver::Version::new(2, 0, 16);
+        let kept = apply(input.clone(), &old, UnsupportedFlags::Omit).unwrap();
+        assert_eq!(
+            kept,
+            args(&[
+                "--verbose",
+                "--fork-session",
+                "--max-budget-usd",
+                "1.5",
+                "--model",
+                "sonnet",
+                "--",
+                "--sandbox"
+            ])
+        );
+
+        let new = semver::Version::new(2, 1, 0);
+        assert_eq!(
+            apply(input, &new, UnsupportedFlags::Omit).unwrap().len(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_unsupported_flags_can_be_errors() {
+        let input = args(&["--verbose", "--sandbox", "{}"]);
+        let err = apply(
+            input,
+            &semver::Version::new(2, 0, 1),
+            UnsupportedFlags::Error,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("'sandbox'"), "{}", err);
+        assert!(err.contains("2.0.24"), "{}", err);
+        assert!(err.contains("2.0.1 is installed"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_check_args_skips_version_probe() {
+        // No gated flags, so the (missing) CLI is never run
+        let input = args(&["--verbose", "--model", "sonnet"]);
+        let kept = check_args(
+            Path::new("/nonexistent/claude"),
+            input.clone(),
+            UnsupportedFlags::Error,
+        )
+        .await
+        .unwrap();
+        assert_eq!(kept, input);
+    }
+}
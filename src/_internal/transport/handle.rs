@@ -0,0 +1,220 @@
+//! The transport a [`Query`](crate::_internal::query::Query) runs over.
+//!
+//! The CLI's own [`SubprocessTransport`] hands out its stdout as a channel
+//! and writes prompts in parts as they are read. Any other [`Transport`]
+//! only offers the trait: its message stream is forwarded to a channel by
+//! a task of its own, prompts are written whole, and frames are metered
+//! here before they are written.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, Mutex, OwnedRwLockReadGuard, RwLock};
+use tokio::task::JoinHandle;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, warn};
+
+#[cfg(feature = "subprocess")]
+use super::SubprocessTransport;
+use super::Transport;
+use crate::_internal::frame_limit::FrameMeter;
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{OutboundFrameLimit, ShutdownReport, ShutdownStage};
+
+/// Capacity of the channel messages of other transports are forwarded to.
+const FORWARD_CAPACITY: usize = 256;
+
+/// How long another transport gets to close.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+enum Inner {
+    #[cfg(feature = "subprocess")]
+    Subprocess(Box<SubprocessTransport>),
+    Other(Box<dyn Transport>),
+}
+
+/// A connected transport, shared by a query and its tasks.
+pub(crate) struct TransportHandle {
+    inner: Arc<RwLock<Inner>>,
+    frame_meter: Arc<FrameMeter>,
+    /// Task forwarding the message stream of another transport.
+    forwarder: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl TransportHandle {
+    /// Run over the CLI's own transport.
+    #[cfg(feature = "subprocess")]
+    pub(crate) fn subprocess(transport: SubprocessTransport) -> Self {
+        Self::subprocess_boxed(Box::new(transport))
+    }
+
+    #[cfg(feature = "subprocess")]
+    fn subprocess_boxed(transport: Box<SubprocessTransport>) -> Self {
+        Self {
+            frame_meter: Arc::clone(transport.frame_meter()),
+            inner: Arc::new(RwLock::new(Inner::Subprocess(transport))),
+            forwarder: Mutex::new(None),
+        }
+    }
+
+    /// Run over a transport given to a client: natively if it's the CLI's
+    /// own, which keeps its own frame limit, or else limited to `limit`.
+    pub(crate) fn new(transport: Box<dyn Transport>, limit: Option<OutboundFrameLimit>) -> Self {
+        #[cfg(feature = "subprocess")]
+        if is_subprocess(&*transport) {
+            // SAFETY: the box holds a SubprocessTransport, checked above, so
+            // this is what `Box<dyn Any>::downcast` does.
+            let transport =
+                unsafe { Box::from_raw(Box::into_raw(transport).cast::<SubprocessTransport>()) };
+            return Self::subprocess_boxed(transport);
+        }
+        Self::other(transport, limit)
+    }
+
+    /// Run over another transport, its frames limited to `limit`.
+    pub(crate) fn other(transport: Box<dyn Transport>, limit: Option<OutboundFrameLimit>) -> Self {
+        Self {
+            frame_meter: Arc::new(FrameMeter::new(limit)),
+            inner: Arc::new(RwLock::new(Inner::Other(transport))),
+            forwarder: Mutex::new(None),
+        }
+    }
+
+    /// Size limit and tally of the frames written.
+    pub(crate) fn frame_meter(&self) -> &Arc<FrameMeter> {
+        &self.frame_meter
+    }
+
+    /// Whether the prompt is written to the transport, rather than given
+    /// to the CLI on its command line.
+    pub(crate) async fn is_streaming_mode(&self) -> bool {
+        match &*self.inner.read().await {
+            #[cfg(feature = "subprocess")]
+            Inner::Subprocess(transport) => transport.is_streaming_mode(),
+            Inner::Other(_) => true,
+        }
+    }
+
+    /// Take the channel of the frames the transport reads, once.
+    pub(crate) async fn take_messages(&self) -> Option<mpsc::Receiver<Result<serde_json::Value>>> {
+        let mut forwarder = self.forwarder.lock().await;
+        if forwarder.is_some() {
+            return None;
+        }
+        let guard = Arc::clone(&self.inner).read_owned().await;
+        #[cfg(feature = "subprocess")]
+        if let Inner::Subprocess(_) = &*guard {
+            drop(guard);
+            return match &mut *self.inner.write().await {
+                Inner::Subprocess(transport) => transport.take_stdout_rx(),
+                Inner::Other(_) => None,
+            };
+        }
+        // The stream borrows the transport, so the task keeps it locked for
+        // reading until shutdown aborts it
+        let (tx, rx) = mpsc::channel(FORWARD_CAPACITY);
+        *forwarder = Some(tokio::spawn(forward(guard, tx)));
+        Some(rx)
+    }
+
+    /// Write one frame.
+    pub(crate) async fn write(&self, line: &str) -> Result<()> {
+        match &*self.inner.read().await {
+            #[cfg(feature = "subprocess")]
+            Inner::Subprocess(transport) => transport.write(line).await,
+            Inner::Other(transport) => {
+                let line = line.trim_end_matches(['\n', '\r']);
+                self.frame_meter.admit(line.len())?;
+                transport.write(line).await
+            }
+        }
+    }
+
+    /// Write one frame from `parts`, as they arrive if the transport can.
+    pub(crate) async fn write_parts<S>(&self, mut parts: S) -> Result<()>
+    where
+        S: Stream<Item = Result<String>> + Unpin,
+    {
+        #[cfg(feature = "subprocess")]
+        if let Inner::Subprocess(transport) = &*self.inner.read().await {
+            return transport.write_parts(parts).await;
+        }
+        let mut line = String::new();
+        while let Some(part) = parts.next().await {
+            line.push_str(&part?);
+            self.frame_meter.check(line.len())?;
+        }
+        if line.contains(['\n', '\r']) {
+            return Err(ClaudeSDKError::internal(
+                "Frame written to transport contains a line break",
+            ));
+        }
+        self.write(&line).await
+    }
+
+    /// Close the transport, recording each stage in `report`.
+    pub(crate) async fn shutdown(&self, report: &mut ShutdownReport) {
+        let forwarder = self.forwarder.lock().await.take();
+        if let Some(task) = forwarder {
+            task.abort();
+            let _ = task.await;
+        }
+        match &mut *self.inner.write().await {
+            #[cfg(feature = "subprocess")]
+            Inner::Subprocess(transport) => transport.shutdown(report).await,
+            Inner::Other(transport) => {
+                let closed = report
+                    .run_stage(
+                        ShutdownStage::CloseTransport,
+                        CLOSE_TIMEOUT,
+                        transport.close(),
+                    )
+                    .await;
+                match closed {
+                    Some(Ok(())) => debug!("Transport closed"),
+                    Some(Err(e)) => warn!("Failed to close transport: {}", e),
+                    None => warn!("Transport did not close in time"),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TransportHandle {
+    fn drop(&mut self) {
+        if let Some(task) = self.forwarder.get_mut().take() {
+            task.abort();
+        }
+    }
+}
+
+/// Forward the message stream of the transport behind `guard` to `tx`.
+async fn forward(guard: OwnedRwLockReadGuard<Inner>, tx: mpsc::Sender<Result<serde_json::Value>>) {
+    #[allow(clippy::infallible_destructuring_match)] // without the `subprocess` feature
+    let transport = match &*guard {
+        Inner::Other(transport) => transport,
+        #[cfg(feature = "subprocess")]
+        Inner::Subprocess(_) => return,
+    };
+    let mut stream = transport.message_stream();
+    while let Some(frame) = stream.next().await {
+        if tx.send(frame).await.is_err() {
+            debug!("Transport forwarder: receiver dropped");
+            break;
+        }
+    }
+    debug!("Transport forwarder finished");
+}
+
+/// Whether `transport` is a [`SubprocessTransport`] itself, rather than
+/// something else claiming to be one.
+#[cfg(feature = "subprocess")]
+fn is_subprocess(transport: &dyn Transport) -> bool {
+    transport.as_any().is_some_and(|any| {
+        any.is::<SubprocessTransport>()
+            && std::ptr::eq(
+                (any as *const dyn std::any::Any).cast::<()>(),
+                (transport as *const dyn Transport).cast::<()>(),
+            )
+    })
+}
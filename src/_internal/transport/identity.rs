@@ -0,0 +1,538 @@
+//! Running the CLI as a different user.
+//!
+//! With `run_as`, the CLI and every tool it runs get fewer permissions than
+//! the SDK, so they can't read the host user's credentials and dotfiles.
+//! On Unix the child switches to the configured uid and gid, dropping
+//! supplementary groups, before running the CLI; this needs root or
+//! `CAP_SETUID` and `CAP_SETGID`. On Windows the CLI is started under a
+//! restricted version of the SDK's own token, with the administrator group
+//! disabled and privileges removed. Other platforms aren't supported.
+//!
+//! Either way the CLI gets only the host environment variables the
+//! [`RunAs`] allowlist names, plus those set in the options.
+
+use std::collections::HashMap;
+
+use tokio::process::Command;
+
+use crate::types::RunAs;
+
+/// The host environment variables `run_as` passes on to the CLI.
+pub(crate) fn inherited_env(run_as: &RunAs) -> HashMap<String, String> {
+    std::env::vars()
+        .filter(|(key, _)| {
+            run_as.inherit_env.iter().any(|name| {
+                // Variable names are case-insensitive on Windows
+                if cfg!(windows) {
+                    name.eq_ignore_ascii_case(key)
+                } else {
+                    name == key
+                }
+            })
+        })
+        .collect()
+}
+
+/// Point `HOME`, `USER` and `LOGNAME` in `env` at the target user, so the
+/// CLI keeps its configuration in that user's home, unless `explicit` (the
+/// configured environment) sets them.
+pub(crate) fn apply_env(
+    env: &mut HashMap<String, String>,
+    run_as: &RunAs,
+    explicit: &HashMap<String, String>,
+) {
+    #[cfg(unix)]
+    {
+        let account = account(run_as.uid);
+        let home = run_as
+            .home
+            .clone()
+            .or_else(|| account.as_ref().map(|(_, home)| home.clone()));
+        let mut set = |key: &str, value: String| {
+            if !explicit.contains_key(key) {
+                env.insert(key.to_string(), value);
+            }
+        };
+        if let Some(home) = home {
+            set("HOME", home.to_string_lossy().into_owned());
+        }
+        if let Some((name, _)) = account {
+            set("USER", name.clone());
+            set("LOGNAME", name);
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (env, run_as, explicit);
+}
+
+/// Prepare `cmd` to run as `run_as`.
+///
+/// On Windows the command isn't used: the CLI is started by
+/// [`spawn_restricted`] instead. Returns an error message if the platform
+/// can't lower the CLI's permissions.
+pub(crate) fn configure(cmd: &mut Command, run_as: &RunAs) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        tracing::debug!("Running the CLI as uid {} gid {}", run_as.uid, run_as.gid);
+        cmd.uid(run_as.uid).gid(run_as.gid);
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = (cmd, run_as);
+        tracing::debug!("Running the CLI under a restricted token");
+        Ok(())
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = cmd;
+        Err(format!(
+            "Running the CLI as uid {} gid {} is only supported on Unix and Windows",
+            run_as.uid, run_as.gid
+        ))
+    }
+}
+
+/// Command line running `program` with `args`, quoted the way the C
+/// runtime splits it.
+#[cfg(any(windows, test))]
+fn command_line(program: &str, args: &[String]) -> String {
+    let mut line = String::new();
+    for (i, arg) in std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .enumerate()
+    {
+        if i > 0 {
+            line.push(' ');
+        }
+        if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+            line.push_str(arg);
+            continue;
+        }
+        line.push('"');
+        let mut backslashes = 0;
+        for c in arg.chars() {
+            match c {
+                '\\' => backslashes += 1,
+                // Backslashes before a quote escape, so double them and
+                // escape the quote too
+                '"' => {
+                    line.extend(std::iter::repeat('\\').take(backslashes + 1));
+                    backslashes = 0;
+                }
+                _ => backslashes = 0,
+            }
+            line.push(c);
+        }
+        // As do those before the closing quote
+        line.extend(std::iter::repeat('\\').take(backslashes));
+        line.push('"');
+    }
+    line
+}
+
+/// Environment block holding `env`, sorted as Windows expects.
+#[cfg(any(windows, test))]
+fn environment_block(env: &HashMap<String, String>) -> Vec<u16> {
+    let mut vars: Vec<_> = env.iter().collect();
+    vars.sort_by_key(|(key, _)| key.to_uppercase());
+    let mut block: Vec<u16> = vars
+        .into_iter()
+        .flat_map(|(key, value)| {
+            format!("{}={}\0", key, value)
+                .encode_utf16()
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    // An empty block still needs its terminator
+    if block.is_empty() {
+        block.push(0);
+    }
+    block.push(0);
+    block
+}
+
+#[cfg(windows)]
+pub(crate) use windows::{spawn_restricted, RestrictedProcess};
+
+#[cfg(windows)]
+mod windows {
+    use std::collections::HashMap;
+    use std::io;
+    use std::mem::size_of;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle, RawHandle};
+    use std::os::windows::process::ExitStatusExt;
+    use std::path::Path;
+    use std::process::ExitStatus;
+    use std::ptr::{null, null_mut};
+    use std::time::Duration;
+
+    use windows_sys::Win32::Foundation::{
+        SetHandleInformation, HANDLE, HANDLE_FLAG_INHERIT, WAIT_FAILED, WAIT_OBJECT_0,
+    };
+    use windows_sys::Win32::Security::{
+        CreateRestrictedToken, CreateWellKnownSid, WinBuiltinAdministratorsSid,
+        DISABLE_MAX_PRIVILEGE, LUA_TOKEN, PSID, SECURITY_ATTRIBUTES, SECURITY_MAX_SID_SIZE,
+        SID_AND_ATTRIBUTES, TOKEN_ASSIGN_PRIMARY, TOKEN_DUPLICATE, TOKEN_QUERY,
+    };
+    use windows_sys::Win32::System::Pipes::CreatePipe;
+    use windows_sys::Win32::System::Threading::{
+        CreateProcessAsUserW, GetCurrentProcess, GetExitCodeProcess, OpenProcessToken,
+        TerminateProcess, WaitForSingleObject, CREATE_UNICODE_ENVIRONMENT, PROCESS_INFORMATION,
+        STARTF_USESTDHANDLES, STARTUPINFOW,
+    };
+
+    use super::{command_line, environment_block};
+
+    /// How often a wait checks whether the CLI has exited.
+    const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    /// The CLI started under a restricted token, with the SDK's ends of its
+    /// standard streams.
+    pub(crate) struct RestrictedChild {
+        pub(crate) process: RestrictedProcess,
+        /// `None` unless in streaming mode.
+        pub(crate) stdin: Option<tokio::fs::File>,
+        pub(crate) stdout: tokio::fs::File,
+        pub(crate) stderr: tokio::fs::File,
+    }
+
+    /// The CLI process, through its handle.
+    pub(crate) struct RestrictedProcess {
+        handle: OwnedHandle,
+        pid: u32,
+        status: Option<ExitStatus>,
+    }
+
+    fn check(result: i32) -> io::Result<()> {
+        if result == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Take ownership of a handle the system just returned.
+    fn owned(handle: HANDLE) -> OwnedHandle {
+        // SAFETY: callers pass a valid handle that nothing else owns.
+        unsafe { OwnedHandle::from_raw_handle(handle as RawHandle) }
+    }
+
+    /// A restricted version of the SDK's own token: the administrator group
+    /// only denies access, and all privileges but change notification are
+    /// removed.
+    fn restricted_token() -> io::Result<OwnedHandle> {
+        let mut token: HANDLE = null_mut();
+        // SAFETY: `token` is valid for writes.
+        check(unsafe {
+            OpenProcessToken(
+                GetCurrentProcess(),
+                TOKEN_DUPLICATE | TOKEN_ASSIGN_PRIMARY | TOKEN_QUERY,
+                &mut token,
+            )
+        })?;
+        let token = owned(token);
+
+        let mut admins = [0u8; SECURITY_MAX_SID_SIZE as usize];
+        let mut size = SECURITY_MAX_SID_SIZE;
+        // SAFETY: `admins` holds `size` bytes.
+        check(unsafe {
+            CreateWellKnownSid(
+                WinBuiltinAdministratorsSid,
+                null_mut(),
+                admins.as_mut_ptr() as PSID,
+                &mut size,
+            )
+        })?;
+        let disable = SID_AND_ATTRIBUTES {
+            Sid: admins.as_mut_ptr() as PSID,
+            Attributes: 0,
+        };
+
+        let mut restricted: HANDLE = null_mut();
+        // SAFETY: every pointer is valid for the duration of the call.
+        check(unsafe {
+            CreateRestrictedToken(
+                token.as_raw_handle() as HANDLE,
+                DISABLE_MAX_PRIVILEGE | LUA_TOKEN,
+                1,
+                &disable,
+                0,
+                null(),
+                0,
+                null(),
+                &mut restricted,
+            )
+        })?;
+        Ok(owned(restricted))
+    }
+
+    /// An anonymous pipe, as the end the SDK keeps and the end the CLI
+    /// inherits.
+    fn pipe(child_reads: bool) -> io::Result<(OwnedHandle, OwnedHandle)> {
+        let attributes = SECURITY_ATTRIBUTES {
+            nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: null_mut(),
+            bInheritHandle: 1,
+        };
+        let (mut read, mut write): (HANDLE, HANDLE) = (null_mut(), null_mut());
+        // SAFETY: both handles are valid for writes.
+        check(unsafe { CreatePipe(&mut read, &mut write, &attributes, 0) })?;
+        let (read, write) = (owned(read), owned(write));
+        let (parent, child) = if child_reads {
+            (write, read)
+        } else {
+            (read, write)
+        };
+        // SAFETY: `parent` is a valid handle.
+        check(unsafe {
+            SetHandleInformation(parent.as_raw_handle() as HANDLE, HANDLE_FLAG_INHERIT, 0)
+        })?;
+        Ok((parent, child))
+    }
+
+    /// Start `program` with `args`, exactly the environment `env`, in `cwd`,
+    /// under a restricted token.
+    pub(crate) fn spawn_restricted(
+        program: &Path,
+        args: &[String],
+        env: &HashMap<String, String>,
+        cwd: Option<&Path>,
+        streaming: bool,
+    ) -> io::Result<RestrictedChild> {
+        let program = program.to_str().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "CLI path is not valid Unicode")
+        })?;
+        let lower = program.to_ascii_lowercase();
+        if lower.ends_with(".cmd") || lower.ends_with(".bat") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "A restricted token needs the CLI as an executable, not a batch file",
+            ));
+        }
+
+        let token = restricted_token()?;
+        let (stdin, child_stdin) = pipe(true)?;
+        let (stdout, child_stdout) = pipe(false)?;
+        let (stderr, child_stderr) = pipe(false)?;
+        let mut line: Vec<u16> = command_line(program, args)
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+        let env = environment_block(env);
+        let cwd: Option<Vec<u16>> =
+            cwd.map(|dir| dir.as_os_str().encode_wide().chain(Some(0)).collect());
+        let startup = STARTUPINFOW {
+            cb: size_of::<STARTUPINFOW>() as u32,
+            dwFlags: STARTF_USESTDHANDLES,
+            hStdInput: child_stdin.as_raw_handle() as HANDLE,
+            hStdOutput: child_stdout.as_raw_handle() as HANDLE,
+            hStdError: child_stderr.as_raw_handle() as HANDLE,
+            ..Default::default()
+        };
+        let mut info = PROCESS_INFORMATION::default();
+        // SAFETY: every pointer is valid for the duration of the call, and
+        // `line` is writable as the call requires.
+        check(unsafe {
+            CreateProcessAsUserW(
+                token.as_raw_handle() as HANDLE,
+                null(),
+                line.as_mut_ptr(),
+                null(),
+                null(),
+                1,
+                CREATE_UNICODE_ENVIRONMENT,
+                env.as_ptr().cast(),
+                cwd.as_ref().map_or(null(), |dir| dir.as_ptr()),
+                &startup,
+                &mut info,
+            )
+        })?;
+        drop(owned(info.hThread));
+        // The CLI has its own copies of its ends now; closing ours lets it
+        // see the end of its input
+        drop((child_stdin, child_stdout, child_stderr));
+
+        let file = |handle: OwnedHandle| tokio::fs::File::from_std(std::fs::File::from(handle));
+        Ok(RestrictedChild {
+            process: RestrictedProcess {
+                handle: owned(info.hProcess),
+                pid: info.dwProcessId,
+                status: None,
+            },
+            stdin: streaming.then(|| file(stdin)),
+            stdout: file(stdout),
+            stderr: file(stderr),
+        })
+    }
+
+    impl RestrictedProcess {
+        pub(crate) fn id(&self) -> Option<u32> {
+            Some(self.pid)
+        }
+
+        pub(crate) fn raw_handle(&self) -> RawHandle {
+            self.handle.as_raw_handle()
+        }
+
+        /// The exit status, if the CLI has exited.
+        pub(crate) fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+            if self.status.is_none() {
+                let handle = self.handle.as_raw_handle() as HANDLE;
+                // SAFETY: `handle` is valid for as long as `self`.
+                match unsafe { WaitForSingleObject(handle, 0) } {
+                    WAIT_OBJECT_0 => {
+                        let mut code = 0;
+                        // SAFETY: as above, and `code` is valid for writes.
+                        check(unsafe { GetExitCodeProcess(handle, &mut code) })?;
+                        self.status = Some(ExitStatus::from_raw(code));
+                    }
+                    WAIT_FAILED => return Err(io::Error::last_os_error()),
+                    _ => {}
+                }
+            }
+            Ok(self.status)
+        }
+
+        pub(crate) async fn wait(&mut self) -> io::Result<ExitStatus> {
+            loop {
+                if let Some(status) = self.try_wait()? {
+                    return Ok(status);
+                }
+                tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+            }
+        }
+
+        pub(crate) fn start_kill(&mut self) -> io::Result<()> {
+            if self.try_wait()?.is_some() {
+                return Ok(());
+            }
+            // SAFETY: the handle is valid for as long as `self`.
+            check(unsafe { TerminateProcess(self.handle.as_raw_handle() as HANDLE, 1) })
+        }
+
+        pub(crate) async fn kill(&mut self) -> io::Result<()> {
+            self.start_kill()?;
+            self.wait().await.map(drop)
+        }
+    }
+}
+
+/// Explain a spawn that failed with `PermissionDenied` while switching to
+/// `run_as`.
+pub(crate) fn privilege_error(run_as: &RunAs) -> Option<String> {
+    #[cfg(unix)]
+    {
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        let euid = unsafe { libc::geteuid() };
+        // Root can switch to anyone; the target user couldn't run the CLI
+        if euid == 0 {
+            return None;
+        }
+        Some(format!(
+            "Cannot run the CLI as uid {} gid {}: the SDK runs as uid {}, and switching users \
+             needs root or the CAP_SETUID and CAP_SETGID capabilities",
+            run_as.uid, run_as.gid, euid
+        ))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = run_as;
+        None
+    }
+}
+
+/// Name and home directory of the account with `uid`, if it has one.
+#[cfg(unix)]
+fn account(uid: u32) -> Option<(String, std::path::PathBuf)> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    // SAFETY: passwd is plain data; getpwuid_r fills it in on success.
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+    // SAFETY: every pointer is valid for the duration of the call, and the
+    // strings it writes live in `buf`.
+    let rc = unsafe { libc::getpwuid_r(uid, &mut entry, buf.as_mut_ptr(), buf.len(), &mut found) };
+    if rc != 0 || found.is_null() || entry.pw_name.is_null() || entry.pw_dir.is_null() {
+        return None;
+    }
+    // SAFETY: both point to NUL-terminated strings in `buf`.
+    let (name, dir) = unsafe { (CStr::from_ptr(entry.pw_name), CStr::from_ptr(entry.pw_dir)) };
+    Some((
+        name.to_string_lossy().into_owned(),
+        std::path::PathBuf::from(OsStr::from_bytes(dir.to_bytes())),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line_quoting() {
+        let args = [
+            "-p".to_string(),
+            "two words".to_string(),
+            String::new(),
+            r#"say "hi""#.to_string(),
+            r"C:\my dir\".to_string(),
+            r#"a\"b"#.to_string(),
+        ];
+        assert_eq!(
+            command_line(r"C:\bin\claude.exe", &args),
+            r#"C:\bin\claude.exe -p "two words" "" "say \"hi\"" "C:\my dir\\" "a\\\"b""#
+        );
+    }
+
+    #[test]
+    fn test_environment_block() {
+        let env = HashMap::from([
+            ("b".to_string(), "2".to_string()),
+            ("A".to_string(), "1".to_string()),
+        ]);
+        let block = String::from_utf16(&environment_block(&env)).unwrap();
+        assert_eq!(block, "A=1\0b=2\0\0");
+        assert_eq!(environment_block(&HashMap::new()), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_inherited_env_is_allowlisted() {
+        let run_as = RunAs::new(0, 0).with_inherited_env(["PATH"]);
+        let env = inherited_env(&run_as);
+        assert_eq!(env.get("PATH"), std::env::var("PATH").ok().as_ref());
+        assert!(env.keys().all(|key| key == "PATH"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_account_lookup() {
+        // SAFETY: getuid has no preconditions and cannot fail.
+        let uid = unsafe { libc::getuid() };
+        if let Some((name, home)) = account(uid) {
+            assert!(!name.is_empty());
+            assert!(home.is_absolute());
+        }
+        assert!(account(u32::MAX - 1).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_env_respects_explicit_values() {
+        let run_as = RunAs::new(0, 0).with_home("/srv/agent");
+        let mut env = HashMap::from([("HOME".to_string(), "/root".to_string())]);
+        apply_env(&mut env, &run_as, &HashMap::new());
+        assert_eq!(env["HOME"], "/srv/agent");
+
+        let explicit = HashMap::from([("HOME".to_string(), "/custom".to_string())]);
+        let mut env = explicit.clone();
+        apply_env(&mut env, &run_as, &explicit);
+        assert_eq!(env["HOME"], "/custom");
+    }
+}
@@ -2,10 +2,28 @@
 //!
 //! This module provides the [`Transport`] trait for abstracting communication
 //! with the CLI process, and [`SubprocessTransport`] as the concrete implementation.
+//! With the `websocket` feature, `WebSocketTransport` reaches a CLI running
+//! elsewhere through a bridge.
 
+#[cfg(feature = "subprocess")]
+mod compat;
+mod handle;
+#[cfg(feature = "subprocess")]
+mod identity;
+#[cfg(feature = "subprocess")]
+mod process_tree;
+#[cfg(feature = "subprocess")]
 mod subprocess;
+#[cfg(feature = "websocket")]
+mod websocket;
 
-pub use subprocess::SubprocessTransport;
+#[cfg(feature = "subprocess")]
+pub(crate) use compat::parse_cli_version;
+pub(crate) use handle::TransportHandle;
+#[cfg(feature = "subprocess")]
+pub use subprocess::{JsonLineReader, SubprocessTransport};
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketTransport;
 
 use async_trait::async_trait;
 use std::pin::Pin;
@@ -37,4 +55,11 @@ pub trait Transport: Send + Sync {
 
     /// Check if the transport is ready for communication.
     fn is_ready(&self) -> bool;
+
+    /// The transport as [`Any`](std::any::Any), so the SDK can recognize
+    /// its own transports and run them natively.
+    #[doc(hidden)]
+    fn as_any(&self) -> Option<&dyn std::any::Any> {
+        None
+    }
 }
@@ -0,0 +1,194 @@
+//! Termination of the CLI process together with its descendants.
+//!
+//! Killing only the CLI leaves processes started by its tools (shells,
+//! `sleep`, dev servers) running. With `kill_descendants_on_drop`, the CLI
+//! is spawned in its own process group on Unix, or assigned to a job object
+//! on Windows, and the whole tree is terminated when the transport closes.
+
+use tokio::process::{Child, Command};
+use tracing::{debug, warn};
+
+/// The CLI process tree, killed on [`kill`](Self::kill) or drop.
+pub(crate) struct ProcessTree {
+    #[cfg(unix)]
+    pgid: libc::pid_t,
+    /// Job object handle, stored as an integer so the type stays `Send`.
+    #[cfg(windows)]
+    job: isize,
+    killed: bool,
+}
+
+impl ProcessTree {
+    /// Prepare `cmd` so its process tree can be tracked after spawning.
+    pub(crate) fn configure(cmd: &mut Command) {
+        #[cfg(unix)]
+        cmd.process_group(0);
+        #[cfg(not(unix))]
+        let _ = cmd;
+    }
+
+    /// Start tracking the tree of a child spawned from a command passed to
+    /// [`configure`](Self::configure).
+    pub(crate) fn attach(child: &Child) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            let pgid = libc::pid_t::try_from(child.id()?).ok()?;
+            Some(Self {
+                pgid,
+                killed: false,
+            })
+        }
+
+        #[cfg(windows)]
+        {
+            Self::attach_handle(child.raw_handle()?)
+        }
+
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = child;
+            None
+        }
+    }
+
+    /// Start tracking the tree of the process with handle `process`.
+    #[cfg(windows)]
+    pub(crate) fn attach_handle(process: std::os::windows::io::RawHandle) -> Option<Self> {
+        let job = windows::create_kill_on_close_job(process)?;
+        Some(Self { job, killed: false })
+    }
+
+    /// Terminate every process in the tree.
+    pub(crate) fn kill(&mut self) {
+        if self.killed {
+            return;
+        }
+        self.killed = true;
+
+        #[cfg(unix)]
+        {
+            // SAFETY: killpg has no memory-safety preconditions.
+            let result = unsafe { libc::killpg(self.pgid, libc::SIGKILL) };
+            if result == 0 {
+                debug!("Killed CLI process group {}", self.pgid);
+            } else {
+                let error = std::io::Error::last_os_error();
+                // ESRCH: every process in the group has already exited
+                if error.raw_os_error() != Some(libc::ESRCH) {
+                    warn!("Failed to kill CLI process group {}: {}", self.pgid, error);
+                }
+            }
+        }
+
+        #[cfg(windows)]
+        windows::terminate(self.job);
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        self.kill();
+        #[cfg(windows)]
+        windows::close(self.job);
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::os::windows::io::RawHandle;
+
+    use tracing::{debug, warn};
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    /// Create a job object that kills its processes when closed, and assign
+    /// `process` to it.
+    pub(super) fn create_kill_on_close_job(process: RawHandle) -> Option<isize> {
+        // SAFETY: all pointers passed are valid for the duration of each call,
+        // and the job handle is closed on every failure path.
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() {
+                warn!(
+                    "Failed to create job object: {}",
+                    std::io::Error::last_os_error()
+                );
+                return None;
+            }
+
+            let mut limits = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+            limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            let configured = SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &limits as *const _ as *const std::ffi::c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            ) != 0;
+            if !configured || AssignProcessToJobObject(job, process as HANDLE) == 0 {
+                warn!(
+                    "Failed to assign CLI process to job object: {}",
+                    std::io::Error::last_os_error()
+                );
+                CloseHandle(job);
+                return None;
+            }
+            Some(job as isize)
+        }
+    }
+
+    pub(super) fn terminate(job: isize) {
+        // SAFETY: `job` is a job object handle owned by the ProcessTree.
+        if unsafe { TerminateJobObject(job as HANDLE, 1) } != 0 {
+            debug!("Terminated CLI job object");
+        }
+    }
+
+    pub(super) fn close(job: isize) {
+        // SAFETY: `job` is owned by the ProcessTree and closed exactly once.
+        unsafe {
+            CloseHandle(job as HANDLE);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+    use std::time::Duration;
+    use tokio::io::AsyncBufReadExt;
+
+    #[tokio::test]
+    async fn test_kill_terminates_grandchildren() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(Stdio::piped())
+            .kill_on_drop(true);
+        ProcessTree::configure(&mut cmd);
+        let mut child = cmd.spawn().unwrap();
+        let mut tree = ProcessTree::attach(&child).unwrap();
+
+        let stdout = child.stdout.take().unwrap();
+        let mut lines = tokio::io::BufReader::new(stdout).lines();
+        let grandchild: libc::pid_t = lines.next_line().await.unwrap().unwrap().parse().unwrap();
+
+        tree.kill();
+        child.wait().await.unwrap();
+
+        // Killed but unreaped processes linger as zombies
+        let mut alive = true;
+        for _ in 0..50 {
+            alive = std::fs::read_to_string(format!("/proc/{}/stat", grandchild))
+                .is_ok_and(|stat| !stat.contains(") Z "));
+            if !alive {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!alive, "grandchild {} survived", grandchild);
+    }
+}
@@ -9,14 +9,23 @@ use std::path::PathBuf;
 use std::pin::Pin;
 use std::process::Stdio;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
 use tokio_stream::Stream;
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, error, trace, warn, Instrument};
 
+use super::compat;
+use super::identity;
+use super::process_tree::ProcessTree;
 use super::Transport;
-use crate::errors::{ClaudeSDKError, Result};
+use crate::_internal::frame_limit::FrameMeter;
+use crate::config_dir::CONFIG_DIR_ENV;
+use crate::diagnostics::Diagnostic;
+use crate::env_vars;
+use crate::errors::{CLILaunchError, CLILaunchErrorKind, ClaudeSDKError, Result};
 use crate::types::*;
 
 /// Default maximum buffer size (1MB).
@@ -25,6 +34,281 @@ const DEFAULT_MAX_BUFFER_SIZE: usize = 1024 * 1024;
 /// Default CLI command name.
 const DEFAULT_CLI_PATH: &str = "claude";
 
+/// Writing end of the CLI's stdin.
+type CliStdin = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// The CLI process.
+enum CliProcess {
+    Tokio(Child),
+    /// Started under a restricted token for `run_as`.
+    #[cfg(windows)]
+    Restricted(identity::RestrictedProcess),
+}
+
+impl CliProcess {
+    #[cfg(test)]
+    fn id(&self) -> Option<u32> {
+        match self {
+            Self::Tokio(child) => child.id(),
+            #[cfg(windows)]
+            Self::Restricted(process) => process.id(),
+        }
+    }
+
+    fn try_wait(&mut self) -> std::io::Result<Option<std::process::ExitStatus>> {
+        match self {
+            Self::Tokio(child) => child.try_wait(),
+            #[cfg(windows)]
+            Self::Restricted(process) => process.try_wait(),
+        }
+    }
+
+    async fn wait(&mut self) -> std::io::Result<std::process::ExitStatus> {
+        match self {
+            Self::Tokio(child) => child.wait().await,
+            #[cfg(windows)]
+            Self::Restricted(process) => process.wait().await,
+        }
+    }
+
+    fn start_kill(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Tokio(child) => child.start_kill(),
+            #[cfg(windows)]
+            Self::Restricted(process) => process.start_kill(),
+        }
+    }
+
+    async fn kill(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Tokio(child) => child.kill().await,
+            #[cfg(windows)]
+            Self::Restricted(process) => process.kill().await,
+        }
+    }
+}
+
+/// How long the CLI gets to exit after its stdin is closed.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Timeout of the other shutdown stages.
+const STAGE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Times a dropped transport checks whether the killed CLI can be reaped.
+const DROP_REAP_ATTEMPTS: u32 = 10;
+
+/// Pause between those checks.
+const DROP_REAP_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Flags whose values may embed credentials (MCP headers, settings, env).
+const REDACTED_FLAGS: &[&str] = &["--mcp-config", "--mcp-servers", "--settings", "--agents"];
+
+/// Flags whose values are free text and are only truncated.
+const TRUNCATED_FLAGS: &[&str] = &["--system-prompt", "--append-system-prompt"];
+
+/// Longest free-text argument kept verbatim in launch errors.
+const MAX_DISPLAYED_ARG_LEN: usize = 80;
+
+/// Redact command-line arguments for inclusion in error messages.
+///
+/// Values of flags that may carry secrets are replaced, long prompts are
+/// truncated, and the positional prompt after `--` is dropped.
+pub(crate) fn redact_args(args: &[String]) -> Vec<String> {
+    let mut redacted = Vec::with_capacity(args.len());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        redacted.push(arg.clone());
+        if arg == "--" {
+            if iter.next().is_some() {
+                redacted.push("<prompt>".to_string());
+            }
+            continue;
+        }
+        let lower = arg.to_ascii_lowercase();
+        let sensitive = REDACTED_FLAGS.contains(&arg.as_str())
+            || [
+                "api-key",
+                "auth-token",
+                "access-token",
+                "secret",
+                "password",
+            ]
+            .iter()
+            .any(|word| arg.starts_with("--") && lower.contains(word));
+        if sensitive {
+            if let Some(value) = iter.next() {
+                redacted.push(format!("<redacted {} bytes>", value.len()));
+            }
+        } else if TRUNCATED_FLAGS.contains(&arg.as_str()) {
+            if let Some(value) = iter.next() {
+                redacted.push(truncate_arg(value));
+            }
+        }
+    }
+    redacted
+}
+
+fn truncate_arg(value: &str) -> String {
+    if value.chars().count() <= MAX_DISPLAYED_ARG_LEN {
+        return value.to_string();
+    }
+    let head: String = value.chars().take(MAX_DISPLAYED_ARG_LEN).collect();
+    format!("{}... ({} chars)", head, value.chars().count())
+}
+
+/// UTF-8 byte order mark, emitted by some shells and locales.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Newline-delimited JSON reader over an async byte stream.
+///
+/// Lines are framed in a single reusable buffer and parsed straight from
+/// its bytes, so reading a message costs no per-line allocation.
+///
+/// To cope with CLIs launched through shells or wrappers, a leading UTF-8
+/// byte order mark is stripped from each line, blank lines are skipped,
+/// and lines before the first JSON value that don't look like JSON (login
+/// banners, `nvm` notices, ...) are skipped with a warning instead of
+/// failing the first parse.
+pub struct JsonLineReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    /// Start of the part of `buf` not yet returned.
+    start: usize,
+    /// End of the part of `buf` already searched for a newline.
+    scanned: usize,
+    /// Whether a JSON value has been read yet.
+    started: bool,
+    /// How much of each line to log.
+    log_sampling: LogSampling,
+}
+
+impl<R: AsyncRead + Unpin> JsonLineReader<R> {
+    /// Minimum free space to keep in the buffer before each read.
+    const MIN_READ_SIZE: usize = 8 * 1024;
+
+    /// Create a reader with the given initial buffer capacity.
+    pub fn new(reader: R, capacity: usize) -> Self {
+        Self {
+            reader,
+            buf: Vec::with_capacity(capacity.max(Self::MIN_READ_SIZE)),
+            start: 0,
+            scanned: 0,
+            started: false,
+            log_sampling: LogSampling::default(),
+        }
+    }
+
+    /// Log lines per `sampling`.
+    pub fn with_log_sampling(mut self, sampling: LogSampling) -> Self {
+        self.log_sampling = sampling;
+        self
+    }
+
+    /// Read the next line, without its line terminator.
+    ///
+    /// Returns `None` at EOF. A final line without a trailing newline is
+    /// still returned.
+    pub async fn next_line(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let line = self.frame_line().await?;
+        Ok(line.map(|range| self.buf[range].to_vec()))
+    }
+
+    /// Frame the next line, returning where it is in the buffer, without
+    /// its line terminator. The range is valid until the next call.
+    async fn frame_line(&mut self) -> std::io::Result<Option<std::ops::Range<usize>>> {
+        loop {
+            if let Some(pos) = self.buf[self.scanned..].iter().position(|&b| b == b'\n') {
+                let newline = self.scanned + pos;
+                let start = std::mem::replace(&mut self.start, newline + 1);
+                self.scanned = self.start;
+                let end = match self.buf[start..newline].last() {
+                    Some(b'\r') => newline - 1,
+                    _ => newline,
+                };
+                return Ok(Some(start..end));
+            }
+
+            // Drop the lines already returned before reading more
+            self.buf.drain(..self.start);
+            self.start = 0;
+            self.scanned = self.buf.len();
+
+            self.buf.reserve(Self::MIN_READ_SIZE);
+            if self.reader.read_buf(&mut self.buf).await? == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                self.start = self.buf.len();
+                self.scanned = self.start;
+                return Ok(Some(0..self.start));
+            }
+        }
+    }
+
+    /// Frame the next line that may hold a JSON value, skipping blank lines
+    /// and startup banners.
+    async fn next_json_line(&mut self) -> std::io::Result<Option<std::ops::Range<usize>>> {
+        loop {
+            let Some(mut range) = self.frame_line().await? else {
+                return Ok(None);
+            };
+            if self.buf[range.clone()].starts_with(UTF8_BOM) {
+                range.start += UTF8_BOM.len();
+            }
+
+            let line = &self.buf[range.clone()];
+            match line.iter().find(|b| !b.is_ascii_whitespace()) {
+                None => {
+                    trace!("Skipping blank line from CLI");
+                    continue;
+                }
+                Some(b'{' | b'[') => {}
+                Some(_) if !self.started => {
+                    warn!(
+                        "Skipping non-JSON line before the first CLI message: {}",
+                        self.log_sampling.sample(line)
+                    );
+                    continue;
+                }
+                Some(_) => {}
+            }
+            return Ok(Some(range));
+        }
+    }
+
+    /// Read and parse the next JSON line.
+    ///
+    /// Returns `None` at EOF. Read failures are reported as connection
+    /// errors, after which the reader should not be used again.
+    pub async fn next_value(&mut self) -> Option<Result<serde_json::Value>> {
+        let line = match self.next_json_line().await {
+            Ok(Some(range)) => &self.buf[range],
+            Ok(None) => return None,
+            Err(e) => {
+                return Some(Err(ClaudeSDKError::cli_connection_with_source(
+                    "Failed to read from CLI stdout",
+                    e,
+                )))
+            }
+        };
+
+        trace!("Received line from CLI: {}", self.log_sampling.sample(line));
+
+        let parsed = serde_json::from_slice(line);
+        if parsed.is_ok() {
+            self.started = true;
+        }
+        Some(parsed.map_err(|e| {
+            ClaudeSDKError::json_decode_with_context(
+                "Failed to parse JSON from CLI",
+                Some(String::from_utf8_lossy(line).into_owned()),
+                None,
+                e,
+            )
+        }))
+    }
+}
+
 /// Subprocess-based transport for communicating with the Claude CLI.
 ///
 /// This transport spawns the Claude CLI as a subprocess and communicates
@@ -43,13 +327,15 @@ pub struct SubprocessTransport {
     /// Maximum buffer size.
     max_buffer_size: usize,
     /// Child process handle.
-    process: Option<Child>,
+    process: Option<CliProcess>,
     /// Stdin handle (wrapped in mutex for thread safety).
-    stdin: Option<Arc<Mutex<tokio::process::ChildStdin>>>,
+    stdin: Option<Arc<Mutex<CliStdin>>>,
     /// Stdout lines stream receiver.
     stdout_rx: Option<tokio::sync::mpsc::Receiver<Result<serde_json::Value>>>,
     /// Stderr callback.
     stderr_callback: Option<Arc<dyn Fn(String) + Send + Sync>>,
+    /// Where to publish stderr lines parsed as diagnostics, if anywhere.
+    diagnostics: Option<broadcast::Sender<Arc<Diagnostic>>>,
     /// Whether the transport is ready.
     ready: bool,
     /// Whether we're in streaming mode.
@@ -59,6 +345,22 @@ pub struct SubprocessTransport {
     initial_prompt: Option<String>,
     /// Working directory.
     cwd: Option<PathBuf>,
+    /// Whether to kill the CLI's descendants along with it.
+    kill_descendants: bool,
+    /// The CLI's process tree, when `kill_descendants` is set.
+    process_tree: Option<ProcessTree>,
+    /// Tasks reading the CLI's stdout and stderr.
+    output_readers: Vec<JoinHandle<()>>,
+    /// What to do with flags the CLI is too old to support.
+    unsupported_flags: UnsupportedFlags,
+    /// User to run the CLI as.
+    run_as: Option<RunAs>,
+    /// How much of each frame to log.
+    log_sampling: LogSampling,
+    /// Size limit and tally of the frames written.
+    frame_meter: Arc<FrameMeter>,
+    /// Whether to fail on a CLI older than the minimum version.
+    check_min_version: bool,
 }
 
 impl SubprocessTransport {
@@ -80,6 +382,15 @@ impl SubprocessTransport {
             }
         }
 
+        // Expanded here, so a missing variable fails before the CLI starts
+        let expanded;
+        let options = if options.expand_env {
+            expanded = env_vars::expand_options(options)?;
+            &expanded
+        } else {
+            options
+        };
+
         let streaming_mode = initial_prompt.is_none();
         let args = Self::build_args(options, streaming_mode, initial_prompt.as_deref())?;
         let env = Self::build_env(options);
@@ -94,13 +405,31 @@ impl SubprocessTransport {
             stdin: None,
             stdout_rx: None,
             stderr_callback: options.stderr.clone(),
+            diagnostics: None,
             ready: false,
             streaming_mode,
             initial_prompt,
             cwd: options.cwd.clone(),
+            kill_descendants: options.kill_descendants_on_drop,
+            process_tree: None,
+            output_readers: Vec::new(),
+            unsupported_flags: options.unsupported_flags,
+            run_as: options.run_as.clone(),
+            log_sampling: options.log_sampling,
+            frame_meter: Arc::new(FrameMeter::new(options.outbound_frame_limit)),
+            check_min_version: false,
         })
     }
 
+    /// The arguments a session in streaming mode starts the CLI with.
+    pub(crate) fn cli_args(options: &ClaudeAgentOptions) -> Result<Vec<String>> {
+        if options.expand_env {
+            Self::build_args(&env_vars::expand_options(options)?, true, None)
+        } else {
+            Self::build_args(options, true, None)
+        }
+    }
+
     /// Build command-line arguments from options.
     fn build_args(
         options: &ClaudeAgentOptions,
@@ -200,6 +529,10 @@ impl SubprocessTransport {
         if let Some(ref session) = options.resume {
             args.push("--resume".to_string());
             args.push(session.clone());
+            if let Some(ref uuid) = options.resume_session_at {
+                args.push("--resume-session-at".to_string());
+                args.push(uuid.clone());
+            }
         }
 
         // Fork session
@@ -241,14 +574,25 @@ impl SubprocessTransport {
                 args.push("--mcp-config".to_string());
                 args.push(path.to_string_lossy().to_string());
             }
-            McpServersConfig::Map(servers) if !servers.is_empty() => {
-                let json = serde_json::to_string(servers).map_err(|e| {
-                    ClaudeSDKError::configuration(format!("Failed to serialize MCP servers: {}", e))
-                })?;
-                args.push("--mcp-servers".to_string());
-                args.push(json);
+            McpServersConfig::Map(servers) => {
+                let enabled: HashMap<_, _> = servers
+                    .iter()
+                    .filter(|(name, _)| !options.disabled_mcp_servers.contains(name))
+                    .collect();
+                if !enabled.is_empty() {
+                    let json = serde_json::to_string(&enabled).map_err(|e| {
+                        ClaudeSDKError::configuration(format!(
+                            "Failed to serialize MCP servers: {}",
+                            e
+                        ))
+                    })?;
+                    args.push("--mcp-servers".to_string());
+                    args.push(json);
+                }
             }
-            _ => {}
+        }
+        if options.strict_mcp_config {
+            args.push("--strict-mcp-config".to_string());
         }
 
         // User
@@ -357,121 +701,259 @@ impl SubprocessTransport {
 
     /// Build environment variables.
     fn build_env(options: &ClaudeAgentOptions) -> HashMap<String, String> {
-        let mut env = std::env::vars().collect::<HashMap<_, _>>();
+        // Another user gets only the allowlisted part of ours
+        let mut env = match options.run_as {
+            Some(ref run_as) => identity::inherited_env(run_as),
+            None => std::env::vars().collect::<HashMap<_, _>>(),
+        };
 
         // Override with user-specified env vars
         for (key, value) in &options.env {
             env.insert(key.clone(), value.clone());
         }
 
+        if let Some(ref run_as) = options.run_as {
+            identity::apply_env(&mut env, run_as, &options.env);
+        }
+
+        if let Some(ref dir) = options.config_dir {
+            if !options.env.contains_key(CONFIG_DIR_ENV) {
+                env.insert(
+                    CONFIG_DIR_ENV.to_string(),
+                    dir.to_string_lossy().into_owned(),
+                );
+            }
+        }
+
         // Required SDK env vars
         env.insert("CLAUDE_SDK".to_string(), "true".to_string());
 
         env
     }
 
+    /// Build a launch error describing this transport's invocation.
+    fn launch_error(
+        &self,
+        kind: CLILaunchErrorKind,
+        message: String,
+        source: Option<std::io::Error>,
+    ) -> ClaudeSDKError {
+        ClaudeSDKError::cli_launch(CLILaunchError {
+            kind,
+            message,
+            cli_path: self.cli_path.clone(),
+            argv: redact_args(&self.args),
+            cwd: self.cwd.clone(),
+            path_env: self.env.get("PATH").cloned(),
+            source,
+        })
+    }
+
+    /// Map a failure to spawn the CLI to a launch error.
+    fn spawn_error(&self, e: std::io::Error) -> ClaudeSDKError {
+        let kind = match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                return ClaudeSDKError::cli_not_found(format!(
+                    "Failed to start Claude CLI at '{}': {}",
+                    self.cli_path.display(),
+                    e
+                ))
+            }
+            std::io::ErrorKind::PermissionDenied => CLILaunchErrorKind::PermissionDenied,
+            _ => CLILaunchErrorKind::Other,
+        };
+        if kind == CLILaunchErrorKind::PermissionDenied {
+            if let Some(message) = self.run_as.as_ref().and_then(identity::privilege_error) {
+                return self.launch_error(
+                    CLILaunchErrorKind::InsufficientPrivileges,
+                    format!("{} ({})", message, e),
+                    Some(e),
+                );
+            }
+        }
+        self.launch_error(kind, e.to_string(), Some(e))
+    }
+
+    /// Take over the SDK's ends of the CLI's standard streams and start
+    /// reading its output.
+    fn start_readers(
+        &mut self,
+        stdin: Option<impl AsyncWrite + Send + Unpin + 'static>,
+        stdout: impl AsyncRead + Send + Unpin + 'static,
+        stderr: Option<impl AsyncRead + Send + Unpin + 'static>,
+    ) {
+        if let Some(stdin) = stdin {
+            self.stdin = Some(Arc::new(Mutex::new(Box::new(stdin) as CliStdin)));
+        }
+        let (stdout_rx, stdout_task) =
+            Self::spawn_stdout_reader(stdout, self.max_buffer_size, self.log_sampling);
+        self.stdout_rx = Some(stdout_rx);
+        self.output_readers.push(stdout_task);
+        if let Some(stderr) = stderr {
+            self.output_readers.push(Self::spawn_stderr_reader(
+                stderr,
+                self.stderr_callback.clone(),
+                self.diagnostics.clone(),
+            ));
+        }
+        self.ready = true;
+    }
+
     /// Start reading stdout in background task.
     fn spawn_stdout_reader(
-        stdout: tokio::process::ChildStdout,
+        stdout: impl AsyncRead + Send + Unpin + 'static,
         max_buffer_size: usize,
-    ) -> tokio::sync::mpsc::Receiver<Result<serde_json::Value>> {
+        log_sampling: LogSampling,
+    ) -> (
+        tokio::sync::mpsc::Receiver<Result<serde_json::Value>>,
+        JoinHandle<()>,
+    ) {
         let (tx, rx) = tokio::sync::mpsc::channel(256);
 
-        tokio::spawn(async move {
-            let reader = BufReader::with_capacity(max_buffer_size, stdout);
-            let mut lines = reader.lines();
-
-            loop {
-                match lines.next_line().await {
-                    Ok(Some(line)) => {
-                        let display_len = line.len().min(200);
-                        trace!("Received line from CLI: {}", &line[..display_len]);
-
-                        let result = match serde_json::from_str(&line) {
-                            Ok(value) => Ok(value),
-                            Err(e) => Err(ClaudeSDKError::json_decode_with_context(
-                                "Failed to parse JSON from CLI",
-                                Some(line),
-                                None,
-                                e,
-                            )),
-                        };
-
-                        if tx.send(result).await.is_err() {
-                            debug!("Stdout reader: receiver dropped");
-                            break;
-                        }
-                    }
-                    Ok(None) => {
-                        debug!("Stdout reader: EOF received");
+        let task = tokio::spawn(
+            async move {
+                let mut reader =
+                    JsonLineReader::new(stdout, max_buffer_size).with_log_sampling(log_sampling);
+
+                while let Some(result) = reader.next_value().await {
+                    let failed = matches!(result, Err(ClaudeSDKError::CLIConnection { .. }));
+                    if tx.send(result).await.is_err() {
+                        debug!("Stdout reader: receiver dropped");
                         break;
                     }
-                    Err(e) => {
-                        let _ = tx
-                            .send(Err(ClaudeSDKError::cli_connection_with_source(
-                                "Failed to read from CLI stdout",
-                                e,
-                            )))
-                            .await;
+                    if failed {
                         break;
                     }
                 }
+
+                debug!("Stdout reader task finished");
             }
+            .in_current_span(),
+        );
+
+        (rx, task)
+    }
 
-            debug!("Stdout reader task finished");
-        });
+    /// Publish stderr lines, parsed as [`Diagnostic`]s, to `diagnostics`.
+    pub(crate) fn with_diagnostics(
+        mut self,
+        diagnostics: broadcast::Sender<Arc<Diagnostic>>,
+    ) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
 
-        rx
+    /// Fail to connect with
+    /// [`VersionMismatch`](ClaudeSDKError::VersionMismatch) if the CLI is
+    /// older than [`MIN_CLI_VERSION`](crate::MIN_CLI_VERSION).
+    pub(crate) fn with_min_version_check(mut self) -> Self {
+        self.check_min_version = true;
+        self
     }
 
     /// Start reading stderr in background task.
     fn spawn_stderr_reader(
-        stderr: tokio::process::ChildStderr,
+        stderr: impl AsyncRead + Send + Unpin + 'static,
         callback: Option<Arc<dyn Fn(String) + Send + Sync>>,
-    ) {
-        tokio::spawn(async move {
-            let reader = BufReader::new(stderr);
-            let mut lines = reader.lines();
-
-            loop {
-                match lines.next_line().await {
-                    Ok(Some(line)) => {
-                        trace!("CLI stderr: {}", line);
-                        if let Some(ref cb) = callback {
-                            cb(line);
+        diagnostics: Option<broadcast::Sender<Arc<Diagnostic>>>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(
+            async move {
+                let reader = BufReader::new(stderr);
+                let mut lines = reader.lines();
+
+                loop {
+                    match lines.next_line().await {
+                        Ok(Some(line)) => {
+                            trace!("CLI stderr: {}", line);
+                            // Never waits: lagging receivers lose the oldest
+                            if let Some(ref tx) = diagnostics {
+                                if tx.receiver_count() > 0 {
+                                    let _ = tx.send(Arc::new(Diagnostic::parse(&line)));
+                                }
+                            }
+                            if let Some(ref cb) = callback {
+                                cb(line);
+                            }
+                        }
+                        Ok(None) => {
+                            // EOF
+                            break;
+                        }
+                        Err(e) => {
+                            warn!("Error reading stderr: {}", e);
+                            break;
                         }
-                    }
-                    Ok(None) => {
-                        // EOF
-                        break;
-                    }
-                    Err(e) => {
-                        warn!("Error reading stderr: {}", e);
-                        break;
                     }
                 }
-            }
 
-            debug!("Stderr reader task finished");
-        });
+                debug!("Stderr reader task finished");
+            }
+            .in_current_span(),
+        )
     }
 }
 
 #[async_trait]
 impl Transport for SubprocessTransport {
     async fn connect(&mut self) -> Result<()> {
+        if self.check_min_version {
+            compat::check_min_version(&self.cli_path).await?;
+        }
+        let args = std::mem::take(&mut self.args);
+        self.args = compat::check_args(&self.cli_path, args, self.unsupported_flags).await?;
         debug!(
             "Starting CLI process: {} {:?}",
             self.cli_path.display(),
             self.args
         );
 
+        if let Some(ref cwd) = self.cwd {
+            if !cwd.is_dir() {
+                return Err(self.launch_error(
+                    CLILaunchErrorKind::WorkingDirMissing,
+                    format!("Working directory '{}' does not exist", cwd.display()),
+                    None,
+                ));
+            }
+        }
+
+        #[cfg(windows)]
+        if self.run_as.is_some() {
+            let child = identity::spawn_restricted(
+                &self.cli_path,
+                &self.args,
+                &self.env,
+                self.cwd.as_deref(),
+                self.streaming_mode,
+            )
+            .map_err(|e| self.spawn_error(e))?;
+            if self.kill_descendants {
+                self.process_tree = ProcessTree::attach_handle(child.process.raw_handle());
+            }
+            self.start_readers(child.stdin, child.stdout, Some(child.stderr));
+            self.process = Some(CliProcess::Restricted(child.process));
+            debug!("CLI process started successfully under a restricted token");
+            return Ok(());
+        }
+
         let mut cmd = Command::new(&self.cli_path);
-        cmd.args(&self.args)
-            .envs(&self.env)
+        cmd.args(&self.args);
+        if self.run_as.is_some() {
+            cmd.env_clear();
+        }
+        cmd.envs(&self.env)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .kill_on_drop(true);
+        if self.kill_descendants {
+            ProcessTree::configure(&mut cmd);
+        }
+        if let Some(ref run_as) = self.run_as {
+            identity::configure(&mut cmd, run_as).map_err(|message| {
+                self.launch_error(CLILaunchErrorKind::InsufficientPrivileges, message, None)
+            })?;
+        }
 
         // In non-streaming mode (using --print), we don't need stdin
         // Using Stdio::null() allows the CLI to complete without waiting for input
@@ -485,42 +967,28 @@ impl Transport for SubprocessTransport {
             cmd.current_dir(cwd);
         }
 
-        let mut child = cmd.spawn().map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                ClaudeSDKError::cli_not_found(format!(
-                    "Failed to start Claude CLI at '{}': {}",
-                    self.cli_path.display(),
-                    e
+        let mut child = cmd.spawn().map_err(|e| self.spawn_error(e))?;
+
+        // Only available in streaming mode
+        let stdin = match child.stdin.take() {
+            Some(stdin) => Some(stdin),
+            None if self.streaming_mode => {
+                return Err(ClaudeSDKError::cli_connection(
+                    "Failed to open stdin to CLI process",
                 ))
-            } else {
-                ClaudeSDKError::cli_connection_with_source(
-                    format!("Failed to start Claude CLI: {}", e),
-                    e,
-                )
             }
-        })?;
-
-        // Take stdin and wrap in mutex (only available in streaming mode)
-        if self.streaming_mode {
-            let stdin = child.stdin.take().ok_or_else(|| {
-                ClaudeSDKError::cli_connection("Failed to open stdin to CLI process")
-            })?;
-            self.stdin = Some(Arc::new(Mutex::new(stdin)));
-        }
-
-        // Take stdout and start reader task
+            None => None,
+        };
         let stdout = child.stdout.take().ok_or_else(|| {
             ClaudeSDKError::cli_connection("Failed to open stdout from CLI process")
         })?;
-        self.stdout_rx = Some(Self::spawn_stdout_reader(stdout, self.max_buffer_size));
+        let stderr = child.stderr.take();
 
-        // Take stderr and start reader task
-        if let Some(stderr) = child.stderr.take() {
-            Self::spawn_stderr_reader(stderr, self.stderr_callback.clone());
+        if self.kill_descendants {
+            self.process_tree = ProcessTree::attach(&child);
         }
-
-        self.process = Some(child);
-        self.ready = true;
+        self.start_readers(stdin, stdout, stderr);
+        self.process = Some(CliProcess::Tokio(child));
 
         debug!("CLI process started successfully");
         Ok(())
@@ -532,8 +1000,20 @@ impl Transport for SubprocessTransport {
             .as_ref()
             .ok_or_else(|| ClaudeSDKError::cli_connection("Transport not connected"))?;
 
+        // One frame per line: a line break inside would split it
+        let data = data.trim_end_matches(['\n', '\r']);
+        if data.contains(['\n', '\r']) {
+            return Err(ClaudeSDKError::internal(
+                "Frame written to CLI stdin contains a line break",
+            ));
+        }
+
+        self.frame_meter.admit(data.len())?;
         let mut stdin_guard = stdin.lock().await;
-        trace!("Writing to CLI: {}", &data[..data.len().min(200)]);
+        trace!(
+            "Writing to CLI: {}",
+            self.log_sampling.sample(data.as_bytes())
+        );
 
         stdin_guard.write_all(data.as_bytes()).await.map_err(|e| {
             ClaudeSDKError::cli_connection_with_source("Failed to write to CLI stdin", e)
@@ -558,37 +1038,14 @@ impl Transport for SubprocessTransport {
         // This returns an empty stream - the actual message stream is obtained
         // via take_stdout_rx() on SubprocessTransport directly.
         //
-        // Note: If custom transport support is re-added in the future, this trait
-        // method should be redesigned to use &mut self or an Arc-wrapped receiver.
+        // Clients given a SubprocessTransport recognize it through `as_any`
+        // and read its channel instead.
         warn!("message_stream() called on SubprocessTransport - use take_stdout_rx() instead");
         Box::pin(futures::stream::empty())
     }
 
     async fn close(&mut self) -> Result<()> {
-        self.ready = false;
-
-        // Close stdin first
-        if let Some(stdin) = self.stdin.take() {
-            drop(stdin);
-        }
-
-        // Wait for process to exit or kill it
-        if let Some(mut process) = self.process.take() {
-            // Give it a moment to exit gracefully
-            match tokio::time::timeout(std::time::Duration::from_secs(2), process.wait()).await {
-                Ok(Ok(status)) => {
-                    debug!("CLI process exited with status: {:?}", status);
-                }
-                Ok(Err(e)) => {
-                    error!("Error waiting for CLI process: {}", e);
-                }
-                Err(_) => {
-                    warn!("CLI process did not exit in time, killing");
-                    let _ = process.kill().await;
-                }
-            }
-        }
-
+        self.shutdown(&mut ShutdownReport::default()).await;
         Ok(())
     }
 
@@ -606,9 +1063,76 @@ impl Transport for SubprocessTransport {
     fn is_ready(&self) -> bool {
         self.ready
     }
+
+    fn as_any(&self) -> Option<&dyn std::any::Any> {
+        Some(self)
+    }
 }
 
 impl SubprocessTransport {
+    /// Stop the CLI process and join the output readers, recording each
+    /// stage in `report`.
+    ///
+    /// Stages run in order: close stdin, wait for the process to exit, kill
+    /// it if it didn't, kill its process tree, then join the stdout and
+    /// stderr readers (aborting them if they don't finish, e.g. because a
+    /// tool process still holds the pipes open).
+    pub async fn shutdown(&mut self, report: &mut ShutdownReport) {
+        self.ready = false;
+
+        // Dropping the last handle closes the pipe
+        report
+            .run_stage(ShutdownStage::CloseInput, STAGE_TIMEOUT, async {
+                if let Some(stdin) = self.stdin.take() {
+                    let mut stdin = stdin.lock().await;
+                    let _ = stdin.shutdown().await;
+                }
+            })
+            .await;
+
+        if let Some(mut process) = self.process.take() {
+            let exited = report
+                .run_stage(ShutdownStage::WaitForExit, EXIT_TIMEOUT, process.wait())
+                .await;
+            match exited {
+                Some(Ok(status)) => debug!("CLI process exited with status: {:?}", status),
+                Some(Err(e)) => error!("Error waiting for CLI process: {}", e),
+                None => {
+                    warn!("CLI process did not exit in time, killing");
+                    report.forced_kill = true;
+                    report
+                        .run_stage(ShutdownStage::KillProcess, STAGE_TIMEOUT, process.kill())
+                        .await;
+                }
+            }
+        }
+
+        // Reap anything the CLI's tools left running
+        if let Some(mut tree) = self.process_tree.take() {
+            report
+                .run_stage(ShutdownStage::KillProcessTree, STAGE_TIMEOUT, async {
+                    tree.kill()
+                })
+                .await;
+        }
+
+        let readers = std::mem::take(&mut self.output_readers);
+        if !readers.is_empty() {
+            let aborts: Vec<_> = readers.iter().map(|task| task.abort_handle()).collect();
+            let joined = report
+                .run_stage(
+                    ShutdownStage::JoinOutputReaders,
+                    STAGE_TIMEOUT,
+                    futures::future::join_all(readers),
+                )
+                .await;
+            if joined.is_none() {
+                warn!("CLI output readers did not finish in time, aborting");
+                aborts.iter().for_each(|task| task.abort());
+            }
+        }
+    }
+
     /// Get the stdout receiver for message reading.
     pub fn take_stdout_rx(
         &mut self,
@@ -616,16 +1140,249 @@ impl SubprocessTransport {
         self.stdout_rx.take()
     }
 
+    /// Write one line to the CLI's stdin from `parts`, as they arrive.
+    ///
+    /// Stdin stays locked until the line is complete, so no other frame
+    /// lands in the middle of it. Under an outbound frame limit, the line is
+    /// collected and measured before any of it is written, holding at most
+    /// the limit in memory. A part that fails after others were written
+    /// leaves the CLI with a frame it can't parse, so its input is closed
+    /// and the session ends.
+    pub(crate) async fn write_parts<S>(&self, mut parts: S) -> Result<()>
+    where
+        S: Stream<Item = Result<String>> + Unpin,
+    {
+        use tokio_stream::StreamExt;
+
+        if self.frame_meter.is_limited() {
+            let mut line = String::new();
+            while let Some(part) = parts.next().await {
+                line.push_str(&part?);
+                self.frame_meter.check(line.len())?;
+            }
+            return self.write(&line).await;
+        }
+
+        let stdin = self
+            .stdin
+            .as_ref()
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Transport not connected"))?;
+        let mut stdin_guard = stdin.lock().await;
+        let mut written = 0;
+        let mut size = 0;
+        let mut result = Ok(());
+        while let Some(part) = parts.next().await {
+            result = match part {
+                Ok(part) if part.contains(['\n', '\r']) => Err(ClaudeSDKError::internal(
+                    "Frame written to CLI stdin contains a line break",
+                )),
+                Ok(part) => {
+                    size += part.len();
+                    stdin_guard.write_all(part.as_bytes()).await.map_err(|e| {
+                        ClaudeSDKError::cli_connection_with_source(
+                            "Failed to write to CLI stdin",
+                            e,
+                        )
+                    })
+                }
+                Err(e) => Err(e),
+            };
+            if result.is_err() {
+                break;
+            }
+            written += 1;
+        }
+        trace!("Wrote {} parts of a frame to CLI", written);
+
+        if let Err(e) = result {
+            if size == 0 {
+                return Err(e);
+            }
+            warn!(
+                "Frame to CLI failed after {} bytes, closing its input",
+                size
+            );
+            let _ = stdin_guard.shutdown().await;
+            return Err(ClaudeSDKError::cli_connection(format!(
+                "Frame to CLI stdin failed after {} bytes were written, so its input was closed: {}",
+                size, e
+            )));
+        }
+        self.frame_meter.record(size);
+        stdin_guard.write_all(b"\n").await.map_err(|e| {
+            ClaudeSDKError::cli_connection_with_source("Failed to write newline to CLI stdin", e)
+        })?;
+        stdin_guard.flush().await.map_err(|e| {
+            ClaudeSDKError::cli_connection_with_source("Failed to flush CLI stdin", e)
+        })?;
+        Ok(())
+    }
+
+    /// Size limit and tally of the frames written to the CLI.
+    pub(crate) fn frame_meter(&self) -> &Arc<FrameMeter> {
+        &self.frame_meter
+    }
+
     /// Check if in streaming mode.
     pub fn is_streaming_mode(&self) -> bool {
         self.streaming_mode
     }
 }
 
+impl Drop for SubprocessTransport {
+    /// Abort the output readers and kill the CLI, if [`shutdown`](Self::shutdown)
+    /// didn't already.
+    ///
+    /// Readers would otherwise run for as long as a tool process holds the
+    /// pipes open. The killed CLI is reaped if it exits within a few
+    /// milliseconds; if not, tokio reaps it later in the background.
+    fn drop(&mut self) {
+        for task in self.output_readers.drain(..) {
+            task.abort();
+        }
+        let Some(mut process) = self.process.take() else {
+            return;
+        };
+        if let Err(e) = process.start_kill() {
+            debug!("Failed to kill dropped CLI process: {}", e);
+        }
+        for attempt in 0..DROP_REAP_ATTEMPTS {
+            // Non-blocking wait: reaps the process once it has exited
+            match process.try_wait() {
+                Ok(Some(status)) => {
+                    debug!("Dropped CLI process exited with status: {:?}", status);
+                    return;
+                }
+                Ok(None) if attempt + 1 < DROP_REAP_ATTEMPTS => {
+                    std::thread::sleep(DROP_REAP_INTERVAL);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    debug!("Failed to reap dropped CLI process: {}", e);
+                    return;
+                }
+            }
+        }
+        debug!("Dropped CLI process not reaped yet, leaving it to tokio");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_redact_args() {
+        let args: Vec<String> = [
+            "--settings",
+            "{\"apiKeyHelper\":\"x\"}",
+            "--max-thinking-tokens",
+            "1000",
+            "--system-prompt",
+            &"x".repeat(200),
+            "--print",
+            "--",
+            "secret prompt",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let redacted = redact_args(&args);
+        assert_eq!(redacted[1], "<redacted 20 bytes>");
+        assert_eq!(redacted[3], "1000");
+        assert!(redacted[5].ends_with("... (200 chars)"));
+        assert_eq!(redacted[8], "<prompt>");
+    }
+
+    #[tokio::test]
+    async fn test_json_line_reader() {
+        let data: &[u8] = b"{\"a\":1}\r\n\nnot json\n{\"b\":2}";
+        let mut reader = JsonLineReader::new(data, 0);
+
+        // The blank line is skipped; garbage after startup is still an error
+        assert_eq!(reader.next_value().await.unwrap().unwrap()["a"], 1);
+        match reader.next_value().await.unwrap() {
+            Err(ClaudeSDKError::JSONDecode { raw_data, .. }) => {
+                assert_eq!(raw_data.as_deref(), Some("not json"));
+            }
+            other => panic!("expected decode error, got {:?}", other),
+        }
+        assert_eq!(reader.next_value().await.unwrap().unwrap()["b"], 2);
+        assert!(reader.next_value().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_json_line_reader_strips_bom() {
+        let data: &[u8] = b"\xEF\xBB\xBF{\"a\":1}\n\xEF\xBB\xBF{\"b\":2}\n";
+        let mut reader = JsonLineReader::new(data, 0);
+
+        assert_eq!(reader.next_value().await.unwrap().unwrap()["a"], 1);
+        assert_eq!(reader.next_value().await.unwrap().unwrap()["b"], 2);
+        assert!(reader.next_value().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_json_line_reader_skips_whitespace_lines() {
+        let data: &[u8] = b"  \t\r\n{\"a\":1}\n   \n\r\n \t{\"b\":2}\n\n";
+        let mut reader = JsonLineReader::new(data, 0);
+
+        assert_eq!(reader.next_value().await.unwrap().unwrap()["a"], 1);
+        assert_eq!(reader.next_value().await.unwrap().unwrap()["b"], 2);
+        assert!(reader.next_value().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_json_line_reader_skips_startup_banners() {
+        let data: &[u8] =
+            b"Welcome to fish, the friendly shell\nNow using node v20.1.0\n{\"a\":1}\nlate banner\n";
+        let mut reader = JsonLineReader::new(data, 0);
+
+        assert_eq!(reader.next_value().await.unwrap().unwrap()["a"], 1);
+        assert!(matches!(
+            reader.next_value().await.unwrap(),
+            Err(ClaudeSDKError::JSONDecode { .. })
+        ));
+
+        // Garbled JSON at startup is not a banner
+        let mut reader = JsonLineReader::new(&b"{\"a\":\n"[..], 0);
+        assert!(reader.next_value().await.unwrap().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_connect_missing_interpreter_is_not_found() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cli = dir.path().join("claude");
+        std::fs::write(&cli, "#!/nonexistent/interpreter\n").unwrap();
+        std::fs::set_permissions(&cli, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut options = ClaudeAgentOptions::new();
+        options.cli_path = Some(cli.clone());
+        let mut transport = SubprocessTransport::new(&options, None).unwrap();
+
+        let err = transport.connect().await.unwrap_err();
+        assert!(matches!(err, ClaudeSDKError::CLINotFound { .. }), "{}", err);
+        assert!(err.to_string().contains(&cli.display().to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_connect_missing_cwd_is_launch_error() {
+        let mut options = ClaudeAgentOptions::new().with_cwd("/nonexistent/claude-sdk-test");
+        options.cli_path = Some(PathBuf::from("/bin/sh"));
+        let mut transport = SubprocessTransport::new(&options, None).unwrap();
+
+        let err = transport.connect().await.unwrap_err();
+        let launch = err.launch_error().expect("launch error");
+        assert_eq!(launch.kind, CLILaunchErrorKind::WorkingDirMissing);
+        assert_eq!(
+            launch.cwd,
+            Some(PathBuf::from("/nonexistent/claude-sdk-test"))
+        );
+        assert!(err.to_string().contains("--output-format"));
+    }
+
     #[test]
     fn test_build_args_basic() {
         let options = ClaudeAgentOptions::default();
@@ -646,6 +1403,34 @@ mod tests {
         assert!(args.contains(&"claude-3-sonnet".to_string()));
     }
 
+    #[test]
+    fn test_build_args_mcp_servers() {
+        let server = || {
+            McpServerConfig::Http(McpHttpServerConfig {
+                server_type: "http".to_string(),
+                url: "https://example.com/mcp".to_string(),
+                headers: HashMap::new(),
+            })
+        };
+        let options = ClaudeAgentOptions::new()
+            .with_mcp_server("github", server())
+            .with_mcp_server("linear", server())
+            .with_mcp_server_disabled("linear")
+            .with_strict_mcp_config();
+        let args = SubprocessTransport::build_args(&options, true, None).unwrap();
+
+        let at = args.iter().position(|arg| arg == "--mcp-servers").unwrap();
+        let servers: serde_json::Value = serde_json::from_str(&args[at + 1]).unwrap();
+        assert!(servers.get("github").is_some());
+        assert!(servers.get("linear").is_none());
+        assert!(args.contains(&"--strict-mcp-config".to_string()));
+
+        // Nothing left to pass
+        let options = options.with_mcp_server_disabled("github");
+        let args = SubprocessTransport::build_args(&options, true, None).unwrap();
+        assert!(!args.contains(&"--mcp-servers".to_string()));
+    }
+
     #[test]
     fn test_build_args_non_streaming() {
         let options = ClaudeAgentOptions::default();
@@ -669,6 +1454,39 @@ mod tests {
         assert_eq!(env.get("CUSTOM_VAR"), Some(&"value".to_string()));
     }
 
+    #[test]
+    fn test_build_env_run_as() {
+        let mut options = ClaudeAgentOptions::new()
+            .with_run_as(RunAs::new(65534, 65534).with_inherited_env(["PATH"]));
+        options
+            .env
+            .insert("ANTHROPIC_API_KEY".to_string(), "key".to_string());
+
+        let env = SubprocessTransport::build_env(&options);
+        let mut keys: Vec<_> = env.keys().map(String::as_str).collect();
+        keys.retain(|key| !["HOME", "USER", "LOGNAME"].contains(key));
+        keys.sort_unstable();
+        let mut expected = vec!["ANTHROPIC_API_KEY", "CLAUDE_SDK"];
+        if std::env::var_os("PATH").is_some() {
+            expected.push("PATH");
+        }
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn test_build_env_config_dir() {
+        let options = ClaudeAgentOptions::new().with_config_dir("/srv/tenants/a");
+        let env = SubprocessTransport::build_env(&options);
+        assert_eq!(env["CLAUDE_CONFIG_DIR"], "/srv/tenants/a");
+
+        let mut options = options;
+        options
+            .env
+            .insert("CLAUDE_CONFIG_DIR".to_string(), "/custom".to_string());
+        let env = SubprocessTransport::build_env(&options);
+        assert_eq!(env["CLAUDE_CONFIG_DIR"], "/custom");
+    }
+
     #[test]
     fn test_build_args_system_prompt_none() {
         // When system_prompt is None, should pass empty string to disable default
@@ -751,4 +1569,186 @@ mod tests {
             "Append text should match"
         );
     }
+
+    /// Spawn a stand-in CLI running `script`, ignoring the CLI arguments.
+    #[cfg(unix)]
+    async fn spawn_fake_cli(dir: &std::path::Path, script: &str) -> SubprocessTransport {
+        let path = crate::_internal::fake_cli::write_script(dir, "claude", script);
+
+        let mut options = ClaudeAgentOptions::new();
+        options.cli_path = Some(path);
+        let mut transport = SubprocessTransport::new(&options, None).unwrap();
+        transport.connect().await.unwrap();
+        transport
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shutdown_stages() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut transport = spawn_fake_cli(dir.path(), "cat > /dev/null").await;
+        let mut report = ShutdownReport::default();
+        transport.shutdown(&mut report).await;
+        let stages: Vec<_> = report.stages.iter().map(|s| s.stage).collect();
+        assert_eq!(
+            stages,
+            [
+                ShutdownStage::CloseInput,
+                ShutdownStage::WaitForExit,
+                ShutdownStage::JoinOutputReaders
+            ]
+        );
+        assert!(!report.forced_kill);
+        assert!(report.stages.iter().all(|s| !s.timed_out));
+
+        // Ignores EOF on stdin, so it has to be killed
+        let mut transport = spawn_fake_cli(dir.path(), "exec sleep 30").await;
+        let mut report = ShutdownReport::default();
+        transport.shutdown(&mut report).await;
+        assert!(report.forced_kill);
+        assert!(report.stage(ShutdownStage::WaitForExit).unwrap().timed_out);
+        assert!(!report.stage(ShutdownStage::KillProcess).unwrap().timed_out);
+        assert!(
+            !report
+                .stage(ShutdownStage::JoinOutputReaders)
+                .unwrap()
+                .timed_out
+        );
+        assert!(transport.output_readers.is_empty());
+    }
+
+    /// State of process `pid` in the process table (`R`, `S`, `Z`...), or
+    /// `None` once it is gone.
+    #[cfg(target_os = "linux")]
+    fn process_state(pid: u32) -> Option<char> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // The command name in parentheses may contain spaces
+        let (_, rest) = stat.rsplit_once(')')?;
+        rest.trim_start().chars().next()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_drop_reaps_cli_and_aborts_readers() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("tool.pid");
+
+        // A tool left running in the background keeps stderr open, so the
+        // stderr reader never sees EOF by itself
+        let script = format!(
+            "sleep 30 &\necho $! > {}\nexec sleep 30",
+            pid_file.display()
+        );
+        let transport = spawn_fake_cli(dir.path(), &script).await;
+        let pid = transport.process.as_ref().unwrap().id().unwrap();
+        let readers: Vec<_> = transport
+            .output_readers
+            .iter()
+            .map(|task| task.abort_handle())
+            .collect();
+        assert_eq!(readers.len(), 2);
+        while !pid_file.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(matches!(process_state(pid), Some(state) if state != 'Z'));
+
+        drop(transport);
+
+        // Gone from the process table, not left behind as a zombie
+        let mut state = process_state(pid);
+        for _ in 0..100 {
+            if state.is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            state = process_state(pid);
+        }
+        assert_eq!(state, None);
+
+        for _ in 0..100 {
+            if readers.iter().all(|task| task.is_finished()) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(readers.iter().all(|task| task.is_finished()));
+
+        let tool: libc::pid_t = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        unsafe { libc::kill(tool, libc::SIGKILL) };
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stderr_diagnostics() {
+        use crate::_internal::fake_cli::write_script;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_script(
+            dir.path(),
+            "claude",
+            "echo 'Warning: slow' >&2\necho 'plain' >&2\necho 'Error: broken' >&2",
+        );
+
+        let (tx, rx) = broadcast::channel(8);
+        let mut errors = crate::DiagnosticReceiver::new(rx, crate::DiagnosticSeverity::Warning);
+        let mut options = ClaudeAgentOptions::new();
+        options.cli_path = Some(path);
+        let mut transport = SubprocessTransport::new(&options, None)
+            .unwrap()
+            .with_diagnostics(tx);
+        transport.connect().await.unwrap();
+
+        let first = errors.recv().await.unwrap();
+        assert_eq!(first.severity, crate::DiagnosticSeverity::Warning);
+        assert_eq!(first.message, "slow");
+        assert_eq!(errors.recv().await.unwrap().message, "broken");
+        transport.close().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_as_other_user() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        let path = crate::_internal::fake_cli::write_script(
+            dir.path(),
+            "claude",
+            "echo \"{\\\"uid\\\":$(id -u),\\\"home\\\":\\\"$HOME\\\"}\"",
+        );
+
+        let mut options = ClaudeAgentOptions::new()
+            .with_run_as(RunAs::new(65534, 65534).with_home(dir.path()))
+            .with_cwd(dir.path());
+        options.cli_path = Some(path);
+        let mut transport = SubprocessTransport::new(&options, None).unwrap();
+
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        if unsafe { libc::geteuid() } != 0 {
+            let err = transport.connect().await.unwrap_err();
+            let ClaudeSDKError::CLILaunch(launch) = err else {
+                panic!("expected a launch error, got {:?}", err);
+            };
+            assert_eq!(launch.kind, CLILaunchErrorKind::InsufficientPrivileges);
+            assert!(launch.message.contains("uid 65534"), "{}", launch.message);
+            return;
+        }
+
+        transport.connect().await.unwrap();
+        let frame = transport
+            .take_stdout_rx()
+            .unwrap()
+            .recv()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame["uid"], 65534);
+        assert_eq!(frame["home"], dir.path().to_str().unwrap());
+    }
 }
@@ -0,0 +1,688 @@
+//! WebSocket transport to a CLI running elsewhere.
+//!
+//! A bridge next to the CLI (in a container, on a dev server, ...) relays
+//! each WebSocket message to the CLI's stdin and each line of its stdout
+//! back. [`WebSocketTransport`] talks to such a bridge the way
+//! [`SubprocessTransport`](super::SubprocessTransport) talks to a local
+//! CLI: frames are written one at a time, and the message stream yields
+//! each frame read, or the error reading it, until the CLI is done.
+//!
+//! The bridge closes the connection when its CLI exits, which ends the
+//! stream. A connection dropped without closing is reopened with
+//! exponential backoff: frames written meanwhile are sent once it is back,
+//! and after the last attempt fails the stream ends with a connection
+//! error, as it does when a local CLI dies. Once reopened, the `initialize`
+//! request is sent again (its response is dropped), and control requests
+//! still unanswered on the dropped connection fail with an error, as their
+//! responses may have been lost with it.
+//!
+//! The bridge starts the CLI with a command line of its own, so options
+//! passed as CLI flags (model, permission mode, tools, ...) don't reach
+//! it; only what the SDK sends over the control protocol does.
+//!
+//! `wss://` URLs connect over TLS, verified against the Mozilla root
+//! certificates. Requires the `websocket` feature.
+
+use std::collections::{HashSet, VecDeque};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::Stream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::{HeaderName, SEC_WEBSOCKET_PROTOCOL};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, trace, warn};
+
+use super::Transport;
+use crate::errors::{ClaudeSDKError, Result};
+use crate::protocol::codec::{self, FrameCodec, JsonCodec};
+use crate::types::WebSocketConfig;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Capacity of the channels between the transport and its connection task.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// How long the bridge gets to close the connection on close.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Transport to a remote CLI through a WebSocket bridge.
+///
+/// Requires the `websocket` feature. See the [module docs](self) for what
+/// the bridge is expected to do.
+pub struct WebSocketTransport {
+    config: WebSocketConfig,
+    /// Frames to send, until input ends.
+    outbound: StdMutex<Option<mpsc::Sender<String>>>,
+    /// Frames received, until the message stream takes them.
+    inbound: StdMutex<Option<mpsc::Receiver<Result<serde_json::Value>>>>,
+    /// Whether the connection is open.
+    connected: Arc<AtomicBool>,
+    /// Task running the connection.
+    task: Option<JoinHandle<()>>,
+}
+
+impl WebSocketTransport {
+    /// Create a transport to the bridge described by `config`.
+    pub fn new(config: WebSocketConfig) -> Self {
+        Self {
+            config,
+            outbound: StdMutex::new(None),
+            inbound: StdMutex::new(None),
+            connected: Arc::new(AtomicBool::new(false)),
+            task: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for WebSocketTransport {
+    async fn connect(&mut self) -> Result<()> {
+        let (socket, codec) = open(&self.config).await?;
+        let (outbound_tx, outbound_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (inbound_tx, inbound_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        *self.outbound.lock().unwrap_or_else(|e| e.into_inner()) = Some(outbound_tx);
+        *self.inbound.lock().unwrap_or_else(|e| e.into_inner()) = Some(inbound_rx);
+        self.connected.store(true, Ordering::SeqCst);
+        self.task = Some(tokio::spawn(run(
+            Connection {
+                socket,
+                codec,
+                config: self.config.clone(),
+                connected: Arc::clone(&self.connected),
+                unanswered: HashSet::new(),
+                initialize: None,
+                replayed: None,
+            },
+            outbound_rx,
+            inbound_tx,
+        )));
+        debug!("Connected to WebSocket bridge at {}", self.config.url);
+        Ok(())
+    }
+
+    async fn write(&self, data: &str) -> Result<()> {
+        // One frame per message, as the bridge writes one per line
+        let data = data.trim_end_matches(['\n', '\r']);
+        if data.contains(['\n', '\r']) {
+            return Err(ClaudeSDKError::internal(
+                "Frame written to WebSocket contains a line break",
+            ));
+        }
+        let outbound = self
+            .outbound
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Transport not connected"))?;
+        outbound
+            .send(data.to_string())
+            .await
+            .map_err(|_| ClaudeSDKError::cli_connection("WebSocket connection closed"))
+    }
+
+    fn message_stream(&self) -> Pin<Box<dyn Stream<Item = Result<serde_json::Value>> + Send + '_>> {
+        let inbound = self
+            .inbound
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        match inbound {
+            Some(rx) => Box::pin(tokio_stream::wrappers::ReceiverStream::new(rx)),
+            None => {
+                warn!("WebSocketTransport message stream already taken, or not connected");
+                Box::pin(futures::stream::empty())
+            }
+        }
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.end_input().await?;
+        if let Some(mut task) = self.task.take() {
+            if tokio::time::timeout(CLOSE_TIMEOUT, &mut task)
+                .await
+                .is_err()
+            {
+                warn!("WebSocket bridge did not close the connection in time");
+                task.abort();
+            }
+        }
+        self.connected.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Ask the bridge to close the connection, as closing stdin tells a
+    /// local CLI to finish.
+    async fn end_input(&self) -> Result<()> {
+        self.outbound
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        Ok(())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for WebSocketTransport {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// An open connection to the bridge.
+struct Connection {
+    socket: Socket,
+    /// Encoding of binary messages, as negotiated.
+    codec: Arc<dyn FrameCodec>,
+    config: WebSocketConfig,
+    connected: Arc<AtomicBool>,
+    /// IDs of the control requests sent on this connection and not
+    /// answered yet.
+    unanswered: HashSet<String>,
+    /// The `initialize` request, sent again on each new connection.
+    initialize: Option<String>,
+    /// ID of the `initialize` request sent again, whose response is
+    /// dropped.
+    replayed: Option<String>,
+}
+
+impl Connection {
+    /// Send one frame, noting the control requests among them.
+    async fn send(&mut self, line: &str) -> std::result::Result<(), String> {
+        self.send_message(line).await?;
+        if let Some((request_id, subtype)) = control_request(line) {
+            if subtype == "initialize" && self.initialize.is_none() {
+                self.initialize = Some(line.to_string());
+            }
+            self.unanswered.insert(request_id);
+        }
+        Ok(())
+    }
+
+    /// Note a frame read, returning whether to pass it on.
+    fn received(&mut self, frame: &serde_json::Value) -> bool {
+        if frame.get("type").and_then(|t| t.as_str()) != Some("control_response") {
+            return true;
+        }
+        let Some(request_id) = frame
+            .get("response")
+            .and_then(|r| r.get("request_id"))
+            .and_then(|id| id.as_str())
+        else {
+            return true;
+        };
+        self.unanswered.remove(request_id);
+        if self.replayed.as_deref() == Some(request_id) {
+            debug!("Dropping the response to the repeated initialize request");
+            self.replayed = None;
+            return false;
+        }
+        true
+    }
+
+    /// Error responses to the control requests unanswered when the
+    /// connection dropped for `reason`, and the `initialize` request to
+    /// send again on the new one.
+    fn reopened(&mut self, reason: &str) -> (Vec<serde_json::Value>, Option<String>) {
+        let replayed = self.replayed.take();
+        let lost = self
+            .unanswered
+            .drain()
+            .filter(|id| Some(id) != replayed.as_ref())
+            .map(|request_id| {
+                serde_json::json!({
+                    "type": "control_response",
+                    "response": {
+                        "subtype": "error",
+                        "request_id": request_id,
+                        "error": format!(
+                            "WebSocket connection dropped before the CLI answered: {}",
+                            reason
+                        ),
+                    },
+                })
+            })
+            .collect();
+        let initialize = self.initialize.clone();
+        self.replayed = initialize
+            .as_deref()
+            .and_then(control_request)
+            .map(|(request_id, _)| request_id);
+        (lost, initialize)
+    }
+
+    /// Send one frame as a WebSocket message.
+    async fn send_message(&mut self, line: &str) -> std::result::Result<(), String> {
+        let message = if self.codec.name() == JsonCodec.name() {
+            WsMessage::Text(line.to_string())
+        } else {
+            match self.codec.encode_line(line) {
+                Ok(data) => WsMessage::Binary(data),
+                Err(e) => {
+                    // Resending can't help
+                    warn!(
+                        "Dropping frame the {} codec can't encode: {}",
+                        self.codec.name(),
+                        e
+                    );
+                    return Ok(());
+                }
+            }
+        };
+        trace!("Writing to WebSocket: {}", line);
+        self.socket.send(message).await.map_err(|e| e.to_string())
+    }
+
+    /// Reopen the connection, waiting longer before each attempt.
+    async fn reconnect(&mut self) -> Result<()> {
+        let mut last_error = None;
+        for attempt in 1..=self.config.max_reconnects {
+            tokio::time::sleep(self.config.delay(attempt)).await;
+            match open(&self.config).await {
+                Ok((socket, codec)) => {
+                    debug!(
+                        "Reconnected to WebSocket bridge at {} (attempt {})",
+                        self.config.url, attempt
+                    );
+                    self.socket = socket;
+                    self.codec = codec;
+                    self.connected.store(true, Ordering::SeqCst);
+                    return Ok(());
+                }
+                Err(e) => {
+                    debug!("Reconnect attempt {} failed: {}", attempt, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            ClaudeSDKError::cli_connection("Reconnecting is disabled (max_reconnects is 0)")
+        }))
+    }
+}
+
+/// Open a connection to the bridge of `config`, offering its codecs.
+async fn open(config: &WebSocketConfig) -> Result<(Socket, Arc<dyn FrameCodec>)> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        ClaudeSDKError::configuration(format!(
+            "Invalid WebSocket request to {}: {}",
+            config.url, e
+        ))
+    };
+    let mut request = config
+        .url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| invalid(&e))?;
+    for (name, value) in &config.headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(&e))?;
+        let value = HeaderValue::from_str(value).map_err(|e| invalid(&e))?;
+        request.headers_mut().insert(name, value);
+    }
+    if !config.codecs.is_empty() {
+        let offered = HeaderValue::from_str(&config.codecs.join(", ")).map_err(|e| invalid(&e))?;
+        request
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_PROTOCOL, offered);
+    }
+    let (socket, response) = tokio_tungstenite::connect_async(request)
+        .await
+        .map_err(|e| {
+            ClaudeSDKError::cli_connection(format!(
+                "Failed to connect to WebSocket bridge at {}: {}",
+                config.url, e
+            ))
+        })?;
+    let codec = response
+        .headers()
+        .get(SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|name| name.to_str().ok())
+        .and_then(codec::by_name)
+        .unwrap_or_else(|| Arc::new(JsonCodec));
+    Ok((socket, codec))
+}
+
+/// The ID and subtype of a control request frame.
+fn control_request(line: &str) -> Option<(String, String)> {
+    if !line.contains("control_request") {
+        return None;
+    }
+    let frame: serde_json::Value = serde_json::from_str(line).ok()?;
+    if frame.get("type")?.as_str()? != "control_request" {
+        return None;
+    }
+    Some((
+        frame.get("request_id")?.as_str()?.to_string(),
+        frame.get("request")?.get("subtype")?.as_str()?.to_string(),
+    ))
+}
+
+/// Parse the frames in a text message: one per line.
+fn text_frames(text: &str) -> impl Iterator<Item = Result<serde_json::Value>> + '_ {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|e| {
+                ClaudeSDKError::json_decode_with_context(
+                    "Failed to parse JSON from WebSocket",
+                    Some(line.to_string()),
+                    None,
+                    e,
+                )
+            })
+        })
+}
+
+/// Run the connection: send the frames written, forward the frames read,
+/// and reconnect when it drops.
+async fn run(
+    mut connection: Connection,
+    mut outbound: mpsc::Receiver<String>,
+    inbound: mpsc::Sender<Result<serde_json::Value>>,
+) {
+    let mut unsent: VecDeque<String> = VecDeque::new();
+    let mut input_open = true;
+    loop {
+        // Why the connection dropped, or None if it closed
+        let dropped = 'connection: loop {
+            while let Some(line) = unsent.pop_front() {
+                if let Err(e) = connection.send(&line).await {
+                    unsent.push_front(line);
+                    break 'connection Some(e);
+                }
+            }
+            tokio::select! {
+                line = outbound.recv(), if input_open => match line {
+                    Some(line) => unsent.push_back(line),
+                    None => {
+                        debug!("WebSocket input ended, closing");
+                        input_open = false;
+                        if let Err(e) = connection.socket.close(None).await {
+                            break Some(e.to_string());
+                        }
+                    }
+                },
+                message = connection.socket.next() => {
+                    let frames: Vec<_> = match message {
+                        Some(Ok(WsMessage::Text(text))) => text_frames(&text).collect(),
+                        Some(Ok(WsMessage::Binary(data))) => vec![connection.codec.decode(&data)],
+                        Some(Ok(WsMessage::Close(_))) | None => break None,
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => break Some(e.to_string()),
+                    };
+                    for frame in frames {
+                        if matches!(frame, Ok(ref frame) if !connection.received(frame)) {
+                            continue;
+                        }
+                        if inbound.send(frame).await.is_err() {
+                            debug!("WebSocket reader: receiver dropped");
+                            break 'connection None;
+                        }
+                    }
+                }
+            }
+        };
+        connection.connected.store(false, Ordering::SeqCst);
+        let Some(reason) = dropped.filter(|_| input_open) else {
+            break;
+        };
+        warn!(
+            "WebSocket connection to {} dropped, reconnecting: {}",
+            connection.config.url, reason
+        );
+        if let Err(e) = connection.reconnect().await {
+            let error = ClaudeSDKError::cli_connection(format!(
+                "WebSocket connection to {} lost: {} ({})",
+                connection.config.url, reason, e
+            ));
+            let _ = inbound.send(Err(error)).await;
+            break;
+        }
+        let (lost, initialize) = connection.reopened(&reason);
+        for response in lost {
+            if inbound.send(Ok(response)).await.is_err() {
+                return;
+            }
+        }
+        if let Some(line) = initialize {
+            unsent.push_front(line);
+        }
+    }
+    debug!("WebSocket connection task finished");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    async fn listen() -> (TcpListener, WebSocketConfig) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let config = WebSocketConfig::new(url)
+            .with_delays(Duration::from_millis(10), Duration::from_millis(20));
+        (listener, config)
+    }
+
+    async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+        let (tcp, _) = listener.accept().await.unwrap();
+        tokio_tungstenite::accept_async(tcp).await.unwrap()
+    }
+
+    async fn next_frame(socket: &mut WebSocketStream<TcpStream>) -> Value {
+        match socket.next().await {
+            Some(Ok(WsMessage::Text(text))) => serde_json::from_str(&text).unwrap(),
+            other => panic!("expected a text message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_and_forwards_frames() {
+        let (listener, config) = listen().await;
+        let bridge = tokio::spawn(async move {
+            let mut socket = accept(&listener).await;
+            assert_eq!(next_frame(&mut socket).await["n"], 1);
+            // Two frames in one message, then drop without closing
+            socket
+                .send(WsMessage::Text("{\"n\":1}\n{\"n\":2}\n".to_string()))
+                .await
+                .unwrap();
+            drop(socket);
+
+            let mut socket = accept(&listener).await;
+            assert_eq!(next_frame(&mut socket).await["n"], 3);
+            socket
+                .send(WsMessage::Text("{\"n\":3}".to_string()))
+                .await
+                .unwrap();
+            socket.close(None).await.unwrap();
+        });
+
+        let mut transport = WebSocketTransport::new(config);
+        transport.connect().await.unwrap();
+        assert!(transport.is_ready());
+        transport.write("{\"n\":1}\n").await.unwrap();
+        let mut stream = transport.message_stream();
+        assert_eq!(stream.next().await.unwrap().unwrap(), json!({"n": 1}));
+        assert_eq!(stream.next().await.unwrap().unwrap(), json!({"n": 2}));
+        // Sent once the connection is back
+        transport.write("{\"n\":3}").await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), json!({"n": 3}));
+        // Closing ends the stream
+        assert!(stream.next().await.is_none());
+        drop(stream);
+        assert!(transport.message_stream().next().await.is_none());
+
+        bridge.await.unwrap();
+        transport.close().await.unwrap();
+        assert!(!transport.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_repeats_initialize_and_fails_lost_requests() {
+        let initialize = json!({"type": "control_request", "request_id": "init", "request": {"subtype": "initialize"}});
+        let interrupt = json!({"type": "control_request", "request_id": "int", "request": {"subtype": "interrupt"}});
+        let answer = |request: &Value| json!({"type": "control_response", "response": {"subtype": "success", "request_id": request["request_id"], "response": {}}});
+
+        let (listener, config) = listen().await;
+        let bridge = tokio::spawn(async move {
+            let mut socket = accept(&listener).await;
+            let request = next_frame(&mut socket).await;
+            assert_eq!(request["request_id"], "init");
+            socket
+                .send(WsMessage::Text(answer(&request).to_string()))
+                .await
+                .unwrap();
+            // Dropped before answering the interrupt
+            assert_eq!(next_frame(&mut socket).await["request_id"], "int");
+            drop(socket);
+
+            let mut socket = accept(&listener).await;
+            let request = next_frame(&mut socket).await;
+            assert_eq!(request["request_id"], "init");
+            socket
+                .send(WsMessage::Text(answer(&request).to_string()))
+                .await
+                .unwrap();
+            assert_eq!(next_frame(&mut socket).await["n"], 1);
+            socket
+                .send(WsMessage::Text("{\"n\":1}".to_string()))
+                .await
+                .unwrap();
+            socket.close(None).await.unwrap();
+        });
+
+        let mut transport = WebSocketTransport::new(config);
+        transport.connect().await.unwrap();
+        let mut stream = transport.message_stream();
+        transport.write(&initialize.to_string()).await.unwrap();
+        let response = stream.next().await.unwrap().unwrap();
+        assert_eq!(response["response"]["request_id"], "init");
+        transport.write(&interrupt.to_string()).await.unwrap();
+
+        let lost = stream.next().await.unwrap().unwrap();
+        assert_eq!(lost["response"]["subtype"], "error");
+        assert_eq!(lost["response"]["request_id"], "int");
+        transport.write("{\"n\":1}").await.unwrap();
+        // The response to the repeated initialize isn't passed on
+        assert_eq!(stream.next().await.unwrap().unwrap(), json!({"n": 1}));
+        assert!(stream.next().await.is_none());
+        drop(stream);
+        bridge.await.unwrap();
+        transport.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_reconnects() {
+        let (listener, config) = listen().await;
+        let mut transport = WebSocketTransport::new(config.with_reconnects(2));
+        let connect = transport.connect();
+        let (connected, socket) = tokio::join!(connect, accept(&listener));
+        connected.unwrap();
+        drop(socket);
+        drop(listener);
+
+        let mut stream = transport.message_stream();
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(
+            matches!(err, ClaudeSDKError::CLIConnection { .. }),
+            "{}",
+            err
+        );
+        assert!(err.to_string().contains("lost"), "{}", err);
+        assert!(stream.next().await.is_none());
+        assert!(transport.write("{}").await.is_err());
+
+        assert!(
+            WebSocketTransport::new(WebSocketConfig::new("ws://127.0.0.1:1"))
+                .connect()
+                .await
+                .unwrap_err()
+                .is_recoverable()
+        );
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[tokio::test]
+    async fn test_client_fails_over_to_bridge() {
+        use crate::diagnostics::DiagnosticSeverity;
+        use crate::types::{ClaudeAgentOptions, FallbackTransport};
+        use crate::ClaudeClient;
+
+        let (listener, config) = listen().await;
+        // Answers control requests, and each prompt with its text
+        let bridge = tokio::spawn(async move {
+            let mut socket = accept(&listener).await;
+            while let Some(Ok(WsMessage::Text(text))) = socket.next().await {
+                let frame: Value = serde_json::from_str(&text).unwrap();
+                let replies = match frame["type"].as_str() {
+                    Some("control_request") => vec![json!({
+                        "type": "control_response",
+                        "response": {"subtype": "success", "request_id": frame["request_id"], "response": {}}
+                    })],
+                    Some("user") => vec![
+                        json!({"type": "assistant", "message": {"content": [{"type": "text", "text": frame["message"]["content"]}], "model": "remote"}}),
+                        json!({"type": "result", "subtype": "success", "duration_ms": 1, "duration_api_ms": 1, "is_error": false, "num_turns": 1, "session_id": "s"}),
+                    ],
+                    _ => vec![],
+                };
+                for reply in replies {
+                    socket
+                        .send(WsMessage::Text(reply.to_string()))
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        let mut options =
+            ClaudeAgentOptions::new().with_fallback_transport(FallbackTransport::WebSocket(config));
+        options.cli_path = Some("/nonexistent/claude".into());
+        let mut client = ClaudeClient::new(Some(options));
+        let mut diagnostics = client.diagnostics(DiagnosticSeverity::Warning);
+        client.connect().await.unwrap();
+        let failover = diagnostics.recv().await.unwrap();
+        assert!(
+            failover.message.contains("WebSocket bridge at ws://"),
+            "{}",
+            failover.message
+        );
+
+        client.query("Hi there").await.unwrap();
+        let (text, result) = client.receive_response().await.unwrap();
+        assert_eq!(text, "Hi there");
+        assert!(!result.is_error);
+        client.disconnect().await.unwrap();
+        bridge.await.unwrap();
+
+        // Options the bridge can't apply rule it out
+        let mut options = ClaudeAgentOptions::new()
+            .with_fallback_transport(FallbackTransport::WebSocket(WebSocketConfig::new(
+                "ws://127.0.0.1:1",
+            )))
+            .with_allowed_tools(vec!["Read".to_string()]);
+        options.cli_path = Some("/nonexistent/claude".into());
+        let err = ClaudeClient::new(Some(options))
+            .connect()
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("can't apply allowed_tools"), "{}", err);
+    }
+}
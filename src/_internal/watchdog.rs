@@ -0,0 +1,305 @@
+//! Enforcement of turn budgets and message timeouts.
+//!
+//! The CLI reports usage during a turn: each assistant message carries the
+//! usage of the API call it came from, and so do the `message_start` and
+//! `message_delta` stream events. A call's usage is repeated on every
+//! message of the call and grows as it streams, so the watchdog keeps the
+//! largest figures seen per call and adds the calls up.
+//!
+//! The [`StreamTimer`] times the frames of a turn against the
+//! first-message and idle [`Timeouts`]. It stops while the CLI waits on the
+//! SDK, for an answer to a control request or for the reader to resume.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::protocol::wire;
+use crate::types::{Timeouts, TurnBudget, TurnUsage};
+
+/// Result subtype of a turn stopped for going over budget, as the CLI
+/// reports one stopped by `max_budget_usd`.
+const BUDGET_EXCEEDED_SUBTYPE: &str = "error_max_budget_usd";
+
+/// Tracks the usage of the current turn against a [`TurnBudget`].
+pub(crate) struct TurnWatchdog {
+    budget: TurnBudget,
+    /// Usage of each API call of the turn, by message ID.
+    calls: HashMap<String, TurnUsage>,
+    /// ID of the message being streamed, which `message_delta` events
+    /// belong to.
+    streaming: Option<String>,
+    /// Calls without a message ID, each counted on its own.
+    anonymous: u64,
+    /// Whether the turn went over budget.
+    tripped: bool,
+}
+
+impl TurnWatchdog {
+    pub(crate) fn new(budget: TurnBudget) -> Self {
+        Self {
+            budget,
+            calls: HashMap::new(),
+            streaming: None,
+            anonymous: 0,
+            tripped: false,
+        }
+    }
+
+    /// Usage of the turn so far.
+    pub(crate) fn usage(&self) -> TurnUsage {
+        self.calls
+            .values()
+            .fold(TurnUsage::default(), |sum, call| TurnUsage {
+                input_tokens: sum.input_tokens + call.input_tokens,
+                output_tokens: sum.output_tokens + call.output_tokens,
+            })
+    }
+
+    /// Account for a frame from the CLI, returning `true` when it takes the
+    /// turn over budget.
+    ///
+    /// A result ends the turn: if the turn went over budget, the result is
+    /// marked as ended by the budget, and counting starts over.
+    pub(crate) fn observe(&mut self, frame: &mut Value) -> bool {
+        match frame.get("type").and_then(Value::as_str) {
+            Some(wire::ASSISTANT) => {
+                let message = &frame["message"];
+                let id = message.get("id").and_then(Value::as_str).map(String::from);
+                self.record(id, &message["usage"]);
+            }
+            Some(wire::STREAM_EVENT) => {
+                let event = &frame["event"];
+                match event.get("type").and_then(Value::as_str) {
+                    Some("message_start") => {
+                        let message = &event["message"];
+                        let id = message.get("id").and_then(Value::as_str).map(String::from);
+                        self.streaming = id.clone();
+                        self.record(id, &message["usage"]);
+                    }
+                    Some("message_delta") => self.record(self.streaming.clone(), &event["usage"]),
+                    _ => {}
+                }
+            }
+            Some(wire::RESULT) => {
+                if self.tripped {
+                    frame["subtype"] = Value::from(BUDGET_EXCEEDED_SUBTYPE);
+                    frame["is_error"] = Value::Bool(true);
+                }
+                *self = Self::new(self.budget);
+                return false;
+            }
+            _ => return false,
+        }
+
+        if self.tripped || !self.budget.is_exceeded_by(&self.usage()) {
+            return false;
+        }
+        self.tripped = true;
+        true
+    }
+
+    /// Record the usage reported for the call `id`.
+    fn record(&mut self, id: Option<String>, usage: &Value) {
+        let tokens = |field: &str| usage.get(field).and_then(Value::as_u64).unwrap_or(0);
+        let reported = TurnUsage {
+            input_tokens: tokens("input_tokens")
+                + tokens("cache_creation_input_tokens")
+                + tokens("cache_read_input_tokens"),
+            output_tokens: tokens("output_tokens"),
+        };
+        if reported == TurnUsage::default() {
+            return;
+        }
+        let id = id.unwrap_or_else(|| {
+            self.anonymous += 1;
+            format!("#{}", self.anonymous)
+        });
+        let call = self.calls.entry(id).or_default();
+        call.input_tokens = call.input_tokens.max(reported.input_tokens);
+        call.output_tokens = call.output_tokens.max(reported.output_tokens);
+    }
+}
+
+/// Times the frames of a turn, from the prompt to its result.
+pub(crate) struct StreamTimer {
+    first_message: Option<Duration>,
+    idle: Option<Duration>,
+    /// When the frame being waited for is due, and the timeout that set it.
+    due: Option<(Instant, Duration)>,
+    /// Whether a turn is running.
+    in_turn: bool,
+    /// Whether the CLI is waiting on the SDK, which stops the timer.
+    held: bool,
+}
+
+impl StreamTimer {
+    pub(crate) fn new(timeouts: &Timeouts) -> Self {
+        Self {
+            first_message: timeouts.first_message,
+            idle: timeouts.idle,
+            due: None,
+            in_turn: false,
+            held: false,
+        }
+    }
+
+    /// A prompt was sent at `at`: its first frame is due within the
+    /// first-message timeout, or the idle one if that isn't set.
+    pub(crate) fn prompt_sent(&mut self, at: Instant) {
+        self.in_turn = true;
+        self.due = self
+            .first_message
+            .or(self.idle)
+            .map(|timeout| (at + timeout, timeout));
+    }
+
+    /// A frame arrived, which ends the turn if it's a result.
+    pub(crate) fn frame_received(&mut self, ends_turn: bool) {
+        if !self.in_turn {
+            return;
+        }
+        if ends_turn {
+            self.in_turn = false;
+            self.due = None;
+        } else {
+            self.due = self.idle.map(|timeout| (Instant::now() + timeout, timeout));
+        }
+    }
+
+    /// Stop timing while `held`; once no longer held, the frame waited for
+    /// is given its full timeout again.
+    pub(crate) fn hold(&mut self, held: bool) {
+        if held == self.held {
+            return;
+        }
+        self.held = held;
+        if !held {
+            if let Some((ref mut at, timeout)) = self.due {
+                *at = Instant::now() + timeout;
+            }
+        }
+    }
+
+    /// When the next frame is due, if one is.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.due.filter(|_| !self.held).map(|(at, _)| at)
+    }
+
+    /// The timeout that expired, no longer timing the turn.
+    pub(crate) fn expire(&mut self) -> Duration {
+        self.in_turn = false;
+        self.due
+            .take()
+            .map_or(Duration::ZERO, |(_, timeout)| timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn assistant(id: &str, input: u64, output: u64) -> Value {
+        json!({
+            "type": "assistant",
+            "message": {
+                "id": id,
+                "content": [],
+                "model": "m",
+                "usage": {"input_tokens": input, "cache_read_input_tokens": 100, "output_tokens": output}
+            }
+        })
+    }
+
+    #[test]
+    fn test_usage_is_counted_once_per_call() {
+        let mut watchdog = TurnWatchdog::new(TurnBudget::tokens(1_000));
+        let mut frames = [
+            json!({"type": "stream_event", "event": {"type": "message_start", "message": {"id": "msg_1", "usage": {"input_tokens": 10, "output_tokens": 1}}}}),
+            json!({"type": "stream_event", "event": {"type": "message_delta", "usage": {"output_tokens": 40}}}),
+            // Repeated on each message of the call
+            assistant("msg_1", 10, 40),
+            assistant("msg_1", 10, 40),
+            assistant("msg_2", 50, 20),
+        ];
+        for frame in frames.iter_mut() {
+            assert!(!watchdog.observe(frame));
+        }
+        assert_eq!(
+            watchdog.usage(),
+            TurnUsage {
+                input_tokens: 110 + 150,
+                output_tokens: 60
+            }
+        );
+    }
+
+    #[test]
+    fn test_trips_once_and_marks_the_result() {
+        let budget = TurnBudget::usd(0.01, 3.0, 15.0);
+        let mut watchdog = TurnWatchdog::new(budget);
+        // 1100 input and 200 output tokens: $0.0063
+        assert!(!watchdog.observe(&mut assistant("msg_1", 1_000, 200)));
+        // Another $0.0063
+        assert!(watchdog.observe(&mut assistant("msg_2", 1_000, 200)));
+        assert!(!watchdog.observe(&mut assistant("msg_3", 1_000, 200)));
+
+        let mut result =
+            json!({"type": "result", "subtype": "error_during_execution", "is_error": false});
+        watchdog.observe(&mut result);
+        assert_eq!(result["subtype"], BUDGET_EXCEEDED_SUBTYPE);
+        assert_eq!(result["is_error"], true);
+
+        // The next turn starts from nothing
+        assert_eq!(watchdog.usage(), TurnUsage::default());
+        let mut result = json!({"type": "result", "subtype": "success", "is_error": false});
+        watchdog.observe(&mut result);
+        assert_eq!(result["subtype"], "success");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stream_timer() {
+        let mut timer = StreamTimer::new(&Timeouts {
+            first_message: Some(Duration::from_secs(10)),
+            idle: Some(Duration::from_secs(60)),
+            ..Timeouts::none()
+        });
+        // Frames between turns aren't timed
+        timer.frame_received(false);
+        assert_eq!(timer.deadline(), None);
+
+        let sent = Instant::now();
+        timer.prompt_sent(sent);
+        assert_eq!(timer.deadline(), Some(sent + Duration::from_secs(10)));
+        tokio::time::advance(Duration::from_secs(5)).await;
+        timer.frame_received(false);
+        assert_eq!(timer.deadline(), Some(sent + Duration::from_secs(65)));
+
+        // Not timed while the CLI waits on the SDK
+        timer.hold(true);
+        assert_eq!(timer.deadline(), None);
+        tokio::time::advance(Duration::from_secs(120)).await;
+        timer.frame_received(false);
+        assert_eq!(timer.deadline(), None);
+        timer.hold(false);
+        assert_eq!(
+            timer.deadline(),
+            Some(Instant::now() + Duration::from_secs(60))
+        );
+
+        timer.frame_received(true);
+        assert_eq!(timer.deadline(), None);
+
+        // The idle timeout also bounds the first frame, if it's the only one
+        let mut timer = StreamTimer::new(&Timeouts {
+            idle: Some(Duration::from_secs(60)),
+            ..Timeouts::none()
+        });
+        timer.prompt_sent(sent);
+        assert_eq!(timer.expire(), Duration::from_secs(60));
+        assert_eq!(timer.deadline(), None);
+    }
+}
@@ -0,0 +1,427 @@
+//! High-level agents that run tasks end to end.
+//!
+//! An [`Agent`] bundles what defines a role — system prompt, tool policy,
+//! hooks, output schema — and runs tasks with [`Agent::run`], which
+//! connects a [`ClaudeClient`], sends the task, collects the response into
+//! an [`AgentRunReport`] and shuts the client down again. Transient
+//! failures (connection errors, timeouts) are retried.
+//!
+//! Every run gets a fresh CLI session, so tasks don't see each other's
+//! context. Agents sharing a [`SessionLimit`] share its bound on concurrent
+//! sessions.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::{Agent, SessionLimit, PermissionPolicy};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Triage {
+//!     severity: String,
+//!     component: String,
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let limit = SessionLimit::new(4);
+//!     let triager = Agent::new("triager")
+//!         .system_prompt("You triage bug reports for this repository.")
+//!         .allowed_tools(["Read", "Grep", "Glob"])
+//!         .permission_policy(PermissionPolicy::no_network())
+//!         .output_schema(serde_json::json!({
+//!             "type": "object",
+//!             "properties": {
+//!                 "severity": {"type": "string"},
+//!                 "component": {"type": "string"}
+//!             },
+//!             "required": ["severity", "component"]
+//!         }))
+//!         .session_limit(limit.clone());
+//!
+//!     let report = triager.run("Crash when the config file is empty").await?;
+//!     let triage: Triage = report.output()?;
+//!     println!("{} in {} (attempts: {})", triage.severity, triage.component, report.attempts);
+//!     Ok(())
+//! }
+//! ```
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+use crate::_internal::backoff;
+use crate::client::ClaudeClient;
+use crate::errors::{ClaudeSDKError, Result};
+use crate::hooks::HookRegistry;
+use crate::policy::PermissionPolicy;
+use crate::presets::parse_output;
+use crate::types::{ClaudeAgentOptions, Message, PermissionMode, ResultMessage};
+
+/// A limit on concurrent CLI sessions, shared by the agents using it.
+///
+/// Sessions aren't reused: each connect starts a fresh CLI session once a
+/// slot is free. Cloning the limit shares it.
+#[derive(Debug, Clone)]
+pub struct SessionLimit {
+    permits: Arc<Semaphore>,
+    capacity: usize,
+}
+
+/// A connected client holding one of its limit's slots until dropped.
+pub struct LimitedClient {
+    client: ClaudeClient,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl std::ops::Deref for LimitedClient {
+    type Target = ClaudeClient;
+
+    fn deref(&self) -> &ClaudeClient {
+        &self.client
+    }
+}
+
+impl std::ops::DerefMut for LimitedClient {
+    fn deref_mut(&mut self) -> &mut ClaudeClient {
+        &mut self.client
+    }
+}
+
+impl SessionLimit {
+    /// Create a limit allowing `max_sessions` concurrent sessions (at least 1).
+    pub fn new(max_sessions: usize) -> Self {
+        let capacity = max_sessions.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(capacity)),
+            capacity,
+        }
+    }
+
+    /// Get the maximum number of concurrent sessions.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the number of sessions currently running.
+    pub fn in_use(&self) -> usize {
+        self.capacity - self.permits.available_permits()
+    }
+
+    /// Wait for a free slot, then connect a client with `options`.
+    pub async fn connect(&self, options: ClaudeAgentOptions) -> Result<LimitedClient> {
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| ClaudeSDKError::internal("Session limit closed"))?;
+        let mut client = ClaudeClient::new(Some(options));
+        client.connect().await?;
+        Ok(LimitedClient {
+            client,
+            _permit: permit,
+        })
+    }
+}
+
+/// What happened when an [`Agent`] ran a task.
+#[derive(Debug, Clone)]
+pub struct AgentRunReport {
+    /// Name of the agent that ran the task.
+    pub agent: String,
+    /// Concatenated assistant text of the response.
+    pub text: String,
+    /// The final result message.
+    pub result: ResultMessage,
+    /// Every message of the response, including the result.
+    pub messages: Vec<Message>,
+    /// Number of attempts, including the successful one.
+    pub attempts: u32,
+    /// Time spent on the run, including failed attempts and retry delays.
+    pub duration: Duration,
+}
+
+impl AgentRunReport {
+    /// Check if the CLI reported the task as failed.
+    pub fn is_error(&self) -> bool {
+        self.result.is_error
+    }
+
+    /// Get the cost of the successful attempt in USD, if reported.
+    pub fn cost_usd(&self) -> Option<f64> {
+        self.result.total_cost_usd
+    }
+
+    /// Parse the structured output requested with
+    /// [`Agent::output_schema`].
+    ///
+    /// Falls back to parsing the response text as JSON (optionally inside
+    /// a code fence) when the CLI returned no structured output.
+    pub fn output<T: DeserializeOwned>(&self) -> Result<T> {
+        parse_output(
+            self.result.structured_output.as_ref(),
+            Some(self.result.result.as_deref().unwrap_or(&self.text)),
+            &format!("output from agent '{}'", self.agent),
+        )
+    }
+}
+
+/// A named role that runs tasks with its own prompt, tools and hooks.
+#[derive(Clone, Debug)]
+pub struct Agent {
+    name: String,
+    options: ClaudeAgentOptions,
+    max_attempts: u32,
+    retry_delay: Duration,
+    limit: Option<SessionLimit>,
+}
+
+impl Agent {
+    /// Create an agent with default options, retrying failed runs twice.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            options: ClaudeAgentOptions::new(),
+            max_attempts: 3,
+            retry_delay: Duration::from_secs(1),
+            limit: None,
+        }
+    }
+
+    /// Get the agent's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the options each run starts from.
+    pub fn agent_options(&self) -> &ClaudeAgentOptions {
+        &self.options
+    }
+
+    /// Replace the options each run starts from. Settings made with the
+    /// other methods before this call are lost.
+    pub fn options(mut self, options: ClaudeAgentOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set the system prompt.
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.options = self.options.with_system_prompt(prompt);
+        self
+    }
+
+    /// Set the model.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.options.model = Some(model.into());
+        self
+    }
+
+    /// Set the permission mode.
+    pub fn permission_mode(mut self, mode: PermissionMode) -> Self {
+        self.options.permission_mode = Some(mode);
+        self
+    }
+
+    /// Set the tools allowed without asking for permission.
+    pub fn allowed_tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.allowed_tools = tools.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Enforce a [`PermissionPolicy`].
+    pub fn permission_policy(mut self, policy: PermissionPolicy) -> Self {
+        self.options = self.options.with_permission_policy(policy);
+        self
+    }
+
+    /// Add hooks, keeping any already configured.
+    pub fn hooks(mut self, hooks: HookRegistry) -> Self {
+        self.options.add_hooks(hooks);
+        self
+    }
+
+    /// Set the working directory.
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.options.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Set the maximum number of turns per run.
+    pub fn max_turns(mut self, turns: u32) -> Self {
+        self.options.max_turns = Some(turns);
+        self
+    }
+
+    /// Request structured output matching a JSON Schema, available via
+    /// [`AgentRunReport::output`].
+    pub fn output_schema(mut self, schema: serde_json::Value) -> Self {
+        self.options.output_format = Some(schema);
+        self
+    }
+
+    /// Set how many times a run is attempted (at least 1).
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry; it doubles with each retry.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Run sessions under `limit`, sharing it with other agents.
+    pub fn session_limit(mut self, limit: SessionLimit) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Run `task` in a fresh session and report the outcome.
+    ///
+    /// Attempts that fail with a [recoverable](ClaudeSDKError::is_recoverable)
+    /// error are retried with exponential backoff, up to
+    /// [`max_attempts`](Self::max_attempts). A task the CLI reports as
+    /// failed is not retried; check [`AgentRunReport::is_error`].
+    pub async fn run(&self, task: &str) -> Result<AgentRunReport> {
+        self.options.validate()?;
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            match self.attempt(task).await {
+                Ok((text, result, messages)) => {
+                    return Ok(AgentRunReport {
+                        agent: self.name.clone(),
+                        text,
+                        result,
+                        messages,
+                        attempts: attempt,
+                        duration: started.elapsed(),
+                    });
+                }
+                Err(e) if e.is_recoverable() && attempt < self.max_attempts => {
+                    let delay = backoff::exponential(self.retry_delay, Duration::MAX, attempt);
+                    warn!(
+                        agent = %self.name,
+                        attempt,
+                        error = %e,
+                        "Agent run failed, retrying in {:?}",
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Run `task` once, shutting the session down afterwards.
+    async fn attempt(&self, task: &str) -> Result<(String, ResultMessage, Vec<Message>)> {
+        let mut client = match self.limit {
+            Some(ref limit) => limit.connect(self.options.clone()).await?,
+            None => SessionLimit::new(1).connect(self.options.clone()).await?,
+        };
+        let outcome = Self::exchange(&mut client, task).await;
+        client.shutdown().await;
+        outcome
+    }
+
+    /// Send `task` and collect the response.
+    async fn exchange(
+        client: &mut ClaudeClient,
+        task: &str,
+    ) -> Result<(String, ResultMessage, Vec<Message>)> {
+        client.query(task).await?;
+
+        let mut text = String::new();
+        let mut messages = Vec::new();
+        let mut stream = Box::pin(client.receive_response_messages());
+        while let Some(message) = stream.next().await {
+            let message = message?;
+            if let Message::Assistant(ref assistant) = message {
+                text.push_str(&assistant.text());
+            }
+            if let Message::Result(ref result) = message {
+                let result = result.clone();
+                messages.push(message);
+                return Ok((text, result, messages));
+            }
+            messages.push(message);
+        }
+        Err(ClaudeSDKError::cli_connection(
+            "CLI stopped before the agent's task finished",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_composes_options() {
+        let agent = Agent::new("reviewer")
+            .system_prompt("Review code.")
+            .allowed_tools(["Read", "WebFetch"])
+            .permission_policy(PermissionPolicy::no_network())
+            .output_schema(serde_json::json!({"type": "object"}))
+            .max_attempts(0);
+
+        let options = agent.agent_options();
+        assert_eq!(agent.name(), "reviewer");
+        assert_eq!(options.allowed_tools, vec!["Read"]);
+        assert_eq!(options.permission_policies.len(), 1);
+        assert!(options.output_format.is_some());
+        assert_eq!(agent.max_attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_limit() {
+        let limit = SessionLimit::new(2);
+        assert_eq!(limit.capacity(), 2);
+        assert_eq!(limit.in_use(), 0);
+
+        // A failing connect releases its slot
+        let mut options = ClaudeAgentOptions::new();
+        options.cli_path = Some(PathBuf::from("/nonexistent/claude"));
+        assert!(limit.connect(options).await.is_err());
+        assert_eq!(limit.in_use(), 0);
+    }
+
+    #[test]
+    fn test_report_output() {
+        let result: ResultMessage = serde_json::from_value(serde_json::json!({
+            "subtype": "success",
+            "duration_ms": 0,
+            "duration_api_ms": 0,
+            "is_error": false,
+            "num_turns": 1,
+            "session_id": "s",
+            "total_cost_usd": 0.01
+        }))
+        .unwrap();
+        let report = AgentRunReport {
+            agent: "a".to_string(),
+            text: "```json\n{\"ok\": true}\n```".to_string(),
+            result,
+            messages: Vec::new(),
+            attempts: 1,
+            duration: Duration::ZERO,
+        };
+        let output: serde_json::Value = report.output().unwrap();
+        assert_eq!(output["ok"], true);
+        assert_eq!(report.cost_usd(), Some(0.01));
+        assert!(!report.is_error());
+    }
+}
@@ -0,0 +1,179 @@
+//! Blocking (synchronous) API.
+//!
+//! A thin facade over the async API for CLI tools, build scripts and other
+//! programs that don't run an async runtime. Each function drives its own
+//! internal Tokio runtime, so none of them may be called from within an
+//! async context.
+//!
+//! Requires the `blocking` feature.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::blocking;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let answer = blocking::ask("What is 2 + 2?", None)?;
+//!     println!("{}", answer);
+//!     Ok(())
+//! }
+//! ```
+
+use tokio::runtime::Runtime;
+use tokio_stream::StreamExt;
+
+use crate::client::ClaudeClient;
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{ClaudeAgentOptions, Message, PermissionMode, ResultMessage};
+
+fn runtime() -> Result<Runtime> {
+    // The CLI reader tasks must keep running between calls, so use a worker
+    // thread rather than a current-thread runtime.
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .map_err(|e| ClaudeSDKError::internal(format!("Failed to start runtime: {}", e)))
+}
+
+/// Run a query and return the response text with the result message.
+///
+/// Blocking equivalent of [`query_result`](crate::query_result).
+pub fn query_result(
+    prompt: &str,
+    options: Option<ClaudeAgentOptions>,
+) -> Result<(String, ResultMessage)> {
+    runtime()?.block_on(crate::query_result(prompt, options))
+}
+
+/// Continue a previous query's conversation with a follow-up.
+///
+/// Blocking equivalent of [`continue_query`](crate::continue_query).
+pub fn continue_query(
+    session_id: &str,
+    follow_up: &str,
+    options: Option<ClaudeAgentOptions>,
+) -> Result<(String, ResultMessage)> {
+    runtime()?.block_on(crate::continue_query(session_id, follow_up, options))
+}
+
+/// Run a query and return only the response text.
+pub fn ask(prompt: &str, options: Option<ClaudeAgentOptions>) -> Result<String> {
+    query_result(prompt, options).map(|(text, _)| text)
+}
+
+/// Run a query and collect all messages.
+///
+/// Blocking equivalent of [`query_all`](crate::query_all).
+pub fn query_all(prompt: &str, options: Option<ClaudeAgentOptions>) -> Result<Vec<Message>> {
+    runtime()?.block_on(crate::query_all(prompt, options))
+}
+
+/// Blocking wrapper around [`ClaudeClient`].
+///
+/// Owns a private runtime that keeps the CLI session alive between calls.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use claude_agents_sdk::blocking::BlockingClient;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let mut client = BlockingClient::new(None)?;
+///     client.connect()?;
+///
+///     let answer = client.ask("What is the capital of France?")?;
+///     println!("{}", answer);
+///
+///     client.disconnect()?;
+///     Ok(())
+/// }
+/// ```
+pub struct BlockingClient {
+    client: ClaudeClient,
+    runtime: Runtime,
+}
+
+impl BlockingClient {
+    /// Create a new blocking client.
+    pub fn new(options: Option<ClaudeAgentOptions>) -> Result<Self> {
+        Ok(Self {
+            client: ClaudeClient::new(options),
+            runtime: runtime()?,
+        })
+    }
+
+    /// Connect to the Claude CLI.
+    pub fn connect(&mut self) -> Result<()> {
+        self.runtime.block_on(self.client.connect())
+    }
+
+    /// Send a query to Claude.
+    pub fn query(&mut self, prompt: &str) -> Result<()> {
+        self.runtime.block_on(self.client.query(prompt))
+    }
+
+    /// Receive the complete response for the current query.
+    pub fn receive_response(&mut self) -> Result<(String, ResultMessage)> {
+        self.runtime.block_on(self.client.receive_response())
+    }
+
+    /// Send a query and return the response text.
+    pub fn ask(&mut self, prompt: &str) -> Result<String> {
+        self.query(prompt)?;
+        self.receive_response().map(|(text, _)| text)
+    }
+
+    /// Iterate over messages as they are received.
+    ///
+    /// Each call to `next` blocks until a message arrives. The iterator does
+    /// not stop at the result message.
+    pub fn receive_messages(&mut self) -> impl Iterator<Item = Result<Message>> + '_ {
+        let Self { client, runtime } = self;
+        let mut stream = Box::pin(client.receive_messages());
+        std::iter::from_fn(move || runtime.block_on(stream.next()))
+    }
+
+    /// Interrupt the current operation.
+    pub fn interrupt(&self) -> Result<()> {
+        self.runtime.block_on(self.client.interrupt())
+    }
+
+    /// Change the permission mode for the session.
+    pub fn set_permission_mode(&self, mode: PermissionMode) -> Result<()> {
+        self.runtime.block_on(self.client.set_permission_mode(mode))
+    }
+
+    /// Change the model for the session.
+    pub fn set_model(&self, model: impl Into<String>) -> Result<()> {
+        self.runtime.block_on(self.client.set_model(model))
+    }
+
+    /// Disconnect from the Claude CLI.
+    pub fn disconnect(&mut self) -> Result<()> {
+        self.runtime.block_on(self.client.disconnect())
+    }
+
+    /// Check if the client is connected.
+    pub fn is_connected(&self) -> bool {
+        self.client.is_connected()
+    }
+
+    /// Get the underlying async client.
+    pub fn client(&self) -> &ClaudeClient {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocking_client_requires_connection() {
+        let mut client = BlockingClient::new(None).unwrap();
+        assert!(!client.is_connected());
+        assert!(client.query("hello").is_err());
+        assert!(client.receive_messages().next().is_none());
+    }
+}
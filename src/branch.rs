@@ -0,0 +1,215 @@
+//! Conversation branches for experiment-style workflows.
+//!
+//! [`ClaudeClient::branch_at`](crate::ClaudeClient::branch_at) starts a new
+//! session from a past prompt of the current one: the conversation is
+//! forked just before that prompt and the files are rewound to how they were
+//! when it was sent. The original session is left as it is and can keep
+//! going. Each branch records the [`BranchPoint`]s that led to it in its
+//! [`lineage`](crate::ClaudeClient::lineage).
+//!
+//! Branching needs file checkpointing, and the prompt's UUID in the
+//! [message history](crate::ClaudeAgentOptions::message_history_size),
+//! which the CLI only reports with `--replay-user-messages`. Branches are
+//! started with that flag themselves, so they can be branched in turn.
+//!
+//! Each branch works in a temporary copy of the original session's working
+//! directory, removed when the branch's client is dropped, so the sessions
+//! don't see each other's edits. The original's files are rewound only long
+//! enough to copy them, then put back.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::{ClaudeAgentOptions, ClaudeClient};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut options = ClaudeAgentOptions::new().with_message_history(100);
+//! options.enable_file_checkpointing = true;
+//! options
+//!     .extra_args
+//!     .insert("replay-user-messages".to_string(), None);
+//!
+//! let mut client = ClaudeClient::new(Some(options));
+//! client.connect().await?;
+//! client.query("Refactor the parser").await?;
+//! client.receive_response().await?;
+//!
+//! // Try again from the same starting point
+//! let checkpoint = client.export_session().await?.checkpoints[0]
+//!     .user_message_id
+//!     .clone();
+//! let mut branch = client.branch_at(&checkpoint).await?;
+//! branch.query("Refactor the parser, keeping the public API").await?;
+//! branch.receive_response().await?;
+//! assert_eq!(branch.lineage()[0].user_message_id, checkpoint);
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{ClaudeAgentOptions, Message};
+
+/// CLI flag reporting the UUIDs of user messages, which branching needs.
+const REPLAY_USER_MESSAGES: &str = "replay-user-messages";
+
+/// Where a branch left the session it was created from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BranchPoint {
+    /// Session the branch was created from.
+    pub session_id: String,
+    /// Prompt the branch starts before.
+    pub user_message_id: String,
+    /// Last message kept in the branch's conversation, or `None` when the
+    /// branch starts before the first prompt, with a new conversation.
+    pub resumed_at: Option<String>,
+    /// When the branch was created, in milliseconds since the Unix epoch.
+    pub created_at_ms: u64,
+}
+
+impl BranchPoint {
+    /// Find where to branch `session_id` before the prompt
+    /// `user_message_id` in `transcript`, the session's retained messages.
+    ///
+    /// `complete` says whether `transcript` reaches back to the start of
+    /// the session.
+    pub(crate) fn find(
+        session_id: String,
+        transcript: &[Message],
+        user_message_id: &str,
+        complete: bool,
+    ) -> Result<Self> {
+        let index = transcript
+            .iter()
+            .position(|message| match message {
+                Message::User(user) => {
+                    user.parent_tool_use_id.is_none()
+                        && user.uuid.as_deref() == Some(user_message_id)
+                }
+                _ => false,
+            })
+            .ok_or_else(|| {
+                ClaudeSDKError::configuration(format!(
+                    "User message {} is not in the message history; enable \
+                     'message_history_size' and the CLI's --{}",
+                    user_message_id, REPLAY_USER_MESSAGES
+                ))
+            })?;
+        let resumed_at = transcript[..index]
+            .iter()
+            .rev()
+            .find_map(|message| match message {
+                Message::User(user) => user.uuid.clone(),
+                Message::Assistant(assistant) => assistant.uuid.clone(),
+                _ => None,
+            });
+        if resumed_at.is_none() && !complete {
+            return Err(ClaudeSDKError::configuration(format!(
+                "The message history no longer reaches back before user message {}; \
+                 increase 'message_history_size'",
+                user_message_id
+            )));
+        }
+        let created_at_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        Ok(Self {
+            session_id,
+            user_message_id: user_message_id.to_string(),
+            resumed_at,
+            created_at_ms,
+        })
+    }
+
+    /// Options for the branch, based on those of the original session.
+    ///
+    /// Asks the CLI to replay user messages, so the branch's own prompts
+    /// can be branched at.
+    pub fn options(&self, mut options: ClaudeAgentOptions) -> ClaudeAgentOptions {
+        options.continue_conversation = false;
+        options
+            .extra_args
+            .insert(REPLAY_USER_MESSAGES.to_string(), None);
+        match self.resumed_at {
+            Some(ref uuid) => {
+                options.resume = Some(self.session_id.clone());
+                options.resume_session_at = Some(uuid.clone());
+                options.fork_session = true;
+            }
+            None => {
+                options.resume = None;
+                options.resume_session_at = None;
+                options.fork_session = false;
+            }
+        }
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(value: serde_json::Value) -> Message {
+        crate::_internal::parse_message(value).unwrap()
+    }
+
+    fn prompt(uuid: &str) -> Message {
+        message(json!({
+            "type": "user",
+            "uuid": uuid,
+            "message": {"role": "user", "content": "prompt"}
+        }))
+    }
+
+    fn answer(uuid: &str) -> Message {
+        message(json!({
+            "type": "assistant",
+            "uuid": uuid,
+            "message": {"content": [{"type": "text", "text": "done"}], "model": "sonnet"}
+        }))
+    }
+
+    fn init() -> Message {
+        message(json!({"type": "system", "subtype": "init", "data": {}}))
+    }
+
+    #[test]
+    fn test_branch_point() {
+        let transcript = [
+            init(),
+            prompt("u1"),
+            answer("a1"),
+            prompt("u2"),
+            answer("a2"),
+        ];
+
+        let point = BranchPoint::find("s".to_string(), &transcript, "u2", true).unwrap();
+        assert_eq!(point.resumed_at.as_deref(), Some("a1"));
+        let options = point.options(ClaudeAgentOptions::new().with_model("sonnet"));
+        assert_eq!(options.resume.as_deref(), Some("s"));
+        assert_eq!(options.resume_session_at.as_deref(), Some("a1"));
+        assert!(options.fork_session);
+        assert_eq!(options.model.as_deref(), Some("sonnet"));
+        assert!(options.extra_args.contains_key(REPLAY_USER_MESSAGES));
+        assert!(options.validate().is_ok());
+
+        // Before the first prompt there is nothing to resume
+        let point = BranchPoint::find("s".to_string(), &transcript, "u1", true).unwrap();
+        assert_eq!(point.resumed_at, None);
+        let options = point.options(ClaudeAgentOptions::new());
+        assert!(options.resume.is_none());
+        assert!(!options.fork_session);
+    }
+
+    #[test]
+    fn test_branch_point_needs_history() {
+        let transcript = [prompt("u2"), answer("a2")];
+        assert!(BranchPoint::find("s".to_string(), &transcript, "u3", true).is_err());
+        // Earlier messages may have been dropped from a full history
+        assert!(BranchPoint::find("s".to_string(), &transcript, "u2", false).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Bridging sessions to other systems, such as a browser on a websocket.
+//!
+//! Outgoing, [`json_messages`] turns a stream of [`Message`]s, such as a
+//! [`MessageReceiver`](crate::MessageReceiver), into JSON text frames.
+//! Messages are serialized with their serde representation, the one
+//! [`decode_message`] reads back, so a Rust front end can parse them too.
+//!
+//! Incoming, [`command_sink`] is a [`Sink`] of [`BridgeCommand`]s that
+//! sends prompts and control requests through a
+//! [`ClientHandle`](crate::ClientHandle), and [`json_command_sink`] accepts
+//! the same commands as JSON text, e.g. `{"type":"prompt","text":"Hi"}`.
+//!
+//! # Examples
+//!
+//! Forwarding between a session and a pair of text channels, which stand in
+//! for the two halves of a websocket:
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::bridge::{json_command_sink, json_messages};
+//! use claude_agents_sdk::ClaudeClient;
+//! use futures::{SinkExt, StreamExt};
+//!
+//! # async fn example(
+//! #     socket_rx: impl futures::Stream<Item = String> + Send + 'static,
+//! #     socket_tx: impl futures::Sink<String> + Unpin,
+//! # ) -> claude_agents_sdk::Result<()> {
+//! let mut client = ClaudeClient::new(None);
+//! client.connect().await?;
+//! let (handle, receiver) = client.into_split();
+//!
+//! // Browser -> agent
+//! tokio::spawn(socket_rx.map(Ok).forward(json_command_sink(handle)));
+//!
+//! // Agent -> browser
+//! let mut socket_tx = socket_tx;
+//! let mut frames = Box::pin(json_messages(receiver));
+//! while let Some(frame) = frames.next().await {
+//!     if socket_tx.send(frame?).await.is_err() {
+//!         break;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::client::ClientHandle;
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{Message, PermissionMode};
+
+/// Serialize `message` to JSON text.
+pub fn encode_message(message: &Message) -> Result<String> {
+    serde_json::to_string(message)
+        .map_err(|e| ClaudeSDKError::configuration(format!("Failed to serialize message: {}", e)))
+}
+
+/// Parse a message serialized with [`encode_message`].
+pub fn decode_message(text: &str) -> Result<Message> {
+    serde_json::from_str(text).map_err(|e| {
+        ClaudeSDKError::json_decode_with_context(
+            "Failed to decode message",
+            Some(text.to_string()),
+            None,
+            e,
+        )
+    })
+}
+
+/// Turn a stream of messages into a stream of JSON text frames.
+pub fn json_messages<S>(messages: S) -> impl Stream<Item = Result<String>>
+where
+    S: Stream<Item = Result<Message>>,
+{
+    messages.map(|message| message.and_then(|message| encode_message(&message)))
+}
+
+/// A request from the other side of a bridge.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BridgeCommand {
+    /// Send a prompt.
+    Prompt {
+        /// The prompt.
+        text: String,
+    },
+    /// Interrupt the current response.
+    Interrupt,
+    /// Switch models.
+    SetModel {
+        /// The model.
+        model: String,
+    },
+    /// Switch permission modes.
+    SetPermissionMode {
+        /// The mode.
+        mode: PermissionMode,
+    },
+}
+
+impl BridgeCommand {
+    /// Parse a command from JSON text.
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text).map_err(|e| {
+            ClaudeSDKError::json_decode_with_context(
+                "Failed to decode bridge command",
+                Some(text.to_string()),
+                None,
+                e,
+            )
+        })
+    }
+
+    /// Carry the command out through `handle`.
+    pub async fn apply(self, handle: &ClientHandle) -> Result<()> {
+        match self {
+            Self::Prompt { text } => handle.query(&text).await,
+            Self::Interrupt => handle.interrupt().await,
+            Self::SetModel { model } => handle.set_model(model).await,
+            Self::SetPermissionMode { mode } => handle.set_permission_mode(mode).await,
+        }
+    }
+}
+
+/// A sink carrying out each command it is sent through `handle`.
+///
+/// The sink fails, and stops taking commands, with the first command that
+/// fails.
+pub fn command_sink(handle: ClientHandle) -> impl Sink<BridgeCommand, Error = ClaudeSDKError> {
+    futures::sink::unfold(handle, |handle, command: BridgeCommand| async move {
+        command.apply(&handle).await?;
+        Ok(handle)
+    })
+}
+
+/// Like [`command_sink`], for commands sent as JSON text.
+pub fn json_command_sink(handle: ClientHandle) -> impl Sink<String, Error = ClaudeSDKError> {
+    command_sink(handle)
+        .with(|text: String| futures::future::ready(BridgeCommand::from_json(&text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_messages_round_trip() {
+        let messages = [
+            json!({
+                "type": "assistant",
+                "message": {
+                    "content": [
+                        {"type": "text", "text": "Listing"},
+                        {"type": "tool_use", "id": "t1", "name": "Bash", "input": {"command": "ls"}}
+                    ],
+                    "model": "sonnet"
+                }
+            }),
+            json!({
+                "type": "result",
+                "subtype": "success",
+                "duration_ms": 1,
+                "duration_api_ms": 1,
+                "is_error": false,
+                "num_turns": 1,
+                "session_id": "s"
+            }),
+        ];
+        for raw in messages {
+            let message = crate::_internal::parse_message(raw).unwrap();
+            let text = encode_message(&message).unwrap();
+            let decoded = decode_message(&text).unwrap();
+            assert_eq!(encode_message(&decoded).unwrap(), text);
+        }
+        assert!(decode_message("{}").is_err());
+    }
+
+    #[test]
+    fn test_bridge_commands() {
+        assert_eq!(
+            BridgeCommand::from_json(r#"{"type":"prompt","text":"Hi"}"#).unwrap(),
+            BridgeCommand::Prompt {
+                text: "Hi".to_string()
+            }
+        );
+        assert_eq!(
+            BridgeCommand::from_json(r#"{"type":"set_permission_mode","mode":"acceptEdits"}"#)
+                .unwrap(),
+            BridgeCommand::SetPermissionMode {
+                mode: PermissionMode::AcceptEdits
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(BridgeCommand::Interrupt).unwrap(),
+            json!({"type": "interrupt"})
+        );
+        assert!(BridgeCommand::from_json(r#"{"type":"shutdown"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_command_sink_stops_at_failure() {
+        // Not connected, so the prompt fails
+        let (handle, _receiver) = crate::ClaudeClient::new(None).into_split();
+        let mut sink = Box::pin(json_command_sink(handle));
+        assert!(sink
+            .send(r#"{"type":"prompt","text":"Hi"}"#.to_string())
+            .await
+            .is_err());
+
+        let (handle, _receiver) = crate::ClaudeClient::new(None).into_split();
+        let mut sink = Box::pin(json_command_sink(handle));
+        assert!(sink.send("not json".to_string()).await.is_err());
+    }
+}
@@ -0,0 +1,291 @@
+//! Portable session bundles for handing a session to another machine.
+//!
+//! [`ClaudeClient::export_session`](crate::ClaudeClient::export_session) captures what another machine needs to
+//! pick a session up: the session ID, the configuration it ran with, the
+//! retained transcript and the user messages file checkpoints can rewind
+//! to. [`ClaudeClient::import_session`](crate::ClaudeClient::import_session) turns a bundle back into a client
+//! that resumes the session.
+//!
+//! The bundle doesn't carry the conversation itself: resuming needs a CLI
+//! with access to the same session, e.g. a shared or synced Claude config
+//! directory, and the same project checkout.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::{ClaudeAgentOptions, ClaudeClient, SessionBundle};
+//!
+//! # async fn example(client: &ClaudeClient) -> Result<(), Box<dyn std::error::Error>> {
+//! // On the laptop
+//! client.export_session().await?.save("handoff.json")?;
+//!
+//! // In CI
+//! let bundle = SessionBundle::load("handoff.json")?;
+//! let mut client = ClaudeClient::import_session(&bundle, ClaudeAgentOptions::new());
+//! client.connect().await?;
+//! client.query("Continue where you left off and run the tests").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{ClaudeAgentOptions, Message, SessionManifest};
+
+/// Version of the bundle format written by this SDK, the same as
+/// [`export::SCHEMA_VERSION`](crate::export::SCHEMA_VERSION).
+pub const SESSION_BUNDLE_VERSION: u32 = crate::export::SCHEMA_VERSION;
+
+/// Longest prompt excerpt kept per checkpoint, in characters.
+const CHECKPOINT_PROMPT_CHARS: usize = 200;
+
+/// A user message files can be rewound to with
+/// [`ClaudeClient::rewind_files`](crate::ClaudeClient::rewind_files).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointInfo {
+    /// UUID of the user message.
+    pub user_message_id: String,
+    /// Start of the prompt, to tell checkpoints apart.
+    pub prompt: Option<String>,
+}
+
+/// Everything needed to resume a session elsewhere.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionBundle {
+    /// [`SCHEMA_VERSION`](crate::export::SCHEMA_VERSION) of the bundle.
+    pub schema_version: u32,
+    /// CLI session ID to resume.
+    pub session_id: String,
+    /// Configuration the session ran with.
+    pub manifest: SessionManifest,
+    /// [`SessionManifest::fingerprint`] of that configuration.
+    pub fingerprint: String,
+    /// Whether file checkpointing was enabled.
+    pub file_checkpointing: bool,
+    /// Checkpoints available for rewinding, oldest first. Empty unless
+    /// file checkpointing was enabled.
+    pub checkpoints: Vec<CheckpointInfo>,
+    /// Messages retained by the session's message history.
+    pub transcript: Vec<Message>,
+    /// When the bundle was exported, in milliseconds since the Unix epoch.
+    pub exported_at_ms: u64,
+}
+
+impl SessionBundle {
+    /// Create a bundle for a session with the given manifest and history.
+    ///
+    /// Fails if the CLI hasn't reported a session ID yet.
+    pub fn new(
+        manifest: SessionManifest,
+        transcript: Vec<Message>,
+        file_checkpointing: bool,
+    ) -> Result<Self> {
+        let session_id = manifest.session_id.clone().ok_or_else(|| {
+            ClaudeSDKError::configuration("Cannot export a session before the CLI reports its ID")
+        })?;
+        let checkpoints = if file_checkpointing {
+            checkpoints(&transcript)
+        } else {
+            Vec::new()
+        };
+        let exported_at_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        Ok(Self {
+            schema_version: SESSION_BUNDLE_VERSION,
+            session_id,
+            fingerprint: manifest.fingerprint(),
+            manifest,
+            file_checkpointing,
+            checkpoints,
+            transcript,
+            exported_at_ms,
+        })
+    }
+
+    /// Write the bundle to `path` as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| {
+            ClaudeSDKError::internal(format!("Failed to serialize session bundle: {}", e))
+        })?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Read a bundle written by [`save`](Self::save).
+    ///
+    /// Bundles from older SDKs are [migrated](crate::export::migrate);
+    /// fails on bundles from a newer, unknown schema version.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let invalid = |e: &dyn std::fmt::Display| {
+            ClaudeSDKError::configuration(format!(
+                "Invalid session bundle {}: {}",
+                path.display(),
+                e
+            ))
+        };
+        let json = std::fs::read_to_string(path)?;
+        let raw = serde_json::from_str(&json).map_err(|e| invalid(&e))?;
+        let raw = crate::export::migrate(raw).map_err(|e| invalid(&e))?;
+        serde_json::from_value(raw).map_err(|e| invalid(&e))
+    }
+
+    /// Options resuming the session, based on `options`.
+    ///
+    /// Sets `resume` to the session ID and enables file checkpointing if
+    /// the session used it. The model, permission mode, tool lists and
+    /// working directory of the original session fill in whatever `options`
+    /// leaves unset; the working directory only if it exists here.
+    pub fn resume_options(&self, mut options: ClaudeAgentOptions) -> ClaudeAgentOptions {
+        let manifest = &self.manifest;
+        options.resume = Some(self.session_id.clone());
+        options.continue_conversation = false;
+        options.enable_file_checkpointing |= self.file_checkpointing;
+        if options.model.is_none() {
+            options.model = manifest.model.clone();
+        }
+        if options.permission_mode.is_none() {
+            options.permission_mode = manifest.permission_mode;
+        }
+        if options.allowed_tools.is_empty() {
+            options.allowed_tools = manifest.allowed_tools.clone();
+        }
+        if options.disallowed_tools.is_empty() {
+            options.disallowed_tools = manifest.disallowed_tools.clone();
+        }
+        if options.cwd.is_none() {
+            match manifest.cwd {
+                Some(ref cwd) if cwd.is_dir() => options.cwd = Some(cwd.clone()),
+                Some(ref cwd) => debug!(
+                    "Working directory {} of the exported session doesn't exist here",
+                    cwd.display()
+                ),
+                None => {}
+            }
+        }
+        options
+    }
+}
+
+/// Checkpoints in `transcript`: the user prompts carrying a UUID.
+fn checkpoints(transcript: &[Message]) -> Vec<CheckpointInfo> {
+    transcript
+        .iter()
+        .filter_map(|message| match message {
+            Message::User(user) if user.parent_tool_use_id.is_none() => Some(CheckpointInfo {
+                user_message_id: user.uuid.clone()?,
+                prompt: user
+                    .text()
+                    .map(|text| text.chars().take(CHECKPOINT_PROMPT_CHARS).collect()),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PermissionMode;
+
+    fn manifest() -> SessionManifest {
+        let options = ClaudeAgentOptions::new()
+            .with_model("sonnet")
+            .with_permission_mode(PermissionMode::AcceptEdits)
+            .with_cwd("/nonexistent/project");
+        let mut manifest = SessionManifest::from_options(&options);
+        manifest.session_id = Some("sess_1".to_string());
+        manifest
+    }
+
+    fn transcript() -> Vec<Message> {
+        let user = |uuid: &str, text: &str, parent: Option<&str>| {
+            crate::_internal::parse_message(serde_json::json!({
+                "type": "user",
+                "uuid": uuid,
+                "parent_tool_use_id": parent,
+                "message": {"role": "user", "content": text}
+            }))
+            .unwrap()
+        };
+        vec![
+            user("u1", "Fix the parser", None),
+            user("u2", "tool output", Some("toolu_1")),
+            user("u3", "Now add tests", None),
+        ]
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let bundle = SessionBundle::new(manifest(), transcript(), true).unwrap();
+        assert_eq!(bundle.session_id, "sess_1");
+        assert_eq!(bundle.fingerprint, bundle.manifest.fingerprint());
+        let ids: Vec<_> = bundle
+            .checkpoints
+            .iter()
+            .map(|c| c.user_message_id.as_str())
+            .collect();
+        assert_eq!(ids, ["u1", "u3"]);
+        assert_eq!(
+            bundle.checkpoints[0].prompt.as_deref(),
+            Some("Fix the parser")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.json");
+        bundle.save(&path).unwrap();
+        let loaded = SessionBundle::load(&path).unwrap();
+        assert_eq!(loaded.manifest, bundle.manifest);
+        assert_eq!(loaded.checkpoints, bundle.checkpoints);
+        assert_eq!(loaded.transcript.len(), 3);
+
+        let mut newer = serde_json::to_value(&bundle).unwrap();
+        newer["schema_version"] = serde_json::json!(SESSION_BUNDLE_VERSION + 1);
+        std::fs::write(&path, newer.to_string()).unwrap();
+        assert!(SessionBundle::load(&path).is_err());
+
+        // Bundles from before versioning numbered their own format
+        let mut older = serde_json::to_value(&bundle).unwrap();
+        let fields = older.as_object_mut().unwrap();
+        fields.remove("schema_version");
+        fields.insert("version".to_string(), serde_json::json!(1));
+        older["manifest"]
+            .as_object_mut()
+            .unwrap()
+            .remove("schema_version");
+        std::fs::write(&path, older.to_string()).unwrap();
+        let loaded = SessionBundle::load(&path).unwrap();
+        assert_eq!(loaded.schema_version, SESSION_BUNDLE_VERSION);
+        assert_eq!(loaded.manifest, bundle.manifest);
+    }
+
+    #[test]
+    fn test_export_requires_session_id() {
+        let mut manifest = manifest();
+        manifest.session_id = None;
+        assert!(SessionBundle::new(manifest, Vec::new(), false).is_err());
+
+        let bundle = SessionBundle::new(self::manifest(), transcript(), false).unwrap();
+        assert!(bundle.checkpoints.is_empty());
+    }
+
+    #[test]
+    fn test_resume_options() {
+        let bundle = SessionBundle::new(manifest(), Vec::new(), true).unwrap();
+
+        let options = bundle.resume_options(ClaudeAgentOptions::new().with_model("opus"));
+        assert_eq!(options.resume.as_deref(), Some("sess_1"));
+        assert!(options.enable_file_checkpointing);
+        assert_eq!(options.model.as_deref(), Some("opus"));
+        assert_eq!(options.permission_mode, Some(PermissionMode::AcceptEdits));
+        // The original working directory doesn't exist here
+        assert!(options.cwd.is_none());
+        assert!(options.validate().is_ok());
+    }
+}
@@ -9,13 +9,249 @@
 //! - Runtime model and permission changes
 //! - File checkpointing and rewinding
 
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tokio_stream::{Stream, StreamExt};
+use tokio::time::Instant;
+use tokio_stream::Stream;
+use tracing::{debug, info, warn};
 
 use crate::_internal::client::InternalClient;
+use crate::_internal::escalation::Escalation;
+use crate::_internal::query::ControlSlot;
+use crate::_internal::transport::Transport;
+use crate::_internal::AUDIT_TARGET;
 use crate::errors::{ClaudeSDKError, Result};
+use crate::interceptor::QueryInterceptor;
+use crate::tool_schema::ToolSchemaRegistry;
 use crate::types::*;
+use crate::workspace::{self, TempWorkspace};
+
+/// How long to wait for the terminal result message after an interrupt.
+const INTERRUPT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Model alias the CLI resolves to its default model.
+const DEFAULT_MODEL_ALIAS: &str = "default";
+
+/// Session values to restore once a [`ClaudeClient::query_with`] query ends.
+#[derive(Default)]
+struct OverrideRestore {
+    model: Option<String>,
+    permission_mode: Option<PermissionMode>,
+    /// `Some(None)` restores the CLI's default thinking budget.
+    max_thinking_tokens: Option<Option<u32>>,
+}
+
+/// Log a result message and notify interceptors of it.
+fn notify_result(
+    interceptors: &[Arc<dyn QueryInterceptor>],
+    tags: &HashMap<String, String>,
+    result: &ResultMessage,
+) {
+    debug!(
+        session_id = %result.session_id,
+        cost_usd = ?result.total_cost_usd,
+        tags = ?tags,
+        "Query completed"
+    );
+    for interceptor in interceptors {
+        interceptor.after_result(result);
+    }
+}
+
+/// What has been read of a response.
+#[derive(Debug, Default)]
+struct CollectedResponse {
+    /// Text of the assistant messages.
+    parts: Vec<String>,
+    /// First error an assistant message carried, with the message's text.
+    error: Option<(AssistantMessageError, String)>,
+}
+
+/// Collect assistant text into `response` until a result message arrives.
+///
+/// Returns `None` if the channel closes first. Cancel-safe: text received
+/// before cancellation is kept in `response`.
+async fn collect_response(
+    message_rx: &mut Option<mpsc::Receiver<Result<Message>>>,
+    interceptors: &[Arc<dyn QueryInterceptor>],
+    tags: &HashMap<String, String>,
+    response: &mut CollectedResponse,
+) -> Result<Option<ResultMessage>> {
+    let Some(rx) = message_rx.as_mut() else {
+        return Ok(None);
+    };
+
+    let mut auth_challenge = None;
+    while let Some(msg) = rx.recv().await {
+        let msg = msg?;
+        if auth_challenge.is_none() {
+            auth_challenge = msg.auth_challenge();
+        }
+        match msg {
+            Message::Assistant(asst) => {
+                let text = asst.text();
+                if let (Some(error), None) = (asst.error, &response.error) {
+                    response.error = Some((error, text.clone()));
+                }
+                if !text.is_empty() {
+                    response.parts.push(text);
+                }
+            }
+            Message::Result(result) => {
+                notify_result(interceptors, tags, &result);
+                // The whole response has been read, so the next one starts clean
+                return match auth_challenge {
+                    Some(instructions) => Err(ClaudeSDKError::auth_required(instructions)),
+                    None => Ok(Some(result)),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    Ok(None)
+}
+
+/// State shared by the two halves of [`ClaudeClient::stream_response`].
+///
+/// Whichever half is polled reads messages, queueing text for the stream
+/// and keeping the result for the future, and wakes the other half when it
+/// has something for it.
+struct ResponseDriver<'a> {
+    /// Taken by the future once the response is finished.
+    client: Option<&'a mut ClaudeClient>,
+    /// Whether to stream text deltas instead of whole assistant messages.
+    partial: bool,
+    /// What the CLI said to do, if it asked to be logged in again.
+    auth_challenge: Option<String>,
+    text: VecDeque<String>,
+    outcome: Option<Result<ResultMessage>>,
+    finished: bool,
+    text_waker: Option<Waker>,
+    result_waker: Option<Waker>,
+}
+
+impl ResponseDriver<'_> {
+    /// Read messages until there is text to hand out or the response is
+    /// finished.
+    fn drive(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        while !self.finished {
+            let Some(client) = self.client.as_mut() else {
+                break;
+            };
+            let polled = match client.message_rx.as_mut() {
+                Some(rx) => rx.poll_recv(cx),
+                None => Poll::Ready(None),
+            };
+            let message = match polled {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(message))) => message,
+                Poll::Ready(Some(Err(e))) => {
+                    self.finish(Err(e));
+                    break;
+                }
+                Poll::Ready(None) => {
+                    self.finish(Err(ClaudeSDKError::internal(
+                        "Connection closed without result",
+                    )));
+                    break;
+                }
+            };
+            if self.auth_challenge.is_none() {
+                self.auth_challenge = message.auth_challenge();
+            }
+            let text = match message {
+                Message::Assistant(ref asst) if !self.partial => Some(asst.text()),
+                Message::StreamEvent(ref event) if self.partial => event
+                    .event
+                    .pointer("/delta")
+                    .filter(|delta| delta["type"] == "text_delta")
+                    .and_then(|delta| delta["text"].as_str())
+                    .map(String::from),
+                Message::Result(result) => {
+                    notify_result(
+                        &client.interceptors,
+                        &client.internal.options().session_tags,
+                        &result,
+                    );
+                    let outcome = match self.auth_challenge.take() {
+                        Some(instructions) => Err(ClaudeSDKError::auth_required(instructions)),
+                        None => Ok(result),
+                    };
+                    self.finish(outcome);
+                    break;
+                }
+                _ => None,
+            };
+            if let Some(text) = text.filter(|text| !text.is_empty()) {
+                self.text.push_back(text);
+                if let Some(waker) = self.text_waker.take() {
+                    waker.wake();
+                }
+                return Poll::Ready(());
+            }
+        }
+        Poll::Ready(())
+    }
+
+    fn finish(&mut self, outcome: Result<ResultMessage>) {
+        self.outcome = Some(outcome);
+        self.finished = true;
+        for waker in [self.text_waker.take(), self.result_waker.take()]
+            .into_iter()
+            .flatten()
+        {
+            waker.wake();
+        }
+    }
+}
+
+fn lock_driver<'m, 'a>(
+    driver: &'m std::sync::Mutex<ResponseDriver<'a>>,
+) -> std::sync::MutexGuard<'m, ResponseDriver<'a>> {
+    driver.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Text half of [`ClaudeClient::stream_response`].
+struct ResponseText<'a> {
+    driver: Arc<std::sync::Mutex<ResponseDriver<'a>>>,
+}
+
+impl Stream for ResponseText<'_> {
+    type Item = String;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<String>> {
+        let mut driver = lock_driver(&self.driver);
+        loop {
+            if let Some(text) = driver.text.pop_front() {
+                return Poll::Ready(Some(text));
+            }
+            if driver.finished {
+                return Poll::Ready(None);
+            }
+            if driver.drive(cx).is_pending() {
+                driver.text_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl Drop for ResponseText<'_> {
+    fn drop(&mut self) {
+        // The future may have been relying on this half to read messages
+        let mut driver = lock_driver(&self.driver);
+        driver.text_waker = None;
+        if let Some(waker) = driver.result_waker.take() {
+            waker.wake();
+        }
+    }
+}
 
 /// Bidirectional client for streaming Claude interactions.
 ///
@@ -77,8 +313,26 @@ pub struct ClaudeClient {
     internal: InternalClient,
     /// Message receiver from the internal client.
     message_rx: Option<mpsc::Receiver<Result<Message>>>,
+    /// Interceptors run around each query, in registration order.
+    interceptors: Vec<Arc<dyn QueryInterceptor>>,
+    /// Per-query environment the current CLI process was spawned with, if any.
+    env_overlay: Option<HashMap<String, String>>,
+    /// Values to restore after a query sent with overrides.
+    override_restore: Option<OverrideRestore>,
+    /// Branch points leading to this session, oldest first.
+    lineage: Vec<crate::BranchPoint>,
+    /// Copy of the original's working directory a branch runs in.
+    workspace: Option<TempWorkspace>,
+    /// Last prompt sent, after interceptors, to send again after the CLI
+    /// has been logged in again.
+    last_prompt: Option<String>,
+    /// Turn and prompt of the messages read through envelopes.
+    envelopes: crate::envelope::EnvelopeTracker,
 }
 
+/// Name of [`ClaudeClient`] in the Python SDK.
+pub type ClaudeSDKClient = ClaudeClient;
+
 impl ClaudeClient {
     /// Create a new Claude client.
     ///
@@ -101,12 +355,87 @@ impl ClaudeClient {
     /// let client = ClaudeClient::new(Some(options));
     /// ```
     pub fn new(options: Option<ClaudeAgentOptions>) -> Self {
+        Self::from_internal(InternalClient::new(options.unwrap_or_default()))
+    }
+
+    fn from_internal(internal: InternalClient) -> Self {
         Self {
-            internal: InternalClient::new(options.unwrap_or_default()),
+            internal,
             message_rx: None,
+            interceptors: Vec::new(),
+            env_overlay: None,
+            override_restore: None,
+            lineage: Vec::new(),
+            workspace: None,
+            last_prompt: None,
+            envelopes: Default::default(),
         }
     }
 
+    /// Create a client running over `transport` instead of starting the
+    /// CLI, such as a [`MockTransport`](crate::testing::MockTransport) in
+    /// tests or a transport to a CLI running elsewhere.
+    ///
+    /// [`connect`](Self::connect) connects the transport if it isn't ready
+    /// yet. The transport only serves one session, so reconnecting after it
+    /// has been used fails, and prompts are always written to it. A
+    /// [`SubprocessTransport`](crate::_internal::transport::SubprocessTransport)
+    /// runs as a client's own CLI would, under the frame limit of the
+    /// options it was created with.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::testing::MockTransport;
+    /// use claude_agents_sdk::ClaudeClient;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> claude_agents_sdk::Result<()> {
+    /// let transport = MockTransport::with_text_response("Hello!").respond_to_control_requests();
+    /// let mut client = ClaudeClient::with_transport(None, Box::new(transport));
+    /// client.connect().await?;
+    ///
+    /// client.query("Hi").await?;
+    /// let (text, _result) = client.receive_response().await?;
+    /// assert_eq!(text, "Hello!");
+    /// client.disconnect().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_transport(
+        options: Option<ClaudeAgentOptions>,
+        transport: Box<dyn Transport>,
+    ) -> Self {
+        Self::from_internal(
+            InternalClient::new(options.unwrap_or_default()).with_transport(transport),
+        )
+    }
+
+    /// Register a [`QueryInterceptor`] to run around each query.
+    ///
+    /// Interceptors run in registration order.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::{ClaudeAgentOptions, ClaudeClient, QueryInterceptor, Result};
+    ///
+    /// struct Prefix;
+    ///
+    /// impl QueryInterceptor for Prefix {
+    ///     fn before_query(&self, prompt: &mut String, _: &mut ClaudeAgentOptions) -> Result<()> {
+    ///         prompt.insert_str(0, "Answer briefly. ");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let mut client = ClaudeClient::new(None);
+    /// client.add_interceptor(Prefix);
+    /// ```
+    pub fn add_interceptor(&mut self, interceptor: impl QueryInterceptor + 'static) {
+        self.interceptors.push(Arc::new(interceptor));
+    }
+
     /// Connect to the Claude CLI.
     ///
     /// This establishes a connection to the CLI process and initializes
@@ -133,8 +462,41 @@ impl ClaudeClient {
     /// }
     /// ```
     pub async fn connect(&mut self) -> Result<()> {
-        self.internal.connect().await?;
+        self.connect_with_progress(|_| {}).await
+    }
+
+    /// Connect to the Claude CLI, reporting each [`ConnectPhase`] as it completes.
+    ///
+    /// The client is only marked connected once the session is fully
+    /// initialized. A failed connect shuts the CLI process down rather than
+    /// leaving a half-connected client, and dropping the future mid-connect
+    /// (e.g. via `tokio::time::timeout` or `select!`) kills the process.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::{ClaudeClient, ConnectPhase};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client
+    ///         .connect_with_progress(|phase| match phase {
+    ///             ConnectPhase::Spawned => println!("CLI started"),
+    ///             ConnectPhase::ProtocolReady => println!("Control channel ready"),
+    ///             ConnectPhase::Initialized => println!("Session initialized"),
+    ///         })
+    ///         .await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn connect_with_progress(
+        &mut self,
+        on_phase: impl FnMut(ConnectPhase),
+    ) -> Result<()> {
+        self.internal.connect_with_progress(on_phase).await?;
         self.message_rx = self.internal.take_message_rx();
+        self.env_overlay = None;
         Ok(())
     }
 
@@ -169,55 +531,54 @@ impl ClaudeClient {
     /// }
     /// ```
     pub async fn query(&mut self, prompt: &str) -> Result<()> {
-        self.internal.send_message(prompt).await
+        self.apply_env_overlay(None).await?;
+        self.send_query(prompt, QueryOverrides::default()).await
     }
 
-    /// Get a stream of messages from the current query.
+    /// Send a query with some options overridden for this query only.
     ///
-    /// Returns a stream that yields messages as they are received from
-    /// Claude. The stream ends when a result message is received.
+    /// The overrides are applied to the live session with control requests
+    /// before the prompt is sent, and the previous values are restored once
+    /// the response has been received with
+    /// [`receive_response`](Self::receive_response) (or, when reading with
+    /// [`receive_messages`](Self::receive_messages), before the next query).
     ///
     /// # Examples
     ///
     /// ```rust,no_run
-    /// use claude_agents_sdk::{ClaudeClient, Message};
-    /// use tokio_stream::StreamExt;
+    /// use claude_agents_sdk::{ClaudeClient, PermissionMode, QueryOverrides};
     ///
     /// #[tokio::main]
     /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///     let mut client = ClaudeClient::new(None);
     ///     client.connect().await?;
-    ///     client.query("Tell me a joke").await?;
     ///
-    ///     while let Some(msg) = client.receive_messages().next().await {
-    ///         match msg? {
-    ///             Message::Assistant(asst) => println!("{}", asst.text()),
-    ///             Message::Result(_) => break,
-    ///             _ => {}
-    ///         }
-    ///     }
+    ///     let overrides = QueryOverrides::new()
+    ///         .with_model("claude-opus-4-1")
+    ///         .with_permission_mode(PermissionMode::Plan);
+    ///     client.query_with("Plan the migration", overrides).await?;
+    ///     let (plan, _) = client.receive_response().await?;
+    ///     println!("{}", plan);
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub fn receive_messages(&mut self) -> impl Stream<Item = Result<Message>> + '_ {
-        futures::stream::poll_fn(move |cx| {
-            if let Some(ref mut rx) = self.message_rx {
-                Pin::new(rx).poll_recv(cx)
-            } else {
-                std::task::Poll::Ready(None)
-            }
-        })
+    pub async fn query_with(&mut self, prompt: &str, overrides: QueryOverrides) -> Result<()> {
+        self.apply_env_overlay(None).await?;
+        self.send_query(prompt, overrides).await
     }
 
-    /// Receive the complete response for the current query.
-    ///
-    /// Collects all messages until a result message is received and returns
-    /// the combined response text along with the result metadata.
+    /// Send a query with additional environment variables for the CLI.
     ///
-    /// # Returns
+    /// The CLI reads its environment only at spawn time and the protocol has
+    /// no per-turn environment, so the session is restarted with `env` merged
+    /// over [`ClaudeAgentOptions::env`], resuming the current conversation.
+    /// The overlay applies to this query only: the next [`query`](Self::query)
+    /// restarts the session again without it, and consecutive calls with the
+    /// same `env` reuse the running process.
     ///
-    /// A tuple of (response_text, result_message).
+    /// The previous response must have been fully received before calling
+    /// this, as any unread messages are discarded on restart.
     ///
     /// # Examples
     ///
@@ -228,94 +589,439 @@ impl ClaudeClient {
     /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///     let mut client = ClaudeClient::new(None);
     ///     client.connect().await?;
-    ///     client.query("What is 2 + 2?").await?;
     ///
-    ///     let (response, result) = client.receive_response().await?;
-    ///     println!("Response: {}", response);
-    ///     println!("Turns: {}", result.num_turns);
+    ///     client
+    ///         .query_with_env("Run the tenant report", [("TENANT_ID", "acme")])
+    ///         .await?;
+    ///     let (response, _) = client.receive_response().await?;
+    ///     println!("{}", response);
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn receive_response(&mut self) -> Result<(String, ResultMessage)> {
-        let mut response_parts: Vec<String> = Vec::new();
-
-        while let Some(msg) = self.receive_messages().next().await {
-            match msg? {
-                Message::Assistant(asst) => {
-                    let text = asst.text();
-                    if !text.is_empty() {
-                        response_parts.push(text);
-                    }
+    pub async fn query_with_env<I, K, V>(&mut self, prompt: &str, env: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let env = env
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect::<HashMap<_, _>>();
+        self.apply_env_overlay(Some(env)).await?;
+        self.send_query(prompt, QueryOverrides::default()).await
+    }
+
+    /// Restart the session if the requested environment overlay differs from
+    /// the one the running CLI process was spawned with.
+    async fn apply_env_overlay(&mut self, overlay: Option<HashMap<String, String>>) -> Result<()> {
+        if overlay == self.env_overlay {
+            return Ok(());
+        }
+        if !self.internal.is_connected() {
+            return Err(ClaudeSDKError::cli_connection("Client not connected"));
+        }
+
+        debug!("Restarting session to apply per-query environment");
+        self.restart_session(overlay).await
+    }
+
+    /// Restart the CLI process, resuming the current conversation, with the
+    /// environment overlay `overlay`.
+    async fn restart_session(&mut self, overlay: Option<HashMap<String, String>>) -> Result<()> {
+        let session_id = self.internal.session_id().await;
+        self.message_rx = None;
+        self.internal.disconnect().await?;
+
+        // Keep the resumed session and the overlay out of the stored
+        // options, so they stay as configured for later connects
+        let options = self.internal.options_mut();
+        let base = options.clone();
+        if let Some(session_id) = session_id {
+            options.resume = Some(session_id);
+            options.resume_session_at = None;
+            options.fork_session = false;
+            options.continue_conversation = false;
+        }
+        if let Some(ref overlay) = overlay {
+            options.env.extend(overlay.clone());
+        }
+
+        let connected = self.internal.connect().await;
+        let options = self.internal.options_mut();
+        options.resume = base.resume;
+        options.resume_session_at = base.resume_session_at;
+        options.fork_session = base.fork_session;
+        options.continue_conversation = base.continue_conversation;
+        options.env = base.env;
+        connected?;
+
+        self.message_rx = self.internal.take_message_rx();
+        self.env_overlay = overlay;
+        Ok(())
+    }
+
+    /// Send a prompt with per-query overrides, first restoring the previous
+    /// query's overrides and reverting any expired permission escalation.
+    async fn send_query(&mut self, prompt: &str, overrides: QueryOverrides) -> Result<()> {
+        self.restore_query_overrides().await?;
+        self.revert_expired_escalation().await?;
+
+        self.compact_if_full().await?;
+
+        let sent = match self.apply_query_overrides(overrides).await {
+            Ok(()) => self.intercept_and_send(prompt).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            if let Err(restore_error) = self.restore_query_overrides().await {
+                warn!("Failed to restore query overrides: {}", restore_error);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Compact the conversation before the next prompt, if it's past the
+    /// threshold of a [`ContextPolicy`] that compacts.
+    ///
+    /// The compaction runs as a turn of its own, whose messages the reader
+    /// keeps from the consumer, so the next response starts clean without
+    /// waiting for it here. `/compact` is a prompt, so it can't run before
+    /// the turn that crossed the threshold ends; that turn is left to the
+    /// CLI's own auto-compaction, with the reader reporting the crossing.
+    /// The CLI runs the `PreCompact` hooks; once they block a compaction,
+    /// which the reader tells by the missing compaction boundary, none is
+    /// tried until the context is compacted some other way.
+    async fn compact_if_full(&mut self) -> Result<()> {
+        let Some(policy) = self.internal.options().context_policy.clone() else {
+            return Ok(());
+        };
+        let ContextAction::Compact { ref instructions } = policy.action else {
+            return Ok(());
+        };
+        let Some(usage) = self.internal.context_usage() else {
+            return Ok(());
+        };
+        if !policy.is_exceeded_by(usage.used_tokens) {
+            return Ok(());
+        }
+        if self.internal.compaction_blocked() {
+            debug!("Context nearly full, but the last compaction was blocked");
+            return Ok(());
+        }
+        info!(
+            used_tokens = usage.used_tokens,
+            context_window = usage.context_window,
+            "Context nearly full, compacting"
+        );
+        let invocation =
+            SlashCommand::invocation("compact", instructions.as_deref().unwrap_or_default())?;
+        self.internal.send_hidden_message(&invocation).await?;
+        // Not to compact again before the compaction is done
+        self.internal.reset_context_usage();
+        Ok(())
+    }
+
+    /// Apply per-query overrides to the live session, recording the values
+    /// they replace.
+    async fn apply_query_overrides(&mut self, overrides: QueryOverrides) -> Result<()> {
+        if overrides.is_empty() {
+            return Ok(());
+        }
+        // Restored to the values in effect, which runtime changes such as
+        // `set_model` may have moved away from the options
+        let settings = self.internal.settings();
+        let model = settings
+            .model
+            .unwrap_or_else(|| DEFAULT_MODEL_ALIAS.to_string());
+        let permission_mode = settings.permission_mode;
+        let max_thinking_tokens = settings.max_thinking_tokens;
+        debug!(?overrides, "Applying query overrides");
+
+        if let Some(new_model) = overrides.model {
+            self.internal.set_model(new_model).await?;
+            self.override_restore
+                .get_or_insert_with(Default::default)
+                .model = Some(model);
+        }
+        if let Some(mode) = overrides.permission_mode {
+            self.internal.set_permission_mode(mode).await?;
+            self.override_restore
+                .get_or_insert_with(Default::default)
+                .permission_mode = Some(permission_mode);
+        }
+        if let Some(tokens) = overrides.max_thinking_tokens {
+            self.internal.set_max_thinking_tokens(Some(tokens)).await?;
+            self.override_restore
+                .get_or_insert_with(Default::default)
+                .max_thinking_tokens = Some(max_thinking_tokens);
+        }
+        Ok(())
+    }
+
+    /// Restore the session values replaced by the last query's overrides.
+    async fn restore_query_overrides(&mut self) -> Result<()> {
+        let Some(restore) = self.override_restore.take() else {
+            return Ok(());
+        };
+        debug!("Restoring query overrides");
+
+        if let Some(model) = restore.model {
+            self.internal.set_model(model).await?;
+        }
+        if let Some(mode) = restore.permission_mode {
+            self.internal.set_permission_mode(mode).await?;
+        }
+        if let Some(tokens) = restore.max_thinking_tokens {
+            self.internal.set_max_thinking_tokens(tokens).await?;
+        }
+        Ok(())
+    }
+
+    /// Run interceptors and send a prompt to the CLI.
+    async fn intercept_and_send(&mut self, prompt: &str) -> Result<()> {
+        #[cfg(feature = "tokens")]
+        crate::tokens::warn_if_oversized(prompt, self.internal.options());
+        debug!(tags = ?self.tags(), "Sending query");
+
+        if self.interceptors.is_empty() {
+            self.last_prompt = Some(prompt.to_string());
+            return self.internal.send_message(prompt).await;
+        }
+
+        let mut prompt = prompt.to_string();
+        let options = self.internal.options_mut();
+        let (model, permission_mode) = (options.model.clone(), options.permission_mode);
+        for interceptor in &self.interceptors {
+            interceptor.before_query(&mut prompt, options)?;
+        }
+
+        // Apply runtime-changeable options to the live session
+        let (new_model, new_permission_mode) = (options.model.clone(), options.permission_mode);
+        if self.internal.is_connected() {
+            if new_model != model {
+                if let Some(model) = new_model {
+                    self.internal.set_model(model).await?;
                 }
-                Message::Result(result) => {
-                    return Ok((response_parts.concat(), result));
+            }
+            if new_permission_mode != permission_mode {
+                if let Some(mode) = new_permission_mode {
+                    self.internal.set_permission_mode(mode).await?;
                 }
-                _ => {}
             }
         }
 
-        Err(ClaudeSDKError::internal("Connection closed without result"))
+        self.internal.send_message(&prompt).await?;
+        self.last_prompt = Some(prompt);
+        Ok(())
     }
 
-    /// Interrupt the current operation.
+    /// Send the last prompt again after a delay if the response failed with
+    /// an error [`turn_retry`](ClaudeAgentOptions::turn_retry) covers and
+    /// retries are left.
+    ///
+    /// Returns whether the prompt was sent again.
+    async fn retry_turn(
+        &mut self,
+        error: Option<(AssistantMessageError, String)>,
+        retries: &mut u32,
+    ) -> Result<bool> {
+        let (Some(retry), Some((error, message))) =
+            (self.internal.options().turn_retry.clone(), error)
+        else {
+            return Ok(false);
+        };
+        if !retry.errors.contains(&error) || *retries >= retry.max_retries {
+            return Ok(false);
+        }
+        let Some(prompt) = self.last_prompt.clone() else {
+            return Ok(false);
+        };
+
+        *retries += 1;
+        let event = TurnRetryEvent {
+            attempt: *retries,
+            error,
+            message,
+            delay: retry.delay(*retries),
+        };
+        warn!(
+            attempt = event.attempt,
+            error = ?event.error,
+            "Turn failed, retrying in {:?}: {}",
+            event.delay,
+            event.message
+        );
+        if let Some(ref callback) = retry.on_retry {
+            callback(&event);
+        }
+        tokio::time::sleep(event.delay).await;
+        self.internal.send_message(&prompt).await?;
+        Ok(true)
+    }
+
+    /// Run the [`on_auth_required`](ClaudeAgentOptions::on_auth_required)
+    /// callback and, once it reports new credentials, restart the session so
+    /// the CLI picks them up and send the last prompt again.
+    ///
+    /// Returns whether the prompt was sent again.
+    async fn retry_after_auth(&mut self, instructions: &str) -> Result<bool> {
+        let Some(callback) = self.internal.options().on_auth_required.clone() else {
+            return Ok(false);
+        };
+        warn!("CLI needs to be logged in again: {}", instructions);
+        if !callback(instructions.to_string()).await {
+            return Ok(false);
+        }
+
+        debug!("Restarting session with new credentials");
+        self.restart_session(self.env_overlay.clone()).await?;
+        let Some(prompt) = self.last_prompt.clone() else {
+            return Ok(false);
+        };
+        self.internal.send_message(&prompt).await?;
+        Ok(true)
+    }
+
+    /// Get a stream of messages from the current query.
     ///
-    /// Sends an interrupt signal to Claude, stopping the current response.
+    /// Returns a stream that yields messages as they are received from
+    /// Claude. The stream ends when a result message is received.
     ///
     /// # Examples
     ///
     /// ```rust,no_run
-    /// use claude_agents_sdk::ClaudeClient;
-    /// use tokio::time::{timeout, Duration};
+    /// use claude_agents_sdk::{ClaudeClient, Message};
+    /// use tokio_stream::StreamExt;
     ///
     /// #[tokio::main]
     /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///     let mut client = ClaudeClient::new(None);
     ///     client.connect().await?;
-    ///     client.query("Write a very long story").await?;
+    ///     client.query("Tell me a joke").await?;
     ///
-    ///     // Interrupt after 5 seconds
-    ///     tokio::time::sleep(Duration::from_secs(5)).await;
-    ///     client.interrupt().await?;
+    ///     while let Some(msg) = client.receive_messages().next().await {
+    ///         match msg? {
+    ///             Message::Assistant(asst) => println!("{}", asst.text()),
+    ///             Message::Result(_) => break,
+    ///             _ => {}
+    ///         }
+    ///     }
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn interrupt(&self) -> Result<()> {
-        self.internal.interrupt().await
+    pub fn receive_messages(&mut self) -> impl Stream<Item = Result<Message>> + '_ {
+        let Self {
+            internal,
+            message_rx,
+            interceptors,
+            ..
+        } = self;
+        let tags = &internal.options().session_tags;
+        futures::stream::poll_fn(move |cx| {
+            if let Some(ref mut rx) = message_rx {
+                let poll = Pin::new(rx).poll_recv(cx);
+                if let std::task::Poll::Ready(Some(Ok(Message::Result(ref result)))) = poll {
+                    notify_result(interceptors, tags, result);
+                }
+                poll
+            } else {
+                std::task::Poll::Ready(None)
+            }
+        })
     }
 
-    /// Change the permission mode for the session.
-    ///
-    /// # Arguments
+    /// Get a stream of all messages, each wrapped in a
+    /// [`MessageEnvelope`](crate::MessageEnvelope) with the turn it belongs
+    /// to and the prompt that started it.
+    ///
+    /// Turns are counted across calls, from the first response read through
+    /// envelopes. See the [`envelope`](crate::envelope) module.
+    pub fn receive_envelopes(&mut self) -> impl Stream<Item = Result<crate::MessageEnvelope>> + '_ {
+        let Self {
+            internal,
+            message_rx,
+            interceptors,
+            envelopes,
+            ..
+        } = self;
+        let tags = &internal.options().session_tags;
+        futures::stream::poll_fn(move |cx| {
+            let Some(ref mut rx) = message_rx else {
+                return Poll::Ready(None);
+            };
+            let poll = Pin::new(rx).poll_recv(cx);
+            if let Poll::Ready(Some(Ok(Message::Result(ref result)))) = poll {
+                notify_result(interceptors, tags, result);
+            }
+            poll.map(|message| message.map(|message| message.map(|m| envelopes.wrap(m, tags))))
+        })
+    }
+
+    /// Get a stream of the messages of the current query's response.
     ///
-    /// * `mode` - The new permission mode
+    /// Yields every message up to and including the [`ResultMessage`], then
+    /// ends. Equivalent to `receive_response()` in the Python SDK; see
+    /// [`receive_response`](Self::receive_response) to collect the response
+    /// text instead.
     ///
     /// # Examples
     ///
     /// ```rust,no_run
-    /// use claude_agents_sdk::{ClaudeClient, PermissionMode};
+    /// use claude_agents_sdk::{ClaudeClient, Message};
+    /// use tokio_stream::StreamExt;
     ///
     /// #[tokio::main]
     /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///     let mut client = ClaudeClient::new(None);
     ///     client.connect().await?;
+    ///     client.query("Tell me a joke").await?;
     ///
-    ///     // Switch to accept edits mode
-    ///     client.set_permission_mode(PermissionMode::AcceptEdits).await?;
+    ///     let mut messages = Box::pin(client.receive_response_messages());
+    ///     while let Some(msg) = messages.next().await {
+    ///         if let Message::Assistant(asst) = msg? {
+    ///             println!("{}", asst.text());
+    ///         }
+    ///     }
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn set_permission_mode(&self, mode: PermissionMode) -> Result<()> {
-        self.internal.set_permission_mode(mode).await
+    pub fn receive_response_messages(&mut self) -> impl Stream<Item = Result<Message>> + '_ {
+        let mut done = false;
+        let mut messages = Box::pin(self.receive_messages());
+        futures::stream::poll_fn(move |cx| {
+            if done {
+                return std::task::Poll::Ready(None);
+            }
+            let poll = messages.as_mut().poll_next(cx);
+            if let std::task::Poll::Ready(Some(Ok(Message::Result(_)))) = poll {
+                done = true;
+            }
+            poll
+        })
     }
 
-    /// Change the model for the session.
+    /// Receive the complete response for the current query.
     ///
-    /// # Arguments
+    /// Collects all messages until a result message is received and returns
+    /// the combined response text along with the result metadata.
     ///
-    /// * `model` - The new model identifier
+    /// If the CLI reports that it needs to be logged in again, this fails
+    /// with [`AuthRequired`](ClaudeSDKError::AuthRequired) once the response
+    /// has ended, unless the
+    /// [`on_auth_required`](ClaudeAgentOptions::on_auth_required) callback
+    /// provides new credentials, in which case the session is restarted and
+    /// the response to the prompt sent again is returned instead. Turns that
+    /// fail with a transient API error are retried the same way with
+    /// [`turn_retry`](ClaudeAgentOptions::turn_retry).
+    ///
+    /// # Returns
+    ///
+    /// A tuple of (response_text, result_message).
     ///
     /// # Examples
     ///
@@ -326,9 +1032,409 @@ impl ClaudeClient {
     /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///     let mut client = ClaudeClient::new(None);
     ///     client.connect().await?;
+    ///     client.query("What is 2 + 2?").await?;
     ///
-    ///     // Switch to a different model
-    ///     client.set_model("claude-3-opus").await?;
+    ///     let (response, result) = client.receive_response().await?;
+    ///     println!("Response: {}", response);
+    ///     println!("Turns: {}", result.num_turns);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn receive_response(&mut self) -> Result<(String, ResultMessage)> {
+        let mut retries = 0;
+        loop {
+            let mut response = CollectedResponse::default();
+
+            match collect_response(
+                &mut self.message_rx,
+                &self.interceptors,
+                &self.internal.options().session_tags,
+                &mut response,
+            )
+            .await
+            {
+                Ok(Some(result)) => {
+                    if self.retry_turn(response.error, &mut retries).await? {
+                        continue;
+                    }
+                    self.settle_after_response().await;
+                    return Ok((response.parts.concat(), result));
+                }
+                Ok(None) => {
+                    return Err(ClaudeSDKError::internal("Connection closed without result"))
+                }
+                Err(ClaudeSDKError::AuthRequired { instructions }) => {
+                    if !self.retry_after_auth(&instructions).await? {
+                        self.settle_after_response().await;
+                        return Err(ClaudeSDKError::auth_required(instructions));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Receive the current query's response as a stream of text, alongside
+    /// a future resolving to its [`ResultMessage`].
+    ///
+    /// The stream yields each assistant message's text as it arrives, or
+    /// each text delta with
+    /// [`include_partial_messages`](ClaudeAgentOptions::include_partial_messages),
+    /// and ends with the response. Either half can be awaited first or
+    /// both together: whichever is polled reads the messages, and text the
+    /// stream hasn't taken yet is kept for it. The future fails like
+    /// [`receive_response`](Self::receive_response) when the response ends
+    /// without a result, and with
+    /// [`AuthRequired`](ClaudeSDKError::AuthRequired) when the CLI needs to
+    /// be logged in again; the prompt isn't retried here.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    /// use tokio_stream::StreamExt;
+    ///
+    /// # async fn example(client: &mut ClaudeClient) -> claude_agents_sdk::Result<()> {
+    /// client.query("Write a haiku about Rust").await?;
+    ///
+    /// let (mut text, result) = client.stream_response();
+    /// while let Some(chunk) = text.next().await {
+    ///     print!("{}", chunk);
+    /// }
+    /// let result = result.await?;
+    /// println!("\n(cost: {:?})", result.total_cost_usd);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stream_response(
+        &mut self,
+    ) -> (
+        impl Stream<Item = String> + Unpin + Send + '_,
+        impl Future<Output = Result<ResultMessage>> + Send + '_,
+    ) {
+        let partial = self.internal.options().include_partial_messages;
+        let driver = Arc::new(std::sync::Mutex::new(ResponseDriver {
+            client: Some(self),
+            partial,
+            auth_challenge: None,
+            text: VecDeque::new(),
+            outcome: None,
+            finished: false,
+            text_waker: None,
+            result_waker: None,
+        }));
+        let text = ResponseText {
+            driver: Arc::clone(&driver),
+        };
+        let result = async move {
+            let outcome = futures::future::poll_fn(|cx| {
+                let mut state = lock_driver(&driver);
+                loop {
+                    if state.finished {
+                        return Poll::Ready(state.outcome.take().unwrap_or_else(|| {
+                            Err(ClaudeSDKError::internal("Response already received"))
+                        }));
+                    }
+                    if state.drive(cx).is_pending() {
+                        state.result_waker = Some(cx.waker().clone());
+                        return Poll::Pending;
+                    }
+                }
+            })
+            .await;
+            let client = lock_driver(&driver).client.take();
+            if let (Ok(_), Some(client)) = (&outcome, client) {
+                client.settle_after_response().await;
+            }
+            outcome
+        };
+        (text, result)
+    }
+
+    /// Receive the response for the current query, interrupting it if it
+    /// takes longer than `deadline`.
+    ///
+    /// If the deadline expires, an interrupt is sent and the client waits
+    /// briefly for the CLI's terminal result message. The text received so
+    /// far is returned with [`DeadlineResponse::interrupted`] set, leaving the
+    /// session ready for the next query.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///     client.query("Write a very long story").await?;
+    ///
+    ///     let response = client
+    ///         .receive_response_with_deadline(Duration::from_secs(30))
+    ///         .await?;
+    ///     if response.interrupted {
+    ///         println!("Partial: {}", response.text);
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn receive_response_with_deadline(
+        &mut self,
+        deadline: Duration,
+    ) -> Result<DeadlineResponse> {
+        let mut response = CollectedResponse::default();
+
+        let completed = tokio::time::timeout(
+            deadline,
+            collect_response(
+                &mut self.message_rx,
+                &self.interceptors,
+                &self.internal.options().session_tags,
+                &mut response,
+            ),
+        )
+        .await;
+
+        if let Ok(result) = completed {
+            return match result? {
+                Some(result) => {
+                    self.settle_after_response().await;
+                    Ok(DeadlineResponse {
+                        text: response.parts.concat(),
+                        result: Some(result),
+                        interrupted: false,
+                    })
+                }
+                None => Err(ClaudeSDKError::internal("Connection closed without result")),
+            };
+        }
+
+        debug!("Response deadline of {:?} expired, interrupting", deadline);
+        if let Err(e) = self.internal.interrupt().await {
+            warn!("Failed to interrupt after deadline: {}", e);
+        }
+
+        // Drain until the CLI acknowledges with its terminal result message
+        let result = match tokio::time::timeout(
+            INTERRUPT_DRAIN_TIMEOUT,
+            collect_response(
+                &mut self.message_rx,
+                &self.interceptors,
+                &self.internal.options().session_tags,
+                &mut response,
+            ),
+        )
+        .await
+        {
+            Ok(result) => result?,
+            Err(_) => {
+                warn!("No result message received after interrupt");
+                None
+            }
+        };
+
+        if result.is_some() {
+            self.settle_after_response().await;
+        }
+        Ok(DeadlineResponse {
+            text: response.parts.concat(),
+            result,
+            interrupted: true,
+        })
+    }
+
+    /// Interrupt the current operation.
+    ///
+    /// Sends an interrupt signal to Claude, stopping the current response.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    /// use tokio::time::{timeout, Duration};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///     client.query("Write a very long story").await?;
+    ///
+    ///     // Interrupt after 5 seconds
+    ///     tokio::time::sleep(Duration::from_secs(5)).await;
+    ///     client.interrupt().await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn interrupt(&self) -> Result<()> {
+        self.internal.interrupt().await
+    }
+
+    /// Change the permission mode for the session.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The new permission mode
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::{ClaudeClient, PermissionMode};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///
+    ///     // Switch to accept edits mode
+    ///     client.set_permission_mode(PermissionMode::AcceptEdits).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn set_permission_mode(&self, mode: PermissionMode) -> Result<()> {
+        self.internal.set_permission_mode(mode).await
+    }
+
+    /// Temporarily switch to a more permissive mode for a specific task.
+    ///
+    /// Records the switch, with `reason`, as an audit event (tracing target
+    /// `claude_agents_sdk::audit`) and remembers the previous mode so it can
+    /// be restored by [`revert_permissions`](Self::revert_permissions) or
+    /// automatically according to `revert`. Automatic reverts take effect
+    /// as the response that reaches the limit ends, however its messages
+    /// are read, or before the next query is sent. The previous mode is the
+    /// one in effect, including changes made at runtime; escalating again
+    /// while an escalation is active keeps the original previous mode.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::{ClaudeClient, EscalationRevert, PermissionMode};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///
+    ///     client
+    ///         .escalate_permissions(
+    ///             PermissionMode::AcceptEdits,
+    ///             "Apply the generated migration",
+    ///             EscalationRevert::AfterTurns(1),
+    ///         )
+    ///         .await?;
+    ///     client.query("Apply the migration in db/").await?;
+    ///     // The previous mode is restored once this response arrives
+    ///     let _ = client.receive_response().await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn escalate_permissions(
+        &mut self,
+        mode: PermissionMode,
+        reason: impl Into<String>,
+        revert: EscalationRevert,
+    ) -> Result<()> {
+        let reason = reason.into();
+        let previous = match self.internal.escalation().modes() {
+            Some((_, previous)) => previous,
+            None => self.internal.settings().permission_mode,
+        };
+
+        self.internal.set_permission_mode(mode).await?;
+        info!(
+            target: AUDIT_TARGET,
+            from = ?previous,
+            to = ?mode,
+            reason = %reason,
+            revert = ?revert,
+            tags = ?self.tags(),
+            "Permission mode escalated"
+        );
+
+        self.internal.escalation().set(Escalation {
+            mode,
+            previous,
+            revert,
+            started: Instant::now(),
+            turns: 0,
+            tags: self.tags().clone(),
+        });
+        Ok(())
+    }
+
+    /// Restore the permission mode active before
+    /// [`escalate_permissions`](Self::escalate_permissions).
+    ///
+    /// Returns `false` if no escalation was active.
+    pub async fn revert_permissions(&mut self) -> Result<bool> {
+        let Some(escalation) = self.internal.escalation().take() else {
+            return Ok(false);
+        };
+        self.revert(escalation, "manual").await?;
+        Ok(true)
+    }
+
+    /// Check whether a permission escalation is active.
+    pub fn is_escalated(&self) -> bool {
+        self.internal.escalation().is_active()
+    }
+
+    /// Revert an escalation whose limit has been reached.
+    async fn revert_expired_escalation(&mut self) -> Result<()> {
+        match self.internal.escalation().take_expired() {
+            Some(escalation) => self.revert(escalation, "expired").await,
+            None => Ok(()),
+        }
+    }
+
+    /// Restore the mode active before `escalation`, keeping it if that
+    /// fails.
+    async fn revert(&mut self, escalation: Escalation, trigger: &str) -> Result<()> {
+        if let Err(e) = self.internal.set_permission_mode(escalation.previous).await {
+            self.internal.escalation().restore(escalation);
+            return Err(e);
+        }
+        escalation.log_reverted(trigger);
+        Ok(())
+    }
+
+    /// Restore query overrides and revert an expired escalation once a
+    /// response is complete, logging failures so the response is still
+    /// returned.
+    async fn settle_after_response(&mut self) {
+        if let Err(e) = self.restore_query_overrides().await {
+            warn!("Failed to restore query overrides: {}", e);
+        }
+        if let Err(e) = self.revert_expired_escalation().await {
+            warn!("Failed to revert permission escalation: {}", e);
+        }
+    }
+
+    /// Change the model for the session.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The new model identifier
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///
+    ///     // Switch to a different model
+    ///     client.set_model("claude-3-opus").await?;
     ///
     ///     Ok(())
     /// }
@@ -337,6 +1443,15 @@ impl ClaudeClient {
         self.internal.set_model(model).await
     }
 
+    /// Change the maximum thinking tokens for the session.
+    ///
+    /// `None` restores the CLI's default thinking budget.
+    pub async fn set_max_thinking_tokens(&self, max_thinking_tokens: Option<u32>) -> Result<()> {
+        self.internal
+            .set_max_thinking_tokens(max_thinking_tokens)
+            .await
+    }
+
     /// Rewind files to a specific user message.
     ///
     /// This is only available when file checkpointing is enabled.
@@ -399,14 +1514,22 @@ impl ClaudeClient {
         self.internal.get_server_info().await
     }
 
-    /// Get current MCP server connection status (streaming mode only).
-    pub async fn get_mcp_status(&self) -> Result<serde_json::Value> {
-        self.internal.get_mcp_status().await
+    /// Get the slash commands the CLI offers in this session.
+    ///
+    /// Read from the server info; empty when not connected or when the CLI
+    /// doesn't list its commands.
+    pub async fn commands(&self) -> Vec<SlashCommand> {
+        self.get_server_info()
+            .await
+            .and_then(|info| info.get("commands").cloned())
+            .and_then(|commands| serde_json::from_value(commands).ok())
+            .unwrap_or_default()
     }
 
-    /// Disconnect from the Claude CLI.
+    /// Run a slash command such as `/compact`, sending it as the next query.
     ///
-    /// Gracefully closes the connection to the CLI process.
+    /// `command` may be given with or without the leading `/`. The response
+    /// is read like any other query's.
     ///
     /// # Examples
     ///
@@ -418,75 +1541,672 @@ impl ClaudeClient {
     ///     let mut client = ClaudeClient::new(None);
     ///     client.connect().await?;
     ///
-    ///     // Use the client...
+    ///     for command in client.commands().await {
+    ///         println!("/{} {} - {}", command.name, command.args, command.description);
+    ///     }
+    ///
+    ///     client.run_command("/compact", "keep the API notes").await?;
+    ///     client.receive_response().await?;
     ///
-    ///     client.disconnect().await?;
     ///     Ok(())
     /// }
     /// ```
-    pub async fn disconnect(&mut self) -> Result<()> {
-        self.message_rx = None;
-        self.internal.disconnect().await
+    pub async fn run_command(&mut self, command: &str, args: &str) -> Result<()> {
+        let invocation = SlashCommand::invocation(command, args)?;
+        self.query(&invocation).await
     }
 
-    /// Check if the client is connected.
-    pub fn is_connected(&self) -> bool {
-        self.internal.is_connected()
+    /// Subscribe to the messages received from now on, alongside the main
+    /// message stream.
+    ///
+    /// Each receiver gets its own copy of every message, so a logger, a UI
+    /// and the main loop can all observe the session. Receivers never slow
+    /// down the session: each buffers up to
+    /// [`subscriber_capacity`](ClaudeAgentOptions::subscriber_capacity)
+    /// messages, and one that falls further behind loses the oldest ones,
+    /// then gets [`RecvError::Lagged`](tokio::sync::broadcast::error::RecvError::Lagged)
+    /// with the number skipped before resuming. Errors are only delivered on
+    /// the main stream. Receivers are closed when the session ends; after a
+    /// reconnect, subscribe again.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    /// use tokio::sync::broadcast::error::RecvError;
+    ///
+    /// # async fn example(client: &ClaudeClient) -> claude_agents_sdk::Result<()> {
+    /// let mut messages = client.subscribe()?;
+    /// tokio::spawn(async move {
+    ///     loop {
+    ///         match messages.recv().await {
+    ///             Ok(message) => println!("{:?}", message),
+    ///             Err(RecvError::Lagged(skipped)) => eprintln!("skipped {} messages", skipped),
+    ///             Err(RecvError::Closed) => break,
+    ///         }
+    ///     }
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn subscribe(&self) -> Result<tokio::sync::broadcast::Receiver<Arc<Message>>> {
+        self.internal.subscribe()
     }
-}
 
-/// Builder for creating a [`ClaudeClient`] with configuration.
-///
-/// Provides a fluent API for configuring the client before connecting.
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// use claude_agents_sdk::{ClaudeClientBuilder, PermissionMode, PermissionResult};
-///
-/// #[tokio::main]
-/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
-///     let mut client = ClaudeClientBuilder::new()
-///         .model("claude-3-sonnet")
-///         .permission_mode(PermissionMode::AcceptEdits)
-///         .max_turns(10)
-///         .can_use_tool(|tool, input, _| async move {
-///             println!("Tool: {} Input: {:?}", tool, input);
-///             PermissionResult::allow()
-///         })
-///         .build();
-///
-///     client.connect().await?;
-///     Ok(())
-/// }
-/// ```
-pub struct ClaudeClientBuilder {
-    options: ClaudeAgentOptions,
-}
+    /// Watch what the session is busy with, as a [`Progress`](crate::Progress)
+    /// phase derived from its messages.
+    ///
+    /// Works before connecting and keeps reporting across reconnects. The
+    /// receiver holds the current phase only, so a UI reading it never
+    /// falls behind. See the [`progress`](crate::progress) module for an
+    /// example.
+    pub fn progress(&self) -> tokio::sync::watch::Receiver<crate::Progress> {
+        self.internal.progress()
+    }
 
-impl ClaudeClientBuilder {
-    /// Create a new builder with default options.
-    pub fn new() -> Self {
-        Self {
-            options: ClaudeAgentOptions::new(),
-        }
+    /// How full the context window is, as of the last API call of the
+    /// conversation, or `None` when not connected.
+    ///
+    /// The size of the window is taken from the
+    /// [`context_policy`](ClaudeAgentOptions::context_policy), if any.
+    pub fn context_usage(&self) -> Option<ContextUsage> {
+        self.internal.context_usage()
     }
 
-    /// Set the model to use.
-    pub fn model(mut self, model: impl Into<String>) -> Self {
-        self.options.model = Some(model.into());
-        self
+    /// Receive the CLI's stderr output as typed
+    /// [`Diagnostic`](crate::Diagnostic)s at or above `min_severity`.
+    ///
+    /// Works before connecting and keeps receiving across reconnects.
+    /// Reading stderr never waits for receivers: each buffers up to 256
+    /// diagnostics, and one that falls further behind loses the oldest
+    /// ones, then gets
+    /// [`RecvError::Lagged`](tokio::sync::broadcast::error::RecvError::Lagged).
+    /// The [`stderr`](ClaudeAgentOptions::stderr) callback still gets every
+    /// raw line.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::{ClaudeClient, DiagnosticSeverity};
+    /// use tokio::sync::broadcast::error::RecvError;
+    ///
+    /// # async fn example() -> claude_agents_sdk::Result<()> {
+    /// let mut client = ClaudeClient::new(None);
+    /// let mut warnings = client.diagnostics(DiagnosticSeverity::Warning);
+    /// tokio::spawn(async move {
+    ///     loop {
+    ///         match warnings.recv().await {
+    ///             Ok(diagnostic) => eprintln!("{:?}: {}", diagnostic.severity, diagnostic.message),
+    ///             Err(RecvError::Lagged(skipped)) => eprintln!("skipped {} diagnostics", skipped),
+    ///             Err(RecvError::Closed) => break,
+    ///         }
+    ///     }
+    /// });
+    /// client.connect().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn diagnostics(
+        &self,
+        min_severity: crate::DiagnosticSeverity,
+    ) -> crate::DiagnosticReceiver {
+        self.internal.diagnostics(min_severity)
     }
 
-    /// Set the system prompt.
-    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
-        self.options.system_prompt = Some(SystemPromptConfig::Text(prompt.into()));
-        self
+    /// Stop delivering messages until [`resume`](Self::resume), without
+    /// buffering an unbounded backlog.
+    ///
+    /// For a consumer that has to stall, e.g. while waiting on a rate
+    /// limited downstream. The session keeps running: control requests
+    /// (permission prompts, hooks, SDK MCP tool calls) and responses to
+    /// [`interrupt`](Self::interrupt) and the other control methods keep
+    /// flowing while a small number of messages is held back. Once that
+    /// buffer is full, reading from the CLI stops, so the OS pipe fills up
+    /// and the CLI blocks on its next write. From then on control requests
+    /// wait too: a pending permission prompt is only answered after
+    /// resuming, and control methods called meanwhile may time out.
+    /// Subscribers see messages when they are delivered.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    ///
+    /// # async fn example(client: &ClaudeClient) -> claude_agents_sdk::Result<()> {
+    /// client.pause()?;
+    /// // ... wait for the downstream to catch up ...
+    /// client.resume()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pause(&self) -> Result<()> {
+        self.internal.pause()
     }
 
-    /// Set the permission mode.
-    pub fn permission_mode(mut self, mode: PermissionMode) -> Self {
-        self.options.permission_mode = Some(mode);
+    /// Deliver the messages held back by [`pause`](Self::pause), in order,
+    /// and continue reading from the CLI.
+    pub fn resume(&self) -> Result<()> {
+        self.internal.resume()
+    }
+
+    /// Whether message delivery is paused.
+    pub fn is_paused(&self) -> bool {
+        self.internal.is_paused()
+    }
+
+    /// Get up to `n` of the most recently received messages, oldest first.
+    ///
+    /// Messages are retained by the background reader regardless of whether
+    /// they have been consumed, so observers attaching mid-session can see
+    /// recent context. Requires
+    /// [`message_history_size`](ClaudeAgentOptions::message_history_size) to
+    /// be non-zero; otherwise an empty vector is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::{ClaudeClient, ClaudeAgentOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let options = ClaudeAgentOptions::new().with_message_history(50);
+    ///     let mut client = ClaudeClient::new(Some(options));
+    ///     client.connect().await?;
+    ///
+    ///     for msg in client.recent_messages(10).await {
+    ///         println!("{:?}", msg);
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn recent_messages(&self, n: usize) -> Vec<Message> {
+        self.internal.recent_messages(n).await
+    }
+
+    /// Render the retained message history as a [`Transcript`](crate::Transcript),
+    /// e.g. for [`summarize_session`](crate::summarize_session).
+    ///
+    /// Covers the messages kept by
+    /// [`message_history_size`](ClaudeAgentOptions::message_history_size);
+    /// the transcript is empty when history is disabled.
+    pub async fn transcript(&self) -> crate::Transcript {
+        crate::Transcript::from(&self.recent_messages(usize::MAX).await)
+    }
+
+    /// Get the tool uses the CLI started whose results haven't arrived yet,
+    /// oldest first.
+    ///
+    /// Derived from the message stream: a tool use is pending from the
+    /// assistant message requesting it until the matching tool result, or
+    /// until the turn's result message. Supervisors can use the
+    /// [`age`](PendingToolUse::age) of each entry to detect stuck tools and
+    /// [`interrupt`](Self::interrupt) the turn.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use claude_agents_sdk::ClaudeClient;
+    /// # use std::time::Duration;
+    /// # async fn example(client: &ClaudeClient) -> claude_agents_sdk::Result<()> {
+    /// let stuck = client
+    ///     .pending_tool_uses()
+    ///     .await
+    ///     .into_iter()
+    ///     .any(|tool| tool.age() > Duration::from_secs(300));
+    /// if stuck {
+    ///     client.interrupt().await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn pending_tool_uses(&self) -> Vec<PendingToolUse> {
+        self.internal.pending_tool_uses().await
+    }
+
+    /// Get a summary of the protocol frames the SDK dropped or couldn't
+    /// handle during this session.
+    ///
+    /// Useful for spotting protocol changes after a CLI upgrade: the report
+    /// lists unknown control request subtypes, unmatched control responses
+    /// and unknown message types, with counts. How each anomaly is surfaced
+    /// as it happens is controlled by
+    /// [`protocol_strictness`](ClaudeAgentOptions::protocol_strictness).
+    pub async fn protocol_anomalies(&self) -> ProtocolAnomalyReport {
+        self.internal.protocol_anomalies().await
+    }
+
+    /// Get the number and sizes of the frames written to the CLI during
+    /// this session, and how many were over the
+    /// [`outbound_frame_limit`](ClaudeAgentOptions::outbound_frame_limit).
+    ///
+    /// Returns empty stats when not connected.
+    pub fn outbound_frame_stats(&self) -> OutboundFrameStats {
+        self.internal.outbound_frame_stats()
+    }
+
+    /// Get the configuration the session runs with: SDK and CLI versions,
+    /// model, permission mode, tools and working directory.
+    ///
+    /// Starts from the options at connect and is updated with what the CLI
+    /// reports once the session initializes. The same manifest is logged to
+    /// the `claude_agents_sdk::audit` tracing target. Returns `None` when
+    /// not connected.
+    ///
+    /// Compare [`SessionManifest::fingerprint`] values to tell whether two
+    /// sessions ran with the same configuration.
+    pub async fn session_manifest(&self) -> Option<SessionManifest> {
+        self.internal.session_manifest().await
+    }
+
+    /// Export the session for resuming on another machine (see
+    /// [`SessionBundle`](crate::SessionBundle)).
+    ///
+    /// The transcript covers the messages kept by
+    /// [`message_history_size`](ClaudeAgentOptions::message_history_size).
+    /// Fails when not connected or before the CLI reports the session ID.
+    pub async fn export_session(&self) -> Result<crate::SessionBundle> {
+        let manifest = self
+            .session_manifest()
+            .await
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?;
+        let file_checkpointing = self.internal.options().enable_file_checkpointing;
+        crate::SessionBundle::new(
+            manifest,
+            self.recent_messages(usize::MAX).await,
+            file_checkpointing,
+        )
+    }
+
+    /// Create a client resuming an exported session, with `options` as the
+    /// base (see [`SessionBundle::resume_options`](crate::SessionBundle::resume_options)). Call
+    /// [`connect`](Self::connect) to start it.
+    pub fn import_session(bundle: &crate::SessionBundle, options: ClaudeAgentOptions) -> Self {
+        Self::new(Some(bundle.resume_options(options)))
+    }
+
+    /// Branch the conversation before the prompt `user_message_id` (see
+    /// [`BranchPoint`](crate::BranchPoint)).
+    ///
+    /// Returns a connected client whose conversation is forked from this
+    /// session just before that prompt. It works in a temporary copy of
+    /// the working directory with the files as they were when the prompt
+    /// was sent (see [`branch_dir`](Self::branch_dir)); this client's
+    /// conversation and files are unchanged. The branch inherits this
+    /// client's options and interceptors, and its
+    /// [`lineage`](Self::lineage) extends this one's.
+    ///
+    /// The whole working directory is read into memory to copy it, so keep
+    /// sessions that branch in small directories.
+    ///
+    /// Fails unless file checkpointing is enabled and the prompt is in the
+    /// [message history](ClaudeAgentOptions::message_history_size).
+    pub async fn branch_at(&self, user_message_id: &str) -> Result<ClaudeClient> {
+        let options = self.internal.options();
+        if !options.enable_file_checkpointing {
+            return Err(ClaudeSDKError::configuration(
+                "Branching needs 'enable_file_checkpointing'",
+            ));
+        }
+        let session_id = self
+            .session_manifest()
+            .await
+            .ok_or_else(|| ClaudeSDKError::cli_connection("Client not connected"))?
+            .session_id
+            .ok_or_else(|| {
+                ClaudeSDKError::configuration("Cannot branch before the CLI reports the session ID")
+            })?;
+        let transcript = self.recent_messages(usize::MAX).await;
+        // A resumed session started before this client saw it
+        let complete = transcript.len() < options.message_history_size
+            && options.resume.is_none()
+            && !options.continue_conversation;
+        let point = crate::BranchPoint::find(session_id, &transcript, user_message_id, complete)?;
+
+        // Rewind the files just long enough to copy them for the branch
+        let cwd = match options.cwd {
+            Some(ref cwd) => cwd.clone(),
+            None => std::env::current_dir()?,
+        };
+        let current = {
+            let cwd = cwd.clone();
+            workspace::blocking(move || TempWorkspace::copy_of(&cwd)).await?
+        };
+        let copied = match self.rewind_files(user_message_id).await {
+            Ok(()) => {
+                let cwd = cwd.clone();
+                workspace::blocking(move || TempWorkspace::copy_of(&cwd)).await
+            }
+            Err(e) => Err(e),
+        };
+        workspace::blocking(move || current.restore_to(&cwd)).await?;
+        let copied = copied?;
+
+        let branch_options = point.options(options.clone()).with_cwd(copied.path());
+        let mut branch = ClaudeClient::new(Some(branch_options));
+        branch.interceptors = self.interceptors.clone();
+        branch.lineage = self.lineage.clone();
+        branch.lineage.push(point);
+        branch.workspace = Some(copied);
+        branch.connect().await?;
+        info!(
+            target: AUDIT_TARGET,
+            user_message_id,
+            depth = branch.lineage.len(),
+            tags = ?self.tags(),
+            "Branched session"
+        );
+        Ok(branch)
+    }
+
+    /// Get the temporary working directory of a branch created by
+    /// [`branch_at`](Self::branch_at), removed when the client is dropped.
+    pub fn branch_dir(&self) -> Option<&std::path::Path> {
+        self.workspace.as_ref().map(|workspace| workspace.path())
+    }
+
+    /// Get the branch points that led to this session, oldest first. Empty
+    /// unless the client was created by [`branch_at`](Self::branch_at).
+    pub fn lineage(&self) -> &[crate::BranchPoint] {
+        &self.lineage
+    }
+
+    /// Get the session tags (see [`session_tags`](ClaudeAgentOptions::session_tags)).
+    pub fn tags(&self) -> &HashMap<String, String> {
+        &self.internal.options().session_tags
+    }
+
+    /// Set a session tag, replacing any previous value for `key`.
+    ///
+    /// Takes effect immediately, including mid-session; results received
+    /// afterwards are logged with the new tags.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::ClaudeClient;
+    ///
+    /// let mut client = ClaudeClient::new(None);
+    /// client.set_tag("tenant", "acme");
+    /// client.set_tag("job", "nightly-report");
+    /// assert_eq!(client.tags()["tenant"], "acme");
+    /// ```
+    pub fn set_tag(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.internal
+            .options_mut()
+            .session_tags
+            .insert(key.into(), value.into());
+    }
+
+    /// Remove a session tag, returning its previous value.
+    pub fn remove_tag(&mut self, key: &str) -> Option<String> {
+        self.internal.options_mut().session_tags.remove(key)
+    }
+
+    /// Get current MCP server connection status (streaming mode only).
+    pub async fn get_mcp_status(&self) -> Result<serde_json::Value> {
+        self.internal.get_mcp_status().await
+    }
+
+    /// Enable or disable the configured MCP server `name`.
+    ///
+    /// The running session is asked to start or stop the server. CLIs that
+    /// can't do that at runtime, and servers that were left out when the
+    /// CLI started, get the change by restarting the session, resuming the
+    /// conversation, so call this between responses. The change is kept in
+    /// [`disabled_mcp_servers`](ClaudeAgentOptions::disabled_mcp_servers)
+    /// for later connects. When not connected, only the options change.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClientBuilder;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClientBuilder::new()
+    ///         .mcp_http_server("github", "https://api.githubcopilot.com/mcp/")
+    ///         .build();
+    ///     client.connect().await?;
+    ///
+    ///     // Keep the agent away from GitHub for a while
+    ///     client.set_mcp_server_enabled("github", false).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn set_mcp_server_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let options = self.internal.options_mut();
+        let disabled = options.disabled_mcp_servers.clone();
+        options.disabled_mcp_servers.retain(|server| server != name);
+        if !enabled {
+            options.disabled_mcp_servers.push(name.to_string());
+        }
+        if let Err(e) = options.validate() {
+            options.disabled_mcp_servers = disabled;
+            return Err(e);
+        }
+        if !self.internal.is_connected() {
+            return Ok(());
+        }
+
+        match self.internal.toggle_mcp_server(name, enabled).await {
+            Err(ClaudeSDKError::ControlProtocol { message, .. }) => {
+                debug!(
+                    "CLI couldn't toggle MCP server '{}' ({}), restarting the session",
+                    name, message
+                );
+                self.restart_session(self.env_overlay.clone()).await
+            }
+            toggled => toggled,
+        }
+    }
+
+    /// Check that the session is responsive, returning the round-trip
+    /// latency of a lightweight control request.
+    ///
+    /// Meant for health checks of pooled or idle clients: fails if the
+    /// client is not connected, the CLI process died, or no answer arrives
+    /// within `timeout` (a [`Timeout`](ClaudeSDKError::Timeout) error),
+    /// which indicates a wedged protocol. Safe to call between queries; it
+    /// doesn't affect the conversation.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///
+    ///     let latency = client.ping(Duration::from_secs(5)).await?;
+    ///     println!("CLI answered in {:?}", latency);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn ping(&self, timeout: Duration) -> Result<Duration> {
+        self.internal.ping(timeout).await
+    }
+
+    /// Disconnect from the Claude CLI.
+    ///
+    /// Gracefully closes the connection to the CLI process.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///
+    ///     // Use the client...
+    ///
+    ///     client.disconnect().await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn disconnect(&mut self) -> Result<()> {
+        self.shutdown().await;
+        Ok(())
+    }
+
+    /// Disconnect from the Claude CLI, reporting how the shutdown went.
+    ///
+    /// Shutdown runs in a fixed order, each stage bounded by a timeout:
+    /// stop the protocol reader, close the CLI's stdin, wait for it to exit
+    /// (killing it if it doesn't), kill its process tree when
+    /// [`kill_descendants_on_drop`](ClaudeAgentOptions::kill_descendants_on_drop)
+    /// is set, then join the output readers. Every background task is joined
+    /// or aborted by the time this returns. The report is empty when not
+    /// connected.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///
+    ///     let report = client.shutdown().await;
+    ///     if report.forced_kill {
+    ///         eprintln!("CLI had to be killed after {:?}", report.duration());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn shutdown(&mut self) -> ShutdownReport {
+        self.message_rx = None;
+        self.env_overlay = None;
+        self.override_restore = None;
+        if let Some(escalation) = self.internal.escalation().take() {
+            // Not carried into the next connection
+            escalation.log_reverted("disconnect");
+        }
+        self.internal.discard_session_tmp_dir();
+        self.internal.shutdown().await
+    }
+
+    /// Get a temporary directory for this session, creating it on first
+    /// use.
+    ///
+    /// A blessed place for files a session produces or works on: spooled
+    /// tool output, exported transcripts, artifacts for the model's tools.
+    /// The directory is private to the current user and passed to the CLI
+    /// with `--add-dir` when the client connects, so call this before
+    /// [`connect`](Self::connect) for its tools to have access. It is
+    /// removed on [`disconnect`](Self::disconnect) or drop, unless
+    /// [`keep_session_tmp_dir`](ClaudeAgentOptions::keep_session_tmp_dir)
+    /// is set; a later call then creates a new one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut client = ClaudeClient::new(None);
+    /// let tmp = client.session_tmp_dir()?.to_path_buf();
+    /// std::fs::write(tmp.join("data.csv"), "id,total\n1,42\n")?;
+    /// client.connect().await?;
+    /// client
+    ///     .query(&format!("Summarize {}", tmp.join("data.csv").display()))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn session_tmp_dir(&mut self) -> Result<&std::path::Path> {
+        self.internal.session_tmp_dir()
+    }
+
+    /// Check if the client is connected.
+    ///
+    /// Returns `false` once the session has [failed](Self::is_failed).
+    pub fn is_connected(&self) -> bool {
+        self.internal.is_connected()
+    }
+
+    /// Check whether the session failed because the CLI process died or its
+    /// output could no longer be read.
+    ///
+    /// Pending and later control requests fail immediately with
+    /// [`ClaudeSDKError::CLIConnection`]. Call [`connect`](Self::connect) to
+    /// start a new session.
+    pub fn is_failed(&self) -> bool {
+        self.internal.failure().is_some()
+    }
+
+    /// Get the reason the session failed, if it has.
+    pub fn failure_reason(&self) -> Option<&str> {
+        self.internal.failure()
+    }
+}
+
+/// Builder for creating a [`ClaudeClient`] with configuration.
+///
+/// Provides a fluent API for configuring the client before connecting.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use claude_agents_sdk::{ClaudeClientBuilder, PermissionMode, PermissionResult};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let mut client = ClaudeClientBuilder::new()
+///         .model("claude-3-sonnet")
+///         .permission_mode(PermissionMode::AcceptEdits)
+///         .max_turns(10)
+///         .can_use_tool(|tool, input, _| async move {
+///             println!("Tool: {} Input: {:?}", tool, input);
+///             PermissionResult::allow()
+///         })
+///         .build();
+///
+///     client.connect().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct ClaudeClientBuilder {
+    options: ClaudeAgentOptions,
+    interceptors: Vec<Arc<dyn QueryInterceptor>>,
+    transport: Option<Box<dyn Transport>>,
+}
+
+impl ClaudeClientBuilder {
+    /// Create a new builder with default options.
+    pub fn new() -> Self {
+        Self {
+            options: ClaudeAgentOptions::new(),
+            interceptors: Vec::new(),
+            transport: None,
+        }
+    }
+
+    /// Set the model to use.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.options.model = Some(model.into());
+        self
+    }
+
+    /// Set the system prompt.
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.options.system_prompt = Some(SystemPromptConfig::Text(prompt.into()));
+        self
+    }
+
+    /// Set the permission mode.
+    pub fn permission_mode(mut self, mode: PermissionMode) -> Self {
+        self.options.permission_mode = Some(mode);
         self
     }
 
@@ -502,6 +2222,24 @@ impl ClaudeClientBuilder {
         self
     }
 
+    /// Interrupt any turn going over `budget`.
+    pub fn turn_budget(mut self, budget: TurnBudget) -> Self {
+        self.options.turn_budget = Some(budget);
+        self
+    }
+
+    /// Compact or warn as the context fills up.
+    pub fn context_policy(mut self, policy: ContextPolicy) -> Self {
+        self.options.context_policy = Some(policy);
+        self
+    }
+
+    /// Expand `${VAR}` references in the options when connecting.
+    pub fn expand_env(mut self) -> Self {
+        self.options.expand_env = true;
+        self
+    }
+
     /// Set the working directory.
     pub fn cwd(mut self, path: impl Into<std::path::PathBuf>) -> Self {
         self.options.cwd = Some(path.into());
@@ -518,33 +2256,210 @@ impl ClaudeClientBuilder {
         self
     }
 
+    /// Set a callback for when the CLI needs to be logged in again.
+    pub fn on_auth_required<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = bool> + Send + 'static,
+    {
+        self.options = self.options.with_on_auth_required(callback);
+        self
+    }
+
     /// Enable partial message streaming.
     pub fn include_partial_messages(mut self) -> Self {
         self.options.include_partial_messages = true;
         self
     }
 
-    /// Enable file checkpointing.
-    pub fn enable_file_checkpointing(mut self) -> Self {
-        self.options.enable_file_checkpointing = true;
+    /// Enable partial message streaming and synthesize assistant messages
+    /// the CLI doesn't deliver.
+    pub fn synthesize_partial_messages(mut self) -> Self {
+        self.options = self.options.with_synthesized_partial_messages();
         self
     }
 
-    /// Set allowed tools.
-    pub fn allowed_tools(mut self, tools: Vec<String>) -> Self {
-        self.options.allowed_tools = tools;
+    /// Enable partial message streaming with consecutive text deltas merged
+    /// into fewer stream events.
+    pub fn coalesce_partial_messages(mut self, coalescing: PartialCoalescing) -> Self {
+        self.options = self.options.with_partial_coalescing(coalescing);
         self
     }
 
-    /// Set disallowed tools.
-    pub fn disallowed_tools(mut self, tools: Vec<String>) -> Self {
-        self.options.disallowed_tools = tools;
+    /// Enable partial message streaming, dropping the oldest stream events
+    /// when more than `limit` wait for a slow consumer.
+    pub fn max_pending_partial_messages(mut self, limit: usize) -> Self {
+        self.options = self.options.with_max_pending_partial_messages(limit);
         self
     }
 
-    /// Build the client.
-    pub fn build(self) -> ClaudeClient {
-        ClaudeClient::new(Some(self.options))
+    /// Validate tool inputs against `registry` before permission callbacks.
+    pub fn tool_schemas(mut self, registry: ToolSchemaRegistry) -> Self {
+        self.options = self.options.with_tool_schemas(registry);
+        self
+    }
+
+    /// Kill the processes started by the CLI's tools when the session ends.
+    pub fn kill_descendants_on_drop(mut self) -> Self {
+        self.options.kill_descendants_on_drop = true;
+        self
+    }
+
+    /// Keep the session temp directory on disconnect.
+    pub fn keep_session_tmp_dir(mut self) -> Self {
+        self.options.keep_session_tmp_dir = true;
+        self
+    }
+
+    /// Run the CLI as another user.
+    pub fn run_as(mut self, run_as: RunAs) -> Self {
+        self.options.run_as = Some(run_as);
+        self
+    }
+
+    /// Use a separate CLI configuration directory.
+    pub fn config_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.options.config_dir = Some(dir.into());
+        self
+    }
+
+    /// Enable file checkpointing.
+    pub fn enable_file_checkpointing(mut self) -> Self {
+        self.options.enable_file_checkpointing = true;
+        self
+    }
+
+    /// Set allowed tools.
+    pub fn allowed_tools(mut self, tools: Vec<String>) -> Self {
+        self.options.allowed_tools = tools;
+        self
+    }
+
+    /// Set disallowed tools.
+    pub fn disallowed_tools(mut self, tools: Vec<String>) -> Self {
+        self.options.disallowed_tools = tools;
+        self
+    }
+
+    /// Retain the last `size` messages for [`ClaudeClient::recent_messages`].
+    pub fn message_history(mut self, size: usize) -> Self {
+        self.options.message_history_size = size;
+        self
+    }
+
+    /// Buffer up to `capacity` messages per [`ClaudeClient::subscribe`]
+    /// receiver.
+    pub fn subscriber_capacity(mut self, capacity: usize) -> Self {
+        self.options.subscriber_capacity = Some(capacity);
+        self
+    }
+
+    /// Add the hooks registered in a [`HookRegistry`](crate::HookRegistry).
+    ///
+    /// Hooks accumulate across calls to `hooks` and [`hook`](Self::hook).
+    pub fn hooks(mut self, registry: crate::hooks::HookRegistry) -> Self {
+        self.options.add_hooks(registry);
+        self
+    }
+
+    /// Add a hook callback for `event`, filtered by `matcher` (e.g. a tool
+    /// name).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::{ClaudeClientBuilder, HookEvent, HookOutput};
+    ///
+    /// let client = ClaudeClientBuilder::new()
+    ///     .hook(HookEvent::PreToolUse, "Bash", |input, _tool_use_id, _ctx| async move {
+    ///         println!("About to run: {:?}", input);
+    ///         HookOutput::default()
+    ///     })
+    ///     .mcp_stdio_server("files", "npx", ["-y", "@modelcontextprotocol/server-filesystem"])
+    ///     .mcp_http_server("search", "https://search.example.com/mcp")
+    ///     .build();
+    /// ```
+    pub fn hook<F, Fut>(mut self, event: HookEvent, matcher: impl Into<String>, callback: F) -> Self
+    where
+        F: Fn(HookInput, Option<String>, HookContext) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = HookOutput> + Send + 'static,
+    {
+        let hook: HookCallback = Arc::new(move |input, tool_use_id, context| {
+            Box::pin(callback(input, tool_use_id, context))
+        });
+        let matcher = HookMatcher::new(vec![hook]).with_matcher(matcher.into());
+        self.options
+            .add_hooks(HashMap::from([(event, vec![matcher])]));
+        self
+    }
+
+    /// Add an MCP server.
+    pub fn mcp_server(mut self, name: impl Into<String>, config: McpServerConfig) -> Self {
+        self.options = self.options.with_mcp_server(name, config);
+        self
+    }
+
+    /// Add an MCP server launched as a subprocess speaking stdio.
+    pub fn mcp_stdio_server<I, S>(
+        self,
+        name: impl Into<String>,
+        command: impl Into<String>,
+        args: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.mcp_server(
+            name,
+            McpServerConfig::Stdio(McpStdioServerConfig {
+                server_type: "stdio".to_string(),
+                command: command.into(),
+                args: args.into_iter().map(Into::into).collect(),
+                env: HashMap::new(),
+            }),
+        )
+    }
+
+    /// Add an MCP server reached over HTTP.
+    pub fn mcp_http_server(self, name: impl Into<String>, url: impl Into<String>) -> Self {
+        self.mcp_server(
+            name,
+            McpServerConfig::Http(McpHttpServerConfig {
+                server_type: "http".to_string(),
+                url: url.into(),
+                headers: HashMap::new(),
+            }),
+        )
+    }
+
+    /// Add a session tag (see [`ClaudeClient::set_tag`]).
+    pub fn session_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.session_tags.insert(key.into(), value.into());
+        self
+    }
+
+    /// Register a [`QueryInterceptor`] to run around each query.
+    pub fn interceptor(mut self, interceptor: impl QueryInterceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Run over `transport` instead of starting the CLI (see
+    /// [`ClaudeClient::with_transport`]).
+    pub fn transport(mut self, transport: Box<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Build the client.
+    pub fn build(self) -> ClaudeClient {
+        let mut client = match self.transport {
+            Some(transport) => ClaudeClient::with_transport(Some(self.options), transport),
+            None => ClaudeClient::new(Some(self.options)),
+        };
+        client.interceptors = self.interceptors;
+        client
     }
 }
 
@@ -660,11 +2575,249 @@ impl ClaudeClient {
     pub fn into_guard(self) -> ClientGuard {
         ClientGuard::new(self)
     }
+
+    /// Split a connected client into a [`ClientHandle`] for sending queries
+    /// and controlling the session, and the [`MessageReceiver`] the
+    /// responses arrive on.
+    ///
+    /// Handles are cheap to clone and can be shared across tasks, such as
+    /// web handlers, while a single task owns the receiver. Split after
+    /// [`connect`](Self::connect): the receiver only gets the messages of
+    /// the connection the client has when split.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use claude_agents_sdk::ClaudeClient;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = ClaudeClient::new(None);
+    ///     client.connect().await?;
+    ///     let (handle, mut receiver) = client.into_split();
+    ///
+    ///     tokio::spawn(async move {
+    ///         while let Ok((text, _)) = receiver.receive_response().await {
+    ///             println!("{}", text);
+    ///         }
+    ///     });
+    ///
+    ///     let h = handle.clone();
+    ///     tokio::spawn(async move { h.query("What is 2 + 2?").await });
+    ///     handle.interrupt().await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn into_split(mut self) -> (ClientHandle, MessageReceiver) {
+        let receiver = MessageReceiver {
+            message_rx: self.message_rx.take(),
+            interceptors: self.interceptors.clone(),
+            tags: self.internal.options().session_tags.clone(),
+        };
+        let handle = ClientHandle {
+            control: self.internal.control().clone(),
+            client: Arc::new(tokio::sync::Mutex::new(self)),
+        };
+        (handle, receiver)
+    }
+}
+
+/// Clonable handle to a [`ClaudeClient`] split with
+/// [`into_split`](ClaudeClient::into_split).
+///
+/// Calls from several handles are serialized, each holding the client
+/// until its request is answered, except for
+/// [`interrupt`](Self::interrupt) and
+/// [`outbound_frame_stats`](Self::outbound_frame_stats), which don't wait
+/// for the client: an interrupt goes out even while another handle's
+/// query waits on the CLI to apply its overrides. Queries go to the
+/// running CLI process as is, so there is no per-query environment like
+/// [`query_with_env`](ClaudeClient::query_with_env).
+#[derive(Clone)]
+pub struct ClientHandle {
+    client: Arc<tokio::sync::Mutex<ClaudeClient>>,
+    control: ControlSlot,
+}
+
+impl ClientHandle {
+    /// Send a query (see [`ClaudeClient::query`]).
+    pub async fn query(&self, prompt: &str) -> Result<()> {
+        self.query_with(prompt, QueryOverrides::default()).await
+    }
+
+    /// Send a query with some options overridden for this query only (see
+    /// [`ClaudeClient::query_with`]).
+    ///
+    /// The previous values are restored before the next query.
+    pub async fn query_with(&self, prompt: &str, overrides: QueryOverrides) -> Result<()> {
+        let mut client = self.client.lock().await;
+        if !client.is_connected() {
+            return Err(ClaudeSDKError::cli_connection("Client not connected"));
+        }
+        client.send_query(prompt, overrides).await
+    }
+
+    /// Interrupt the current operation, without waiting for other handles'
+    /// calls.
+    pub async fn interrupt(&self) -> Result<()> {
+        match self.control.get() {
+            Some(control) => control.interrupt().await,
+            None => Err(ClaudeSDKError::cli_connection("Client not connected")),
+        }
+    }
+
+    /// Get the sizes of the frames written to the CLI so far (see
+    /// [`ClaudeClient::outbound_frame_stats`]).
+    pub fn outbound_frame_stats(&self) -> OutboundFrameStats {
+        self.control
+            .get()
+            .map(|control| control.outbound_frame_stats())
+            .unwrap_or_default()
+    }
+
+    /// Change the permission mode for the session.
+    pub async fn set_permission_mode(&self, mode: PermissionMode) -> Result<()> {
+        self.client.lock().await.set_permission_mode(mode).await
+    }
+
+    /// Change the model for the session.
+    pub async fn set_model(&self, model: impl Into<String>) -> Result<()> {
+        self.client.lock().await.set_model(model).await
+    }
+
+    /// Change the maximum thinking tokens for the session.
+    pub async fn set_max_thinking_tokens(&self, max_thinking_tokens: Option<u32>) -> Result<()> {
+        self.client
+            .lock()
+            .await
+            .set_max_thinking_tokens(max_thinking_tokens)
+            .await
+    }
+
+    /// Get the tool uses whose results haven't arrived, oldest first.
+    pub async fn pending_tool_uses(&self) -> Vec<PendingToolUse> {
+        self.client.lock().await.pending_tool_uses().await
+    }
+
+    /// Get a summary of the protocol anomalies observed so far.
+    pub async fn protocol_anomalies(&self) -> ProtocolAnomalyReport {
+        self.client.lock().await.protocol_anomalies().await
+    }
+
+    /// Get the configuration the current session runs with.
+    pub async fn session_manifest(&self) -> Option<SessionManifest> {
+        self.client.lock().await.session_manifest().await
+    }
+
+    /// Subscribe to the messages received from now on.
+    pub async fn subscribe(&self) -> Result<tokio::sync::broadcast::Receiver<Arc<Message>>> {
+        self.client.lock().await.subscribe()
+    }
+
+    /// Check if the client is connected.
+    pub async fn is_connected(&self) -> bool {
+        self.client.lock().await.is_connected()
+    }
+
+    /// Disconnect from the CLI, ending the [`MessageReceiver`]'s messages.
+    pub async fn disconnect(&self) -> Result<()> {
+        self.client.lock().await.disconnect().await
+    }
+}
+
+impl std::fmt::Debug for ClientHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientHandle").finish_non_exhaustive()
+    }
+}
+
+/// The messages of a [`ClaudeClient`] split with
+/// [`into_split`](ClaudeClient::into_split).
+///
+/// Responses are received like with the client, except that failed turns
+/// aren't retried: a response the CLI needs to be logged in again for fails
+/// with [`AuthRequired`](ClaudeSDKError::AuthRequired).
+pub struct MessageReceiver {
+    message_rx: Option<mpsc::Receiver<Result<Message>>>,
+    interceptors: Vec<Arc<dyn QueryInterceptor>>,
+    tags: HashMap<String, String>,
+}
+
+impl MessageReceiver {
+    /// Receive the next message, or `None` once the client has
+    /// disconnected.
+    pub async fn recv(&mut self) -> Option<Result<Message>> {
+        let message = self.message_rx.as_mut()?.recv().await;
+        if let Some(Ok(Message::Result(ref result))) = message {
+            notify_result(&self.interceptors, &self.tags, result);
+        }
+        message
+    }
+
+    /// Receive the complete response for the current query (see
+    /// [`ClaudeClient::receive_response`]).
+    pub async fn receive_response(&mut self) -> Result<(String, ResultMessage)> {
+        let mut response = CollectedResponse::default();
+        match collect_response(
+            &mut self.message_rx,
+            &self.interceptors,
+            &self.tags,
+            &mut response,
+        )
+        .await?
+        {
+            Some(result) => Ok((response.parts.concat(), result)),
+            None => Err(ClaudeSDKError::internal("Connection closed without result")),
+        }
+    }
+}
+
+impl Stream for MessageReceiver {
+    type Item = Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(ref mut rx) = this.message_rx else {
+            return Poll::Ready(None);
+        };
+        let poll = rx.poll_recv(cx);
+        if let Poll::Ready(Some(Ok(Message::Result(ref result)))) = poll {
+            notify_result(&this.interceptors, &this.tags, result);
+        }
+        poll
+    }
+}
+
+impl std::fmt::Debug for MessageReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageReceiver")
+            .field("tags", &self.tags)
+            .finish_non_exhaustive()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    struct Recorder {
+        results: Arc<AtomicUsize>,
+    }
+
+    impl QueryInterceptor for Recorder {
+        fn after_result(&self, _result: &ResultMessage) {
+            self.results.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct DenyAll;
+
+    impl QueryInterceptor for DenyAll {
+        fn before_query(&self, _prompt: &mut String, _: &mut ClaudeAgentOptions) -> Result<()> {
+            Err(ClaudeSDKError::configuration("denied by policy"))
+        }
+    }
 
     #[test]
     fn test_client_builder() {
@@ -675,4 +2828,1054 @@ mod tests {
 
         assert!(!client.is_connected());
     }
+
+    fn assistant(text: &str) -> Message {
+        Message::Assistant(AssistantMessage::new(
+            vec![ContentBlock::Text(TextBlock {
+                text: text.to_string(),
+            })],
+            "test",
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_collect_response_stops_at_result() {
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(Ok(assistant("Hello, "))).await.unwrap();
+        tx.send(Ok(assistant("world"))).await.unwrap();
+        tx.send(Ok(success())).await.unwrap();
+
+        let results = Arc::new(AtomicUsize::new(0));
+        let interceptors: Vec<Arc<dyn QueryInterceptor>> = vec![Arc::new(Recorder {
+            results: Arc::clone(&results),
+        })];
+
+        let mut rx = Some(rx);
+        let mut response = CollectedResponse::default();
+        let result = collect_response(&mut rx, &interceptors, &HashMap::new(), &mut response)
+            .await
+            .unwrap();
+        assert!(result.is_some());
+        assert_eq!(response.parts.concat(), "Hello, world");
+        assert_eq!(results.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_collect_response_keeps_partial_text_on_timeout() {
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(Ok(assistant("partial"))).await.unwrap();
+
+        let mut rx = Some(rx);
+        let mut response = CollectedResponse::default();
+        let timed_out = tokio::time::timeout(
+            Duration::from_millis(50),
+            collect_response(&mut rx, &[], &HashMap::new(), &mut response),
+        )
+        .await;
+        assert!(timed_out.is_err());
+        assert_eq!(response.parts, vec!["partial".to_string()]);
+        drop(tx);
+    }
+
+    #[tokio::test]
+    async fn test_receive_response_messages_ends_after_result() {
+        use tokio_stream::StreamExt;
+
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(Ok(assistant("Hello"))).await.unwrap();
+        tx.send(Ok(success())).await.unwrap();
+        tx.send(Ok(assistant("Next turn"))).await.unwrap();
+
+        let mut client = ClaudeClient::new(None);
+        client.message_rx = Some(rx);
+        let messages: Vec<_> = client.receive_response_messages().collect().await;
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[1], Ok(Message::Result(_))));
+
+        let next = client.receive_messages().next().await;
+        assert!(matches!(next, Some(Ok(Message::Assistant(_)))));
+    }
+
+    fn success() -> Message {
+        let mut result = ResultMessage::new("success", "s");
+        result.num_turns = 1;
+        Message::Result(result)
+    }
+
+    #[tokio::test]
+    async fn test_stream_response_halves_in_any_order() {
+        use tokio_stream::StreamExt;
+
+        // Text first, then the result
+        let (tx, rx) = mpsc::channel(8);
+        let mut client = ClaudeClient::new(None);
+        client.message_rx = Some(rx);
+        for message in [assistant("Hello, "), assistant("world"), success()] {
+            tx.send(Ok(message)).await.unwrap();
+        }
+        let (text, result) = client.stream_response();
+        assert_eq!(text.collect::<Vec<_>>().await, ["Hello, ", "world"]);
+        assert_eq!(result.await.unwrap().session_id, "s");
+
+        // Result first: the text is kept for the stream
+        for message in [assistant("again"), success()] {
+            tx.send(Ok(message)).await.unwrap();
+        }
+        let (text, result) = client.stream_response();
+        assert!(result.await.is_ok());
+        assert_eq!(text.collect::<Vec<_>>().await, ["again"]);
+
+        // Both at once, with messages still to come
+        let sender = tx.clone();
+        tokio::spawn(async move {
+            for message in [assistant("live"), success()] {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                sender.send(Ok(message)).await.unwrap();
+            }
+        });
+        let (text, result) = client.stream_response();
+        let (chunks, result) = tokio::join!(text.collect::<Vec<_>>(), result);
+        assert_eq!(chunks, ["live"]);
+        assert!(result.is_ok());
+
+        // Text nobody reads doesn't hold up the result
+        let (text, result) = client.stream_response();
+        drop(text);
+        tx.send(Ok(assistant("unread"))).await.unwrap();
+        tx.send(Ok(success())).await.unwrap();
+        assert!(result.await.is_ok());
+
+        drop(tx);
+        let (text, result) = client.stream_response();
+        assert!(text.collect::<Vec<_>>().await.is_empty());
+        assert!(result.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ping_requires_connection() {
+        let client = ClaudeClient::new(None);
+        let err = client.ping(Duration::from_secs(1)).await.unwrap_err();
+        assert!(matches!(err, ClaudeSDKError::CLIConnection { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_escalation_requires_connection() {
+        let mut client = ClaudeClient::new(None);
+        let escalated = client
+            .escalate_permissions(
+                PermissionMode::AcceptEdits,
+                "test",
+                EscalationRevert::Manual,
+            )
+            .await;
+        assert!(escalated.is_err());
+        assert!(!client.is_escalated());
+        assert!(!client.revert_permissions().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_query_with_requires_connection() {
+        let mut client = ClaudeClient::new(None);
+        let overrides = QueryOverrides::new()
+            .with_model("claude-opus-4-1")
+            .with_max_thinking_tokens(8_000);
+        assert!(client.query_with("Hello", overrides).await.is_err());
+        assert!(client.override_restore.is_none());
+    }
+
+    #[test]
+    fn test_builder_hook_and_mcp_sugar() {
+        use crate::hooks::{HookRegistry, PostToolUse};
+
+        let client = ClaudeClientBuilder::new()
+            .hook(HookEvent::PreToolUse, "Bash", |_, _, _| async {
+                HookOutput::default()
+            })
+            .hooks(HookRegistry::new().on(PostToolUse, |_| async { HookOutput::default() }))
+            .hook(HookEvent::PreToolUse, "Write", |_, _, _| async {
+                HookOutput::default()
+            })
+            .mcp_stdio_server("files", "npx", ["server-filesystem", "/tmp"])
+            .mcp_http_server("search", "https://example.com/mcp")
+            .build();
+
+        let options = client.internal.options();
+        let hooks = options.hooks.as_ref().unwrap();
+        let matchers: Vec<_> = hooks[&HookEvent::PreToolUse]
+            .iter()
+            .map(|m| m.matcher.as_deref())
+            .collect();
+        assert_eq!(matchers, vec![Some("Bash"), Some("Write")]);
+        assert_eq!(hooks[&HookEvent::PostToolUse].len(), 1);
+
+        let McpServersConfig::Map(ref servers) = options.mcp_servers else {
+            panic!("expected inline MCP servers");
+        };
+        assert!(matches!(servers["files"], McpServerConfig::Stdio(ref c) if c.args.len() == 2));
+        assert!(matches!(servers["search"], McpServerConfig::Http(_)));
+    }
+
+    #[test]
+    fn test_session_tags() {
+        let mut client = ClaudeClientBuilder::new()
+            .session_tag("tenant", "acme")
+            .build();
+        assert_eq!(
+            client.tags().get("tenant").map(String::as_str),
+            Some("acme")
+        );
+
+        client.set_tag("job", "report");
+        client.set_tag("tenant", "globex");
+        assert_eq!(client.tags().len(), 2);
+        assert_eq!(client.tags()["tenant"], "globex");
+
+        assert_eq!(client.remove_tag("job").as_deref(), Some("report"));
+        assert!(client.remove_tag("job").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_interceptor_error_aborts_query() {
+        let mut client = ClaudeClientBuilder::new().interceptor(DenyAll).build();
+        let err = client.query("hello").await.unwrap_err();
+        assert!(err.to_string().contains("denied by policy"));
+    }
+
+    #[tokio::test]
+    async fn test_query_with_env_requires_connection() {
+        let mut client = ClaudeClient::new(None);
+        let err = client
+            .query_with_env("hello", [("TENANT_ID", "acme")])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClaudeSDKError::CLIConnection { .. }));
+        assert!(client.env_overlay.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_connect_reports_phases_and_cleans_up() {
+        use crate::testing::MockTransport;
+
+        // A "CLI" that starts but never answers the initialize request
+        let transport = MockTransport::new(vec![]).with_control_responder(|_| None);
+        let options = ClaudeAgentOptions::new()
+            .with_deadline(std::time::Instant::now() + Duration::from_millis(500));
+
+        let mut client = ClaudeClient::with_transport(Some(options), Box::new(transport));
+        let mut phases = Vec::new();
+        let err = client
+            .connect_with_progress(|phase| phases.push(phase))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.timeout_phase(), Some(crate::TimeoutPhase::Control));
+        assert_eq!(
+            phases,
+            vec![ConnectPhase::Spawned, ConnectPhase::ProtocolReady]
+        );
+        assert!(!client.is_connected());
+        assert!(client.get_server_info().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_auth_required_surfaces_after_response() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let options = ClaudeAgentOptions::new().with_on_auth_required(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { false }
+        });
+        let (tx, rx) = mpsc::channel(8);
+        let mut client = ClaudeClient::new(Some(options));
+        client.message_rx = Some(rx);
+
+        let mut expired = assistant("OAuth token has expired. Please run /login");
+        if let Message::Assistant(ref mut asst) = expired {
+            asst.error = Some(AssistantMessageError::AuthenticationFailed);
+        }
+        for message in [expired, success(), assistant("next"), success()] {
+            tx.send(Ok(message)).await.unwrap();
+        }
+        let err = client.receive_response().await.unwrap_err();
+        assert!(matches!(
+            err,
+            ClaudeSDKError::AuthRequired { ref instructions }
+                if instructions.starts_with("OAuth token has expired")
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // The failed response was read to its end
+        assert_eq!(client.receive_response().await.unwrap().0, "next");
+
+        // An error result asking for /login
+        let mut rejected = success();
+        if let Message::Result(ref mut result) = rejected {
+            result.is_error = true;
+            result.result = Some("Invalid API key · Please run /login".to_string());
+        }
+        tx.send(Ok(rejected)).await.unwrap();
+        let (_text, result) = client.stream_response();
+        assert!(result.await.unwrap_err().is_auth_required());
+    }
+
+    /// An assistant frame saying `text`.
+    fn text_frame(text: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": "assistant",
+            "message": {"content": [{"type": "text", "text": text}], "model": "m"}
+        })
+    }
+
+    /// A mock CLI answering control requests, and each prompt with the
+    /// frames `reply` computes from it followed by a successful result.
+    fn answering<F>(reply: F) -> crate::testing::MockTransport
+    where
+        F: Fn(&serde_json::Value) -> Vec<serde_json::Value> + Send + Sync + 'static,
+    {
+        crate::testing::MockTransport::new(vec![])
+            .respond_to_control_requests()
+            .with_prompt_responder(move |prompt| {
+                let mut frames = reply(prompt);
+                frames.push(serde_json::json!({
+                    "type": "result",
+                    "subtype": "success",
+                    "duration_ms": 1,
+                    "duration_api_ms": 1,
+                    "is_error": false,
+                    "num_turns": 1,
+                    "session_id": "s"
+                }));
+                frames
+            })
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_prompt_is_retried_after_reauthentication() {
+        use crate::_internal::fake_cli::scripted_cli;
+
+        // Prompts fail with an auth error until the login marker exists
+        let dir = tempfile::tempdir().unwrap();
+        let cli = scripted_cli(
+            dir.path(),
+            r#"if [ -e "$0.logged-in" ]; then
+  echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Hi"}],"model":"m"}}'
+else
+  echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Please run /login"}],"model":"m","error":"authentication_failed"}}'
+fi"#,
+        );
+
+        let login = dir.path().join("claude.logged-in");
+        let mut options = ClaudeAgentOptions::new().with_on_auth_required(move |_| {
+            let login = login.clone();
+            async move { std::fs::write(login, "").is_ok() }
+        });
+        options.cli_path = Some(cli.clone());
+
+        let mut client = ClaudeClient::new(Some(options));
+        client.connect().await.unwrap();
+        client.query("Hello").await.unwrap();
+        let (text, _) = client.receive_response().await.unwrap();
+        assert_eq!(text, "Hi");
+
+        // The second run resumed the conversation
+        let args = std::fs::read_to_string(dir.path().join("claude.args")).unwrap();
+        let runs = args.lines().collect::<Vec<_>>();
+        assert_eq!(runs.len(), 2);
+        assert!(!runs[0].contains("--resume"));
+        assert!(runs[1].contains("--resume s"));
+        // Resuming was for the restart only
+        assert!(client.internal.options().resume.is_none());
+        client.disconnect().await.unwrap();
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_missing_session_is_reported_or_replaced() {
+        use crate::_internal::fake_cli::{scripted_cli, write_script};
+
+        // Fails like the CLI when resuming "gone", and works otherwise
+        let dir = tempfile::tempdir().unwrap();
+        let real = scripted_cli(
+            dir.path(),
+            r#"  echo '{"type":"assistant","message":{"content":[{"type":"text","text":"Hi"}],"model":"m"}}'"#,
+        );
+        let cli = write_script(
+            dir.path(),
+            "resuming-claude",
+            &format!(
+                r#"case "$*" in
+  *"--resume gone"*)
+    echo "No conversation found with session ID: gone" >&2
+    exit 1
+    ;;
+esac
+exec {} "$@""#,
+                real.display()
+            ),
+        );
+
+        let mut options = ClaudeAgentOptions::builder()
+            .resume("gone")
+            .build()
+            .unwrap();
+        options.cli_path = Some(cli.clone());
+        let mut client = ClaudeClient::new(Some(options.clone()));
+        let err = client.connect().await.unwrap_err();
+        assert!(err.is_session_not_found(), "{}", err);
+        assert!(err.to_string().contains("gone"));
+
+        options.resume_fallback_new = true;
+        let mut client = ClaudeClient::new(Some(options));
+        client.connect().await.unwrap();
+        client.query("Hello").await.unwrap();
+        let (text, _) = client.receive_response().await.unwrap();
+        assert_eq!(text, "Hi");
+        client.disconnect().await.unwrap();
+
+        // Only the fresh session got as far as the real CLI
+        let args = std::fs::read_to_string(dir.path().join("claude.args")).unwrap();
+        assert_eq!(args.lines().count(), 1);
+        assert!(!args.contains("--resume"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_progress_follows_the_turn() {
+        use crate::Progress;
+
+        // Each frame takes a second, so the tool is seen running
+        let transport = answering(|_| {
+            vec![
+                serde_json::json!({
+                    "type": "assistant",
+                    "message": {
+                        "content": [{"type": "tool_use", "id": "t1", "name": "Bash", "input": {}}],
+                        "model": "m"
+                    }
+                }),
+                serde_json::json!({
+                    "type": "user",
+                    "message": {
+                        "role": "user",
+                        "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "ok"}]
+                    }
+                }),
+                text_frame("Done"),
+            ]
+        })
+        .with_latency(Duration::from_secs(1));
+        let mut client = ClaudeClient::with_transport(None, Box::new(transport));
+        let mut progress = client.progress();
+        assert_eq!(*progress.borrow(), Progress::Idle);
+
+        client.connect().await.unwrap();
+        // Connecting, then idle again
+        assert!(progress.has_changed().unwrap());
+        assert_eq!(*progress.borrow_and_update(), Progress::Idle);
+
+        client.query("Run it").await.unwrap();
+        let running = tokio::time::timeout(
+            Duration::from_secs(5),
+            progress.wait_for(|phase| matches!(phase, Progress::RunningTool { .. })),
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .clone();
+        assert_eq!(
+            running,
+            Progress::RunningTool {
+                name: "Bash".to_string()
+            }
+        );
+
+        client.receive_response().await.unwrap();
+        assert_eq!(*progress.borrow(), Progress::Idle);
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_escalation_reverts_to_live_mode_on_message_path() {
+        use tokio_stream::StreamExt;
+
+        let modes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&modes);
+        let transport =
+            answering(|_| vec![text_frame("ok")]).with_control_responder(move |request| {
+                if request["subtype"] == "set_permission_mode" {
+                    seen.lock()
+                        .unwrap()
+                        .push(request["mode"].as_str().unwrap().to_string());
+                }
+                Some(serde_json::json!({}))
+            });
+        let mut client = ClaudeClient::with_transport(None, Box::new(transport));
+        client.connect().await.unwrap();
+
+        client
+            .set_permission_mode(PermissionMode::Plan)
+            .await
+            .unwrap();
+        client
+            .escalate_permissions(
+                PermissionMode::AcceptEdits,
+                "test",
+                EscalationRevert::AfterTurns(1),
+            )
+            .await
+            .unwrap();
+        client.query("Hello").await.unwrap();
+        {
+            let mut messages = Box::pin(client.receive_messages());
+            while let Some(message) = messages.next().await {
+                if let Message::Result(_) = message.unwrap() {
+                    break;
+                }
+            }
+        }
+
+        // Reverted by the reader, to the mode set at runtime
+        for _ in 0..100 {
+            if modes.lock().unwrap().len() == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(*modes.lock().unwrap(), ["plan", "acceptEdits", "plan"]);
+        assert!(!client.is_escalated());
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_overrides_restore_runtime_changes() {
+        use crate::testing::MockTransport;
+
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        let transport =
+            MockTransport::with_text_response("ok").with_control_responder(move |request| {
+                if request["subtype"] == "set_model" {
+                    seen.lock().unwrap().push(request["model"].clone());
+                }
+                Some(serde_json::json!({}))
+            });
+        let options = ClaudeAgentOptions::new().with_model("claude-sonnet-4-5");
+        let mut client = ClaudeClient::with_transport(Some(options), Box::new(transport));
+        client.connect().await.unwrap();
+
+        client.set_model("claude-opus-4-1").await.unwrap();
+        let overrides = QueryOverrides::new().with_model("claude-haiku-4-5");
+        client.query_with("Hello", overrides).await.unwrap();
+        client.receive_response().await.unwrap();
+
+        // Back to the model set at runtime, not the one in the options
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["claude-opus-4-1", "claude-haiku-4-5", "claude-opus-4-1"]
+        );
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_context_policy_compacts_or_warns() {
+        use crate::Progress;
+        use tokio_stream::StreamExt;
+
+        // Every reply fills 150k tokens of a 200k window, and compactions
+        // mark their boundary unless a hook blocks them
+        let prompts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let blocked = Arc::new(AtomicBool::new(false));
+        let reply = |prompt: &str| {
+            serde_json::json!({
+                "type": "assistant",
+                "message": {
+                    "content": [{"type": "text", "text": prompt}],
+                    "model": "m",
+                    "usage": {
+                        "input_tokens": 10,
+                        "cache_read_input_tokens": 149000,
+                        "output_tokens": 990
+                    }
+                }
+            })
+        };
+        let cli = || {
+            let prompts = Arc::clone(&prompts);
+            let blocked = Arc::clone(&blocked);
+            answering(move |frame| {
+                let prompt = frame["message"]["content"].as_str().unwrap().to_string();
+                prompts.lock().unwrap().push(prompt.clone());
+                if !prompt.starts_with("/compact") {
+                    vec![reply(&prompt)]
+                } else if blocked.load(Ordering::SeqCst) {
+                    vec![]
+                } else {
+                    vec![serde_json::json!({
+                        "type": "system",
+                        "subtype": "compact_boundary",
+                        "compact_metadata": {"trigger": "manual", "pre_tokens": 150000}
+                    })]
+                }
+            })
+        };
+        let mut options = ClaudeAgentOptions::new()
+            .with_context_policy(ContextPolicy::compact_at(0.7).with_instructions("keep the plan"));
+        let mut client = ClaudeClient::with_transport(Some(options.clone()), Box::new(cli()));
+        client.connect().await.unwrap();
+        assert_eq!(client.context_usage().unwrap().used_tokens, 0);
+
+        client.query("first").await.unwrap();
+        client.receive_response().await.unwrap();
+        let usage = client.context_usage().unwrap();
+        assert_eq!(usage.used_tokens, 150_000);
+        assert_eq!(usage.fraction(), 0.75);
+
+        // Compacted before the prompt, without the compaction's messages,
+        // and without reading them off a split client's receiver
+        let (handle, mut messages) = client.into_split();
+        handle.query("second").await.unwrap();
+        let (text, _) = messages.receive_response().await.unwrap();
+        assert_eq!(text, "second");
+
+        // A blocked compaction isn't tried again
+        blocked.store(true, Ordering::SeqCst);
+        for prompt in ["third", "fourth"] {
+            handle.query(prompt).await.unwrap();
+            let (text, _) = messages.receive_response().await.unwrap();
+            assert_eq!(text, prompt);
+        }
+        drop((handle, messages));
+        assert_eq!(
+            *prompts.lock().unwrap(),
+            [
+                "first",
+                "/compact keep the plan",
+                "second",
+                "/compact keep the plan",
+                "third",
+                "fourth"
+            ]
+        );
+
+        // Warning instead
+        let full = Progress::ContextNearlyFull(ContextUsage {
+            used_tokens: 150_000,
+            context_window: 200_000,
+        });
+        options.context_policy = Some(ContextPolicy::warn_at(0.7));
+        let mut client = ClaudeClient::with_transport(Some(options.clone()), Box::new(cli()));
+        let progress = client.progress();
+        client.connect().await.unwrap();
+        client.query("fifth").await.unwrap();
+        client.receive_response().await.unwrap();
+        assert_eq!(*progress.borrow(), full);
+        client.disconnect().await.unwrap();
+
+        // Reported as soon as a turn crosses the threshold
+        let transport = crate::testing::MockTransport::new(vec![])
+            .respond_to_control_requests()
+            .with_prompt_responder(move |_| vec![reply("sixth")]);
+        let mut client = ClaudeClient::with_transport(Some(options), Box::new(transport));
+        let progress = client.progress();
+        client.connect().await.unwrap();
+        client.query("sixth").await.unwrap();
+        let message = client.receive_messages().next().await.unwrap().unwrap();
+        assert!(matches!(message, Message::Assistant(_)));
+        assert_eq!(*progress.borrow(), full);
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mock_model_runs_without_cli() {
+        use crate::Model;
+        use tokio_stream::StreamExt;
+
+        let mut options = ClaudeAgentOptions::new().with_model(Model::mock());
+        options.cli_path = Some("/nonexistent/claude".into());
+
+        let mut client = ClaudeClient::new(Some(options.clone()));
+        client.connect().await.unwrap();
+        for prompt in ["ping", "pong"] {
+            client.query(prompt).await.unwrap();
+            let (text, result) = client.receive_response().await.unwrap();
+            assert_eq!(text, prompt);
+            assert!(!result.is_error);
+            assert_eq!(result.total_cost_usd, Some(0.0));
+        }
+        client.disconnect().await.unwrap();
+
+        // Non-streaming mode too
+        let messages: Vec<_> = crate::query("hello", Some(options))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        let Ok(Message::Assistant(ref assistant)) = messages[1] else {
+            panic!("expected an assistant message, got {:?}", messages);
+        };
+        assert_eq!(assistant.text(), "hello");
+        assert!(matches!(messages.last(), Some(Ok(Message::Result(_)))));
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_with_subprocess_transport() {
+        use crate::_internal::fake_cli::scripted_cli;
+        use crate::_internal::transport::SubprocessTransport;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cli = scripted_cli(
+            dir.path(),
+            r#"  echo '{"type":"assistant","message":{"content":[{"type":"text","text":"pong"}],"model":"m"}}'"#,
+        );
+        let mut options = ClaudeAgentOptions::new();
+        options.cli_path = Some(cli);
+        let transport = SubprocessTransport::new(&options, None).unwrap();
+
+        let mut client = ClaudeClient::with_transport(Some(options), Box::new(transport));
+        client.connect().await.unwrap();
+        client.query("ping").await.unwrap();
+        let (text, result) = client.receive_response().await.unwrap();
+        assert_eq!(text, "pong");
+        assert!(!result.is_error);
+        client.disconnect().await.unwrap();
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_session_tmp_dir() {
+        use crate::_internal::fake_cli::scripted_cli;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cli = scripted_cli(dir.path(), "");
+        let mut options = ClaudeAgentOptions::new();
+        options.cli_path = Some(cli.clone());
+
+        let mut client = ClaudeClient::new(Some(options.clone()));
+        let tmp = client.session_tmp_dir().unwrap().to_path_buf();
+        assert!(tmp.is_dir());
+        assert_eq!(client.session_tmp_dir().unwrap(), tmp);
+        client.connect().await.unwrap();
+        let args = std::fs::read_to_string(dir.path().join("claude.args")).unwrap();
+        assert!(
+            args.contains(&format!("--add-dir {}", tmp.display())),
+            "{}",
+            args
+        );
+        client.disconnect().await.unwrap();
+        assert!(!tmp.exists());
+        // A new session gets a new directory
+        let next = client.session_tmp_dir().unwrap().to_path_buf();
+        assert_ne!(next, tmp);
+        drop(client);
+        assert!(!next.exists());
+
+        let mut client = ClaudeClient::new(Some(options.with_keep_session_tmp_dir(true)));
+        let kept = client.session_tmp_dir().unwrap().to_path_buf();
+        client.disconnect().await.unwrap();
+        assert!(kept.is_dir());
+        std::fs::remove_dir(kept).unwrap();
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_connect_fails_over_to_fallback_transports() {
+        use crate::_internal::fake_cli::{scripted_cli, write_script};
+        use crate::diagnostics::DiagnosticSeverity;
+        use crate::FallbackTransport;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cli = scripted_cli(dir.path(), "");
+        let old = write_script(dir.path(), "old-claude", "echo '1.0.3 (Claude Code)'");
+
+        let mut options = ClaudeAgentOptions::new()
+            .with_fallback_transport(FallbackTransport::Cli("/nonexistent/claude".into()))
+            .with_fallback_transport(FallbackTransport::Cli(cli.clone()));
+        options.cli_path = Some(old);
+        let mut client = ClaudeClient::new(Some(options.clone()));
+        let mut diagnostics = client.diagnostics(DiagnosticSeverity::Warning);
+        client.connect().await.unwrap();
+        let args = std::fs::read_to_string(dir.path().join("claude.args")).unwrap();
+        assert!(args.contains("--input-format stream-json"), "{}", args);
+
+        let first = diagnostics.recv().await.unwrap();
+        assert_eq!(first.code.as_deref(), Some("transport_failover"));
+        assert!(
+            first.message.contains("CLI at /nonexistent/claude"),
+            "{}",
+            first.message
+        );
+        assert!(first.message.contains("below minimum"), "{}", first.message);
+        let second = diagnostics.recv().await.unwrap();
+        assert!(
+            second.message.contains(&cli.display().to_string()),
+            "{}",
+            second.message
+        );
+        client.disconnect().await.unwrap();
+
+        // Other failures are returned without trying the fallbacks
+        options.cli_path = Some(cli);
+        options.cwd = Some(dir.path().join("missing"));
+        let mut client = ClaudeClient::new(Some(options));
+        let err = client.connect().await.unwrap_err();
+        assert!(err.launch_error().is_some(), "{}", err);
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[tokio::test]
+    async fn test_outbound_frames_are_limited_and_counted() {
+        use crate::Model;
+
+        let mut options = ClaudeAgentOptions::new()
+            .with_model(Model::mock())
+            .with_outbound_frame_limit(OutboundFrameLimit::new(1000));
+        options.cli_path = Some("/nonexistent/claude".into());
+
+        let mut client = ClaudeClient::new(Some(options));
+        client.connect().await.unwrap();
+        client.query("ping").await.unwrap();
+        client.receive_response().await.unwrap();
+        let stats = client.outbound_frame_stats();
+        // The initialize request and the prompt
+        assert_eq!(stats.frames, 2);
+        assert!(stats.largest > 0 && stats.bytes >= stats.largest as u64);
+
+        let err = client.query(&"x".repeat(1000)).await.unwrap_err();
+        assert!(err.is_frame_too_large(), "{}", err);
+        assert_eq!(client.outbound_frame_stats().rejected, 1);
+        // Nothing was written, so the session carries on
+        client.query("pong").await.unwrap();
+        assert_eq!(client.receive_response().await.unwrap().0, "pong");
+        client.disconnect().await.unwrap();
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_env_references_are_expanded_at_connect() {
+        use crate::_internal::fake_cli::scripted_cli;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cli = scripted_cli(dir.path(), "");
+        let mut options = ClaudeAgentOptions::builder()
+            .system_prompt("You work on ${CLIENT_TEST_PROJECT}.")
+            .settings(r#"{"env":{"TOKEN":"${CLIENT_TEST_TOKEN}"}}"#)
+            .expand_env()
+            .build()
+            .unwrap();
+        options.cli_path = Some(cli);
+
+        // Nothing is started with a reference left unresolved
+        let mut client = ClaudeClient::new(Some(options.clone()));
+        let err = client.connect().await.unwrap_err().to_string();
+        assert!(err.contains("CLIENT_TEST_PROJECT"), "{}", err);
+        assert!(!dir.path().join("claude.args").exists());
+
+        options
+            .env
+            .insert("CLIENT_TEST_PROJECT".to_string(), "the SDK".to_string());
+        options
+            .env
+            .insert("CLIENT_TEST_TOKEN".to_string(), "t0k3n".to_string());
+        let mut client = ClaudeClient::new(Some(options));
+        client.connect().await.unwrap();
+        client.disconnect().await.unwrap();
+
+        let args = std::fs::read_to_string(dir.path().join("claude.args")).unwrap();
+        assert!(args.contains("You work on the SDK."), "{}", args);
+        assert!(args.contains(r#"{"env":{"TOKEN":"t0k3n"}}"#), "{}", args);
+    }
+
+    #[tokio::test]
+    async fn test_failed_turn_is_retried() {
+        // The first two attempts hit a server error
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&attempts);
+        let transport = answering(move |_| {
+            if counter.fetch_add(1, Ordering::SeqCst) >= 2 {
+                return vec![text_frame("Done")];
+            }
+            let mut overloaded = text_frame("API Error: 529 Overloaded");
+            overloaded["message"]["error"] = "server_error".into();
+            vec![overloaded]
+        });
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let retry = TurnRetry::new(2)
+            .with_delays(Duration::from_millis(1), Duration::from_millis(2))
+            .on_retry(move |event| recorded.lock().unwrap().push(event.clone()));
+        let options = ClaudeAgentOptions::new().with_turn_retry(retry);
+
+        let mut client = ClaudeClient::with_transport(Some(options), Box::new(transport));
+        client.connect().await.unwrap();
+        client.query("Hello").await.unwrap();
+        assert_eq!(client.receive_response().await.unwrap().0, "Done");
+
+        let events = events.lock().unwrap().clone();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].attempt, 2);
+        assert_eq!(events[0].error, AssistantMessageError::ServerError);
+        assert_eq!(events[0].message, "API Error: 529 Overloaded");
+
+        // Out of retries: the failed response is returned
+        attempts.store(0, Ordering::SeqCst);
+        client.internal.options_mut().turn_retry =
+            Some(TurnRetry::new(1).with_delays(Duration::from_millis(1), Duration::from_millis(1)));
+        client.query("Again").await.unwrap();
+        let (text, _) = client.receive_response().await.unwrap();
+        assert_eq!(text, "API Error: 529 Overloaded");
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_split_client() {
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<ClientHandle>();
+
+        let results = Arc::new(AtomicUsize::new(0));
+        let mut client = ClaudeClient::new(None);
+        client.add_interceptor(Recorder {
+            results: Arc::clone(&results),
+        });
+        let (tx, rx) = mpsc::channel(8);
+        client.message_rx = Some(rx);
+
+        let (handle, mut receiver) = client.into_split();
+        tx.send(Ok(assistant("Hello"))).await.unwrap();
+        tx.send(Ok(assistant(" world"))).await.unwrap();
+        tx.send(Ok(success())).await.unwrap();
+        let (text, _) = receiver.receive_response().await.unwrap();
+        assert_eq!(text, "Hello world");
+        assert_eq!(results.load(Ordering::SeqCst), 1);
+
+        // Not connected to a CLI
+        let other = handle.clone();
+        assert!(tokio::spawn(async move { other.query("Hi").await })
+            .await
+            .unwrap()
+            .is_err());
+        assert!(handle.interrupt().await.is_err());
+
+        drop(tx);
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_split_client_round_trip() {
+        let transport = answering(|_| vec![text_frame("Hi")]);
+        let mut client = ClaudeClient::with_transport(None, Box::new(transport));
+        client.connect().await.unwrap();
+
+        let (handle, mut receiver) = client.into_split();
+        let reader = tokio::spawn(async move { receiver.receive_response().await });
+        let sender = handle.clone();
+        tokio::spawn(async move { sender.query("Hello").await })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reader.await.unwrap().unwrap().0, "Hi");
+
+        handle.disconnect().await.unwrap();
+        assert!(!handle.is_connected().await);
+    }
+
+    #[tokio::test]
+    async fn test_split_client_interrupts_during_query_overrides() {
+        // The model override waits on a response that never comes
+        let transport = answering(|_| vec![text_frame("Hi")]).with_control_responder(|request| {
+            (request["subtype"] != "set_model").then(|| serde_json::json!({}))
+        });
+        let mut client = ClaudeClient::with_transport(None, Box::new(transport));
+        client.connect().await.unwrap();
+
+        let (handle, _receiver) = client.into_split();
+        let sender = handle.clone();
+        let query = tokio::spawn(async move {
+            sender
+                .query_with("Hello", QueryOverrides::new().with_model("opus"))
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!query.is_finished());
+
+        let written = handle.outbound_frame_stats().frames;
+        tokio::time::timeout(Duration::from_secs(5), handle.interrupt())
+            .await
+            .expect("interrupt waited for the query")
+            .unwrap();
+        assert_eq!(handle.outbound_frame_stats().frames, written + 1);
+        query.abort();
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_mcp_server_failures_and_toggling() {
+        use crate::_internal::fake_cli::scripted_cli_with;
+
+        // Reports the linear server as failed and can't toggle servers
+        let dir = tempfile::tempdir().unwrap();
+        let cli = scripted_cli_with(
+            dir.path(),
+            r#"    *'"mcp_toggle"'*)
+      echo '{"type":"control_response","response":{"subtype":"error","request_id":"'"$id"'","error":"Unsupported control request"}}'
+      ;;
+    *'"mcp_status"'*)
+      echo '{"type":"control_response","response":{"subtype":"success","request_id":"'"$id"'","response":{"mcpServers":[{"name":"github","status":"connected"},{"name":"linear","status":"failed","error":"spawn linear-mcp ENOENT"}]}}}'
+      ;;"#,
+            "",
+        );
+
+        let build = || {
+            ClaudeClientBuilder::new()
+                .mcp_http_server("github", "https://example.com/github")
+                .mcp_http_server("linear", "https://example.com/linear")
+        };
+        let mut client = build().build();
+        client.internal.options_mut().cli_path = Some(cli.clone());
+        client.internal.options_mut().require_mcp_servers = true;
+        match client.connect().await {
+            Err(ClaudeSDKError::McpServerFailed { server, message }) => {
+                assert_eq!(server, "linear");
+                assert_eq!(message, "spawn linear-mcp ENOENT");
+            }
+            other => panic!("expected an MCP server failure, got {:?}", other),
+        }
+        assert!(!client.is_connected());
+
+        // Without the requirement the session starts, and disabling the
+        // server restarts it without
+        client.internal.options_mut().require_mcp_servers = false;
+        client.connect().await.unwrap();
+        client
+            .set_mcp_server_enabled("linear", false)
+            .await
+            .unwrap();
+        assert!(client.is_connected());
+        assert_eq!(client.internal.options().disabled_mcp_servers, ["linear"]);
+        let args = std::fs::read_to_string(dir.path().join("claude.args")).unwrap();
+        let spawns: Vec<_> = args.lines().collect();
+        assert_eq!(spawns.len(), 3);
+        assert!(spawns[1].contains("linear"));
+        assert!(!spawns[2].contains("linear"));
+        assert!(spawns[2].contains("github"));
+
+        assert!(client.set_mcp_server_enabled("jira", false).await.is_err());
+        assert_eq!(client.internal.options().disabled_mcp_servers, ["linear"]);
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recent_messages_when_disconnected() {
+        let client = ClaudeClientBuilder::new().message_history(10).build();
+        assert!(client.recent_messages(5).await.is_empty());
+    }
 }
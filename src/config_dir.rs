@@ -0,0 +1,257 @@
+//! Isolated configuration directories for the CLI.
+//!
+//! The CLI keeps its login, settings, project state and session transcripts
+//! in a configuration directory, `~/.claude` unless `CLAUDE_CONFIG_DIR`
+//! says otherwise. Sessions started by the SDK share that directory with
+//! the developer's own CLI by default. Tests that shouldn't depend on it,
+//! and servers running agents for several tenants, can give each client a
+//! directory of its own with
+//! [`ClaudeAgentOptions::config_dir`](crate::ClaudeAgentOptions::config_dir).
+//!
+//! [`ConfigDir`] creates such a directory and writes the settings and API
+//! key the CLI should use in it.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::{ClaudeAgentOptions, ClaudeClient, ConfigDir, Settings};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = ConfigDir::temporary()?;
+//! config.write_api_key(&std::env::var("TENANT_API_KEY")?)?;
+//! config.write_settings(&Settings::new().deny("Read(./.env)"))?;
+//!
+//! let options = ClaudeAgentOptions::new().with_config_dir(config.path());
+//! let mut client = ClaudeClient::new(Some(options));
+//! client.connect().await?;
+//! // ... the directory is removed when `config` is dropped
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+use tempfile::TempDir;
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::settings::Settings;
+
+/// Environment variable the CLI reads its configuration directory from.
+#[cfg(feature = "subprocess")]
+pub(crate) const CONFIG_DIR_ENV: &str = "CLAUDE_CONFIG_DIR";
+
+/// Name of the settings file in a configuration directory.
+const SETTINGS_FILE: &str = "settings.json";
+
+/// A configuration directory for the CLI.
+///
+/// Temporary directories are removed when this value is dropped, so keep it
+/// alive for as long as clients use it. Settings and the API key are written
+/// to `settings.json`, readable by the current user only.
+#[derive(Debug)]
+pub struct ConfigDir {
+    path: PathBuf,
+    temp: Option<TempDir>,
+}
+
+impl ConfigDir {
+    /// Create an empty temporary directory, removed on drop.
+    pub fn temporary() -> Result<Self> {
+        let temp = tempfile::Builder::new()
+            .prefix("claude-config-")
+            .tempdir()?;
+        Ok(Self {
+            path: temp.path().to_path_buf(),
+            temp: Some(temp),
+        })
+    }
+
+    /// Use the directory at `path`, creating it if needed.
+    ///
+    /// The directory is kept on drop, so sessions and logins stored in it
+    /// survive restarts. A directory created here is accessible to the
+    /// current user only.
+    pub fn at(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if !path.exists() {
+            create_private_dir(&path)?;
+        } else if !path.is_dir() {
+            return Err(ClaudeSDKError::configuration(format!(
+                "Config directory is not a directory: {}",
+                path.display()
+            )));
+        }
+        Ok(Self { path, temp: None })
+    }
+
+    /// The directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the directory is removed on drop.
+    pub fn is_temporary(&self) -> bool {
+        self.temp.is_some()
+    }
+
+    /// Merge `settings` into the directory's `settings.json`.
+    ///
+    /// Top-level keys replace those already in the file, except `env`,
+    /// whose variables are added to the existing ones, so an API key
+    /// written earlier is kept.
+    pub fn write_settings(&self, settings: &Settings) -> Result<()> {
+        settings.validate()?;
+        let new = match serde_json::to_value(settings) {
+            Ok(Value::Object(map)) => map,
+            _ => {
+                return Err(ClaudeSDKError::configuration(
+                    "Failed to serialize settings",
+                ))
+            }
+        };
+        let mut current = self.read_settings()?;
+        for (key, value) in new {
+            match (key.as_str(), current.get_mut("env"), value) {
+                ("env", Some(Value::Object(env)), Value::Object(vars)) => env.extend(vars),
+                (_, _, value) => {
+                    current.insert(key, value);
+                }
+            }
+        }
+        self.store_settings(&current)
+    }
+
+    /// Have the CLI authenticate with `api_key`.
+    ///
+    /// The key is stored as `ANTHROPIC_API_KEY` in the `env` section of
+    /// `settings.json`, which the CLI applies to every session, so it takes
+    /// precedence over a key in the SDK's own environment.
+    pub fn write_api_key(&self, api_key: &str) -> Result<()> {
+        if api_key.trim().is_empty() {
+            return Err(ClaudeSDKError::configuration("API key cannot be empty"));
+        }
+        self.write_settings(&Settings::new().env("ANTHROPIC_API_KEY", api_key))
+    }
+
+    /// Read `settings.json`, or an empty object if there is none yet.
+    fn read_settings(&self) -> Result<Map<String, Value>> {
+        let file = self.path.join(SETTINGS_FILE);
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_str(&text) {
+            Ok(Value::Object(map)) => Ok(map),
+            _ => Err(ClaudeSDKError::configuration(format!(
+                "{} does not hold a JSON object",
+                file.display()
+            ))),
+        }
+    }
+
+    fn store_settings(&self, settings: &Map<String, Value>) -> Result<()> {
+        let json = serde_json::to_vec_pretty(settings).map_err(|e| {
+            ClaudeSDKError::configuration(format!("Failed to serialize settings: {}", e))
+        })?;
+        // Write next to the file and rename, so the CLI never reads half of it
+        let mut temp = tempfile::NamedTempFile::new_in(&self.path)?;
+        temp.write_all(&json)?;
+        temp.persist(self.path.join(SETTINGS_FILE))
+            .map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+impl AsRef<Path> for ConfigDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+pub(crate) fn create_private_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(path)?;
+    }
+
+    #[cfg(not(unix))]
+    fs::create_dir_all(path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(config: &ConfigDir) -> Value {
+        let text = fs::read_to_string(config.path().join(SETTINGS_FILE)).unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn test_temporary_dir_is_removed_on_drop() {
+        let config = ConfigDir::temporary().unwrap();
+        let path = config.path().to_path_buf();
+        assert!(config.is_temporary());
+        assert!(path.is_dir());
+        drop(config);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_settings_and_api_key_are_merged() {
+        let config = ConfigDir::temporary().unwrap();
+        config.write_api_key("sk-test").unwrap();
+        config
+            .write_settings(&Settings::new().deny("Read(./.env)").env("NODE_ENV", "test"))
+            .unwrap();
+        config
+            .write_settings(&Settings::new().model("sonnet"))
+            .unwrap();
+
+        let json = settings(&config);
+        assert_eq!(json["env"]["ANTHROPIC_API_KEY"], "sk-test");
+        assert_eq!(json["env"]["NODE_ENV"], "test");
+        assert_eq!(json["permissions"]["deny"][0], "Read(./.env)");
+        assert_eq!(json["model"], "sonnet");
+
+        assert!(config.write_api_key(" ").is_err());
+        fs::write(config.path().join(SETTINGS_FILE), "[]").unwrap();
+        assert!(config.write_api_key("sk-test").is_err());
+    }
+
+    #[test]
+    fn test_persistent_dir() {
+        let parent = tempfile::tempdir().unwrap();
+        let path = parent.path().join("tenants").join("a");
+        let config = ConfigDir::at(&path).unwrap();
+        assert!(!config.is_temporary());
+        config.write_api_key("sk-a").unwrap();
+        drop(config);
+        assert!(path.join(SETTINGS_FILE).is_file());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+            let mode = fs::metadata(path.join(SETTINGS_FILE))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o077, 0);
+        }
+
+        let file = path.join(SETTINGS_FILE);
+        assert!(ConfigDir::at(&file).is_err());
+    }
+}
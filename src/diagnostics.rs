@@ -0,0 +1,299 @@
+//! Typed diagnostics parsed from the CLI's stderr.
+//!
+//! Every line the CLI writes to stderr is turned into a [`Diagnostic`]:
+//! JSON objects keep their fields, and lines starting with a level such as
+//! `Error:`, `[warn]` or Node's `(node:123) DeprecationWarning:` get that
+//! severity. Anything else is [`Info`](DiagnosticSeverity::Info). The SDK
+//! publishes a few events of its own the same way, such as falling back on
+//! another transport (code `transport_failover`).
+//!
+//! [`ClaudeClient::diagnostics`](crate::ClaudeClient::diagnostics) hands out
+//! receivers filtered by severity. Reading stderr never waits for them: a
+//! receiver that falls behind loses the oldest diagnostics, so a slow
+//! consumer can't stall the CLI on a full pipe.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::diagnostics::{Diagnostic, DiagnosticSeverity};
+//!
+//! let diagnostic = Diagnostic::parse("(node:42) [DEP0040] DeprecationWarning: punycode");
+//! assert_eq!(diagnostic.severity, DiagnosticSeverity::Warning);
+//! assert_eq!(diagnostic.code.as_deref(), Some("DEP0040"));
+//! assert_eq!(diagnostic.message, "punycode");
+//!
+//! let diagnostic = Diagnostic::parse(r#"{"level":"error","message":"MCP server failed"}"#);
+//! assert_eq!(diagnostic.severity, DiagnosticSeverity::Error);
+//! ```
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Diagnostics buffered per receiver before it starts lagging.
+pub(crate) const DIAGNOSTICS_CAPACITY: usize = 256;
+
+/// How severe a [`Diagnostic`] is, from least to most.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    /// Debug or trace output.
+    Debug,
+    /// Informational output, and lines without a recognizable level.
+    #[default]
+    Info,
+    /// A warning.
+    Warning,
+    /// An error.
+    Error,
+}
+
+impl DiagnosticSeverity {
+    /// Severity named by a level such as `warn`, `ERROR` or
+    /// `DeprecationWarning`.
+    fn from_level(level: &str) -> Option<Self> {
+        let level = level.to_ascii_lowercase();
+        match level.as_str() {
+            "trace" | "debug" | "verbose" => Some(Self::Debug),
+            "info" | "notice" | "log" => Some(Self::Info),
+            "warn" | "warning" => Some(Self::Warning),
+            "error" | "err" | "fatal" | "critical" | "panic" => Some(Self::Error),
+            _ if level.ends_with("warning") => Some(Self::Warning),
+            _ if level.ends_with("error") || level.ends_with("exception") => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+/// A line of CLI stderr output, or an event reported by the SDK.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// How severe it is.
+    pub severity: DiagnosticSeverity,
+    /// The message, without the level prefix.
+    pub message: String,
+    /// Code such as `DEP0040` or `ECONNRESET`, if the line has one.
+    pub code: Option<String>,
+    /// The whole object, for JSON lines.
+    pub data: Option<Value>,
+    /// The line as written, or the message for events of the SDK.
+    pub line: String,
+}
+
+impl Diagnostic {
+    /// An event reported by the SDK itself.
+    #[cfg(feature = "subprocess")]
+    pub(crate) fn sdk(
+        severity: DiagnosticSeverity,
+        code: &str,
+        message: impl Into<String>,
+    ) -> Self {
+        let message = message.into();
+        Self {
+            severity,
+            line: message.clone(),
+            message,
+            code: Some(code.to_string()),
+            data: None,
+        }
+    }
+
+    /// Parse a line of stderr output.
+    pub fn parse(line: &str) -> Self {
+        let trimmed = line.trim();
+        if trimmed.starts_with('{') {
+            if let Ok(data @ Value::Object(_)) = serde_json::from_str::<Value>(trimmed) {
+                return Self::from_json(line, data);
+            }
+        }
+        let (severity, code, message) = match split_level(trimmed) {
+            Some((severity, code, message)) => (severity, code, message),
+            None => (DiagnosticSeverity::Info, None, trimmed),
+        };
+        Self {
+            severity,
+            message: message.to_string(),
+            code: code.map(String::from),
+            data: None,
+            line: line.to_string(),
+        }
+    }
+
+    fn from_json(line: &str, data: Value) -> Self {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| data.get(*name).and_then(Value::as_str))
+                .map(String::from)
+        };
+        let severity = field(&["level", "severity", "type"])
+            .and_then(|level| DiagnosticSeverity::from_level(&level))
+            .unwrap_or_default();
+        let code = field(&["code"]).or_else(|| {
+            data.get("code")
+                .and_then(Value::as_i64)
+                .map(|code| code.to_string())
+        });
+        Self {
+            severity,
+            message: field(&["message", "msg", "error"]).unwrap_or_else(|| line.trim().to_string()),
+            code,
+            data: Some(data),
+            line: line.to_string(),
+        }
+    }
+}
+
+/// Split `line` into severity, code and message if it starts with a level:
+/// `Error: ...`, `[WARN] ...`, `TypeError [ERR_X]: ...`, or Node's
+/// `(node:123) [DEP0040] DeprecationWarning: ...`.
+fn split_level(line: &str) -> Option<(DiagnosticSeverity, Option<&str>, &str)> {
+    let mut rest = line;
+    let mut code = None;
+    // Node process warnings
+    if let Some(after) = rest.strip_prefix("(node:") {
+        rest = after.split_once(") ")?.1;
+        if let Some((bracketed, after)) = bracketed(rest) {
+            code = Some(bracketed);
+            rest = after.trim_start();
+        }
+    }
+
+    if let Some((level, after)) = bracketed(rest) {
+        let severity = DiagnosticSeverity::from_level(level)?;
+        let message = after.trim_start_matches(':').trim_start();
+        return Some((severity, code, message));
+    }
+
+    let end = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (level, mut after) = rest.split_at(end);
+    let severity = DiagnosticSeverity::from_level(level)?;
+    if let Some((bracketed, tail)) = bracketed(after.trim_start()) {
+        code = Some(bracketed);
+        after = tail;
+    }
+    let message = after.strip_prefix(':')?.trim_start();
+    Some((severity, code, message))
+}
+
+/// Split `[inner] rest` into `inner` and `rest`.
+fn bracketed(s: &str) -> Option<(&str, &str)> {
+    let (inner, rest) = s.strip_prefix('[')?.split_once(']')?;
+    Some((inner, rest))
+}
+
+/// Receives the diagnostics at or above a minimum severity (see
+/// [`ClaudeClient::diagnostics`](crate::ClaudeClient::diagnostics)).
+#[derive(Debug)]
+pub struct DiagnosticReceiver {
+    rx: broadcast::Receiver<Arc<Diagnostic>>,
+    min_severity: DiagnosticSeverity,
+}
+
+impl DiagnosticReceiver {
+    pub(crate) fn new(
+        rx: broadcast::Receiver<Arc<Diagnostic>>,
+        min_severity: DiagnosticSeverity,
+    ) -> Self {
+        Self { rx, min_severity }
+    }
+
+    /// Wait for the next diagnostic.
+    ///
+    /// Fails with [`RecvError::Lagged`] after diagnostics were dropped
+    /// because this receiver fell behind, and with [`RecvError::Closed`]
+    /// once the client is gone.
+    pub async fn recv(&mut self) -> Result<Arc<Diagnostic>, RecvError> {
+        loop {
+            let diagnostic = self.rx.recv().await?;
+            if diagnostic.severity >= self.min_severity {
+                return Ok(diagnostic);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(line: &str) -> (DiagnosticSeverity, Option<String>, String) {
+        let diagnostic = Diagnostic::parse(line);
+        assert_eq!(diagnostic.line, line);
+        (diagnostic.severity, diagnostic.code, diagnostic.message)
+    }
+
+    #[test]
+    fn test_parse_prefixed_lines() {
+        use DiagnosticSeverity::*;
+
+        let cases = [
+            ("Error: spawn ENOENT", Error, None, "spawn ENOENT"),
+            ("warning: config is stale", Warning, None, "config is stale"),
+            ("[WARN] retrying", Warning, None, "retrying"),
+            ("[debug]: frame 3", Debug, None, "frame 3"),
+            (
+                "TypeError [ERR_INVALID_ARG]: bad",
+                Error,
+                Some("ERR_INVALID_ARG"),
+                "bad",
+            ),
+            (
+                "(node:7) ExperimentalWarning: fetch",
+                Warning,
+                None,
+                "fetch",
+            ),
+            (
+                "Error occurred while reading",
+                Info,
+                None,
+                "Error occurred while reading",
+            ),
+            ("plain output", Info, None, "plain output"),
+            ("[tool] running", Info, None, "[tool] running"),
+        ];
+        for (line, severity, code, message) in cases {
+            assert_eq!(
+                parsed(line),
+                (severity, code.map(String::from), message.to_string()),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_json_lines() {
+        let diagnostic =
+            Diagnostic::parse(r#"{"severity":"warning","msg":"slow hook","code":408,"hook":"h1"}"#);
+        assert_eq!(diagnostic.severity, DiagnosticSeverity::Warning);
+        assert_eq!(diagnostic.message, "slow hook");
+        assert_eq!(diagnostic.code.as_deref(), Some("408"));
+        assert_eq!(diagnostic.data.unwrap()["hook"], "h1");
+
+        // Not an object, or not JSON at all
+        assert!(Diagnostic::parse("[1, 2]").data.is_none());
+        assert!(Diagnostic::parse("{ unbalanced").data.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_receiver_filters_by_severity() {
+        let (tx, rx) = broadcast::channel(8);
+        let mut receiver = DiagnosticReceiver::new(rx, DiagnosticSeverity::Warning);
+        for line in ["info: a", "Warning: b", "debug: c", "Error: d"] {
+            tx.send(Arc::new(Diagnostic::parse(line))).unwrap();
+        }
+        drop(tx);
+        assert_eq!(receiver.recv().await.unwrap().message, "b");
+        assert_eq!(receiver.recv().await.unwrap().message, "d");
+        assert!(matches!(receiver.recv().await, Err(RecvError::Closed)));
+    }
+}
@@ -0,0 +1,299 @@
+//! `${VAR}` references in option strings.
+//!
+//! With [`ClaudeAgentOptions::expand_env`](crate::ClaudeAgentOptions::expand_env)
+//! set, environment variable references in the system prompt, the settings
+//! and the inline MCP server configurations (command, arguments,
+//! environment, URL and headers) are resolved when the client connects, so
+//! options loaded from a file can name a secret instead of holding it:
+//!
+//! - `${NAME}` is replaced by the value of `NAME`, and connecting fails if
+//!   it isn't set
+//! - `${NAME:-default}` falls back to `default` if `NAME` is unset or empty
+//! - `$${NAME}` is a literal `${NAME}`
+//! - any other `$`, including `$$`, is left as is
+//!
+//! Variables are looked up in the options' [`env`](crate::ClaudeAgentOptions::env)
+//! first, then in the environment of the process. Settings given as JSON
+//! are expanded string by string, so a value holding quotes or backslashes
+//! can't change their structure.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::env_vars::expand_with;
+//!
+//! let lookup = |name: &str| (name == "API_TOKEN").then(|| "s3cret".to_string());
+//! assert_eq!(
+//!     expand_with("Bearer ${API_TOKEN}", lookup).unwrap(),
+//!     "Bearer s3cret"
+//! );
+//! assert_eq!(expand_with("${REGION:-us-east-1}", lookup).unwrap(), "us-east-1");
+//! assert_eq!(expand_with("costs $$5, not $${API_TOKEN}", lookup).unwrap(), "costs $$5, not ${API_TOKEN}");
+//! assert!(expand_with("${MISSING}", lookup).is_err());
+//! ```
+
+use crate::errors::{ClaudeSDKError, Result};
+#[cfg(feature = "subprocess")]
+use crate::types::{ClaudeAgentOptions, McpServerConfig, McpServersConfig, SystemPromptConfig};
+
+/// Expand the references in `text` from the environment of the process.
+pub fn expand(text: &str) -> Result<String> {
+    expand_with(text, |name| std::env::var(name).ok())
+}
+
+/// Expand the references in `text`, looking variables up with `lookup`.
+pub fn expand_with(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+            continue;
+        }
+        let Some(reference) = rest.strip_prefix("${") else {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let end = reference.find('}').ok_or_else(|| {
+            ClaudeSDKError::configuration(format!(
+                "Unterminated environment variable reference '{}'",
+                rest
+            ))
+        })?;
+        let (name, default) = match reference[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&reference[..end], None),
+        };
+        if !is_name(name) {
+            return Err(ClaudeSDKError::configuration(format!(
+                "Invalid environment variable reference '${{{}}}'",
+                &reference[..end]
+            )));
+        }
+        match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => out.push_str(default),
+            (Some(value), _) => out.push_str(&value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => {
+                return Err(ClaudeSDKError::configuration(format!(
+                    "Environment variable '{}' is not set",
+                    name
+                )))
+            }
+        }
+        rest = &reference[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Whether `name` is a valid variable name: letters, digits and
+/// underscores, not starting with a digit.
+fn is_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `options` with the references in its string fields expanded.
+#[cfg(feature = "subprocess")]
+pub(crate) fn expand_options(options: &ClaudeAgentOptions) -> Result<ClaudeAgentOptions> {
+    let lookup = |name: &str| {
+        options
+            .env
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    };
+    // Errors name the option the reference is in
+    let expand = |field: &str, text: &mut String| -> Result<()> {
+        *text = expand_with(text, lookup)
+            .map_err(|e| ClaudeSDKError::configuration(format!("{}: {}", field, e)))?;
+        Ok(())
+    };
+
+    let mut options = options.clone();
+    match options.system_prompt {
+        Some(SystemPromptConfig::Text(ref mut prompt)) => expand("system_prompt", prompt)?,
+        Some(SystemPromptConfig::Preset(ref mut preset)) => {
+            if let Some(ref mut append) = preset.append {
+                expand("system_prompt.append", append)?;
+            }
+        }
+        None => {}
+    }
+    if let Some(ref mut settings) = options.settings {
+        match serde_json::from_str::<serde_json::Value>(settings) {
+            Ok(mut json) if json.is_object() => {
+                expand_json("settings", &mut json, &expand)?;
+                *settings = json.to_string();
+            }
+            // The path of a settings file
+            _ => expand("settings", settings)?,
+        }
+    }
+    if let McpServersConfig::Map(ref mut servers) = options.mcp_servers {
+        for (name, server) in servers.iter_mut() {
+            let field = |key: &str| format!("mcp_servers.{}.{}", name, key);
+            match server {
+                McpServerConfig::Stdio(config) => {
+                    expand(&field("command"), &mut config.command)?;
+                    for arg in config.args.iter_mut() {
+                        expand(&field("args"), arg)?;
+                    }
+                    for (key, value) in config.env.iter_mut() {
+                        expand(&field(&format!("env.{}", key)), value)?;
+                    }
+                }
+                McpServerConfig::SSE(config) => {
+                    expand(&field("url"), &mut config.url)?;
+                    for (key, value) in config.headers.iter_mut() {
+                        expand(&field(&format!("headers.{}", key)), value)?;
+                    }
+                }
+                McpServerConfig::Http(config) => {
+                    expand(&field("url"), &mut config.url)?;
+                    for (key, value) in config.headers.iter_mut() {
+                        expand(&field(&format!("headers.{}", key)), value)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Expand the references in the string leaves of `value`, naming them
+/// by their path under `field` in errors.
+#[cfg(feature = "subprocess")]
+fn expand_json(
+    field: &str,
+    value: &mut serde_json::Value,
+    expand: &impl Fn(&str, &mut String) -> Result<()>,
+) -> Result<()> {
+    match value {
+        serde_json::Value::String(text) => expand(field, text),
+        serde_json::Value::Array(items) => items
+            .iter_mut()
+            .try_for_each(|item| expand_json(field, item, expand)),
+        serde_json::Value::Object(map) => map
+            .iter_mut()
+            .try_for_each(|(key, item)| expand_json(&format!("{}.{}", field, key), item, expand)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "TOKEN" => Some("abc".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_with() {
+        assert_eq!(expand_with("${TOKEN}-${TOKEN}", lookup).unwrap(), "abc-abc");
+        assert_eq!(expand_with("${EMPTY:-x}${UNSET:-}", lookup).unwrap(), "x");
+        assert_eq!(expand_with("${EMPTY}", lookup).unwrap(), "");
+        // Defaults aren't expanded themselves
+        assert_eq!(expand_with("${UNSET:-${TOKEN}", lookup).unwrap(), "${TOKEN");
+        assert_eq!(
+            expand_with("$TOKEN $ 5$ $$5 $${TOKEN} $$${TOKEN} €${TOKEN}", lookup).unwrap(),
+            "$TOKEN $ 5$ $$5 ${TOKEN} $${TOKEN} €abc"
+        );
+
+        let err = expand_with("a ${UNSET} b", lookup).unwrap_err().to_string();
+        assert!(err.contains("'UNSET' is not set"), "{}", err);
+        assert!(expand_with("${TOKEN", lookup).is_err());
+        assert!(expand_with("${}", lookup).is_err());
+        assert!(expand_with("${1A}", lookup).is_err());
+        assert!(expand_with("${A B}", lookup).is_err());
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[test]
+    fn test_expand_options() {
+        use crate::types::{McpHttpServerConfig, McpStdioServerConfig};
+        use std::collections::HashMap;
+
+        let mut servers = HashMap::new();
+        servers.insert(
+            "api".to_string(),
+            McpServerConfig::Http(McpHttpServerConfig {
+                server_type: "http".to_string(),
+                url: "https://${ENV_VARS_TEST_HOST}/mcp".to_string(),
+                headers: HashMap::from([(
+                    "Authorization".to_string(),
+                    "Bearer ${ENV_VARS_TEST_TOKEN}".to_string(),
+                )]),
+            }),
+        );
+        servers.insert(
+            "local".to_string(),
+            McpServerConfig::Stdio(McpStdioServerConfig {
+                server_type: "stdio".to_string(),
+                command: "server".to_string(),
+                args: vec!["--key=${ENV_VARS_TEST_TOKEN}".to_string()],
+                env: HashMap::new(),
+            }),
+        );
+        let mut options = ClaudeAgentOptions::new()
+            .with_system_prompt("Deploy to ${ENV_VARS_TEST_HOST}, at $$5 a run");
+        options.env.insert(
+            "ENV_VARS_TEST_QUOTED".to_string(),
+            r#"a "quoted" \ value"#.to_string(),
+        );
+        options
+            .env
+            .insert("ENV_VARS_TEST_HOST".to_string(), "example.com".to_string());
+        options
+            .env
+            .insert("ENV_VARS_TEST_TOKEN".to_string(), "t0k3n".to_string());
+        options.mcp_servers = McpServersConfig::Map(servers);
+        options.settings = Some(
+            r#"{"env": {"HOST": "${ENV_VARS_TEST_HOST}", "NOTE": "${ENV_VARS_TEST_QUOTED}"}}"#
+                .to_string(),
+        );
+
+        let expanded = expand_options(&options).unwrap();
+        assert!(matches!(
+            expanded.system_prompt,
+            Some(SystemPromptConfig::Text(ref prompt)) if prompt == "Deploy to example.com, at $$5 a run"
+        ));
+        // Values are JSON strings, whatever they contain
+        let settings: serde_json::Value =
+            serde_json::from_str(expanded.settings.as_deref().unwrap()).unwrap();
+        assert_eq!(
+            settings,
+            serde_json::json!({"env": {"HOST": "example.com", "NOTE": r#"a "quoted" \ value"#}})
+        );
+        let McpServersConfig::Map(ref servers) = expanded.mcp_servers else {
+            panic!("expected inline servers");
+        };
+        match &servers["api"] {
+            McpServerConfig::Http(config) => {
+                assert_eq!(config.url, "https://example.com/mcp");
+                assert_eq!(config.headers["Authorization"], "Bearer t0k3n");
+            }
+            other => panic!("unexpected server {:?}", other),
+        }
+        match &servers["local"] {
+            McpServerConfig::Stdio(config) => assert_eq!(config.args, vec!["--key=t0k3n"]),
+            other => panic!("unexpected server {:?}", other),
+        }
+
+        // A missing variable names the option it's in
+        options.env.remove("ENV_VARS_TEST_TOKEN");
+        let err = expand_options(&options).unwrap_err().to_string();
+        assert!(err.contains("mcp_servers."), "{}", err);
+        assert!(err.contains("'ENV_VARS_TEST_TOKEN' is not set"), "{}", err);
+    }
+}
@@ -0,0 +1,193 @@
+//! Messages annotated with the turn and prompt they belong to.
+//!
+//! A long session interleaves prompts, assistant messages, tool results and
+//! subagent traffic. [`ClaudeClient::receive_envelopes`](crate::ClaudeClient::receive_envelopes)
+//! wraps each message in a [`MessageEnvelope`] recording which response it
+//! is part of and which prompt started that response, so logs can be
+//! grouped by turn without replaying the conversation.
+//!
+//! Turns are counted from the first response read through envelopes. The
+//! prompt's UUID is only known when the CLI echoes prompts back, which it
+//! does with `--replay-user-messages`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::{ClaudeAgentOptions, ClaudeClient};
+//! use tokio_stream::StreamExt;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut options = ClaudeAgentOptions::new();
+//! options
+//!     .extra_args
+//!     .insert("replay-user-messages".to_string(), None);
+//!
+//! let mut client = ClaudeClient::new(Some(options));
+//! client.connect().await?;
+//! client.query("Review the diff").await?;
+//!
+//! let mut envelopes = Box::pin(client.receive_envelopes());
+//! while let Some(envelope) = envelopes.next().await {
+//!     let envelope = envelope?;
+//!     println!("{}", serde_json::to_string(&envelope)?);
+//!     if envelope.message.is_result() {
+//!         break;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::export::SCHEMA_VERSION;
+use crate::types::{ContentBlock, Message, UserMessage, UserMessageContent};
+
+/// A message with the turn and prompt it belongs to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageEnvelope {
+    /// [`SCHEMA_VERSION`](crate::export::SCHEMA_VERSION) of the record, 0
+    /// if it predates versioning.
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Index of the response the message is part of, from 0. A turn ends
+    /// with its result message.
+    pub turn: u64,
+    /// UUID of the prompt that started the turn, if the CLI echoed it.
+    pub parent_prompt_id: Option<String>,
+    /// Session tags when the message was read (see
+    /// [`ClaudeAgentOptions::session_tags`](crate::ClaudeAgentOptions::session_tags)).
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tags: HashMap<String, String>,
+    /// The message.
+    pub message: Message,
+}
+
+/// Tracks the turn and prompt of the messages it wraps.
+#[derive(Debug, Default)]
+pub(crate) struct EnvelopeTracker {
+    turn: u64,
+    prompt_id: Option<String>,
+    /// Whether the last message ended a turn.
+    ended: bool,
+}
+
+impl EnvelopeTracker {
+    pub(crate) fn wrap(
+        &mut self,
+        message: Message,
+        tags: &HashMap<String, String>,
+    ) -> MessageEnvelope {
+        if self.ended {
+            self.turn += 1;
+            self.prompt_id = None;
+            self.ended = false;
+        }
+        match message {
+            Message::User(ref user) if user.uuid.is_some() && is_prompt(user) => {
+                self.prompt_id = user.uuid.clone();
+            }
+            Message::Result(_) => self.ended = true,
+            _ => {}
+        }
+        MessageEnvelope {
+            schema_version: SCHEMA_VERSION,
+            turn: self.turn,
+            parent_prompt_id: self.prompt_id.clone(),
+            tags: tags.clone(),
+            message,
+        }
+    }
+}
+
+/// Whether `user` is a prompt, rather than tool results or a subagent's
+/// message.
+fn is_prompt(user: &UserMessage) -> bool {
+    if user.parent_tool_use_id.is_some() {
+        return false;
+    }
+    match user.content {
+        UserMessageContent::Text(_) => true,
+        UserMessageContent::Blocks(ref blocks) => !blocks
+            .iter()
+            .all(|block| matches!(block, ContentBlock::ToolResult(_))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(value: serde_json::Value) -> Message {
+        crate::_internal::parse_message(value).unwrap()
+    }
+
+    fn result() -> Message {
+        message(json!({
+            "type": "result",
+            "subtype": "success",
+            "duration_ms": 1,
+            "duration_api_ms": 1,
+            "is_error": false,
+            "num_turns": 1,
+            "session_id": "s"
+        }))
+    }
+
+    #[test]
+    fn test_envelopes_follow_turns() {
+        let messages = [
+            message(json!({
+                "type": "user",
+                "uuid": "u1",
+                "message": {"role": "user", "content": "List files"}
+            })),
+            message(json!({
+                "type": "assistant",
+                "message": {
+                    "content": [{"type": "tool_use", "id": "t1", "name": "Bash", "input": {}}],
+                    "model": "m"
+                }
+            })),
+            message(json!({
+                "type": "user",
+                "uuid": "r1",
+                "message": {
+                    "role": "user",
+                    "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "a.rs"}]
+                }
+            })),
+            result(),
+            // Prompts the CLI didn't echo have no ID
+            message(json!({
+                "type": "assistant",
+                "message": {"content": [{"type": "text", "text": "Hi"}], "model": "m"}
+            })),
+            result(),
+        ];
+
+        let mut tracker = EnvelopeTracker::default();
+        let tags = HashMap::from([("job".to_string(), "nightly".to_string())]);
+        let envelopes: Vec<_> = messages
+            .into_iter()
+            .map(|message| tracker.wrap(message, &tags))
+            .inspect(|envelope| assert_eq!(envelope.tags, tags))
+            .map(|envelope| (envelope.turn, envelope.parent_prompt_id))
+            .collect();
+        let prompt = Some("u1".to_string());
+        assert_eq!(
+            envelopes,
+            [
+                (0, prompt.clone()),
+                (0, prompt.clone()),
+                (0, prompt.clone()),
+                (0, prompt),
+                (1, None),
+                (1, None),
+            ]
+        );
+    }
+}
@@ -4,8 +4,106 @@
 //! failure modes when interacting with the Claude Code CLI.
 
 use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
 
+/// Phase of an operation in which a timeout occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPhase {
+    /// Spawning and connecting to the CLI process.
+    Spawn,
+    /// Waiting for a control request response (including initialization).
+    Control,
+    /// Waiting for messages on the response stream.
+    Stream,
+}
+
+impl std::fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spawn => write!(f, "spawn"),
+            Self::Control => write!(f, "control request"),
+            Self::Stream => write!(f, "stream"),
+        }
+    }
+}
+
+/// Why the CLI process could not be launched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CLILaunchErrorKind {
+    /// The executable exists but could not be executed.
+    PermissionDenied,
+    /// Running `claude --version` failed.
+    VersionCheckFailed,
+    /// The configured working directory does not exist.
+    WorkingDirMissing,
+    /// The SDK may not switch to the user in
+    /// [`run_as`](crate::ClaudeAgentOptions::run_as).
+    InsufficientPrivileges,
+    /// Any other spawn failure.
+    Other,
+}
+
+impl std::fmt::Display for CLILaunchErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PermissionDenied => write!(f, "permission denied"),
+            Self::VersionCheckFailed => write!(f, "version check failed"),
+            Self::WorkingDirMissing => write!(f, "working directory missing"),
+            Self::InsufficientPrivileges => write!(f, "insufficient privileges"),
+            Self::Other => write!(f, "spawn failed"),
+        }
+    }
+}
+
+/// Details of a failed CLI launch.
+///
+/// Carries the exact invocation so onboarding problems can be diagnosed
+/// from the error alone. Values of arguments that may contain secrets are
+/// redacted in `argv`.
+#[derive(Debug, Error)]
+pub struct CLILaunchError {
+    /// Why the launch failed.
+    pub kind: CLILaunchErrorKind,
+    /// Human-readable description.
+    pub message: String,
+    /// The CLI executable that was launched.
+    pub cli_path: PathBuf,
+    /// Command-line arguments, with sensitive values redacted.
+    pub argv: Vec<String>,
+    /// Working directory of the process, if one was set.
+    pub cwd: Option<PathBuf>,
+    /// `PATH` as seen by the process.
+    pub path_env: Option<String>,
+    /// Underlying IO error, if any.
+    #[source]
+    pub source: Option<io::Error>,
+}
+
+impl std::fmt::Display for CLILaunchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to launch Claude CLI ({}): {}\n  command: {}",
+            self.kind,
+            self.message,
+            self.cli_path.display()
+        )?;
+        for arg in &self.argv {
+            write!(f, " {}", arg)?;
+        }
+        match self.cwd {
+            Some(ref cwd) => write!(f, "\n  cwd: {}", cwd.display())?,
+            None => write!(f, "\n  cwd: (inherited)")?,
+        }
+        write!(
+            f,
+            "\n  PATH: {}",
+            self.path_env.as_deref().unwrap_or("(unset)")
+        )
+    }
+}
+
 /// Main error type for the Claude Agents SDK.
 ///
 /// All errors in this SDK derive from this type, making it easy to handle
@@ -19,6 +117,10 @@ pub enum ClaudeSDKError {
         message: String,
     },
 
+    /// The CLI process could not be launched.
+    #[error(transparent)]
+    CLILaunch(Box<CLILaunchError>),
+
     /// Failed to connect to or communicate with the CLI process.
     #[error("CLI connection error: {message}")]
     CLIConnection {
@@ -84,10 +186,53 @@ pub enum ClaudeSDKError {
     Interrupted,
 
     /// A timeout occurred.
-    #[error("Operation timed out after {duration_ms}ms")]
+    #[error(
+        "Operation timed out after {duration_ms}ms{}",
+        .phase.map(|p| format!(" during {}", p)).unwrap_or_default()
+    )]
     Timeout {
         /// Duration in milliseconds
         duration_ms: u64,
+        /// The phase of the operation that timed out, if known
+        phase: Option<TimeoutPhase>,
+    },
+
+    /// The CLI needs to be logged in again, e.g. because its OAuth token
+    /// expired or its API key was revoked.
+    #[error("Authentication required: {instructions}")]
+    AuthRequired {
+        /// What the CLI said to do about it, e.g. run `/login`
+        instructions: String,
+    },
+
+    /// An MCP server couldn't be started, with
+    /// [`require_mcp_servers`](crate::ClaudeAgentOptions::require_mcp_servers)
+    /// set.
+    #[error("MCP server '{server}' failed to start: {message}")]
+    McpServerFailed {
+        /// Name of the server
+        server: String,
+        /// What the CLI reported
+        message: String,
+    },
+
+    /// The session to [`resume`](crate::ClaudeAgentOptions::resume) doesn't
+    /// exist, or was started in a different working directory.
+    #[error("Session not found: {session_id}")]
+    SessionNotFound {
+        /// ID of the session
+        session_id: String,
+    },
+
+    /// A frame was too large to write to the CLI, with an
+    /// [`outbound_frame_limit`](crate::ClaudeAgentOptions::outbound_frame_limit)
+    /// set.
+    #[error("Frame of {size} bytes exceeds the outbound limit of {limit} bytes")]
+    FrameTooLarge {
+        /// Size of the frame, in bytes
+        size: usize,
+        /// The limit, in bytes
+        limit: usize,
     },
 
     /// The CLI version is too old.
@@ -126,6 +271,11 @@ impl ClaudeSDKError {
         }
     }
 
+    /// Create a CLI launch error.
+    pub fn cli_launch(error: CLILaunchError) -> Self {
+        Self::CLILaunch(Box::new(error))
+    }
+
     /// Create a CLI connection error.
     pub fn cli_connection(message: impl Into<String>) -> Self {
         Self::CLIConnection {
@@ -236,7 +386,45 @@ impl ClaudeSDKError {
 
     /// Create a timeout error.
     pub fn timeout(duration_ms: u64) -> Self {
-        Self::Timeout { duration_ms }
+        Self::Timeout {
+            duration_ms,
+            phase: None,
+        }
+    }
+
+    /// Create a timeout error for a specific phase.
+    pub fn timeout_in(phase: TimeoutPhase, duration_ms: u64) -> Self {
+        Self::Timeout {
+            duration_ms,
+            phase: Some(phase),
+        }
+    }
+
+    /// Create an authentication required error.
+    pub fn auth_required(instructions: impl Into<String>) -> Self {
+        Self::AuthRequired {
+            instructions: instructions.into(),
+        }
+    }
+
+    /// Create an MCP server failure error.
+    pub fn mcp_server_failed(server: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::McpServerFailed {
+            server: server.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Create a session not found error.
+    pub fn session_not_found(session_id: impl Into<String>) -> Self {
+        Self::SessionNotFound {
+            session_id: session_id.into(),
+        }
+    }
+
+    /// Create a frame too large error.
+    pub fn frame_too_large(size: usize, limit: usize) -> Self {
+        Self::FrameTooLarge { size, limit }
     }
 
     /// Create a version mismatch error.
@@ -266,6 +454,43 @@ impl ClaudeSDKError {
         matches!(self, Self::CLINotFound { .. })
     }
 
+    /// Check if this error means the CLI can't be used at all: it's missing
+    /// or older than [`MIN_CLI_VERSION`](crate::MIN_CLI_VERSION).
+    pub fn is_cli_unavailable(&self) -> bool {
+        self.is_cli_not_found() || matches!(self, Self::VersionMismatch { .. })
+    }
+
+    /// Check if this error means the CLI needs to be logged in again.
+    pub fn is_auth_required(&self) -> bool {
+        matches!(self, Self::AuthRequired { .. })
+    }
+
+    /// Check if this error means the session to resume doesn't exist.
+    pub fn is_session_not_found(&self) -> bool {
+        matches!(self, Self::SessionNotFound { .. })
+    }
+
+    /// Check if this error means a frame was over the outbound size limit.
+    pub fn is_frame_too_large(&self) -> bool {
+        matches!(self, Self::FrameTooLarge { .. })
+    }
+
+    /// Get the launch failure details, if this is a CLI launch error.
+    pub fn launch_error(&self) -> Option<&CLILaunchError> {
+        match self {
+            Self::CLILaunch(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Get the phase in which a timeout occurred, if this is a timeout error.
+    pub fn timeout_phase(&self) -> Option<TimeoutPhase> {
+        match self {
+            Self::Timeout { phase, .. } => *phase,
+            _ => None,
+        }
+    }
+
     /// Check if this error is recoverable (might succeed if retried).
     pub fn is_recoverable(&self) -> bool {
         matches!(
@@ -300,4 +525,49 @@ mod tests {
         assert!(ClaudeSDKError::timeout(1000).is_recoverable());
         assert!(!ClaudeSDKError::cli_not_found("not found").is_recoverable());
     }
+
+    #[test]
+    fn test_cli_unavailable() {
+        assert!(ClaudeSDKError::cli_not_found("not found").is_cli_unavailable());
+        assert!(ClaudeSDKError::version_mismatch("1.0.0", "2.0.0").is_cli_unavailable());
+        assert!(!ClaudeSDKError::timeout(1000).is_cli_unavailable());
+    }
+
+    #[test]
+    fn test_auth_required() {
+        let err = ClaudeSDKError::auth_required("Please run /login");
+        assert!(err.is_auth_required());
+        assert!(!err.is_recoverable());
+        assert!(err.to_string().contains("Please run /login"));
+    }
+
+    #[test]
+    fn test_session_not_found() {
+        let err = ClaudeSDKError::session_not_found("abc");
+        assert!(err.is_session_not_found());
+        assert!(!err.is_recoverable());
+        assert_eq!(err.to_string(), "Session not found: abc");
+    }
+
+    #[test]
+    fn test_cli_launch_error() {
+        let err = ClaudeSDKError::cli_launch(CLILaunchError {
+            kind: CLILaunchErrorKind::PermissionDenied,
+            message: "Permission denied".to_string(),
+            cli_path: PathBuf::from("/opt/claude"),
+            argv: vec!["--verbose".to_string()],
+            cwd: None,
+            path_env: Some("/usr/bin".to_string()),
+            source: None,
+        });
+        assert!(!err.is_cli_not_found());
+        assert_eq!(
+            err.launch_error().unwrap().kind,
+            CLILaunchErrorKind::PermissionDenied
+        );
+
+        let text = err.to_string();
+        assert!(text.contains("/opt/claude --verbose"));
+        assert!(text.contains("PATH: /usr/bin"));
+    }
 }
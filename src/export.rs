@@ -0,0 +1,158 @@
+//! Versioning of the records the SDK exports.
+//!
+//! Session bundles, session manifests (also logged to the
+//! `claude_agents_sdk::audit` tracing target) and message envelopes carry a
+//! `schema_version` field set to [`SCHEMA_VERSION`]. It only changes when a
+//! release changes one of these formats in a way older readers can't
+//! handle, so tools built on exports can tell what they are reading.
+//!
+//! Records written before versioning have no `schema_version` and count as
+//! version 0. [`migrate`] upgrades a record to the current version and
+//! [`migrate_ndjson`] does the same for a file of records, one per line,
+//! such as envelopes logged as JSON lines. Records from a newer SDK are
+//! rejected rather than misread.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::export::{migrate, schema_version, SCHEMA_VERSION};
+//! use claude_agents_sdk::MessageEnvelope;
+//!
+//! let recorded = serde_json::json!({
+//!     "turn": 0,
+//!     "parent_prompt_id": null,
+//!     "message": {"type": "user", "content": "Hi"}
+//! });
+//! assert_eq!(schema_version(&recorded), 0);
+//!
+//! let envelope: MessageEnvelope = serde_json::from_value(migrate(recorded)?)?;
+//! assert_eq!(envelope.schema_version, SCHEMA_VERSION);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use serde_json::Value;
+
+use crate::errors::{ClaudeSDKError, Result};
+
+/// Version of the export schema written by this SDK.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Name of the version field of exported records.
+const FIELD: &str = "schema_version";
+
+/// Field holding the version of session bundles written before versioning,
+/// which numbered their own format from 1.
+const LEGACY_BUNDLE_FIELD: &str = "version";
+
+/// Schema version of `record`, 0 if it predates versioning.
+pub fn schema_version(record: &Value) -> u32 {
+    let version = match record.get(FIELD) {
+        Some(version) => version,
+        None if is_bundle(record) => match record.get(LEGACY_BUNDLE_FIELD) {
+            Some(version) => version,
+            None => return 0,
+        },
+        None => return 0,
+    };
+    version
+        .as_u64()
+        .map_or(u32::MAX, |v| u32::try_from(v).unwrap_or(u32::MAX))
+}
+
+/// Upgrade an exported record to [`SCHEMA_VERSION`].
+///
+/// Records already at the current version are returned as is. Fails if
+/// `record` isn't a JSON object or comes from a newer SDK.
+pub fn migrate(mut record: Value) -> Result<Value> {
+    let version = schema_version(&record);
+    if version > SCHEMA_VERSION {
+        return Err(ClaudeSDKError::configuration(format!(
+            "Record has schema version {}, but this SDK reads up to version {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+    let bundle = is_bundle(&record);
+    let Some(fields) = record.as_object_mut() else {
+        return Err(ClaudeSDKError::configuration(
+            "Exported records must be JSON objects",
+        ));
+    };
+    // Version 0 to 1: records gain the field, and bundles rename theirs
+    if bundle {
+        fields.remove(LEGACY_BUNDLE_FIELD);
+        if let Some(manifest) = fields.remove("manifest") {
+            fields.insert("manifest".to_string(), migrate(manifest)?);
+        }
+    }
+    fields.insert(FIELD.to_string(), SCHEMA_VERSION.into());
+    Ok(record)
+}
+
+/// Upgrade a file of records, one JSON object per line.
+///
+/// Blank lines are dropped. Errors name the line they are on.
+pub fn migrate_ndjson(text: &str) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let in_line = |e: &dyn std::fmt::Display| {
+            ClaudeSDKError::configuration(format!("Line {}: {}", index + 1, e))
+        };
+        let record = serde_json::from_str(line).map_err(|e| in_line(&e))?;
+        let record = migrate(record).map_err(|e| in_line(&e))?;
+        out.push_str(&record.to_string());
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Whether `record` is a session bundle.
+fn is_bundle(record: &Value) -> bool {
+    record.get("manifest").is_some() && record.get("transcript").is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrate() {
+        let manifest = json!({"sdk_version": "0.4.0", "allowed_tools": []});
+        let bundle = json!({
+            "version": 1,
+            "session_id": "sess_1",
+            "manifest": manifest,
+            "transcript": []
+        });
+        assert_eq!(schema_version(&bundle), 1);
+        assert_eq!(schema_version(&manifest), 0);
+
+        let migrated = migrate(bundle).unwrap();
+        assert_eq!(migrated["schema_version"], SCHEMA_VERSION);
+        assert!(migrated.get("version").is_none());
+        assert_eq!(migrated["manifest"]["schema_version"], SCHEMA_VERSION);
+        assert_eq!(migrated["session_id"], "sess_1");
+        assert_eq!(migrate(migrated.clone()).unwrap(), migrated);
+
+        let newer = json!({"schema_version": SCHEMA_VERSION + 1});
+        let err = migrate(newer).unwrap_err().to_string();
+        assert!(err.contains("reads up to version"), "{}", err);
+        assert!(migrate(json!({"schema_version": "1"})).is_err());
+        assert!(migrate(json!([1])).is_err());
+    }
+
+    #[test]
+    fn test_migrate_ndjson() {
+        let text = "{\"turn\":0}\n\n{\"turn\":1,\"schema_version\":1}\n";
+        assert_eq!(
+            migrate_ndjson(text).unwrap(),
+            "{\"schema_version\":1,\"turn\":0}\n{\"schema_version\":1,\"turn\":1}\n"
+        );
+        let err = migrate_ndjson("{}\nnot json\n").unwrap_err().to_string();
+        assert!(err.contains("Line 2"), "{}", err);
+    }
+}
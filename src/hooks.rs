@@ -0,0 +1,533 @@
+//! Typed hook registration.
+//!
+//! [`HookCallback`] receives the untyped [`HookInput`] union, so callbacks
+//! usually start with an `if let HookInput::PreToolUse(..)` check. The
+//! [`HookRegistry`] lets callbacks be registered per event instead, with the
+//! concrete input type carried in the callback signature:
+//!
+//! ```rust
+//! use claude_agents_sdk::hooks::{HookRegistry, PreToolUse};
+//! use claude_agents_sdk::{ClaudeAgentOptions, HookOutput};
+//!
+//! let hooks = HookRegistry::new().on_matching(PreToolUse, "Bash", |input| async move {
+//!     // `input` is a `PreToolUseHookInput`
+//!     println!("Running: {}", input.tool_input);
+//!     HookOutput::default()
+//! });
+//!
+//! let options = ClaudeAgentOptions::new().with_hooks(hooks);
+//! ```
+//!
+//! Tool results can be reviewed before they are fed back to the model with
+//! [`HookRegistry::filter_tool_results`]:
+//!
+//! ```rust
+//! use claude_agents_sdk::hooks::{redact_tool_result, HookRegistry};
+//!
+//! let hooks = HookRegistry::new().filter_tool_results(|input| {
+//!     redact_tool_result(&input.tool_response, &["sk-live-1234"])
+//! });
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::types::*;
+
+/// A hook event with a statically known input type.
+///
+/// Implemented by the marker types in this module, one per [`HookEvent`].
+pub trait TypedHookEvent: Send + Sync + 'static {
+    /// The concrete input type delivered to callbacks for this event.
+    type Input: Send + 'static;
+
+    /// The event this marker represents.
+    const EVENT: HookEvent;
+
+    /// Extract the concrete input, returning `None` for other events.
+    fn extract(input: HookInput) -> Option<Self::Input>;
+}
+
+macro_rules! typed_hook_event {
+    ($(#[$meta:meta])* $name:ident, $input:ty) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name;
+
+        impl TypedHookEvent for $name {
+            type Input = $input;
+            const EVENT: HookEvent = HookEvent::$name;
+
+            fn extract(input: HookInput) -> Option<Self::Input> {
+                match input {
+                    HookInput::$name(input) => Some(input),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+typed_hook_event!(
+    /// Marker for [`HookEvent::PreToolUse`].
+    PreToolUse,
+    PreToolUseHookInput
+);
+typed_hook_event!(
+    /// Marker for [`HookEvent::PostToolUse`].
+    PostToolUse,
+    PostToolUseHookInput
+);
+typed_hook_event!(
+    /// Marker for [`HookEvent::PostToolUseFailure`].
+    PostToolUseFailure,
+    PostToolUseFailureHookInput
+);
+typed_hook_event!(
+    /// Marker for [`HookEvent::UserPromptSubmit`].
+    UserPromptSubmit,
+    UserPromptSubmitHookInput
+);
+typed_hook_event!(
+    /// Marker for [`HookEvent::Stop`].
+    Stop,
+    StopHookInput
+);
+typed_hook_event!(
+    /// Marker for [`HookEvent::SubagentStop`].
+    SubagentStop,
+    SubagentStopHookInput
+);
+typed_hook_event!(
+    /// Marker for [`HookEvent::PreCompact`].
+    PreCompact,
+    PreCompactHookInput
+);
+
+/// What to do with a tool result reviewed by
+/// [`HookRegistry::filter_tool_results`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolResultDecision {
+    /// Pass the result to the model unchanged.
+    Pass,
+    /// Replace the result, e.g. with redacted or truncated output.
+    ///
+    /// The CLI only lets hooks replace the output of MCP tools (`mcp__*`).
+    /// For built-in tools the result is blocked instead, with the
+    /// replacement as the reason the model sees.
+    Replace(serde_json::Value),
+    /// Tell the model to disregard the result, giving a reason.
+    Block(String),
+}
+
+impl ToolResultDecision {
+    /// Convert into the hook output for a result of `tool_name`.
+    pub fn into_hook_output(self, tool_name: &str) -> HookOutput {
+        let output = match self {
+            Self::Pass => return HookOutput::default(),
+            Self::Replace(replacement) if tool_name.starts_with("mcp__") => SyncHookOutput {
+                hook_specific_output: Some(HookSpecificOutput::PostToolUse(
+                    PostToolUseHookSpecificOutput {
+                        hook_event_name: "PostToolUse".to_string(),
+                        updated_mcp_tool_output: Some(replacement),
+                        ..Default::default()
+                    },
+                )),
+                ..Default::default()
+            },
+            Self::Replace(replacement) => {
+                let text = match replacement {
+                    serde_json::Value::String(text) => text,
+                    other => other.to_string(),
+                };
+                SyncHookOutput {
+                    decision: Some("block".to_string()),
+                    reason: Some(format!(
+                        "The {} result was filtered; disregard the original output and use this instead:\n{}",
+                        tool_name, text
+                    )),
+                    ..Default::default()
+                }
+            }
+            Self::Block(reason) => SyncHookOutput {
+                decision: Some("block".to_string()),
+                reason: Some(reason),
+                ..Default::default()
+            },
+        };
+        HookOutput::Sync(output)
+    }
+}
+
+/// Apply `f` to every string in `value`, returning `None` if none changed.
+fn map_strings(
+    value: &serde_json::Value,
+    f: &impl Fn(&str) -> Option<String>,
+) -> Option<serde_json::Value> {
+    use serde_json::Value;
+
+    match value {
+        Value::String(s) => f(s).map(Value::String),
+        Value::Array(items) => {
+            let mapped: Vec<_> = items.iter().map(|item| map_strings(item, f)).collect();
+            mapped.iter().any(Option::is_some).then(|| {
+                Value::Array(
+                    mapped
+                        .into_iter()
+                        .zip(items)
+                        .map(|(new, old)| new.unwrap_or_else(|| old.clone()))
+                        .collect(),
+                )
+            })
+        }
+        Value::Object(map) => {
+            let mapped: Vec<_> = map.values().map(|item| map_strings(item, f)).collect();
+            mapped.iter().any(Option::is_some).then(|| {
+                Value::Object(
+                    mapped
+                        .into_iter()
+                        .zip(map)
+                        .map(|(new, (key, old))| (key.clone(), new.unwrap_or_else(|| old.clone())))
+                        .collect(),
+                )
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A `PreToolUse` output denying the call for `reason`.
+pub(crate) fn deny_tool_use(reason: String) -> HookOutput {
+    HookOutput::Sync(SyncHookOutput {
+        hook_specific_output: Some(HookSpecificOutput::PreToolUse(
+            PreToolUseHookSpecificOutput {
+                hook_event_name: "PreToolUse".to_string(),
+                permission_decision: Some(PermissionBehavior::Deny),
+                permission_decision_reason: Some(reason),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    })
+}
+
+/// Replace every occurrence of `secrets` in a tool result with `[REDACTED]`.
+///
+/// Returns [`ToolResultDecision::Pass`] if no secret occurs.
+pub fn redact_tool_result(
+    response: &serde_json::Value,
+    secrets: &[impl AsRef<str>],
+) -> ToolResultDecision {
+    let redact = |s: &str| {
+        let mut redacted = s.to_string();
+        for secret in secrets.iter().map(AsRef::as_ref) {
+            if !secret.is_empty() {
+                redacted = redacted.replace(secret, "[REDACTED]");
+            }
+        }
+        (redacted != s).then_some(redacted)
+    };
+    match map_strings(response, &redact) {
+        Some(redacted) => ToolResultDecision::Replace(redacted),
+        None => ToolResultDecision::Pass,
+    }
+}
+
+/// Truncate every string in a tool result to at most `max_chars` characters.
+///
+/// Returns [`ToolResultDecision::Pass`] if nothing is longer.
+pub fn truncate_tool_result(response: &serde_json::Value, max_chars: usize) -> ToolResultDecision {
+    let truncate = |s: &str| {
+        let (end, _) = s.char_indices().nth(max_chars)?;
+        let omitted = s[end..].chars().count();
+        Some(format!(
+            "{}... [{} characters truncated]",
+            &s[..end],
+            omitted
+        ))
+    };
+    match map_strings(response, &truncate) {
+        Some(truncated) => ToolResultDecision::Replace(truncated),
+        None => ToolResultDecision::Pass,
+    }
+}
+
+/// Builder for hook configurations with typed callbacks.
+///
+/// The event marker passed to [`on`](Self::on) determines the callback's
+/// input type, so a callback can't be registered against the wrong event.
+///
+/// Converts into the `HashMap<HookEvent, Vec<HookMatcher>>` used by
+/// [`ClaudeAgentOptions::hooks`].
+#[derive(Clone, Default)]
+pub struct HookRegistry {
+    matchers: HashMap<HookEvent, Vec<HookMatcher>>,
+}
+
+impl HookRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a callback for every occurrence of `event`.
+    pub fn on<E, F, Fut>(self, _event: E, callback: F) -> Self
+    where
+        E: TypedHookEvent,
+        F: Fn(E::Input) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HookOutput> + Send + 'static,
+    {
+        self.register::<E, F, Fut>(None, callback)
+    }
+
+    /// Register a callback for `event` filtered by a matcher pattern
+    /// (e.g. a tool name).
+    pub fn on_matching<E, F, Fut>(self, _event: E, matcher: impl Into<String>, callback: F) -> Self
+    where
+        E: TypedHookEvent,
+        F: Fn(E::Input) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HookOutput> + Send + 'static,
+    {
+        self.register::<E, F, Fut>(Some(matcher.into()), callback)
+    }
+
+    /// Review every tool result before it is fed back to the model.
+    ///
+    /// The filter runs as a `PostToolUse` hook, so the tool has already run;
+    /// it decides what the model gets to see of the result.
+    pub fn filter_tool_results<F>(self, filter: F) -> Self
+    where
+        F: Fn(&PostToolUseHookInput) -> ToolResultDecision + Send + Sync + 'static,
+    {
+        self.register_filter(None, filter)
+    }
+
+    /// Review the results of tools matching a pattern before they are fed
+    /// back to the model.
+    pub fn filter_tool_results_matching<F>(self, matcher: impl Into<String>, filter: F) -> Self
+    where
+        F: Fn(&PostToolUseHookInput) -> ToolResultDecision + Send + Sync + 'static,
+    {
+        self.register_filter(Some(matcher.into()), filter)
+    }
+
+    fn register_filter<F>(self, matcher: Option<String>, filter: F) -> Self
+    where
+        F: Fn(&PostToolUseHookInput) -> ToolResultDecision + Send + Sync + 'static,
+    {
+        self.register::<PostToolUse, _, _>(matcher, move |input| {
+            let output = filter(&input).into_hook_output(&input.tool_name);
+            async move { output }
+        })
+    }
+
+    /// Register a callback for every occurrence of `event` that is also
+    /// given the tool use ID, which the CLI waits for up to `timeout`
+    /// seconds.
+    pub(crate) fn on_tool_use<E, F, Fut>(self, _event: E, timeout: Option<f64>, callback: F) -> Self
+    where
+        E: TypedHookEvent,
+        F: Fn(E::Input, Option<String>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HookOutput> + Send + 'static,
+    {
+        self.register_with::<E, F, Fut>(None, timeout, callback)
+    }
+
+    fn register<E, F, Fut>(self, matcher: Option<String>, callback: F) -> Self
+    where
+        E: TypedHookEvent,
+        F: Fn(E::Input) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HookOutput> + Send + 'static,
+    {
+        self.register_with::<E, _, _>(matcher, None, move |input, _| callback(input))
+    }
+
+    fn register_with<E, F, Fut>(
+        mut self,
+        matcher: Option<String>,
+        timeout: Option<f64>,
+        callback: F,
+    ) -> Self
+    where
+        E: TypedHookEvent,
+        F: Fn(E::Input, Option<String>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HookOutput> + Send + 'static,
+    {
+        let callback = Arc::new(callback);
+        let hook: HookCallback = Arc::new(move |input, tool_use_id, _context| {
+            let callback = Arc::clone(&callback);
+            Box::pin(async move {
+                match E::extract(input) {
+                    Some(input) => callback(input, tool_use_id).await,
+                    None => {
+                        tracing::warn!("Hook for {:?} received input for another event", E::EVENT);
+                        HookOutput::default()
+                    }
+                }
+            })
+        });
+
+        self.matchers
+            .entry(E::EVENT)
+            .or_default()
+            .push(HookMatcher {
+                matcher,
+                hooks: vec![hook],
+                timeout,
+                tool_matcher: None,
+            });
+        self
+    }
+
+    /// Check if no hooks have been registered.
+    pub fn is_empty(&self) -> bool {
+        self.matchers.is_empty()
+    }
+
+    /// Convert into the hook map used by [`ClaudeAgentOptions`].
+    pub fn into_hooks(self) -> HashMap<HookEvent, Vec<HookMatcher>> {
+        self.matchers
+    }
+}
+
+impl From<HookRegistry> for HashMap<HookEvent, Vec<HookMatcher>> {
+    fn from(registry: HookRegistry) -> Self {
+        registry.into_hooks()
+    }
+}
+
+impl std::fmt::Debug for HookRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HookRegistry")
+            .field("matchers", &self.matchers)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn base() -> BaseHookInput {
+        BaseHookInput {
+            session_id: "s".to_string(),
+            transcript_path: "/tmp/t".to_string(),
+            cwd: "/tmp".to_string(),
+            permission_mode: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_callback_receives_concrete_input() {
+        let seen = Arc::new(AtomicBool::new(false));
+        let seen_clone = Arc::clone(&seen);
+
+        let hooks = HookRegistry::new()
+            .on_matching(PreToolUse, "Bash", move |input| {
+                let seen = Arc::clone(&seen_clone);
+                async move {
+                    assert_eq!(input.tool_name, "Bash");
+                    seen.store(true, Ordering::SeqCst);
+                    HookOutput::default()
+                }
+            })
+            .into_hooks();
+
+        let matcher = &hooks[&HookEvent::PreToolUse][0];
+        assert_eq!(matcher.matcher.as_deref(), Some("Bash"));
+
+        let input = HookInput::PreToolUse(PreToolUseHookInput {
+            base: base(),
+            hook_event_name: "PreToolUse".to_string(),
+            tool_name: "Bash".to_string(),
+            tool_input: serde_json::json!({"command": "ls"}),
+        });
+        (matcher.hooks[0])(input, None, HookContext::default()).await;
+        assert!(seen.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_typed_callback_ignores_other_events() {
+        let hooks = HookRegistry::new()
+            .on(Stop, |_input| async { panic!("should not be called") })
+            .into_hooks();
+
+        let input = HookInput::UserPromptSubmit(UserPromptSubmitHookInput {
+            base: base(),
+            hook_event_name: "UserPromptSubmit".to_string(),
+            prompt: "hi".to_string(),
+        });
+        let output =
+            (hooks[&HookEvent::Stop][0].hooks[0])(input, None, HookContext::default()).await;
+        assert!(matches!(output, HookOutput::Sync(_)));
+    }
+
+    fn post_tool_use(tool_name: &str, tool_response: serde_json::Value) -> HookInput {
+        HookInput::PostToolUse(PostToolUseHookInput {
+            base: base(),
+            hook_event_name: "PostToolUse".to_string(),
+            tool_name: tool_name.to_string(),
+            tool_input: serde_json::json!({}),
+            tool_response,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_tool_result_filter() {
+        let hooks = HookRegistry::new()
+            .filter_tool_results(|input| redact_tool_result(&input.tool_response, &["hunter2"]))
+            .into_hooks();
+        let filter = &hooks[&HookEvent::PostToolUse][0].hooks[0];
+
+        let response = serde_json::json!({"stdout": "password=hunter2", "exit_code": 0});
+        let output = filter(
+            post_tool_use("Bash", response),
+            None,
+            HookContext::default(),
+        )
+        .await;
+        let HookOutput::Sync(output) = output else {
+            panic!("expected sync output");
+        };
+        assert_eq!(output.decision.as_deref(), Some("block"));
+        assert!(output.reason.unwrap().contains("password=[REDACTED]"));
+
+        let response = serde_json::json!([{"type": "text", "text": "token hunter2"}]);
+        let output = filter(
+            post_tool_use("mcp__db__query", response),
+            None,
+            HookContext::default(),
+        )
+        .await;
+        let json = serde_json::to_value(output).unwrap();
+        assert_eq!(
+            json["hookSpecificOutput"]["updatedMCPToolOutput"],
+            serde_json::json!([{"type": "text", "text": "token [REDACTED]"}])
+        );
+
+        let clean = serde_json::json!({"stdout": "ok"});
+        let output = filter(post_tool_use("Bash", clean), None, HookContext::default()).await;
+        assert!(serde_json::to_value(output)
+            .unwrap()
+            .get("decision")
+            .is_none());
+    }
+
+    #[test]
+    fn test_truncate_tool_result() {
+        let response = serde_json::json!({"stdout": "héllo world", "code": 0});
+        assert_eq!(
+            truncate_tool_result(&response, 5),
+            ToolResultDecision::Replace(serde_json::json!({
+                "stdout": "héllo... [6 characters truncated]",
+                "code": 0
+            }))
+        );
+        assert_eq!(
+            truncate_tool_result(&response, 20),
+            ToolResultDecision::Pass
+        );
+    }
+}
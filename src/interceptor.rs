@@ -0,0 +1,51 @@
+//! Query interceptors for [`ClaudeClient`](crate::ClaudeClient).
+//!
+//! Interceptors implement cross-cutting behavior — prompt prefixes, tagging,
+//! cost logging, policy checks — once, instead of at every call site. They
+//! are registered with [`ClaudeClient::add_interceptor`](crate::ClaudeClient::add_interceptor)
+//! or [`ClaudeClientBuilder::interceptor`](crate::ClaudeClientBuilder::interceptor)
+//! and run in registration order.
+
+use crate::errors::Result;
+use crate::types::{ClaudeAgentOptions, ResultMessage};
+
+/// Middleware invoked around each query sent by a [`ClaudeClient`](crate::ClaudeClient).
+///
+/// Both methods have no-op defaults, so implementors only override what they
+/// need.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::{ClaudeAgentOptions, QueryInterceptor, ResultMessage, Result};
+///
+/// struct CostLogger;
+///
+/// impl QueryInterceptor for CostLogger {
+///     fn before_query(&self, prompt: &mut String, _options: &mut ClaudeAgentOptions) -> Result<()> {
+///         prompt.insert_str(0, "[team:infra] ");
+///         Ok(())
+///     }
+///
+///     fn after_result(&self, result: &ResultMessage) {
+///         println!("Query cost: {:?}", result.total_cost_usd);
+///     }
+/// }
+/// ```
+pub trait QueryInterceptor: Send + Sync {
+    /// Called before a prompt is sent.
+    ///
+    /// The prompt may be rewritten in place. Changes to `options.model` and
+    /// `options.permission_mode` are applied to the live session; other
+    /// options take effect on the next connect. Returning an error aborts
+    /// the query before anything is sent.
+    fn before_query(&self, prompt: &mut String, options: &mut ClaudeAgentOptions) -> Result<()> {
+        let _ = (prompt, options);
+        Ok(())
+    }
+
+    /// Called when a query's result message is received.
+    fn after_result(&self, result: &ResultMessage) {
+        let _ = result;
+    }
+}
@@ -12,8 +12,7 @@
 //! ### Simple Query
 //!
 //! ```rust,no_run
-//! use claude_agents_sdk::{query, ClaudeAgentOptions, Message};
-//! use tokio_stream::StreamExt;
+//! use claude_agents_sdk::prelude::*;
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -114,7 +113,7 @@
 //!         Err(ClaudeSDKError::CLINotFound { message }) => {
 //!             eprintln!("Claude CLI not installed: {}", message);
 //!         }
-//!         Err(ClaudeSDKError::Timeout { duration_ms }) => {
+//!         Err(ClaudeSDKError::Timeout { duration_ms, .. }) => {
 //!             eprintln!("Operation timed out after {}ms", duration_ms);
 //!         }
 //!         Err(e) => {
@@ -140,26 +139,127 @@
 //!     .with_timeout_secs(60);
 //! ```
 //!
+//! ## Porting from the Python SDK
+//!
+//! Most names match the Python `claude-agent-sdk`. Where they differ:
+//!
+//! | Python | Rust |
+//! |--------|------|
+//! | `ClaudeSDKClient` | [`ClaudeClient`] (aliased as [`ClaudeSDKClient`]) |
+//! | `async for msg in client.receive_response()` | [`ClaudeClient::receive_response_messages`] |
+//! | `CLINotFoundError`, `CLIConnectionError`, `ProcessError`, `CLIJSONDecodeError` | [`ClaudeSDKError`] variants `CLINotFound`, `CLIConnection`, `Process`, `JSONDecode` |
+//! | `PermissionResultAllow(updated_input=...)` | [`PermissionResultAllow::with_updated_input`] |
+//! | `{"continue_": False}` / `{"async_": True}` hook output | [`SyncHookOutput::continue_`] / [`AsyncHookOutput::async_`] |
+//!
+//! Hook outputs and permission results also deserialize from the Python
+//! spellings (`continue_`, `async_`, `updated_input`, `tool_name`, ...), so
+//! JSON fixtures captured from Python agents can be reused.
+//!
 //! ## Feature Flags
 //!
 //! - **default**: Core SDK functionality
 //! - **mcp**: Enables MCP (Model Context Protocol) tool support for defining custom tools
+//! - **blocking**: Enables the synchronous `blocking` API for non-async programs
+//! - **tokens**: Enables approximate prompt token counting and cost estimates via the `tokens` module
+//! - **msgpack** / **cbor**: Enable binary frame codecs in `protocol::codec` for custom transports
+//! - **websocket**: Enables `WebSocketTransport`, for a CLI running elsewhere behind a WebSocket bridge
 
 #![warn(missing_docs)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+mod agent;
+mod branch;
+mod bundle;
 mod client;
+mod config_dir;
 mod errors;
+mod interceptor;
+mod options;
 mod query;
+mod settings;
 mod types;
+mod workspace;
 
 pub mod _internal;
 
 // Re-export public API
-pub use client::{ClaudeClient, ClaudeClientBuilder, ClientGuard};
+pub use agent::{Agent, AgentRunReport, LimitedClient, SessionLimit};
+pub use branch::BranchPoint;
+pub use bundle::{CheckpointInfo, SessionBundle, SESSION_BUNDLE_VERSION};
+pub use client::{
+    ClaudeClient, ClaudeClientBuilder, ClaudeSDKClient, ClientGuard, ClientHandle, MessageReceiver,
+};
+pub use config_dir::ConfigDir;
 pub use errors::*;
-pub use query::{query, query_all, query_chunks, query_result};
+pub use interceptor::QueryInterceptor;
+pub use options::ClaudeAgentOptionsBuilder;
+pub use query::{
+    continue_query, query, query_all, query_chunks, query_from_reader, query_result,
+    query_with_files, query_with_profile, query_with_transport,
+};
+pub use settings::{
+    Settings, SettingsHookCommand, SettingsHookMatcher, SettingsPermissions, StatusLine,
+};
 pub use types::*;
+pub use workspace::{FileChange, FileChangeKind, FilesQueryResult};
+
+pub mod bridge;
+
+pub mod diagnostics;
+pub use diagnostics::{Diagnostic, DiagnosticReceiver, DiagnosticSeverity};
+
+pub mod env_vars;
+
+pub mod envelope;
+pub use envelope::MessageEnvelope;
+
+pub mod export;
+
+pub mod hooks;
+pub use hooks::HookRegistry;
+
+pub mod markdown;
+pub use markdown::CodeBlock;
+
+pub mod paths;
+pub use paths::{PathResolver, ResolvedPath, WorkspaceGuard};
+
+pub mod policy;
+pub use policy::{PermissionPolicy, Quota, QuotaExceeded, QuotaExceededCallback, QuotaUsage};
+
+pub mod prelude;
+
+pub mod presets;
+
+pub mod profile;
+pub use profile::AgentProfile;
+
+pub mod progress;
+pub use progress::Progress;
+
+pub mod protocol;
+pub use protocol::{
+    ControlErrorResponse, ControlRequest, ControlRequestPayload, ControlResponse,
+    ControlResponsePayload, ControlSuccessResponse,
+};
+
+pub mod testing;
+
+pub mod summary;
+pub use summary::{summarize_session, SessionSummary, SummaryOptions, Transcript};
+
+pub mod tool_schema;
+pub use tool_schema::{ToolInputError, ToolInputValidation, ToolSchemaRegistry};
+
+pub mod wire;
+
+#[cfg(feature = "blocking")]
+#[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+pub mod blocking;
+
+#[cfg(feature = "tokens")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokens")))]
+pub mod tokens;
 
 // Re-export MCP tools when feature enabled
 #[cfg(feature = "mcp")]
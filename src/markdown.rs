@@ -0,0 +1,193 @@
+//! Fenced code blocks in response text.
+//!
+//! Asking for a script and saving it is common enough that the blocks of a
+//! Markdown response can be pulled out directly, with
+//! [`AssistantMessage::code_blocks`](crate::AssistantMessage::code_blocks)
+//! or [`ResultMessage::extract_code`](crate::ResultMessage::extract_code),
+//! or from any text with [`code_blocks`].
+//!
+//! Fences follow CommonMark: three or more backticks or tildes, indented by
+//! at most three spaces, closed by a fence of the same character at least
+//! as long. A fence left open runs to the end of the text.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::markdown::code_blocks;
+//!
+//! let text = "Save this as `hello.py`:\n\n```python\nprint(\"hello\")\n```\n";
+//! let blocks = code_blocks(text);
+//! assert_eq!(blocks[0].language.as_deref(), Some("python"));
+//! assert_eq!(blocks[0].code, "print(\"hello\")\n");
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// A fenced code block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeBlock {
+    /// Language tag, the first word after the opening fence, up to any
+    /// comma (as in `rust,ignore`).
+    pub language: Option<String>,
+    /// The code, each line ending in a newline.
+    pub code: String,
+}
+
+impl CodeBlock {
+    /// Whether the block is tagged `language`, ignoring case.
+    pub fn is_language(&self, language: &str) -> bool {
+        self.language
+            .as_deref()
+            .is_some_and(|tag| tag.eq_ignore_ascii_case(language))
+    }
+}
+
+/// An opening fence: its character, length and indentation.
+struct Fence {
+    marker: char,
+    len: usize,
+    indent: usize,
+}
+
+/// Parse `line` as a fence, with its info string.
+fn fence(line: &str) -> Option<(Fence, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.len() - rest.trim_start_matches(marker).len();
+    if len < 3 {
+        return None;
+    }
+    let info = rest[len..].trim();
+    // Backticks can't open a fence whose info string has one
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+    Some((
+        Fence {
+            marker,
+            len,
+            indent,
+        },
+        info,
+    ))
+}
+
+/// Whether `line` closes `open`.
+fn closes(line: &str, open: &Fence) -> bool {
+    match fence(line) {
+        Some((close, info)) => {
+            close.marker == open.marker && close.len >= open.len && info.is_empty()
+        }
+        None => false,
+    }
+}
+
+/// The fenced code blocks in `text`, in order.
+pub fn code_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let Some((open, info)) = fence(line) else {
+            continue;
+        };
+        let language = info
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .next()
+            .filter(|tag| !tag.is_empty())
+            .map(String::from);
+        let mut code = String::new();
+        for line in lines.by_ref() {
+            if closes(line, &open) {
+                break;
+            }
+            // Content loses up to as many spaces as the fence was indented
+            let strip = line.len() - line.trim_start_matches(' ').len();
+            code.push_str(&line[strip.min(open.indent)..]);
+            code.push('\n');
+        }
+        blocks.push(CodeBlock { language, code });
+    }
+    blocks
+}
+
+/// The code of the blocks in `text` tagged `language`, or of all blocks if
+/// `language` is `None`.
+pub(crate) fn extract_code(text: &str, language: Option<&str>) -> Vec<String> {
+    code_blocks(text)
+        .into_iter()
+        .filter(|block| language.map_or(true, |language| block.is_language(language)))
+        .map(|block| block.code)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_blocks() {
+        let text = "Here you go:\n\
+                    ```Rust,ignore\n\
+                    fn main() {\n\
+                    \x20   println!(\"hi\");\n\
+                    }\n\
+                    ```\n\
+                    and a shell one\n\
+                    \x20 ~~~~ bash title=\"run.sh\"\n\
+                    \x20   cargo run\n\
+                    ~~~\n\
+                    ```\n\
+                    \x20~~~~\n\
+                    ````\n\
+                    no tag\n\
+                    ```\n\
+                    ````\n";
+        let blocks = code_blocks(text);
+        assert_eq!(
+            blocks,
+            vec![
+                CodeBlock {
+                    language: Some("Rust".to_string()),
+                    code: "fn main() {\n    println!(\"hi\");\n}\n".to_string(),
+                },
+                CodeBlock {
+                    language: Some("bash".to_string()),
+                    // A shorter fence doesn't close a longer one
+                    code: "  cargo run\n~~~\n```\n".to_string(),
+                },
+                CodeBlock {
+                    language: None,
+                    code: "no tag\n```\n".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unclosed_and_non_fences() {
+        assert!(code_blocks("``not a fence``\n    ```indented code```\n").is_empty());
+        assert_eq!(
+            code_blocks("```py\nprint(1)"),
+            vec![CodeBlock {
+                language: Some("py".to_string()),
+                code: "print(1)\n".to_string(),
+            }]
+        );
+        assert_eq!(code_blocks("```\n```")[0].code, "");
+    }
+
+    #[test]
+    fn test_extract_code_by_language() {
+        let text = "```python\na = 1\n```\n```bash\nls\n```\n```PYTHON\nb = 2\n```\n";
+        assert_eq!(
+            extract_code(text, Some("python")),
+            vec!["a = 1\n", "b = 2\n"]
+        );
+        assert_eq!(extract_code(text, Some("go")), Vec::<String>::new());
+        assert_eq!(extract_code(text, None).len(), 3);
+    }
+}
@@ -11,12 +11,41 @@
 //! [dependencies]
 //! claude-agents-sdk = { version = "0.1", features = ["mcp"] }
 //! ```
+//!
+//! # Defining tools
+//!
+//! [`tool!`](crate::tool) turns a function into an [`SdkMcpTool`], deriving
+//! the input schema from the argument types and the descriptions from the
+//! doc comments. For larger inputs, [`tool_input!`](crate::tool_input)
+//! defines an argument struct for [`SdkMcpTool::typed`].
+//!
+//! ```rust
+//! use claude_agents_sdk::mcp::{create_sdk_mcp_server, ToolResult};
+//! use claude_agents_sdk::tool;
+//!
+//! let add = tool! {
+//!     /// Add two numbers.
+//!     async fn add(
+//!         /// First number
+//!         a: f64,
+//!         /// Second number
+//!         b: f64,
+//!     ) -> ToolResult {
+//!         ToolResult::text(format!("{}", a + b))
+//!     }
+//! };
+//! assert_eq!(add.input_schema.required, ["a", "b"]);
+//!
+//! let (config, tools) = create_sdk_mcp_server("calculator", "1.0.0", vec![add]);
+//! ```
 
 use std::collections::HashMap;
 use std::future::Future;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -169,6 +198,111 @@ impl ToolInputSchema {
         self.required.push(name.into());
         self
     }
+
+    /// Add a property with the schema of `T`, required unless `T` is an
+    /// `Option`. An empty description is left out.
+    pub fn param<T: ToolParam>(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        let description = description.into();
+        let mut schema = T::json_schema();
+        if let (Some(object), false) = (schema.as_object_mut(), description.is_empty()) {
+            object.insert("description".to_string(), Value::String(description));
+        }
+        self.properties.insert(name.clone(), schema);
+        if T::required() {
+            self.required.push(name);
+        }
+        self
+    }
+}
+
+/// Types usable as tool arguments, with their JSON Schema.
+pub trait ToolParam {
+    /// Get the JSON Schema describing the type.
+    fn json_schema() -> Value;
+
+    /// Check whether the argument must be given.
+    fn required() -> bool {
+        true
+    }
+}
+
+macro_rules! impl_tool_param {
+    ($schema_type:literal: $($ty:ty),*) => {
+        $(
+            impl ToolParam for $ty {
+                fn json_schema() -> Value {
+                    serde_json::json!({ "type": $schema_type })
+                }
+            }
+        )*
+    };
+}
+
+impl_tool_param!("string": String, char, PathBuf);
+impl_tool_param!("boolean": bool);
+impl_tool_param!("integer": i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+impl_tool_param!("number": f32, f64);
+
+impl ToolParam for Value {
+    fn json_schema() -> Value {
+        serde_json::json!({})
+    }
+}
+
+impl<T: ToolParam> ToolParam for Option<T> {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+
+    fn required() -> bool {
+        false
+    }
+}
+
+impl<T: ToolParam> ToolParam for Vec<T> {
+    fn json_schema() -> Value {
+        serde_json::json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: ToolParam> ToolParam for HashMap<String, T> {
+    fn json_schema() -> Value {
+        serde_json::json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+}
+
+/// Typed tool input: a struct deserialized from the tool call, with a
+/// matching input schema.
+///
+/// Usually implemented with [`tool_input!`](crate::tool_input).
+pub trait ToolInput: DeserializeOwned {
+    /// Get the input schema of the tool.
+    fn input_schema() -> ToolInputSchema;
+}
+
+/// Join doc comment lines into a description.
+#[doc(hidden)]
+pub fn __doc_text(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .map(|line| line.trim())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Deserialize the argument `name` of a tool call.
+#[doc(hidden)]
+pub fn __tool_arg<T: DeserializeOwned>(input: &Value, name: &str) -> Result<T, ToolResult> {
+    let value = input.get(name).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value)
+        .map_err(|e| ToolResult::error(format!("Invalid argument `{}`: {}", name, e)))
 }
 
 /// Type alias for tool handler functions.
@@ -208,6 +342,55 @@ impl SdkMcpTool {
             handler: Arc::new(move |input| Box::pin(handler(input))),
         }
     }
+
+    /// Create a tool taking a typed input, with the schema of `T`.
+    ///
+    /// Calls with input that doesn't deserialize into `T` get an error
+    /// result without reaching the handler.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::mcp::{SdkMcpTool, ToolResult};
+    /// use claude_agents_sdk::tool_input;
+    ///
+    /// tool_input! {
+    ///     /// Arguments of the search tool.
+    ///     struct SearchArgs {
+    ///         /// Text to look for
+    ///         query: String,
+    ///         /// Maximum number of hits
+    ///         limit: Option<u32>,
+    ///     }
+    /// }
+    ///
+    /// let search = SdkMcpTool::typed("search", "Search the docs", |args: SearchArgs| async move {
+    ///     ToolResult::text(format!("{} (limit {})", args.query, args.limit.unwrap_or(10)))
+    /// });
+    /// assert_eq!(search.input_schema.required, ["query"]);
+    /// ```
+    pub fn typed<T, F, Fut>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        handler: F,
+    ) -> Self
+    where
+        T: ToolInput,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ToolResult> + Send + 'static,
+    {
+        Self::new(name, description, T::input_schema(), move |input| {
+            let call = serde_json::from_value::<T>(input)
+                .map(&handler)
+                .map_err(|e| ToolResult::error(format!("Invalid input: {}", e)));
+            async move {
+                match call {
+                    Ok(output) => output.await,
+                    Err(error) => error,
+                }
+            }
+        })
+    }
 }
 
 impl std::fmt::Debug for SdkMcpTool {
@@ -281,48 +464,103 @@ pub fn create_sdk_mcp_server(
     (config, tools)
 }
 
-/// Macro for defining tools with a simpler syntax.
+/// Define a tool from a function.
+///
+/// The tool is named after the function and described by its doc comment.
+/// Each argument becomes a property of the input schema, typed after the
+/// argument's [`ToolParam`](crate::mcp::ToolParam) schema, described by its
+/// doc comment, and required unless it is an `Option`. The body returns a
+/// [`ToolResult`](crate::mcp::ToolResult); calls with invalid arguments get
+/// an error result without running it.
 ///
 /// # Examples
 ///
-/// ```rust,ignore
+/// ```rust
+/// use claude_agents_sdk::mcp::{ToolContent, ToolResult};
 /// use claude_agents_sdk::tool;
 ///
-/// tool! {
-///     /// Add two numbers together.
-///     fn add(a: f64, b: f64) -> ToolResult {
-///         ToolResult::text(format!("{}", a + b))
+/// # tokio_test::block_on(async {
+/// let greet = tool! {
+///     /// Greet someone.
+///     async fn greet(
+///         /// Who to greet
+///         name: String,
+///         /// Greeting to use instead of "Hello"
+///         greeting: Option<String>,
+///     ) -> ToolResult {
+///         let greeting = greeting.unwrap_or_else(|| "Hello".to_string());
+///         ToolResult::text(format!("{}, {}!", greeting, name))
 ///     }
-/// }
+/// };
+/// assert_eq!(greet.description, "Greet someone.");
+///
+/// let result = (greet.handler)(serde_json::json!({"name": "Ada"})).await;
+/// assert!(matches!(&result.content[0], ToolContent::Text { text } if text == "Hello, Ada!"));
+/// # });
 /// ```
 #[macro_export]
 macro_rules! tool {
     (
-        $(#[$meta:meta])*
-        fn $name:ident($($arg:ident: $type:ty),*) -> $ret:ty $body:block
+        $(#[doc = $doc:literal])*
+        $(async)? fn $name:ident($(
+            $(#[doc = $arg_doc:literal])*
+            $arg:ident: $type:ty
+        ),* $(,)?) -> $ret:ty $body:block
     ) => {
-        {
-            use $crate::mcp::{SdkMcpTool, ToolInputSchema, ToolResult};
+        $crate::mcp::SdkMcpTool::new(
+            stringify!($name),
+            $crate::mcp::__doc_text(&[$($doc),*]),
+            $crate::mcp::ToolInputSchema::object()
+                $(.param::<$type>(stringify!($arg), $crate::mcp::__doc_text(&[$($arg_doc),*])))*,
+            |input| async move {
+                let _ = &input;
+                $(
+                    let $arg: $type = match $crate::mcp::__tool_arg(&input, stringify!($arg)) {
+                        Ok(value) => value,
+                        Err(error) => return error,
+                    };
+                )*
+                let result: $ret = $body;
+                result
+            },
+        )
+    };
+}
 
-            let mut schema = ToolInputSchema::object();
+/// Define a tool input struct and its [`ToolInput`](crate::mcp::ToolInput)
+/// implementation.
+///
+/// Field doc comments become property descriptions, and `Option` fields are
+/// optional. The struct derives `serde::Deserialize`, so the calling crate
+/// needs `serde` with the `derive` feature; attributes other than doc
+/// comments aren't supported on fields.
+///
+/// See [`SdkMcpTool::typed`](crate::mcp::SdkMcpTool::typed) for an example.
+#[macro_export]
+macro_rules! tool_input {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
             $(
-                schema = schema.string_property(stringify!($arg), "");
-                schema = schema.required_property(stringify!($arg));
+                $(#[doc = $doc:literal])*
+                $field_vis:vis $field:ident: $type:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(serde::Deserialize)]
+        $vis struct $name {
+            $(
+                $(#[doc = $doc])*
+                $field_vis $field: $type,
             )*
+        }
 
-            SdkMcpTool::new(
-                stringify!($name),
-                "",
-                schema,
-                |input: serde_json::Value| async move {
-                    $(
-                        let $arg: $type = serde_json::from_value(
-                            input.get(stringify!($arg)).cloned().unwrap_or_default()
-                        ).unwrap_or_default();
-                    )*
-                    $body
-                },
-            )
+        impl $crate::mcp::ToolInput for $name {
+            fn input_schema() -> $crate::mcp::ToolInputSchema {
+                $crate::mcp::ToolInputSchema::object()
+                    $(.param::<$type>(stringify!($field), $crate::mcp::__doc_text(&[$($doc),*])))*
+            }
         }
     };
 }
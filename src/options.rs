@@ -0,0 +1,830 @@
+//! Validating builder for [`ClaudeAgentOptions`].
+//!
+//! [`ClaudeAgentOptionsBuilder`] has a method for every option and checks
+//! the combination once, in [`build`](ClaudeAgentOptionsBuilder::build),
+//! so invalid states such as `fork_session` without a session to fork are
+//! rejected before a CLI is ever spawned.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::{ClaudeAgentOptions, PermissionMode};
+//!
+//! let options = ClaudeAgentOptions::builder()
+//!     .model("claude-sonnet-4-5")
+//!     .permission_mode(PermissionMode::AcceptEdits)
+//!     .max_turns(10)
+//!     .resume("session-123")
+//!     .fork_session()
+//!     .build()
+//!     .unwrap();
+//! assert!(options.fork_session);
+//!
+//! // Nothing to fork
+//! assert!(ClaudeAgentOptions::builder().fork_session().build().is_err());
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::paths::WorkspaceGuard;
+use crate::tool_schema::ToolSchemaRegistry;
+use crate::types::{
+    AgentDefinition, ClaudeAgentOptions, ContextPolicy, FallbackTransport, HookEvent, HookMatcher,
+    LogSampling, McpServerConfig, McpServersConfig, OutboundFrameLimit, PartialCoalescing,
+    PermissionMode, PermissionResult, ProtocolStrictness, RunAs, SandboxSettings, SdkBeta,
+    SdkPluginConfig, SettingSource, SystemPromptConfig, SystemPromptPreset, Timeouts,
+    ToolPermissionContext, ToolsConfig, TurnBudget, TurnRetry, UnsupportedFlags,
+};
+
+/// Builder for [`ClaudeAgentOptions`], validated at [`build`](Self::build).
+///
+/// Methods are named after the option they set; flags take no argument and
+/// collection options add one entry per call.
+#[derive(Clone, Default)]
+pub struct ClaudeAgentOptionsBuilder {
+    options: ClaudeAgentOptions,
+}
+
+impl std::fmt::Debug for ClaudeAgentOptionsBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClaudeAgentOptionsBuilder")
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+impl ClaudeAgentOptionsBuilder {
+    /// Create a builder with default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the tools available to the model.
+    pub fn tools(mut self, tools: ToolsConfig) -> Self {
+        self.options.tools = Some(tools);
+        self
+    }
+
+    /// Set the tools allowed without asking for permission.
+    pub fn allowed_tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.allowed_tools = tools.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the tools the model may not use.
+    pub fn disallowed_tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.disallowed_tools = tools.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set a plain text system prompt.
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.options.system_prompt = Some(SystemPromptConfig::Text(prompt.into()));
+        self
+    }
+
+    /// Use a preset system prompt.
+    pub fn system_prompt_preset(mut self, preset: SystemPromptPreset) -> Self {
+        self.options.system_prompt = Some(SystemPromptConfig::Preset(preset));
+        self
+    }
+
+    /// Set the MCP server configuration, replacing any servers added so far.
+    pub fn mcp_servers(mut self, servers: McpServersConfig) -> Self {
+        self.options.mcp_servers = servers;
+        self
+    }
+
+    /// Add an MCP server.
+    pub fn mcp_server(mut self, name: impl Into<String>, config: McpServerConfig) -> Self {
+        self.options = self.options.with_mcp_server(name, config);
+        self
+    }
+
+    /// Ignore MCP servers from the CLI's settings files.
+    pub fn strict_mcp_config(mut self) -> Self {
+        self.options.strict_mcp_config = true;
+        self
+    }
+
+    /// Leave an MCP server out when the CLI starts.
+    pub fn disable_mcp_server(mut self, name: impl Into<String>) -> Self {
+        self.options.disabled_mcp_servers.push(name.into());
+        self
+    }
+
+    /// Fail to connect if an MCP server can't be started.
+    pub fn require_mcp_servers(mut self) -> Self {
+        self.options.require_mcp_servers = true;
+        self
+    }
+
+    /// Set the permission mode.
+    pub fn permission_mode(mut self, mode: PermissionMode) -> Self {
+        self.options.permission_mode = Some(mode);
+        self
+    }
+
+    /// Continue the most recent conversation.
+    pub fn continue_conversation(mut self) -> Self {
+        self.options.continue_conversation = true;
+        self
+    }
+
+    /// Resume the session with `session_id`.
+    pub fn resume(mut self, session_id: impl Into<String>) -> Self {
+        self.options.resume = Some(session_id.into());
+        self
+    }
+
+    /// Keep the resumed conversation only up to and including the message
+    /// with `uuid`.
+    pub fn resume_session_at(mut self, uuid: impl Into<String>) -> Self {
+        self.options.resume_session_at = Some(uuid.into());
+        self
+    }
+
+    /// Start a new session if the one to resume doesn't exist.
+    pub fn resume_fallback_new(mut self) -> Self {
+        self.options.resume_fallback_new = true;
+        self
+    }
+
+    /// Fork the resumed or continued session into a new one.
+    pub fn fork_session(mut self) -> Self {
+        self.options.fork_session = true;
+        self
+    }
+
+    /// Set the maximum number of turns.
+    pub fn max_turns(mut self, turns: u32) -> Self {
+        self.options.max_turns = Some(turns);
+        self
+    }
+
+    /// Set the maximum budget in USD.
+    pub fn max_budget_usd(mut self, budget: f64) -> Self {
+        self.options.max_budget_usd = Some(budget);
+        self
+    }
+
+    /// Interrupt any turn going over `budget`.
+    pub fn turn_budget(mut self, budget: TurnBudget) -> Self {
+        self.options.turn_budget = Some(budget);
+        self
+    }
+
+    /// Compact or warn as the context fills up.
+    pub fn context_policy(mut self, policy: ContextPolicy) -> Self {
+        self.options.context_policy = Some(policy);
+        self
+    }
+
+    /// Set the model.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.options.model = Some(model.into());
+        self
+    }
+
+    /// Set the model to fall back to when the main model is unavailable.
+    pub fn fallback_model(mut self, model: impl Into<String>) -> Self {
+        self.options.fallback_model = Some(model.into());
+        self
+    }
+
+    /// Enable a beta feature.
+    pub fn beta(mut self, beta: SdkBeta) -> Self {
+        self.options.betas.push(beta);
+        self
+    }
+
+    /// Set the MCP tool that answers permission prompts.
+    pub fn permission_prompt_tool_name(mut self, tool: impl Into<String>) -> Self {
+        self.options.permission_prompt_tool_name = Some(tool.into());
+        self
+    }
+
+    /// Set the working directory.
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.options.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Set the path to the CLI executable.
+    pub fn cli_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.cli_path = Some(path.into());
+        self
+    }
+
+    /// Fall back on `transport` if the CLI can't be used (see
+    /// [`fallback_transports`](ClaudeAgentOptions::fallback_transports)).
+    pub fn fallback_transport(mut self, transport: FallbackTransport) -> Self {
+        self.options.fallback_transports.push(transport);
+        self
+    }
+
+    /// Set the settings passed via `--settings`, e.g. from
+    /// [`Settings::build`](crate::Settings::build).
+    pub fn settings(mut self, settings: impl Into<String>) -> Self {
+        self.options.settings = Some(settings.into());
+        self
+    }
+
+    /// Give the CLI access to an additional directory.
+    pub fn add_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.add_dirs.push(dir.into());
+        self
+    }
+
+    /// Set an environment variable for the CLI process.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Expand `${VAR}` references in the system prompt, settings and MCP
+    /// server configurations when connecting.
+    pub fn expand_env(mut self) -> Self {
+        self.options.expand_env = true;
+        self
+    }
+
+    /// Pass an extra `--flag [value]` to the CLI.
+    pub fn extra_arg(mut self, flag: impl Into<String>, value: Option<String>) -> Self {
+        self.options.extra_args.insert(flag.into(), value);
+        self
+    }
+
+    /// Set the maximum buffer size for CLI stdout.
+    pub fn max_buffer_size(mut self, size: usize) -> Self {
+        self.options.max_buffer_size = Some(size);
+        self
+    }
+
+    /// Receive each line the CLI writes to stderr.
+    pub fn stderr<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(String) + Send + Sync + 'static,
+    {
+        self.options.stderr = Some(Arc::new(callback));
+        self
+    }
+
+    /// Set the tool permission callback.
+    pub fn can_use_tool<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(String, serde_json::Value, ToolPermissionContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = PermissionResult> + Send + 'static,
+    {
+        self.options = self.options.with_can_use_tool(callback);
+        self
+    }
+
+    /// Retry turns that fail with a transient API error.
+    pub fn turn_retry(mut self, retry: TurnRetry) -> Self {
+        self.options.turn_retry = Some(retry);
+        self
+    }
+
+    /// Set a callback for when the CLI needs to be logged in again.
+    pub fn on_auth_required<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.options = self.options.with_on_auth_required(callback);
+        self
+    }
+
+    /// Add hooks, e.g. from a [`HookRegistry`](crate::HookRegistry),
+    /// keeping any hooks already added.
+    pub fn hooks(mut self, hooks: impl Into<HashMap<HookEvent, Vec<HookMatcher>>>) -> Self {
+        self.options.add_hooks(hooks);
+        self
+    }
+
+    /// Set the user identifier.
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.options.user = Some(user.into());
+        self
+    }
+
+    /// Include partial messages in the stream.
+    pub fn include_partial_messages(mut self) -> Self {
+        self.options.include_partial_messages = true;
+        self
+    }
+
+    /// Include partial messages and synthesize assistant messages the CLI
+    /// doesn't deliver.
+    pub fn synthesize_partial_messages(mut self) -> Self {
+        self.options = self.options.with_synthesized_partial_messages();
+        self
+    }
+
+    /// Include partial messages with text deltas coalesced per `coalescing`.
+    pub fn partial_coalescing(mut self, coalescing: PartialCoalescing) -> Self {
+        self.options = self.options.with_partial_coalescing(coalescing);
+        self
+    }
+
+    /// Include partial messages, dropping the oldest stream events when
+    /// more than `limit` wait for a slow consumer.
+    pub fn max_pending_partial_messages(mut self, limit: usize) -> Self {
+        self.options = self.options.with_max_pending_partial_messages(limit);
+        self
+    }
+
+    /// Define a subagent.
+    pub fn agent(mut self, name: impl Into<String>, definition: AgentDefinition) -> Self {
+        self.options
+            .agents
+            .get_or_insert_with(HashMap::new)
+            .insert(name.into(), definition);
+        self
+    }
+
+    /// Set the setting sources the CLI loads.
+    pub fn setting_sources(mut self, sources: Vec<SettingSource>) -> Self {
+        self.options.setting_sources = Some(sources);
+        self
+    }
+
+    /// Set the sandbox settings.
+    pub fn sandbox(mut self, sandbox: SandboxSettings) -> Self {
+        self.options.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Load a plugin.
+    pub fn plugin(mut self, plugin: SdkPluginConfig) -> Self {
+        self.options.plugins.push(plugin);
+        self
+    }
+
+    /// Set the maximum number of thinking tokens.
+    pub fn max_thinking_tokens(mut self, tokens: u32) -> Self {
+        self.options.max_thinking_tokens = Some(tokens);
+        self
+    }
+
+    /// Set the output format for structured outputs.
+    pub fn output_format(mut self, format: serde_json::Value) -> Self {
+        self.options.output_format = Some(format);
+        self
+    }
+
+    /// Enable file checkpointing.
+    pub fn enable_file_checkpointing(mut self) -> Self {
+        self.options.enable_file_checkpointing = true;
+        self
+    }
+
+    /// Set the timeout for connecting and control requests in seconds (0
+    /// disables it).
+    pub fn timeout_secs(mut self, timeout: u64) -> Self {
+        self.options.timeouts = Timeouts::from_secs(timeout);
+        self
+    }
+
+    /// Set how long each stage of a session may take.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.options.timeouts = timeouts;
+        self
+    }
+
+    /// Retain the last `size` messages for late-attaching observers.
+    pub fn message_history(mut self, size: usize) -> Self {
+        self.options.message_history_size = size;
+        self
+    }
+
+    /// Buffer up to `capacity` messages per subscriber.
+    pub fn subscriber_capacity(mut self, capacity: usize) -> Self {
+        self.options.subscriber_capacity = Some(capacity);
+        self
+    }
+
+    /// Keep the raw JSON of each parsed message.
+    pub fn keep_raw_messages(mut self) -> Self {
+        self.options.keep_raw_messages = true;
+        self
+    }
+
+    /// Set how much of each frame exchanged with the CLI is logged.
+    pub fn log_sampling(mut self, sampling: LogSampling) -> Self {
+        self.options.log_sampling = sampling;
+        self
+    }
+
+    /// Limit the size of frames written to the CLI.
+    pub fn outbound_frame_limit(mut self, limit: OutboundFrameLimit) -> Self {
+        self.options.outbound_frame_limit = Some(limit);
+        self
+    }
+
+    /// Set how the SDK reacts to protocol frames it can't handle.
+    pub fn protocol_strictness(mut self, strictness: ProtocolStrictness) -> Self {
+        self.options.protocol_strictness = strictness;
+        self
+    }
+
+    /// Set what to do with options the CLI is too old to support.
+    pub fn unsupported_flags(mut self, policy: UnsupportedFlags) -> Self {
+        self.options.unsupported_flags = policy;
+        self
+    }
+
+    /// Set an overall deadline for the query.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.options.deadline = Some(deadline);
+        self
+    }
+
+    /// Spool tool results larger than `threshold_bytes` to files in `dir`.
+    pub fn tool_output_spool(mut self, dir: impl Into<PathBuf>, threshold_bytes: usize) -> Self {
+        self.options = self.options.with_tool_output_spool(dir, threshold_bytes);
+        self
+    }
+
+    /// Add a session tag.
+    pub fn session_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.session_tags.insert(key.into(), value.into());
+        self
+    }
+
+    /// Kill the processes started by the CLI's tools along with the CLI.
+    pub fn kill_descendants_on_drop(mut self) -> Self {
+        self.options.kill_descendants_on_drop = true;
+        self
+    }
+
+    /// Keep the session temp directory on disconnect.
+    pub fn keep_session_tmp_dir(mut self) -> Self {
+        self.options.keep_session_tmp_dir = true;
+        self
+    }
+
+    /// Run the CLI as another user.
+    pub fn run_as(mut self, run_as: RunAs) -> Self {
+        self.options.run_as = Some(run_as);
+        self
+    }
+
+    /// Use a separate CLI configuration directory.
+    pub fn config_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.config_dir = Some(dir.into());
+        self
+    }
+
+    /// Enforce a [`PermissionPolicy`](crate::PermissionPolicy).
+    pub fn permission_policy(mut self, policy: crate::PermissionPolicy) -> Self {
+        self.options = self.options.with_permission_policy(policy);
+        self
+    }
+
+    /// Keep the agent offline (see
+    /// [`PermissionPolicy::no_network`](crate::PermissionPolicy::no_network)).
+    pub fn no_network(mut self) -> Self {
+        self.options = self.options.with_no_network();
+        self
+    }
+
+    /// Validate tool inputs against `registry`.
+    pub fn tool_schemas(mut self, registry: ToolSchemaRegistry) -> Self {
+        self.options.tool_schemas = Some(registry);
+        self
+    }
+
+    /// Deny tool calls outside the workspace.
+    pub fn workspace_guard(mut self, guard: WorkspaceGuard) -> Self {
+        self.options.workspace_guard = Some(guard);
+        self
+    }
+
+    /// Validate the options and return them.
+    ///
+    /// Fails with a [`Configuration`](ClaudeSDKError::Configuration) error
+    /// describing the first problem found by
+    /// [`ClaudeAgentOptions::validate`].
+    pub fn build(self) -> Result<ClaudeAgentOptions> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+impl ClaudeAgentOptions {
+    /// Start building options with [`ClaudeAgentOptionsBuilder`].
+    pub fn builder() -> ClaudeAgentOptionsBuilder {
+        ClaudeAgentOptionsBuilder::new()
+    }
+
+    /// Check that the options are consistent.
+    ///
+    /// Rejects combinations the CLI would ignore or misinterpret:
+    /// - `fork_session` without `resume` or `continue_conversation`
+    /// - both `can_use_tool` and `permission_prompt_tool_name`
+    /// - a [`PermissionPolicy`](crate::PermissionPolicy) concurrency limit
+    ///   of 0
+    /// - `tool_schemas` that don't deny invalid calls without `can_use_tool`
+    /// - `synthesize_partial_messages`, `partial_coalescing` or
+    ///   `max_pending_partial_messages` without `include_partial_messages`,
+    ///   or a `max_pending_partial_messages` of 0
+    /// - a `max_turns` of 0, or a `max_budget_usd` that isn't a positive
+    ///   number
+    /// - a `turn_budget` without a cap, or with a `max_usd` or price that
+    ///   isn't a positive number
+    /// - a `context_policy` with an empty window, or a threshold outside
+    ///   0 (excluded) to 1
+    /// - an `outbound_frame_limit` of 0 bytes
+    /// - an empty `resume` session ID
+    /// - `resume_session_at` or `resume_fallback_new` without `resume`
+    /// - a tool that is both allowed and disallowed
+    /// - an empty environment variable name, or one containing `=`
+    /// - a `turn_retry` jitter outside 0 to 1, or an initial delay longer
+    ///   than its maximum
+    /// - `disabled_mcp_servers` naming a server that isn't in an inline
+    ///   `mcp_servers` configuration
+    pub fn validate(&self) -> Result<()> {
+        if self.fork_session && self.resume.is_none() && !self.continue_conversation {
+            return Err(ClaudeSDKError::configuration(
+                "'fork_session' requires 'resume' or 'continue_conversation'",
+            ));
+        }
+        if self.resume.as_deref() == Some("") {
+            return Err(ClaudeSDKError::configuration(
+                "'resume' needs a non-empty session ID",
+            ));
+        }
+        if self.resume_session_at.is_some() && self.resume.is_none() {
+            return Err(ClaudeSDKError::configuration(
+                "'resume_session_at' requires 'resume'",
+            ));
+        }
+        if self.resume_fallback_new && self.resume.is_none() {
+            return Err(ClaudeSDKError::configuration(
+                "'resume_fallback_new' requires 'resume'",
+            ));
+        }
+        if self.can_use_tool.is_some() && self.permission_prompt_tool_name.is_some() {
+            return Err(ClaudeSDKError::configuration(
+                "Cannot specify both 'can_use_tool' and 'permission_prompt_tool_name'",
+            ));
+        }
+        if self
+            .tool_schemas
+            .as_ref()
+            .is_some_and(|registry| !registry.denies_invalid())
+            && self.can_use_tool.is_none()
+        {
+            return Err(ClaudeSDKError::configuration(
+                "'tool_schemas' requires a 'can_use_tool' callback unless it denies invalid calls",
+            ));
+        }
+        if let Some(tool) = self
+            .permission_policies
+            .iter()
+            .flat_map(|policy| policy.concurrency_limits())
+            .find_map(|(tool, limit)| (*limit == 0).then_some(tool))
+        {
+            return Err(ClaudeSDKError::configuration(format!(
+                "Concurrency limit for '{}' must be at least 1",
+                tool
+            )));
+        }
+        if !self.include_partial_messages {
+            if self.synthesize_partial_messages {
+                return Err(ClaudeSDKError::configuration(
+                    "'synthesize_partial_messages' requires 'include_partial_messages'",
+                ));
+            }
+            if self.partial_coalescing.is_some() {
+                return Err(ClaudeSDKError::configuration(
+                    "'partial_coalescing' requires 'include_partial_messages'",
+                ));
+            }
+            if self.max_pending_partial_messages.is_some() {
+                return Err(ClaudeSDKError::configuration(
+                    "'max_pending_partial_messages' requires 'include_partial_messages'",
+                ));
+            }
+        }
+        if self.max_pending_partial_messages == Some(0) {
+            return Err(ClaudeSDKError::configuration(
+                "'max_pending_partial_messages' must be at least 1",
+            ));
+        }
+        if self.max_turns == Some(0) {
+            return Err(ClaudeSDKError::configuration(
+                "'max_turns' must be at least 1",
+            ));
+        }
+        if let Some(budget) = self.max_budget_usd {
+            if !budget.is_finite() || budget <= 0.0 {
+                return Err(ClaudeSDKError::configuration(format!(
+                    "'max_budget_usd' must be a positive amount, got {}",
+                    budget
+                )));
+            }
+        }
+        if let Some(ref budget) = self.turn_budget {
+            let positive = |amount: f64| amount.is_finite() && amount > 0.0;
+            if budget.max_tokens.is_none() && budget.max_usd.is_none() {
+                return Err(ClaudeSDKError::configuration(
+                    "'turn_budget' needs 'max_tokens' or 'max_usd'",
+                ));
+            }
+            if let Some(max) = budget.max_usd {
+                if !positive(max)
+                    || !positive(budget.input_usd_per_mtok)
+                    || !positive(budget.output_usd_per_mtok)
+                {
+                    return Err(ClaudeSDKError::configuration(
+                        "'turn_budget' needs a positive 'max_usd' and token prices",
+                    ));
+                }
+            }
+        }
+        if let Some(ref policy) = self.context_policy {
+            if policy.context_window == 0 || !(policy.threshold > 0.0 && policy.threshold <= 1.0) {
+                return Err(ClaudeSDKError::configuration(format!(
+                    "'context_policy' needs a context window and a threshold above 0 and at most 1, got {} of {}",
+                    policy.threshold, policy.context_window
+                )));
+            }
+        }
+        if self
+            .outbound_frame_limit
+            .is_some_and(|limit| limit.max_bytes == 0)
+        {
+            return Err(ClaudeSDKError::configuration(
+                "'outbound_frame_limit' must allow at least 1 byte",
+            ));
+        }
+        if let Some(tool) = self
+            .allowed_tools
+            .iter()
+            .find(|tool| self.disallowed_tools.contains(tool))
+        {
+            return Err(ClaudeSDKError::configuration(format!(
+                "Tool '{}' is both allowed and disallowed",
+                tool
+            )));
+        }
+        if let Some(key) = self
+            .env
+            .keys()
+            .find(|key| key.is_empty() || key.contains('='))
+        {
+            return Err(ClaudeSDKError::configuration(format!(
+                "Invalid environment variable name '{}'",
+                key
+            )));
+        }
+        if let Some(ref retry) = self.turn_retry {
+            if !(0.0..=1.0).contains(&retry.jitter) {
+                return Err(ClaudeSDKError::configuration(format!(
+                    "'turn_retry' jitter must be between 0 and 1, got {}",
+                    retry.jitter
+                )));
+            }
+            if retry.initial_delay > retry.max_delay {
+                return Err(ClaudeSDKError::configuration(
+                    "'turn_retry' initial delay is longer than its maximum",
+                ));
+            }
+        }
+        if !self.disabled_mcp_servers.is_empty() {
+            let McpServersConfig::Map(ref servers) = self.mcp_servers else {
+                return Err(ClaudeSDKError::configuration(
+                    "'disabled_mcp_servers' requires MCP servers configured inline",
+                ));
+            };
+            if let Some(name) = self
+                .disabled_mcp_servers
+                .iter()
+                .find(|name| !servers.contains_key(*name))
+            {
+                return Err(ClaudeSDKError::configuration(format!(
+                    "Unknown MCP server '{}' in 'disabled_mcp_servers'",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(builder: ClaudeAgentOptionsBuilder) -> String {
+        builder.build().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_builder_sets_every_kind_of_option() {
+        let options = ClaudeAgentOptions::builder()
+            .model("claude-sonnet-4-5")
+            .allowed_tools(["Read", "Grep"])
+            .disallowed_tools(vec!["Bash".to_string()])
+            .env("A", "1")
+            .add_dir("/tmp")
+            .session_tag("tenant", "acme")
+            .continue_conversation()
+            .fork_session()
+            .partial_coalescing(PartialCoalescing::default())
+            .build()
+            .unwrap();
+
+        assert_eq!(options.model.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(options.allowed_tools, vec!["Read", "Grep"]);
+        assert_eq!(options.env.get("A").map(String::as_str), Some("1"));
+        assert_eq!(options.add_dirs, vec![PathBuf::from("/tmp")]);
+        assert!(options.fork_session);
+        assert!(options.include_partial_messages);
+        assert!(options.partial_coalescing.is_some());
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_combinations() {
+        let builder = ClaudeAgentOptions::builder;
+
+        assert!(error(builder().fork_session()).contains("fork_session"));
+        assert!(error(builder().resume("")).contains("resume"));
+        assert!(error(builder().resume_session_at("msg_1")).contains("resume_session_at"));
+        assert!(error(builder().resume_fallback_new()).contains("resume_fallback_new"));
+        assert!(error(
+            builder()
+                .can_use_tool(|_, _, _| async { PermissionResult::allow() })
+                .permission_prompt_tool_name("mcp__perm__ask")
+        )
+        .contains("permission_prompt_tool_name"));
+        assert!(error(
+            builder().permission_policy(crate::PermissionPolicy::new().max_concurrent("Bash", 0))
+        )
+        .contains("at least 1"));
+        assert!(error(builder().max_pending_partial_messages(0)).contains("at least 1"));
+        assert!(error(builder().max_turns(0)).contains("max_turns"));
+        assert!(error(builder().max_budget_usd(f64::NAN)).contains("max_budget_usd"));
+        assert!(error(builder().max_budget_usd(-1.0)).contains("max_budget_usd"));
+        assert!(error(builder().turn_budget(TurnBudget::default())).contains("turn_budget"));
+        assert!(
+            error(builder().context_policy(ContextPolicy::warn_at(1.5))).contains("context_policy")
+        );
+        assert!(error(
+            builder().context_policy(ContextPolicy::compact_at(0.8).with_context_window(0))
+        )
+        .contains("context_policy"));
+        assert!(
+            error(builder().turn_budget(TurnBudget::usd(1.0, 3.0, 0.0))).contains("turn_budget")
+        );
+        assert!(
+            error(builder().outbound_frame_limit(OutboundFrameLimit::new(0)))
+                .contains("outbound_frame_limit")
+        );
+        assert!(
+            error(builder().allowed_tools(["Bash"]).disallowed_tools(["Bash"]))
+                .contains("both allowed and disallowed")
+        );
+        assert!(error(builder().env("A=B", "1")).contains("environment variable"));
+        assert!(error(builder().turn_retry(TurnRetry::new(1).with_jitter(1.5))).contains("jitter"));
+        assert!(error(builder().turn_retry(TurnRetry::new(1).with_delays(
+            std::time::Duration::from_secs(2),
+            std::time::Duration::from_secs(1)
+        )))
+        .contains("initial delay"));
+        assert!(error(builder().disable_mcp_server("github")).contains("github"));
+        assert!(error(
+            builder()
+                .mcp_servers(McpServersConfig::Path("mcp.json".into()))
+                .disable_mcp_server("github")
+        )
+        .contains("inline"));
+
+        // Struct fields set directly are checked as well
+        let mut options = ClaudeAgentOptions::new();
+        options.synthesize_partial_messages = true;
+        assert!(options.validate().is_err());
+        options.include_partial_messages = true;
+        assert!(options.validate().is_ok());
+        options.include_partial_messages = false;
+        options.synthesize_partial_messages = false;
+        options.max_pending_partial_messages = Some(16);
+        assert!(options.validate().is_err());
+    }
+}
@@ -0,0 +1,563 @@
+//! Workspace-relative path utilities for tool inputs.
+//!
+//! Permission policies frequently need to answer "does this tool call touch
+//! a file outside the workspace?". [`PathResolver`] resolves the paths found
+//! in a tool input against the session's working directory and additional
+//! directories, and reports any that escape them.
+//!
+//! Resolution is lexical: `.` and `..` components are collapsed without
+//! touching the filesystem, so paths that don't exist yet (e.g. a `Write`
+//! target) resolve the same way as existing ones. Symlinks are not followed.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::PathResolver;
+//! use serde_json::json;
+//!
+//! let resolver = PathResolver::new("/work/repo");
+//! let paths = resolver.resolve(&json!({"file_path": "../secrets.txt"}));
+//!
+//! assert_eq!(paths[0].path.to_str(), Some("/work/secrets.txt"));
+//! assert!(!paths[0].within_workspace);
+//! ```
+//!
+//! [`WorkspaceGuard`] builds on this to deny writes and shell commands that
+//! reach outside the workspace; unlike the resolver it follows symlinks.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::hooks::{deny_tool_use, HookRegistry, PreToolUse};
+use crate::types::ClaudeAgentOptions;
+
+/// Tool input keys that hold a file or directory path.
+const PATH_KEYS: &[&str] = &["file_path", "path", "notebook_path"];
+
+/// Tools checked by a [`WorkspaceGuard`] by default.
+pub const GUARDED_TOOLS: &[&str] = &["Write", "Edit", "MultiEdit", "NotebookEdit", "Bash"];
+
+/// Paths shell commands may use regardless of the workspace.
+const DEVICE_PATHS: &[&str] = &[
+    "/dev/null",
+    "/dev/stdin",
+    "/dev/stdout",
+    "/dev/stderr",
+    "/dev/tty",
+];
+
+/// A path found in a tool input, resolved against the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedPath {
+    /// The input key the path was read from.
+    pub key: String,
+    /// The path as it appeared in the tool input.
+    pub original: String,
+    /// The absolute, normalized path.
+    pub path: PathBuf,
+    /// Whether the path lies within the working directory or an additional directory.
+    pub within_workspace: bool,
+}
+
+/// Resolves tool input paths against a session's workspace roots.
+#[derive(Debug, Clone)]
+pub struct PathResolver {
+    cwd: PathBuf,
+    roots: Vec<PathBuf>,
+}
+
+impl PathResolver {
+    /// Create a resolver rooted at `cwd`.
+    pub fn new(cwd: impl Into<PathBuf>) -> Self {
+        let cwd = normalize(&cwd.into());
+        Self {
+            roots: vec![cwd.clone()],
+            cwd,
+        }
+    }
+
+    /// Create a resolver from the `cwd` and `add_dirs` of an options struct.
+    ///
+    /// Falls back to the process working directory when `cwd` is unset.
+    pub fn from_options(options: &ClaudeAgentOptions) -> Self {
+        let cwd = options
+            .cwd
+            .clone()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("/"));
+        options
+            .add_dirs
+            .iter()
+            .fold(Self::new(cwd), |resolver, dir| {
+                resolver.with_additional_dir(dir)
+            })
+    }
+
+    /// Add a directory that counts as part of the workspace.
+    ///
+    /// Relative directories are resolved against the working directory.
+    pub fn with_additional_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        let dir = self.absolute(dir.as_ref());
+        self.roots.push(dir);
+        self
+    }
+
+    /// The normalized working directory.
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Resolve a single path string to an absolute, normalized path.
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        normalize(&self.join(path))
+    }
+
+    /// Check whether an absolute, normalized path lies within the workspace.
+    pub fn is_within_workspace(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Resolve every path found in a tool input.
+    ///
+    /// Looks at the well-known path keys used by the built-in file tools
+    /// (`file_path`, `path`, `notebook_path`).
+    pub fn resolve(&self, tool_input: &serde_json::Value) -> Vec<ResolvedPath> {
+        PATH_KEYS
+            .iter()
+            .filter_map(|key| {
+                let original = tool_input.get(*key)?.as_str()?;
+                let path = self.resolve_path(original);
+                Some(ResolvedPath {
+                    key: key.to_string(),
+                    original: original.to_string(),
+                    within_workspace: self.is_within_workspace(&path),
+                    path,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the paths in a tool input that escape the workspace.
+    pub fn escapes(&self, tool_input: &serde_json::Value) -> Vec<ResolvedPath> {
+        self.resolve(tool_input)
+            .into_iter()
+            .filter(|p| !p.within_workspace)
+            .collect()
+    }
+
+    fn absolute(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            normalize(path)
+        } else {
+            normalize(&self.cwd.join(path))
+        }
+    }
+
+    /// Expand `~/` and make `path` absolute, without normalizing it.
+    fn join(&self, path: &str) -> PathBuf {
+        let home_relative = match path {
+            "~" => Some(""),
+            _ => path.strip_prefix("~/"),
+        };
+        let expanded = match (home_relative, std::env::var_os("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(path),
+        };
+        self.cwd.join(expanded)
+    }
+}
+
+/// Denies tool calls that write or run commands outside the workspace.
+///
+/// The workspace is the session's `cwd` and `add_dirs`, plus any
+/// directories added with [`allow_dir`](Self::allow_dir). File tools are
+/// checked on their path inputs; for `Bash`, every path-like argument of
+/// the command line is checked (anything containing a `/`, or `.`, `..`
+/// and `~`), including redirection targets like `>../x`, except the
+/// programs being run and the standard devices such as `/dev/null`.
+///
+/// The check for `Bash` is best effort: it splits the command line on
+/// shell operators and quotes without a full shell parser, and doesn't
+/// expand variables, globs or command substitutions, so `cat > $OUT` or a
+/// script writing files itself gets past it. Combine the guard with
+/// [`SandboxSettings`](crate::SandboxSettings) for a hard guarantee.
+///
+/// Paths are resolved the way the OS would, following symlinks of the
+/// parts that exist, so neither `..` nor a link pointing out of the
+/// workspace gets past the guard. Enable it with
+/// [`ClaudeAgentOptions::with_workspace_guard`], which checks every tool
+/// call in a `PreToolUse` hook, regardless of the permission mode or
+/// `allowed_tools`.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::{PathResolver, WorkspaceGuard};
+/// use serde_json::json;
+///
+/// let guard = WorkspaceGuard::new().allow_dir("/tmp/scratch");
+/// let resolver = PathResolver::new("/work/repo");
+///
+/// let write = json!({"file_path": "../other/notes.md"});
+/// assert!(guard.check(&resolver, "Write", &write).is_some());
+/// let bash = json!({"command": "cp build.log /tmp/scratch/"});
+/// assert!(guard.check(&resolver, "Bash", &bash).is_none());
+/// ```
+#[derive(Debug, Clone)]
+pub struct WorkspaceGuard {
+    tools: Vec<String>,
+    additional_dirs: Vec<PathBuf>,
+}
+
+impl Default for WorkspaceGuard {
+    fn default() -> Self {
+        Self {
+            tools: GUARDED_TOOLS.iter().map(|t| t.to_string()).collect(),
+            additional_dirs: Vec::new(),
+        }
+    }
+}
+
+impl WorkspaceGuard {
+    /// Create a guard checking the [`GUARDED_TOOLS`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also check calls of `tool` (on its `file_path`, `path` and
+    /// `notebook_path` inputs).
+    pub fn guard_tool(mut self, tool: impl Into<String>) -> Self {
+        let tool = tool.into();
+        if !self.tools.contains(&tool) {
+            self.tools.push(tool);
+        }
+        self
+    }
+
+    /// Stop checking calls of `tool`.
+    pub fn unguard_tool(mut self, tool: &str) -> Self {
+        self.tools.retain(|t| t != tool);
+        self
+    }
+
+    /// Allow access to `dir` in addition to the session's workspace.
+    ///
+    /// Relative directories are resolved against the working directory.
+    pub fn allow_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.additional_dirs.push(dir.into());
+        self
+    }
+
+    /// Get the checked tools.
+    pub fn tools(&self) -> &[String] {
+        &self.tools
+    }
+
+    /// Check a tool call, returning why it is denied, if it is.
+    ///
+    /// The workspace is that of `resolver` plus the directories allowed by
+    /// this guard.
+    pub fn check(
+        &self,
+        resolver: &PathResolver,
+        tool_name: &str,
+        input: &serde_json::Value,
+    ) -> Option<String> {
+        if !self.tools.iter().any(|t| t == tool_name) {
+            return None;
+        }
+        let paths: Vec<&str> = if tool_name == "Bash" {
+            let command_line = input.get("command").and_then(|c| c.as_str())?;
+            path_arguments(command_line).collect()
+        } else {
+            PATH_KEYS
+                .iter()
+                .filter_map(|key| input.get(*key)?.as_str())
+                .collect()
+        };
+
+        let roots: Vec<PathBuf> = resolver
+            .roots
+            .iter()
+            .cloned()
+            .chain(
+                self.additional_dirs
+                    .iter()
+                    .map(|dir| resolver.cwd.join(dir)),
+            )
+            .map(|root| physical(&root))
+            .collect();
+        paths.into_iter().find_map(|path| {
+            let resolved = physical(&resolver.join(path));
+            (!roots.iter().any(|root| resolved.starts_with(root))).then(|| {
+                format!(
+                    "{} may not access {} (resolves to {}), which is outside the workspace",
+                    tool_name,
+                    path,
+                    resolved.display()
+                )
+            })
+        })
+    }
+
+    /// Hooks denying the calls this guard rejects, resolving paths with
+    /// `resolver`.
+    pub fn hooks(&self, resolver: PathResolver) -> HookRegistry {
+        let guard = self.clone();
+        HookRegistry::new().on(PreToolUse, move |input| {
+            let output = guard
+                .check(&resolver, &input.tool_name, &input.tool_input)
+                .map(deny_tool_use)
+                .unwrap_or_default();
+            async move { output }
+        })
+    }
+}
+
+/// Resolve an absolute path the way the OS would: components are applied in
+/// order and symlinks among the existing ones are followed, so `..` after a
+/// symlink leaves the link's target.
+fn physical(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => {
+                out.push(other.as_os_str());
+                if let Ok(real) = std::fs::canonicalize(&out) {
+                    out = real;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Path-like arguments of a shell command line, skipping the program each
+/// part of it runs.
+fn path_arguments(command_line: &str) -> impl Iterator<Item = &str> {
+    command_line
+        .split([';', '&', '|', '\n', '(', ')', '`'])
+        .flat_map(|segment| {
+            // Redirections split words too, so `cat>../x` yields `../x`
+            segment
+                .split(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+                .filter(|word| !word.is_empty() && !word.contains('='))
+                .skip(1)
+                .chain(
+                    // Options like --out=/some/path
+                    segment
+                        .split_whitespace()
+                        .filter_map(|word| word.split_once('=').map(|(_, value)| value)),
+                )
+        })
+        .filter(|word| {
+            !word.contains("://")
+                && !DEVICE_PATHS.contains(word)
+                && (word.contains('/') || matches!(*word, "." | ".." | "~"))
+        })
+}
+
+/// Lexically normalize a path, collapsing `.` and `..` components.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                // Never pop past the root
+                if !matches!(
+                    out.components().next_back(),
+                    None | Some(Component::RootDir | Component::Prefix(_))
+                ) {
+                    out.pop();
+                }
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve_relative_and_absolute_paths() {
+        let resolver = PathResolver::new("/work/repo");
+
+        assert_eq!(
+            resolver.resolve_path("src/./lib.rs"),
+            PathBuf::from("/work/repo/src/lib.rs")
+        );
+        assert_eq!(
+            resolver.resolve_path("/work/repo/a/../b"),
+            PathBuf::from("/work/repo/b")
+        );
+        assert_eq!(resolver.resolve_path("/../../etc"), PathBuf::from("/etc"));
+    }
+
+    #[test]
+    fn test_detects_escape_attempts() {
+        let resolver = PathResolver::new("/work/repo");
+
+        let escapes = resolver.escapes(&json!({"file_path": "src/../../other/file"}));
+        assert_eq!(escapes.len(), 1);
+        assert_eq!(escapes[0].path, PathBuf::from("/work/other/file"));
+
+        assert!(resolver
+            .escapes(&json!({"file_path": "/work/repo/src/main.rs"}))
+            .is_empty());
+        // A sibling directory sharing a prefix is not inside the workspace
+        assert_eq!(
+            resolver.escapes(&json!({"path": "/work/repo-evil"})).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_additional_dirs_count_as_workspace() {
+        let mut options = ClaudeAgentOptions::new().with_cwd("/work/repo");
+        options.add_dirs.push(PathBuf::from("../shared"));
+
+        let resolver = PathResolver::from_options(&options);
+        let resolved = resolver.resolve(&json!({"path": "/work/shared/lib", "pattern": "*.rs"}));
+
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].key, "path");
+        assert!(resolved[0].within_workspace);
+    }
+
+    #[test]
+    fn test_guard_denies_dot_dot_escapes() {
+        let guard = WorkspaceGuard::new();
+        let resolver = PathResolver::new("/work/repo").with_additional_dir("/work/shared");
+
+        for input in [
+            json!({"file_path": "src/../../other/file"}),
+            json!({"file_path": "/work/repo/../repo-evil/x"}),
+            json!({"notebook_path": "~/notes.ipynb"}),
+        ] {
+            assert!(
+                guard.check(&resolver, "Edit", &input).is_some(),
+                "{}",
+                input
+            );
+        }
+        assert!(guard
+            .check(&resolver, "Write", &json!({"file_path": "../shared/a.txt"}))
+            .is_none());
+        // Reads are not guarded by default
+        assert!(guard
+            .check(&resolver, "Read", &json!({"file_path": "/etc/passwd"}))
+            .is_none());
+        let guard = guard.guard_tool("Read").unguard_tool("Edit");
+        assert!(guard
+            .check(&resolver, "Read", &json!({"file_path": "/etc/passwd"}))
+            .is_some());
+        assert!(guard
+            .check(&resolver, "Edit", &json!({"file_path": "/etc/passwd"}))
+            .is_none());
+    }
+
+    #[test]
+    fn test_guard_checks_bash_arguments() {
+        let guard = WorkspaceGuard::new().allow_dir("/tmp/scratch");
+        let resolver = PathResolver::new("/work/repo");
+        let bash = |command: &str| guard.check(&resolver, "Bash", &json!({ "command": command }));
+
+        for command in [
+            "rm -rf ../other",
+            "cd / && ls",
+            "echo hi > ~/.bashrc",
+            "tar -xf a.tar --directory=/etc",
+            "cargo build 2>/work/log.txt",
+            "bash -c 'cp secret /tmp/exfil'",
+            "cat>../x",
+            "echo hi >>~/.profile",
+            "sort<../input.txt",
+        ] {
+            assert!(bash(command).is_some(), "{}", command);
+        }
+        for command in [
+            "cargo test",
+            "/usr/bin/git status 2>/dev/null",
+            "cp target/out.bin /tmp/scratch/",
+            "curl https://example.com/a/b",
+            "ls ./src | wc -l",
+        ] {
+            assert!(bash(command).is_none(), "{}", command);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_guard_hook_denies_escapes() {
+        use crate::types::{
+            HookEvent, HookInput, HookOutput, HookSpecificOutput, PermissionBehavior,
+            PreToolUseHookInput,
+        };
+
+        let hooks = WorkspaceGuard::new()
+            .hooks(PathResolver::new("/work/repo"))
+            .into_hooks();
+        let hook = &hooks[&HookEvent::PreToolUse][0].hooks[0];
+        let decision = |file_path: &str| {
+            let input: PreToolUseHookInput = serde_json::from_value(json!({
+                "session_id": "s",
+                "transcript_path": "/tmp/t.jsonl",
+                "cwd": "/work/repo",
+                "hook_event_name": "PreToolUse",
+                "tool_name": "Write",
+                "tool_input": {"file_path": file_path},
+            }))
+            .unwrap();
+            hook(HookInput::PreToolUse(input), None, Default::default())
+        };
+
+        let HookOutput::Sync(output) = decision("../x").await else {
+            panic!("expected a sync output");
+        };
+        match output.hook_specific_output {
+            Some(HookSpecificOutput::PreToolUse(specific)) => {
+                assert_eq!(specific.permission_decision, Some(PermissionBehavior::Deny));
+            }
+            other => panic!("unexpected output: {:?}", other),
+        }
+        let HookOutput::Sync(output) = decision("x").await else {
+            panic!("expected a sync output");
+        };
+        assert!(output.hook_specific_output.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_guard_follows_symlinks() {
+        let outside = tempfile::tempdir().unwrap();
+        let workspace = tempfile::tempdir().unwrap();
+        let root = workspace.path();
+        std::os::unix::fs::symlink(outside.path(), root.join("link")).unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+
+        let guard = WorkspaceGuard::new();
+        let resolver = PathResolver::new(root);
+        let write = |path: &str| guard.check(&resolver, "Write", &json!({ "file_path": path }));
+
+        assert!(write("link/new.txt").is_some());
+        // `..` after a symlink leaves the link's target, not the workspace
+        assert!(write("link/../escaped.txt").is_some());
+        assert!(write("src/../new.txt").is_none());
+        assert!(write("src/missing/dir/new.txt").is_none());
+
+        // A workspace reached through a symlink still counts
+        let alias = outside.path().join("alias");
+        std::os::unix::fs::symlink(root, &alias).unwrap();
+        let resolver = PathResolver::new(&alias);
+        assert!(guard
+            .check(&resolver, "Write", &json!({"file_path": "src/lib.rs"}))
+            .is_none());
+    }
+}
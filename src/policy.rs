@@ -0,0 +1,879 @@
+//! Declarative tool restrictions.
+//!
+//! A [`PermissionPolicy`] lists tools and shell commands the agent may not
+//! use, and [`Quota`]s on how much it may use others. Applied with
+//! [`ClaudeAgentOptions::with_permission_policy`], denied tools are
+//! disallowed on the CLI and every call is also checked by a `PreToolUse`
+//! hook running before the [`hooks`](ClaudeAgentOptions::hooks), so the
+//! policy holds regardless of the permission mode or `allowed_tools`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::{ClaudeAgentOptions, PermissionPolicy};
+//! use serde_json::json;
+//!
+//! // Deny WebFetch, WebSearch and networking shell commands, except ssh
+//! let policy = PermissionPolicy::no_network().allow_command("ssh");
+//! assert!(policy
+//!     .check("Bash", &json!({"command": "cd src && curl -s https://example.com"}))
+//!     .is_some());
+//! assert!(policy.check("Bash", &json!({"command": "cargo test"})).is_none());
+//!
+//! let options = ClaudeAgentOptions::new().with_permission_policy(policy);
+//! assert!(options.disallowed_tools.contains(&"WebFetch".to_string()));
+//! ```
+//!
+//! Command checks look at the program each part of a shell command line
+//! runs, so they catch `env X=1 curl` or `ls; wget`, but not a script that
+//! opens connections itself. For a hard guarantee, combine the policy with
+//! [`SandboxSettings`](crate::SandboxSettings).
+//!
+//! # Quotas
+//!
+//! Quotas are counted per session, once a call has run (in a `PostToolUse`
+//! hook), so calls denied later on, e.g. by the user, don't count. A call
+//! that would go over a quota is denied, and reported to
+//! [`on_quota_exceeded`](PermissionPolicy::on_quota_exceeded) as a
+//! [`QuotaExceeded`]. Calls running in parallel are checked against the
+//! same count, so together they can go over a quota by what they need.
+//!
+//! Clones of a policy share the counts, so keep one to ask how much of each
+//! quota is left. A session's counts are dropped when its client
+//! disconnects.
+//!
+//! ```rust
+//! use claude_agents_sdk::{ClaudeAgentOptions, PermissionPolicy, Quota};
+//! use serde_json::json;
+//!
+//! let policy = PermissionPolicy::new()
+//!     .max_tool_calls("Bash", 20)
+//!     .max_tool_calls("WebFetch", 5)
+//!     .max_bytes_written(1 << 20);
+//! let options = ClaudeAgentOptions::new().with_permission_policy(policy.clone());
+//!
+//! // What the hooks do for each call
+//! let fetch = json!({"url": "https://example.com"});
+//! assert!(policy.check_quotas("session", "WebFetch", &fetch).is_ok());
+//! policy.charge("session", "WebFetch", &fetch);
+//! let usage = policy.quota_usage("session");
+//! assert_eq!(usage[1].quota, Quota::ToolCalls("WebFetch".to_string()));
+//! assert_eq!(usage[1].remaining(), 4);
+//! ```
+//!
+//! # Concurrency limits
+//!
+//! [`max_concurrent`](PermissionPolicy::max_concurrent) caps how many calls
+//! of a tool run at once in a session, e.g. to keep parallel subagents off
+//! a shared resource. A call over the limit waits in the `PreToolUse` hook
+//! until a running call of the tool finishes (its `PostToolUse` or
+//! `PostToolUseFailure` hook), or the turn ends (the `Stop` hook). This
+//! works in any permission mode, with or without a
+//! [`can_use_tool`](ClaudeAgentOptions::can_use_tool) callback.
+//!
+//! ```rust
+//! use claude_agents_sdk::{ClaudeAgentOptions, PermissionPolicy};
+//!
+//! // One Bash and four Reads at a time
+//! let policy = PermissionPolicy::new()
+//!     .max_concurrent("Bash", 1)
+//!     .max_concurrent("Read", 4);
+//! let options = ClaudeAgentOptions::new().with_permission_policy(policy);
+//! assert!(options.validate().is_ok());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+
+use crate::hooks::{
+    deny_tool_use, HookRegistry, PostToolUse, PostToolUseFailure, PreToolUse, Stop,
+};
+use crate::types::ClaudeAgentOptions;
+
+/// Tools that reach the network directly.
+pub const NETWORK_TOOLS: &[&str] = &["WebFetch", "WebSearch"];
+
+/// Shell commands denied by [`PermissionPolicy::no_network`].
+pub const NETWORK_COMMANDS: &[&str] = &[
+    "curl", "wget", "nc", "ncat", "netcat", "socat", "telnet", "ssh", "scp", "sftp", "ftp", "rsync",
+];
+
+/// Seconds the CLI waits for the `PreToolUse` hook of a call queued behind
+/// a concurrency limit.
+const SLOT_WAIT_TIMEOUT_SECS: f64 = 3600.0;
+
+/// Programs that run the command following them.
+const COMMAND_WRAPPERS: &[&str] = &[
+    "sudo", "env", "command", "exec", "nohup", "time", "nice", "xargs", "timeout", "builtin",
+];
+
+/// A per-session limit a [`PermissionPolicy`] puts on tool use.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Quota {
+    /// Calls of a tool, e.g. `Bash`.
+    ToolCalls(String),
+    /// Bytes of content written by the `Write`, `Edit` and `MultiEdit`
+    /// tools.
+    BytesWritten,
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quota::ToolCalls(tool) => write!(f, "{} calls", tool),
+            Quota::BytesWritten => write!(f, "bytes written"),
+        }
+    }
+}
+
+/// How much of a [`Quota`] a session has used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// The quota.
+    pub quota: Quota,
+    /// Its limit per session.
+    pub limit: u64,
+    /// How much the session has used.
+    pub used: u64,
+}
+
+impl QuotaUsage {
+    /// How much is left.
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.used)
+    }
+}
+
+/// A tool call denied because it would take its session over a [`Quota`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// ID of the session.
+    pub session_id: String,
+    /// The quota.
+    pub quota: Quota,
+    /// Its limit per session.
+    pub limit: u64,
+    /// How much the session had used.
+    pub used: u64,
+    /// How much the call needed: one call, or the bytes it would write.
+    pub requested: u64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Quota exceeded for {}: {} of {} used this session",
+            self.quota, self.used, self.limit
+        )?;
+        if self.requested > 1 {
+            write!(f, ", and the call needs {}", self.requested)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// The calls of a session running under a concurrency limit.
+#[derive(Default)]
+struct RunningCalls {
+    /// Slots of each limited tool.
+    semaphores: HashMap<String, Arc<Semaphore>>,
+    /// Slots held by the calls running, by tool use ID.
+    permits: HashMap<String, OwnedSemaphorePermit>,
+}
+
+/// Callback for [`PermissionPolicy::on_quota_exceeded`].
+pub type QuotaExceededCallback = Arc<dyn Fn(&QuotaExceeded) + Send + Sync>;
+
+/// Tools and shell commands the agent may not use, and quotas on the tools
+/// it may.
+#[derive(Clone, Default)]
+pub struct PermissionPolicy {
+    denied_tools: Vec<String>,
+    denied_commands: Vec<String>,
+    quotas: Vec<(Quota, u64)>,
+    /// Quota usage per session ID, shared by clones.
+    usage: Arc<Mutex<HashMap<String, HashMap<Quota, u64>>>>,
+    concurrency_limits: Vec<(String, usize)>,
+    /// Calls running under a concurrency limit per session ID, shared by
+    /// clones.
+    running: Arc<Mutex<HashMap<String, RunningCalls>>>,
+    on_quota_exceeded: Option<QuotaExceededCallback>,
+}
+
+impl fmt::Debug for PermissionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PermissionPolicy")
+            .field("denied_tools", &self.denied_tools)
+            .field("denied_commands", &self.denied_commands)
+            .field("quotas", &self.quotas)
+            .field("concurrency_limits", &self.concurrency_limits)
+            .field(
+                "on_quota_exceeded",
+                &self.on_quota_exceeded.as_ref().map(|_| "<callback>"),
+            )
+            .finish()
+    }
+}
+
+impl PartialEq for PermissionPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        self.denied_tools == other.denied_tools
+            && self.denied_commands == other.denied_commands
+            && self.quotas == other.quotas
+            && self.concurrency_limits == other.concurrency_limits
+    }
+}
+
+impl Eq for PermissionPolicy {}
+
+impl PermissionPolicy {
+    /// Create a policy that denies nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deny network access: [`NETWORK_TOOLS`], and Bash commands running
+    /// one of [`NETWORK_COMMANDS`].
+    pub fn no_network() -> Self {
+        let policy = NETWORK_TOOLS
+            .iter()
+            .fold(Self::new(), |policy, tool| policy.deny_tool(*tool));
+        NETWORK_COMMANDS
+            .iter()
+            .fold(policy, |policy, command| policy.deny_command(*command))
+    }
+
+    /// Deny a tool.
+    pub fn deny_tool(mut self, tool: impl Into<String>) -> Self {
+        let tool = tool.into();
+        if !self.denied_tools.contains(&tool) {
+            self.denied_tools.push(tool);
+        }
+        self
+    }
+
+    /// Deny Bash commands running `command` (a program name, e.g. `curl`).
+    pub fn deny_command(mut self, command: impl Into<String>) -> Self {
+        let command = command.into();
+        if !self.denied_commands.contains(&command) {
+            self.denied_commands.push(command);
+        }
+        self
+    }
+
+    /// Stop denying `command`.
+    pub fn allow_command(mut self, command: &str) -> Self {
+        self.denied_commands.retain(|c| c != command);
+        self
+    }
+
+    /// Allow at most `limit` calls of `tool` per session.
+    pub fn max_tool_calls(self, tool: impl Into<String>, limit: u64) -> Self {
+        self.quota(Quota::ToolCalls(tool.into()), limit)
+    }
+
+    /// Allow the `Write`, `Edit` and `MultiEdit` tools to write at most
+    /// `limit` bytes of content per session.
+    pub fn max_bytes_written(self, limit: u64) -> Self {
+        self.quota(Quota::BytesWritten, limit)
+    }
+
+    fn quota(mut self, quota: Quota, limit: u64) -> Self {
+        match self.quotas.iter_mut().find(|(q, _)| *q == quota) {
+            Some(entry) => entry.1 = limit,
+            None => self.quotas.push((quota, limit)),
+        }
+        self
+    }
+
+    /// Allow at most `limit` calls of `tool` to run at once per session;
+    /// further calls wait for a running one to finish.
+    ///
+    /// A limit of 0 is rejected by [`ClaudeAgentOptions::validate`].
+    pub fn max_concurrent(mut self, tool: impl Into<String>, limit: usize) -> Self {
+        let tool = tool.into();
+        match self.concurrency_limits.iter_mut().find(|(t, _)| *t == tool) {
+            Some(entry) => entry.1 = limit,
+            None => self.concurrency_limits.push((tool, limit)),
+        }
+        self
+    }
+
+    /// Call `callback` with each call denied for going over a quota.
+    pub fn on_quota_exceeded(
+        mut self,
+        callback: impl Fn(&QuotaExceeded) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_quota_exceeded = Some(Arc::new(callback));
+        self
+    }
+
+    /// Get the quotas and their limits.
+    pub fn quotas(&self) -> &[(Quota, u64)] {
+        &self.quotas
+    }
+
+    /// Get the concurrency limits per tool.
+    pub fn concurrency_limits(&self) -> &[(String, usize)] {
+        &self.concurrency_limits
+    }
+
+    /// Get how much of each quota `session_id` has used, in the order the
+    /// quotas were added.
+    pub fn quota_usage(&self, session_id: &str) -> Vec<QuotaUsage> {
+        let usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        let session = usage.get(session_id);
+        self.quotas
+            .iter()
+            .map(|(quota, limit)| QuotaUsage {
+                quota: quota.clone(),
+                limit: *limit,
+                used: session
+                    .and_then(|s| s.get(quota))
+                    .copied()
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Check if a tool call of `session_id` would go over a quota.
+    pub fn check_quotas(
+        &self,
+        session_id: &str,
+        tool_name: &str,
+        input: &Value,
+    ) -> Result<(), QuotaExceeded> {
+        let charges = self.charges(tool_name, input);
+        if charges.is_empty() {
+            return Ok(());
+        }
+        let usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        let session = usage.get(session_id);
+        for (quota, limit, requested) in charges {
+            let used = session
+                .and_then(|s| s.get(quota))
+                .copied()
+                .unwrap_or_default();
+            if used.saturating_add(requested) > limit {
+                return Err(QuotaExceeded {
+                    session_id: session_id.to_string(),
+                    quota: quota.clone(),
+                    limit,
+                    used,
+                    requested,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a tool call of `session_id` that ran against the quotas.
+    pub fn charge(&self, session_id: &str, tool_name: &str, input: &Value) {
+        let charges = self.charges(tool_name, input);
+        if charges.is_empty() {
+            return;
+        }
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        let session = usage.entry(session_id.to_string()).or_default();
+        for (quota, _, requested) in charges {
+            let used = session.entry(quota.clone()).or_default();
+            *used = used.saturating_add(requested);
+        }
+    }
+
+    /// Drop the counts and running calls of `session_id`, once it has
+    /// ended.
+    pub fn end_session(&self, session_id: &str) {
+        self.usage
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(session_id);
+        self.running_calls().remove(session_id);
+    }
+
+    fn running_calls(&self) -> std::sync::MutexGuard<'_, HashMap<String, RunningCalls>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait until `session_id` may run another call of `tool_name`, then
+    /// hold a slot for `tool_use_id` until the call finishes.
+    async fn acquire_slot(&self, session_id: &str, tool_name: &str, tool_use_id: String) {
+        let Some(&(_, limit)) = self.concurrency_limits.iter().find(|(t, _)| t == tool_name) else {
+            return;
+        };
+        let semaphore = Arc::clone(
+            self.running_calls()
+                .entry(session_id.to_string())
+                .or_default()
+                .semaphores
+                .entry(tool_name.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(limit))),
+        );
+        let Ok(permit) = semaphore.acquire_owned().await else {
+            return;
+        };
+        // Unless the session ended while the call waited
+        if let Some(calls) = self.running_calls().get_mut(session_id) {
+            calls.permits.insert(tool_use_id, permit);
+        }
+    }
+
+    /// Free the slot of a call of `session_id` that finished.
+    fn release_slot(&self, session_id: &str, tool_use_id: &str) {
+        if let Some(calls) = self.running_calls().get_mut(session_id) {
+            calls.permits.remove(tool_use_id);
+        }
+    }
+
+    /// Free the slots of `session_id` at the end of a turn, which no call
+    /// outlives, including calls denied after the `PreToolUse` hook.
+    fn end_turn(&self, session_id: &str) {
+        if let Some(calls) = self.running_calls().get_mut(session_id) {
+            if !calls.permits.is_empty() {
+                debug!("Releasing tool concurrency slots at end of turn");
+            }
+            calls.permits.clear();
+        }
+    }
+
+    /// The quotas a call of `tool_name` counts against, with their limits
+    /// and how much the call needs.
+    fn charges(&self, tool_name: &str, input: &Value) -> Vec<(&Quota, u64, u64)> {
+        self.quotas
+            .iter()
+            .filter_map(|(quota, limit)| {
+                let requested = match quota {
+                    Quota::ToolCalls(tool) if tool == tool_name => 1,
+                    Quota::BytesWritten => bytes_written(tool_name, input)?,
+                    Quota::ToolCalls(_) => return None,
+                };
+                Some((quota, *limit, requested))
+            })
+            .collect()
+    }
+
+    /// Get the denied tools.
+    pub fn denied_tools(&self) -> &[String] {
+        &self.denied_tools
+    }
+
+    /// Get the denied shell commands.
+    pub fn denied_commands(&self) -> &[String] {
+        &self.denied_commands
+    }
+
+    /// Check a tool call, returning why it is denied, if it is.
+    pub fn check(&self, tool_name: &str, input: &Value) -> Option<String> {
+        if self.denied_tools.iter().any(|t| t == tool_name) {
+            return Some(format!(
+                "{} is not allowed by the permission policy",
+                tool_name
+            ));
+        }
+        if tool_name != "Bash" || self.denied_commands.is_empty() {
+            return None;
+        }
+        let command_line = input.get("command").and_then(Value::as_str)?;
+        invoked_programs(command_line)
+            .find(|program| self.denied_commands.iter().any(|c| c == program))
+            .map(|program| {
+                format!(
+                    "The command `{}` is not allowed by the permission policy",
+                    program
+                )
+            })
+    }
+
+    /// Hooks denying the calls this policy rejects, holding back those over
+    /// a concurrency limit, and counting the others against its quotas once
+    /// they have run.
+    pub fn hooks(&self) -> HookRegistry {
+        let limited = !self.concurrency_limits.is_empty();
+        let policy = self.clone();
+        let timeout = limited.then_some(SLOT_WAIT_TIMEOUT_SECS);
+        let hooks =
+            HookRegistry::new().on_tool_use(PreToolUse, timeout, move |input, tool_use_id| {
+                let policy = policy.clone();
+                async move {
+                    let denial = policy
+                        .check(&input.tool_name, &input.tool_input)
+                        .or_else(|| {
+                            let exceeded = policy
+                                .check_quotas(
+                                    &input.base.session_id,
+                                    &input.tool_name,
+                                    &input.tool_input,
+                                )
+                                .err()?;
+                            if let Some(ref callback) = policy.on_quota_exceeded {
+                                callback(&exceeded);
+                            }
+                            Some(exceeded.to_string())
+                        });
+                    if let Some(reason) = denial {
+                        return deny_tool_use(reason);
+                    }
+                    match tool_use_id {
+                        Some(id) => {
+                            policy
+                                .acquire_slot(&input.base.session_id, &input.tool_name, id)
+                                .await
+                        }
+                        // Without an ID the call's end can't be told
+                        None if limited => {
+                            debug!(
+                                "Not limiting a {} call without a tool use ID",
+                                input.tool_name
+                            )
+                        }
+                        None => {}
+                    }
+                    Default::default()
+                }
+            });
+        if self.quotas.is_empty() && !limited {
+            return hooks;
+        }
+        let policy = self.clone();
+        let hooks = hooks.on_tool_use(PostToolUse, None, move |input, tool_use_id| {
+            policy.charge(&input.base.session_id, &input.tool_name, &input.tool_input);
+            if let Some(id) = tool_use_id {
+                policy.release_slot(&input.base.session_id, &id);
+            }
+            async { Default::default() }
+        });
+        if !limited {
+            return hooks;
+        }
+        let policy = self.clone();
+        let hooks = hooks.on(PostToolUseFailure, move |input| {
+            policy.release_slot(&input.base.session_id, &input.tool_use_id);
+            async { Default::default() }
+        });
+        let policy = self.clone();
+        hooks.on(Stop, move |input| {
+            policy.end_turn(&input.base.session_id);
+            async { Default::default() }
+        })
+    }
+}
+
+/// Names of the programs a shell command line runs, as far as they can be
+/// told without a full shell parser.
+///
+/// Quotes are treated as separators too, so `bash -c "curl ..."` yields
+/// `curl`; this errs on the side of finding too many programs.
+fn invoked_programs(command_line: &str) -> impl Iterator<Item = &str> {
+    command_line
+        .split(|c: char| {
+            matches!(
+                c,
+                ';' | '&' | '|' | '\n' | '(' | ')' | '`' | '{' | '}' | '"' | '\''
+            )
+        })
+        .filter_map(|segment| {
+            segment
+                .split_whitespace()
+                .map(|word| word.trim_start_matches('$'))
+                // Skip variable assignments, wrappers and their options
+                .find(|word| {
+                    !word.is_empty()
+                        && !word.contains('=')
+                        && !word.starts_with('-')
+                        && !word.chars().all(|c| c.is_ascii_digit())
+                        && !COMMAND_WRAPPERS.contains(&program_name(word))
+                })
+                .map(program_name)
+        })
+}
+
+/// Bytes of content a call of `tool_name` writes, if it is a write tool.
+fn bytes_written(tool_name: &str, input: &Value) -> Option<u64> {
+    let len = |value: &Value, key: &str| {
+        value.get(key).and_then(Value::as_str).map_or(0, str::len) as u64
+    };
+    match tool_name {
+        "Write" => Some(len(input, "content")),
+        "Edit" => Some(len(input, "new_string")),
+        "MultiEdit" => Some(
+            input
+                .get("edits")
+                .and_then(Value::as_array)
+                .map_or(0, |edits| edits.iter().map(|e| len(e, "new_string")).sum()),
+        ),
+        _ => None,
+    }
+}
+
+/// The file name of `path`, e.g. `curl` for `/usr/bin/curl`.
+fn program_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+impl ClaudeAgentOptions {
+    /// Enforce `policy`: its tools are disallowed (and removed from
+    /// `allowed_tools`), and it is added to the
+    /// [`permission_policies`](Self::permission_policies), whose hooks deny
+    /// the calls it rejects.
+    pub fn with_permission_policy(mut self, policy: PermissionPolicy) -> Self {
+        self.allowed_tools
+            .retain(|tool| !policy.denied_tools.contains(tool));
+        for tool in &policy.denied_tools {
+            if !self.disallowed_tools.contains(tool) {
+                self.disallowed_tools.push(tool.clone());
+            }
+        }
+        self.permission_policies.push(policy);
+        self
+    }
+
+    /// Keep the agent offline (see [`PermissionPolicy::no_network`]).
+    pub fn with_no_network(self) -> Self {
+        self.with_permission_policy(PermissionPolicy::no_network())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        HookEvent, HookInput, HookMatcher, HookOutput, HookSpecificOutput, PermissionBehavior,
+    };
+    use serde_json::json;
+
+    fn bash(command: &str) -> Value {
+        json!({ "command": command })
+    }
+
+    #[test]
+    fn test_no_network_denies_network_commands() {
+        let policy = PermissionPolicy::no_network();
+        for command in [
+            "curl https://example.com",
+            "ls && wget -q http://x",
+            "cat data | nc host 80",
+            "FOO=1 /usr/bin/curl x",
+            "sudo -E timeout 5 ssh host",
+            "echo $(curl -s x)",
+            "bash -c 'scp a host:b'",
+        ] {
+            assert!(
+                policy.check("Bash", &bash(command)).is_some(),
+                "{}",
+                command
+            );
+        }
+        for command in ["cargo build", "grep curl src/", "ls -la", "ncdu /"] {
+            assert!(
+                policy.check("Bash", &bash(command)).is_none(),
+                "{}",
+                command
+            );
+        }
+        assert!(policy.check("WebFetch", &json!({})).is_some());
+        assert!(policy.check("Read", &json!({})).is_none());
+
+        let policy = policy.allow_command("curl");
+        assert!(policy.check("Bash", &bash("curl x")).is_none());
+    }
+
+    #[test]
+    fn test_quotas_are_counted_per_session() {
+        let policy = PermissionPolicy::new()
+            .max_tool_calls("Bash", 2)
+            .max_bytes_written(10);
+        let shared = policy.clone();
+        let run = |session: &str, tool: &str, input: &Value| {
+            policy.check_quotas(session, tool, input)?;
+            policy.charge(session, tool, input);
+            Ok::<_, QuotaExceeded>(())
+        };
+
+        assert!(run("a", "Bash", &bash("ls")).is_ok());
+        assert!(run("a", "Bash", &bash("ls")).is_ok());
+        let denied = run("a", "Bash", &bash("ls")).unwrap_err();
+        assert_eq!(denied.session_id, "a");
+        assert_eq!(denied.quota, Quota::ToolCalls("Bash".to_string()));
+        assert_eq!((denied.used, denied.limit), (2, 2));
+        assert!(run("b", "Bash", &bash("ls")).is_ok());
+
+        let write = json!({"file_path": "a.txt", "content": "12345678"});
+        assert!(run("a", "Write", &write).is_ok());
+        let edits = json!({"edits": [{"new_string": "12"}, {"new_string": "3"}]});
+        let denied = run("a", "MultiEdit", &edits).unwrap_err();
+        assert_eq!(denied.requested, 3);
+        assert!(denied.to_string().contains("8 of 10 used"));
+        assert!(run("a", "Edit", &json!({"new_string": "12"})).is_ok());
+        assert!(run("a", "Read", &json!({})).is_ok());
+
+        let usage = shared.quota_usage("a");
+        assert_eq!(usage[0].remaining(), 0);
+        assert_eq!(usage[1].used, 10);
+        assert_eq!(shared.quota_usage("c")[0].remaining(), 2);
+        assert_eq!(shared, policy.clone().max_bytes_written(10));
+
+        shared.end_session("a");
+        assert_eq!(policy.quota_usage("a")[0].used, 0);
+        assert_eq!(policy.quota_usage("b")[0].used, 1);
+    }
+
+    fn tool_input(tool_name: &str, tool_input: Value) -> Value {
+        json!({
+            "session_id": "s",
+            "transcript_path": "/tmp/t.jsonl",
+            "cwd": "/tmp",
+            "tool_name": tool_name,
+            "tool_input": tool_input,
+        })
+    }
+
+    fn pre_tool_use(tool_name: &str, input: Value) -> HookInput {
+        let mut input = tool_input(tool_name, input);
+        input["hook_event_name"] = json!("PreToolUse");
+        HookInput::PreToolUse(serde_json::from_value(input).unwrap())
+    }
+
+    fn post_tool_use(tool_name: &str, input: Value) -> HookInput {
+        let mut input = tool_input(tool_name, input);
+        input["hook_event_name"] = json!("PostToolUse");
+        input["tool_response"] = json!("ok");
+        HookInput::PostToolUse(serde_json::from_value(input).unwrap())
+    }
+
+    /// Run the `PreToolUse` hook, returning its denial reason if it denied
+    /// the call.
+    async fn denial(
+        hooks: &HashMap<HookEvent, Vec<HookMatcher>>,
+        input: HookInput,
+    ) -> Option<String> {
+        let hook = &hooks[&HookEvent::PreToolUse][0].hooks[0];
+        let HookOutput::Sync(output) = hook(input, None, Default::default()).await else {
+            panic!("expected a sync output");
+        };
+        match output.hook_specific_output {
+            Some(HookSpecificOutput::PreToolUse(specific)) => {
+                assert_eq!(specific.permission_decision, Some(PermissionBehavior::Deny));
+                specific.permission_decision_reason
+            }
+            None => None,
+            other => panic!("unexpected output: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_options_enforce_policy() {
+        let options = ClaudeAgentOptions::new()
+            .with_allowed_tools(vec!["WebSearch".to_string(), "Read".to_string()])
+            .with_no_network();
+        assert_eq!(options.allowed_tools, vec!["Read"]);
+        assert_eq!(options.disallowed_tools, vec!["WebFetch", "WebSearch"]);
+        assert!(options.validate().is_ok());
+
+        let hooks = options.permission_policies[0].hooks().into_hooks();
+        assert!(!hooks.contains_key(&HookEvent::PostToolUse));
+        assert!(denial(&hooks, pre_tool_use("Bash", bash("wget x")))
+            .await
+            .is_some());
+        assert!(denial(&hooks, pre_tool_use("Bash", bash("ls")))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hooks_count_calls_that_ran() {
+        let exceeded = Arc::new(Mutex::new(Vec::new()));
+        let policy = PermissionPolicy::new()
+            .max_tool_calls("WebFetch", 1)
+            .on_quota_exceeded({
+                let exceeded = Arc::clone(&exceeded);
+                move |e| exceeded.lock().unwrap().push(e.clone())
+            });
+        let hooks = policy.hooks().into_hooks();
+        let fetch = json!({"url": "https://example.com"});
+
+        // Allowed calls only count once they have run
+        assert_eq!(
+            denial(&hooks, pre_tool_use("WebFetch", fetch.clone())).await,
+            None
+        );
+        assert_eq!(
+            denial(&hooks, pre_tool_use("WebFetch", fetch.clone())).await,
+            None
+        );
+        let post = &hooks[&HookEvent::PostToolUse][0].hooks[0];
+        post(
+            post_tool_use("WebFetch", fetch.clone()),
+            None,
+            Default::default(),
+        )
+        .await;
+
+        let reason = denial(&hooks, pre_tool_use("WebFetch", fetch.clone()))
+            .await
+            .unwrap();
+        assert!(reason.starts_with("Quota exceeded for WebFetch calls"));
+        let exceeded = exceeded.lock().unwrap();
+        assert_eq!(exceeded.len(), 1);
+        assert_eq!(exceeded[0].session_id, "s");
+        assert_eq!((exceeded[0].used, exceeded[0].limit), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_hooks_limit_concurrent_calls() {
+        let policy = PermissionPolicy::new().max_concurrent("Bash", 1);
+        let hooks = Arc::new(policy.hooks().into_hooks());
+        assert_eq!(
+            hooks[&HookEvent::PreToolUse][0].timeout,
+            Some(SLOT_WAIT_TIMEOUT_SECS)
+        );
+        let run = |event: HookEvent, input: HookInput, id: &str| {
+            let hooks = Arc::clone(&hooks);
+            let id = Some(id.to_string());
+            tokio::spawn(async move {
+                let hook = &hooks[&event][0].hooks[0];
+                hook(input, id, Default::default()).await;
+            })
+        };
+        let pre =
+            |tool: &str, id: &str| run(HookEvent::PreToolUse, pre_tool_use(tool, bash("ls")), id);
+        let settle = || tokio::time::sleep(std::time::Duration::from_millis(20));
+
+        pre("Bash", "t1").await.unwrap();
+        // Other tools aren't limited
+        pre("Read", "t2").await.unwrap();
+
+        let second = pre("Bash", "t3");
+        settle().await;
+        assert!(!second.is_finished());
+        run(
+            HookEvent::PostToolUse,
+            post_tool_use("Bash", bash("ls")),
+            "t1",
+        )
+        .await
+        .unwrap();
+        second.await.unwrap();
+
+        // A call that never finishes holds its slot until the turn ends
+        let third = pre("Bash", "t4");
+        settle().await;
+        assert!(!third.is_finished());
+        let mut stop = tool_input("Bash", json!({}));
+        stop["hook_event_name"] = json!("Stop");
+        stop["stop_hook_active"] = json!(false);
+        let stop = HookInput::Stop(serde_json::from_value(stop).unwrap());
+        run(HookEvent::Stop, stop, "").await.unwrap();
+        third.await.unwrap();
+
+        policy.end_session("s");
+        assert!(policy.running_calls().is_empty());
+    }
+}
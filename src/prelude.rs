@@ -0,0 +1,25 @@
+//! Commonly used items, for glob import.
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::prelude::*;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), ClaudeSDKError> {
+//!     let mut stream = query("What is 2 + 2?", None).await?;
+//!     while let Some(message) = stream.next().await {
+//!         if let Message::Assistant(assistant) = message? {
+//!             println!("{}", assistant.text());
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+pub use crate::client::{ClaudeClient, ClaudeClientBuilder};
+pub use crate::errors::ClaudeSDKError;
+pub use crate::query::{query, query_result};
+pub use crate::types::{
+    ClaudeAgentOptions, ContentBlock, Message, PermissionMode, PermissionResult, ResultMessage,
+    UserMessageContent,
+};
+pub use tokio_stream::StreamExt;
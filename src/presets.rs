@@ -0,0 +1,258 @@
+//! Ready-made option bundles for common agent tasks.
+//!
+//! Each preset returns a plain [`ClaudeAgentOptions`] that can be customized
+//! further with the usual builder methods before use.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::presets::{self, ReviewReport};
+//! use claude_agents_sdk::query_result;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let options = presets::code_reviewer("./my-repo").with_max_turns(20);
+//!     let (_, result) = query_result("Review the changes in src/", Some(options)).await?;
+//!
+//!     let report = ReviewReport::from_result(&result)?;
+//!     for finding in &report.findings {
+//!         println!("[{:?}] {}: {}", finding.severity, finding.file, finding.title);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{
+    ClaudeAgentOptions, PermissionMode, ResultMessage, SystemPromptConfig, ToolsConfig,
+};
+
+/// Tools available to the code reviewer; none of them can modify the repository.
+pub const READ_ONLY_TOOLS: &[&str] = &["Read", "Grep", "Glob"];
+
+const CODE_REVIEWER_PROMPT: &str = "You are a meticulous senior code reviewer. \
+Inspect the repository with the read-only tools available to you and report \
+concrete problems: bugs, security issues, performance pitfalls, and \
+maintainability concerns. Cite the file and line for every finding, explain \
+why it matters, and suggest a fix where one is clear. Do not report style \
+preferences unless they hide a real defect. Never attempt to modify files.";
+
+/// Create options for a read-only code review of `repo_path`.
+///
+/// The preset:
+/// - runs in `repo_path` with only [`READ_ONLY_TOOLS`] available,
+/// - uses a review-focused system prompt,
+/// - requests structured output matching [`review_schema`], which
+///   [`ReviewReport::from_result`] parses.
+pub fn code_reviewer(repo_path: impl Into<PathBuf>) -> ClaudeAgentOptions {
+    let tools: Vec<String> = READ_ONLY_TOOLS.iter().map(|t| t.to_string()).collect();
+
+    let mut options = ClaudeAgentOptions::new()
+        .with_cwd(repo_path)
+        .with_permission_mode(PermissionMode::Default)
+        .with_allowed_tools(tools.clone());
+    options.tools = Some(ToolsConfig::List(tools));
+    options.system_prompt = Some(SystemPromptConfig::Text(CODE_REVIEWER_PROMPT.to_string()));
+    options.output_format = Some(review_schema());
+    options
+}
+
+/// Severity of a review finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewSeverity {
+    /// Must be fixed: bugs, security issues, data loss.
+    Critical,
+    /// Should be fixed before merging.
+    Major,
+    /// Worth fixing, but not blocking.
+    Minor,
+    /// Optional polish.
+    Nit,
+}
+
+/// A single issue reported by the code reviewer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewFinding {
+    /// File path relative to the repository root.
+    pub file: String,
+    /// Line number, if the finding refers to a specific line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// How serious the issue is.
+    pub severity: ReviewSeverity,
+    /// One-line summary.
+    pub title: String,
+    /// Explanation of the problem.
+    pub description: String,
+    /// Suggested fix, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+/// The structured result of a code review.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewReport {
+    /// Overall assessment.
+    pub summary: String,
+    /// Individual findings.
+    #[serde(default)]
+    pub findings: Vec<ReviewFinding>,
+}
+
+impl ReviewReport {
+    /// Parse a review report from a query's result message.
+    ///
+    /// Uses the structured output when present, and otherwise falls back to
+    /// parsing the result text as JSON (optionally inside a code fence).
+    pub fn from_result(result: &ResultMessage) -> Result<Self> {
+        parse_output(
+            result.structured_output.as_ref(),
+            result.result.as_deref(),
+            "review report",
+        )
+    }
+
+    /// Get findings at or above a severity, most severe first.
+    pub fn at_least(&self, severity: ReviewSeverity) -> Vec<&ReviewFinding> {
+        let mut findings: Vec<_> = self
+            .findings
+            .iter()
+            .filter(|f| f.severity <= severity)
+            .collect();
+        findings.sort_by_key(|f| f.severity);
+        findings
+    }
+}
+
+/// JSON Schema for [`ReviewReport`], used as the code reviewer's output format.
+pub fn review_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "summary": {"type": "string"},
+            "findings": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "file": {"type": "string"},
+                        "line": {"type": "integer", "minimum": 1},
+                        "severity": {
+                            "type": "string",
+                            "enum": ["critical", "major", "minor", "nit"]
+                        },
+                        "title": {"type": "string"},
+                        "description": {"type": "string"},
+                        "suggestion": {"type": "string"}
+                    },
+                    "required": ["file", "severity", "title", "description"]
+                }
+            }
+        },
+        "required": ["summary", "findings"]
+    })
+}
+
+/// Parse a query's output: the structured output when present, and
+/// otherwise `text` as JSON (optionally inside a code fence).
+///
+/// `what` names the output in error messages.
+pub(crate) fn parse_output<T: DeserializeOwned>(
+    structured_output: Option<&serde_json::Value>,
+    text: Option<&str>,
+    what: &str,
+) -> Result<T> {
+    if let Some(output) = structured_output {
+        return serde_json::from_value(output.clone()).map_err(|e| {
+            ClaudeSDKError::message_parse_with_raw(
+                format!("Invalid {}: {}", what, e),
+                output.clone(),
+            )
+        });
+    }
+
+    let text =
+        text.ok_or_else(|| ClaudeSDKError::message_parse(format!("Result contains no {}", what)))?;
+    serde_json::from_str(strip_code_fence(text))
+        .map_err(|e| ClaudeSDKError::message_parse(format!("Invalid {}: {}", what, e)))
+}
+
+/// Strip a Markdown code fence around `text`, if any.
+fn strip_code_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    match trimmed.strip_prefix("```") {
+        Some(rest) => {
+            let body = rest.split_once('\n').map_or("", |(_, body)| body);
+            body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+        }
+        None => trimmed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(structured_output: Option<serde_json::Value>, text: Option<&str>) -> ResultMessage {
+        ResultMessage {
+            subtype: "success".to_string(),
+            duration_ms: 0,
+            duration_api_ms: 0,
+            is_error: false,
+            num_turns: 1,
+            session_id: "s".to_string(),
+            total_cost_usd: None,
+            usage: None,
+            result: text.map(String::from),
+            structured_output,
+            extras: Default::default(),
+            meta: None,
+            raw: None,
+        }
+    }
+
+    #[test]
+    fn test_code_reviewer_is_read_only() {
+        let options = code_reviewer("/repo");
+        assert_eq!(options.cwd, Some(PathBuf::from("/repo")));
+        assert_eq!(options.allowed_tools, vec!["Read", "Grep", "Glob"]);
+        assert!(matches!(options.tools, Some(ToolsConfig::List(ref t)) if t.len() == 3));
+        assert_eq!(options.output_format, Some(review_schema()));
+    }
+
+    #[test]
+    fn test_report_from_structured_output() {
+        let output = serde_json::json!({
+            "summary": "Two issues",
+            "findings": [
+                {"file": "src/a.rs", "severity": "minor", "title": "Unused", "description": "x"},
+                {"file": "src/b.rs", "line": 12, "severity": "critical", "title": "Overflow",
+                 "description": "y", "suggestion": "Use checked_add"}
+            ]
+        });
+
+        let report = ReviewReport::from_result(&result(Some(output), None)).unwrap();
+        assert_eq!(report.findings.len(), 2);
+
+        let serious = report.at_least(ReviewSeverity::Major);
+        assert_eq!(serious.len(), 1);
+        assert_eq!(serious[0].line, Some(12));
+        assert_eq!(serious[0].suggestion.as_deref(), Some("Use checked_add"));
+    }
+
+    #[test]
+    fn test_report_from_fenced_text() {
+        let text = "```json\n{\"summary\": \"Clean\", \"findings\": []}\n```";
+        let report = ReviewReport::from_result(&result(None, Some(text))).unwrap();
+        assert_eq!(report.summary, "Clean");
+        assert!(report.findings.is_empty());
+
+        assert!(ReviewReport::from_result(&result(None, Some("Looks good!"))).is_err());
+    }
+}
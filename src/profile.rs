@@ -0,0 +1,377 @@
+//! Named agent configurations.
+//!
+//! An [`AgentProfile`] bundles what makes an agent "the refactoring agent"
+//! or "the release-notes writer": its options, a [`PermissionPolicy`],
+//! hooks and the schema of its output. Define it once, in code or in a
+//! JSON file, and run it anywhere with
+//! [`query_with_profile`](crate::query_with_profile), or turn it into
+//! options for a [`ClaudeClient`](crate::ClaudeClient) with
+//! [`AgentProfile::options`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::{AgentProfile, ClaudeAgentOptions, PermissionMode, PermissionPolicy};
+//! use serde_json::json;
+//!
+//! let refactorer = AgentProfile::new("refactorer")
+//!     .with_description("Restructures code without changing behavior")
+//!     .with_options(
+//!         ClaudeAgentOptions::new()
+//!             .with_model("sonnet")
+//!             .with_permission_mode(PermissionMode::AcceptEdits),
+//!     )
+//!     .with_policy(PermissionPolicy::no_network())
+//!     .with_output_schema(json!({
+//!         "type": "object",
+//!         "properties": {"changed_files": {"type": "array", "items": {"type": "string"}}}
+//!     }));
+//!
+//! let options = refactorer.options();
+//! assert!(options.disallowed_tools.contains(&"WebFetch".to_string()));
+//! assert!(options.output_format.is_some());
+//! ```
+//!
+//! The same profile as a file, read with [`AgentProfile::load`]. Hooks are
+//! code, so a profile loaded from a file can have them added with
+//! [`with_hooks`](AgentProfile::with_hooks):
+//!
+//! ```json
+//! {
+//!   "name": "refactorer",
+//!   "description": "Restructures code without changing behavior",
+//!   "model": "sonnet",
+//!   "permission_mode": "acceptEdits",
+//!   "policy": {"deny_tools": ["WebFetch", "WebSearch"], "max_tool_calls": {"Bash": 50}},
+//!   "mcp_servers": {
+//!     "tracker": {
+//!       "type": "http",
+//!       "url": "https://tracker.example.com/mcp",
+//!       "headers": {"Authorization": "Bearer ${TRACKER_TOKEN}"}
+//!     }
+//!   },
+//!   "output_schema": {"type": "object"}
+//! }
+//! ```
+//!
+//! A profile file shouldn't hold secrets, so `${VAR}` references in its
+//! system prompt, settings and MCP servers are expanded when the agent
+//! connects (see [`env_vars`](crate::env_vars)).
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::hooks::HookRegistry;
+use crate::policy::PermissionPolicy;
+use crate::types::{
+    ClaudeAgentOptions, McpServerConfig, McpServersConfig, PermissionMode, SystemPromptConfig,
+};
+
+/// An agent defined once and run under its name.
+#[derive(Debug, Clone)]
+pub struct AgentProfile {
+    name: String,
+    description: Option<String>,
+    options: ClaudeAgentOptions,
+    policy: Option<PermissionPolicy>,
+    hooks: Option<HookRegistry>,
+    output_schema: Option<Value>,
+}
+
+impl AgentProfile {
+    /// Create a profile running with default options.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            options: ClaudeAgentOptions::new(),
+            policy: None,
+            hooks: None,
+            output_schema: None,
+        }
+    }
+
+    /// Describe what the agent is for.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the options the agent runs with.
+    pub fn with_options(mut self, options: ClaudeAgentOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Enforce `policy` (see [`ClaudeAgentOptions::with_permission_policy`]).
+    ///
+    /// Quotas are counted per session, so each session started from the
+    /// profile gets all of them.
+    pub fn with_policy(mut self, policy: PermissionPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Add `hooks` to those already in the options.
+    pub fn with_hooks(mut self, hooks: HookRegistry) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Request structured output matching the JSON Schema `schema`.
+    pub fn with_output_schema(mut self, schema: Value) -> Self {
+        self.output_schema = Some(schema);
+        self
+    }
+
+    /// The profile's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// What the agent is for, if described.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The schema of the agent's structured output, if any.
+    pub fn output_schema(&self) -> Option<&Value> {
+        self.output_schema.as_ref()
+    }
+
+    /// Options running the agent: the profile's options with its hooks,
+    /// policy and output schema applied.
+    pub fn options(&self) -> ClaudeAgentOptions {
+        let mut options = self.options.clone();
+        if let Some(ref hooks) = self.hooks {
+            options.add_hooks(hooks.clone());
+        }
+        if let Some(ref policy) = self.policy {
+            options = options.with_permission_policy(policy.clone());
+        }
+        if let Some(ref schema) = self.output_schema {
+            options.output_format = Some(schema.clone());
+        }
+        options
+    }
+
+    /// Parse a profile from JSON text (see the [module docs](self) for the
+    /// format).
+    pub fn from_json(text: &str) -> Result<Self> {
+        let file: ProfileFile = serde_json::from_str(text)
+            .map_err(|e| ClaudeSDKError::configuration(format!("Invalid agent profile: {}", e)))?;
+        file.into_profile()
+    }
+
+    /// Read a profile from a JSON file.
+    ///
+    /// A relative `cwd` in the file is taken relative to the file's
+    /// directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let mut profile = Self::from_json(&text)
+            .map_err(|e| ClaudeSDKError::configuration(format!("{}: {}", path.display(), e)))?;
+        if let (Some(cwd), Some(dir)) = (profile.options.cwd.as_mut(), path.parent()) {
+            if cwd.is_relative() {
+                *cwd = dir.join(&*cwd);
+            }
+        }
+        Ok(profile)
+    }
+}
+
+/// A profile as written in a file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    system_prompt: Option<String>,
+    #[serde(default)]
+    permission_mode: Option<PermissionMode>,
+    #[serde(default)]
+    allowed_tools: Vec<String>,
+    #[serde(default)]
+    disallowed_tools: Vec<String>,
+    #[serde(default)]
+    max_turns: Option<u32>,
+    #[serde(default)]
+    max_budget_usd: Option<f64>,
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+    /// Settings, as JSON or as the path of a settings file.
+    #[serde(default)]
+    settings: Option<Value>,
+    #[serde(default)]
+    mcp_servers: HashMap<String, McpServerConfig>,
+    #[serde(default)]
+    policy: Option<PolicyFile>,
+    #[serde(default)]
+    output_schema: Option<Value>,
+}
+
+/// A [`PermissionPolicy`] as written in a profile file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    deny_tools: Vec<String>,
+    #[serde(default)]
+    deny_commands: Vec<String>,
+    #[serde(default)]
+    max_tool_calls: BTreeMap<String, u64>,
+    #[serde(default)]
+    max_bytes_written: Option<u64>,
+}
+
+impl ProfileFile {
+    fn into_profile(self) -> Result<AgentProfile> {
+        if self.name.trim().is_empty() {
+            return Err(ClaudeSDKError::configuration(
+                "Agent profile name cannot be empty",
+            ));
+        }
+        let mut options = ClaudeAgentOptions::new();
+        options.model = self.model;
+        options.system_prompt = self.system_prompt.map(SystemPromptConfig::Text);
+        options.permission_mode = self.permission_mode;
+        options.allowed_tools = self.allowed_tools;
+        options.disallowed_tools = self.disallowed_tools;
+        options.max_turns = self.max_turns;
+        options.max_budget_usd = self.max_budget_usd;
+        options.cwd = self.cwd;
+        options.settings = self.settings.map(|settings| match settings {
+            Value::String(settings) => settings,
+            settings => settings.to_string(),
+        });
+        options.mcp_servers = McpServersConfig::Map(self.mcp_servers);
+        options.expand_env = true;
+
+        Ok(AgentProfile {
+            name: self.name,
+            description: self.description,
+            options,
+            policy: self.policy.map(PolicyFile::into_policy),
+            hooks: None,
+            output_schema: self.output_schema,
+        })
+    }
+}
+
+impl PolicyFile {
+    fn into_policy(self) -> PermissionPolicy {
+        let mut policy = PermissionPolicy::new();
+        for tool in self.deny_tools {
+            policy = policy.deny_tool(tool);
+        }
+        for command in self.deny_commands {
+            policy = policy.deny_command(command);
+        }
+        for (tool, limit) in self.max_tool_calls {
+            policy = policy.max_tool_calls(tool, limit);
+        }
+        if let Some(limit) = self.max_bytes_written {
+            policy = policy.max_bytes_written(limit);
+        }
+        policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::PreToolUse;
+    use crate::types::{HookEvent, HookOutput};
+    use serde_json::json;
+
+    #[test]
+    fn test_options_apply_hooks_policy_and_schema() {
+        let hooks = HookRegistry::new().on(PreToolUse, |_input| async { HookOutput::default() });
+        let profile = AgentProfile::new("refactorer")
+            .with_options(
+                ClaudeAgentOptions::new()
+                    .with_model("sonnet")
+                    .with_allowed_tools(vec!["Read".to_string(), "Bash".to_string()]),
+            )
+            .with_hooks(hooks)
+            .with_policy(PermissionPolicy::new().deny_tool("Bash"))
+            .with_output_schema(json!({"type": "object"}));
+
+        // Built afresh every time, so profiles can be reused
+        for _ in 0..2 {
+            let options = profile.options();
+            assert_eq!(options.model.as_deref(), Some("sonnet"));
+            assert_eq!(options.allowed_tools, vec!["Read"]);
+            assert_eq!(options.disallowed_tools, vec!["Bash"]);
+            assert_eq!(options.hooks.unwrap()[&HookEvent::PreToolUse].len(), 1);
+            assert_eq!(options.permission_policies.len(), 1);
+            assert_eq!(options.output_format, Some(json!({"type": "object"})));
+        }
+    }
+
+    #[test]
+    fn test_from_json() {
+        let profile = AgentProfile::from_json(
+            r#"{
+                "name": "release-notes",
+                "description": "Writes release notes",
+                "system_prompt": "Summarize the changes since the last tag.",
+                "permission_mode": "plan",
+                "max_turns": 5,
+                "policy": {"deny_commands": ["curl"], "max_tool_calls": {"Bash": 3}},
+                "settings": {"env": {"REGION": "${REGION}"}},
+                "mcp_servers": {"docs": {"type": "sse", "url": "https://${DOCS_HOST}/sse"}},
+                "output_schema": {"type": "object"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(profile.name(), "release-notes");
+        assert_eq!(profile.description(), Some("Writes release notes"));
+
+        let options = profile.options();
+        assert_eq!(options.permission_mode, Some(PermissionMode::Plan));
+        assert_eq!(options.max_turns, Some(5));
+        assert!(matches!(
+            options.system_prompt,
+            Some(SystemPromptConfig::Text(_))
+        ));
+        assert_eq!(options.permission_policies[0].quotas().len(), 1);
+        // References are left for the connect to expand
+        assert!(options.expand_env);
+        assert_eq!(
+            options.settings.as_deref(),
+            Some(r#"{"env":{"REGION":"${REGION}"}}"#)
+        );
+        assert!(matches!(
+            options.mcp_servers,
+            McpServersConfig::Map(ref servers) if servers.contains_key("docs")
+        ));
+        assert_eq!(profile.output_schema(), Some(&json!({"type": "object"})));
+
+        assert!(AgentProfile::from_json(r#"{"name": " "}"#).is_err());
+        assert!(AgentProfile::from_json(r#"{"name": "a", "modle": "sonnet"}"#).is_err());
+    }
+
+    #[test]
+    fn test_load_resolves_cwd_next_to_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("refactorer.json");
+        std::fs::write(&path, r#"{"name": "refactorer", "cwd": "repo"}"#).unwrap();
+
+        let profile = AgentProfile::load(&path).unwrap();
+        assert_eq!(profile.options().cwd, Some(dir.path().join("repo")));
+
+        std::fs::write(&path, "{}").unwrap();
+        let err = AgentProfile::load(&path).unwrap_err().to_string();
+        assert!(err.contains("refactorer.json"), "{}", err);
+    }
+}
@@ -0,0 +1,247 @@
+//! Coarse progress of a session, for spinners and status lines.
+//!
+//! [`ClaudeClient::progress`](crate::ClaudeClient::progress) hands out a
+//! [`watch`](tokio::sync::watch) receiver holding the current [`Progress`]
+//! of the client: connecting, waiting for the model, thinking, running a
+//! tool, finalizing, or idle. The phase is derived from the messages the
+//! CLI sends, so a UI can show what the agent is doing without
+//! interpreting them. Receivers only see the latest phase: one that reads
+//! slowly skips the phases in between instead of falling behind.
+//!
+//! [`Progress::from_message`] does the same for the message stream of a
+//! one-shot [`query`](crate::query).
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::{ClaudeClient, Progress};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut client = ClaudeClient::new(None);
+//! let mut progress = client.progress();
+//! tokio::spawn(async move {
+//!     while progress.changed().await.is_ok() {
+//!         match &*progress.borrow() {
+//!             Progress::RunningTool { name } => eprintln!("running {}...", name),
+//!             Progress::Idle => eprintln!("done"),
+//!             phase => eprintln!("{}...", phase),
+//!         }
+//!     }
+//! });
+//! client.connect().await?;
+//! client.query("Run the tests").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::types::{ContentBlock, ContextUsage, Message, UserMessageContent};
+
+/// What a session is busy with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Progress {
+    /// Nothing is running: not connected yet, or between turns.
+    #[default]
+    Idle,
+    /// Starting the CLI and initializing the session.
+    Connecting,
+    /// A prompt or tool result was sent and the model is working on a
+    /// reply, or writing one.
+    WaitingForModel,
+    /// The model is thinking.
+    Thinking,
+    /// A tool the model called is running.
+    RunningTool {
+        /// Name of the tool.
+        name: String,
+    },
+    /// The model finished its reply and the CLI is wrapping up the turn.
+    ///
+    /// Only reported with
+    /// [`include_partial_messages`](crate::ClaudeAgentOptions::include_partial_messages),
+    /// whose stream events tell when the model stops.
+    Finalizing,
+    /// The conversation is past the threshold of a
+    /// [`ContextPolicy`](crate::ContextPolicy).
+    ///
+    /// Reported mid-turn in place of the phase of the message that crossed
+    /// the threshold, then in place of [`Idle`](Self::Idle) at the end of
+    /// each turn until the context is compacted.
+    ContextNearlyFull(ContextUsage),
+}
+
+impl Progress {
+    /// The phase `message` shows the session to be in, if it shows one.
+    ///
+    /// Messages of subagents (with a `parent_tool_use_id`) report their own
+    /// tools and thinking, but never finalize the turn.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        match message {
+            Message::System(system) if system.subtype == "init" => Some(Self::WaitingForModel),
+            Message::User(user) => match user.content {
+                UserMessageContent::Blocks(ref blocks)
+                    if blocks
+                        .iter()
+                        .any(|block| matches!(block, ContentBlock::ToolResult(_))) =>
+                {
+                    Some(Self::WaitingForModel)
+                }
+                _ => None,
+            },
+            // A message's last block is what the model moved on to
+            Message::Assistant(assistant) => match assistant.content.last()? {
+                ContentBlock::Thinking(_) => Some(Self::Thinking),
+                ContentBlock::ToolUse(tool_use) => Some(Self::RunningTool {
+                    name: tool_use.name.clone(),
+                }),
+                ContentBlock::Text(_) | ContentBlock::ToolResult(_) => Some(Self::WaitingForModel),
+            },
+            Message::StreamEvent(stream) => {
+                let event = &stream.event;
+                match event.get("type").and_then(Value::as_str)? {
+                    "content_block_start" => {
+                        match event["content_block"].get("type").and_then(Value::as_str)? {
+                            "thinking" | "redacted_thinking" => Some(Self::Thinking),
+                            "text" => Some(Self::WaitingForModel),
+                            _ => None,
+                        }
+                    }
+                    "message_delta" if stream.parent_tool_use_id.is_none() => {
+                        match event["delta"].get("stop_reason").and_then(Value::as_str)? {
+                            "end_turn" | "stop_sequence" | "max_tokens" | "refusal" => {
+                                Some(Self::Finalizing)
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            Message::Result(_) => Some(Self::Idle),
+            Message::System(_) => None,
+        }
+    }
+
+    /// Whether the session is busy.
+    pub fn is_busy(&self) -> bool {
+        !matches!(self, Self::Idle | Self::ContextNearlyFull(_))
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Idle => f.write_str("Idle"),
+            Self::Connecting => f.write_str("Connecting"),
+            Self::WaitingForModel => f.write_str("Waiting for the model"),
+            Self::Thinking => f.write_str("Thinking"),
+            Self::RunningTool { name } => write!(f, "Running {}", name),
+            Self::Finalizing => f.write_str("Finalizing"),
+            Self::ContextNearlyFull(usage) => {
+                write!(f, "Context {:.0}% full", usage.fraction() * 100.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::parser::parse_message;
+    use serde_json::json;
+
+    fn progress(frame: Value) -> Option<Progress> {
+        Progress::from_message(&parse_message(frame).unwrap())
+    }
+
+    fn stream_event(event: Value, parent: Option<&str>) -> Value {
+        json!({"type": "stream_event", "uuid": "u", "session_id": "s", "event": event, "parent_tool_use_id": parent})
+    }
+
+    #[test]
+    fn test_progress_of_a_turn() {
+        let assistant = |block: Value| json!({"type": "assistant", "message": {"content": [block], "model": "m"}});
+        let turn = [
+            (
+                json!({"type": "system", "subtype": "init", "session_id": "s"}),
+                Some(Progress::WaitingForModel),
+            ),
+            (
+                stream_event(
+                    json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking"}}),
+                    None,
+                ),
+                Some(Progress::Thinking),
+            ),
+            (
+                assistant(json!({"type": "thinking", "thinking": "hm", "signature": "x"})),
+                Some(Progress::Thinking),
+            ),
+            (
+                assistant(json!({"type": "tool_use", "id": "t1", "name": "Bash", "input": {}})),
+                Some(Progress::RunningTool {
+                    name: "Bash".to_string(),
+                }),
+            ),
+            (
+                json!({"type": "user", "message": {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "ok"}]}}),
+                Some(Progress::WaitingForModel),
+            ),
+            (
+                stream_event(
+                    json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Do"}}),
+                    None,
+                ),
+                None,
+            ),
+            (
+                stream_event(
+                    json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}}),
+                    None,
+                ),
+                Some(Progress::Finalizing),
+            ),
+            (
+                json!({"type": "result", "subtype": "success", "duration_ms": 1, "duration_api_ms": 1, "is_error": false, "num_turns": 1, "session_id": "s"}),
+                Some(Progress::Idle),
+            ),
+        ];
+        for (frame, expected) in turn {
+            assert_eq!(progress(frame.clone()), expected, "{}", frame);
+        }
+    }
+
+    #[test]
+    fn test_subagents_and_tool_stops_dont_finalize() {
+        let stop = |reason: &str, parent| {
+            stream_event(
+                json!({"type": "message_delta", "delta": {"stop_reason": reason}}),
+                parent,
+            )
+        };
+        assert_eq!(progress(stop("end_turn", Some("task_1"))), None);
+        assert_eq!(progress(stop("tool_use", None)), None);
+        assert_eq!(
+            progress(json!({"type": "user", "message": {"role": "user", "content": "Hi"}})),
+            None
+        );
+        assert_eq!(
+            Progress::RunningTool {
+                name: "Read".to_string()
+            }
+            .to_string(),
+            "Running Read"
+        );
+        assert!(!Progress::default().is_busy());
+        let nearly_full = Progress::ContextNearlyFull(ContextUsage {
+            used_tokens: 170_000,
+            context_window: 200_000,
+        });
+        assert!(!nearly_full.is_busy());
+        assert_eq!(nearly_full.to_string(), "Context 85% full");
+    }
+}
@@ -0,0 +1,478 @@
+//! Wire protocol between the SDK and the Claude CLI.
+//!
+//! The CLI speaks newline-delimited JSON ("stream-json") over stdio. This
+//! module holds the frame types the SDK writes and the control protocol
+//! types it reads, re-exports the message types parsed from the CLI's
+//! output, and documents every frame as JSON Schema via [`schema`].
+//!
+//! Frames read from the CLI:
+//! - `user`, `assistant`, `system`, `result`, `stream_event` — conversation
+//!   messages, parsed into [`Message`] by [`parser`]
+//! - `control_request` — permission prompts, hook callbacks and MCP messages
+//!   ([`ControlRequest`])
+//! - `control_response` — replies to the SDK's control requests
+//!   ([`ControlResponse`])
+//!
+//! Frames written to the CLI:
+//! - `user` — prompts ([`UserInputMessage`])
+//! - `control_request` — interrupt, initialize, mode/model changes, ...
+//! - `control_response` — replies to the CLI's control requests
+//!
+//! Custom transports can carry frames in other encodings; see [`codec`]. The
+//! control protocol state itself lives in [`engine`], which does no I/O.
+//! Neither depends on process management, so this module builds without
+//! the `subprocess` feature.
+
+pub mod codec;
+pub mod engine;
+pub mod parser;
+pub mod wire;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ClaudeSDKError, Result};
+
+pub use crate::types::{
+    AssistantMessage, ContentBlock, Message, ResultMessage, StreamEvent, SystemMessage, TextBlock,
+    ThinkingBlock, ToolResultBlock, ToolResultContent, ToolUseBlock, UserMessage,
+    UserMessageContent,
+};
+
+/// A prompt sent to the CLI in streaming mode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInputMessage {
+    /// Always "user".
+    #[serde(rename = "type")]
+    pub message_type: String,
+    /// The message body.
+    pub message: UserInputBody,
+    /// Tool use this message answers, if any.
+    pub parent_tool_use_id: Option<String>,
+    /// Session to post the message to.
+    pub session_id: String,
+}
+
+/// Body of a [`UserInputMessage`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInputBody {
+    /// Always "user".
+    pub role: String,
+    /// Prompt text or content blocks.
+    pub content: serde_json::Value,
+}
+
+impl UserInputMessage {
+    /// Create a text prompt for the default session.
+    pub fn text(content: impl Into<String>) -> Self {
+        Self {
+            message_type: wire::USER.to_string(),
+            message: UserInputBody {
+                role: wire::USER_ROLE.to_string(),
+                content: serde_json::Value::String(content.into()),
+            },
+            parent_tool_use_id: None,
+            session_id: wire::DEFAULT_SESSION.to_string(),
+        }
+    }
+}
+
+/// Control request subtypes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "subtype")]
+pub enum ControlRequestPayload {
+    /// Interrupt request.
+    #[serde(rename = "interrupt")]
+    Interrupt,
+    /// Tool permission request.
+    #[serde(rename = "can_use_tool")]
+    CanUseTool {
+        /// Tool name.
+        tool_name: String,
+        /// Tool input.
+        input: serde_json::Value,
+        /// Permission suggestions.
+        permission_suggestions: Option<Vec<serde_json::Value>>,
+        /// Blocked path.
+        blocked_path: Option<String>,
+        /// ID of the tool use awaiting permission, if reported.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tool_use_id: Option<String>,
+    },
+    /// Initialize request.
+    #[serde(rename = "initialize")]
+    Initialize {
+        /// Hook configurations.
+        hooks: Option<serde_json::Value>,
+    },
+    /// Set permission mode request.
+    #[serde(rename = "set_permission_mode")]
+    SetPermissionMode {
+        /// New mode.
+        mode: String,
+    },
+    /// Set model request.
+    #[serde(rename = "set_model")]
+    SetModel {
+        /// New model.
+        model: String,
+    },
+    /// Set max thinking tokens request.
+    #[serde(rename = "set_max_thinking_tokens")]
+    SetMaxThinkingTokens {
+        /// New thinking budget; `None` restores the CLI default.
+        max_thinking_tokens: Option<u32>,
+    },
+    /// Hook callback request.
+    #[serde(rename = "hook_callback")]
+    HookCallback {
+        /// Callback ID.
+        callback_id: String,
+        /// Hook input.
+        input: serde_json::Value,
+        /// Tool use ID.
+        tool_use_id: Option<String>,
+    },
+    /// MCP message request.
+    #[serde(rename = "mcp_message")]
+    McpMessage {
+        /// Server name.
+        server_name: String,
+        /// JSONRPC message.
+        message: serde_json::Value,
+    },
+    /// MCP status request.
+    #[serde(rename = "mcp_status")]
+    McpStatus,
+    /// Enable or disable an MCP server.
+    #[serde(rename = "mcp_toggle")]
+    McpToggle {
+        /// Server name.
+        #[serde(rename = "serverName")]
+        server_name: String,
+        /// Whether the server should run.
+        enabled: bool,
+    },
+    /// Rewind files request.
+    #[serde(rename = "rewind_files")]
+    RewindFiles {
+        /// User message ID to rewind to.
+        user_message_id: String,
+    },
+}
+
+/// Control request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlRequest {
+    /// Always "control_request".
+    #[serde(rename = "type")]
+    pub request_type: String,
+    /// Request ID.
+    pub request_id: String,
+    /// Request payload.
+    pub request: ControlRequestPayload,
+}
+
+impl ControlRequest {
+    /// Create a control request frame.
+    pub fn new(request_id: impl Into<String>, request: ControlRequestPayload) -> Self {
+        Self {
+            request_type: wire::CONTROL_REQUEST.to_string(),
+            request_id: request_id.into(),
+            request,
+        }
+    }
+}
+
+/// Success response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlSuccessResponse {
+    /// Always "success".
+    pub subtype: String,
+    /// Request ID.
+    pub request_id: String,
+    /// Response data.
+    pub response: Option<serde_json::Value>,
+}
+
+/// Error response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlErrorResponse {
+    /// Always "error".
+    pub subtype: String,
+    /// Request ID.
+    pub request_id: String,
+    /// Error message.
+    pub error: String,
+}
+
+/// Control response payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "subtype")]
+pub enum ControlResponsePayload {
+    /// Success response.
+    #[serde(rename = "success")]
+    Success {
+        /// Request ID.
+        request_id: String,
+        /// Response data.
+        response: Option<serde_json::Value>,
+    },
+    /// Error response.
+    #[serde(rename = "error")]
+    Error {
+        /// Request ID.
+        request_id: String,
+        /// Error message.
+        error: String,
+    },
+}
+
+/// Control response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlResponse {
+    /// Always "control_response".
+    #[serde(rename = "type")]
+    pub response_type: String,
+    /// Response payload.
+    pub response: ControlResponsePayload,
+}
+
+impl ControlResponse {
+    /// Create a success response frame.
+    pub fn success(request_id: impl Into<String>, response: serde_json::Value) -> Self {
+        Self {
+            response_type: wire::CONTROL_RESPONSE.to_string(),
+            response: ControlResponsePayload::Success {
+                request_id: request_id.into(),
+                response: Some(response),
+            },
+        }
+    }
+
+    /// Create an error response frame.
+    pub fn error_response(request_id: impl Into<String>, error: impl Into<String>) -> Self {
+        Self {
+            response_type: wire::CONTROL_RESPONSE.to_string(),
+            response: ControlResponsePayload::Error {
+                request_id: request_id.into(),
+                error: error.into(),
+            },
+        }
+    }
+
+    /// Create a success or error response frame from a handler's result.
+    pub fn from_result(request_id: impl Into<String>, result: Result<serde_json::Value>) -> Self {
+        match result {
+            Ok(data) => Self::success(request_id, data),
+            Err(e) => Self::error_response(request_id, e.to_string()),
+        }
+    }
+
+    /// Get the request ID.
+    pub fn request_id(&self) -> &str {
+        match &self.response {
+            ControlResponsePayload::Success { request_id, .. } => request_id,
+            ControlResponsePayload::Error { request_id, .. } => request_id,
+        }
+    }
+
+    /// Check if this is a success response.
+    pub fn is_success(&self) -> bool {
+        matches!(&self.response, ControlResponsePayload::Success { .. })
+    }
+
+    /// Get the response data if successful.
+    pub fn data(&self) -> Option<&serde_json::Value> {
+        match &self.response {
+            ControlResponsePayload::Success { response, .. } => response.as_ref(),
+            ControlResponsePayload::Error { .. } => None,
+        }
+    }
+
+    /// Get the error message if failed.
+    pub fn error(&self) -> Option<&str> {
+        match &self.response {
+            ControlResponsePayload::Success { .. } => None,
+            ControlResponsePayload::Error { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Serialize a frame as a single protocol line.
+pub fn encode_frame(frame: &impl Serialize) -> Result<String> {
+    serde_json::to_string(frame)
+        .map_err(|e| ClaudeSDKError::internal(format!("Failed to encode protocol frame: {}", e)))
+}
+
+/// JSON Schema (draft 2020-12) of every frame the SDK reads or writes.
+///
+/// The schema records the SDK's assumptions: `required` lists the fields the
+/// SDK can't do without, and `properties` the fields it reads or writes.
+/// Frames may carry additional fields. Frames read from the CLI are under
+/// `$defs/incoming`, frames written to it under `$defs/outgoing`.
+pub fn schema() -> serde_json::Value {
+    use serde_json::json;
+
+    let string = json!({"type": "string"});
+    let nullable_string = json!({"type": ["string", "null"]});
+    let frame = |frame_type: &str, required: &[&str], properties: serde_json::Value| {
+        let mut properties = properties;
+        properties["type"] = json!({"const": frame_type});
+        let mut required: Vec<&str> = required.to_vec();
+        required.insert(0, "type");
+        json!({"type": "object", "required": required, "properties": properties})
+    };
+    let subtype = |name: &str, required: &[&str], properties: serde_json::Value| {
+        let mut properties = properties;
+        properties["subtype"] = json!({"const": name});
+        let mut required: Vec<&str> = required.to_vec();
+        required.insert(0, "subtype");
+        json!({"type": "object", "required": required, "properties": properties})
+    };
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Claude CLI stream-json protocol",
+        "anyOf": [{"$ref": "#/$defs/incoming"}, {"$ref": "#/$defs/outgoing"}],
+        "$defs": {
+            "incoming": {"oneOf": [
+                {"$ref": "#/$defs/user_message"},
+                {"$ref": "#/$defs/assistant_message"},
+                {"$ref": "#/$defs/system_message"},
+                {"$ref": "#/$defs/result_message"},
+                {"$ref": "#/$defs/stream_event"},
+                {"$ref": "#/$defs/incoming_control_request"},
+                {"$ref": "#/$defs/control_response"}
+            ]},
+            "outgoing": {"oneOf": [
+                {"$ref": "#/$defs/user_input"},
+                {"$ref": "#/$defs/outgoing_control_request"},
+                {"$ref": "#/$defs/control_response"}
+            ]},
+            "content_block": {"oneOf": [
+                {"type": "object", "required": ["type", "text"], "properties": {
+                    "type": {"const": "text"}, "text": string
+                }},
+                {"type": "object", "required": ["type", "thinking"], "properties": {
+                    "type": {"const": "thinking"}, "thinking": string, "signature": string
+                }},
+                {"type": "object", "required": ["type", "id", "name", "input"], "properties": {
+                    "type": {"const": "tool_use"}, "id": string, "name": string,
+                    "input": {"type": "object"}
+                }},
+                {"type": "object", "required": ["type", "tool_use_id"], "properties": {
+                    "type": {"const": "tool_result"}, "tool_use_id": string,
+                    "content": {"type": ["string", "array", "null"]},
+                    "is_error": {"type": ["boolean", "null"]}
+                }}
+            ]},
+            "user_message": frame(wire::USER, &["message"], json!({
+                "message": {"type": "object", "required": ["content"], "properties": {
+                    "content": {"oneOf": [
+                        string,
+                        {"type": "array", "items": {"$ref": "#/$defs/content_block"}}
+                    ]}
+                }},
+                "uuid": string,
+                "parent_tool_use_id": nullable_string
+            })),
+            "assistant_message": frame("assistant", &["message"], json!({
+                "message": {"type": "object", "required": ["content"], "properties": {
+                    "content": {"type": "array", "items": {"$ref": "#/$defs/content_block"}},
+                    "model": string
+                }},
+                "parent_tool_use_id": nullable_string,
+                "error": {"enum": [
+                    "authentication_failed", "billing_error", "rate_limit",
+                    "invalid_request", "server_error", "unknown"
+                ]}
+            })),
+            "system_message": frame("system", &["subtype"], json!({
+                "subtype": string,
+                "data": {}
+            })),
+            "result_message": frame("result", &["subtype", "session_id"], json!({
+                "subtype": string,
+                "duration_ms": {"type": "integer", "minimum": 0},
+                "duration_api_ms": {"type": "integer", "minimum": 0},
+                "is_error": {"type": "boolean"},
+                "num_turns": {"type": "integer", "minimum": 0},
+                "session_id": string,
+                "total_cost_usd": {"type": ["number", "null"]},
+                "usage": {"type": ["object", "null"]},
+                "result": nullable_string,
+                "structured_output": {}
+            })),
+            "stream_event": frame("stream_event", &["uuid", "session_id", "event"], json!({
+                "uuid": string,
+                "session_id": string,
+                "event": {"type": "object"},
+                "parent_tool_use_id": nullable_string
+            })),
+            "incoming_control_request": frame(wire::CONTROL_REQUEST, &["request_id", "request"], json!({
+                "request_id": string,
+                "request": {"oneOf": [
+                    subtype("can_use_tool", &["tool_name", "input"], json!({
+                        "tool_name": string,
+                        "input": {"type": "object"},
+                        "permission_suggestions": {"type": ["array", "null"]},
+                        "blocked_path": nullable_string,
+                        "tool_use_id": nullable_string
+                    })),
+                    subtype("hook_callback", &["callback_id", "input"], json!({
+                        "callback_id": string,
+                        "input": {"type": "object"},
+                        "tool_use_id": nullable_string
+                    })),
+                    subtype("mcp_message", &["server_name", "message"], json!({
+                        "server_name": string,
+                        "message": {"type": "object"}
+                    })),
+                    subtype("initialize", &[], json!({"hooks": {}}))
+                ]}
+            })),
+            "outgoing_control_request": frame(wire::CONTROL_REQUEST, &["request_id", "request"], json!({
+                "request_id": string,
+                "request": {"oneOf": [
+                    subtype("interrupt", &[], json!({})),
+                    subtype("initialize", &[], json!({"hooks": {"type": ["object", "null"]}})),
+                    subtype("set_permission_mode", &["mode"], json!({
+                        "mode": {"enum": ["default", "acceptEdits", "plan", "bypassPermissions"]}
+                    })),
+                    subtype("set_model", &["model"], json!({"model": string})),
+                    subtype("set_max_thinking_tokens", &["max_thinking_tokens"], json!({
+                        "max_thinking_tokens": {"type": ["integer", "null"]}
+                    })),
+                    subtype("mcp_status", &[], json!({})),
+                    subtype("mcp_toggle", &["serverName", "enabled"], json!({
+                        "serverName": string,
+                        "enabled": {"type": "boolean"}
+                    })),
+                    subtype("rewind_files", &["user_message_id"], json!({
+                        "user_message_id": string
+                    }))
+                ]}
+            })),
+            "control_response": frame(wire::CONTROL_RESPONSE, &["response"], json!({
+                "response": {"oneOf": [
+                    subtype(wire::SUCCESS, &["request_id"], json!({
+                        "request_id": string,
+                        "response": {}
+                    })),
+                    subtype(wire::ERROR, &["request_id", "error"], json!({
+                        "request_id": string,
+                        "error": string
+                    }))
+                ]}
+            })),
+            "user_input": frame(wire::USER, &["message", "session_id"], json!({
+                "message": {"type": "object", "required": ["role", "content"], "properties": {
+                    "role": {"const": "user"},
+                    "content": {"oneOf": [string, {"type": "array"}]}
+                }},
+                "parent_tool_use_id": nullable_string,
+                "session_id": string
+            }))
+        }
+    })
+}
@@ -0,0 +1,159 @@
+//! Frame encodings for custom transports.
+//!
+//! The SDK builds every frame as JSON and the CLI's stdio transport always
+//! speaks newline-delimited JSON. A remote transport (for example a
+//! WebSocket bridge to a CLI running elsewhere) can instead negotiate a
+//! binary encoding with its peer and convert frames at its boundary with a
+//! [`FrameCodec`], leaving the rest of the SDK unchanged.
+//!
+//! [`JsonCodec`] is always available. [`MsgPackCodec`] and [`CborCodec`]
+//! require the `msgpack` and `cbor` features.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::protocol::codec::{self, FrameCodec};
+//!
+//! // Pick the first encoding both sides support
+//! let codec = codec::negotiate(&["zstd+msgpack", "json"]);
+//! assert_eq!(codec.name(), "json");
+//!
+//! let frame = codec.encode_line(r#"{"type":"control_request","request_id":"req_1"}"#)?;
+//! let decoded = codec.decode(&frame)?;
+//! assert_eq!(decoded["request_id"], "req_1");
+//! # Ok::<(), claude_agents_sdk::ClaudeSDKError>(())
+//! ```
+
+use std::sync::Arc;
+
+use crate::errors::{ClaudeSDKError, Result};
+
+/// Encodes protocol frames for the wire and decodes them back.
+///
+/// Each call handles exactly one frame; delimiting frames on a byte stream
+/// is up to the transport.
+pub trait FrameCodec: Send + Sync {
+    /// Name used to negotiate the encoding, e.g. `"json"`.
+    fn name(&self) -> &'static str;
+
+    /// Encode a single frame.
+    fn encode(&self, frame: &serde_json::Value) -> Result<Vec<u8>>;
+
+    /// Decode a single frame.
+    fn decode(&self, data: &[u8]) -> Result<serde_json::Value>;
+
+    /// Encode a frame given as the JSON text the SDK writes to transports.
+    fn encode_line(&self, line: &str) -> Result<Vec<u8>> {
+        let frame = serde_json::from_str(line.trim_end()).map_err(|e| {
+            ClaudeSDKError::json_decode(format!("Invalid frame from the SDK: {}", e))
+        })?;
+        self.encode(&frame)
+    }
+}
+
+/// Plain JSON, as spoken by the CLI over stdio.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl FrameCodec for JsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode(&self, frame: &serde_json::Value) -> Result<Vec<u8>> {
+        serde_json::to_vec(frame)
+            .map_err(|e| ClaudeSDKError::internal(format!("Failed to encode JSON frame: {}", e)))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<serde_json::Value> {
+        serde_json::from_slice(data)
+            .map_err(|e| ClaudeSDKError::json_decode(format!("Invalid JSON frame: {}", e)))
+    }
+
+    fn encode_line(&self, line: &str) -> Result<Vec<u8>> {
+        // Already encoded; only validate it
+        let line = line.trim_end();
+        self.decode(line.as_bytes())?;
+        Ok(line.as_bytes().to_vec())
+    }
+}
+
+/// MessagePack.
+///
+/// Requires the `msgpack` feature.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl FrameCodec for MsgPackCodec {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode(&self, frame: &serde_json::Value) -> Result<Vec<u8>> {
+        rmp_serde::to_vec(frame).map_err(|e| {
+            ClaudeSDKError::internal(format!("Failed to encode MessagePack frame: {}", e))
+        })
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<serde_json::Value> {
+        rmp_serde::from_slice(data)
+            .map_err(|e| ClaudeSDKError::json_decode(format!("Invalid MessagePack frame: {}", e)))
+    }
+}
+
+/// CBOR (RFC 8949).
+///
+/// Requires the `cbor` feature.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl FrameCodec for CborCodec {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode(&self, frame: &serde_json::Value) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        ciborium::into_writer(frame, &mut data)
+            .map_err(|e| ClaudeSDKError::internal(format!("Failed to encode CBOR frame: {}", e)))?;
+        Ok(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<serde_json::Value> {
+        ciborium::from_reader(data)
+            .map_err(|e| ClaudeSDKError::json_decode(format!("Invalid CBOR frame: {}", e)))
+    }
+}
+
+/// Names of the codecs compiled into this build, most compact first.
+pub fn supported() -> Vec<&'static str> {
+    ["cbor", "msgpack", "json"]
+        .into_iter()
+        .filter(|name| by_name(name).is_some())
+        .collect()
+}
+
+/// Look up a codec by its negotiated name.
+pub fn by_name(name: &str) -> Option<Arc<dyn FrameCodec>> {
+    match name {
+        "json" => Some(Arc::new(JsonCodec)),
+        #[cfg(feature = "msgpack")]
+        "msgpack" => Some(Arc::new(MsgPackCodec)),
+        #[cfg(feature = "cbor")]
+        "cbor" => Some(Arc::new(CborCodec)),
+        _ => None,
+    }
+}
+
+/// Pick the first of the peer's `offered` encodings this build supports,
+/// falling back to JSON.
+pub fn negotiate(offered: &[&str]) -> Arc<dyn FrameCodec> {
+    offered
+        .iter()
+        .find_map(|name| by_name(name))
+        .unwrap_or_else(|| Arc::new(JsonCodec))
+}
@@ -0,0 +1,686 @@
+//! Transport-agnostic control protocol engine.
+//!
+//! [`ProtocolEngine`] holds the state of the control protocol: it classifies
+//! the frames read from the CLI, correlates control responses with the
+//! requests waiting for them, and answers the CLI's control requests with the
+//! permission callback and hook callbacks. It performs no I/O and doesn't
+//! depend on process management — only on `tokio::sync` and `tokio::time` —
+//! so the same logic drives a CLI spawned as a subprocess and one reached
+//! through a bridge (HTTP, WebSocket) from a remote or `wasm32` consumer.
+//!
+//! The caller owns the connection: it feeds every frame it reads to
+//! [`receive`](ProtocolEngine::receive), and writes the frames the engine
+//! builds.
+//!
+//! ```no_run
+//! use claude_agents_sdk::protocol::engine::{Incoming, ProtocolEngine};
+//! use claude_agents_sdk::protocol::{encode_frame, ControlRequestPayload, ControlResponse};
+//!
+//! # async fn example(
+//! #     write: impl Fn(String),
+//! #     frames: Vec<serde_json::Value>,
+//! # ) -> claude_agents_sdk::Result<()> {
+//! let engine = ProtocolEngine::new(None);
+//!
+//! let (request, pending) = engine.request(ControlRequestPayload::McpStatus).await;
+//! write(encode_frame(&request)?);
+//!
+//! for frame in frames {
+//!     match engine.receive(frame).await {
+//!         Incoming::Message(raw) => println!("message: {}", raw),
+//!         Incoming::ControlRequest { request, .. } => {
+//!             let request_id = request.request_id.clone();
+//!             let data = engine.answer(request).await.resolve().await;
+//!             write(encode_frame(&ControlResponse::from_result(request_id, data))?);
+//!         }
+//!         Incoming::ControlResponse | Incoming::Anomaly(_) => {}
+//!     }
+//! }
+//! let status = pending.wait().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde_json::Value;
+use tokio::sync::{oneshot, RwLock};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use super::parser::{
+    is_control_request, is_control_response, parse_control_request, parse_control_response,
+};
+use super::wire::{self, EmptyResponse, InitializeAck};
+use super::{ControlRequest, ControlRequestPayload};
+use crate::errors::{ClaudeSDKError, Result};
+use crate::tool_schema::ToolSchemaRegistry;
+use crate::types::{
+    CanUseTool, HookCallback, HookContext, HookDeferral, HookInput, HookOutput, PermissionResult,
+    ProtocolAnomaly, ProtocolAnomalyKind, ToolPermissionContext,
+};
+
+/// Counter for generating unique request IDs.
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Generate a unique request ID.
+fn generate_request_id() -> String {
+    let count = REQUEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+    let uuid = Uuid::new_v4();
+    let uuid_str = uuid.to_string();
+    format!("req_{}_{}", count, &uuid_str[..8])
+}
+
+/// Build an anomaly from a raw control frame.
+pub(crate) fn control_anomaly(kind: ProtocolAnomalyKind, raw: Value) -> ProtocolAnomaly {
+    let field = |key: &str| {
+        raw.get(key)
+            .and_then(|v| v.get("subtype"))
+            .and_then(|v| v.as_str())
+            .map(String::from)
+    };
+    let subtype = field("request").or_else(|| field("response"));
+    let request_id = raw
+        .get("request_id")
+        .or_else(|| raw.get("response").and_then(|r| r.get("request_id")))
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    ProtocolAnomaly {
+        kind,
+        subtype,
+        request_id,
+        raw,
+    }
+}
+
+/// Serialize a hook callback's output for the CLI.
+pub(crate) fn hook_output_value(output: &HookOutput) -> Result<Value> {
+    let mut value = serde_json::to_value(output)
+        .map_err(|e| ClaudeSDKError::internal(format!("Failed to serialize HookOutput: {}", e)))?;
+
+    // Rename async_ to async and continue_ to continue
+    if let Value::Object(ref mut map) = value {
+        if let Some(v) = map.remove("async_") {
+            map.insert("async".to_string(), v);
+        }
+        if let Some(v) = map.remove("continue_") {
+            map.insert("continue".to_string(), v);
+        }
+    }
+    Ok(value)
+}
+
+/// A frame read from the CLI, classified by [`ProtocolEngine::receive`].
+#[derive(Debug)]
+pub enum Incoming {
+    /// A conversation message, to parse with
+    /// [`parse_message`](super::parser::parse_message).
+    Message(Value),
+    /// A control request from the CLI, to answer with
+    /// [`ProtocolEngine::answer`].
+    ControlRequest {
+        /// The parsed request.
+        request: ControlRequest,
+        /// Set if the engine has no handler for the request's subtype; its
+        /// answer is `null`.
+        anomaly: Option<ProtocolAnomaly>,
+    },
+    /// A control response, delivered to the request waiting for it.
+    ControlResponse,
+    /// A control frame that couldn't be parsed or routed. Answer a control
+    /// request among them with [`ProtocolEngine::rejection`], so the CLI
+    /// isn't left waiting.
+    Anomaly(ProtocolAnomaly),
+}
+
+/// Response to a control request sent with [`ProtocolEngine::request`].
+#[derive(Debug)]
+pub struct PendingResponse {
+    request_id: String,
+    receiver: oneshot::Receiver<Result<Value>>,
+}
+
+impl PendingResponse {
+    /// ID of the request this response answers.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Wait for the CLI's response.
+    ///
+    /// There is no timeout; wrap the future in one and call
+    /// [`ProtocolEngine::cancel`] when it expires.
+    pub async fn wait(self) -> Result<Value> {
+        self.receiver
+            .await
+            .unwrap_or_else(|_| Err(ClaudeSDKError::internal("Control request receiver dropped")))
+    }
+}
+
+/// Answer to a control request from the CLI.
+#[derive(Debug)]
+pub struct ControlReply(ReplyKind);
+
+#[derive(Debug)]
+enum ReplyKind {
+    Ready(Result<Value>),
+    /// Respond once a deferred hook result is completed.
+    Deferred {
+        deferral: HookDeferral,
+        /// Response used if the deferral times out.
+        fallback: Value,
+    },
+}
+
+impl ControlReply {
+    /// Whether the answer waits for a hook result deferred with
+    /// [`HookContext::defer`]. Resolve deferred replies off the task that
+    /// reads frames, so the session keeps flowing.
+    pub fn is_deferred(&self) -> bool {
+        matches!(self.0, ReplyKind::Deferred { .. })
+    }
+
+    /// Get the response data to send back to the CLI.
+    pub async fn resolve(self) -> Result<Value> {
+        match self.0 {
+            ReplyKind::Ready(data) => data,
+            ReplyKind::Deferred { deferral, fallback } => await_deferral(deferral, fallback).await,
+        }
+    }
+}
+
+/// Wait for a deferred hook result, falling back on timeout.
+async fn await_deferral(deferral: HookDeferral, fallback: Value) -> Result<Value> {
+    debug!("Waiting for deferred hook result {}", deferral.id);
+    match tokio::time::timeout(deferral.timeout, deferral.receiver).await {
+        Ok(Ok(output)) => {
+            debug!("Deferred hook result {} completed", deferral.id);
+            hook_output_value(&output)
+        }
+        Ok(Err(_)) => {
+            warn!(
+                "Deferred hook result {} was dropped without completing",
+                deferral.id
+            );
+            wire::body(&EmptyResponse {})
+        }
+        Err(_) => {
+            warn!(
+                "Deferred hook result {} timed out after {:?}",
+                deferral.id, deferral.timeout
+            );
+            Ok(fallback)
+        }
+    }
+}
+
+/// Control protocol state of one CLI session.
+pub struct ProtocolEngine {
+    /// Senders of the control requests awaiting responses.
+    pending: RwLock<HashMap<String, oneshot::Sender<Result<Value>>>>,
+    /// Tool permission callback.
+    can_use_tool: Option<CanUseTool>,
+    /// Hook callback registry (callback_id -> callback function).
+    hook_callbacks: RwLock<HashMap<String, HookCallback>>,
+    /// Schemas to validate tool inputs against.
+    tool_schemas: Option<ToolSchemaRegistry>,
+}
+
+impl ProtocolEngine {
+    /// Create an engine answering permission requests with `can_use_tool`,
+    /// or allowing every tool without one.
+    pub fn new(can_use_tool: Option<CanUseTool>) -> Self {
+        Self {
+            pending: RwLock::new(HashMap::new()),
+            can_use_tool,
+            hook_callbacks: RwLock::new(HashMap::new()),
+            tool_schemas: None,
+        }
+    }
+
+    /// Validate tool inputs against `registry` before answering permission
+    /// requests.
+    pub fn with_tool_schemas(mut self, registry: ToolSchemaRegistry) -> Self {
+        self.tool_schemas = Some(registry);
+        self
+    }
+
+    /// Register the hook callback the CLI invokes as `callback_id`.
+    pub async fn register_hook_callback(
+        &self,
+        callback_id: impl Into<String>,
+        callback: HookCallback,
+    ) {
+        self.hook_callbacks
+            .write()
+            .await
+            .insert(callback_id.into(), callback);
+    }
+
+    /// Build a control request and register it to receive its response.
+    pub async fn request(
+        &self,
+        payload: ControlRequestPayload,
+    ) -> (ControlRequest, PendingResponse) {
+        let request_id = generate_request_id();
+        let (sender, receiver) = oneshot::channel();
+        self.pending
+            .write()
+            .await
+            .insert(request_id.clone(), sender);
+        (
+            ControlRequest::new(request_id.clone(), payload),
+            PendingResponse {
+                request_id,
+                receiver,
+            },
+        )
+    }
+
+    /// Stop waiting for the response to `request_id`.
+    pub async fn cancel(&self, request_id: &str) {
+        self.pending.write().await.remove(request_id);
+    }
+
+    /// Fail every request awaiting a response, since none can arrive any more.
+    pub async fn fail_pending(&self, reason: &str) {
+        let pending: Vec<_> = self.pending.write().await.drain().collect();
+        if !pending.is_empty() {
+            debug!("Failing {} pending control requests", pending.len());
+        }
+        for (_, sender) in pending {
+            let _ = sender.send(Err(ClaudeSDKError::cli_connection(reason)));
+        }
+    }
+
+    /// Classify a frame read from the CLI, delivering control responses to
+    /// the requests waiting for them.
+    pub async fn receive(&self, frame: Value) -> Incoming {
+        if is_control_response(&frame) {
+            debug!("Routing control response");
+            match self.deliver(frame).await {
+                Some(anomaly) => Incoming::Anomaly(anomaly),
+                None => Incoming::ControlResponse,
+            }
+        } else if is_control_request(&frame) {
+            debug!("Routing control request");
+            match parse_control_request(frame.clone()) {
+                Ok(request) => {
+                    let handled = matches!(
+                        request.request,
+                        ControlRequestPayload::CanUseTool { .. }
+                            | ControlRequestPayload::HookCallback { .. }
+                            | ControlRequestPayload::Initialize { .. }
+                            | ControlRequestPayload::McpMessage { .. }
+                    );
+                    Incoming::ControlRequest {
+                        request,
+                        anomaly: (!handled).then(|| {
+                            control_anomaly(ProtocolAnomalyKind::UnhandledControlRequest, frame)
+                        }),
+                    }
+                }
+                Err(e) => {
+                    debug!("Failed to parse control request: {}", e);
+                    Incoming::Anomaly(control_anomaly(
+                        ProtocolAnomalyKind::UnknownControlRequest,
+                        frame,
+                    ))
+                }
+            }
+        } else {
+            Incoming::Message(frame)
+        }
+    }
+
+    /// The ID of the control request behind `anomaly` and the error to
+    /// answer it with, if it is a request that couldn't be parsed and its
+    /// ID could be read.
+    pub fn rejection(&self, anomaly: &ProtocolAnomaly) -> Option<(String, Result<Value>)> {
+        if anomaly.kind != ProtocolAnomalyKind::UnknownControlRequest {
+            return None;
+        }
+        let request_id = anomaly.request_id.clone()?;
+        let message = match anomaly.subtype {
+            Some(ref subtype) => format!("Unsupported control request: {}", subtype),
+            None => "Malformed control request".to_string(),
+        };
+        let error = ClaudeSDKError::control_protocol_with_id(message, request_id.clone());
+        Some((request_id, Err(error)))
+    }
+
+    /// Deliver a control response to its request.
+    ///
+    /// Returns an anomaly if the response couldn't be routed.
+    async fn deliver(&self, raw: Value) -> Option<ProtocolAnomaly> {
+        match parse_control_response(raw.clone()) {
+            Ok(response) => {
+                let request_id = response.request_id().to_string();
+                let Some(sender) = self.pending.write().await.remove(&request_id) else {
+                    debug!("Received response for unknown request: {}", request_id);
+                    return Some(control_anomaly(
+                        ProtocolAnomalyKind::UnmatchedControlResponse,
+                        raw,
+                    ));
+                };
+
+                let result = if response.is_success() {
+                    Ok(response.data().cloned().unwrap_or(Value::Null))
+                } else {
+                    Err(ClaudeSDKError::control_protocol_with_id(
+                        response.error().unwrap_or("Unknown error"),
+                        request_id,
+                    ))
+                };
+                let _ = sender.send(result);
+                None
+            }
+            Err(e) => {
+                error!("Failed to parse control response: {}", e);
+                Some(control_anomaly(
+                    ProtocolAnomalyKind::MalformedControlResponse,
+                    raw,
+                ))
+            }
+        }
+    }
+
+    /// Answer a control request from the CLI.
+    pub async fn answer(&self, request: ControlRequest) -> ControlReply {
+        let response = match request.request {
+            ControlRequestPayload::CanUseTool {
+                tool_name,
+                input,
+                permission_suggestions,
+                ..
+            } => {
+                self.answer_can_use_tool(tool_name, input, permission_suggestions)
+                    .await
+            }
+
+            ControlRequestPayload::HookCallback {
+                callback_id,
+                input,
+                tool_use_id,
+            } => {
+                let callback = self.hook_callbacks.read().await.get(&callback_id).cloned();
+                match callback {
+                    Some(callback) => {
+                        return self.run_hook(callback, input, tool_use_id).await;
+                    }
+                    None => {
+                        warn!("Unknown hook callback ID: {}", callback_id);
+                        wire::body(&EmptyResponse {})
+                    }
+                }
+            }
+
+            ControlRequestPayload::Initialize { .. } => {
+                // CLI is initializing - acknowledge
+                debug!("Received initialize request from CLI");
+                wire::body(&InitializeAck { initialized: true })
+            }
+
+            ControlRequestPayload::McpMessage {
+                server_name,
+                message: _,
+            } => {
+                // MCP message routing - this would be implemented with full MCP support
+                debug!("Received MCP message for server: {}", server_name);
+                Err(ClaudeSDKError::internal(format!(
+                    "MCP server '{}' not found (SDK MCP not yet implemented)",
+                    server_name
+                )))
+            }
+
+            _ => {
+                debug!("Unhandled control request type");
+                Ok(Value::Null)
+            }
+        };
+        ControlReply(ReplyKind::Ready(response))
+    }
+
+    /// Invoke a hook callback, deferring the reply if the hook asked to.
+    async fn run_hook(
+        &self,
+        callback: HookCallback,
+        input: Value,
+        tool_use_id: Option<String>,
+    ) -> ControlReply {
+        let hook_input: HookInput = match serde_json::from_value(input) {
+            Ok(input) => input,
+            Err(e) => {
+                return ControlReply(ReplyKind::Ready(Err(ClaudeSDKError::message_parse(
+                    format!("Failed to parse hook input: {}", e),
+                ))))
+            }
+        };
+
+        let context = HookContext::default();
+        let output = callback(hook_input, tool_use_id, context.clone()).await;
+        let output_value = match hook_output_value(&output) {
+            Ok(value) => value,
+            Err(e) => return ControlReply(ReplyKind::Ready(Err(e))),
+        };
+
+        match context.take_deferral() {
+            Some(deferral) if matches!(output, HookOutput::Async(_)) => {
+                return ControlReply(ReplyKind::Deferred {
+                    deferral,
+                    fallback: output_value,
+                });
+            }
+            Some(deferral) => warn!(
+                "Hook deferred result {} but returned a synchronous output; ignoring the deferral",
+                deferral.id
+            ),
+            None => {}
+        }
+        ControlReply(ReplyKind::Ready(Ok(output_value)))
+    }
+
+    /// Ask the permission callback whether a tool may run.
+    pub(crate) async fn answer_can_use_tool(
+        &self,
+        tool_name: String,
+        input: Value,
+        permission_suggestions: Option<Vec<Value>>,
+    ) -> Result<Value> {
+        let validation = self
+            .tool_schemas
+            .as_ref()
+            .map(|registry| registry.validate(&tool_name, &input));
+        let result = match &self.can_use_tool {
+            // No callback - default to allow
+            None => PermissionResult::allow(),
+            Some(callback) => {
+                let context = ToolPermissionContext {
+                    suggestions: permission_suggestions
+                        .map(|s| {
+                            s.into_iter()
+                                .filter_map(|v| serde_json::from_value(v).ok())
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    validation,
+                };
+                callback(tool_name, input, context).await
+            }
+        };
+        serde_json::to_value(result).map_err(|e| {
+            ClaudeSDKError::internal(format!("Failed to serialize PermissionResult: {}", e))
+        })
+    }
+}
+
+impl std::fmt::Debug for ProtocolEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProtocolEngine")
+            .field("has_can_use_tool", &self.can_use_tool.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SyncHookOutput;
+    use std::time::Duration;
+
+    #[test]
+    fn test_generate_request_id() {
+        let id1 = generate_request_id();
+        let id2 = generate_request_id();
+
+        assert!(id1.starts_with("req_"));
+        assert!(id2.starts_with("req_"));
+        assert_ne!(id1, id2);
+    }
+
+    #[tokio::test]
+    async fn test_deferred_hook_result() {
+        let context = HookContext::default();
+        let (deferred, output) = context.defer(Duration::from_secs(5));
+        let fallback = hook_output_value(&output).unwrap();
+        assert_eq!(
+            fallback,
+            serde_json::json!({"async": true, "asyncTimeout": 5000})
+        );
+
+        let deferral = context.take_deferral().unwrap();
+        assert_eq!(deferral.id, deferred.id());
+        let waiting = tokio::spawn(await_deferral(deferral, fallback));
+        assert!(deferred.complete(HookOutput::Sync(SyncHookOutput {
+            decision: Some("block".to_string()),
+            ..Default::default()
+        })));
+        assert_eq!(
+            waiting.await.unwrap().unwrap(),
+            serde_json::json!({"decision": "block"})
+        );
+
+        let (deferred, output) = context.defer(Duration::from_millis(10));
+        let fallback = hook_output_value(&output).unwrap();
+        let result = await_deferral(context.take_deferral().unwrap(), fallback.clone())
+            .await
+            .unwrap();
+        assert_eq!(result, fallback);
+        assert!(!deferred.complete(HookOutput::default()));
+    }
+
+    #[tokio::test]
+    async fn test_unparsed_control_requests_are_rejected() {
+        let engine = ProtocolEngine::new(None);
+        let request = |request: Value| serde_json::json!({"type": "control_request", "request_id": "req_1", "request": request});
+
+        let Incoming::Anomaly(anomaly) = engine
+            .receive(request(serde_json::json!({"subtype": "brand_new_thing"})))
+            .await
+        else {
+            panic!("expected an anomaly");
+        };
+        let (request_id, response) = engine.rejection(&anomaly).unwrap();
+        assert_eq!(request_id, "req_1");
+        let err = response.unwrap_err();
+        assert!(err.to_string().contains("brand_new_thing"), "{}", err);
+
+        // Without an ID there is nothing to answer
+        let mut frame = request(serde_json::json!({"subtype": "brand_new_thing"}));
+        frame.as_object_mut().unwrap().remove("request_id");
+        let Incoming::Anomaly(anomaly) = engine.receive(frame).await else {
+            panic!("expected an anomaly");
+        };
+        assert!(engine.rejection(&anomaly).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_receive_routes_control_frames() {
+        let engine = ProtocolEngine::new(None);
+        let (request, pending) = engine.request(ControlRequestPayload::McpStatus).await;
+        assert_eq!(pending.request_id(), request.request_id);
+
+        let response = serde_json::json!({
+            "type": "control_response",
+            "response": {
+                "subtype": "success",
+                "request_id": request.request_id,
+                "response": {"mcpServers": []}
+            }
+        });
+        assert!(matches!(
+            engine.receive(response.clone()).await,
+            Incoming::ControlResponse
+        ));
+        assert_eq!(
+            pending.wait().await.unwrap(),
+            serde_json::json!({"mcpServers": []})
+        );
+
+        // The request is no longer pending
+        match engine.receive(response).await {
+            Incoming::Anomaly(anomaly) => {
+                assert_eq!(anomaly.kind, ProtocolAnomalyKind::UnmatchedControlResponse);
+                assert_eq!(anomaly.subtype.as_deref(), Some("success"));
+            }
+            other => panic!("unexpected frame: {:?}", other),
+        }
+
+        let request = serde_json::json!({
+            "type": "control_request",
+            "request_id": "cli_1",
+            "request": {"subtype": "can_use_tool", "tool_name": "Bash", "input": {}}
+        });
+        let Incoming::ControlRequest { request, anomaly } = engine.receive(request).await else {
+            panic!("expected a control request");
+        };
+        assert!(anomaly.is_none());
+        let reply = engine.answer(request).await;
+        assert!(!reply.is_deferred());
+        assert_eq!(
+            reply.resolve().await.unwrap(),
+            serde_json::json!({"behavior": "allow"})
+        );
+
+        let message = serde_json::json!({"type": "assistant"});
+        assert!(matches!(
+            engine.receive(message).await,
+            Incoming::Message(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_can_use_tool_sees_validation() {
+        use crate::tool_schema::ToolSchemaRegistry;
+        use std::sync::{Arc, Mutex};
+
+        let seen = Arc::new(Mutex::new(None));
+        let seen_clone = Arc::clone(&seen);
+        let callback: CanUseTool = Arc::new(move |_, _, context| {
+            *seen_clone.lock().unwrap() = context.validation;
+            Box::pin(async { PermissionResult::allow() })
+        });
+        let invalid = serde_json::json!({"command": ["ls"]});
+
+        let engine =
+            ProtocolEngine::new(Some(callback)).with_tool_schemas(ToolSchemaRegistry::builtin());
+        let allowed = engine
+            .answer_can_use_tool("Bash".to_string(), invalid, None)
+            .await
+            .unwrap();
+        assert_eq!(allowed["behavior"], "allow");
+        let report = seen.lock().unwrap().take().unwrap();
+        assert_eq!(report.errors[0].path, "/command");
+    }
+
+    #[tokio::test]
+    async fn test_fail_pending() {
+        let engine = ProtocolEngine::new(None);
+        let (_, pending) = engine.request(ControlRequestPayload::Interrupt).await;
+        engine.fail_pending("CLI process exited").await;
+        let err = pending.wait().await.unwrap_err();
+        assert!(matches!(err, ClaudeSDKError::CLIConnection { .. }));
+        assert!(err.to_string().contains("CLI process exited"));
+    }
+}
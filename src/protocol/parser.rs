@@ -3,6 +3,9 @@
 //! This module handles conversion of raw JSON messages from the CLI
 //! into strongly-typed Message objects.
 
+use std::sync::Arc;
+
+use super::{wire, ControlRequest, ControlResponse};
 use crate::errors::{ClaudeSDKError, Result};
 use crate::types::*;
 
@@ -16,11 +19,11 @@ pub fn parse_message(raw: serde_json::Value) -> Result<Message> {
     })?;
 
     match msg_type {
-        "user" => parse_user_message(raw),
-        "assistant" => parse_assistant_message(raw),
-        "system" => parse_system_message(raw),
-        "result" => parse_result_message(raw),
-        "stream_event" => parse_stream_event(raw),
+        wire::USER => parse_user_message(raw),
+        wire::ASSISTANT => parse_assistant_message(raw),
+        wire::SYSTEM => parse_system_message(raw),
+        wire::RESULT => parse_result_message(raw),
+        wire::STREAM_EVENT => parse_stream_event(raw),
         other => Err(ClaudeSDKError::message_parse_with_raw(
             format!("Unknown message type: {}", other),
             raw,
@@ -28,6 +31,16 @@ pub fn parse_message(raw: serde_json::Value) -> Result<Message> {
     }
 }
 
+/// Parse a raw JSON value into a typed Message, keeping the raw JSON.
+///
+/// The original value is available afterwards via [`Message::raw`].
+pub fn parse_message_with_raw(raw: serde_json::Value) -> Result<Message> {
+    let shared = Arc::new(raw.clone());
+    let mut message = parse_message(raw)?;
+    message.set_raw(shared);
+    Ok(message)
+}
+
 /// Parse a user message.
 fn parse_user_message(raw: serde_json::Value) -> Result<Message> {
     // CLI sends user messages with content nested under "message" field
@@ -62,6 +75,8 @@ fn parse_user_message(raw: serde_json::Value) -> Result<Message> {
             .get("parent_tool_use_id")
             .and_then(|v| v.as_str())
             .map(String::from),
+        meta: None,
+        raw: None,
     }))
 }
 
@@ -112,7 +127,11 @@ fn parse_assistant_message(raw: serde_json::Value) -> Result<Message> {
             .get("parent_tool_use_id")
             .and_then(|v| v.as_str())
             .map(String::from),
+        uuid: raw.get("uuid").and_then(|v| v.as_str()).map(String::from),
         error,
+        synthesized: false,
+        meta: None,
+        raw: None,
     }))
 }
 
@@ -126,59 +145,97 @@ fn parse_system_message(raw: serde_json::Value) -> Result<Message> {
 
     let data = raw.get("data").cloned().unwrap_or(serde_json::Value::Null);
 
-    Ok(Message::System(SystemMessage { subtype, data }))
+    Ok(Message::System(SystemMessage {
+        subtype,
+        data,
+        meta: None,
+        raw: None,
+    }))
+}
+
+/// The fields of a frame, read by any of their spellings, remembering
+/// each spelling so the fields left unread can be collected.
+struct Fields<'a> {
+    raw: &'a serde_json::Value,
+    read: Vec<&'static str>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(raw: &'a serde_json::Value) -> Self {
+        Self {
+            raw,
+            read: Vec::new(),
+        }
+    }
+
+    /// The field with any of `names`, trying them in order.
+    fn get(&mut self, names: &[&'static str]) -> Option<&'a serde_json::Value> {
+        self.read.extend_from_slice(names);
+        names.iter().find_map(|name| self.raw.get(name))
+    }
+
+    /// The fields never read.
+    fn rest(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.raw
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| !self.read.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
 }
 
 /// Parse a result message.
 fn parse_result_message(raw: serde_json::Value) -> Result<Message> {
-    let subtype = raw
-        .get("subtype")
+    let mut fields = Fields::new(&raw);
+    fields.get(&["type"]);
+
+    let subtype = fields
+        .get(&["subtype"])
         .and_then(|v| v.as_str())
         .unwrap_or("success")
         .to_string();
 
-    let duration_ms = raw
-        .get("duration_ms")
-        .or_else(|| raw.get("durationMs"))
+    let duration_ms = fields
+        .get(&["duration_ms", "durationMs"])
         .and_then(|v| v.as_u64())
         .unwrap_or(0);
 
-    let duration_api_ms = raw
-        .get("duration_api_ms")
-        .or_else(|| raw.get("durationApiMs"))
+    let duration_api_ms = fields
+        .get(&["duration_api_ms", "durationApiMs"])
         .and_then(|v| v.as_u64())
         .unwrap_or(0);
 
-    let is_error = raw
-        .get("is_error")
-        .or_else(|| raw.get("isError"))
+    let is_error = fields
+        .get(&["is_error", "isError"])
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
 
-    let num_turns = raw
-        .get("num_turns")
-        .or_else(|| raw.get("numTurns"))
+    let num_turns = fields
+        .get(&["num_turns", "numTurns"])
         .and_then(|v| v.as_u64())
         .unwrap_or(0) as u32;
 
-    let session_id = raw
-        .get("session_id")
-        .or_else(|| raw.get("sessionId"))
+    let session_id = fields
+        .get(&["session_id", "sessionId"])
         .and_then(|v| v.as_str())
         .unwrap_or("")
         .to_string();
 
-    let total_cost_usd = raw
-        .get("total_cost_usd")
-        .or_else(|| raw.get("totalCostUsd"))
+    let total_cost_usd = fields
+        .get(&["total_cost_usd", "totalCostUsd"])
         .and_then(|v| v.as_f64());
 
-    let usage = raw.get("usage").cloned();
-    let result = raw.get("result").and_then(|v| v.as_str()).map(String::from);
-    let structured_output = raw
-        .get("structured_output")
-        .or_else(|| raw.get("structuredOutput"))
+    let usage = fields.get(&["usage"]).cloned();
+    let result = fields
+        .get(&["result"])
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    let structured_output = fields
+        .get(&["structured_output", "structuredOutput"])
         .cloned();
+    let extras = fields.rest();
 
     Ok(Message::Result(ResultMessage {
         subtype,
@@ -191,6 +248,9 @@ fn parse_result_message(raw: serde_json::Value) -> Result<Message> {
         usage,
         result,
         structured_output,
+        extras,
+        meta: None,
+        raw: None,
     }))
 }
 
@@ -221,6 +281,8 @@ fn parse_stream_event(raw: serde_json::Value) -> Result<Message> {
         session_id,
         event,
         parent_tool_use_id,
+        meta: None,
+        raw: None,
     }))
 }
 
@@ -280,12 +342,20 @@ fn parse_content_block(raw: &serde_json::Value) -> Result<ContentBlock> {
                 .and_then(|v| v.as_str())
                 .unwrap_or("")
                 .to_string();
-            let content = raw.get("content").cloned();
+            let content = raw
+                .get("content")
+                .cloned()
+                .map(ToolResultContent::from_value);
             let is_error = raw.get("is_error").and_then(|v| v.as_bool());
+            // Set by the SDK when it spooled the content
+            let content_ref = raw
+                .get("content_ref")
+                .and_then(|v| serde_json::from_value(v.clone()).ok());
             Ok(ContentBlock::ToolResult(ToolResultBlock {
                 tool_use_id,
                 content,
                 is_error,
+                content_ref,
             }))
         }
         other => Err(ClaudeSDKError::message_parse_with_raw(
@@ -299,7 +369,7 @@ fn parse_content_block(raw: &serde_json::Value) -> Result<ContentBlock> {
 pub fn is_control_request(raw: &serde_json::Value) -> bool {
     raw.get("type")
         .and_then(|v| v.as_str())
-        .map(|t| t == "control_request")
+        .map(|t| t == wire::CONTROL_REQUEST)
         .unwrap_or(false)
 }
 
@@ -307,7 +377,7 @@ pub fn is_control_request(raw: &serde_json::Value) -> bool {
 pub fn is_control_response(raw: &serde_json::Value) -> bool {
     raw.get("type")
         .and_then(|v| v.as_str())
-        .map(|t| t == "control_response")
+        .map(|t| t == wire::CONTROL_RESPONSE)
         .unwrap_or(false)
 }
 
@@ -421,11 +491,40 @@ mod tests {
                 assert_eq!(result.duration_ms, 1000);
                 assert_eq!(result.session_id, "sess_123");
                 assert_eq!(result.total_cost_usd, Some(0.05));
+                assert!(result.extras.is_empty());
             }
             _ => panic!("Expected result message"),
         }
     }
 
+    #[test]
+    fn test_parse_result_message_keeps_unmodeled_fields() {
+        let raw = serde_json::json!({
+            "type": "result",
+            "subtype": "success",
+            "durationMs": 1000,
+            "session_id": "sess_123",
+            "permission_denials": [],
+            "modelUsage": {"claude-sonnet-4-5": {"inputTokens": 12}}
+        });
+
+        let Message::Result(result) = parse_message(raw).unwrap() else {
+            panic!("Expected result message");
+        };
+        assert_eq!(result.duration_ms, 1000);
+        assert_eq!(result.extras.len(), 2);
+        assert_eq!(
+            result.extras["modelUsage"]["claude-sonnet-4-5"]["inputTokens"],
+            12
+        );
+
+        // Extras round-trip as top-level fields
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["permission_denials"], serde_json::json!([]));
+        let back: ResultMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(back.extras, result.extras);
+    }
+
     #[test]
     fn test_is_control_request() {
         let raw = serde_json::json!({
@@ -771,6 +870,10 @@ mod tests {
                 if let ContentBlock::ToolResult(tr) = &blocks[0] {
                     assert_eq!(tr.tool_use_id, "tool_abc");
                     assert_eq!(tr.is_error, Some(false));
+                    assert_eq!(
+                        tr.content.as_ref().and_then(ToolResultContent::as_json),
+                        Some(&serde_json::json!({"output": "success"}))
+                    );
                 } else {
                     panic!("Expected tool result block");
                 }
@@ -0,0 +1,96 @@
+//! Strings and bodies of the frames on the wire.
+//!
+//! Frame `type`s, control response `subtype`s and the other fixed strings
+//! of the protocol are spelled once here, and the bodies the SDK writes
+//! inside control frames have typed forms, so the code building frames and
+//! the code reading them can't drift apart.
+
+use serde::Serialize;
+
+/// `type` of user messages, including the prompts the SDK sends.
+pub const USER: &str = "user";
+/// `type` of assistant messages.
+pub const ASSISTANT: &str = "assistant";
+/// `type` of system messages.
+pub const SYSTEM: &str = "system";
+/// `type` of result messages.
+pub const RESULT: &str = "result";
+/// `type` of partial message events.
+pub const STREAM_EVENT: &str = "stream_event";
+/// `type` of control requests, in either direction.
+pub const CONTROL_REQUEST: &str = "control_request";
+/// `type` of control responses, in either direction.
+pub const CONTROL_RESPONSE: &str = "control_response";
+
+/// `type`s of the conversation messages parsed into
+/// [`Message`](crate::Message).
+pub const MESSAGE_TYPES: [&str; 5] = [USER, ASSISTANT, SYSTEM, RESULT, STREAM_EVENT];
+
+/// `subtype` of a control response carrying a result.
+pub const SUCCESS: &str = "success";
+/// `subtype` of a control response carrying an error.
+pub const ERROR: &str = "error";
+/// `subtype` of the system message that starts a session.
+pub const INIT: &str = "init";
+
+/// `role` of a prompt's body.
+pub const USER_ROLE: &str = "user";
+/// Session prompts are posted to.
+pub const DEFAULT_SESSION: &str = "default";
+
+/// A hook matcher registered with the CLI in the initialize request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HookMatcherConfig {
+    /// Pattern the hook applies to, e.g. a tool name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<String>,
+    /// Timeout in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+    /// IDs the CLI sends back in `hook_callback` requests.
+    #[serde(rename = "callbackIds", skip_serializing_if = "Vec::is_empty")]
+    pub callback_ids: Vec<String>,
+}
+
+/// Answer to an initialize request from the CLI.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct InitializeAck {
+    /// Always true.
+    pub initialized: bool,
+}
+
+/// Answer to a control request that has nothing to report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct EmptyResponse {}
+
+/// Serialize a control response body.
+pub(crate) fn body(body: &impl Serialize) -> crate::Result<serde_json::Value> {
+    serde_json::to_value(body).map_err(|e| {
+        crate::ClaudeSDKError::internal(format!("Failed to encode control response: {}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bodies() {
+        let matcher = HookMatcherConfig {
+            matcher: Some("Bash".to_string()),
+            timeout: None,
+            callback_ids: vec!["hook_0".to_string()],
+        };
+        assert_eq!(
+            body(&matcher).unwrap(),
+            json!({"matcher": "Bash", "callbackIds": ["hook_0"]})
+        );
+        assert_eq!(body(&HookMatcherConfig::default()).unwrap(), json!({}));
+        assert_eq!(
+            body(&InitializeAck { initialized: true }).unwrap(),
+            json!({"initialized": true})
+        );
+        assert_eq!(body(&EmptyResponse {}).unwrap(), json!({}));
+    }
+}
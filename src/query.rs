@@ -4,12 +4,17 @@
 //! to Claude. For more complex interactions requiring bidirectional
 //! communication, use [`ClaudeClient`](crate::ClaudeClient).
 
+use std::path::PathBuf;
 use std::pin::Pin;
+use tokio::io::AsyncRead;
 use tokio_stream::Stream;
 
 use crate::_internal::client::InternalClient;
+use crate::_internal::transport::Transport;
 use crate::errors::Result;
+use crate::profile::AgentProfile;
 use crate::types::{ClaudeAgentOptions, Message};
+use crate::workspace::{self, FilesQueryResult, TempWorkspace};
 
 /// Execute a one-shot query to Claude.
 ///
@@ -66,9 +71,124 @@ pub async fn query(
     options: Option<ClaudeAgentOptions>,
 ) -> Result<Pin<Box<dyn Stream<Item = Result<Message>> + Send>>> {
     let options = options.unwrap_or_default();
+    #[cfg(feature = "tokens")]
+    crate::tokens::warn_if_oversized(prompt, &options);
     InternalClient::process_query(options, prompt).await
 }
 
+/// Execute a query whose prompt is read from `reader`.
+///
+/// The prompt is streamed to the CLI's stdin as it is read, in chunks, so a
+/// prompt of hundreds of megabytes, such as a log file to analyze, is never
+/// held in memory whole. The query always runs in streaming mode. Invalid
+/// UTF-8 in the prompt is replaced with U+FFFD.
+///
+/// Under an [`outbound_frame_limit`](ClaudeAgentOptions::outbound_frame_limit),
+/// the prompt is read up to the limit before any of it is written, so one
+/// over the limit fails with [`FrameTooLarge`](crate::ClaudeSDKError::FrameTooLarge)
+/// without reaching the CLI.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use claude_agents_sdk::{query_from_reader, ClaudeAgentOptions, Message};
+/// use tokio_stream::StreamExt;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let logs = tokio::fs::File::open("/var/log/app.log").await?;
+///     let mut stream = query_from_reader(logs, None).await?;
+///
+///     while let Some(message) = stream.next().await {
+///         if let Message::Assistant(msg) = message? {
+///             println!("{}", msg.text());
+///         }
+///     }
+///     Ok(())
+/// }
+/// ```
+pub async fn query_from_reader<R>(
+    reader: R,
+    options: Option<ClaudeAgentOptions>,
+) -> Result<Pin<Box<dyn Stream<Item = Result<Message>> + Send>>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    InternalClient::process_query_from_reader(options.unwrap_or_default(), reader).await
+}
+
+/// Execute a one-shot query over `transport` instead of the CLI.
+///
+/// The transport is connected first if it isn't ready, and the query always
+/// runs in streaming mode, with the prompt written to the transport. See
+/// [`ClaudeClient::with_transport`](crate::ClaudeClient::with_transport).
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::testing::MockTransport;
+/// use claude_agents_sdk::{query_with_transport, Message};
+/// use tokio_stream::StreamExt;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> claude_agents_sdk::Result<()> {
+/// let transport = MockTransport::with_text_response("Hello!").respond_to_control_requests();
+/// let mut stream = query_with_transport("Hi", None, Box::new(transport)).await?;
+///
+/// while let Some(message) = stream.next().await {
+///     match message? {
+///         Message::Assistant(msg) => assert_eq!(msg.text(), "Hello!"),
+///         Message::Result(_) => break,
+///         _ => {}
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn query_with_transport(
+    prompt: &str,
+    options: Option<ClaudeAgentOptions>,
+    transport: Box<dyn Transport>,
+) -> Result<Pin<Box<dyn Stream<Item = Result<Message>> + Send>>> {
+    let options = options.unwrap_or_default();
+    #[cfg(feature = "tokens")]
+    crate::tokens::warn_if_oversized(prompt, &options);
+    InternalClient::process_query_with_transport(options, prompt, transport).await
+}
+
+/// Execute a query with the agent described by `profile`.
+///
+/// Equivalent to [`query`] with [`AgentProfile::options`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use claude_agents_sdk::{query_with_profile, AgentProfile, Message};
+/// use tokio_stream::StreamExt;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let refactorer = AgentProfile::load("agents/refactorer.json")?;
+///
+///     for module in ["src/parser.rs", "src/lexer.rs"] {
+///         let prompt = format!("Split the long functions in {}", module);
+///         let mut stream = query_with_profile(&refactorer, &prompt).await?;
+///         while let Some(message) = stream.next().await {
+///             if let Message::Result(result) = message? {
+///                 println!("{}: {:?}", module, result.structured_output);
+///             }
+///         }
+///     }
+///     Ok(())
+/// }
+/// ```
+pub async fn query_with_profile(
+    profile: &AgentProfile,
+    prompt: &str,
+) -> Result<Pin<Box<dyn Stream<Item = Result<Message>> + Send>>> {
+    query(prompt, Some(profile.options())).await
+}
+
 /// Execute a query and collect all messages.
 ///
 /// This is a convenience function that collects all messages from a query
@@ -182,7 +302,11 @@ where
 ///
 /// # Returns
 ///
-/// The final text response and the result message with metadata.
+/// The final text response and the result message with metadata. The
+/// result's `session_id` can be passed to [`continue_query`] to ask a
+/// follow-up in the same conversation. Fails with
+/// [`AuthRequired`](crate::ClaudeSDKError::AuthRequired) if the CLI needs to
+/// be logged in again.
 ///
 /// # Examples
 ///
@@ -194,6 +318,7 @@ where
 ///     let (response, result) = query_result("What is the capital of France?", None).await?;
 ///
 ///     println!("Response: {}", response);
+///     println!("Session: {}", result.session_id);
 ///     if let Some(cost) = result.total_cost_usd {
 ///         println!("Cost: ${:.4}", cost);
 ///     }
@@ -210,9 +335,14 @@ pub async fn query_result(
     let mut stream = query(prompt, options).await?;
     let mut response_parts: Vec<String> = Vec::new();
     let mut result_message = None;
+    let mut auth_challenge = None;
 
     while let Some(result) = stream.next().await {
-        match result? {
+        let message = result?;
+        if auth_challenge.is_none() {
+            auth_challenge = message.auth_challenge();
+        }
+        match message {
             Message::Assistant(msg) => {
                 let text = msg.text();
                 if !text.is_empty() {
@@ -227,6 +357,9 @@ pub async fn query_result(
         }
     }
 
+    if let Some(instructions) = auth_challenge {
+        return Err(crate::errors::ClaudeSDKError::auth_required(instructions));
+    }
     let result = result_message.ok_or_else(|| {
         crate::errors::ClaudeSDKError::internal("Query completed without result message")
     })?;
@@ -234,6 +367,120 @@ pub async fn query_result(
     Ok((response_parts.concat(), result))
 }
 
+/// Continue a previous one-shot query's conversation with a follow-up.
+///
+/// Resumes the CLI session `session_id` (the `session_id` of a previous
+/// [`ResultMessage`](crate::types::ResultMessage)) and runs `follow_up` in
+/// it, so multi-turn conversations don't need a live
+/// [`ClaudeClient`](crate::ClaudeClient). This suits stateless handlers
+/// that only keep the session ID between requests. Any `resume` or
+/// `continue_conversation` in `options` is replaced.
+///
+/// The returned result carries the session ID to continue from next.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use claude_agents_sdk::{continue_query, query_result};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let (_, result) = query_result("Pick a number between 1 and 10", None).await?;
+///
+///     let (response, _) =
+///         continue_query(&result.session_id, "Now double it", None).await?;
+///     println!("{}", response);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn continue_query(
+    session_id: &str,
+    follow_up: &str,
+    options: Option<ClaudeAgentOptions>,
+) -> Result<(String, crate::types::ResultMessage)> {
+    query_result(follow_up, Some(resume_options(session_id, options)?)).await
+}
+
+/// Build the options for resuming `session_id`.
+fn resume_options(
+    session_id: &str,
+    options: Option<ClaudeAgentOptions>,
+) -> Result<ClaudeAgentOptions> {
+    if session_id.trim().is_empty() {
+        return Err(crate::errors::ClaudeSDKError::configuration(
+            "Cannot continue a query without a session ID",
+        ));
+    }
+    let mut options = options.unwrap_or_default();
+    options.resume = Some(session_id.to_string());
+    options.continue_conversation = false;
+    Ok(options)
+}
+
+/// Execute a query against a set of files in a temporary workspace.
+///
+/// The given files and directories are copied into a fresh temporary
+/// directory, which becomes the query's working directory (overriding any
+/// `cwd` in `options`). After the query completes, the workspace is compared
+/// against its initial state and every created, modified or deleted file is
+/// returned with its new contents. The workspace is removed afterwards and
+/// the original files are never touched.
+///
+/// Files are placed in the workspace root under their file name, and
+/// directories are copied recursively under their directory name. Two inputs
+/// with the same name are rejected with a configuration error.
+///
+/// # Arguments
+///
+/// * `prompt` - The prompt to send to Claude
+/// * `files` - Files and directories to copy into the workspace
+/// * `options` - Optional configuration for the query
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use claude_agents_sdk::{query_with_files, ClaudeAgentOptions, PermissionMode};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let options = ClaudeAgentOptions::new()
+///         .with_permission_mode(PermissionMode::AcceptEdits);
+///
+///     let output = query_with_files(
+///         "Fix the typos in notes.md",
+///         ["docs/notes.md"],
+///         Some(options),
+///     )
+///     .await?;
+///
+///     for change in &output.changes {
+///         println!("{:?} {}", change.kind, change.path.display());
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn query_with_files<I, P>(
+    prompt: &str,
+    files: I,
+    options: Option<ClaudeAgentOptions>,
+) -> Result<FilesQueryResult>
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+{
+    let inputs: Vec<PathBuf> = files.into_iter().map(Into::into).collect();
+    let workspace = workspace::blocking(move || TempWorkspace::create(&inputs)).await?;
+
+    let options = options.unwrap_or_default().with_cwd(workspace.path());
+    let messages = query_all(prompt, Some(options)).await?;
+
+    let changes = workspace::blocking(move || workspace.changes()).await?;
+
+    Ok(FilesQueryResult { messages, changes })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,6 +496,62 @@ mod tests {
         assert_eq!(options.max_turns, Some(5));
     }
 
+    #[cfg(feature = "subprocess")]
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_query_from_reader_streams_prompt() {
+        use crate::_internal::fake_cli::scripted_cli;
+        use tokio_stream::StreamExt;
+
+        // Answers control requests, and saves the prompt line
+        let dir = tempfile::tempdir().unwrap();
+        let cli = scripted_cli(dir.path(), r#"      printf '%s\n' "$line" > "$0.prompt""#);
+
+        // Several chunks' worth, with characters split between them
+        let prompt = "2024-05-01 ERROR \"disk\" full ✗\n".repeat(10_000);
+        let options = ClaudeAgentOptions {
+            cli_path: Some(cli.clone()),
+            ..Default::default()
+        };
+        let mut stream = query_from_reader(prompt.as_bytes(), Some(options))
+            .await
+            .unwrap();
+        let message = stream.next().await.unwrap().unwrap();
+        assert!(message.is_result());
+
+        let line = std::fs::read_to_string(cli.with_extension("prompt")).unwrap();
+        let frame: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame["message"]["content"], prompt.as_str());
+
+        // Over the frame limit, nothing of the prompt is written
+        std::fs::remove_file(cli.with_extension("prompt")).unwrap();
+        let options = ClaudeAgentOptions {
+            cli_path: Some(cli.clone()),
+            outbound_frame_limit: Some(crate::OutboundFrameLimit::new(64 * 1024)),
+            ..Default::default()
+        };
+        let err = match query_from_reader(prompt.as_bytes(), Some(options)).await {
+            Ok(_) => panic!("prompt over the frame limit was sent"),
+            Err(e) => e,
+        };
+        assert!(err.is_frame_too_large(), "{}", err);
+        assert!(!cli.with_extension("prompt").exists());
+    }
+
+    #[test]
+    fn test_resume_options() {
+        let options = ClaudeAgentOptions {
+            continue_conversation: true,
+            ..ClaudeAgentOptions::new().with_model("claude-sonnet-4-5")
+        };
+        let options = resume_options("sess_1", Some(options)).unwrap();
+        assert_eq!(options.resume.as_deref(), Some("sess_1"));
+        assert!(!options.continue_conversation);
+        assert_eq!(options.model.as_deref(), Some("claude-sonnet-4-5"));
+
+        assert!(resume_options(" ", None).is_err());
+    }
+
     #[test]
     fn test_query_chunks_builds_prompt() {
         // Test that query_chunks correctly joins chunks
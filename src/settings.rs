@@ -0,0 +1,436 @@
+//! Typed builder for CLI settings JSON.
+//!
+//! The CLI accepts a settings payload via `--settings`, which
+//! [`ClaudeAgentOptions::settings`](crate::ClaudeAgentOptions::settings) passes
+//! through verbatim. This module provides [`Settings`] for constructing that
+//! payload without hand-writing JSON.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::{ClaudeAgentOptions, HookEvent, Settings};
+//!
+//! let settings = Settings::new()
+//!     .allow("Bash(npm run test:*)")
+//!     .deny("Read(./.env)")
+//!     .env("NODE_ENV", "test")
+//!     .command_hook(HookEvent::PreToolUse, Some("Bash"), "./scripts/audit.sh")
+//!     .status_line("~/.claude/statusline.sh");
+//!
+//! let mut options = ClaudeAgentOptions::new();
+//! options.settings = Some(settings.build().unwrap());
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{HookEvent, PermissionMode};
+
+/// Permission rules section of the settings file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsPermissions {
+    /// Rules for tool uses that are allowed without prompting.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Rules for tool uses that are always denied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    /// Rules for tool uses that always prompt for confirmation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ask: Vec<String>,
+    /// Additional working directories Claude may access.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional_directories: Vec<String>,
+    /// Default permission mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_mode: Option<PermissionMode>,
+    /// Set to "disable" to prevent bypassPermissions mode from being used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_bypass_permissions_mode: Option<String>,
+}
+
+impl SettingsPermissions {
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// A shell command hook in the settings file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsHookCommand {
+    /// Hook type (always "command").
+    #[serde(rename = "type")]
+    pub hook_type: String,
+    /// Shell command to run.
+    pub command: String,
+    /// Timeout in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+/// A hook matcher entry in the settings file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsHookMatcher {
+    /// Tool name pattern to match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<String>,
+    /// Commands to run when matched.
+    pub hooks: Vec<SettingsHookCommand>,
+}
+
+/// Custom status line configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusLine {
+    /// Status line type (always "command").
+    #[serde(rename = "type")]
+    pub status_type: String,
+    /// Command whose output is shown as the status line.
+    pub command: String,
+    /// Padding around the status line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding: Option<u32>,
+}
+
+/// Typed CLI settings, serialized to the JSON accepted by `--settings`.
+///
+/// Keys not modelled here can be supplied with [`Settings::set`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    /// Permission rules.
+    #[serde(default, skip_serializing_if = "SettingsPermissions::is_empty")]
+    pub permissions: SettingsPermissions,
+    /// Environment variables applied to every session.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Command hooks keyed by event name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hooks: HashMap<String, Vec<SettingsHookMatcher>>,
+    /// Custom status line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_line: Option<StatusLine>,
+    /// Model override.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Script that outputs an API key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_helper: Option<String>,
+    /// How long to retain chat transcripts, in days.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cleanup_period_days: Option<u32>,
+    /// Output style name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_style: Option<String>,
+    /// Additional settings keys passed through as-is.
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl Settings {
+    /// Create empty settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an allow rule (e.g. `"Bash(npm run test:*)"`).
+    pub fn allow(mut self, rule: impl Into<String>) -> Self {
+        self.permissions.allow.push(rule.into());
+        self
+    }
+
+    /// Add a deny rule (e.g. `"Read(./.env)"`).
+    pub fn deny(mut self, rule: impl Into<String>) -> Self {
+        self.permissions.deny.push(rule.into());
+        self
+    }
+
+    /// Add an ask rule.
+    pub fn ask(mut self, rule: impl Into<String>) -> Self {
+        self.permissions.ask.push(rule.into());
+        self
+    }
+
+    /// Add an additional working directory.
+    pub fn additional_directory(mut self, dir: impl Into<String>) -> Self {
+        self.permissions.additional_directories.push(dir.into());
+        self
+    }
+
+    /// Set the default permission mode.
+    pub fn default_mode(mut self, mode: PermissionMode) -> Self {
+        self.permissions.default_mode = Some(mode);
+        self
+    }
+
+    /// Prevent bypassPermissions mode from being used.
+    pub fn disable_bypass_permissions_mode(mut self) -> Self {
+        self.permissions.disable_bypass_permissions_mode = Some("disable".to_string());
+        self
+    }
+
+    /// Set an environment variable.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add a shell command hook for an event.
+    pub fn command_hook(
+        self,
+        event: HookEvent,
+        matcher: Option<&str>,
+        command: impl Into<String>,
+    ) -> Self {
+        self.command_hook_with_timeout(event, matcher, command, None)
+    }
+
+    /// Add a shell command hook for an event with a timeout in seconds.
+    pub fn command_hook_with_timeout(
+        mut self,
+        event: HookEvent,
+        matcher: Option<&str>,
+        command: impl Into<String>,
+        timeout: Option<u64>,
+    ) -> Self {
+        let event_name = serde_json::to_value(event)
+            .ok()
+            .and_then(|v| v.as_str().map(String::from))
+            .unwrap_or_default();
+        self.hooks
+            .entry(event_name)
+            .or_default()
+            .push(SettingsHookMatcher {
+                matcher: matcher.map(String::from),
+                hooks: vec![SettingsHookCommand {
+                    hook_type: "command".to_string(),
+                    command: command.into(),
+                    timeout,
+                }],
+            });
+        self
+    }
+
+    /// Set a command-based status line.
+    pub fn status_line(mut self, command: impl Into<String>) -> Self {
+        self.status_line = Some(StatusLine {
+            status_type: "command".to_string(),
+            command: command.into(),
+            padding: None,
+        });
+        self
+    }
+
+    /// Set the model.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Set the API key helper script.
+    pub fn api_key_helper(mut self, script: impl Into<String>) -> Self {
+        self.api_key_helper = Some(script.into());
+        self
+    }
+
+    /// Set the transcript retention period in days.
+    pub fn cleanup_period_days(mut self, days: u32) -> Self {
+        self.cleanup_period_days = Some(days);
+        self
+    }
+
+    /// Set the output style.
+    pub fn output_style(mut self, style: impl Into<String>) -> Self {
+        self.output_style = Some(style.into());
+        self
+    }
+
+    /// Set an arbitrary settings key not modelled by this type.
+    pub fn set(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.extra.insert(key.into(), value);
+        self
+    }
+
+    /// Validate the settings.
+    ///
+    /// Checks permission rule syntax, rejects rules that are both allowed and
+    /// denied, and rejects empty hook or status line commands.
+    pub fn validate(&self) -> Result<()> {
+        let perms = &self.permissions;
+        for rule in perms.allow.iter().chain(&perms.deny).chain(&perms.ask) {
+            validate_rule(rule)?;
+        }
+
+        if let Some(rule) = perms.allow.iter().find(|r| perms.deny.contains(r)) {
+            return Err(ClaudeSDKError::configuration(format!(
+                "Permission rule '{}' is both allowed and denied",
+                rule
+            )));
+        }
+
+        for key in self.env.keys() {
+            if key.is_empty() || key.contains('=') {
+                return Err(ClaudeSDKError::configuration(format!(
+                    "Invalid environment variable name '{}'",
+                    key
+                )));
+            }
+        }
+
+        for (event, matchers) in &self.hooks {
+            for hook in matchers.iter().flat_map(|m| &m.hooks) {
+                if hook.command.trim().is_empty() {
+                    return Err(ClaudeSDKError::configuration(format!(
+                        "Empty hook command for event '{}'",
+                        event
+                    )));
+                }
+                if hook.timeout == Some(0) {
+                    return Err(ClaudeSDKError::configuration(format!(
+                        "Hook timeout for event '{}' must be greater than zero",
+                        event
+                    )));
+                }
+            }
+        }
+
+        if let Some(ref status_line) = self.status_line {
+            if status_line.command.trim().is_empty() {
+                return Err(ClaudeSDKError::configuration(
+                    "Status line command cannot be empty",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate and serialize to the JSON string expected by `--settings`.
+    pub fn build(&self) -> Result<String> {
+        self.validate()?;
+        serde_json::to_string(self).map_err(|e| {
+            ClaudeSDKError::configuration(format!("Failed to serialize settings: {}", e))
+        })
+    }
+}
+
+/// Validate a permission rule of the form `Tool` or `Tool(specifier)`.
+fn validate_rule(rule: &str) -> Result<()> {
+    let invalid = |reason: &str| {
+        Err(ClaudeSDKError::configuration(format!(
+            "Invalid permission rule '{}': {}",
+            rule, reason
+        )))
+    };
+
+    let tool = match rule.find('(') {
+        Some(idx) => {
+            if !rule.ends_with(')') {
+                return invalid("missing closing parenthesis");
+            }
+            &rule[..idx]
+        }
+        None => {
+            if rule.contains(')') {
+                return invalid("unexpected closing parenthesis");
+            }
+            rule
+        }
+    };
+
+    if tool.is_empty() {
+        return invalid("missing tool name");
+    }
+    if !tool
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '*')
+    {
+        return invalid("tool name contains invalid characters");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_settings_known_good_payload() {
+        let settings = Settings::new()
+            .allow("Bash(npm run lint)")
+            .allow("Bash(npm run test:*)")
+            .deny("Read(./.env)")
+            .env("FOO", "bar")
+            .command_hook(HookEvent::PreToolUse, Some("Bash"), "echo pre")
+            .status_line("~/.claude/statusline.sh");
+
+        let value: serde_json::Value = serde_json::from_str(&settings.build().unwrap()).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "permissions": {
+                    "allow": ["Bash(npm run lint)", "Bash(npm run test:*)"],
+                    "deny": ["Read(./.env)"]
+                },
+                "env": {"FOO": "bar"},
+                "hooks": {
+                    "PreToolUse": [{
+                        "matcher": "Bash",
+                        "hooks": [{"type": "command", "command": "echo pre"}]
+                    }]
+                },
+                "statusLine": {"type": "command", "command": "~/.claude/statusline.sh"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_settings_empty_serializes_to_empty_object() {
+        assert_eq!(Settings::new().build().unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_settings_round_trip_with_extra_keys() {
+        let raw = json!({
+            "permissions": {"defaultMode": "acceptEdits", "additionalDirectories": ["../docs"]},
+            "model": "claude-sonnet-4-5",
+            "includeCoAuthoredBy": false
+        });
+        let settings: Settings = serde_json::from_value(raw.clone()).unwrap();
+
+        assert_eq!(
+            settings.permissions.default_mode,
+            Some(PermissionMode::AcceptEdits)
+        );
+        assert_eq!(
+            settings.extra.get("includeCoAuthoredBy"),
+            Some(&json!(false))
+        );
+        assert_eq!(serde_json::to_value(&settings).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_settings_rejects_invalid_rules() {
+        assert!(Settings::new().allow("Bash(npm").build().is_err());
+        assert!(Settings::new().allow("(foo)").build().is_err());
+        assert!(Settings::new().deny("Read foo").build().is_err());
+        assert!(Settings::new()
+            .allow("mcp__github__list_issues")
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_settings_rejects_conflicts_and_empty_commands() {
+        assert!(Settings::new().allow("Bash").deny("Bash").build().is_err());
+        assert!(Settings::new()
+            .command_hook(HookEvent::Stop, None, " ")
+            .build()
+            .is_err());
+        assert!(Settings::new().status_line("").build().is_err());
+        assert!(Settings::new().env("A=B", "c").build().is_err());
+    }
+}
@@ -0,0 +1,442 @@
+//! Conversation summaries produced by a cheap model.
+//!
+//! [`summarize_session`] renders a conversation as a plain-text
+//! [`Transcript`] and runs a separate one-shot query over it, by default
+//! with the `haiku` model and no tools, asking for a structured
+//! [`SessionSummary`]. The summary is short enough for dashboards, and
+//! [`SessionSummary::to_prompt`] turns it into context for a fresh session.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use claude_agents_sdk::summary::{summarize_session, SummaryOptions};
+//! use claude_agents_sdk::{ClaudeAgentOptions, ClaudeClient};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let options = ClaudeAgentOptions::new().with_message_history(500);
+//!     let mut client = ClaudeClient::new(Some(options));
+//!     client.connect().await?;
+//!     client.query("Refactor the config loader").await?;
+//!     // ... receive the response ...
+//!
+//!     let summary = summarize_session(client.transcript().await, SummaryOptions::default()).await?;
+//!     println!("{}", summary.summary);
+//!     Ok(())
+//! }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::presets::parse_output;
+use crate::query::query_result;
+use crate::types::{
+    ClaudeAgentOptions, ContentBlock, Message, ResultMessage, SystemPromptConfig, ToolsConfig,
+    UserMessageContent,
+};
+
+/// Model used for summaries unless [`SummaryOptions::model`] says otherwise.
+pub const DEFAULT_SUMMARY_MODEL: &str = "haiku";
+
+/// Longest tool input or result kept in a rendered transcript, in characters.
+const MAX_TOOL_CHARS: usize = 500;
+
+const SUMMARIZER_PROMPT: &str = "You summarize conversations between a user \
+and a coding agent. Read the transcript and report what the user asked for, \
+what the agent did and found, and what is still open. Be concise and \
+concrete: name files, commands and decisions. Do not invent details that \
+are not in the transcript.";
+
+/// A conversation rendered as plain text, one line per message part.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    text: String,
+}
+
+/// Shorten `text` to at most `max` characters, marking the cut.
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}… [truncated]", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+impl Transcript {
+    /// Render user and assistant messages, including tool calls and
+    /// (truncated) tool results. System messages and stream events are
+    /// left out.
+    pub fn from_messages(messages: &[Message]) -> Self {
+        let mut lines = Vec::new();
+        let mut blocks = |speaker: &str, blocks: &[ContentBlock]| {
+            for block in blocks {
+                match block {
+                    ContentBlock::Text(text) => lines.push(format!("{}: {}", speaker, text.text)),
+                    ContentBlock::ToolUse(tool_use) => lines.push(format!(
+                        "{} used {}: {}",
+                        speaker,
+                        tool_use.name,
+                        truncate(&tool_use.input.to_string(), MAX_TOOL_CHARS)
+                    )),
+                    ContentBlock::ToolResult(result) => {
+                        let content = match result.content {
+                            Some(ref content) => content
+                                .as_text()
+                                .unwrap_or_else(|| content.to_value().to_string()),
+                            None => String::new(),
+                        };
+                        let label = if result.is_error == Some(true) {
+                            "Tool error"
+                        } else {
+                            "Tool result"
+                        };
+                        lines.push(format!("{}: {}", label, truncate(&content, MAX_TOOL_CHARS)));
+                    }
+                    ContentBlock::Thinking(_) => {}
+                }
+            }
+        };
+
+        for message in messages {
+            match message {
+                Message::User(user) => match user.content {
+                    UserMessageContent::Text(ref text) => blocks(
+                        "User",
+                        &[ContentBlock::Text(crate::types::TextBlock {
+                            text: text.clone(),
+                        })],
+                    ),
+                    UserMessageContent::Blocks(ref content) => blocks("User", content),
+                },
+                Message::Assistant(assistant) => blocks("Assistant", &assistant.content),
+                _ => {}
+            }
+        }
+        Self {
+            text: lines.join("\n"),
+        }
+    }
+
+    /// Get the transcript text.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Check if the transcript is empty.
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    /// The last `max_chars` characters, starting at a line boundary when
+    /// the transcript is longer.
+    fn tail(&self, max_chars: usize) -> &str {
+        let total = self.text.chars().count();
+        if total <= max_chars {
+            return &self.text;
+        }
+        let (start, _) = self
+            .text
+            .char_indices()
+            .nth(total - max_chars)
+            .unwrap_or((0, ' '));
+        let tail = &self.text[start..];
+        tail.split_once('\n').map_or(tail, |(_, rest)| rest)
+    }
+}
+
+impl From<&[Message]> for Transcript {
+    fn from(messages: &[Message]) -> Self {
+        Self::from_messages(messages)
+    }
+}
+
+impl From<&Vec<Message>> for Transcript {
+    fn from(messages: &Vec<Message>) -> Self {
+        Self::from_messages(messages)
+    }
+}
+
+impl From<String> for Transcript {
+    fn from(text: String) -> Self {
+        Self { text }
+    }
+}
+
+impl From<&str> for Transcript {
+    fn from(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+        }
+    }
+}
+
+/// How [`summarize_session`] runs its query.
+#[derive(Debug, Clone)]
+pub struct SummaryOptions {
+    /// Model writing the summary (default: [`DEFAULT_SUMMARY_MODEL`]).
+    pub model: String,
+    /// Only the most recent part of longer transcripts is summarized
+    /// (default: 100,000 characters).
+    pub max_transcript_chars: usize,
+    /// Extra instructions, e.g. what the summary is for.
+    pub instructions: Option<String>,
+    /// Options the query starts from, e.g. for `cli_path`, `env` or
+    /// `max_budget_usd`; model, prompt, tools and output format are
+    /// replaced.
+    pub base: ClaudeAgentOptions,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self {
+            model: DEFAULT_SUMMARY_MODEL.to_string(),
+            max_transcript_chars: 100_000,
+            instructions: None,
+            base: ClaudeAgentOptions::new(),
+        }
+    }
+}
+
+impl SummaryOptions {
+    /// Set the model writing the summary.
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Set how much of the transcript is summarized, in characters.
+    pub fn with_max_transcript_chars(mut self, max_chars: usize) -> Self {
+        self.max_transcript_chars = max_chars;
+        self
+    }
+
+    /// Add instructions for the summarizer.
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = Some(instructions.into());
+        self
+    }
+
+    /// Set the options the query starts from.
+    pub fn with_base_options(mut self, base: ClaudeAgentOptions) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Build the options for the summary query.
+    fn query_options(&self) -> ClaudeAgentOptions {
+        let mut options = self.base.clone().with_model(self.model.clone());
+        options.tools = Some(ToolsConfig::List(Vec::new()));
+        options.system_prompt = Some(SystemPromptConfig::Text(SUMMARIZER_PROMPT.to_string()));
+        options.output_format = Some(summary_schema());
+        options.continue_conversation = false;
+        options.resume = None;
+        options.fork_session = false;
+        options
+    }
+
+    /// Build the prompt asking for a summary of `transcript`.
+    fn prompt(&self, transcript: &Transcript) -> String {
+        let mut prompt = String::from("Summarize this conversation.");
+        if let Some(ref instructions) = self.instructions {
+            prompt.push(' ');
+            prompt.push_str(instructions);
+        }
+        let tail = transcript.tail(self.max_transcript_chars);
+        if tail.len() < transcript.as_str().len() {
+            prompt.push_str("\nOnly the end of the conversation is included.");
+        }
+        prompt.push_str("\n\n<transcript>\n");
+        prompt.push_str(tail);
+        prompt.push_str("\n</transcript>");
+        prompt
+    }
+}
+
+/// A short structured summary of a conversation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// A few sentences on what happened.
+    pub summary: String,
+    /// Decisions, findings and changes worth remembering.
+    #[serde(default)]
+    pub key_points: Vec<String>,
+    /// Work that is still open.
+    #[serde(default)]
+    pub open_tasks: Vec<String>,
+}
+
+impl SessionSummary {
+    /// Parse a summary from the summary query's result message.
+    ///
+    /// Uses the structured output when present, and otherwise falls back to
+    /// parsing the result text as JSON (optionally inside a code fence).
+    pub fn from_result(result: &ResultMessage) -> Result<Self> {
+        parse_output(
+            result.structured_output.as_ref(),
+            result.result.as_deref(),
+            "session summary",
+        )
+    }
+
+    /// Render the summary as context for a new session's prompt.
+    pub fn to_prompt(&self) -> String {
+        let mut prompt = format!("Summary of the previous session:\n{}", self.summary);
+        for (title, items) in [
+            ("Key points", &self.key_points),
+            ("Open tasks", &self.open_tasks),
+        ] {
+            if !items.is_empty() {
+                prompt.push_str(&format!("\n\n{}:", title));
+                for item in items {
+                    prompt.push_str(&format!("\n- {}", item));
+                }
+            }
+        }
+        prompt
+    }
+}
+
+/// JSON Schema for [`SessionSummary`], used as the summary query's output format.
+pub fn summary_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "summary": {"type": "string"},
+            "key_points": {"type": "array", "items": {"type": "string"}},
+            "open_tasks": {"type": "array", "items": {"type": "string"}}
+        },
+        "required": ["summary", "key_points", "open_tasks"]
+    })
+}
+
+/// Summarize a conversation with a separate one-shot query.
+///
+/// `transcript` can be messages (e.g. from
+/// [`ClaudeClient::transcript`](crate::ClaudeClient::transcript) or
+/// [`query_all`](crate::query_all)) or already rendered text. Fails with a
+/// [`Configuration`](ClaudeSDKError::Configuration) error for an empty
+/// transcript, and with a [`Process`](ClaudeSDKError::Process) error when
+/// the CLI reports the summary query as failed.
+pub async fn summarize_session(
+    transcript: impl Into<Transcript>,
+    options: SummaryOptions,
+) -> Result<SessionSummary> {
+    let transcript = transcript.into();
+    if transcript.is_empty() {
+        return Err(ClaudeSDKError::configuration(
+            "Cannot summarize an empty transcript",
+        ));
+    }
+
+    let (_, result) =
+        query_result(&options.prompt(&transcript), Some(options.query_options())).await?;
+    if result.is_error {
+        return Err(ClaudeSDKError::process(
+            None,
+            format!(
+                "Summary query failed: {}",
+                result.result.as_deref().unwrap_or(&result.subtype)
+            ),
+        ));
+    }
+    SessionSummary::from_result(&result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{AssistantMessage, TextBlock, ToolResultBlock, ToolUseBlock, UserMessage};
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::User(UserMessage::new(UserMessageContent::Text(
+                "List the files".to_string(),
+            ))),
+            Message::Assistant(AssistantMessage::new(
+                vec![
+                    ContentBlock::Thinking(crate::types::ThinkingBlock {
+                        thinking: "hmm".to_string(),
+                        signature: String::new(),
+                    }),
+                    ContentBlock::ToolUse(ToolUseBlock {
+                        id: "toolu_1".to_string(),
+                        name: "Bash".to_string(),
+                        input: serde_json::json!({"command": "ls"}),
+                    }),
+                ],
+                "m",
+            )),
+            Message::User(UserMessage::new(UserMessageContent::Blocks(vec![
+                ContentBlock::ToolResult(ToolResultBlock {
+                    tool_use_id: "toolu_1".to_string(),
+                    content: Some("x".repeat(600).into()),
+                    is_error: None,
+                    content_ref: None,
+                }),
+            ]))),
+            Message::Assistant(AssistantMessage::new(
+                vec![ContentBlock::Text(TextBlock {
+                    text: "There is one file.".to_string(),
+                })],
+                "m",
+            )),
+        ]
+    }
+
+    #[test]
+    fn test_transcript_from_messages() {
+        let transcript = Transcript::from(&conversation());
+        let lines: Vec<_> = transcript.as_str().lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "User: List the files");
+        assert_eq!(lines[1], r#"Assistant used Bash: {"command":"ls"}"#);
+        assert!(lines[2].starts_with("Tool result: xxx"));
+        assert!(lines[2].ends_with("[truncated]"));
+        assert_eq!(lines[3], "Assistant: There is one file.");
+    }
+
+    #[test]
+    fn test_prompt_keeps_the_end_of_long_transcripts() {
+        let transcript = Transcript::from("first line\nsecond line\nthird line");
+        let options = SummaryOptions::default()
+            .with_max_transcript_chars(15)
+            .with_instructions("Focus on bugs.");
+        let prompt = options.prompt(&transcript);
+        assert!(prompt.contains("Focus on bugs."));
+        assert!(prompt.contains("Only the end"));
+        assert!(prompt.contains("<transcript>\nthird line\n</transcript>"));
+
+        let query = options.query_options();
+        assert_eq!(query.model.as_deref(), Some(DEFAULT_SUMMARY_MODEL));
+        assert!(matches!(query.tools, Some(ToolsConfig::List(ref t)) if t.is_empty()));
+    }
+
+    #[test]
+    fn test_summary_to_prompt() {
+        let text = "```json\n{\"summary\": \"Listed files.\", \"key_points\": [\"One file\"]}\n```";
+        let result: ResultMessage = serde_json::from_value(serde_json::json!({
+            "subtype": "success",
+            "duration_ms": 0,
+            "duration_api_ms": 0,
+            "is_error": false,
+            "num_turns": 1,
+            "session_id": "s",
+            "result": text
+        }))
+        .unwrap();
+        let summary = SessionSummary::from_result(&result).unwrap();
+        assert!(summary.open_tasks.is_empty());
+        assert_eq!(
+            summary.to_prompt(),
+            "Summary of the previous session:\nListed files.\n\nKey points:\n- One file"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_transcript_is_rejected() {
+        let err = summarize_session("  ", SummaryOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClaudeSDKError::Configuration { .. }));
+    }
+}
@@ -0,0 +1,691 @@
+//! Test doubles for code built on the SDK.
+//!
+//! [`MockTransport`] implements [`Transport`] over a script of CLI frames,
+//! with faults injected at chosen points: delays, malformed JSON lines,
+//! mid-stream process death, a CLI that stops reading its input, and
+//! control responses that arrive late, out of order or not at all. Delays use `tokio::time`, so tests running with
+//! a paused clock (`#[tokio::test(start_paused = true)]`) are
+//! deterministic and don't actually wait.
+//!
+//! To run code that goes through [`query`](crate::query) or
+//! [`ClaudeClient`](crate::ClaudeClient) without the CLI, hand a
+//! `MockTransport` to [`query_with_transport`](crate::query_with_transport)
+//! or [`ClaudeClient::with_transport`](crate::ClaudeClient::with_transport),
+//! or use the echo model [`Model::mock`](crate::Model::mock).
+//!
+//! [`build_cli_args`] returns the command line the SDK would start the CLI
+//! with, and [`assert_flag`], [`assert_flag_value`] and [`assert_no_flag`]
+//! check it, to test that options are configured as intended:
+//!
+//! ```rust
+//! use claude_agents_sdk::testing::{assert_flag_value, assert_no_flag, build_cli_args};
+//! use claude_agents_sdk::ClaudeAgentOptions;
+//!
+//! let options = ClaudeAgentOptions::new()
+//!     .with_model("claude-sonnet-4-5")
+//!     .with_max_turns(3);
+//! let args = build_cli_args(&options).unwrap();
+//! assert_flag_value(&args, "--model", "claude-sonnet-4-5");
+//! assert_flag_value(&args, "--max-turns", "3");
+//! assert_no_flag(&args, "--permission-mode");
+//! ```
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::_internal::transport::Transport;
+//! use claude_agents_sdk::testing::MockTransport;
+//! use claude_agents_sdk::ClaudeSDKError;
+//! use serde_json::json;
+//! use std::time::Duration;
+//! use tokio_stream::StreamExt;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let transport = MockTransport::new(vec![json!({"type": "system", "subtype": "init"})])
+//!     .then_delay(Duration::from_millis(10))
+//!     .then_malformed_line("{\"type\": \"assistant\"")
+//!     .then_process_death(Some(137));
+//!
+//! let mut stream = transport.message_stream();
+//! assert!(stream.next().await.unwrap().is_ok());
+//! assert!(matches!(
+//!     stream.next().await,
+//!     Some(Err(ClaudeSDKError::JSONDecode { .. }))
+//! ));
+//! assert!(matches!(
+//!     stream.next().await,
+//!     Some(Err(ClaudeSDKError::Process { exit_code: Some(137), .. }))
+//! ));
+//! assert!(stream.next().await.is_none());
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tokio_stream::Stream;
+
+#[cfg(feature = "subprocess")]
+use crate::_internal::transport::SubprocessTransport;
+use crate::_internal::transport::Transport;
+use crate::errors::{ClaudeSDKError, Result};
+#[cfg(feature = "subprocess")]
+use crate::types::ClaudeAgentOptions;
+use crate::types::Model;
+
+/// Computes the `response` body of a control request, or `None` to leave
+/// it unanswered.
+type ControlResponder = Arc<dyn Fn(&Value) -> Option<Value> + Send + Sync>;
+
+/// Computes the frames answering a prompt.
+type PromptResponder = Arc<dyn Fn(&Value) -> Vec<Value> + Send + Sync>;
+
+/// One step of a [`MockTransport`] script.
+enum Step {
+    /// Deliver a frame.
+    Frame(Value),
+    /// Wait before the next step.
+    Delay(Duration),
+    /// Deliver a line that isn't valid JSON.
+    Malformed(String),
+    /// End the stream as if the CLI process exited.
+    Die(Option<i32>),
+}
+
+/// Order in which [`MockTransport`] delivers automatic control responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ControlResponseOrder {
+    /// Answer each control request as it is written (default).
+    #[default]
+    InOrder,
+    /// Hold responses until `batch` requests are waiting, then deliver
+    /// them newest first.
+    Reversed {
+        /// Number of responses held before delivery.
+        batch: usize,
+    },
+}
+
+#[derive(Default)]
+struct State {
+    /// Scripted steps not yet played.
+    script: VecDeque<Step>,
+    /// Control responses ready for delivery.
+    responses: VecDeque<Value>,
+    /// Control responses held back for reordering.
+    held: Vec<Value>,
+    /// Messages written to the transport.
+    written: Vec<String>,
+    /// Delayed control responses not yet queued.
+    in_flight: usize,
+    /// Whether input was ended or the transport closed.
+    input_closed: bool,
+    /// Whether the simulated process died.
+    dead: bool,
+}
+
+/// A scripted [`Transport`] with fault injection, for testing code that
+/// consumes CLI frames without a real CLI.
+pub struct MockTransport {
+    state: Arc<Mutex<State>>,
+    /// Wakes the message stream when a control response is queued or the
+    /// transport closes.
+    wake: Arc<Notify>,
+    connected: AtomicBool,
+    /// Delay before each delivered frame.
+    latency: Duration,
+    responder: Option<ControlResponder>,
+    prompt_responder: Option<PromptResponder>,
+    /// Whether writes wait until the transport closes instead of being
+    /// accepted.
+    input_blocked: bool,
+    /// Wakes blocked writes when the transport closes.
+    input_closed: Arc<Notify>,
+    response_delay: Duration,
+    response_order: ControlResponseOrder,
+}
+
+impl MockTransport {
+    /// Create a mock transport delivering `responses` in order.
+    ///
+    /// The message stream ends after the last scripted step, unless
+    /// control requests or prompts are answered (see
+    /// [`respond_to_control_requests`](Self::respond_to_control_requests)
+    /// and [`with_prompt_responder`](Self::with_prompt_responder)), in
+    /// which case it stays open until input is ended or the transport
+    /// is closed and every response has been delivered.
+    pub fn new(responses: Vec<Value>) -> Self {
+        let state = State {
+            script: responses.into_iter().map(Step::Frame).collect(),
+            ..Default::default()
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            wake: Arc::new(Notify::new()),
+            connected: AtomicBool::new(false),
+            latency: Duration::ZERO,
+            responder: None,
+            prompt_responder: None,
+            input_blocked: false,
+            input_closed: Arc::new(Notify::new()),
+            response_delay: Duration::ZERO,
+            response_order: ControlResponseOrder::InOrder,
+        }
+    }
+
+    /// Create a mock transport with a simple text response.
+    pub fn with_text_response(text: &str) -> Self {
+        Self::new(vec![
+            json!({
+                "type": "system",
+                "subtype": "init",
+                "data": {"session_id": "mock-session"}
+            }),
+            json!({
+                "type": "assistant",
+                "message": {
+                    "content": [{"type": "text", "text": text}],
+                    "model": "mock-model"
+                }
+            }),
+            json!({
+                "type": "result",
+                "subtype": "success",
+                "is_error": false,
+                "duration_ms": 100,
+                "duration_api_ms": 80,
+                "num_turns": 1,
+                "session_id": "mock-session",
+                "total_cost_usd": 0.001
+            }),
+        ])
+    }
+
+    /// Create a mock transport that simulates an error.
+    pub fn with_error_response(error_message: &str) -> Self {
+        Self::new(vec![
+            json!({
+                "type": "system",
+                "subtype": "init",
+                "data": {"session_id": "mock-session"}
+            }),
+            json!({
+                "type": "result",
+                "subtype": "error",
+                "is_error": true,
+                "duration_ms": 50,
+                "duration_api_ms": 40,
+                "num_turns": 0,
+                "session_id": "mock-session",
+                "result": error_message
+            }),
+        ])
+    }
+
+    /// Create a mock transport that simulates tool use.
+    pub fn with_tool_use(tool_name: &str, tool_input: Value) -> Self {
+        Self::new(vec![
+            json!({
+                "type": "system",
+                "subtype": "init",
+                "data": {"session_id": "mock-session"}
+            }),
+            json!({
+                "type": "assistant",
+                "message": {
+                    "content": [
+                        {"type": "text", "text": "Let me use a tool."},
+                        {
+                            "type": "tool_use",
+                            "id": "mock-tool-id",
+                            "name": tool_name,
+                            "input": tool_input
+                        }
+                    ],
+                    "model": "mock-model"
+                }
+            }),
+            json!({
+                "type": "result",
+                "subtype": "success",
+                "is_error": false,
+                "duration_ms": 200,
+                "duration_api_ms": 150,
+                "num_turns": 1,
+                "session_id": "mock-session"
+            }),
+        ])
+    }
+
+    /// Create a mock transport answering like the echo model
+    /// [`Model::mock`]: control requests succeed, and each prompt is
+    /// answered with an assistant message repeating its text and a
+    /// successful result that cost nothing.
+    ///
+    /// With an `initial_prompt` (non-streaming mode) it answers that prompt
+    /// and ends, as `claude --print` does.
+    pub(crate) fn echo_model(initial_prompt: Option<&str>) -> Self {
+        let session_id = format!("echo-{}", uuid::Uuid::new_v4());
+        if let Some(prompt) = initial_prompt {
+            return Self::new(echo_turn(&session_id, 1, prompt));
+        }
+        let turns = AtomicU64::new(0);
+        Self::new(vec![])
+            .with_control_responder(|request| {
+                Some(match request.get("subtype").and_then(Value::as_str) {
+                    Some("initialize") => json!({
+                        "commands": [],
+                        "output_style": "default",
+                        "available_output_styles": ["default"],
+                    }),
+                    Some("mcp_status") => json!({"mcpServers": []}),
+                    _ => json!({}),
+                })
+            })
+            .with_prompt_responder(move |frame| {
+                let turn = turns.fetch_add(1, Ordering::SeqCst) + 1;
+                echo_turn(
+                    &session_id,
+                    turn,
+                    &prompt_text(&frame["message"]["content"]),
+                )
+            })
+    }
+
+    fn then(self, step: Step) -> Self {
+        self.lock().script.push_back(step);
+        self
+    }
+
+    /// Append a frame to the script.
+    pub fn then_frame(self, frame: Value) -> Self {
+        self.then(Step::Frame(frame))
+    }
+
+    /// Pause the script for `delay` before its next step.
+    pub fn then_delay(self, delay: Duration) -> Self {
+        self.then(Step::Delay(delay))
+    }
+
+    /// Deliver `line` as if the CLI printed it; it should not be valid
+    /// JSON, and surfaces as a [`JSONDecode`](ClaudeSDKError::JSONDecode)
+    /// error like a real garbled line.
+    pub fn then_malformed_line(self, line: impl Into<String>) -> Self {
+        self.then(Step::Malformed(line.into()))
+    }
+
+    /// Simulate the CLI process exiting with `exit_code` at this point.
+    ///
+    /// The stream yields a [`Process`](ClaudeSDKError::Process) error and
+    /// ends, the transport stops being ready, and further writes fail.
+    pub fn then_process_death(self, exit_code: Option<i32>) -> Self {
+        self.then(Step::Die(exit_code))
+    }
+
+    /// Wait `latency` before delivering each frame.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Answer every control request written to the transport with an
+    /// empty success response.
+    pub fn respond_to_control_requests(self) -> Self {
+        self.with_control_responder(|_| Some(json!({})))
+    }
+
+    /// Answer control requests with the body computed by `responder` from
+    /// the request payload; returning `None` leaves the request
+    /// unanswered, for exercising timeouts.
+    pub fn with_control_responder<F>(mut self, responder: F) -> Self
+    where
+        F: Fn(&Value) -> Option<Value> + Send + Sync + 'static,
+    {
+        self.responder = Some(Arc::new(responder));
+        self
+    }
+
+    /// Answer each prompt written to the transport with the frames computed
+    /// by `responder` from its `user` frame, delivered like control
+    /// responses; the message stream stays open until input is ended.
+    pub fn with_prompt_responder<F>(mut self, responder: F) -> Self
+    where
+        F: Fn(&Value) -> Vec<Value> + Send + Sync + 'static,
+    {
+        self.prompt_responder = Some(Arc::new(responder));
+        self
+    }
+
+    /// Simulate a CLI that stops reading its input: writes never complete,
+    /// as when the pipe to the CLI is full, until the transport is closed,
+    /// when they fail.
+    pub fn with_blocked_input(mut self) -> Self {
+        self.input_blocked = true;
+        self
+    }
+
+    /// Deliver each control response `delay` after its request was written.
+    pub fn with_control_response_delay(mut self, delay: Duration) -> Self {
+        self.response_delay = delay;
+        self
+    }
+
+    /// Set the order in which control responses are delivered.
+    pub fn with_control_response_order(mut self, order: ControlResponseOrder) -> Self {
+        self.response_order = order;
+        self
+    }
+
+    /// Get messages that were written to the transport.
+    pub fn get_written_messages(&self) -> Vec<String> {
+        self.lock().written.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Build the control response for a written `control_request` frame.
+    fn control_response(&self, frame: &Value) -> Option<Value> {
+        let responder = self.responder.as_ref()?;
+        if frame.get("type").and_then(Value::as_str) != Some("control_request") {
+            return None;
+        }
+        let request_id = frame.get("request_id")?.clone();
+        let body = responder(frame.get("request").unwrap_or(&Value::Null))?;
+        Some(json!({
+            "type": "control_response",
+            "response": {
+                "subtype": "success",
+                "request_id": request_id,
+                "response": body
+            }
+        }))
+    }
+
+    /// Queue `response` for delivery, respecting the configured order.
+    fn enqueue(state: &Mutex<State>, wake: &Notify, order: ControlResponseOrder, response: Value) {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        match order {
+            ControlResponseOrder::InOrder => state.responses.push_back(response),
+            ControlResponseOrder::Reversed { batch } => {
+                state.held.push(response);
+                if state.held.len() >= batch {
+                    let held = std::mem::take(&mut state.held);
+                    state.responses.extend(held.into_iter().rev());
+                }
+            }
+        }
+        wake.notify_one();
+    }
+}
+
+/// The echo model's answer to `prompt`, announcing the session first if
+/// it's the first turn.
+fn echo_turn(session_id: &str, turn: u64, prompt: &str) -> Vec<Value> {
+    let mut frames = Vec::new();
+    if turn == 1 {
+        frames.push(json!({
+            "type": "system",
+            "subtype": "init",
+            "session_id": session_id,
+            "model": Model::MOCK,
+            "tools": [],
+            "mcp_servers": [],
+            "permissionMode": "default",
+        }));
+    }
+    let usage = json!({"input_tokens": 0, "output_tokens": 0});
+    frames.push(json!({
+        "type": "assistant",
+        "message": {
+            "id": format!("msg_echo_{}", turn),
+            "type": "message",
+            "role": "assistant",
+            "model": Model::MOCK,
+            "content": [{"type": "text", "text": prompt}],
+            "stop_reason": "end_turn",
+            "usage": usage,
+        },
+        "parent_tool_use_id": null,
+        "session_id": session_id,
+    }));
+    frames.push(json!({
+        "type": "result",
+        "subtype": "success",
+        "duration_ms": 0,
+        "duration_api_ms": 0,
+        "is_error": false,
+        "num_turns": 1,
+        "session_id": session_id,
+        "total_cost_usd": 0.0,
+        "usage": usage,
+        "result": prompt,
+    }));
+    frames
+}
+
+/// Text of a user message's content, its text blocks joined by newlines.
+fn prompt_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// What the message stream does next.
+enum Next {
+    Step(Step),
+    Wait,
+    End,
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn connect(&mut self) -> Result<()> {
+        self.connected.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn write(&self, data: &str) -> Result<()> {
+        loop {
+            let closed = self.input_closed.notified();
+            {
+                let mut state = self.lock();
+                if state.dead || state.input_closed {
+                    return Err(ClaudeSDKError::cli_connection(if state.dead {
+                        "CLI process has exited"
+                    } else {
+                        "CLI input has been closed"
+                    }));
+                }
+                if !self.input_blocked {
+                    state.written.push(data.to_string());
+                    break;
+                }
+            }
+            closed.await;
+        }
+
+        let Ok(frame) = serde_json::from_str::<Value>(data) else {
+            return Ok(());
+        };
+        if let Some(responder) = self
+            .prompt_responder
+            .as_ref()
+            .filter(|_| frame.get("type").and_then(Value::as_str) == Some("user"))
+        {
+            let frames = responder(&frame);
+            self.lock().responses.extend(frames);
+            self.wake.notify_one();
+            return Ok(());
+        }
+        let Some(response) = self.control_response(&frame) else {
+            return Ok(());
+        };
+        if self.response_delay.is_zero() {
+            Self::enqueue(&self.state, &self.wake, self.response_order, response);
+        } else {
+            let (state, wake) = (Arc::clone(&self.state), Arc::clone(&self.wake));
+            let (delay, order) = (self.response_delay, self.response_order);
+            self.lock().in_flight += 1;
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                state.lock().unwrap_or_else(|e| e.into_inner()).in_flight -= 1;
+                Self::enqueue(&state, &wake, order, response);
+            });
+        }
+        Ok(())
+    }
+
+    fn message_stream(&self) -> Pin<Box<dyn Stream<Item = Result<Value>> + Send + '_>> {
+        Box::pin(futures::stream::unfold(false, move |ended| async move {
+            if ended {
+                return None;
+            }
+            loop {
+                let next = {
+                    let mut state = self.lock();
+                    if state.dead {
+                        Next::End
+                    } else if let Some(response) = state.responses.pop_front() {
+                        Next::Step(Step::Frame(response))
+                    } else if let Some(step) = state.script.pop_front() {
+                        Next::Step(step)
+                    } else if ((self.responder.is_some() || self.prompt_responder.is_some())
+                        && !state.input_closed)
+                        || state.in_flight > 0
+                    {
+                        Next::Wait
+                    } else {
+                        Next::End
+                    }
+                };
+                match next {
+                    Next::End => return None,
+                    Next::Wait => self.wake.notified().await,
+                    Next::Step(Step::Delay(delay)) => tokio::time::sleep(delay).await,
+                    Next::Step(Step::Frame(frame)) => {
+                        tokio::time::sleep(self.latency).await;
+                        return Some((Ok(frame), false));
+                    }
+                    Next::Step(Step::Malformed(line)) => {
+                        tokio::time::sleep(self.latency).await;
+                        let error = match serde_json::from_str::<Value>(&line) {
+                            Err(e) => ClaudeSDKError::json_decode_with_context(
+                                "Failed to parse JSON from CLI",
+                                Some(line),
+                                None,
+                                e,
+                            ),
+                            Ok(_) => ClaudeSDKError::json_decode("Malformed line from CLI"),
+                        };
+                        return Some((Err(error), false));
+                    }
+                    Next::Step(Step::Die(exit_code)) => {
+                        self.lock().dead = true;
+                        self.input_closed.notify_waiters();
+                        self.connected.store(false, Ordering::SeqCst);
+                        let error =
+                            ClaudeSDKError::process(exit_code, "CLI process exited unexpectedly");
+                        return Some((Err(error), true));
+                    }
+                }
+            }
+        }))
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.end_input().await?;
+        self.connected.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Stop answering control requests; responses held for reordering are
+    /// released, newest first.
+    async fn end_input(&self) -> Result<()> {
+        let mut state = self.lock();
+        state.input_closed = true;
+        let held = std::mem::take(&mut state.held);
+        state.responses.extend(held.into_iter().rev());
+        self.wake.notify_one();
+        self.input_closed.notify_waiters();
+        Ok(())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.connected.load(Ordering::SeqCst) && !self.lock().dead
+    }
+}
+
+/// The arguments the SDK starts the CLI with for `options`, in streaming
+/// mode as [`ClaudeClient`](crate::ClaudeClient) runs it, without the path
+/// of the CLI itself.
+///
+/// `${VAR}` references are expanded if
+/// [`expand_env`](crate::ClaudeAgentOptions::expand_env) is set. When
+/// connecting, flags the installed CLI doesn't support may still be dropped
+/// (see [`unsupported_flags`](crate::ClaudeAgentOptions::unsupported_flags)).
+#[cfg(feature = "subprocess")]
+#[cfg_attr(docsrs, doc(cfg(feature = "subprocess")))]
+pub fn build_cli_args(options: &ClaudeAgentOptions) -> Result<Vec<String>> {
+    SubprocessTransport::cli_args(options)
+}
+
+/// Values following each occurrence of `flag` in `args`, in order.
+pub fn flag_values<'a>(args: &'a [String], flag: &str) -> Vec<&'a str> {
+    args.windows(2)
+        .filter(|pair| pair[0] == flag)
+        .map(|pair| pair[1].as_str())
+        .collect()
+}
+
+/// Assert that `args` contain `flag`.
+#[track_caller]
+pub fn assert_flag(args: &[String], flag: &str) {
+    assert!(
+        args.iter().any(|arg| arg == flag),
+        "expected flag {} in {:?}",
+        flag,
+        args
+    );
+}
+
+/// Assert that `flag` is given `value` in `args`, in any of its
+/// occurrences.
+#[track_caller]
+pub fn assert_flag_value(args: &[String], flag: &str, value: &str) {
+    let values = flag_values(args, flag);
+    assert!(
+        values.contains(&value),
+        "expected {} {:?}, got {:?} in {:?}",
+        flag,
+        value,
+        values,
+        args
+    );
+}
+
+/// Assert that `args` don't contain `flag`.
+#[track_caller]
+pub fn assert_no_flag(args: &[String], flag: &str) {
+    assert!(
+        !args.iter().any(|arg| arg == flag),
+        "unexpected flag {} in {:?}",
+        flag,
+        args
+    );
+}
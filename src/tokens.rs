@@ -0,0 +1,349 @@
+//! Approximate token counting for prompt sizing and cost previews.
+//!
+//! The estimator mimics how BPE tokenizers split text — short words are a
+//! single token, long words are split into ~4-character pieces, punctuation
+//! is a token of its own, and non-Latin scripts cost roughly one token per
+//! character. It tends to overestimate slightly, which is the safe side for
+//! deciding whether to chunk an input before sending it.
+//!
+//! Requires the `tokens` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::tokens::estimate_tokens;
+//! use claude_agents_sdk::ClaudeAgentOptions;
+//!
+//! let document = "A long document. ".repeat(10);
+//! assert!(estimate_tokens(&document) > 0);
+//!
+//! let options = ClaudeAgentOptions::new();
+//! assert!(options.fits_context(&document));
+//! ```
+//!
+//! Estimate what a run will cost before sending it, e.g. to ask the user for
+//! confirmation:
+//!
+//! ```rust
+//! use claude_agents_sdk::tokens::estimate_cost;
+//! use claude_agents_sdk::ClaudeAgentOptions;
+//!
+//! let options = ClaudeAgentOptions::new().with_model("claude-sonnet-4-5");
+//! let estimate = estimate_cost("Summarize this file", &options);
+//! assert!(estimate.min_usd <= estimate.max_usd);
+//!
+//! // Fails if the upper bound exceeds the remaining budget
+//! let remaining_usd = 1.0;
+//! estimate.check_budget(remaining_usd)?;
+//! # Ok::<(), claude_agents_sdk::ClaudeSDKError>(())
+//! ```
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{ClaudeAgentOptions, SystemPromptConfig};
+
+/// Context window assumed for models without a known larger window.
+pub const DEFAULT_CONTEXT_WINDOW: usize = 200_000;
+
+/// Context window of models using the extended (`[1m]`) context.
+pub const EXTENDED_CONTEXT_WINDOW: usize = 1_000_000;
+
+/// Tokens kept free for the system prompt, tools and the response.
+const RESERVED_TOKENS: usize = 16_000;
+
+/// Characters per token for Latin-script words.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Longest word that is assumed to be a single token.
+const MAX_SINGLE_TOKEN_WORD: usize = 6;
+
+fn word_tokens(len: usize) -> usize {
+    match len {
+        0 => 0,
+        1..=MAX_SINGLE_TOKEN_WORD => 1,
+        _ => len.div_ceil(CHARS_PER_TOKEN),
+    }
+}
+
+/// Estimate the number of tokens in `text`.
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut word_len: usize = 0;
+
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            word_len += 1;
+            continue;
+        }
+        tokens += word_tokens(word_len);
+        word_len = 0;
+
+        if c.is_whitespace() {
+            // Whitespace is merged into the following token.
+        } else if c.is_ascii() || c.is_alphabetic() {
+            tokens += 1;
+        } else {
+            // Emoji and other symbols usually span several byte-level tokens.
+            tokens += c.len_utf8().div_ceil(2);
+        }
+    }
+
+    tokens + word_tokens(word_len)
+}
+
+/// Get the context window size, in tokens, for a model name.
+pub fn context_window(model: Option<&str>) -> usize {
+    match model {
+        Some(model) if model.ends_with("[1m]") => EXTENDED_CONTEXT_WINDOW,
+        _ => DEFAULT_CONTEXT_WINDOW,
+    }
+}
+
+impl ClaudeAgentOptions {
+    /// Estimate how many prompt tokens fit in the configured model's context.
+    ///
+    /// Accounts for a text system prompt and a fixed reserve for tools and
+    /// the response.
+    pub fn context_budget(&self) -> usize {
+        let system = match self.system_prompt {
+            Some(SystemPromptConfig::Text(ref text)) => estimate_tokens(text),
+            _ => 0,
+        };
+        context_window(self.model.as_deref()).saturating_sub(RESERVED_TOKENS + system)
+    }
+
+    /// Check whether `prompt` is expected to fit in the model's context.
+    pub fn fits_context(&self, prompt: &str) -> bool {
+        estimate_tokens(prompt) <= self.context_budget()
+    }
+}
+
+/// Output tokens assumed for the shortest responses.
+const MIN_OUTPUT_TOKENS: usize = 256;
+
+/// Output tokens assumed for the longest single responses, excluding
+/// extended thinking.
+const MAX_OUTPUT_TOKENS: usize = 8_192;
+
+/// Price of a model, in USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    /// USD per million input tokens.
+    pub input_per_mtok: f64,
+    /// USD per million output tokens.
+    pub output_per_mtok: f64,
+}
+
+impl ModelPrice {
+    /// Create a price from USD per million input and output tokens.
+    pub fn new(input_per_mtok: f64, output_per_mtok: f64) -> Self {
+        Self {
+            input_per_mtok,
+            output_per_mtok,
+        }
+    }
+
+    fn cost(&self, input_tokens: usize, output_tokens: usize) -> f64 {
+        (input_tokens as f64 * self.input_per_mtok + output_tokens as f64 * self.output_per_mtok)
+            / 1_000_000.0
+    }
+}
+
+/// Prices per model, matched by substring of the model name.
+///
+/// The default table holds list prices at the time of release; override
+/// entries with [`with_price`](Self::with_price) when they change or for
+/// negotiated rates.
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    /// Entries checked in order; the first whose pattern occurs in the model
+    /// name wins.
+    entries: Vec<(String, ModelPrice)>,
+    /// Price used for unknown or unset models.
+    fallback: ModelPrice,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        let entries = [
+            ("opus-4-5", ModelPrice::new(5.0, 25.0)),
+            ("opus", ModelPrice::new(15.0, 75.0)),
+            ("sonnet", ModelPrice::new(3.0, 15.0)),
+            ("haiku-4-5", ModelPrice::new(1.0, 5.0)),
+            ("haiku", ModelPrice::new(0.8, 4.0)),
+        ];
+        Self {
+            entries: entries
+                .into_iter()
+                .map(|(pattern, price)| (pattern.to_string(), price))
+                .collect(),
+            fallback: ModelPrice::new(3.0, 15.0),
+        }
+    }
+}
+
+impl PriceTable {
+    /// Set the price for models whose name contains `pattern`, taking
+    /// precedence over existing entries.
+    pub fn with_price(mut self, pattern: impl Into<String>, price: ModelPrice) -> Self {
+        self.entries.insert(0, (pattern.into(), price));
+        self
+    }
+
+    /// Set the price used for unknown or unset models.
+    pub fn with_fallback(mut self, price: ModelPrice) -> Self {
+        self.fallback = price;
+        self
+    }
+
+    /// Get the price for a model name.
+    pub fn price_for(&self, model: Option<&str>) -> ModelPrice {
+        model
+            .and_then(|model| {
+                self.entries
+                    .iter()
+                    .find(|(pattern, _)| model.contains(pattern.as_str()))
+            })
+            .map(|(_, price)| *price)
+            .unwrap_or(self.fallback)
+    }
+}
+
+/// Rough cost range of sending a prompt, from [`estimate_cost`].
+///
+/// Covers a single turn: the prompt and system prompt as input, and a
+/// response between a short answer and a long one with the configured
+/// thinking budget. Agentic runs that use tools take several turns and
+/// can cost more than `max_usd`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostEstimate {
+    /// Estimated input tokens.
+    pub input_tokens: usize,
+    /// Output tokens assumed for the lower bound.
+    pub min_output_tokens: usize,
+    /// Output tokens assumed for the upper bound.
+    pub max_output_tokens: usize,
+    /// Lower bound, in USD.
+    pub min_usd: f64,
+    /// Upper bound, in USD.
+    pub max_usd: f64,
+}
+
+impl CostEstimate {
+    /// Check that the upper bound fits in `remaining_usd`.
+    ///
+    /// Returns a configuration error otherwise.
+    pub fn check_budget(&self, remaining_usd: f64) -> Result<()> {
+        if self.max_usd > remaining_usd {
+            return Err(ClaudeSDKError::configuration(format!(
+                "Estimated cost of up to ${:.4} exceeds the remaining budget of ${:.4}",
+                self.max_usd, remaining_usd
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Estimate the cost of sending `prompt` with `options`, using the default
+/// [`PriceTable`].
+pub fn estimate_cost(prompt: &str, options: &ClaudeAgentOptions) -> CostEstimate {
+    estimate_cost_with(prompt, options, &PriceTable::default())
+}
+
+/// Estimate the cost of sending `prompt` with `options` using `prices`.
+pub fn estimate_cost_with(
+    prompt: &str,
+    options: &ClaudeAgentOptions,
+    prices: &PriceTable,
+) -> CostEstimate {
+    let system = match options.system_prompt {
+        Some(SystemPromptConfig::Text(ref text)) => estimate_tokens(text),
+        _ => 0,
+    };
+    let input_tokens = estimate_tokens(prompt) + system;
+    let max_output_tokens =
+        MAX_OUTPUT_TOKENS + options.max_thinking_tokens.unwrap_or_default() as usize;
+    let price = prices.price_for(options.model.as_deref());
+
+    CostEstimate {
+        input_tokens,
+        min_output_tokens: MIN_OUTPUT_TOKENS,
+        max_output_tokens,
+        min_usd: price.cost(input_tokens, MIN_OUTPUT_TOKENS),
+        max_usd: price.cost(input_tokens, max_output_tokens),
+    }
+}
+
+impl ClaudeAgentOptions {
+    /// Estimate the cost of sending `prompt` and check it against
+    /// [`max_budget_usd`](Self::max_budget_usd), if set.
+    pub fn check_estimated_cost(&self, prompt: &str) -> Result<CostEstimate> {
+        let estimate = estimate_cost(prompt, self);
+        if let Some(budget) = self.max_budget_usd {
+            estimate.check_budget(budget)?;
+        }
+        Ok(estimate)
+    }
+}
+
+/// Log a warning if `prompt` will obviously not fit in the context window.
+pub(crate) fn warn_if_oversized(prompt: &str, options: &ClaudeAgentOptions) {
+    let estimated = estimate_tokens(prompt);
+    let budget = options.context_budget();
+    if estimated > budget {
+        tracing::warn!(
+            "Prompt is ~{} tokens, which exceeds the ~{} tokens available in the context window; \
+             consider splitting the input",
+            estimated,
+            budget
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("Hello, world!"), 4);
+        assert_eq!(estimate_tokens("internationalization"), 5);
+        assert_eq!(estimate_tokens("日本語"), 3);
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let options = ClaudeAgentOptions::new().with_model("claude-opus-4-1");
+        let estimate = estimate_cost("Hello, world!", &options);
+        assert_eq!(estimate.input_tokens, 4);
+        assert!((estimate.max_usd - (4.0 * 15.0 + 8_192.0 * 75.0) / 1e6).abs() < 1e-9);
+
+        let prices = PriceTable::default().with_price("opus", ModelPrice::new(0.0, 0.0));
+        assert_eq!(estimate_cost_with("Hello", &options, &prices).max_usd, 0.0);
+        assert_eq!(
+            prices.price_for(Some("claude-haiku-4-5")),
+            ModelPrice::new(1.0, 5.0)
+        );
+        assert_eq!(prices.price_for(None), ModelPrice::new(3.0, 15.0));
+
+        assert!(estimate.check_budget(1.0).is_ok());
+        assert!(estimate.check_budget(0.01).is_err());
+        let options = ClaudeAgentOptions {
+            max_budget_usd: Some(0.01),
+            ..options
+        };
+        assert!(options.check_estimated_cost("Hello").is_err());
+    }
+
+    #[test]
+    fn test_fits_context() {
+        let options = ClaudeAgentOptions::new();
+        assert!(options.fits_context("What is 2 + 2?"));
+
+        let huge = "word ".repeat(DEFAULT_CONTEXT_WINDOW);
+        assert!(!options.fits_context(&huge));
+        assert!(options
+            .with_model("claude-sonnet-4-5[1m]")
+            .fits_context(&huge));
+    }
+}
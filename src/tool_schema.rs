@@ -0,0 +1,492 @@
+//! JSON Schemas of tool inputs, and validation of tool calls against them.
+//!
+//! A [`ToolSchemaRegistry`] maps tool names to the JSON Schema of their
+//! input. [`ToolSchemaRegistry::builtin`] covers the CLI's built-in tools;
+//! custom tools (MCP or SDK tools) can be registered alongside. With
+//! [`ClaudeAgentOptions::tool_schemas`](crate::ClaudeAgentOptions::tool_schemas)
+//! set, every permission request is validated before it reaches the
+//! [`can_use_tool`](crate::ClaudeAgentOptions::can_use_tool) callback, which
+//! receives the [`ToolInputValidation`] report in its
+//! [`ToolPermissionContext`](crate::ToolPermissionContext). With
+//! [`ToolSchemaRegistry::deny_invalid`], every tool call is validated in a
+//! `PreToolUse` hook instead, and invalid ones are denied.
+//!
+//! Validation supports the subset of JSON Schema tool schemas use in
+//! practice: `type`, `enum`, `required`, `properties`,
+//! `additionalProperties: false`, `items`, `minimum` and `maximum`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::ToolSchemaRegistry;
+//! use serde_json::json;
+//!
+//! let registry = ToolSchemaRegistry::builtin().with_schema(
+//!     "mcp__db__query",
+//!     json!({
+//!         "type": "object",
+//!         "properties": {"sql": {"type": "string"}},
+//!         "required": ["sql"],
+//!         "additionalProperties": false
+//!     }),
+//! );
+//!
+//! let report = registry.validate("Bash", &json!({"command": 42}));
+//! assert!(!report.is_valid());
+//! assert_eq!(report.errors[0].path, "/command");
+//!
+//! assert!(registry.validate("mcp__db__query", &json!({"sql": "SELECT 1"})).is_valid());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::hooks::{deny_tool_use, HookRegistry, PreToolUse};
+
+/// A problem found in a tool input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolInputError {
+    /// JSON Pointer to the offending value (`""` for the whole input).
+    pub path: String,
+    /// What is wrong with it.
+    pub message: String,
+}
+
+impl fmt::Display for ToolInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Result of validating a tool input against its schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolInputValidation {
+    /// Name of the tool.
+    pub tool_name: String,
+    /// Whether the registry has a schema for the tool. Inputs of unknown
+    /// tools are not checked and count as valid.
+    pub schema_known: bool,
+    /// Problems found, empty if the input is valid.
+    pub errors: Vec<ToolInputError>,
+}
+
+impl ToolInputValidation {
+    /// Whether the input matched the schema (or no schema is known).
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Describe the problems found, one per line.
+    pub fn summary(&self) -> String {
+        self.errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Registry of tool input schemas.
+#[derive(Debug, Clone, Default)]
+pub struct ToolSchemaRegistry {
+    schemas: HashMap<String, Value>,
+    deny_invalid: bool,
+}
+
+impl ToolSchemaRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the schemas of the CLI's built-in tools.
+    ///
+    /// The built-in schemas list the fields the SDK knows about without
+    /// forbidding others, so CLI releases adding optional fields don't
+    /// fail validation.
+    pub fn builtin() -> Self {
+        let string = json!({"type": "string"});
+        let integer = json!({"type": "integer", "minimum": 0});
+        let boolean = json!({"type": "boolean"});
+        let strings = json!({"type": "array", "items": {"type": "string"}});
+        let object = |properties: Value, required: &[&str]| json!({"type": "object", "properties": properties, "required": required});
+
+        let mut registry = Self::new();
+        registry.register(
+            "Bash",
+            object(
+                json!({
+                    "command": string,
+                    "timeout": {"type": "number", "minimum": 0, "maximum": 600000},
+                    "description": string,
+                    "run_in_background": boolean,
+                }),
+                &["command"],
+            ),
+        );
+        registry.register(
+            "Read",
+            object(
+                json!({"file_path": string, "offset": integer, "limit": integer}),
+                &["file_path"],
+            ),
+        );
+        registry.register(
+            "Write",
+            object(
+                json!({"file_path": string, "content": string}),
+                &["file_path", "content"],
+            ),
+        );
+        registry.register(
+            "Edit",
+            object(
+                json!({
+                    "file_path": string,
+                    "old_string": string,
+                    "new_string": string,
+                    "replace_all": boolean,
+                }),
+                &["file_path", "old_string", "new_string"],
+            ),
+        );
+        registry.register(
+            "MultiEdit",
+            object(
+                json!({
+                    "file_path": string,
+                    "edits": {
+                        "type": "array",
+                        "items": object(
+                            json!({"old_string": string, "new_string": string, "replace_all": boolean}),
+                            &["old_string", "new_string"],
+                        ),
+                    },
+                }),
+                &["file_path", "edits"],
+            ),
+        );
+        registry.register(
+            "Glob",
+            object(json!({"pattern": string, "path": string}), &["pattern"]),
+        );
+        registry.register(
+            "Grep",
+            object(
+                json!({
+                    "pattern": string,
+                    "path": string,
+                    "glob": string,
+                    "type": string,
+                    "output_mode": {"enum": ["content", "files_with_matches", "count"]},
+                    "-i": boolean,
+                    "-n": boolean,
+                    "multiline": boolean,
+                    "head_limit": integer,
+                }),
+                &["pattern"],
+            ),
+        );
+        registry.register(
+            "NotebookEdit",
+            object(
+                json!({
+                    "notebook_path": string,
+                    "new_source": string,
+                    "cell_id": string,
+                    "cell_type": {"enum": ["code", "markdown"]},
+                    "edit_mode": {"enum": ["replace", "insert", "delete"]},
+                }),
+                &["notebook_path", "new_source"],
+            ),
+        );
+        registry.register(
+            "WebFetch",
+            object(json!({"url": string, "prompt": string}), &["url", "prompt"]),
+        );
+        registry.register(
+            "WebSearch",
+            object(
+                json!({"query": string, "allowed_domains": strings, "blocked_domains": strings}),
+                &["query"],
+            ),
+        );
+        registry.register(
+            "Task",
+            object(
+                json!({"description": string, "prompt": string, "subagent_type": string}),
+                &["description", "prompt", "subagent_type"],
+            ),
+        );
+        registry.register(
+            "TodoWrite",
+            object(
+                json!({"todos": {"type": "array", "items": {"type": "object"}}}),
+                &["todos"],
+            ),
+        );
+        registry
+    }
+
+    /// Register the input schema of `tool`, replacing any existing one.
+    pub fn register(&mut self, tool: impl Into<String>, schema: Value) {
+        self.schemas.insert(tool.into(), schema);
+    }
+
+    /// Register the input schema of `tool` (builder form of
+    /// [`register`](Self::register)).
+    pub fn with_schema(mut self, tool: impl Into<String>, schema: Value) -> Self {
+        self.register(tool, schema);
+        self
+    }
+
+    /// Deny tool calls with invalid inputs, telling the model what was
+    /// wrong.
+    ///
+    /// Enforced by a `PreToolUse` hook, so it covers every tool call
+    /// regardless of the permission mode or `allowed_tools`.
+    pub fn deny_invalid(mut self) -> Self {
+        self.deny_invalid = true;
+        self
+    }
+
+    /// Whether invalid inputs are denied.
+    pub fn denies_invalid(&self) -> bool {
+        self.deny_invalid
+    }
+
+    /// Get the input schema of `tool`.
+    pub fn schema(&self, tool: &str) -> Option<&Value> {
+        self.schemas.get(tool)
+    }
+
+    /// Validate a tool input against the tool's schema.
+    pub fn validate(&self, tool: &str, input: &Value) -> ToolInputValidation {
+        let mut errors = Vec::new();
+        let schema = self.schemas.get(tool);
+        if let Some(schema) = schema {
+            check(schema, input, "", &mut errors);
+        }
+        ToolInputValidation {
+            tool_name: tool.to_string(),
+            schema_known: schema.is_some(),
+            errors,
+        }
+    }
+
+    /// Hooks denying calls whose input doesn't match their tool's schema.
+    pub fn hooks(&self) -> HookRegistry {
+        let registry = self.clone();
+        HookRegistry::new().on(PreToolUse, move |input| {
+            let report = registry.validate(&input.tool_name, &input.tool_input);
+            let output = if report.is_valid() {
+                Default::default()
+            } else {
+                deny_tool_use(format!(
+                    "Invalid input for {}:\n{}",
+                    input.tool_name,
+                    report.summary()
+                ))
+            };
+            async move { output }
+        })
+    }
+}
+
+/// JSON Schema type name of a value.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_u64() || n.is_i64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Record a problem at `path`.
+fn push(errors: &mut Vec<ToolInputError>, path: &str, message: String) {
+    errors.push(ToolInputError {
+        path: path.to_string(),
+        message,
+    });
+}
+
+/// Check `value` against `schema`, appending problems to `errors`.
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<ToolInputError>) {
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        let actual = type_name(value);
+        if !types
+            .iter()
+            .any(|t| *t == actual || (*t == "number" && actual == "integer"))
+        {
+            push(
+                errors,
+                path,
+                format!("expected {}, got {}", types.join(" or "), actual),
+            );
+            // Nested keywords don't apply to a value of the wrong type
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            push(
+                errors,
+                path,
+                format!("expected one of {}", allowed.join(", ")),
+            );
+        }
+    }
+    if let Some(number) = value.as_f64() {
+        if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+            if number < minimum {
+                push(errors, path, format!("must be at least {}", minimum));
+            }
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+            if number > maximum {
+                push(errors, path, format!("must be at most {}", maximum));
+            }
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    push(errors, path, format!("missing required field '{}'", key));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+        for (key, field) in object {
+            let field_path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+            match properties.and_then(|p| p.get(key)) {
+                Some(property) => check(property, field, &field_path, errors),
+                None if closed => push(errors, &field_path, "unexpected field".to_string()),
+                None => {}
+            }
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in array.iter().enumerate() {
+            check(items, item, &format!("{}/{}", path, i), errors);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_schemas() {
+        let registry = ToolSchemaRegistry::builtin();
+
+        let valid = registry.validate(
+            "Edit",
+            &json!({"file_path": "a.rs", "old_string": "a", "new_string": "b"}),
+        );
+        assert!(valid.is_valid());
+        assert!(valid.schema_known);
+
+        let report = registry.validate(
+            "Grep",
+            &json!({"path": ".", "output_mode": "lines", "head_limit": -1}),
+        );
+        let paths: Vec<&str> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["", "/head_limit", "/output_mode"]);
+        assert!(report
+            .summary()
+            .contains("missing required field 'pattern'"));
+
+        let report = registry.validate(
+            "MultiEdit",
+            &json!({"file_path": "a.rs", "edits": [{"old_string": "a", "new_string": 1}]}),
+        );
+        assert_eq!(report.errors[0].path, "/edits/0/new_string");
+        assert_eq!(report.errors[0].message, "expected string, got integer");
+
+        // Unknown tools and unknown fields of built-in tools pass
+        assert!(!registry.validate("Unknown", &json!(null)).schema_known);
+        assert!(registry
+            .validate("Bash", &json!({"command": "ls", "sandbox": true}))
+            .is_valid());
+    }
+
+    #[test]
+    fn test_closed_custom_schema() {
+        let registry = ToolSchemaRegistry::new().with_schema(
+            "lookup",
+            json!({
+                "type": "object",
+                "properties": {"id": {"type": ["string", "integer"]}},
+                "additionalProperties": false
+            }),
+        );
+        assert!(registry.validate("lookup", &json!({"id": 7})).is_valid());
+        let report = registry.validate("lookup", &json!({"id": 7, "a/b": 1}));
+        assert_eq!(report.errors[0].path, "/a~1b");
+        assert_eq!(report.errors[0].message, "unexpected field");
+    }
+
+    #[tokio::test]
+    async fn test_hook_denies_invalid_input() {
+        use crate::types::{
+            HookEvent, HookInput, HookOutput, HookSpecificOutput, PermissionBehavior,
+            PreToolUseHookInput,
+        };
+
+        let hooks = ToolSchemaRegistry::builtin()
+            .deny_invalid()
+            .hooks()
+            .into_hooks();
+        let hook = &hooks[&HookEvent::PreToolUse][0].hooks[0];
+        let decision = |command: Value| {
+            let input: PreToolUseHookInput = serde_json::from_value(json!({
+                "session_id": "s",
+                "transcript_path": "/tmp/t.jsonl",
+                "cwd": "/work/repo",
+                "hook_event_name": "PreToolUse",
+                "tool_name": "Bash",
+                "tool_input": {"command": command},
+            }))
+            .unwrap();
+            hook(HookInput::PreToolUse(input), None, Default::default())
+        };
+
+        let HookOutput::Sync(output) = decision(json!(["ls"])).await else {
+            panic!("expected a sync output");
+        };
+        match output.hook_specific_output {
+            Some(HookSpecificOutput::PreToolUse(specific)) => {
+                assert_eq!(specific.permission_decision, Some(PermissionBehavior::Deny));
+                assert!(specific
+                    .permission_decision_reason
+                    .unwrap()
+                    .contains("/command: expected string, got array"));
+            }
+            other => panic!("unexpected output: {:?}", other),
+        }
+        let HookOutput::Sync(output) = decision(json!("ls")).await else {
+            panic!("expected a sync output");
+        };
+        assert!(output.hook_specific_output.is_none());
+    }
+}
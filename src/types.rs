@@ -9,6 +9,11 @@ use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::paths::WorkspaceGuard;
+use crate::policy::PermissionPolicy;
+use crate::tool_schema::{ToolInputValidation, ToolSchemaRegistry};
 
 // ============================================================================
 // Permission Types
@@ -63,13 +68,33 @@ pub enum PermissionUpdateDestination {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PermissionRuleValue {
     /// The tool name this rule applies to.
-    #[serde(rename = "toolName")]
+    #[serde(rename = "toolName", alias = "tool_name")]
     pub tool_name: String,
     /// Optional rule content.
-    #[serde(rename = "ruleContent", skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "ruleContent",
+        alias = "rule_content",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub rule_content: Option<String>,
 }
 
+impl PermissionRuleValue {
+    /// Create a rule matching every use of `tool_name`.
+    pub fn new(tool_name: impl Into<String>) -> Self {
+        Self {
+            tool_name: tool_name.into(),
+            rule_content: None,
+        }
+    }
+
+    /// Limit the rule to uses matching `content`, such as `ls:*` for Bash.
+    pub fn with_content(mut self, content: impl Into<String>) -> Self {
+        self.rule_content = Some(content.into());
+        self
+    }
+}
+
 /// Type of permission update.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -111,27 +136,225 @@ pub struct PermissionUpdate {
     pub destination: Option<PermissionUpdateDestination>,
 }
 
+impl PermissionUpdate {
+    fn of_type(update_type: PermissionUpdateType) -> Self {
+        Self {
+            update_type,
+            rules: None,
+            behavior: None,
+            mode: None,
+            directories: None,
+            destination: None,
+        }
+    }
+
+    fn rule(tool_name: &str, rule_content: Option<&str>) -> PermissionRuleValue {
+        PermissionRuleValue {
+            tool_name: tool_name.to_string(),
+            rule_content: rule_content.map(String::from),
+        }
+    }
+
+    /// Add a rule allowing `tool_name`, or only the uses matching
+    /// `rule_content`.
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::PermissionUpdate;
+    ///
+    /// let update = PermissionUpdate::allow_tool("Bash", Some("ls:*")).session();
+    /// ```
+    pub fn allow_tool(tool_name: &str, rule_content: Option<&str>) -> Self {
+        Self::add_rules(
+            PermissionBehavior::Allow,
+            vec![Self::rule(tool_name, rule_content)],
+        )
+    }
+
+    /// Add a rule denying `tool_name`, or only the uses matching
+    /// `rule_content`.
+    pub fn deny_tool(tool_name: &str, rule_content: Option<&str>) -> Self {
+        Self::add_rules(
+            PermissionBehavior::Deny,
+            vec![Self::rule(tool_name, rule_content)],
+        )
+    }
+
+    /// Add a rule asking before `tool_name` runs, or only before the uses
+    /// matching `rule_content`.
+    pub fn ask_tool(tool_name: &str, rule_content: Option<&str>) -> Self {
+        Self::add_rules(
+            PermissionBehavior::Ask,
+            vec![Self::rule(tool_name, rule_content)],
+        )
+    }
+
+    /// Add `rules` with `behavior`.
+    pub fn add_rules(behavior: PermissionBehavior, rules: Vec<PermissionRuleValue>) -> Self {
+        Self {
+            rules: Some(rules),
+            behavior: Some(behavior),
+            ..Self::of_type(PermissionUpdateType::AddRules)
+        }
+    }
+
+    /// Replace the rules with `behavior` by `rules`.
+    pub fn replace_rules(behavior: PermissionBehavior, rules: Vec<PermissionRuleValue>) -> Self {
+        Self {
+            rules: Some(rules),
+            behavior: Some(behavior),
+            ..Self::of_type(PermissionUpdateType::ReplaceRules)
+        }
+    }
+
+    /// Remove `rules` with `behavior`.
+    pub fn remove_rules(behavior: PermissionBehavior, rules: Vec<PermissionRuleValue>) -> Self {
+        Self {
+            rules: Some(rules),
+            behavior: Some(behavior),
+            ..Self::of_type(PermissionUpdateType::RemoveRules)
+        }
+    }
+
+    /// Switch to permission `mode`.
+    pub fn set_mode(mode: PermissionMode) -> Self {
+        Self {
+            mode: Some(mode),
+            ..Self::of_type(PermissionUpdateType::SetMode)
+        }
+    }
+
+    /// Give tools access to `path`, in addition to the working directory.
+    pub fn add_directory(path: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            directories: Some(vec![path.as_ref().to_string_lossy().into_owned()]),
+            ..Self::of_type(PermissionUpdateType::AddDirectories)
+        }
+    }
+
+    /// Take away access to `path` given by [`add_directory`](Self::add_directory).
+    pub fn remove_directory(path: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            directories: Some(vec![path.as_ref().to_string_lossy().into_owned()]),
+            ..Self::of_type(PermissionUpdateType::RemoveDirectories)
+        }
+    }
+
+    /// Deny reading and editing anything under `path`.
+    ///
+    /// Absolute paths become `//path/**` rules, relative ones `path/**`,
+    /// which the CLI resolves against the settings file's directory.
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::PermissionUpdate;
+    ///
+    /// let update = PermissionUpdate::deny_directory("secrets").project_settings();
+    /// let rules = update.rules.unwrap();
+    /// assert_eq!(rules[0].rule_content.as_deref(), Some("secrets/**"));
+    /// ```
+    pub fn deny_directory(path: impl AsRef<std::path::Path>) -> Self {
+        let path = path.as_ref().to_string_lossy();
+        let path = path.trim_end_matches('/');
+        let pattern = if path.starts_with('/') {
+            format!("/{}/**", path)
+        } else {
+            format!("{}/**", path)
+        };
+        Self::add_rules(
+            PermissionBehavior::Deny,
+            vec![
+                Self::rule("Read", Some(&pattern)),
+                Self::rule("Edit", Some(&pattern)),
+            ],
+        )
+    }
+
+    /// Add another rule for `tool_name` to this update.
+    pub fn with_rule(mut self, tool_name: &str, rule_content: Option<&str>) -> Self {
+        self.rules
+            .get_or_insert_with(Vec::new)
+            .push(Self::rule(tool_name, rule_content));
+        self
+    }
+
+    /// Add `path` to the directories of this update.
+    pub fn with_directory(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.directories
+            .get_or_insert_with(Vec::new)
+            .push(path.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Store the update in `destination`.
+    pub fn destination(mut self, destination: PermissionUpdateDestination) -> Self {
+        self.destination = Some(destination);
+        self
+    }
+
+    /// Apply the update to the current session only.
+    pub fn session(self) -> Self {
+        self.destination(PermissionUpdateDestination::Session)
+    }
+
+    /// Store the update in the user's settings, for every project.
+    pub fn user_settings(self) -> Self {
+        self.destination(PermissionUpdateDestination::UserSettings)
+    }
+
+    /// Store the update in the project's shared settings.
+    pub fn project_settings(self) -> Self {
+        self.destination(PermissionUpdateDestination::ProjectSettings)
+    }
+
+    /// Store the update in the project's local, uncommitted settings.
+    pub fn local_settings(self) -> Self {
+        self.destination(PermissionUpdateDestination::LocalSettings)
+    }
+}
+
 // ============================================================================
 // Tool Permission Callback Types
 // ============================================================================
 
 /// Context information for tool permission callbacks.
 #[derive(Debug, Clone, Default)]
+#[non_exhaustive]
 pub struct ToolPermissionContext {
     /// Permission suggestions from CLI.
     pub suggestions: Vec<PermissionUpdate>,
+    /// Validation of the tool input against its schema, when
+    /// [`tool_schemas`](ClaudeAgentOptions::tool_schemas) is set.
+    pub validation: Option<ToolInputValidation>,
+}
+
+impl ToolPermissionContext {
+    /// Create a context with the CLI's `suggestions`.
+    pub fn new(suggestions: Vec<PermissionUpdate>) -> Self {
+        Self {
+            suggestions,
+            validation: None,
+        }
+    }
 }
 
 /// Allow permission result.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct PermissionResultAllow {
     /// Always "allow".
     pub behavior: String,
     /// Updated input for the tool.
-    #[serde(rename = "updatedInput", skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "updatedInput",
+        alias = "updated_input",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub updated_input: Option<serde_json::Value>,
     /// Permission updates to apply.
-    #[serde(rename = "updatedPermissions", skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "updatedPermissions",
+        alias = "updated_permissions",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub updated_permissions: Option<Vec<PermissionUpdate>>,
 }
 
@@ -163,6 +386,7 @@ impl Default for PermissionResultAllow {
 
 /// Deny permission result.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct PermissionResultDeny {
     /// Always "deny".
     pub behavior: String,
@@ -172,6 +396,33 @@ pub struct PermissionResultDeny {
     /// Whether to interrupt execution.
     #[serde(default)]
     pub interrupt: bool,
+    /// What the model may do instead, already rendered into `message`.
+    #[serde(skip)]
+    pub suggestion: Option<DenialSuggestion>,
+}
+
+/// Guidance attached to a denial, telling the model what it may do instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DenialSuggestion {
+    /// Tool the model should use instead.
+    pub alternative_tool: String,
+    /// Why the original call was denied.
+    pub reason: String,
+}
+
+impl DenialSuggestion {
+    /// Render as the deny message the model receives: a sentence followed
+    /// by the same guidance as JSON, so both the model and tooling that
+    /// inspects transcripts can act on it.
+    fn to_message(&self) -> String {
+        let guidance = serde_json::to_string(self).unwrap_or_default();
+        format!(
+            "{} Use the {} tool instead.\n<denial_guidance>{}</denial_guidance>",
+            self.reason.trim_end(),
+            self.alternative_tool,
+            guidance
+        )
+    }
 }
 
 impl PermissionResultDeny {
@@ -181,6 +432,7 @@ impl PermissionResultDeny {
             behavior: "deny".to_string(),
             message: String::new(),
             interrupt: false,
+            suggestion: None,
         }
     }
 
@@ -190,6 +442,7 @@ impl PermissionResultDeny {
             behavior: "deny".to_string(),
             message: message.into(),
             interrupt: false,
+            suggestion: None,
         }
     }
 
@@ -199,6 +452,32 @@ impl PermissionResultDeny {
             behavior: "deny".to_string(),
             message: message.into(),
             interrupt: true,
+            suggestion: None,
+        }
+    }
+
+    /// Create a deny result pointing the model at `alternative_tool`.
+    ///
+    /// The message states `reason` and the alternative, followed by a
+    /// `<denial_guidance>` JSON tag with the same fields, which recovers
+    /// better than a bare refusal.
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::PermissionResultDeny;
+    ///
+    /// let deny = PermissionResultDeny::with_suggestion("Read", "Shell access is disabled.");
+    /// assert!(deny.message.starts_with("Shell access is disabled. Use the Read tool instead."));
+    /// ```
+    pub fn with_suggestion(alternative_tool: impl Into<String>, reason: impl Into<String>) -> Self {
+        let suggestion = DenialSuggestion {
+            alternative_tool: alternative_tool.into(),
+            reason: reason.into(),
+        };
+        Self {
+            behavior: "deny".to_string(),
+            message: suggestion.to_message(),
+            interrupt: false,
+            suggestion: Some(suggestion),
         }
     }
 }
@@ -234,6 +513,18 @@ impl PermissionResult {
     pub fn deny_with_message(message: impl Into<String>) -> Self {
         Self::Deny(PermissionResultDeny::with_message(message))
     }
+
+    /// Create a deny result suggesting `alternative_tool` (see
+    /// [`PermissionResultDeny::with_suggestion`]).
+    pub fn deny_with_suggestion(
+        alternative_tool: impl Into<String>,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self::Deny(PermissionResultDeny::with_suggestion(
+            alternative_tool,
+            reason,
+        ))
+    }
 }
 
 /// The async future type returned by tool permission callbacks.
@@ -267,6 +558,16 @@ pub type CanUseToolFuture = Pin<Box<dyn Future<Output = PermissionResult> + Send
 pub type CanUseTool =
     Arc<dyn Fn(String, serde_json::Value, ToolPermissionContext) -> CanUseToolFuture + Send + Sync>;
 
+/// The async future type returned by [`OnAuthRequired`] callbacks.
+pub type OnAuthRequiredFuture = Pin<Box<dyn Future<Output = bool> + Send>>;
+
+/// Type alias for the callback run when the CLI needs to be logged in again.
+///
+/// It receives what the CLI said to do about it and resolves to `true`
+/// once new credentials are in place (see
+/// [`ClaudeAgentOptions::on_auth_required`]).
+pub type OnAuthRequired = Arc<dyn Fn(String) -> OnAuthRequiredFuture + Send + Sync>;
+
 // ============================================================================
 // Hook Types
 // ============================================================================
@@ -417,8 +718,11 @@ pub struct PreCompactHookInput {
 }
 
 /// Union of all hook input types.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "hook_event_name")]
+///
+/// Serialized as the input it holds; read according to its
+/// `hook_event_name`, which the input keeps.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
 pub enum HookInput {
     /// PreToolUse hook input.
     PreToolUse(PreToolUseHookInput),
@@ -436,6 +740,55 @@ pub enum HookInput {
     PreCompact(PreCompactHookInput),
 }
 
+impl HookInput {
+    /// Name of the tool the input is about, for tool events.
+    pub fn tool_name(&self) -> Option<&str> {
+        match self {
+            Self::PreToolUse(input) => Some(&input.tool_name),
+            Self::PostToolUse(input) => Some(&input.tool_name),
+            Self::PostToolUseFailure(input) => Some(&input.tool_name),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HookInput {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        // An internally tagged enum would consume the tag its inputs keep
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let event = value
+            .get("hook_event_name")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| D::Error::missing_field("hook_event_name"))?;
+        let input = match event {
+            "PreToolUse" => serde_json::from_value(value).map(Self::PreToolUse),
+            "PostToolUse" => serde_json::from_value(value).map(Self::PostToolUse),
+            "PostToolUseFailure" => serde_json::from_value(value).map(Self::PostToolUseFailure),
+            "UserPromptSubmit" => serde_json::from_value(value).map(Self::UserPromptSubmit),
+            "Stop" => serde_json::from_value(value).map(Self::Stop),
+            "SubagentStop" => serde_json::from_value(value).map(Self::SubagentStop),
+            "PreCompact" => serde_json::from_value(value).map(Self::PreCompact),
+            other => {
+                return Err(D::Error::unknown_variant(
+                    other,
+                    &[
+                        "PreToolUse",
+                        "PostToolUse",
+                        "PostToolUseFailure",
+                        "UserPromptSubmit",
+                        "Stop",
+                        "SubagentStop",
+                        "PreCompact",
+                    ],
+                ))
+            }
+        };
+        input.map_err(D::Error::custom)
+    }
+}
+
 /// Hook-specific output for PreToolUse events.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -464,6 +817,12 @@ pub struct PostToolUseHookSpecificOutput {
     /// Additional context to add.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub additional_context: Option<String>,
+    /// Replacement output for an MCP tool.
+    #[serde(
+        rename = "updatedMCPToolOutput",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_mcp_tool_output: Option<serde_json::Value>,
 }
 
 /// Hook-specific output for PostToolUseFailure events.
@@ -508,10 +867,14 @@ pub enum HookSpecificOutput {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AsyncHookOutput {
     /// Set to true to defer execution.
-    #[serde(rename = "async")]
+    #[serde(rename = "async", alias = "async_")]
     pub async_: bool,
     /// Optional timeout in milliseconds.
-    #[serde(rename = "asyncTimeout", skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "asyncTimeout",
+        alias = "async_timeout",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub async_timeout: Option<u64>,
 }
 
@@ -520,7 +883,11 @@ pub struct AsyncHookOutput {
 #[serde(rename_all = "camelCase")]
 pub struct SyncHookOutput {
     /// Whether to continue execution.
-    #[serde(rename = "continue", skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "continue",
+        alias = "continue_",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub continue_: Option<bool>,
     /// Whether to suppress output.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -561,7 +928,81 @@ impl Default for HookOutput {
 /// Context for hook callbacks.
 #[derive(Debug, Clone, Default)]
 pub struct HookContext {
-    // Reserved for future abort signal support.
+    deferral: Arc<std::sync::Mutex<Option<HookDeferral>>>,
+}
+
+impl HookContext {
+    /// Defer the hook's result until [`HookDeferred::complete`] is called,
+    /// e.g. from a task waiting for a human decision.
+    ///
+    /// Return the output from this method from the callback. The SDK then
+    /// answers the CLI once the result is completed, without blocking the
+    /// session in the meantime. If `timeout` passes first, the CLI is told
+    /// the hook ran asynchronously and continues without its result. Keep
+    /// `timeout` below the matcher's [`timeout`](HookMatcher::timeout), after
+    /// which the CLI stops waiting.
+    pub fn defer(&self, timeout: std::time::Duration) -> (HookDeferred, HookOutput) {
+        static DEFERRAL_COUNTER: std::sync::atomic::AtomicU64 =
+            std::sync::atomic::AtomicU64::new(0);
+
+        let id = format!(
+            "hook_deferred_{}",
+            DEFERRAL_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        );
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        *self.deferral.lock().unwrap_or_else(|e| e.into_inner()) = Some(HookDeferral {
+            id: id.clone(),
+            receiver,
+            timeout,
+        });
+
+        let output = HookOutput::Async(AsyncHookOutput {
+            async_: true,
+            async_timeout: Some(timeout.as_millis() as u64),
+        });
+        (HookDeferred { id, sender }, output)
+    }
+
+    /// Take the deferral registered by [`defer`](Self::defer), if any.
+    pub(crate) fn take_deferral(&self) -> Option<HookDeferral> {
+        self.deferral
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+    }
+}
+
+/// A hook result deferred with [`HookContext::defer`].
+#[derive(Debug)]
+pub(crate) struct HookDeferral {
+    pub(crate) id: String,
+    pub(crate) receiver: tokio::sync::oneshot::Receiver<HookOutput>,
+    pub(crate) timeout: std::time::Duration,
+}
+
+/// Handle for completing a hook callback after it has returned.
+///
+/// Created by [`HookContext::defer`]. Dropping the handle without completing
+/// it lets the CLI continue as if the hook returned no output.
+#[derive(Debug)]
+pub struct HookDeferred {
+    id: String,
+    sender: tokio::sync::oneshot::Sender<HookOutput>,
+}
+
+impl HookDeferred {
+    /// Identifier of the deferred result, as used in SDK log events.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Deliver the hook's output to the CLI.
+    ///
+    /// Returns `false` if the deferral already timed out or the session
+    /// has ended.
+    pub fn complete(self, output: HookOutput) -> bool {
+        self.sender.send(output).is_ok()
+    }
 }
 
 /// The async future type returned by hook callbacks.
@@ -575,7 +1016,7 @@ pub type HookCallbackFuture = Pin<Box<dyn Future<Output = HookOutput> + Send>>;
 /// # Arguments
 /// * `input` - The hook input containing event-specific data
 /// * `tool_use_id` - Optional tool use ID (for pre/post tool hooks)
-/// * `context` - Hook context (reserved for future use)
+/// * `context` - Hook context, used to [defer](HookContext::defer) the result
 ///
 /// # Returns
 /// A [`HookOutput`] that can modify the tool input, block execution, or log a message.
@@ -593,14 +1034,64 @@ pub type HookCallback =
     Arc<dyn Fn(HookInput, Option<String>, HookContext) -> HookCallbackFuture + Send + Sync>;
 
 /// Hook matcher configuration.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::{HookCallback, HookMatcher};
+///
+/// fn bash_guard(callback: HookCallback) -> HookMatcher {
+///     HookMatcher::new(vec![callback])
+///         .with_matcher("Bash")
+///         .with_timeout(30.0)
+/// }
+/// ```
 #[derive(Clone, Default)]
+#[non_exhaustive]
 pub struct HookMatcher {
-    /// Pattern to match (e.g., tool name or regex).
+    /// Pattern to match (e.g., tool name or regex), passed to the CLI.
     pub matcher: Option<String>,
     /// List of hook callbacks.
     pub hooks: Vec<HookCallback>,
     /// Timeout in seconds.
     pub timeout: Option<f64>,
+    /// Tool names the callbacks run for, checked by the SDK when the CLI
+    /// calls back.
+    ///
+    /// Unlike [`matcher`](Self::matcher), whose syntax is up to the CLI
+    /// version, this behaves the same everywhere. Callbacks are skipped,
+    /// letting the tool call proceed, when the tool name doesn't match.
+    /// Inputs of events without a tool name are never filtered. Leave
+    /// `matcher` unset to have the CLI call back for every tool.
+    pub tool_matcher: Option<ToolMatcher>,
+}
+
+impl HookMatcher {
+    /// Run `hooks` for every tool.
+    pub fn new(hooks: Vec<HookCallback>) -> Self {
+        Self {
+            hooks,
+            ..Self::default()
+        }
+    }
+
+    /// Set the pattern passed to the CLI (see [`matcher`](Self::matcher)).
+    pub fn with_matcher(mut self, matcher: impl Into<String>) -> Self {
+        self.matcher = Some(matcher.into());
+        self
+    }
+
+    /// Set the timeout in seconds.
+    pub fn with_timeout(mut self, timeout: f64) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Run the callbacks only for tools `tool_matcher` matches.
+    pub fn with_tool_matcher(mut self, tool_matcher: ToolMatcher) -> Self {
+        self.tool_matcher = Some(tool_matcher);
+        self
+    }
 }
 
 impl std::fmt::Debug for HookMatcher {
@@ -609,10 +1100,129 @@ impl std::fmt::Debug for HookMatcher {
             .field("matcher", &self.matcher)
             .field("hooks", &format!("[{} callbacks]", self.hooks.len()))
             .field("timeout", &self.timeout)
+            .field("tool_matcher", &self.tool_matcher)
             .finish()
     }
 }
 
+/// A pattern tool names are matched against by the SDK, for
+/// [`HookMatcher::tool_matcher`].
+///
+/// Patterns match the whole name.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::ToolMatcher;
+///
+/// assert!(ToolMatcher::exact("Bash").matches("Bash"));
+/// assert!(ToolMatcher::glob("mcp__github__*").matches("mcp__github__create_issue"));
+/// assert!(!ToolMatcher::glob("Read?").matches("Read"));
+/// ```
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ToolMatcher {
+    /// The tool name, exactly.
+    Exact(String),
+    /// A glob: `*` matches any run of characters and `?` any one character.
+    Glob(String),
+    /// A regular expression.
+    #[cfg(feature = "regex")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "regex")))]
+    Regex(regex::Regex),
+}
+
+impl ToolMatcher {
+    /// Match the tool named `name`.
+    pub fn exact(name: impl Into<String>) -> Self {
+        Self::Exact(name.into())
+    }
+
+    /// Match tool names against a glob.
+    pub fn glob(pattern: impl Into<String>) -> Self {
+        Self::Glob(pattern.into())
+    }
+
+    /// Match tool names against a regular expression.
+    ///
+    /// Fails if `pattern` isn't a valid regular expression.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::ToolMatcher;
+    ///
+    /// assert!(ToolMatcher::regex("Edit|Write")?.matches("Write"));
+    /// assert!(!ToolMatcher::regex("Edit|Write")?.matches("MultiEdit"));
+    /// # Ok::<(), claude_agents_sdk::ClaudeSDKError>(())
+    /// ```
+    #[cfg(feature = "regex")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "regex")))]
+    pub fn regex(pattern: &str) -> crate::errors::Result<Self> {
+        regex::Regex::new(&format!("^(?:{})$", pattern))
+            .map(Self::Regex)
+            .map_err(|e| {
+                crate::errors::ClaudeSDKError::configuration(format!(
+                    "Invalid tool matcher regex '{}': {}",
+                    pattern, e
+                ))
+            })
+    }
+
+    /// Whether the tool named `tool_name` matches.
+    pub fn matches(&self, tool_name: &str) -> bool {
+        match self {
+            Self::Exact(name) => name == tool_name,
+            Self::Glob(pattern) => glob_matches(pattern, tool_name),
+            #[cfg(feature = "regex")]
+            Self::Regex(regex) => regex.is_match(tool_name),
+        }
+    }
+
+    /// `callback`, skipped for inputs naming a tool that doesn't match.
+    pub(crate) fn filter(&self, callback: HookCallback) -> HookCallback {
+        let matcher = self.clone();
+        Arc::new(move |input, tool_use_id, context| match input.tool_name() {
+            Some(name) if !matcher.matches(name) => {
+                tracing::trace!("Skipping hook callback for tool {}", name);
+                Box::pin(async { HookOutput::default() })
+            }
+            _ => callback(input, tool_use_id, context),
+        })
+    }
+}
+
+/// Whether `text` matches the glob `pattern` as a whole.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and the text position it currently covers up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the `*` take one more character
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 // ============================================================================
 // MCP Server Configuration
 // ============================================================================
@@ -637,11 +1247,19 @@ fn default_stdio() -> String {
     "stdio".to_string()
 }
 
+fn default_sse() -> String {
+    "sse".to_string()
+}
+
+fn default_http() -> String {
+    "http".to_string()
+}
+
 /// MCP SSE server configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpSSEServerConfig {
     /// Server type (always "sse").
-    #[serde(rename = "type")]
+    #[serde(rename = "type", default = "default_sse")]
     pub server_type: String,
     /// Server URL.
     pub url: String,
@@ -654,7 +1272,7 @@ pub struct McpSSEServerConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpHttpServerConfig {
     /// Server type (always "http").
-    #[serde(rename = "type")]
+    #[serde(rename = "type", default = "default_http")]
     pub server_type: String,
     /// Server URL.
     pub url: String,
@@ -788,74 +1406,350 @@ pub struct ToolUseBlock {
 
 /// Tool result content block.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ToolResultBlock {
     /// ID of the tool use this is a result for.
     pub tool_use_id: String,
     /// Result content.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<serde_json::Value>,
+    pub content: Option<ToolResultContent>,
     /// Whether this is an error result.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_error: Option<bool>,
+    /// Location of the content when it was spooled to disk (see
+    /// [`ClaudeAgentOptions::tool_output_spool`]); `content` is `None` then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_ref: Option<ContentRef>,
 }
 
-/// Content block union type.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
-pub enum ContentBlock {
-    /// Text block.
-    #[serde(rename = "text")]
-    Text(TextBlock),
-    /// Thinking block.
-    #[serde(rename = "thinking")]
-    Thinking(ThinkingBlock),
-    /// Tool use block.
-    #[serde(rename = "tool_use")]
-    ToolUse(ToolUseBlock),
-    /// Tool result block.
-    #[serde(rename = "tool_result")]
-    ToolResult(ToolResultBlock),
+impl ToolResultBlock {
+    /// Create a result for the tool use `tool_use_id`.
+    pub fn new(tool_use_id: impl Into<String>, content: impl Into<ToolResultContent>) -> Self {
+        Self {
+            tool_use_id: tool_use_id.into(),
+            content: Some(content.into()),
+            is_error: None,
+            content_ref: None,
+        }
+    }
 }
 
-impl ContentBlock {
-    /// Get the text if this is a text block.
-    pub fn as_text(&self) -> Option<&str> {
+/// Content of a tool result, in whichever shape the tool returned it.
+///
+/// Serialized back to the JSON it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolResultContent {
+    /// A string, as most built-in tools return.
+    Text(String),
+    /// Content blocks, as MCP tools and tools returning images do.
+    Blocks(Vec<ToolResultContentBlock>),
+    /// Any other JSON.
+    Json(serde_json::Value),
+}
+
+impl ToolResultContent {
+    /// Read content from its JSON.
+    pub fn from_value(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(text) => Self::Text(text),
+            serde_json::Value::Array(blocks) => Self::Blocks(
+                blocks
+                    .into_iter()
+                    .map(ToolResultContentBlock::from_value)
+                    .collect(),
+            ),
+            other => Self::Json(other),
+        }
+    }
+
+    /// The content as JSON, as the CLI sent it.
+    pub fn to_value(&self) -> serde_json::Value {
         match self {
-            ContentBlock::Text(block) => Some(&block.text),
-            _ => None,
+            Self::Text(text) => serde_json::Value::String(text.clone()),
+            Self::Blocks(blocks) => blocks
+                .iter()
+                .map(ToolResultContentBlock::to_value)
+                .collect(),
+            Self::Json(value) => value.clone(),
         }
     }
 
-    /// Check if this is a tool use block.
-    pub fn is_tool_use(&self) -> bool {
-        matches!(self, ContentBlock::ToolUse(_))
+    /// Get the text: the string, or the text blocks joined with newlines.
+    ///
+    /// `None` for other JSON, and for blocks without any text.
+    pub fn as_text(&self) -> Option<String> {
+        match self {
+            Self::Text(text) => Some(text.clone()),
+            Self::Blocks(blocks) => {
+                let texts: Vec<&str> = blocks.iter().filter_map(|b| b.as_text()).collect();
+                (!texts.is_empty()).then(|| texts.join("\n"))
+            }
+            Self::Json(_) => None,
+        }
     }
-}
 
-// ============================================================================
-// Message Types
-// ============================================================================
+    /// Get the content blocks; empty unless the content is blocks.
+    pub fn blocks(&self) -> &[ToolResultContentBlock] {
+        match self {
+            Self::Blocks(blocks) => blocks,
+            _ => &[],
+        }
+    }
 
-/// Assistant message error types.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum AssistantMessageError {
-    /// Authentication failed.
-    AuthenticationFailed,
-    /// Billing error.
-    BillingError,
-    /// Rate limit exceeded.
-    RateLimit,
-    /// Invalid request.
-    InvalidRequest,
-    /// Server error.
-    ServerError,
-    /// Unknown error.
-    Unknown,
+    /// Get the JSON, if the content is neither a string nor blocks.
+    pub fn as_json(&self) -> Option<&serde_json::Value> {
+        match self {
+            Self::Json(value) => Some(value),
+            _ => None,
+        }
+    }
 }
 
-/// User message.
+impl From<&str> for ToolResultContent {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<String> for ToolResultContent {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<serde_json::Value> for ToolResultContent {
+    fn from(value: serde_json::Value) -> Self {
+        Self::from_value(value)
+    }
+}
+
+impl Serialize for ToolResultContent {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolResultContent {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serde_json::Value::deserialize(deserializer).map(Self::from_value)
+    }
+}
+
+/// A block of [`ToolResultContent`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolResultContentBlock {
+    /// Text.
+    Text {
+        /// The text.
+        text: String,
+    },
+    /// An image.
+    Image {
+        /// Where the image data is.
+        source: ImageSource,
+    },
+    /// A block of another type, kept as JSON.
+    Other(serde_json::Value),
+}
+
+impl ToolResultContentBlock {
+    fn from_value(value: serde_json::Value) -> Self {
+        let parsed = match value.get("type").and_then(serde_json::Value::as_str) {
+            Some("text") => value
+                .get("text")
+                .and_then(serde_json::Value::as_str)
+                .map(|text| Self::Text {
+                    text: text.to_string(),
+                }),
+            Some("image") => value
+                .get("source")
+                .and_then(|source| serde_json::from_value(source.clone()).ok())
+                .map(|source| Self::Image { source }),
+            _ => None,
+        };
+        parsed.unwrap_or(Self::Other(value))
+    }
+
+    fn to_value(&self) -> serde_json::Value {
+        match self {
+            Self::Text { text } => serde_json::json!({"type": "text", "text": text}),
+            Self::Image { source } => serde_json::json!({"type": "image", "source": source}),
+            Self::Other(value) => value.clone(),
+        }
+    }
+
+    /// Get the text if this is a text block.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text { text } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Get the image source if this is an image block.
+    pub fn as_image(&self) -> Option<&ImageSource> {
+        match self {
+            Self::Image { source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Source of an image in a tool result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageSource {
+    /// How the image is given, e.g. `base64` or `url`.
+    #[serde(rename = "type")]
+    pub source_type: String,
+    /// MIME type of the image, e.g. `image/png`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    /// Base64-encoded image data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// URL of the image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Reference to tool result content written to a spool file.
+///
+/// String content is written as-is; any other content is written as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentRef {
+    /// Path of the spool file.
+    pub path: PathBuf,
+    /// Size of the content in bytes.
+    pub bytes: u64,
+    /// Hex-encoded SHA-256 of the content.
+    pub sha256: String,
+}
+
+impl ContentRef {
+    /// Read the spooled content back from disk.
+    pub fn read(&self) -> std::io::Result<Vec<u8>> {
+        std::fs::read(&self.path)
+    }
+}
+
+/// Redirects large tool results to files so the message stream stays small.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolOutputSpool {
+    /// Directory the spool files are written to (created if missing).
+    pub dir: PathBuf,
+    /// Tool results larger than this many bytes are spooled.
+    pub threshold_bytes: usize,
+}
+
+/// Content block union type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ContentBlock {
+    /// Text block.
+    #[serde(rename = "text")]
+    Text(TextBlock),
+    /// Thinking block.
+    #[serde(rename = "thinking")]
+    Thinking(ThinkingBlock),
+    /// Tool use block.
+    #[serde(rename = "tool_use")]
+    ToolUse(ToolUseBlock),
+    /// Tool result block.
+    #[serde(rename = "tool_result")]
+    ToolResult(ToolResultBlock),
+}
+
+impl ContentBlock {
+    /// Get the text if this is a text block.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ContentBlock::Text(block) => Some(&block.text),
+            _ => None,
+        }
+    }
+
+    /// Check if this is a tool use block.
+    pub fn is_tool_use(&self) -> bool {
+        matches!(self, ContentBlock::ToolUse(_))
+    }
+}
+
+// ============================================================================
+// Message Types
+// ============================================================================
+
+/// Assistant message error types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssistantMessageError {
+    /// Authentication failed.
+    AuthenticationFailed,
+    /// Billing error.
+    BillingError,
+    /// Rate limit exceeded.
+    RateLimit,
+    /// Invalid request.
+    InvalidRequest,
+    /// Server error.
+    ServerError,
+    /// Unknown error.
+    Unknown,
+}
+
+/// Position and receive time of a message in a session's stream.
+///
+/// Every frame the reader routes as a message takes the next sequence
+/// number, including frames that fail to parse or are dropped by policy, so
+/// a gap between consecutive messages means something was dropped. Messages
+/// are delivered in sequence order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageMeta {
+    /// Sequence number, starting at 0 for the first message of the session.
+    pub seq: u64,
+    /// Wall-clock time the SDK received the message.
+    pub received_at: SystemTime,
+}
+
+impl MessageMeta {
+    /// Take the next sequence number from `counter`, stamped now.
+    pub(crate) fn next(counter: &mut u64) -> Self {
+        let seq = *counter;
+        *counter += 1;
+        Self {
+            seq,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    /// Whether messages were dropped between `previous` and this message.
+    pub fn follows_gap(&self, previous: &MessageMeta) -> bool {
+        self.seq > previous.seq + 1
+    }
+}
+
+/// A tool use whose result hasn't arrived yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingToolUse {
+    /// Tool use ID.
+    pub id: String,
+    /// Tool name.
+    pub name: String,
+    /// Tool use of the subagent that made this call, if any.
+    pub parent_tool_use_id: Option<String>,
+    /// When the SDK received the tool use.
+    pub observed_at: std::time::Instant,
+}
+
+impl PendingToolUse {
+    /// Time since the SDK received the tool use.
+    pub fn age(&self) -> Duration {
+        self.observed_at.elapsed()
+    }
+}
+
+/// User message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct UserMessage {
     /// Message content (string or content blocks).
     pub content: UserMessageContent,
@@ -865,6 +1759,13 @@ pub struct UserMessage {
     /// Parent tool use ID if this is a tool result.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parent_tool_use_id: Option<String>,
+    /// Sequence number and receive time, for messages read from the CLI.
+    #[serde(skip)]
+    pub meta: Option<MessageMeta>,
+    /// Raw JSON this message was parsed from, when
+    /// [`keep_raw_messages`](ClaudeAgentOptions::keep_raw_messages) is enabled.
+    #[serde(skip)]
+    pub raw: Option<Arc<serde_json::Value>>,
 }
 
 /// User message content can be a string or content blocks.
@@ -878,6 +1779,17 @@ pub enum UserMessageContent {
 }
 
 impl UserMessage {
+    /// Create a message with `content`.
+    pub fn new(content: UserMessageContent) -> Self {
+        Self {
+            content,
+            uuid: None,
+            parent_tool_use_id: None,
+            meta: None,
+            raw: None,
+        }
+    }
+
     /// Get text content if this is a simple text message.
     pub fn text(&self) -> Option<&str> {
         match &self.content {
@@ -895,6 +1807,7 @@ impl UserMessage {
 
 /// Assistant message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct AssistantMessage {
     /// Content blocks.
     pub content: Vec<ContentBlock>,
@@ -903,12 +1816,41 @@ pub struct AssistantMessage {
     /// Parent tool use ID.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parent_tool_use_id: Option<String>,
+    /// Unique identifier.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
     /// Error if the message failed.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<AssistantMessageError>,
+    /// Whether the SDK rebuilt this message from partial stream events
+    /// because the CLI never delivered it (see
+    /// [`synthesize_partial_messages`](ClaudeAgentOptions::synthesize_partial_messages)).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub synthesized: bool,
+    /// Sequence number and receive time, for messages read from the CLI.
+    #[serde(skip)]
+    pub meta: Option<MessageMeta>,
+    /// Raw JSON this message was parsed from, when
+    /// [`keep_raw_messages`](ClaudeAgentOptions::keep_raw_messages) is enabled.
+    #[serde(skip)]
+    pub raw: Option<Arc<serde_json::Value>>,
 }
 
 impl AssistantMessage {
+    /// Create a message with `content`, generated by `model`.
+    pub fn new(content: Vec<ContentBlock>, model: impl Into<String>) -> Self {
+        Self {
+            content,
+            model: model.into(),
+            parent_tool_use_id: None,
+            uuid: None,
+            error: None,
+            synthesized: false,
+            meta: None,
+            raw: None,
+        }
+    }
+
     /// Get all text content from this message.
     pub fn text(&self) -> String {
         self.content
@@ -928,19 +1870,50 @@ impl AssistantMessage {
             })
             .collect()
     }
+
+    /// Get the fenced code blocks in the message's text, in order (see
+    /// [`markdown`](crate::markdown)).
+    pub fn code_blocks(&self) -> Vec<crate::markdown::CodeBlock> {
+        self.content
+            .iter()
+            .filter_map(|block| block.as_text())
+            .flat_map(crate::markdown::code_blocks)
+            .collect()
+    }
 }
 
 /// System message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct SystemMessage {
     /// Message subtype.
     pub subtype: String,
     /// Message data.
     pub data: serde_json::Value,
+    /// Sequence number and receive time, for messages read from the CLI.
+    #[serde(skip)]
+    pub meta: Option<MessageMeta>,
+    /// Raw JSON this message was parsed from, when
+    /// [`keep_raw_messages`](ClaudeAgentOptions::keep_raw_messages) is enabled.
+    #[serde(skip)]
+    pub raw: Option<Arc<serde_json::Value>>,
+}
+
+impl SystemMessage {
+    /// Create a message of `subtype` carrying `data`.
+    pub fn new(subtype: impl Into<String>, data: serde_json::Value) -> Self {
+        Self {
+            subtype: subtype.into(),
+            data,
+            meta: None,
+            raw: None,
+        }
+    }
 }
 
 /// Result message with cost and usage information.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ResultMessage {
     /// Message subtype.
     pub subtype: String,
@@ -966,10 +1939,335 @@ pub struct ResultMessage {
     /// Structured output if output_format was specified.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub structured_output: Option<serde_json::Value>,
+    /// Fields the CLI sent that this SDK version doesn't model, e.g. usage
+    /// details added by newer CLI releases. The first time a field shows
+    /// up in a session, a warning listing it is logged.
+    #[serde(flatten, default)]
+    pub extras: serde_json::Map<String, serde_json::Value>,
+    /// Sequence number and receive time, for messages read from the CLI.
+    #[serde(skip)]
+    pub meta: Option<MessageMeta>,
+    /// Raw JSON this message was parsed from, when
+    /// [`keep_raw_messages`](ClaudeAgentOptions::keep_raw_messages) is enabled.
+    #[serde(skip)]
+    pub raw: Option<Arc<serde_json::Value>>,
+}
+
+/// Why a query finished, derived from a [`ResultMessage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionReason {
+    /// The query completed normally.
+    Completed,
+    /// The `max_turns` limit was reached.
+    MaxTurnsReached,
+    /// The `max_budget_usd` limit, or the
+    /// [`turn_budget`](ClaudeAgentOptions::turn_budget), was exceeded.
+    BudgetExceeded,
+    /// The query was interrupted.
+    Interrupted,
+    /// The query failed; holds the raw result subtype.
+    Error(String),
+}
+
+impl CompletionReason {
+    /// Check if the query ended because a configured limit was hit.
+    pub fn is_limit(&self) -> bool {
+        matches!(self, Self::MaxTurnsReached | Self::BudgetExceeded)
+    }
+}
+
+impl ResultMessage {
+    /// Create a result of `subtype` for `session_id`, with no turns, cost
+    /// or output.
+    pub fn new(subtype: impl Into<String>, session_id: impl Into<String>) -> Self {
+        Self {
+            subtype: subtype.into(),
+            duration_ms: 0,
+            duration_api_ms: 0,
+            is_error: false,
+            num_turns: 0,
+            session_id: session_id.into(),
+            total_cost_usd: None,
+            usage: None,
+            result: None,
+            structured_output: None,
+            extras: serde_json::Map::new(),
+            meta: None,
+            raw: None,
+        }
+    }
+
+    /// Get the typed reason this query finished.
+    ///
+    /// Maps the CLI's result `subtype` and `is_error` flag onto a
+    /// [`CompletionReason`] so callers don't have to match on subtype strings.
+    pub fn completion_reason(&self) -> CompletionReason {
+        match self.subtype.as_str() {
+            "error_max_turns" => CompletionReason::MaxTurnsReached,
+            "error_max_budget_usd" => CompletionReason::BudgetExceeded,
+            "interrupted" | "error_interrupted" => CompletionReason::Interrupted,
+            "success" if !self.is_error => CompletionReason::Completed,
+            other => CompletionReason::Error(other.to_string()),
+        }
+    }
+
+    /// Get the code of the fenced blocks in the result text tagged
+    /// `language` (ignoring case), or of all blocks if `language` is `None`.
+    ///
+    /// ```rust
+    /// # use claude_agents_sdk::ResultMessage;
+    /// # fn example(result: &ResultMessage) -> std::io::Result<()> {
+    /// if let Some(script) = result.extract_code(Some("python")).first() {
+    ///     std::fs::write("script.py", script)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn extract_code(&self, language: Option<&str>) -> Vec<String> {
+        self.result
+            .as_deref()
+            .map(|text| crate::markdown::extract_code(text, language))
+            .unwrap_or_default()
+    }
+}
+
+/// Response collected by
+/// [`ClaudeClient::receive_response_with_deadline`](crate::ClaudeClient::receive_response_with_deadline).
+#[derive(Debug, Clone)]
+pub struct DeadlineResponse {
+    /// Assistant text received, possibly partial.
+    pub text: String,
+    /// The terminal result message, if one arrived.
+    pub result: Option<ResultMessage>,
+    /// Whether the deadline expired and the query was interrupted.
+    pub interrupted: bool,
+}
+
+/// When a permission escalation made with
+/// [`ClaudeClient::escalate_permissions`](crate::ClaudeClient::escalate_permissions)
+/// is reverted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EscalationRevert {
+    /// Only when [`revert_permissions`](crate::ClaudeClient::revert_permissions)
+    /// is called or the client disconnects.
+    #[default]
+    Manual,
+    /// After this many queries have been answered.
+    AfterTurns(u32),
+    /// Once this much time has passed.
+    After(std::time::Duration),
+}
+
+/// A slash command offered by the CLI, as listed in its server info.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashCommand {
+    /// Command name, without the leading `/`.
+    pub name: String,
+    /// What the command does.
+    #[serde(default)]
+    pub description: String,
+    /// Hint describing the command's arguments, e.g. `<instructions>`.
+    #[serde(default, rename = "argumentHint")]
+    pub args: String,
+}
+
+impl SlashCommand {
+    /// Format an invocation of `command` (with or without the leading `/`)
+    /// as the user message the CLI expects.
+    ///
+    /// Fails if `command` is empty or contains whitespace.
+    ///
+    /// ```rust
+    /// use claude_agents_sdk::SlashCommand;
+    ///
+    /// let message = SlashCommand::invocation("compact", "keep the API notes")?;
+    /// assert_eq!(message, "/compact keep the API notes");
+    /// # Ok::<(), claude_agents_sdk::ClaudeSDKError>(())
+    /// ```
+    pub fn invocation(command: &str, args: &str) -> crate::errors::Result<String> {
+        let name = command.strip_prefix('/').unwrap_or(command);
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(crate::errors::ClaudeSDKError::configuration(format!(
+                "Invalid slash command name: '{}'",
+                command
+            )));
+        }
+        let args = args.trim();
+        Ok(if args.is_empty() {
+            format!("/{}", name)
+        } else {
+            format!("/{} {}", name, args)
+        })
+    }
+}
+
+/// The configuration a session ran with, for reproducing a recorded run and
+/// explaining differences between runs.
+///
+/// Captured from the options when the session starts, then updated with the
+/// values the CLI reports in its `init` message. Returned by
+/// [`ClaudeClient::session_manifest`](crate::ClaudeClient::session_manifest)
+/// and logged to the `claude_agents_sdk::audit` tracing target.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionManifest {
+    /// [`SCHEMA_VERSION`](crate::export::SCHEMA_VERSION) of the record, 0
+    /// if it predates versioning.
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Version of this SDK.
+    pub sdk_version: String,
+    /// Version of the CLI, once reported.
+    pub cli_version: Option<String>,
+    /// CLI session ID, once reported.
+    pub session_id: Option<String>,
+    /// Model, as resolved by the CLI once reported.
+    pub model: Option<String>,
+    /// Permission mode.
+    pub permission_mode: Option<PermissionMode>,
+    /// Tools allowed without prompting.
+    pub allowed_tools: Vec<String>,
+    /// Tools disallowed.
+    pub disallowed_tools: Vec<String>,
+    /// Tools available to the model, once reported.
+    pub tools: Vec<String>,
+    /// Working directory.
+    pub cwd: Option<PathBuf>,
+    /// When the session started, in milliseconds since the Unix epoch.
+    pub started_at_ms: u64,
+    /// Session tags (see [`ClaudeAgentOptions::session_tags`]). Not part
+    /// of the [`fingerprint`](Self::fingerprint).
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tags: HashMap<String, String>,
+}
+
+impl SessionManifest {
+    /// Capture the session-relevant parts of `options`.
+    pub(crate) fn from_options(options: &ClaudeAgentOptions) -> Self {
+        let started_at_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            schema_version: crate::export::SCHEMA_VERSION,
+            sdk_version: crate::VERSION.to_string(),
+            cli_version: None,
+            session_id: None,
+            model: options.model.clone(),
+            permission_mode: options.permission_mode,
+            allowed_tools: options.allowed_tools.clone(),
+            disallowed_tools: options.disallowed_tools.clone(),
+            tools: Vec::new(),
+            cwd: options.cwd.clone(),
+            started_at_ms,
+            tags: options.session_tags.clone(),
+        }
+    }
+
+    /// Update with the values from the CLI's `init` system message.
+    pub(crate) fn apply_init(&mut self, raw: &serde_json::Value) {
+        let text = |key: &str| raw.get(key).and_then(|v| v.as_str()).map(String::from);
+
+        if let Some(version) = text("claude_code_version") {
+            self.cli_version = Some(version);
+        }
+        if let Some(session_id) = text("session_id") {
+            self.session_id = Some(session_id);
+        }
+        if let Some(model) = text("model") {
+            self.model = Some(model);
+        }
+        if let Some(mode) = raw
+            .get("permissionMode")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+        {
+            self.permission_mode = Some(mode);
+        }
+        if let Some(cwd) = text("cwd") {
+            self.cwd = Some(PathBuf::from(cwd));
+        }
+        if let Some(tools) = raw.get("tools").and_then(|v| v.as_array()) {
+            self.tools = tools
+                .iter()
+                .filter_map(|t| t.as_str().map(String::from))
+                .collect();
+        }
+    }
+
+    /// SHA-256 over the configuration fields, as a hex string.
+    ///
+    /// Equal for sessions with the same SDK and CLI versions, model,
+    /// permission mode, tools and working directory; the session ID and
+    /// start time are not included.
+    pub fn fingerprint(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        let sorted = |tools: &[String]| {
+            let mut tools = tools.to_vec();
+            tools.sort();
+            tools
+        };
+        let config = serde_json::json!({
+            "sdk_version": self.sdk_version,
+            "cli_version": self.cli_version,
+            "model": self.model,
+            "permission_mode": self.permission_mode,
+            "allowed_tools": sorted(&self.allowed_tools),
+            "disallowed_tools": sorted(&self.disallowed_tools),
+            "tools": sorted(&self.tools),
+            "cwd": self.cwd,
+        });
+        crate::_internal::to_hex(&Sha256::digest(config.to_string().as_bytes()))
+    }
+}
+
+/// Options overridden for a single query with
+/// [`ClaudeClient::query_with`](crate::ClaudeClient::query_with).
+///
+/// Only options the CLI can change mid-session are included. Fields left as
+/// `None` keep the session's current value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryOverrides {
+    /// Maximum tokens for extended thinking.
+    pub max_thinking_tokens: Option<u32>,
+    /// Model to answer this query with.
+    pub model: Option<String>,
+    /// Permission mode for tool use during this query.
+    pub permission_mode: Option<PermissionMode>,
+}
+
+impl QueryOverrides {
+    /// Create empty overrides.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the maximum thinking tokens.
+    pub fn with_max_thinking_tokens(mut self, tokens: u32) -> Self {
+        self.max_thinking_tokens = Some(tokens);
+        self
+    }
+
+    /// Override the model.
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Override the permission mode.
+    pub fn with_permission_mode(mut self, mode: PermissionMode) -> Self {
+        self.permission_mode = Some(mode);
+        self
+    }
+
+    /// Check whether no option is overridden.
+    pub fn is_empty(&self) -> bool {
+        self.max_thinking_tokens.is_none() && self.model.is_none() && self.permission_mode.is_none()
+    }
 }
 
 /// Stream event for partial message updates.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct StreamEvent {
     /// Event UUID.
     pub uuid: String,
@@ -980,6 +2278,526 @@ pub struct StreamEvent {
     /// Parent tool use ID.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parent_tool_use_id: Option<String>,
+    /// Sequence number and receive time, for messages read from the CLI.
+    #[serde(skip)]
+    pub meta: Option<MessageMeta>,
+    /// Raw JSON this message was parsed from, when
+    /// [`keep_raw_messages`](ClaudeAgentOptions::keep_raw_messages) is enabled.
+    #[serde(skip)]
+    pub raw: Option<Arc<serde_json::Value>>,
+}
+
+impl StreamEvent {
+    /// Create an event of session `session_id` carrying the API stream
+    /// `event`.
+    pub fn new(
+        uuid: impl Into<String>,
+        session_id: impl Into<String>,
+        event: serde_json::Value,
+    ) -> Self {
+        Self {
+            uuid: uuid.into(),
+            session_id: session_id.into(),
+            event,
+            parent_tool_use_id: None,
+            meta: None,
+            raw: None,
+        }
+    }
+}
+
+/// How consecutive text deltas are merged when partial messages are
+/// coalesced (see
+/// [`partial_coalescing`](ClaudeAgentOptions::partial_coalescing)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialCoalescing {
+    /// Longest time a delta is held back waiting for more.
+    pub interval: Duration,
+    /// Merged text size that triggers an immediate flush.
+    pub max_bytes: usize,
+}
+
+impl Default for PartialCoalescing {
+    /// Flush at roughly 60 frames per second, or every 4 KiB of text.
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(16),
+            max_bytes: 4096,
+        }
+    }
+}
+
+/// How much of each frame exchanged with the CLI is logged, at `trace`
+/// level (see [`log_sampling`](ClaudeAgentOptions::log_sampling)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSampling {
+    /// Whole frames, e.g. to debug parsing.
+    Full,
+    /// The first bytes of each frame, followed by the number cut off.
+    Truncated(usize),
+    /// Only the size of each frame.
+    MetadataOnly,
+}
+
+impl Default for LogSampling {
+    /// The first 200 bytes of each frame.
+    fn default() -> Self {
+        Self::Truncated(200)
+    }
+}
+
+impl LogSampling {
+    /// The part of `frame` to log.
+    pub fn sample(&self, frame: &[u8]) -> String {
+        match *self {
+            Self::Full => String::from_utf8_lossy(frame).into_owned(),
+            Self::Truncated(max) if frame.len() > max => {
+                // Cut before a character `max` falls inside of
+                let end = match std::str::from_utf8(&frame[..max]) {
+                    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                    _ => max,
+                };
+                format!(
+                    "{}... [{} more bytes]",
+                    String::from_utf8_lossy(&frame[..end]),
+                    frame.len() - end
+                )
+            }
+            Self::Truncated(_) => String::from_utf8_lossy(frame).into_owned(),
+            Self::MetadataOnly => format!("<{} bytes>", frame.len()),
+        }
+    }
+}
+
+/// Limit on the size of frames written to the CLI (see
+/// [`outbound_frame_limit`](ClaudeAgentOptions::outbound_frame_limit)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutboundFrameLimit {
+    /// Largest frame written, in bytes, without its line break.
+    pub max_bytes: usize,
+    /// What to do with the response to a permission or hook callback whose
+    /// updated tool input makes it too large.
+    pub oversized_input: OversizedInput,
+}
+
+impl OutboundFrameLimit {
+    /// Reject frames larger than `max_bytes`.
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            oversized_input: OversizedInput::default(),
+        }
+    }
+
+    /// Handle responses made too large by an updated input per `strategy`.
+    pub fn with_oversized_input(mut self, strategy: OversizedInput) -> Self {
+        self.oversized_input = strategy;
+        self
+    }
+}
+
+/// How to shrink a control response whose updated tool input puts it over
+/// the [`OutboundFrameLimit`].
+///
+/// Whatever the strategy, a response that is still too large is replaced
+/// by an error response, so the CLI isn't left waiting for an answer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OversizedInput {
+    /// Don't shrink it: the CLI gets an error response.
+    #[default]
+    Reject,
+    /// Drop the updated input, so the tool runs with the input the model
+    /// gave it.
+    ///
+    /// There is no strategy cutting the input down to size: a tool run
+    /// with part of its input, such as a Write with part of the file's
+    /// content, would do the wrong thing without failing.
+    KeepOriginal,
+}
+
+/// Sizes of the frames written to the CLI (see
+/// [`ClaudeClient::outbound_frame_stats`](crate::ClaudeClient::outbound_frame_stats)).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutboundFrameStats {
+    /// Frames written.
+    pub frames: u64,
+    /// Bytes written, without line breaks.
+    pub bytes: u64,
+    /// Size of the largest frame written, in bytes.
+    pub largest: usize,
+    /// Frames not written for being over the limit.
+    pub rejected: u64,
+    /// Control responses whose updated input was dropped to fit the limit.
+    pub shrunk: u64,
+}
+
+/// A transport to fall back on when the CLI can't be used (see
+/// [`fallback_transports`](ClaudeAgentOptions::fallback_transports)).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FallbackTransport {
+    /// The CLI installed at another path, started with the same options.
+    Cli(PathBuf),
+    /// A remote CLI, through a WebSocket bridge (see
+    /// [`WebSocketTransport`](crate::_internal::transport::WebSocketTransport)).
+    ///
+    /// The bridge starts the CLI with a command line of its own, so the
+    /// options given to the CLI as flags can't be applied. Rather than run
+    /// without them, connecting fails instead of falling back when any of
+    /// `model`, `permission_mode`, `allowed_tools`, `disallowed_tools`,
+    /// `system_prompt`, `sandbox` or `settings` is set; configure those on
+    /// the bridge.
+    ///
+    /// Requires the `websocket` feature.
+    #[cfg(feature = "websocket")]
+    WebSocket(WebSocketConfig),
+}
+
+impl FallbackTransport {
+    /// Whether the transport is unable to take a prompt on the CLI's
+    /// command line, so one-shot queries have to stream it.
+    pub(crate) fn is_remote(&self) -> bool {
+        match self {
+            Self::Cli(_) => false,
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(_) => true,
+        }
+    }
+}
+
+impl std::fmt::Display for FallbackTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cli(path) => write!(f, "CLI at {}", path.display()),
+            #[cfg(feature = "websocket")]
+            Self::WebSocket(config) => write!(f, "WebSocket bridge at {}", config.url),
+        }
+    }
+}
+
+/// Where to reach a WebSocket bridge to a remote CLI, and how to reconnect
+/// to it (see
+/// [`WebSocketTransport`](crate::_internal::transport::WebSocketTransport)).
+///
+/// Requires the `websocket` feature.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::WebSocketConfig;
+/// use std::time::Duration;
+///
+/// let config = WebSocketConfig::new("wss://devbox:8765/claude")
+///     .with_header("Authorization", "Bearer secret")
+///     .with_reconnects(10)
+///     .with_delays(Duration::from_millis(200), Duration::from_secs(5));
+/// ```
+#[cfg(feature = "websocket")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketConfig {
+    /// URL of the bridge, `wss://` for TLS (verified against the Mozilla
+    /// root certificates) or `ws://`.
+    pub url: String,
+    /// Headers sent with each handshake, e.g. `Authorization`.
+    pub headers: HashMap<String, String>,
+    /// Frame encodings to offer the bridge as subprotocols, most preferred
+    /// first (default: none, for JSON text messages). Names are those of
+    /// [`codec::by_name`](crate::protocol::codec::by_name).
+    pub codecs: Vec<String>,
+    /// How many times a dropped connection is reopened before giving up
+    /// (default: 5).
+    pub max_reconnects: u32,
+    /// Delay before the first attempt to reopen it (default: 500
+    /// milliseconds), doubling with each attempt after it.
+    pub initial_delay: Duration,
+    /// Longest delay between attempts (default: 10 seconds).
+    pub max_delay: Duration,
+}
+
+#[cfg(feature = "websocket")]
+impl WebSocketConfig {
+    /// Reach the bridge at `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            headers: HashMap::new(),
+            codecs: Vec::new(),
+            max_reconnects: 5,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+
+    /// Send a header with each handshake.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Offer the frame encodings `codecs`, most preferred first.
+    pub fn with_codecs(mut self, codecs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.codecs = codecs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set how many times a dropped connection is reopened.
+    pub fn with_reconnects(mut self, max_reconnects: u32) -> Self {
+        self.max_reconnects = max_reconnects;
+        self
+    }
+
+    /// Set the delay before the first attempt to reopen a connection and
+    /// the longest delay.
+    pub fn with_delays(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_delay = initial;
+        self.max_delay = max;
+        self
+    }
+
+    /// The delay before reconnect attempt number `attempt` (starting at 1).
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        crate::_internal::backoff::exponential(self.initial_delay, self.max_delay, attempt)
+    }
+}
+
+/// Caps on what a single turn may use, checked as the CLI reports usage
+/// during the turn (see [`turn_budget`](ClaudeAgentOptions::turn_budget)).
+///
+/// Costs are estimated from token counts at the given prices, with cached
+/// input priced as fresh input, so the estimate errs high.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TurnBudget {
+    /// Most tokens, input and output, the turn may use.
+    pub max_tokens: Option<u64>,
+    /// Most the turn may cost, in USD.
+    pub max_usd: Option<f64>,
+    /// Price of input tokens, in USD per million.
+    pub input_usd_per_mtok: f64,
+    /// Price of output tokens, in USD per million.
+    pub output_usd_per_mtok: f64,
+}
+
+impl TurnBudget {
+    /// Cap a turn at `max_tokens` tokens.
+    pub fn tokens(max_tokens: u64) -> Self {
+        Self {
+            max_tokens: Some(max_tokens),
+            ..Default::default()
+        }
+    }
+
+    /// Cap a turn at `max_usd`, pricing tokens at USD per million input and
+    /// output tokens.
+    pub fn usd(max_usd: f64, input_usd_per_mtok: f64, output_usd_per_mtok: f64) -> Self {
+        Self {
+            max_usd: Some(max_usd),
+            input_usd_per_mtok,
+            output_usd_per_mtok,
+            ..Default::default()
+        }
+    }
+
+    /// Also cap the turn at `max_tokens` tokens.
+    pub fn with_max_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Estimated cost of `usage`, in USD.
+    pub fn cost_usd(&self, usage: &TurnUsage) -> f64 {
+        (usage.input_tokens as f64 * self.input_usd_per_mtok
+            + usage.output_tokens as f64 * self.output_usd_per_mtok)
+            / 1_000_000.0
+    }
+
+    /// Whether `usage` is over either cap.
+    pub fn is_exceeded_by(&self, usage: &TurnUsage) -> bool {
+        self.max_tokens
+            .is_some_and(|max| usage.total_tokens() > max)
+            || self.max_usd.is_some_and(|max| self.cost_usd(usage) > max)
+    }
+}
+
+/// Tokens used so far in a turn, across all of its API calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TurnUsage {
+    /// Input tokens, cached or not.
+    pub input_tokens: u64,
+    /// Output tokens.
+    pub output_tokens: u64,
+}
+
+impl TurnUsage {
+    /// Input and output tokens together.
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+/// How long each stage of a session may take (see
+/// [`timeouts`](ClaudeAgentOptions::timeouts)). `None` waits indefinitely.
+///
+/// Splitting them lets a turn run a 20-minute tool while a CLI that never
+/// starts answering still fails fast.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::{ClaudeAgentOptions, Timeouts};
+/// use std::time::Duration;
+///
+/// let options = ClaudeAgentOptions::new().with_timeouts(Timeouts {
+///     first_message: Some(Duration::from_secs(60)),
+///     idle: Some(Duration::from_secs(30 * 60)),
+///     ..Timeouts::default()
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Starting the CLI and initializing the session (default: 5 minutes).
+    ///
+    /// Bounds the initialize request along with
+    /// [`control_request`](Self::control_request).
+    pub connect: Option<Duration>,
+    /// From sending a prompt to the first frame the CLI sends back
+    /// (default: none).
+    pub first_message: Option<Duration>,
+    /// Between two frames from the CLI during a turn (default: none).
+    ///
+    /// Any frame counts, including control requests. The timer stops while
+    /// the CLI waits on the SDK: for a permission or hook callback to answer,
+    /// or for a paused reader with a full buffer to resume. Also bounds the
+    /// wait for the first frame if [`first_message`](Self::first_message)
+    /// isn't set.
+    pub idle: Option<Duration>,
+    /// Waiting for the answer to a control request (default: 5 minutes).
+    pub control_request: Option<Duration>,
+}
+
+impl Timeouts {
+    /// `secs` to connect and for each control request, or no limit if 0,
+    /// with messages not timed: what the single `timeout_secs` option set
+    /// before these were split.
+    pub fn from_secs(secs: u64) -> Self {
+        let timeout = (secs > 0).then(|| Duration::from_secs(secs));
+        Self {
+            connect: timeout,
+            first_message: None,
+            idle: None,
+            control_request: timeout,
+        }
+    }
+
+    /// No timeouts at all.
+    pub fn none() -> Self {
+        Self::from_secs(0)
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self::from_secs(300)
+    }
+}
+
+/// What to do about a conversation filling up the model's context window
+/// (see [`context_policy`](ClaudeAgentOptions::context_policy)).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextPolicy {
+    /// Size of the model's context window, in tokens (default: 200,000).
+    pub context_window: u64,
+    /// Fraction of the window, above 0 and at most 1, past which the
+    /// context counts as nearly full.
+    pub threshold: f64,
+    /// What to do once it is.
+    pub action: ContextAction,
+}
+
+/// Action of a [`ContextPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContextAction {
+    /// Run `/compact` before the next prompt, with optional instructions
+    /// on what to keep.
+    ///
+    /// The compaction runs the session's `PreCompact` hooks like a manual
+    /// one, and its messages aren't delivered with the next response. If a
+    /// hook blocks it, it is logged and no compaction is tried again until
+    /// the context is compacted some other way, such as by the CLI's own
+    /// auto-compaction, which also covers a turn that fills the context
+    /// before it ends.
+    Compact {
+        /// Instructions passed to `/compact`.
+        instructions: Option<String>,
+    },
+    /// Only report it, as
+    /// [`Progress::ContextNearlyFull`](crate::Progress::ContextNearlyFull).
+    Warn,
+}
+
+impl ContextPolicy {
+    /// Context window assumed unless set.
+    pub const DEFAULT_CONTEXT_WINDOW: u64 = 200_000;
+
+    /// Compact the conversation once it fills `threshold` of the window.
+    pub fn compact_at(threshold: f64) -> Self {
+        Self {
+            context_window: Self::DEFAULT_CONTEXT_WINDOW,
+            threshold,
+            action: ContextAction::Compact { instructions: None },
+        }
+    }
+
+    /// Warn once the conversation fills `threshold` of the window.
+    pub fn warn_at(threshold: f64) -> Self {
+        Self {
+            action: ContextAction::Warn,
+            ..Self::compact_at(threshold)
+        }
+    }
+
+    /// Set the size of the model's context window.
+    pub fn with_context_window(mut self, tokens: u64) -> Self {
+        self.context_window = tokens;
+        self
+    }
+
+    /// Pass `instructions` to `/compact`, if the policy compacts.
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        if let ContextAction::Compact {
+            instructions: ref mut slot,
+        } = self.action
+        {
+            *slot = Some(instructions.into());
+        }
+        self
+    }
+
+    /// Whether `used_tokens` is past the threshold.
+    pub fn is_exceeded_by(&self, used_tokens: u64) -> bool {
+        used_tokens as f64 > self.context_window as f64 * self.threshold
+    }
+}
+
+/// How full the context window is, as of the last API call of the main
+/// conversation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContextUsage {
+    /// Tokens in the context: the call's input, cached or not, and output.
+    pub used_tokens: u64,
+    /// Size of the context window.
+    pub context_window: u64,
+}
+
+impl ContextUsage {
+    /// Fraction of the window in use, or 0 if its size is unknown.
+    pub fn fraction(&self) -> f64 {
+        if self.context_window == 0 {
+            return 0.0;
+        }
+        self.used_tokens as f64 / self.context_window as f64
+    }
 }
 
 /// Message union type.
@@ -1029,6 +2847,85 @@ impl Message {
             _ => None,
         }
     }
+
+    /// Get the raw JSON this message was parsed from.
+    ///
+    /// Only available when [`ClaudeAgentOptions::keep_raw_messages`] is
+    /// enabled. Useful for reading CLI fields the typed structs don't model.
+    pub fn raw(&self) -> Option<&serde_json::Value> {
+        let raw = match self {
+            Message::User(msg) => &msg.raw,
+            Message::Assistant(msg) => &msg.raw,
+            Message::System(msg) => &msg.raw,
+            Message::Result(msg) => &msg.raw,
+            Message::StreamEvent(msg) => &msg.raw,
+        };
+        raw.as_deref()
+    }
+
+    /// Attach the raw JSON this message was parsed from.
+    pub(crate) fn set_raw(&mut self, raw: Arc<serde_json::Value>) {
+        let slot = match self {
+            Message::User(msg) => &mut msg.raw,
+            Message::Assistant(msg) => &mut msg.raw,
+            Message::System(msg) => &mut msg.raw,
+            Message::Result(msg) => &mut msg.raw,
+            Message::StreamEvent(msg) => &mut msg.raw,
+        };
+        *slot = Some(raw);
+    }
+
+    /// Get the sequence number and receive time of this message.
+    ///
+    /// Set on every message read from the CLI; `None` for messages built
+    /// by hand.
+    pub fn meta(&self) -> Option<&MessageMeta> {
+        let meta = match self {
+            Message::User(msg) => &msg.meta,
+            Message::Assistant(msg) => &msg.meta,
+            Message::System(msg) => &msg.meta,
+            Message::Result(msg) => &msg.meta,
+            Message::StreamEvent(msg) => &msg.meta,
+        };
+        meta.as_ref()
+    }
+
+    /// Attach the sequence number and receive time of this message.
+    pub(crate) fn set_meta(&mut self, meta: MessageMeta) {
+        let slot = match self {
+            Message::User(msg) => &mut msg.meta,
+            Message::Assistant(msg) => &mut msg.meta,
+            Message::System(msg) => &mut msg.meta,
+            Message::Result(msg) => &mut msg.meta,
+            Message::StreamEvent(msg) => &mut msg.meta,
+        };
+        *slot = Some(meta);
+    }
+
+    /// What the CLI said to do if this message reports that it needs to be
+    /// logged in again: an assistant message with an
+    /// [`AuthenticationFailed`](AssistantMessageError::AuthenticationFailed)
+    /// error, or an error result asking for `/login`.
+    pub(crate) fn auth_challenge(&self) -> Option<String> {
+        match self {
+            Message::Assistant(msg)
+                if msg.error == Some(AssistantMessageError::AuthenticationFailed) =>
+            {
+                let text = msg.text();
+                Some(if text.trim().is_empty() {
+                    "Authentication failed; log the CLI in again with /login".to_string()
+                } else {
+                    text
+                })
+            }
+            Message::Result(msg) if msg.is_error => msg
+                .result
+                .as_deref()
+                .filter(|text| text.contains("/login"))
+                .map(String::from),
+            _ => None,
+        }
+    }
 }
 
 // ============================================================================
@@ -1092,6 +2989,43 @@ pub enum AgentModel {
     Inherit,
 }
 
+/// Model names with a meaning to the SDK itself.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::{ClaudeAgentOptions, Model};
+///
+/// // Runs without the CLI or an API key: each prompt is echoed back
+/// let options = ClaudeAgentOptions::new().with_model(Model::mock());
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Model;
+
+impl Model {
+    /// Name of the echo model, see [`mock`](Self::mock).
+    pub const MOCK: &'static str = "sdk-mock-echo";
+
+    /// A model that answers each prompt with the prompt itself, at no cost.
+    ///
+    /// Sessions using it don't start the CLI, so it works without the
+    /// `subprocess` feature: a [`MockTransport`](crate::testing::MockTransport)
+    /// answers instead, with an init message, an assistant message
+    /// repeating the prompt's text and a successful result reporting zero
+    /// cost and usage. Control requests such as interrupts succeed without
+    /// doing anything. Meant for testing code built on the SDK, through
+    /// the same [`query`](crate::query) and [`ClaudeClient`](crate::ClaudeClient)
+    /// calls it makes in production.
+    pub fn mock() -> String {
+        Self::MOCK.to_string()
+    }
+
+    /// Whether `model` is the echo model.
+    pub fn is_mock(model: Option<&str>) -> bool {
+        model == Some(Self::MOCK)
+    }
+}
+
 /// Agent definition.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentDefinition {
@@ -1144,6 +3078,11 @@ impl Default for McpServersConfig {
 }
 
 /// Query options for Claude SDK.
+///
+/// Prefer building options with [`ClaudeAgentOptions::builder`], which
+/// checks the combination with [`validate`](Self::validate). The fields
+/// stay public for now, but setting them directly skips that check and may
+/// be deprecated in a future release.
 #[derive(Clone, Default)]
 pub struct ClaudeAgentOptions {
     /// Tools to use.
@@ -1154,16 +3093,58 @@ pub struct ClaudeAgentOptions {
     pub system_prompt: Option<SystemPromptConfig>,
     /// MCP server configurations.
     pub mcp_servers: McpServersConfig,
+    /// Only use the MCP servers in [`mcp_servers`](Self::mcp_servers),
+    /// ignoring those in the CLI's settings files (`--strict-mcp-config`).
+    pub strict_mcp_config: bool,
+    /// Names of servers in [`mcp_servers`](Self::mcp_servers) to leave out
+    /// when the CLI starts (default: none).
+    ///
+    /// [`ClaudeClient::set_mcp_server_enabled`](crate::ClaudeClient::set_mcp_server_enabled)
+    /// keeps this up to date when servers are toggled at runtime.
+    pub disabled_mcp_servers: Vec<String>,
+    /// Fail to connect, with
+    /// [`McpServerFailed`](crate::ClaudeSDKError::McpServerFailed), if an
+    /// MCP server couldn't be started, rather than running without its
+    /// tools (default: false).
+    pub require_mcp_servers: bool,
     /// Permission mode.
     pub permission_mode: Option<PermissionMode>,
     /// Continue previous conversation.
     pub continue_conversation: bool,
     /// Resume session ID.
     pub resume: Option<String>,
+    /// When resuming, keep the conversation only up to and including the
+    /// message with this UUID.
+    pub resume_session_at: Option<String>,
+    /// Start a new session instead of failing with
+    /// [`SessionNotFound`](crate::ClaudeSDKError::SessionNotFound) when the
+    /// session to [`resume`](Self::resume) doesn't exist (default: false).
+    ///
+    /// The fallback clears `resume`, `resume_session_at` and `fork_session`
+    /// from the client's options, so reconnecting doesn't try again.
+    pub resume_fallback_new: bool,
     /// Maximum turns.
     pub max_turns: Option<u32>,
     /// Maximum budget in USD.
     pub max_budget_usd: Option<f64>,
+    /// Caps on a single turn, enforced while it runs (default: none).
+    ///
+    /// The CLI checks [`max_budget_usd`](Self::max_budget_usd) between
+    /// turns, so one long tool-using turn can run far past it. With a turn
+    /// budget, the SDK adds up the usage the CLI reports during the turn and
+    /// interrupts the turn once over. Its result then reports
+    /// [`CompletionReason::BudgetExceeded`].
+    pub turn_budget: Option<TurnBudget>,
+    /// What to do as the conversation fills the context window (default:
+    /// nothing).
+    ///
+    /// The SDK follows how full the context is from the usage the CLI
+    /// reports, reports it through [`ClaudeClient::progress`](crate::ClaudeClient::progress)
+    /// as soon as the threshold is crossed, even mid-turn, and either
+    /// compacts the conversation before the next prompt or only warns.
+    /// Compaction is only triggered by [`ClaudeClient`](crate::ClaudeClient)
+    /// queries. Compactions the CLI does on its own reset the count.
+    pub context_policy: Option<ContextPolicy>,
     /// Disallowed tools.
     pub disallowed_tools: Vec<String>,
     /// Model to use.
@@ -1178,12 +3159,28 @@ pub struct ClaudeAgentOptions {
     pub cwd: Option<PathBuf>,
     /// Path to CLI executable.
     pub cli_path: Option<PathBuf>,
+    /// Transports to try in order when the CLI is missing or older than
+    /// [`MIN_CLI_VERSION`](crate::MIN_CLI_VERSION) (default: none).
+    ///
+    /// Each failover is published as a
+    /// [`Diagnostic`](crate::diagnostics::Diagnostic) with the code
+    /// `transport_failover`. Other connect failures are returned as they
+    /// are. With fallbacks configured, the CLI's version is checked before
+    /// it is started.
+    pub fallback_transports: Vec<FallbackTransport>,
     /// Settings string.
     pub settings: Option<String>,
     /// Additional directories.
     pub add_dirs: Vec<PathBuf>,
     /// Environment variables.
     pub env: HashMap<String, String>,
+    /// Expand `${VAR}` references in the system prompt, the settings and
+    /// inline MCP server configurations when connecting (default: false).
+    ///
+    /// See [`env_vars`](crate::env_vars) for the syntax. A reference to a
+    /// variable that isn't set, in [`env`](Self::env) or the environment of
+    /// the process, fails the connect.
+    pub expand_env: bool,
     /// Extra CLI arguments.
     pub extra_args: HashMap<String, Option<String>>,
     /// Maximum buffer size for stdout.
@@ -1198,6 +3195,29 @@ pub struct ClaudeAgentOptions {
     pub user: Option<String>,
     /// Include partial messages in stream.
     pub include_partial_messages: bool,
+    /// When partial messages are included, emit a best-effort assistant
+    /// message rebuilt from the stream events if the CLI doesn't deliver
+    /// the complete one, e.g. after an interrupt (default: false). Such
+    /// messages have [`synthesized`](AssistantMessage::synthesized) set.
+    /// Tool calls whose input was cut off are left out of them.
+    pub synthesize_partial_messages: bool,
+    /// When partial messages are included, merge consecutive text deltas of
+    /// the same content block into fewer stream events (default: off).
+    ///
+    /// A merged event holds the concatenated text and is delivered when the
+    /// interval elapses, the size limit is reached, or any other message
+    /// arrives, so ordering is preserved. Merged events take a single
+    /// [sequence number](Message::meta) and have no [`raw`](Message::raw)
+    /// JSON.
+    pub partial_coalescing: Option<PartialCoalescing>,
+    /// When partial messages are included, let at most this many stream
+    /// events wait for a consumer that has fallen behind, dropping the
+    /// oldest beyond that (default: unlimited, so the reader waits).
+    ///
+    /// Other messages are never dropped: a slow consumer sees fewer, coarser
+    /// stream events instead of holding up the whole session. Dropped
+    /// events leave gaps in the [sequence numbers](MessageMeta::follows_gap).
+    pub max_pending_partial_messages: Option<usize>,
     /// Fork session when resuming.
     pub fork_session: bool,
     /// Agent definitions.
@@ -1214,9 +3234,133 @@ pub struct ClaudeAgentOptions {
     pub output_format: Option<serde_json::Value>,
     /// Enable file checkpointing.
     pub enable_file_checkpointing: bool,
-    /// Timeout in seconds for CLI operations (default: 300 = 5 minutes).
-    /// Set to 0 to disable timeout.
-    pub timeout_secs: Option<u64>,
+    /// How long connecting, the first message of a turn, the gaps between
+    /// messages and control requests may take (default: 5 minutes to
+    /// connect and per control request, no limit on messages).
+    ///
+    /// The [`deadline`](Self::deadline) applies on top of these.
+    pub timeouts: Timeouts,
+    /// Number of recent messages to retain for late-attaching observers
+    /// (default: 0 = disabled).
+    pub message_history_size: usize,
+    /// Messages buffered for each
+    /// [`ClaudeClient::subscribe`](crate::ClaudeClient::subscribe) receiver
+    /// before it starts lagging (default: 256).
+    pub subscriber_capacity: Option<usize>,
+    /// Keep the raw JSON of each parsed message, available via
+    /// [`Message::raw`] (default: false).
+    pub keep_raw_messages: bool,
+    /// How much of each frame read from or written to the CLI is logged at
+    /// `trace` level (default: the first 200 bytes).
+    ///
+    /// Frames can hold file contents and tool output, so only turn on
+    /// [`LogSampling::Full`] where such logs are safe to keep.
+    pub log_sampling: LogSampling,
+    /// Largest frame the SDK writes to the CLI (default: no limit).
+    ///
+    /// A frame over the limit fails with
+    /// [`FrameTooLarge`](crate::ClaudeSDKError::FrameTooLarge) instead of
+    /// being written. Responses to permission and hook callbacks are
+    /// shrunk per the limit's [`OversizedInput`] strategy first, and
+    /// answered with an error if they still don't fit.
+    pub outbound_frame_limit: Option<OutboundFrameLimit>,
+    /// How to react to protocol frames the SDK can't handle (default: warn).
+    pub protocol_strictness: ProtocolStrictness,
+    /// What to do with options the installed CLI is too old to support
+    /// (default: omit them with a warning).
+    pub unsupported_flags: UnsupportedFlags,
+    /// Overall deadline bounding spawn, control requests and the message
+    /// stream. Expiry surfaces as a [`Timeout`](crate::ClaudeSDKError::Timeout)
+    /// error tagged with the phase that was in progress; past it while
+    /// streaming, the turn is also interrupted.
+    ///
+    /// The deadline holds for the whole session: a prompt sent to a
+    /// [`ClaudeClient`](crate::ClaudeClient) after it has passed fails with
+    /// a `Timeout` in the [`Stream`](crate::TimeoutPhase::Stream) phase.
+    ///
+    /// Timeouts run on tokio's clock, so tests using
+    /// `tokio::time::pause()` can build the deadline from
+    /// `tokio::time::Instant::now().into_std()` and expire it instantly.
+    pub deadline: Option<std::time::Instant>,
+    /// Write tool results above a size threshold to disk, replacing their
+    /// content with a [`ContentRef`] (default: keep everything in memory).
+    ///
+    /// Results are spooled as their frames are read, before the messages
+    /// are parsed, so raw JSON kept by
+    /// [`keep_raw_messages`](Self::keep_raw_messages) holds the
+    /// `content_ref` too. The spool files are deleted when the client
+    /// disconnects or is dropped; copy what should outlive the session.
+    pub tool_output_spool: Option<ToolOutputSpool>,
+    /// Labels identifying the session (tenant, job, ...). Kept by the SDK
+    /// only — never sent to the CLI.
+    ///
+    /// Everything the session's tasks log — errors, callback logs, result
+    /// and shutdown reports — is logged in a `claude_session` span carrying
+    /// the tags as of connecting. They are also recorded in the
+    /// [`SessionManifest`] and in each
+    /// [`MessageEnvelope`](crate::MessageEnvelope).
+    pub session_tags: HashMap<String, String>,
+    /// Terminate the processes started by the CLI's tools (shells, servers,
+    /// ...) along with the CLI when the session ends (default: false).
+    ///
+    /// On Unix the CLI runs in its own process group, so it no longer
+    /// receives the terminal's Ctrl+C; on Windows it is placed in a job
+    /// object.
+    pub kill_descendants_on_drop: bool,
+    /// Keep the directory of
+    /// [`ClaudeClient::session_tmp_dir`](crate::ClaudeClient::session_tmp_dir)
+    /// on disconnect, e.g. to inspect what a session left there (default:
+    /// false).
+    pub keep_session_tmp_dir: bool,
+    /// Run the CLI as a different user (default: the SDK's own user).
+    ///
+    /// See [`RunAs`]. Unix and Windows only; connecting fails elsewhere.
+    pub run_as: Option<RunAs>,
+    /// Directory the CLI keeps its configuration, login and session
+    /// transcripts in, passed as `CLAUDE_CONFIG_DIR` (default: the CLI's
+    /// own, usually `~/.claude`). An explicit `CLAUDE_CONFIG_DIR` in
+    /// [`env`](Self::env) takes precedence.
+    ///
+    /// See [`ConfigDir`](crate::ConfigDir) for creating an isolated one.
+    pub config_dir: Option<PathBuf>,
+    /// Callback run when the CLI reports mid-session that it needs to be
+    /// logged in again, e.g. after its OAuth token expired (default: none).
+    ///
+    /// [`ClaudeClient::receive_response`](crate::ClaudeClient::receive_response)
+    /// fails with [`AuthRequired`](crate::ClaudeSDKError::AuthRequired) in
+    /// that case. If this callback resolves to `true`, meaning new
+    /// credentials are in place, the session is restarted so the CLI picks
+    /// them up, and the prompt is sent again instead.
+    pub on_auth_required: Option<OnAuthRequired>,
+    /// Retry turns that fail with a transient API error (default: no
+    /// retries). See [`TurnRetry`].
+    pub turn_retry: Option<TurnRetry>,
+    /// Schemas to validate tool inputs against (default: none).
+    ///
+    /// Each permission request is validated before the
+    /// [`can_use_tool`](Self::can_use_tool) callback runs, and the report
+    /// is passed in [`ToolPermissionContext::validation`]; this only covers
+    /// calls that ask for permission, so it requires the callback. With [`ToolSchemaRegistry::deny_invalid`],
+    /// every call is also validated in a `PreToolUse` hook running before
+    /// the [`hooks`](Self::hooks), and invalid ones are denied regardless
+    /// of the permission mode or `allowed_tools`.
+    pub tool_schemas: Option<ToolSchemaRegistry>,
+    /// SDK-side guard denying writes and shell commands that reach outside
+    /// `cwd` and `add_dirs` (default: none).
+    ///
+    /// Enforced by a `PreToolUse` hook running before the
+    /// [`hooks`](Self::hooks), so it covers every tool call regardless of
+    /// the permission mode or `allowed_tools`, independently of
+    /// [`sandbox`](Self::sandbox).
+    pub workspace_guard: Option<WorkspaceGuard>,
+    /// Permission policies to enforce (default: none), added with
+    /// [`with_permission_policy`](Self::with_permission_policy).
+    ///
+    /// Their `PreToolUse` hooks run after the
+    /// [`workspace_guard`](Self::workspace_guard)'s and before the
+    /// [`hooks`](Self::hooks). Their quota counts for a session are dropped
+    /// when the client disconnects from it.
+    pub permission_policies: Vec<PermissionPolicy>,
 }
 
 impl std::fmt::Debug for ClaudeAgentOptions {
@@ -1228,10 +3372,18 @@ impl std::fmt::Debug for ClaudeAgentOptions {
             .field("permission_mode", &self.permission_mode)
             .field("continue_conversation", &self.continue_conversation)
             .field("resume", &self.resume)
+            .field("resume_session_at", &self.resume_session_at)
+            .field("resume_fallback_new", &self.resume_fallback_new)
             .field("max_turns", &self.max_turns)
             .field("max_budget_usd", &self.max_budget_usd)
+            .field("turn_budget", &self.turn_budget)
+            .field("context_policy", &self.context_policy)
             .field("disallowed_tools", &self.disallowed_tools)
             .field("model", &self.model)
+            .field("expand_env", &self.expand_env)
+            .field("log_sampling", &self.log_sampling)
+            .field("outbound_frame_limit", &self.outbound_frame_limit)
+            .field("fallback_transports", &self.fallback_transports)
             .field(
                 "can_use_tool",
                 &self.can_use_tool.as_ref().map(|_| "<callback>"),
@@ -1241,6 +3393,11 @@ impl std::fmt::Debug for ClaudeAgentOptions {
                 &self.hooks.as_ref().map(|h| format!("{} events", h.len())),
             )
             .field("stderr", &self.stderr.as_ref().map(|_| "<callback>"))
+            .field(
+                "on_auth_required",
+                &self.on_auth_required.as_ref().map(|_| "<callback>"),
+            )
+            .field("session_tags", &self.session_tags)
             .finish_non_exhaustive()
     }
 }
@@ -1293,15 +3450,196 @@ impl ClaudeAgentOptions {
         self
     }
 
+    /// Enable partial message streaming and synthesize assistant messages
+    /// the CLI doesn't deliver (see
+    /// [`synthesize_partial_messages`](Self::synthesize_partial_messages)).
+    pub fn with_synthesized_partial_messages(mut self) -> Self {
+        self.include_partial_messages = true;
+        self.synthesize_partial_messages = true;
+        self
+    }
+
+    /// Log frames exchanged with the CLI per `sampling` (see
+    /// [`log_sampling`](Self::log_sampling)).
+    pub fn with_log_sampling(mut self, sampling: LogSampling) -> Self {
+        self.log_sampling = sampling;
+        self
+    }
+
+    /// Limit the size of frames written to the CLI (see
+    /// [`outbound_frame_limit`](Self::outbound_frame_limit)).
+    pub fn with_outbound_frame_limit(mut self, limit: OutboundFrameLimit) -> Self {
+        self.outbound_frame_limit = Some(limit);
+        self
+    }
+
+    /// Enable partial message streaming with text deltas coalesced per
+    /// `coalescing` (see [`partial_coalescing`](Self::partial_coalescing)).
+    pub fn with_partial_coalescing(mut self, coalescing: PartialCoalescing) -> Self {
+        self.include_partial_messages = true;
+        self.partial_coalescing = Some(coalescing);
+        self
+    }
+
+    /// Interrupt any turn going over `budget` (see
+    /// [`turn_budget`](Self::turn_budget)).
+    pub fn with_turn_budget(mut self, budget: TurnBudget) -> Self {
+        self.turn_budget = Some(budget);
+        self
+    }
+
+    /// Compact or warn as the context fills up (see
+    /// [`context_policy`](Self::context_policy)).
+    pub fn with_context_policy(mut self, policy: ContextPolicy) -> Self {
+        self.context_policy = Some(policy);
+        self
+    }
+
+    /// Expand `${VAR}` references when connecting (see
+    /// [`expand_env`](Self::expand_env)).
+    pub fn with_expand_env(mut self, expand: bool) -> Self {
+        self.expand_env = expand;
+        self
+    }
+
+    /// Enable partial message streaming, dropping the oldest stream events
+    /// when more than `limit` wait for the consumer (see
+    /// [`max_pending_partial_messages`](Self::max_pending_partial_messages)).
+    pub fn with_max_pending_partial_messages(mut self, limit: usize) -> Self {
+        self.include_partial_messages = true;
+        self.max_pending_partial_messages = Some(limit);
+        self
+    }
+
     /// Set the timeout for CLI operations in seconds.
     ///
     /// Default is 300 seconds (5 minutes). Set to 0 to disable timeout.
     /// This timeout applies to:
     /// - Initial CLI process startup
     /// - Control protocol requests (initialize, can_use_tool, etc.)
-    /// - Waiting for CLI responses
+    ///
+    /// Shorthand for [`with_timeouts`](Self::with_timeouts) with
+    /// [`Timeouts::from_secs`].
     pub fn with_timeout_secs(mut self, timeout: u64) -> Self {
-        self.timeout_secs = Some(timeout);
+        self.timeouts = Timeouts::from_secs(timeout);
+        self
+    }
+
+    /// Set how long each stage of a session may take.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Retain the last `size` messages for [`ClaudeClient::recent_messages`](crate::ClaudeClient::recent_messages).
+    pub fn with_message_history(mut self, size: usize) -> Self {
+        self.message_history_size = size;
+        self
+    }
+
+    /// Buffer up to `capacity` messages per subscriber (see
+    /// [`subscriber_capacity`](Self::subscriber_capacity)).
+    pub fn with_subscriber_capacity(mut self, capacity: usize) -> Self {
+        self.subscriber_capacity = Some(capacity);
+        self
+    }
+
+    /// Keep the raw JSON of each parsed message (see [`Message::raw`]).
+    pub fn with_raw_messages(mut self) -> Self {
+        self.keep_raw_messages = true;
+        self
+    }
+
+    /// Spool tool results larger than `threshold_bytes` to files in `dir`
+    /// (see [`tool_output_spool`](Self::tool_output_spool)).
+    pub fn with_tool_output_spool(
+        mut self,
+        dir: impl Into<PathBuf>,
+        threshold_bytes: usize,
+    ) -> Self {
+        self.tool_output_spool = Some(ToolOutputSpool {
+            dir: dir.into(),
+            threshold_bytes,
+        });
+        self
+    }
+
+    /// Kill the CLI's descendant processes with it (see
+    /// [`kill_descendants_on_drop`](Self::kill_descendants_on_drop)).
+    pub fn with_kill_descendants_on_drop(mut self, kill: bool) -> Self {
+        self.kill_descendants_on_drop = kill;
+        self
+    }
+
+    /// Fall back on `transport` if the CLI, and any fallback added before,
+    /// can't be used (see
+    /// [`fallback_transports`](Self::fallback_transports)).
+    pub fn with_fallback_transport(mut self, transport: FallbackTransport) -> Self {
+        self.fallback_transports.push(transport);
+        self
+    }
+
+    /// Keep the session temp directory on disconnect (see
+    /// [`keep_session_tmp_dir`](Self::keep_session_tmp_dir)).
+    pub fn with_keep_session_tmp_dir(mut self, keep: bool) -> Self {
+        self.keep_session_tmp_dir = keep;
+        self
+    }
+
+    /// Run the CLI as another user (see [`run_as`](Self::run_as)).
+    pub fn with_run_as(mut self, run_as: RunAs) -> Self {
+        self.run_as = Some(run_as);
+        self
+    }
+
+    /// Use a separate CLI configuration directory (see
+    /// [`config_dir`](Self::config_dir)).
+    pub fn with_config_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config_dir = Some(dir.into());
+        self
+    }
+
+    /// Validate tool inputs against `registry` (see
+    /// [`tool_schemas`](Self::tool_schemas)).
+    pub fn with_tool_schemas(mut self, registry: ToolSchemaRegistry) -> Self {
+        self.tool_schemas = Some(registry);
+        self
+    }
+
+    /// Deny tool calls outside the workspace (see
+    /// [`workspace_guard`](Self::workspace_guard)).
+    pub fn with_workspace_guard(mut self, guard: WorkspaceGuard) -> Self {
+        self.workspace_guard = Some(guard);
+        self
+    }
+
+    /// Add a session tag (see [`session_tags`](Self::session_tags)).
+    pub fn with_session_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.session_tags.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set an overall deadline for the query (see [`deadline`](Self::deadline)).
+    pub fn with_deadline(mut self, deadline: std::time::Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Set how the SDK reacts to protocol frames it can't handle.
+    pub fn with_protocol_strictness(mut self, strictness: ProtocolStrictness) -> Self {
+        self.protocol_strictness = strictness;
+        self
+    }
+
+    /// Set what to do with options the CLI is too old to support.
+    pub fn with_unsupported_flags(mut self, policy: UnsupportedFlags) -> Self {
+        self.unsupported_flags = policy;
+        self
+    }
+
+    /// Set hooks from a [`HookRegistry`](crate::HookRegistry).
+    pub fn with_hooks(mut self, hooks: impl Into<HashMap<HookEvent, Vec<HookMatcher>>>) -> Self {
+        self.hooks = Some(hooks.into());
         self
     }
 
@@ -1316,173 +3654,493 @@ impl ClaudeAgentOptions {
         }));
         self
     }
+
+    /// Set a callback for when the CLI needs to be logged in again (see
+    /// [`on_auth_required`](Self::on_auth_required)).
+    pub fn with_on_auth_required<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.on_auth_required = Some(Arc::new(move |instructions| {
+            Box::pin(callback(instructions))
+        }));
+        self
+    }
+
+    /// Retry turns that fail with a transient API error (see
+    /// [`turn_retry`](Self::turn_retry)).
+    pub fn with_turn_retry(mut self, retry: TurnRetry) -> Self {
+        self.turn_retry = Some(retry);
+        self
+    }
+
+    /// Add hook matchers, keeping any hooks already configured.
+    pub fn add_hooks(&mut self, hooks: impl Into<HashMap<HookEvent, Vec<HookMatcher>>>) {
+        let existing = self.hooks.get_or_insert_with(HashMap::new);
+        for (event, matchers) in hooks.into() {
+            existing.entry(event).or_default().extend(matchers);
+        }
+    }
+
+    /// Only use the configured MCP servers (see
+    /// [`strict_mcp_config`](Self::strict_mcp_config)).
+    pub fn with_strict_mcp_config(mut self) -> Self {
+        self.strict_mcp_config = true;
+        self
+    }
+
+    /// Leave the MCP server `name` out when the CLI starts (see
+    /// [`disabled_mcp_servers`](Self::disabled_mcp_servers)).
+    pub fn with_mcp_server_disabled(mut self, name: impl Into<String>) -> Self {
+        self.disabled_mcp_servers.push(name.into());
+        self
+    }
+
+    /// Fail to connect if an MCP server can't be started (see
+    /// [`require_mcp_servers`](Self::require_mcp_servers)).
+    pub fn with_required_mcp_servers(mut self) -> Self {
+        self.require_mcp_servers = true;
+        self
+    }
+
+    /// Add an MCP server, keeping any servers already configured.
+    ///
+    /// Replaces a [`McpServersConfig::Path`] configuration.
+    pub fn with_mcp_server(mut self, name: impl Into<String>, config: McpServerConfig) -> Self {
+        if let McpServersConfig::Path(ref path) = self.mcp_servers {
+            tracing::warn!(
+                "Replacing MCP config file {} with inline server configuration",
+                path.display()
+            );
+            self.mcp_servers = McpServersConfig::default();
+        }
+        if let McpServersConfig::Map(ref mut servers) = self.mcp_servers {
+            servers.insert(name.into(), config);
+        }
+        self
+    }
+}
+
+/// Phases of establishing a streaming session, reported by
+/// [`ClaudeClient::connect_with_progress`](crate::ClaudeClient::connect_with_progress).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectPhase {
+    /// The CLI process has been spawned.
+    Spawned,
+    /// The control protocol reader is running.
+    ProtocolReady,
+    /// The CLI acknowledged the initialize request.
+    Initialized,
+}
+
+/// Stages of shutting down a session, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownStage {
+    /// Signal the protocol reader task to stop and join it.
+    StopReader,
+    /// Close the CLI's stdin so it can exit on its own.
+    CloseInput,
+    /// Wait for the CLI process to exit.
+    WaitForExit,
+    /// Kill the CLI process after it failed to exit in time.
+    KillProcess,
+    /// Kill the processes started by the CLI's tools (with
+    /// [`kill_descendants_on_drop`](ClaudeAgentOptions::kill_descendants_on_drop)).
+    KillProcessTree,
+    /// Join the tasks reading the CLI's stdout and stderr.
+    JoinOutputReaders,
+    /// Close a transport other than the CLI's own, in place of the stages
+    /// from [`CloseInput`](Self::CloseInput) on.
+    CloseTransport,
+}
+
+/// Outcome of one [`ShutdownStage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownStageReport {
+    /// The stage.
+    pub stage: ShutdownStage,
+    /// How long the stage took.
+    pub duration: Duration,
+    /// Whether the stage hit its timeout; tasks are aborted and processes
+    /// killed in that case.
+    pub timed_out: bool,
+}
+
+/// What happened while shutting down a session, returned by
+/// [`ClaudeClient::shutdown`](crate::ClaudeClient::shutdown).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// The stages that ran, in order.
+    pub stages: Vec<ShutdownStageReport>,
+    /// Whether the CLI process had to be killed.
+    pub forced_kill: bool,
+}
+
+impl ShutdownReport {
+    /// Total time spent shutting down.
+    pub fn duration(&self) -> Duration {
+        self.stages.iter().map(|s| s.duration).sum()
+    }
+
+    /// Get the report of `stage`, if it ran.
+    pub fn stage(&self, stage: ShutdownStage) -> Option<&ShutdownStageReport> {
+        self.stages.iter().find(|s| s.stage == stage)
+    }
+
+    /// Run `fut` as `stage`, giving up after `timeout`.
+    ///
+    /// Returns the output, or `None` on timeout.
+    pub(crate) async fn run_stage<T>(
+        &mut self,
+        stage: ShutdownStage,
+        timeout: Duration,
+        fut: impl Future<Output = T>,
+    ) -> Option<T> {
+        let started = tokio::time::Instant::now();
+        let output = tokio::time::timeout(timeout, fut).await.ok();
+        self.stages.push(ShutdownStageReport {
+            stage,
+            duration: started.elapsed(),
+            timed_out: output.is_none(),
+        });
+        output
+    }
 }
 
 // ============================================================================
-// Control Protocol Types
+// Protocol Anomalies
 // ============================================================================
 
-/// Control request subtypes.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "subtype")]
-pub enum ControlRequestPayload {
-    /// Interrupt request.
-    #[serde(rename = "interrupt")]
-    Interrupt,
-    /// Tool permission request.
-    #[serde(rename = "can_use_tool")]
-    CanUseTool {
-        /// Tool name.
-        tool_name: String,
-        /// Tool input.
-        input: serde_json::Value,
-        /// Permission suggestions.
-        permission_suggestions: Option<Vec<serde_json::Value>>,
-        /// Blocked path.
-        blocked_path: Option<String>,
-    },
-    /// Initialize request.
-    #[serde(rename = "initialize")]
-    Initialize {
-        /// Hook configurations.
-        hooks: Option<serde_json::Value>,
-    },
-    /// Set permission mode request.
-    #[serde(rename = "set_permission_mode")]
-    SetPermissionMode {
-        /// New mode.
-        mode: String,
-    },
-    /// Set model request.
-    #[serde(rename = "set_model")]
-    SetModel {
-        /// New model.
-        model: String,
-    },
-    /// Hook callback request.
-    #[serde(rename = "hook_callback")]
-    HookCallback {
-        /// Callback ID.
-        callback_id: String,
-        /// Hook input.
-        input: serde_json::Value,
-        /// Tool use ID.
-        tool_use_id: Option<String>,
-    },
-    /// MCP message request.
-    #[serde(rename = "mcp_message")]
-    McpMessage {
-        /// Server name.
-        server_name: String,
-        /// JSONRPC message.
-        message: serde_json::Value,
-    },
-    /// MCP status request.
-    #[serde(rename = "mcp_status")]
-    McpStatus,
-    /// Rewind files request.
-    #[serde(rename = "rewind_files")]
-    RewindFiles {
-        /// User message ID to rewind to.
-        user_message_id: String,
-    },
+/// Kinds of protocol frames the SDK could not handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ProtocolAnomalyKind {
+    /// A control request with a subtype the SDK doesn't recognize, or
+    /// that couldn't be parsed (answered with an error).
+    UnknownControlRequest,
+    /// A recognized control request the SDK doesn't handle (answered with `null`).
+    UnhandledControlRequest,
+    /// A control response that matches no pending request.
+    UnmatchedControlResponse,
+    /// A control response that could not be parsed.
+    MalformedControlResponse,
+    /// A message with an unknown `type`.
+    UnknownMessageType,
 }
 
-/// Control request.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ControlRequest {
-    /// Always "control_request".
-    #[serde(rename = "type")]
-    pub request_type: String,
-    /// Request ID.
-    pub request_id: String,
-    /// Request payload.
-    pub request: ControlRequestPayload,
+/// A protocol frame the SDK dropped or could not handle.
+#[derive(Debug, Clone)]
+pub struct ProtocolAnomaly {
+    /// What went wrong.
+    pub kind: ProtocolAnomalyKind,
+    /// The frame's subtype or type, if present.
+    pub subtype: Option<String>,
+    /// The control request ID, if present.
+    pub request_id: Option<String>,
+    /// The raw frame.
+    pub raw: serde_json::Value,
 }
 
-/// Success response.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ControlSuccessResponse {
-    /// Always "success".
-    pub subtype: String,
-    /// Request ID.
-    pub request_id: String,
-    /// Response data.
-    pub response: Option<serde_json::Value>,
+impl std::fmt::Display for ProtocolAnomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.kind)?;
+        if let Some(ref subtype) = self.subtype {
+            write!(f, " '{}'", subtype)?;
+        }
+        if let Some(ref request_id) = self.request_id {
+            write!(f, " (request {})", request_id)?;
+        }
+        Ok(())
+    }
 }
 
-/// Error response.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ControlErrorResponse {
-    /// Always "error".
-    pub subtype: String,
-    /// Request ID.
-    pub request_id: String,
-    /// Error message.
-    pub error: String,
+/// Callback invoked for each protocol anomaly.
+pub type ProtocolAnomalyCallback = Arc<dyn Fn(&ProtocolAnomaly) + Send + Sync>;
+
+/// A user to run the CLI as, keeping the agent's tools away from the host
+/// user's credentials and dotfiles.
+///
+/// On Unix the CLI switches to `uid` and `gid` before it starts, without
+/// the host user's supplementary groups, and gets `HOME`, `USER` and
+/// `LOGNAME` of the target account unless
+/// [`env`](ClaudeAgentOptions::env) sets them. This needs the SDK to run as
+/// root or with `CAP_SETUID` and `CAP_SETGID`; otherwise connecting fails
+/// with [`InsufficientPrivileges`](crate::CLILaunchErrorKind::InsufficientPrivileges).
+/// The target user must be able to run the CLI and enter `cwd`.
+///
+/// On Windows `uid`, `gid` and `home` are ignored: the CLI runs under a
+/// restricted version of the SDK's own token, with the administrators
+/// group disabled and all privileges removed. The CLI must then be an
+/// executable rather than a `.cmd` or `.bat` wrapper.
+///
+/// The CLI doesn't inherit the SDK's environment: it gets only the
+/// variables named in [`inherit_env`](Self::inherit_env), plus those set
+/// in `env`. It needs its own CLI login or an API key passed through `env`.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::{ClaudeAgentOptions, RunAs};
+///
+/// let options = ClaudeAgentOptions::new()
+///     .with_run_as(RunAs::new(1500, 1500).with_home("/srv/agent"))
+///     .with_cwd("/srv/agent/project");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RunAs {
+    /// User ID.
+    pub uid: u32,
+    /// Primary group ID.
+    pub gid: u32,
+    /// Home directory; looked up from the user database if unset.
+    pub home: Option<PathBuf>,
+    /// Host environment variables passed on to the CLI (default: the
+    /// platform's path, locale, terminal and temp directory variables).
+    pub inherit_env: Vec<String>,
 }
 
-/// Control response payload.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "subtype")]
-pub enum ControlResponsePayload {
-    /// Success response.
-    #[serde(rename = "success")]
-    Success {
-        /// Request ID.
-        request_id: String,
-        /// Response data.
-        response: Option<serde_json::Value>,
-    },
-    /// Error response.
-    #[serde(rename = "error")]
-    Error {
-        /// Request ID.
-        request_id: String,
-        /// Error message.
-        error: String,
-    },
+impl RunAs {
+    /// Run as `uid` with primary group `gid`.
+    pub fn new(uid: u32, gid: u32) -> Self {
+        #[cfg(windows)]
+        const INHERITED: &[&str] = &[
+            "PATH",
+            "PATHEXT",
+            "SYSTEMROOT",
+            "SYSTEMDRIVE",
+            "WINDIR",
+            "COMSPEC",
+            "TEMP",
+            "TMP",
+            "USERPROFILE",
+            "APPDATA",
+            "LOCALAPPDATA",
+            "HOMEDRIVE",
+            "HOMEPATH",
+        ];
+        #[cfg(not(windows))]
+        const INHERITED: &[&str] = &["PATH", "LANG", "LC_ALL", "LC_CTYPE", "TERM", "TZ", "TMPDIR"];
+        Self {
+            uid,
+            gid,
+            home: None,
+            inherit_env: INHERITED.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    /// Set the home directory.
+    pub fn with_home(mut self, home: impl Into<PathBuf>) -> Self {
+        self.home = Some(home.into());
+        self
+    }
+
+    /// Set the host environment variables passed on to the CLI (see
+    /// [`inherit_env`](Self::inherit_env)).
+    pub fn with_inherited_env<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.inherit_env = names.into_iter().map(Into::into).collect();
+        self
+    }
 }
 
-/// Control response.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ControlResponse {
-    /// Always "control_response".
-    #[serde(rename = "type")]
-    pub response_type: String,
-    /// Response payload.
-    pub response: ControlResponsePayload,
+/// Callback told about each retry of a [`TurnRetry`].
+pub type TurnRetryCallback = Arc<dyn Fn(&TurnRetryEvent) + Send + Sync>;
+
+/// A failed turn about to be retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurnRetryEvent {
+    /// Which retry this is, starting at 1.
+    pub attempt: u32,
+    /// The error the turn failed with.
+    pub error: AssistantMessageError,
+    /// Text of the assistant message carrying the error.
+    pub message: String,
+    /// How long the SDK waits before sending the prompt again.
+    pub delay: Duration,
+}
+
+/// Retrying turns that fail with a transient API error.
+///
+/// When an assistant message in a response carries one of the retried
+/// [`errors`](Self::errors),
+/// [`ClaudeClient::receive_response`](crate::ClaudeClient::receive_response)
+/// waits, sends the prompt again in the same session, and returns the
+/// response to that instead. The delay doubles with each retry up to
+/// `max_delay`, and is randomized by `jitter` so that many sessions hit by
+/// the same outage don't retry in lockstep.
+///
+/// Only `receive_response` retries: messages read with
+/// [`receive_messages`](crate::ClaudeClient::receive_messages) or a split
+/// client's [`MessageReceiver`](crate::MessageReceiver) are delivered as
+/// they come, the failed turn included. Since the prompt is sent again in
+/// the same session, the conversation holds the failed turn and the prompt
+/// twice, and the model sees both.
+///
+/// # Examples
+///
+/// ```rust
+/// use claude_agents_sdk::{ClaudeAgentOptions, TurnRetry};
+/// use std::time::Duration;
+///
+/// let options = ClaudeAgentOptions::new().with_turn_retry(
+///     TurnRetry::new(3)
+///         .with_delays(Duration::from_millis(500), Duration::from_secs(10))
+///         .on_retry(|event| eprintln!("Retry {}: {}", event.attempt, event.message)),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct TurnRetry {
+    /// How many times a turn is retried.
+    pub max_retries: u32,
+    /// Delay before the first retry (default: 1 second).
+    pub initial_delay: Duration,
+    /// Longest delay between retries (default: 30 seconds).
+    pub max_delay: Duration,
+    /// Fraction of each delay that is random, from 0 to 1 (default: 0.5):
+    /// the SDK waits between `(1 - jitter) * delay` and `delay`.
+    pub jitter: f64,
+    /// Errors that are retried (default: server and unknown errors).
+    pub errors: Vec<AssistantMessageError>,
+    /// Called before each retry.
+    pub on_retry: Option<TurnRetryCallback>,
+}
+
+impl std::fmt::Debug for TurnRetry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TurnRetry")
+            .field("max_retries", &self.max_retries)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .field("errors", &self.errors)
+            .field("on_retry", &self.on_retry.as_ref().map(|_| "<callback>"))
+            .finish()
+    }
 }
 
-impl ControlResponse {
-    /// Get the request ID.
-    pub fn request_id(&self) -> &str {
-        match &self.response {
-            ControlResponsePayload::Success { request_id, .. } => request_id,
-            ControlResponsePayload::Error { request_id, .. } => request_id,
+impl TurnRetry {
+    /// Retry failed turns up to `max_retries` times.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.5,
+            errors: vec![
+                AssistantMessageError::ServerError,
+                AssistantMessageError::Unknown,
+            ],
+            on_retry: None,
         }
     }
 
-    /// Check if this is a success response.
-    pub fn is_success(&self) -> bool {
-        matches!(&self.response, ControlResponsePayload::Success { .. })
+    /// Set the delay before the first retry and the longest delay.
+    pub fn with_delays(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_delay = initial;
+        self.max_delay = max;
+        self
+    }
+
+    /// Set the fraction of each delay that is random.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the errors that are retried.
+    pub fn with_errors(mut self, errors: impl IntoIterator<Item = AssistantMessageError>) -> Self {
+        self.errors = errors.into_iter().collect();
+        self
+    }
+
+    /// Call `callback` before each retry.
+    pub fn on_retry(mut self, callback: impl Fn(&TurnRetryEvent) + Send + Sync + 'static) -> Self {
+        self.on_retry = Some(Arc::new(callback));
+        self
+    }
+
+    /// The delay before retry number `attempt` (starting at 1).
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        use crate::_internal::backoff;
+
+        let delay = backoff::exponential(self.initial_delay, self.max_delay, attempt);
+        backoff::jittered(delay, self.jitter)
     }
+}
+
+/// What to do with options the installed CLI is too old to support.
+///
+/// Checked when connecting, by running `claude --version` once per CLI
+/// path if the options use a flag added after
+/// [`MIN_CLI_VERSION`](crate::MIN_CLI_VERSION).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnsupportedFlags {
+    /// Leave the flag out and log a warning (default).
+    #[default]
+    Omit,
+    /// Fail to connect with a [`Configuration`](crate::ClaudeSDKError::Configuration)
+    /// error naming the option and the CLI version it needs.
+    Error,
+    /// Pass every flag without checking the CLI version.
+    Pass,
+}
+
+/// How the SDK reacts to protocol frames it can't handle.
+#[derive(Clone, Default)]
+pub enum ProtocolStrictness {
+    /// Log a warning and continue (default).
+    #[default]
+    Warn,
+    /// Surface an error on the message stream.
+    Error,
+    /// Invoke a callback and continue.
+    Callback(ProtocolAnomalyCallback),
+}
 
-    /// Get the response data if successful.
-    pub fn data(&self) -> Option<&serde_json::Value> {
-        match &self.response {
-            ControlResponsePayload::Success { response, .. } => response.as_ref(),
-            ControlResponsePayload::Error { .. } => None,
+impl std::fmt::Debug for ProtocolStrictness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Warn => write!(f, "Warn"),
+            Self::Error => write!(f, "Error"),
+            Self::Callback(_) => write!(f, "Callback(<callback>)"),
         }
     }
+}
+
+/// Summary of the protocol anomalies observed during a session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolAnomalyReport {
+    /// Number of anomalies by kind.
+    pub counts: std::collections::BTreeMap<ProtocolAnomalyKind, u64>,
+    /// Distinct unknown subtypes observed, with occurrence counts.
+    pub subtypes: std::collections::BTreeMap<(ProtocolAnomalyKind, String), u64>,
+}
 
-    /// Get the error message if failed.
-    pub fn error(&self) -> Option<&str> {
-        match &self.response {
-            ControlResponsePayload::Success { .. } => None,
-            ControlResponsePayload::Error { error, .. } => Some(error),
+impl ProtocolAnomalyReport {
+    /// Record an anomaly.
+    pub fn record(&mut self, anomaly: &ProtocolAnomaly) {
+        *self.counts.entry(anomaly.kind).or_default() += 1;
+        if let Some(ref subtype) = anomaly.subtype {
+            *self
+                .subtypes
+                .entry((anomaly.kind, subtype.clone()))
+                .or_default() += 1;
         }
     }
+
+    /// Total number of anomalies observed.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Check if no anomalies were observed.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -1496,6 +4154,64 @@ mod tests {
         assert!(json.contains("allow"));
     }
 
+    #[tokio::test]
+    async fn test_tool_matcher_filters_callbacks() {
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("mcp__*__get_*", "mcp__db__get_row"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(!glob_matches("a*b", "aXbY"));
+        assert!(!glob_matches("Bash", "Bas"));
+        #[cfg(feature = "regex")]
+        assert!(ToolMatcher::regex("(").is_err());
+
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let callback: HookCallback = Arc::new(move |_, _, _| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::pin(async { HookOutput::default() })
+        });
+        let filtered = ToolMatcher::glob("Bash*").filter(callback);
+        let input = |event: &str, tool: &str| -> HookInput {
+            serde_json::from_value(serde_json::json!({
+                "session_id": "s",
+                "transcript_path": "/tmp/t",
+                "cwd": "/",
+                "hook_event_name": event,
+                "tool_name": tool,
+                "tool_input": {},
+                "tool_response": {},
+                "prompt": "Hi"
+            }))
+            .unwrap()
+        };
+        for (event, tool) in [
+            ("PreToolUse", "BashOutput"),
+            ("PostToolUse", "Read"),
+            ("UserPromptSubmit", "Read"),
+        ] {
+            filtered(input(event, tool), None, HookContext::default()).await;
+        }
+        // The Read call was skipped, the prompt has no tool to filter on
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_turn_retry_delays() {
+        let retry = TurnRetry::new(5)
+            .with_delays(Duration::from_secs(1), Duration::from_secs(5))
+            .with_jitter(0.0);
+        let delays = (1..=5)
+            .map(|n| retry.delay(n).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+        assert_eq!(retry.delay(u32::MAX), Duration::from_secs(5));
+
+        let retry = retry.with_jitter(1.0);
+        for attempt in 1..=5 {
+            assert!(retry.delay(attempt) <= Duration::from_secs(5));
+        }
+    }
+
     #[test]
     fn test_message_parsing() {
         let json = r#"{"type": "assistant", "content": [{"type": "text", "text": "Hello"}], "model": "claude-3"}"#;
@@ -1522,4 +4238,22 @@ mod tests {
         assert_eq!(opts.max_turns, Some(5));
         assert_eq!(opts.permission_mode, Some(PermissionMode::AcceptEdits));
     }
+
+    #[test]
+    fn test_log_sampling() {
+        let frame = "añb".as_bytes();
+        assert_eq!(LogSampling::Full.sample(frame), "añb");
+        assert_eq!(
+            LogSampling::Truncated(3).sample(frame),
+            "añ... [1 more bytes]"
+        );
+        // Never cuts a character in two
+        assert_eq!(
+            LogSampling::Truncated(2).sample(frame),
+            "a... [3 more bytes]"
+        );
+        assert_eq!(LogSampling::Truncated(10).sample(frame), "añb");
+        assert_eq!(LogSampling::MetadataOnly.sample(frame), "<4 bytes>");
+        assert_eq!(LogSampling::default(), LogSampling::Truncated(200));
+    }
 }
@@ -0,0 +1,84 @@
+//! Parsing of the CLI's `stream-json` output.
+//!
+//! The same parsing the SDK runs on a live session, for tools that capture
+//! the CLI's output on their own, such as log processors or replays of
+//! `claude -p --output-format stream-json`. Unlike [`_internal`](crate::_internal),
+//! this module follows semantic versioning: what it exports only changes in
+//! a compatible way between minor releases.
+//!
+//! Each line of output is one frame: a conversation [`Message`], or a
+//! control request or response of the protocol between the SDK and the
+//! CLI. [`parse_line`] takes a line of text, [`parse_frame`] a JSON value
+//! already read, and [`parse_message`] only conversation messages.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use claude_agents_sdk::wire::{parse_line, Frame};
+//! use claude_agents_sdk::Message;
+//!
+//! let log = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Hi"}],"model":"claude-sonnet-4-5"}}
+//! {"type":"control_request","request_id":"req_1","request":{"subtype":"interrupt"}}
+//! "#;
+//! for line in log.lines() {
+//!     match parse_line(line)? {
+//!         Frame::Message(Message::Assistant(message)) => assert_eq!(message.text(), "Hi"),
+//!         Frame::ControlRequest(request) => assert_eq!(request.request_id, "req_1"),
+//!         other => panic!("unexpected frame {:?}", other),
+//!     }
+//! }
+//! # Ok::<(), claude_agents_sdk::ClaudeSDKError>(())
+//! ```
+
+use serde_json::Value;
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::protocol::{ControlRequest, ControlResponse};
+use crate::types::Message;
+
+pub use crate::protocol::parser::{
+    is_control_request, is_control_response, parse_control_request, parse_control_response,
+    parse_message, parse_message_with_raw,
+};
+pub use crate::protocol::wire::{
+    ASSISTANT, CONTROL_REQUEST, CONTROL_RESPONSE, MESSAGE_TYPES, RESULT, STREAM_EVENT, SYSTEM, USER,
+};
+
+/// A frame of the CLI's output.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Frame {
+    /// A conversation message.
+    Message(Message),
+    /// A control request, e.g. a permission prompt or hook callback.
+    ControlRequest(ControlRequest),
+    /// The CLI's response to a control request.
+    ControlResponse(ControlResponse),
+}
+
+/// Parse a frame from its JSON value.
+pub fn parse_frame(raw: Value) -> Result<Frame> {
+    if is_control_request(&raw) {
+        parse_control_request(raw).map(Frame::ControlRequest)
+    } else if is_control_response(&raw) {
+        parse_control_response(raw).map(Frame::ControlResponse)
+    } else {
+        parse_message(raw).map(Frame::Message)
+    }
+}
+
+/// Parse a line of output into a frame.
+///
+/// Surrounding whitespace, including a line terminator, is ignored.
+pub fn parse_line(line: &str) -> Result<Frame> {
+    let line = line.trim();
+    let raw = serde_json::from_str(line).map_err(|e| {
+        ClaudeSDKError::json_decode_with_context(
+            "Invalid JSON frame",
+            Some(line.to_string()),
+            None,
+            e,
+        )
+    })?;
+    parse_frame(raw)
+}
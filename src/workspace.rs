@@ -0,0 +1,370 @@
+//! Temporary workspaces for file-based one-shot queries.
+//!
+//! Backs [`query_with_files`](crate::query_with_files): input files are
+//! copied into a fresh temporary directory, the query runs with that
+//! directory as its `cwd`, and the directory is diffed against its initial
+//! state afterwards to find the files Claude created, modified or deleted.
+//!
+//! [`ClaudeClient::branch_at`](crate::ClaudeClient::branch_at) also runs
+//! each branch in a copy of the session's working directory.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use crate::errors::{ClaudeSDKError, Result};
+use crate::types::{Message, ResultMessage};
+
+/// How a file in the workspace changed during a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChangeKind {
+    /// The file did not exist before the query.
+    Created,
+    /// The file existed and its contents changed.
+    Modified,
+    /// The file existed and was removed.
+    Deleted,
+}
+
+/// A file changed by a [`query_with_files`](crate::query_with_files) run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    /// Path relative to the workspace root.
+    pub path: PathBuf,
+    /// How the file changed.
+    pub kind: FileChangeKind,
+    /// The new contents, or `None` for deleted files.
+    pub contents: Option<Vec<u8>>,
+}
+
+impl FileChange {
+    /// Get the new contents as UTF-8 text, if present and valid.
+    pub fn text(&self) -> Option<&str> {
+        self.contents
+            .as_deref()
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+}
+
+/// Result of a [`query_with_files`](crate::query_with_files) run.
+#[derive(Debug, Clone)]
+pub struct FilesQueryResult {
+    /// All messages received during the query.
+    pub messages: Vec<Message>,
+    /// Files created, modified or deleted in the workspace, sorted by path.
+    pub changes: Vec<FileChange>,
+}
+
+impl FilesQueryResult {
+    /// Get the final result message, if one was received.
+    pub fn result(&self) -> Option<&ResultMessage> {
+        self.messages.iter().rev().find_map(|msg| match msg {
+            Message::Result(result) => Some(result),
+            _ => None,
+        })
+    }
+
+    /// Get the change for a path relative to the workspace root.
+    pub fn change(&self, path: impl AsRef<Path>) -> Option<&FileChange> {
+        self.changes.iter().find(|c| c.path == path.as_ref())
+    }
+}
+
+/// A temporary directory populated with input files.
+///
+/// The directory is removed when this value is dropped.
+pub(crate) struct TempWorkspace {
+    dir: TempDir,
+    snapshot: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl TempWorkspace {
+    /// Create a workspace and copy `inputs` into its root.
+    ///
+    /// Files are placed under their file name; directories are copied
+    /// recursively under their directory name. An input that is a symlink
+    /// is copied as what it points to, but symlinks inside directories are
+    /// recreated as links to the same target (on Unix; skipped elsewhere),
+    /// so a link out of the directory doesn't copy what it points to.
+    pub(crate) fn create(inputs: &[PathBuf]) -> Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("claude-workspace-")
+            .tempdir()?;
+
+        for input in inputs {
+            let name = input.file_name().ok_or_else(|| {
+                ClaudeSDKError::configuration(format!(
+                    "Input path has no file name: {}",
+                    input.display()
+                ))
+            })?;
+            let target = dir.path().join(name);
+            if fs::symlink_metadata(&target).is_ok() {
+                return Err(ClaudeSDKError::configuration(format!(
+                    "Duplicate input name in workspace: {}",
+                    Path::new(name).display()
+                )));
+            }
+            if fs::metadata(input)?.is_dir() {
+                copy_recursive(input, &target)?;
+            } else {
+                fs::copy(input, &target)?;
+            }
+        }
+
+        let snapshot = snapshot(dir.path())?;
+        Ok(Self { dir, snapshot })
+    }
+
+    /// Create a workspace holding a copy of everything in `dir`.
+    ///
+    /// Symlinks in `dir` are recreated as links, like those inside input
+    /// directories.
+    pub(crate) fn copy_of(dir: &Path) -> Result<Self> {
+        let workspace = tempfile::Builder::new()
+            .prefix("claude-workspace-")
+            .tempdir()?;
+        copy_recursive(dir, workspace.path())?;
+        let snapshot = snapshot(workspace.path())?;
+        Ok(Self {
+            dir: workspace,
+            snapshot,
+        })
+    }
+
+    /// Put the files of `dir` back to the workspace's initial contents:
+    /// files that differ are rewritten, missing ones recreated, and ones
+    /// the workspace didn't start with removed.
+    pub(crate) fn restore_to(&self, dir: &Path) -> Result<()> {
+        let current = snapshot(dir)?;
+        for path in current.keys() {
+            if !self.snapshot.contains_key(path) {
+                fs::remove_file(dir.join(path))?;
+            }
+        }
+        for (path, contents) in &self.snapshot {
+            if current.get(path) != Some(contents) {
+                let target = dir.join(path);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(target, contents)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The workspace root.
+    pub(crate) fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Compare the workspace against its initial contents.
+    pub(crate) fn changes(&self) -> Result<Vec<FileChange>> {
+        let current = snapshot(self.dir.path())?;
+        let mut changes = Vec::new();
+
+        for (path, contents) in &current {
+            let kind = match self.snapshot.get(path) {
+                None => FileChangeKind::Created,
+                Some(before) if before != contents => FileChangeKind::Modified,
+                Some(_) => continue,
+            };
+            changes.push(FileChange {
+                path: path.clone(),
+                kind,
+                contents: Some(contents.clone()),
+            });
+        }
+        for path in self.snapshot.keys() {
+            if !current.contains_key(path) {
+                changes.push(FileChange {
+                    path: path.clone(),
+                    kind: FileChangeKind::Deleted,
+                    contents: None,
+                });
+            }
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+}
+
+/// Run `job` on the blocking thread pool.
+pub(crate) async fn blocking<T, F>(job: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(job)
+        .await
+        .map_err(|e| ClaudeSDKError::internal(e.to_string()))?
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let (path, target) = (entry.path(), to.join(entry.file_name()));
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            copy_link(&path, &target)?;
+        } else if file_type.is_dir() {
+            copy_recursive(&path, &target)?;
+        } else {
+            fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Recreate the symlink `from` at `to`, pointing to the same target.
+#[cfg(unix)]
+fn copy_link(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+/// Skip the symlink `from`: creating links needs privileges on Windows.
+#[cfg(not(unix))]
+fn copy_link(from: &Path, _to: &Path) -> io::Result<()> {
+    tracing::debug!("Not copying symlink {} into workspace", from.display());
+    Ok(())
+}
+
+/// Read every regular file under `root`, keyed by relative path.
+fn snapshot(root: &Path) -> io::Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+                files.insert(relative, fs::read(&path)?);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_copies_inputs_and_detects_changes() {
+        let src = tempfile::tempdir().unwrap();
+        let file = src.path().join("notes.txt");
+        fs::write(&file, "hello").unwrap();
+        let nested = src.path().join("data");
+        fs::create_dir(&nested).unwrap();
+        fs::write(nested.join("a.csv"), "1,2").unwrap();
+        fs::write(nested.join("b.csv"), "3,4").unwrap();
+
+        let workspace = TempWorkspace::create(&[file, nested]).unwrap();
+        assert!(workspace.changes().unwrap().is_empty());
+
+        fs::write(workspace.path().join("notes.txt"), "hello world").unwrap();
+        fs::write(workspace.path().join("summary.md"), "# Summary").unwrap();
+        fs::remove_file(workspace.path().join("data/b.csv")).unwrap();
+
+        let changes = workspace.changes().unwrap();
+        let kinds: Vec<_> = changes.iter().map(|c| (c.path.clone(), c.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (PathBuf::from("data/b.csv"), FileChangeKind::Deleted),
+                (PathBuf::from("notes.txt"), FileChangeKind::Modified),
+                (PathBuf::from("summary.md"), FileChangeKind::Created),
+            ]
+        );
+        assert_eq!(changes[1].text(), Some("hello world"));
+        assert_eq!(changes[0].contents, None);
+
+        // Originals are untouched
+        assert_eq!(
+            fs::read_to_string(src.path().join("notes.txt")).unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn test_workspace_is_removed_on_drop() {
+        let workspace = TempWorkspace::create(&[]).unwrap();
+        let path = workspace.path().to_path_buf();
+        assert!(path.exists());
+        drop(workspace);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_restore_to_undoes_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "v1").unwrap();
+        fs::write(dir.path().join("README.md"), "readme").unwrap();
+
+        let copy = TempWorkspace::copy_of(dir.path()).unwrap();
+        assert_eq!(
+            fs::read_to_string(copy.path().join("src/lib.rs")).unwrap(),
+            "v1"
+        );
+
+        fs::write(dir.path().join("src/lib.rs"), "v2").unwrap();
+        fs::remove_file(dir.path().join("README.md")).unwrap();
+        fs::write(dir.path().join("new.txt"), "new").unwrap();
+        copy.restore_to(dir.path()).unwrap();
+
+        assert_eq!(snapshot(dir.path()).unwrap(), copy.snapshot);
+    }
+
+    #[test]
+    fn test_duplicate_input_names_are_rejected() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        fs::write(a.path().join("x.txt"), "a").unwrap();
+        fs::write(b.path().join("x.txt"), "b").unwrap();
+
+        let result = TempWorkspace::create(&[a.path().join("x.txt"), b.path().join("x.txt")]);
+        assert!(matches!(result, Err(ClaudeSDKError::Configuration { .. })));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_are_copied_as_links() {
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret"), "key").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), dir.path().join("leak")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("out")).unwrap();
+
+        let copy = TempWorkspace::copy_of(dir.path()).unwrap();
+        for name in ["leak", "out"] {
+            let link = copy.path().join(name);
+            assert!(fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+            assert_eq!(
+                fs::read_link(&link).unwrap(),
+                fs::read_link(dir.path().join(name)).unwrap()
+            );
+        }
+        // Nothing behind the links was copied or counted
+        assert_eq!(
+            copy.snapshot.keys().collect::<Vec<_>>(),
+            [Path::new("a.txt")]
+        );
+    }
+}
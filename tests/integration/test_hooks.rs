@@ -44,11 +44,7 @@ async fn test_pre_tool_use_hook_invoked() {
     let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
     hooks.insert(
         HookEvent::PreToolUse,
-        vec![HookMatcher {
-            matcher: None, // Match all tools
-            hooks: vec![callback],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![callback])],
     );
 
     let mut options = ClaudeAgentOptions::new()
@@ -116,11 +112,7 @@ async fn test_pre_tool_use_hook_modifies_input() {
     let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
     hooks.insert(
         HookEvent::PreToolUse,
-        vec![HookMatcher {
-            matcher: Some("Bash".to_string()), // Only match Bash
-            hooks: vec![callback],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![callback]).with_matcher("Bash")],
     );
 
     let mut options = ClaudeAgentOptions::new()
@@ -166,11 +158,7 @@ async fn test_post_tool_use_hook_invoked() {
     let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
     hooks.insert(
         HookEvent::PostToolUse,
-        vec![HookMatcher {
-            matcher: None,
-            hooks: vec![callback],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![callback])],
     );
 
     let mut options = ClaudeAgentOptions::new()
@@ -239,16 +227,8 @@ async fn test_multiple_hooks_same_event() {
     hooks.insert(
         HookEvent::PreToolUse,
         vec![
-            HookMatcher {
-                matcher: None,
-                hooks: vec![callback1],
-                timeout: None,
-            },
-            HookMatcher {
-                matcher: None,
-                hooks: vec![callback2],
-                timeout: None,
-            },
+            HookMatcher::new(vec![callback1]),
+            HookMatcher::new(vec![callback2]),
         ],
     );
 
@@ -307,19 +287,11 @@ async fn test_hooks_different_events() {
     let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
     hooks.insert(
         HookEvent::PreToolUse,
-        vec![HookMatcher {
-            matcher: None,
-            hooks: vec![pre_callback],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![pre_callback])],
     );
     hooks.insert(
         HookEvent::PostToolUse,
-        vec![HookMatcher {
-            matcher: None,
-            hooks: vec![post_callback],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![post_callback])],
     );
 
     let mut options = ClaudeAgentOptions::new()
@@ -386,16 +358,8 @@ async fn test_hook_matcher_specific_tool() {
     hooks.insert(
         HookEvent::PreToolUse,
         vec![
-            HookMatcher {
-                matcher: Some("Bash".to_string()),
-                hooks: vec![bash_callback],
-                timeout: None,
-            },
-            HookMatcher {
-                matcher: Some("Read".to_string()),
-                hooks: vec![other_callback],
-                timeout: None,
-            },
+            HookMatcher::new(vec![bash_callback]).with_matcher("Bash"),
+            HookMatcher::new(vec![other_callback]).with_matcher("Read"),
         ],
     );
 
@@ -450,11 +414,7 @@ async fn test_hooks_with_client_multi_turn() {
     let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
     hooks.insert(
         HookEvent::PreToolUse,
-        vec![HookMatcher {
-            matcher: None,
-            hooks: vec![callback],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![callback])],
     );
 
     let mut options = ClaudeAgentOptions::new()
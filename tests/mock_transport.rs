@@ -1,171 +1,15 @@
 //! Mock transport for deterministic testing.
 //!
-//! This module provides a mock transport that returns pre-recorded responses,
-//! allowing for deterministic unit testing without requiring the actual CLI.
+//! These tests cover `testing::MockTransport`, which returns pre-recorded
+//! responses with optional fault injection, allowing for deterministic unit
+//! testing without requiring the actual CLI.
 
-use async_trait::async_trait;
-use claude_agents_sdk::Result;
 use claude_agents_sdk::_internal::transport::Transport;
-use futures::stream;
+use claude_agents_sdk::testing::{ControlResponseOrder, MockTransport};
+use claude_agents_sdk::{query_with_transport, ClaudeClientBuilder, ClaudeSDKError, Message};
 use serde_json::{json, Value};
-use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio_stream::Stream;
-
-/// A mock transport that returns pre-recorded responses.
-pub struct MockTransport {
-    /// Pre-recorded responses to return (in order).
-    responses: Arc<Mutex<Vec<Value>>>,
-    /// Index of the next response to return.
-    response_index: Arc<AtomicUsize>,
-    /// Whether the transport is connected.
-    connected: AtomicBool,
-    /// Messages written to the transport.
-    written_messages: Arc<Mutex<Vec<String>>>,
-}
-
-impl MockTransport {
-    /// Create a new mock transport with the given responses.
-    pub fn new(responses: Vec<Value>) -> Self {
-        Self {
-            responses: Arc::new(Mutex::new(responses)),
-            response_index: Arc::new(AtomicUsize::new(0)),
-            connected: AtomicBool::new(false),
-            written_messages: Arc::new(Mutex::new(Vec::new())),
-        }
-    }
-
-    /// Create a mock transport with a simple text response.
-    pub fn with_text_response(text: &str) -> Self {
-        Self::new(vec![
-            json!({
-                "type": "system",
-                "subtype": "init",
-                "data": {"session_id": "mock-session"}
-            }),
-            json!({
-                "type": "assistant",
-                "message": {
-                    "content": [{"type": "text", "text": text}],
-                    "model": "mock-model"
-                }
-            }),
-            json!({
-                "type": "result",
-                "subtype": "success",
-                "is_error": false,
-                "duration_ms": 100,
-                "duration_api_ms": 80,
-                "num_turns": 1,
-                "session_id": "mock-session",
-                "total_cost_usd": 0.001
-            }),
-        ])
-    }
-
-    /// Create a mock transport that simulates an error.
-    pub fn with_error_response(error_message: &str) -> Self {
-        Self::new(vec![
-            json!({
-                "type": "system",
-                "subtype": "init",
-                "data": {"session_id": "mock-session"}
-            }),
-            json!({
-                "type": "result",
-                "subtype": "error",
-                "is_error": true,
-                "duration_ms": 50,
-                "duration_api_ms": 40,
-                "num_turns": 0,
-                "session_id": "mock-session",
-                "result": error_message
-            }),
-        ])
-    }
-
-    /// Create a mock transport that simulates tool use.
-    pub fn with_tool_use(tool_name: &str, tool_input: Value) -> Self {
-        Self::new(vec![
-            json!({
-                "type": "system",
-                "subtype": "init",
-                "data": {"session_id": "mock-session"}
-            }),
-            json!({
-                "type": "assistant",
-                "message": {
-                    "content": [
-                        {"type": "text", "text": "Let me use a tool."},
-                        {
-                            "type": "tool_use",
-                            "id": "mock-tool-id",
-                            "name": tool_name,
-                            "input": tool_input
-                        }
-                    ],
-                    "model": "mock-model"
-                }
-            }),
-            json!({
-                "type": "result",
-                "subtype": "success",
-                "is_error": false,
-                "duration_ms": 200,
-                "duration_api_ms": 150,
-                "num_turns": 1,
-                "session_id": "mock-session"
-            }),
-        ])
-    }
-
-    /// Get messages that were written to the transport.
-    pub fn get_written_messages(&self) -> Vec<String> {
-        self.written_messages.lock().unwrap().clone()
-    }
-}
-
-#[async_trait]
-impl Transport for MockTransport {
-    async fn connect(&mut self) -> Result<()> {
-        self.connected.store(true, Ordering::SeqCst);
-        Ok(())
-    }
-
-    async fn write(&self, data: &str) -> Result<()> {
-        self.written_messages.lock().unwrap().push(data.to_string());
-        Ok(())
-    }
-
-    fn message_stream(&self) -> Pin<Box<dyn Stream<Item = Result<Value>> + Send + '_>> {
-        let responses = self.responses.clone();
-        let index = self.response_index.clone();
-
-        Box::pin(stream::iter(std::iter::from_fn(move || {
-            let idx = index.fetch_add(1, Ordering::SeqCst);
-            let responses = responses.lock().unwrap();
-            if idx < responses.len() {
-                Some(Ok(responses[idx].clone()))
-            } else {
-                None
-            }
-        })))
-    }
-
-    async fn close(&mut self) -> Result<()> {
-        self.connected.store(false, Ordering::SeqCst);
-        Ok(())
-    }
-
-    async fn end_input(&self) -> Result<()> {
-        Ok(())
-    }
-
-    fn is_ready(&self) -> bool {
-        self.connected.load(Ordering::SeqCst)
-    }
-}
+use std::time::Duration;
 
 // ============================================================================
 // Mock Transport Tests
@@ -277,4 +121,192 @@ mod tests {
         assert_eq!(content[1]["name"], "Bash");
         assert_eq!(content[1]["input"]["command"], "ls");
     }
+
+    fn control_request(id: &str) -> String {
+        json!({
+            "type": "control_request",
+            "request_id": id,
+            "request": {"subtype": "interrupt"}
+        })
+        .to_string()
+    }
+
+    fn response_id(frame: &Value) -> &str {
+        frame["response"]["request_id"].as_str().unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_transport_injects_latency_and_delays() {
+        let transport = MockTransport::new(vec![json!({"index": 0})])
+            .then_delay(Duration::from_secs(5))
+            .then_frame(json!({"index": 1}))
+            .with_latency(Duration::from_millis(100));
+        let start = tokio::time::Instant::now();
+        let mut stream = transport.message_stream();
+
+        stream.next().await.unwrap().unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+        let frame = stream.next().await.unwrap().unwrap();
+        assert_eq!(frame["index"], 1);
+        assert_eq!(start.elapsed(), Duration::from_millis(5200));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_malformed_line_and_process_death() {
+        let mut transport = MockTransport::with_text_response("partial")
+            .then_malformed_line("{\"type\": \"assist")
+            .then_process_death(Some(137))
+            .then_frame(json!({"never": "delivered"}));
+        transport.connect().await.unwrap();
+
+        let frames: Vec<_> = transport.message_stream().collect().await;
+        assert_eq!(frames.len(), 5);
+        assert!(frames[..3].iter().all(|f| f.is_ok()));
+        match &frames[3] {
+            Err(ClaudeSDKError::JSONDecode { raw_data, .. }) => {
+                assert_eq!(raw_data.as_deref(), Some("{\"type\": \"assist"));
+            }
+            other => panic!("expected JSONDecode, got {:?}", other),
+        }
+        assert!(matches!(
+            frames[4],
+            Err(ClaudeSDKError::Process {
+                exit_code: Some(137),
+                ..
+            })
+        ));
+
+        // A dead process takes no more input
+        assert!(!transport.is_ready());
+        assert!(transport.write("{}").await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_transport_answers_control_requests_late() {
+        let mut transport = MockTransport::new(vec![])
+            .respond_to_control_requests()
+            .with_control_response_delay(Duration::from_secs(30));
+        transport.connect().await.unwrap();
+        transport.write(&control_request("req_1")).await.unwrap();
+
+        let start = tokio::time::Instant::now();
+        let mut stream = transport.message_stream();
+        let frame = stream.next().await.unwrap().unwrap();
+        assert_eq!(frame["type"], "control_response");
+        assert_eq!(response_id(&frame), "req_1");
+        assert_eq!(start.elapsed(), Duration::from_secs(30));
+
+        transport.end_input().await.unwrap();
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_reorders_and_drops_control_responses() {
+        let transport = MockTransport::new(vec![])
+            .with_control_responder(|request| {
+                (request["subtype"] == "interrupt").then(|| json!({"ok": true}))
+            })
+            .with_control_response_order(ControlResponseOrder::Reversed { batch: 3 });
+        for id in ["req_1", "req_2", "req_3"] {
+            transport.write(&control_request(id)).await.unwrap();
+        }
+        // Unanswered: the responder returns None
+        let dropped = json!({
+            "type": "control_request",
+            "request_id": "req_4",
+            "request": {"subtype": "mcp_status"}
+        });
+        transport.write(&dropped.to_string()).await.unwrap();
+        transport.end_input().await.unwrap();
+
+        let frames: Vec<_> = transport.message_stream().collect().await;
+        let ids: Vec<_> = frames
+            .iter()
+            .map(|f| response_id(f.as_ref().unwrap()))
+            .collect();
+        assert_eq!(ids, ["req_3", "req_2", "req_1"]);
+        assert_eq!(
+            frames[0].as_ref().unwrap()["response"]["response"]["ok"],
+            true
+        );
+        assert_eq!(transport.get_written_messages().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_answers_prompts() {
+        let mut transport = MockTransport::new(vec![]).with_prompt_responder(|prompt| {
+            vec![json!({"type": "system", "subtype": "echo", "data": prompt["message"]})]
+        });
+        transport.connect().await.unwrap();
+        let prompt = json!({"type": "user", "message": {"role": "user", "content": "Hi"}});
+        transport.write(&prompt.to_string()).await.unwrap();
+        transport.end_input().await.unwrap();
+
+        let frames: Vec<_> = transport.message_stream().collect().await;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].as_ref().unwrap()["data"]["content"], "Hi");
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_blocked_input() {
+        let mut transport = MockTransport::new(vec![]).with_blocked_input();
+        transport.connect().await.unwrap();
+        let transport = Arc::new(transport);
+
+        let writer = tokio::spawn({
+            let transport = Arc::clone(&transport);
+            async move { transport.write("{}").await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!writer.is_finished());
+
+        // Closing releases the write, which fails
+        transport.end_input().await.unwrap();
+        assert!(writer.await.unwrap().is_err());
+        assert!(transport.get_written_messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_client_runs_over_injected_transport() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        let transport = MockTransport::with_text_response("Hello from the mock")
+            .with_control_responder(move |request| {
+                seen.lock().unwrap().push(request["subtype"].clone());
+                Some(json!({}))
+            });
+        let mut client = ClaudeClientBuilder::new()
+            .transport(Box::new(transport))
+            .build();
+        client.connect().await.unwrap();
+
+        client.query("Hi").await.unwrap();
+        let (text, result) = client.receive_response().await.unwrap();
+        assert_eq!(text, "Hello from the mock");
+        assert_eq!(result.session_id, "mock-session");
+        assert_eq!(requests.lock().unwrap().first(), Some(&json!("initialize")));
+
+        // The transport served its one session
+        client.disconnect().await.unwrap();
+        let err = client.connect().await.unwrap_err();
+        assert!(err.to_string().contains("already been used"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_query_runs_over_injected_transport() {
+        let transport = MockTransport::with_text_response("One shot").respond_to_control_requests();
+        let mut stream = query_with_transport("Hi", None, Box::new(transport))
+            .await
+            .unwrap();
+        let mut texts = Vec::new();
+        while let Some(message) = stream.next().await {
+            match message.unwrap() {
+                Message::Assistant(msg) => texts.push(msg.text()),
+                Message::Result(_) => break,
+                _ => {}
+            }
+        }
+        assert_eq!(texts, ["One shot"]);
+    }
 }
@@ -7,10 +7,11 @@ use claude_agents_sdk::{
     AgentDefinition, AssistantMessage, ClaudeAgentOptions, ContentBlock, McpServerConfig,
     McpServersConfig, McpStdioServerConfig, Message, PermissionMode, ResultMessage,
     SandboxNetworkConfig, SandboxSettings, SettingSource, SystemPromptConfig, SystemPromptPreset,
-    TextBlock, ToolsConfig, ToolsPreset,
+    TextBlock, Timeouts, ToolsConfig, ToolsPreset,
 };
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 // ============================================================================
 // ClaudeAgentOptions Default State Tests
@@ -117,9 +118,9 @@ fn test_builder_chain_sets_all_fields_correctly() {
         "allowed_tools should match set value"
     );
     assert_eq!(
-        options.timeout_secs,
-        Some(120),
-        "timeout_secs should match set value"
+        options.timeouts.control_request,
+        Some(Duration::from_secs(120)),
+        "timeout_secs should set the control request timeout"
     );
     assert!(
         options.include_partial_messages,
@@ -555,14 +556,12 @@ fn test_max_buffer_size_configuration() {
 
 #[test]
 fn test_message_is_assistant_returns_true_for_assistant_message() {
-    let msg = Message::Assistant(AssistantMessage {
-        content: vec![ContentBlock::Text(TextBlock {
+    let msg = Message::Assistant(AssistantMessage::new(
+        vec![ContentBlock::Text(TextBlock {
             text: "Hello".to_string(),
         })],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    });
+        "claude-3",
+    ));
 
     assert!(
         msg.is_assistant(),
@@ -576,18 +575,11 @@ fn test_message_is_assistant_returns_true_for_assistant_message() {
 
 #[test]
 fn test_message_is_result_returns_true_for_result_message() {
-    let msg = Message::Result(ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 100,
-        duration_api_ms: 80,
-        is_error: false,
-        num_turns: 1,
-        session_id: "test".to_string(),
-        total_cost_usd: None,
-        usage: None,
-        result: None,
-        structured_output: None,
-    });
+    let mut result = ResultMessage::new("success", "test");
+    result.duration_ms = 100;
+    result.duration_api_ms = 80;
+    result.num_turns = 1;
+    let msg = Message::Result(result);
 
     assert!(
         msg.is_result(),
@@ -601,14 +593,12 @@ fn test_message_is_result_returns_true_for_result_message() {
 
 #[test]
 fn test_message_as_assistant_returns_some_for_assistant_message() {
-    let msg = Message::Assistant(AssistantMessage {
-        content: vec![ContentBlock::Text(TextBlock {
+    let msg = Message::Assistant(AssistantMessage::new(
+        vec![ContentBlock::Text(TextBlock {
             text: "Hello".to_string(),
         })],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    });
+        "claude-3",
+    ));
 
     let asst = msg.as_assistant();
     assert!(
@@ -624,18 +614,12 @@ fn test_message_as_assistant_returns_some_for_assistant_message() {
 
 #[test]
 fn test_message_as_assistant_returns_none_for_non_assistant_message() {
-    let msg = Message::Result(ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 100,
-        duration_api_ms: 80,
-        is_error: false,
-        num_turns: 1,
-        session_id: "test".to_string(),
-        total_cost_usd: Some(0.001),
-        usage: None,
-        result: None,
-        structured_output: None,
-    });
+    let mut result = ResultMessage::new("success", "test");
+    result.duration_ms = 100;
+    result.duration_api_ms = 80;
+    result.num_turns = 1;
+    result.total_cost_usd = Some(0.001);
+    let msg = Message::Result(result);
 
     assert!(
         msg.as_assistant().is_none(),
@@ -645,18 +629,12 @@ fn test_message_as_assistant_returns_none_for_non_assistant_message() {
 
 #[test]
 fn test_message_as_result_returns_some_for_result_message() {
-    let msg = Message::Result(ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 100,
-        duration_api_ms: 80,
-        is_error: false,
-        num_turns: 1,
-        session_id: "test".to_string(),
-        total_cost_usd: Some(0.001),
-        usage: None,
-        result: None,
-        structured_output: None,
-    });
+    let mut result = ResultMessage::new("success", "test");
+    result.duration_ms = 100;
+    result.duration_api_ms = 80;
+    result.num_turns = 1;
+    result.total_cost_usd = Some(0.001);
+    let msg = Message::Result(result);
 
     let result = msg.as_result();
     assert!(
@@ -672,14 +650,12 @@ fn test_message_as_result_returns_some_for_result_message() {
 
 #[test]
 fn test_message_as_result_returns_none_for_non_result_message() {
-    let msg = Message::Assistant(AssistantMessage {
-        content: vec![ContentBlock::Text(TextBlock {
+    let msg = Message::Assistant(AssistantMessage::new(
+        vec![ContentBlock::Text(TextBlock {
             text: "Hello".to_string(),
         })],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    });
+        "claude-3",
+    ));
 
     assert!(
         msg.as_result().is_none(),
@@ -716,8 +692,8 @@ fn test_zero_max_turns_is_valid() {
 fn test_zero_timeout_disables_timeout() {
     let options = ClaudeAgentOptions::new().with_timeout_secs(0);
     assert_eq!(
-        options.timeout_secs,
-        Some(0),
+        options.timeouts,
+        Timeouts::none(),
         "Zero timeout should be allowed (disables timeout)"
     );
 }
@@ -802,3 +778,56 @@ fn test_overlapping_allowed_and_disallowed_tools() {
     assert!(options.allowed_tools.contains(&"Bash".to_string()));
     assert!(options.disallowed_tools.contains(&"Bash".to_string()));
 }
+
+// ============================================================================
+// CLI Argument Tests
+// ============================================================================
+
+#[cfg(feature = "subprocess")]
+#[test]
+fn test_build_cli_args_reflects_options() {
+    use claude_agents_sdk::testing::{
+        assert_flag, assert_flag_value, assert_no_flag, build_cli_args, flag_values,
+    };
+
+    let mut options = ClaudeAgentOptions::new()
+        .with_model("claude-sonnet-4-5")
+        .with_permission_mode(PermissionMode::AcceptEdits);
+    options.add_dirs = vec![PathBuf::from("/a"), PathBuf::from("/b")];
+    let args = build_cli_args(&options).unwrap();
+
+    assert_flag(&args, "--verbose");
+    assert_flag_value(&args, "--input-format", "stream-json");
+    assert_flag_value(&args, "--model", "claude-sonnet-4-5");
+    assert_flag_value(&args, "--permission-mode", "acceptEdits");
+    assert_eq!(flag_values(&args, "--add-dir"), vec!["/a", "/b"]);
+    assert_no_flag(&args, "--fork-session");
+}
+
+#[cfg(feature = "subprocess")]
+#[test]
+fn test_build_cli_args_expands_env_references() {
+    use claude_agents_sdk::testing::{assert_flag_value, build_cli_args};
+
+    let mut options = ClaudeAgentOptions::new()
+        .with_system_prompt("Deploy to ${CLI_ARGS_TEST_HOST}")
+        .with_expand_env(true);
+    options
+        .env
+        .insert("CLI_ARGS_TEST_HOST".to_string(), "example.com".to_string());
+    let args = build_cli_args(&options).unwrap();
+    assert_flag_value(&args, "--system-prompt", "Deploy to example.com");
+
+    options.env.clear();
+    assert!(build_cli_args(&options).is_err());
+}
+
+#[cfg(feature = "subprocess")]
+#[test]
+#[should_panic(expected = "expected --model \"opus\"")]
+fn test_assert_flag_value_reports_mismatch() {
+    use claude_agents_sdk::testing::{assert_flag_value, build_cli_args};
+
+    let args = build_cli_args(&ClaudeAgentOptions::new().with_model("sonnet")).unwrap();
+    assert_flag_value(&args, "--model", "opus");
+}
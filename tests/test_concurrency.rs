@@ -7,12 +7,13 @@
 use claude_agents_sdk::{
     AssistantMessage, BaseHookInput, ClaudeAgentOptions, ClaudeClientBuilder, ContentBlock,
     HookCallback, HookContext, HookEvent, HookInput, HookMatcher, HookOutput, Message,
-    PermissionMode, PermissionResult, PreToolUseHookInput, ResultMessage, TextBlock,
+    PermissionMode, PermissionResult, PreToolUseHookInput, ResultMessage, TextBlock, Timeouts,
     ToolPermissionContext,
 };
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 // ============================================================================
@@ -84,7 +85,7 @@ async fn test_concurrent_options_building() {
 
                 assert_eq!(options.model, Some(format!("model-{}", i)));
                 assert_eq!(options.max_turns, Some(i as u32 + 1));
-                assert_eq!(options.timeout_secs, Some(60 + i as u64));
+                assert_eq!(options.timeouts, Timeouts::from_secs(60 + i as u64));
             })
         })
         .collect();
@@ -337,14 +338,12 @@ async fn test_concurrent_message_creation() {
     let handles: Vec<_> = (0..100)
         .map(|i| {
             tokio::spawn(async move {
-                let msg = Message::Assistant(AssistantMessage {
-                    content: vec![ContentBlock::Text(TextBlock {
+                let msg = Message::Assistant(AssistantMessage::new(
+                    vec![ContentBlock::Text(TextBlock {
                         text: format!("Message {}", i),
                     })],
-                    model: "claude-3".to_string(),
-                    parent_tool_use_id: None,
-                    error: None,
-                });
+                    "claude-3",
+                ));
 
                 if let Message::Assistant(asst) = msg {
                     assert_eq!(asst.text(), format!("Message {}", i));
@@ -362,14 +361,12 @@ async fn test_concurrent_message_creation() {
 async fn test_shared_message_reading() {
     let messages: Vec<Arc<Message>> = (0..10)
         .map(|i| {
-            Arc::new(Message::Assistant(AssistantMessage {
-                content: vec![ContentBlock::Text(TextBlock {
+            Arc::new(Message::Assistant(AssistantMessage::new(
+                vec![ContentBlock::Text(TextBlock {
                     text: format!("Message {}", i),
                 })],
-                model: "claude-3".to_string(),
-                parent_tool_use_id: None,
-                error: None,
-            }))
+                "claude-3",
+            )))
         })
         .collect();
 
@@ -412,11 +409,9 @@ async fn test_concurrent_hook_config_building() {
                 let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
                 hooks.insert(
                     HookEvent::PreToolUse,
-                    vec![HookMatcher {
-                        matcher: Some(format!("Tool{}", i)),
-                        hooks: vec![callback],
-                        timeout: Some(5000.0),
-                    }],
+                    vec![HookMatcher::new(vec![callback])
+                        .with_matcher(format!("Tool{}", i))
+                        .with_timeout(5000.0)],
                 );
 
                 hooks
@@ -473,21 +468,27 @@ async fn test_concurrent_client_builder() {
 fn test_timeout_configuration() {
     let options = ClaudeAgentOptions::new().with_timeout_secs(120);
 
-    assert_eq!(options.timeout_secs, Some(120));
+    assert_eq!(
+        options.timeouts.control_request,
+        Some(Duration::from_secs(120))
+    );
+    assert_eq!(options.timeouts.connect, Some(Duration::from_secs(120)));
 }
 
 #[test]
 fn test_zero_timeout_disables() {
     let options = ClaudeAgentOptions::new().with_timeout_secs(0);
 
-    assert_eq!(options.timeout_secs, Some(0));
+    assert_eq!(options.timeouts, Timeouts::none());
 }
 
 #[test]
 fn test_default_timeout() {
     let options = ClaudeAgentOptions::new();
 
-    assert!(options.timeout_secs.is_none());
+    assert_eq!(options.timeouts, Timeouts::from_secs(300));
+    assert!(options.timeouts.first_message.is_none());
+    assert!(options.timeouts.idle.is_none());
 }
 
 // ============================================================================
@@ -528,18 +529,13 @@ async fn test_concurrent_error_creation() {
 
 #[tokio::test]
 async fn test_concurrent_result_message_access() {
-    let result = Arc::new(ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 1000,
-        duration_api_ms: 800,
-        is_error: false,
-        num_turns: 5,
-        session_id: "test-session".to_string(),
-        total_cost_usd: Some(0.05),
-        usage: None,
-        result: Some("test result".to_string()),
-        structured_output: None,
-    });
+    let mut result = ResultMessage::new("success", "test-session");
+    result.duration_ms = 1000;
+    result.duration_api_ms = 800;
+    result.num_turns = 5;
+    result.total_cost_usd = Some(0.05);
+    result.result = Some("test result".to_string());
+    let result = Arc::new(result);
 
     let handles: Vec<_> = (0..100)
         .map(|_| {
@@ -572,14 +568,12 @@ async fn test_concurrent_message_channel() {
             let tx = tx.clone();
             tokio::spawn(async move {
                 for j in 0..10 {
-                    let msg = Message::Assistant(AssistantMessage {
-                        content: vec![ContentBlock::Text(TextBlock {
+                    let msg = Message::Assistant(AssistantMessage::new(
+                        vec![ContentBlock::Text(TextBlock {
                             text: format!("Producer {} Message {}", i, j),
                         })],
-                        model: "claude-3".to_string(),
-                        parent_tool_use_id: None,
-                        error: None,
-                    });
+                        "claude-3",
+                    ));
                     tx.send(msg).await.unwrap();
                 }
             })
@@ -662,8 +656,8 @@ async fn test_high_concurrency_message_creation() {
     let handles: Vec<_> = (0..1000)
         .map(|i| {
             tokio::spawn(async move {
-                let msg = Message::Assistant(AssistantMessage {
-                    content: vec![
+                let msg = Message::Assistant(AssistantMessage::new(
+                    vec![
                         ContentBlock::Text(TextBlock {
                             text: format!("Message {} part 1", i),
                         }),
@@ -671,10 +665,8 @@ async fn test_high_concurrency_message_creation() {
                             text: format!("Message {} part 2", i),
                         }),
                     ],
-                    model: "claude-3".to_string(),
-                    parent_tool_use_id: None,
-                    error: None,
-                });
+                    "claude-3",
+                ));
 
                 if let Message::Assistant(asst) = &msg {
                     assert_eq!(asst.content.len(), 2);
@@ -90,6 +90,17 @@ fn test_timeout_error() {
     let err = ClaudeSDKError::timeout(5000);
     assert!(err.is_recoverable());
     assert!(err.to_string().contains("5000ms"));
+    assert_eq!(err.timeout_phase(), None);
+}
+
+#[test]
+fn test_timeout_error_with_phase() {
+    let err = ClaudeSDKError::timeout_in(TimeoutPhase::Spawn, 250);
+    assert_eq!(err.timeout_phase(), Some(TimeoutPhase::Spawn));
+    assert_eq!(
+        err.to_string(),
+        "Operation timed out after 250ms during spawn"
+    );
 }
 
 #[test]
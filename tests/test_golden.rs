@@ -0,0 +1,163 @@
+//! Golden tests against wire exchanges recorded from specific CLI versions.
+//!
+//! Each directory under `tests/fixtures/golden` holds the frames one CLI
+//! version wrote during a session (`exchange.jsonl`), and what the SDK made
+//! of them (`expected.jsonl`): the parsed messages, and the responses it
+//! wrote to the CLI's control requests. The frames are read from a
+//! [`MockTransport`] and go through the protocol engine and the message
+//! parser, as they would in a session.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the expected output after an
+//! intended change, and review the diff.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use claude_agents_sdk::_internal::transport::Transport;
+use claude_agents_sdk::protocol::engine::{Incoming, ProtocolEngine};
+use claude_agents_sdk::protocol::ControlResponse;
+use claude_agents_sdk::testing::MockTransport;
+use claude_agents_sdk::wire::parse_message;
+use claude_agents_sdk::{
+    CanUseTool, HookCallback, HookInput, HookOutput, PermissionResult, SyncHookOutput,
+};
+use serde_json::{json, Value};
+use tokio_stream::StreamExt;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden")
+}
+
+fn read_frames(path: &Path) -> Vec<Value> {
+    std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e))
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .unwrap_or_else(|e| panic!("invalid line in {}: {}: {}", path.display(), e, line))
+        })
+        .collect()
+}
+
+/// The engine a session would run with: Edit is denied, everything else
+/// allowed, and `hook_0` answers with the tool it was called for.
+async fn engine() -> ProtocolEngine {
+    let can_use_tool: CanUseTool = Arc::new(|tool_name, _input, _context| {
+        Box::pin(async move {
+            match tool_name.as_str() {
+                "Edit" => PermissionResult::deny_with_message("Edits are not allowed"),
+                _ => PermissionResult::allow(),
+            }
+        })
+    });
+    let hook: HookCallback = Arc::new(|input, _tool_use_id, _context| {
+        Box::pin(async move {
+            let reason = match input {
+                HookInput::PreToolUse(input) => format!("{} checked", input.tool_name),
+                _ => "unexpected hook input".to_string(),
+            };
+            HookOutput::Sync(SyncHookOutput {
+                reason: Some(reason),
+                ..Default::default()
+            })
+        })
+    });
+    let engine = ProtocolEngine::new(Some(can_use_tool));
+    engine.register_hook_callback("hook_0", hook).await;
+    engine
+}
+
+/// Run `frames` through the pipeline, recording what comes out of it.
+async fn run(frames: Vec<Value>) -> Vec<Value> {
+    let engine = engine().await;
+    let mut transport = MockTransport::new(frames);
+    transport.connect().await.unwrap();
+
+    let mut output = Vec::new();
+    let mut stream = transport.message_stream();
+    while let Some(frame) = stream.next().await {
+        let frame = frame.expect("mock transport failed");
+        output.push(match engine.receive(frame).await {
+            Incoming::Message(raw) => match parse_message(raw) {
+                Ok(message) => json!({ "message": message }),
+                Err(e) => json!({ "parse_error": e.to_string() }),
+            },
+            Incoming::ControlRequest { request, anomaly } => {
+                let request_id = request.request_id.clone();
+                let response = engine.answer(request).await.resolve().await;
+                let response = ControlResponse::from_result(request_id, response);
+                json!({
+                    "control_response": response,
+                    "anomaly": anomaly.map(|a| format!("{:?}", a.kind)),
+                })
+            }
+            Incoming::ControlResponse => json!({ "control_response_received": true }),
+            Incoming::Anomaly(anomaly) => json!({ "anomaly": format!("{:?}", anomaly.kind) }),
+        });
+    }
+    output
+}
+
+#[tokio::test]
+async fn test_golden_exchanges() {
+    let mut corpora: Vec<PathBuf> = std::fs::read_dir(golden_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    corpora.sort();
+    assert!(corpora.len() >= 2, "golden corpora missing");
+
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    for corpus in corpora {
+        let name = corpus.file_name().unwrap().to_string_lossy().into_owned();
+        let actual = run(read_frames(&corpus.join("exchange.jsonl"))).await;
+        let expected_path = corpus.join("expected.jsonl");
+
+        if update {
+            let lines: Vec<String> = actual
+                .iter()
+                .map(|value| serde_json::to_string(value).unwrap())
+                .collect();
+            std::fs::write(&expected_path, lines.join("\n") + "\n").unwrap();
+            continue;
+        }
+
+        let expected = read_frames(&expected_path);
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{}: number of outputs changed",
+            name
+        );
+        for (i, (actual, expected)) in actual.iter().zip(&expected).enumerate() {
+            assert_eq!(
+                actual,
+                expected,
+                "{}: output {} changed (rerun with UPDATE_GOLDEN=1 if intended)",
+                name,
+                i + 1
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_golden_exchanges_cover_each_version() {
+    for (corpus, version) in [("cli-2.0.x", "2.0."), ("cli-2.1.x", "2.1.")] {
+        let frames = read_frames(&golden_dir().join(corpus).join("exchange.jsonl"));
+        let init = &frames[0];
+        assert_eq!(init["subtype"], "init", "{}", corpus);
+        assert!(
+            init["claude_code_version"]
+                .as_str()
+                .is_some_and(|v| v.starts_with(version)),
+            "{}",
+            corpus
+        );
+        // Every corpus answers control requests and ends with a result
+        assert!(frames.iter().any(|f| f["type"] == "control_request"));
+        assert_eq!(frames.last().unwrap()["type"], "result", "{}", corpus);
+    }
+}
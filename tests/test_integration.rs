@@ -9,30 +9,22 @@ use claude_agents_sdk::{
 
 /// Create a mock assistant message for testing.
 fn create_assistant_message(text: &str) -> AssistantMessage {
-    AssistantMessage {
-        content: vec![ContentBlock::Text(TextBlock {
+    AssistantMessage::new(
+        vec![ContentBlock::Text(TextBlock {
             text: text.to_string(),
         })],
-        model: "claude-opus-4-1-20250805".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    }
+        "claude-opus-4-1-20250805",
+    )
 }
 
 /// Create a mock result message for testing.
 fn create_result_message(session_id: &str, cost: f64) -> ResultMessage {
-    ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 1000,
-        duration_api_ms: 800,
-        is_error: false,
-        num_turns: 1,
-        session_id: session_id.to_string(),
-        total_cost_usd: Some(cost),
-        usage: None,
-        result: None,
-        structured_output: None,
-    }
+    let mut result = ResultMessage::new("success", session_id);
+    result.duration_ms = 1000;
+    result.duration_api_ms = 800;
+    result.num_turns = 1;
+    result.total_cost_usd = Some(cost);
+    result
 }
 
 #[test]
@@ -59,8 +51,8 @@ fn test_result_message_parsing() {
 #[test]
 fn test_query_with_tool_use() {
     // Test parsing a response with tool use
-    let assistant = AssistantMessage {
-        content: vec![
+    let assistant = AssistantMessage::new(
+        vec![
             ContentBlock::Text(TextBlock {
                 text: "Let me read that file for you.".to_string(),
             }),
@@ -70,10 +62,8 @@ fn test_query_with_tool_use() {
                 input: serde_json::json!({"file_path": "/test.txt"}),
             }),
         ],
-        model: "claude-opus-4-1-20250805".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    };
+        "claude-opus-4-1-20250805",
+    );
 
     assert_eq!(assistant.content.len(), 2);
     assert_eq!(assistant.text(), "Let me read that file for you.");
@@ -110,21 +100,15 @@ fn test_options_with_max_budget() {
 #[test]
 fn test_budget_exceeded_result() {
     // Test parsing a budget exceeded result
-    let result = ResultMessage {
-        subtype: "error_max_budget_usd".to_string(),
-        duration_ms: 500,
-        duration_api_ms: 400,
-        is_error: false,
-        num_turns: 1,
-        session_id: "test-session-budget".to_string(),
-        total_cost_usd: Some(0.0002),
-        usage: Some(serde_json::json!({
-            "input_tokens": 100,
-            "output_tokens": 50,
-        })),
-        result: None,
-        structured_output: None,
-    };
+    let mut result = ResultMessage::new("error_max_budget_usd", "test-session-budget");
+    result.duration_ms = 500;
+    result.duration_api_ms = 400;
+    result.num_turns = 1;
+    result.total_cost_usd = Some(0.0002);
+    result.usage = Some(serde_json::json!({
+        "input_tokens": 100,
+        "output_tokens": 50,
+    }));
 
     assert_eq!(result.subtype, "error_max_budget_usd");
     assert!(!result.is_error); // Budget exceeded is not considered an error
@@ -235,8 +219,8 @@ fn test_tool_use_block_serialization() {
 
 #[test]
 fn test_assistant_message_multiple_content_blocks() {
-    let assistant = AssistantMessage {
-        content: vec![
+    let assistant = AssistantMessage::new(
+        vec![
             ContentBlock::Text(TextBlock {
                 text: "First ".to_string(),
             }),
@@ -244,10 +228,8 @@ fn test_assistant_message_multiple_content_blocks() {
                 text: "Second".to_string(),
             }),
         ],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    };
+        "claude-3",
+    );
 
     // text() should concatenate all text blocks
     assert_eq!(assistant.text(), "First Second");
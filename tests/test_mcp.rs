@@ -503,3 +503,100 @@ async fn test_tool_with_complex_output() {
     let result = (tool.handler)(json!({})).await;
     assert_eq!(result.content.len(), 3);
 }
+
+// ============================================================================
+// Tool Macro Tests
+// ============================================================================
+
+claude_agents_sdk::tool_input! {
+    /// Arguments of the search tool.
+    struct SearchArgs {
+        /// Text to look for
+        query: String,
+        /// Maximum number of hits
+        limit: Option<u32>,
+        tags: Vec<String>,
+    }
+}
+
+#[test]
+fn test_tool_macro_schema() {
+    let tool = claude_agents_sdk::tool! {
+        /// Scale a value.
+        ///
+        /// Multiplies by the factor.
+        fn scale(
+            /// Value to scale
+            value: f64,
+            factor: Option<i64>,
+            verbose: bool,
+        ) -> ToolResult {
+            let _ = verbose;
+            ToolResult::text(format!("{}", value * factor.unwrap_or(2) as f64))
+        }
+    };
+
+    assert_eq!(tool.name, "scale");
+    assert_eq!(
+        tool.description,
+        "Scale a value.\n\nMultiplies by the factor."
+    );
+    let schema = serde_json::to_value(&tool.input_schema).unwrap();
+    assert_eq!(
+        schema["properties"]["value"],
+        json!({"type": "number", "description": "Value to scale"})
+    );
+    assert_eq!(schema["properties"]["factor"], json!({"type": "integer"}));
+    assert_eq!(schema["properties"]["verbose"], json!({"type": "boolean"}));
+    assert_eq!(schema["required"], json!(["value", "verbose"]));
+}
+
+#[tokio::test]
+async fn test_tool_macro_handler() {
+    let tool = claude_agents_sdk::tool! {
+        async fn scale(value: f64, factor: Option<i64>) -> ToolResult {
+            ToolResult::text(format!("{}", value * factor.unwrap_or(2) as f64))
+        }
+    };
+
+    let result = (tool.handler)(json!({"value": 1.5})).await;
+    match &result.content[0] {
+        ToolContent::Text { text } => assert_eq!(text, "3"),
+        other => panic!("Expected text content, got {:?}", other),
+    }
+
+    let result = (tool.handler)(json!({"value": "big"})).await;
+    assert_eq!(result.is_error, Some(true));
+    match &result.content[0] {
+        ToolContent::Text { text } => assert!(text.contains("`value`"), "{}", text),
+        other => panic!("Expected text content, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_typed_tool() {
+    let tool = SdkMcpTool::typed("search", "Search", |args: SearchArgs| async move {
+        ToolResult::text(format!(
+            "{} {} {}",
+            args.query,
+            args.limit.unwrap_or(10),
+            args.tags.join(",")
+        ))
+    });
+
+    let schema = serde_json::to_value(&tool.input_schema).unwrap();
+    assert_eq!(
+        schema["properties"]["tags"],
+        json!({"type": "array", "items": {"type": "string"}})
+    );
+    assert_eq!(schema["required"], json!(["query", "tags"]));
+
+    let result = (tool.handler)(json!({"query": "rust", "tags": ["a", "b"]})).await;
+    match &result.content[0] {
+        ToolContent::Text { text } => assert_eq!(text, "rust 10 a,b"),
+        other => panic!("Expected text content, got {:?}", other),
+    }
+
+    let result = (tool.handler)(json!({"limit": 3})).await;
+    assert_eq!(result.is_error, Some(true));
+}
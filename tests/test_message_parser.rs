@@ -1,6 +1,6 @@
 //! Tests for message parsing.
 
-use claude_agents_sdk::_internal::message_parser::*;
+use claude_agents_sdk::wire::*;
 use claude_agents_sdk::*;
 use serde_json::json;
 
@@ -308,6 +308,26 @@ fn test_parse_tool_result_block() {
     }
 }
 
+#[test]
+fn test_parse_message_with_raw_keeps_unmodeled_fields() {
+    let raw = json!({
+        "type": "result",
+        "subtype": "success",
+        "session_id": "sess_1",
+        "permission_denials": [{"tool_name": "Bash"}]
+    });
+
+    let msg = parse_message_with_raw(raw.clone()).unwrap();
+    assert_eq!(msg.raw(), Some(&raw));
+    assert_eq!(
+        msg.raw().unwrap()["permission_denials"][0]["tool_name"],
+        "Bash"
+    );
+
+    // Plain parsing doesn't retain the raw JSON
+    assert!(parse_message(raw).unwrap().raw().is_none());
+}
+
 #[test]
 fn test_parse_unknown_message_type() {
     let raw = json!({
@@ -422,3 +442,32 @@ fn test_parse_control_response_error() {
     assert!(!response.is_success());
     assert_eq!(response.error(), Some("Tool not found"));
 }
+
+#[test]
+fn test_parse_line_classifies_frames() {
+    let frame = parse_line(
+        "{\"type\":\"result\",\"subtype\":\"success\",\"duration_ms\":1,\"duration_api_ms\":1,\"is_error\":false,\"num_turns\":1,\"session_id\":\"s\"}\r\n",
+    )
+    .unwrap();
+    assert!(matches!(frame, Frame::Message(Message::Result(_))));
+
+    let frame = parse_line(
+        r#"{"type":"control_response","response":{"subtype":"success","request_id":"req_1"}}"#,
+    )
+    .unwrap();
+    assert!(matches!(frame, Frame::ControlResponse(ref r) if r.request_id() == "req_1"));
+
+    let frame = parse_frame(json!({
+        "type": "control_request",
+        "request_id": "req_2",
+        "request": {"subtype": "can_use_tool", "tool_name": "Bash", "input": {}}
+    }))
+    .unwrap();
+    assert!(matches!(frame, Frame::ControlRequest(ref r) if r.request_id == "req_2"));
+
+    assert!(matches!(
+        parse_line("not json"),
+        Err(ClaudeSDKError::JSONDecode { .. })
+    ));
+    assert!(parse_line(r#"{"type":"telemetry"}"#).is_err());
+}
@@ -186,7 +186,7 @@ proptest! {
         let options = ClaudeAgentOptions::new()
             .with_timeout_secs(timeout);
 
-        prop_assert_eq!(options.timeout_secs, Some(timeout));
+        prop_assert_eq!(options.timeouts, Timeouts::from_secs(timeout));
     }
 
     /// ClaudeAgentOptions builder should accept any budget.
@@ -238,15 +238,14 @@ proptest! {
         content in arbitrary_safe_string(),
         is_error in proptest::bool::ANY
     ) {
-        let block = ContentBlock::ToolResult(ToolResultBlock {
-            tool_use_id: tool_use_id.clone(),
-            content: Some(serde_json::Value::String(content.clone())),
-            is_error: Some(is_error),
-        });
+        let mut block =
+            ToolResultBlock::new(tool_use_id.clone(), ToolResultContent::Text(content.clone()));
+        block.is_error = Some(is_error);
+        let block = ContentBlock::ToolResult(block);
 
         if let ContentBlock::ToolResult(tr) = block {
             prop_assert_eq!(&tr.tool_use_id, &tool_use_id);
-            prop_assert_eq!(tr.content, Some(serde_json::Value::String(content)));
+            prop_assert_eq!(tr.content, Some(ToolResultContent::Text(content)));
             prop_assert_eq!(tr.is_error, Some(is_error));
         }
     }
@@ -343,6 +342,6 @@ proptest! {
             .with_timeout_secs(timeout);
 
         prop_assert_eq!(opts1.max_turns, opts2.max_turns);
-        prop_assert_eq!(opts1.timeout_secs, opts2.timeout_secs);
+        prop_assert_eq!(opts1.timeouts, opts2.timeouts);
     }
 }
@@ -0,0 +1,487 @@
+//! Tests for the wire protocol: schema coverage and round-trips of captured
+//! CLI frames.
+
+use claude_agents_sdk::_internal::message_parser::*;
+use claude_agents_sdk::protocol::codec;
+use claude_agents_sdk::protocol::{self, UserInputMessage};
+use claude_agents_sdk::*;
+use serde_json::{json, Value};
+
+const CLI_SESSION: &str = include_str!("fixtures/protocol/cli_session.jsonl");
+
+fn fixture_frames() -> Vec<Value> {
+    CLI_SESSION
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).expect("fixture line is valid JSON"))
+        .collect()
+}
+
+/// Validate `value` against the subset of JSON Schema used by
+/// [`protocol::schema`]: `$ref`, `oneOf`, `anyOf`, `type`, `const`, `enum`,
+/// `required`, `properties` and `items`.
+fn validate(schema: &Value, root: &Value, value: &Value) -> bool {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let target = reference
+            .trim_start_matches("#/")
+            .split('/')
+            .fold(root, |node, key| &node[key]);
+        return validate(target, root, value);
+    }
+    if let Some(options) = schema.get("oneOf").and_then(Value::as_array) {
+        let matches = options.iter().filter(|s| validate(s, root, value)).count();
+        if matches != 1 {
+            return false;
+        }
+    }
+    if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+        if !options.iter().any(|s| validate(s, root, value)) {
+            return false;
+        }
+    }
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        let actual = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_u64() || n.is_i64() => "integer",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        let ok = types
+            .iter()
+            .any(|t| *t == actual || (*t == "number" && actual == "integer"));
+        if !ok {
+            return false;
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return false;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return false;
+        }
+    }
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        if required
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|key| value.get(key).is_none())
+        {
+            return false;
+        }
+    }
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        for (key, property) in properties {
+            if let Some(field) = value.get(key) {
+                if !validate(property, root, field) {
+                    return false;
+                }
+            }
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        if !array.iter().all(|item| validate(items, root, item)) {
+            return false;
+        }
+    }
+    true
+}
+
+fn conforms(def: &str, value: &Value) -> bool {
+    let schema = protocol::schema();
+    validate(&schema["$defs"][def], &schema, value)
+}
+
+/// Drop `null` object fields, which serde writes for `None` but the CLI
+/// usually omits.
+fn strip_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), strip_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(strip_nulls).collect()),
+        other => other.clone(),
+    }
+}
+
+#[test]
+fn test_schema_defines_every_frame() {
+    let schema = protocol::schema();
+    for def in [
+        "incoming",
+        "outgoing",
+        "user_message",
+        "assistant_message",
+        "system_message",
+        "result_message",
+        "stream_event",
+        "incoming_control_request",
+        "outgoing_control_request",
+        "control_response",
+        "user_input",
+    ] {
+        assert!(schema["$defs"][def].is_object(), "missing $defs/{}", def);
+    }
+}
+
+#[test]
+fn test_fixtures_conform_to_schema() {
+    for frame in fixture_frames() {
+        assert!(
+            conforms("incoming", &frame),
+            "frame not in schema: {}",
+            frame
+        );
+    }
+}
+
+#[test]
+fn test_schema_rejects_malformed_frames() {
+    assert!(!conforms("incoming", &json!({"type": "result"})));
+    assert!(!conforms("incoming", &json!({"type": "bogus"})));
+    assert!(!conforms(
+        "incoming",
+        &json!({"type": "control_request", "request_id": "r", "request": {"subtype": "can_use_tool"}})
+    ));
+}
+
+#[test]
+fn test_fixture_messages_parse() {
+    let mut parsed = 0;
+    for frame in fixture_frames() {
+        if is_control_request(&frame) || is_control_response(&frame) {
+            continue;
+        }
+        let message = parse_message_with_raw(frame.clone())
+            .unwrap_or_else(|e| panic!("failed to parse {}: {}", frame, e));
+        assert_eq!(message.raw(), Some(&frame));
+        parsed += 1;
+
+        match message {
+            Message::Assistant(ref assistant) => {
+                assert_eq!(assistant.model, "claude-sonnet-4-5-20250929");
+                assert!(!assistant.content.is_empty());
+            }
+            Message::User(ref user) => match user.content {
+                UserMessageContent::Blocks(ref blocks) => {
+                    assert!(matches!(blocks[0], ContentBlock::ToolResult(_)));
+                }
+                UserMessageContent::Text(_) => panic!("expected tool result blocks"),
+            },
+            Message::Result(ref result) => {
+                assert_eq!(result.num_turns, 2);
+                assert_eq!(result.total_cost_usd, Some(0.01234));
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(parsed, 6);
+}
+
+#[test]
+fn test_fixture_control_frames_round_trip() {
+    let mut round_tripped = 0;
+    for frame in fixture_frames() {
+        let encoded = if is_control_request(&frame) {
+            serde_json::to_value(parse_control_request(frame.clone()).unwrap()).unwrap()
+        } else if is_control_response(&frame) {
+            serde_json::to_value(parse_control_response(frame.clone()).unwrap()).unwrap()
+        } else {
+            continue;
+        };
+        assert_eq!(strip_nulls(&encoded), strip_nulls(&frame));
+        round_tripped += 1;
+    }
+    assert_eq!(round_tripped, 4);
+}
+
+#[test]
+fn test_outgoing_frames_conform_to_schema() {
+    let payloads = [
+        ControlRequestPayload::Interrupt,
+        ControlRequestPayload::Initialize { hooks: None },
+        ControlRequestPayload::SetPermissionMode {
+            mode: "acceptEdits".to_string(),
+        },
+        ControlRequestPayload::SetModel {
+            model: "claude-opus-4-1".to_string(),
+        },
+        ControlRequestPayload::SetMaxThinkingTokens {
+            max_thinking_tokens: Some(8_000),
+        },
+        ControlRequestPayload::SetMaxThinkingTokens {
+            max_thinking_tokens: None,
+        },
+        ControlRequestPayload::McpStatus,
+        ControlRequestPayload::McpToggle {
+            server_name: "github".to_string(),
+            enabled: false,
+        },
+        ControlRequestPayload::RewindFiles {
+            user_message_id: "3c4d5e6f".to_string(),
+        },
+    ];
+    for payload in payloads {
+        let frame = serde_json::to_value(ControlRequest::new("req_1", payload)).unwrap();
+        assert!(
+            conforms("outgoing", &frame),
+            "frame not in schema: {}",
+            frame
+        );
+    }
+
+    let frames = [
+        serde_json::to_value(UserInputMessage::text("Hello")).unwrap(),
+        serde_json::to_value(ControlResponse::success(
+            "req_1",
+            json!({"behavior": "allow"}),
+        ))
+        .unwrap(),
+        serde_json::to_value(ControlResponse::error_response("req_2", "denied")).unwrap(),
+    ];
+    for frame in frames {
+        assert!(
+            conforms("outgoing", &frame),
+            "frame not in schema: {}",
+            frame
+        );
+    }
+}
+
+/// Subtype of a control request, and whether the CLI sends it (incoming)
+/// and the SDK writes it (outgoing). Exhaustive, so a new payload has to be
+/// placed in the schema.
+fn control_direction(payload: &ControlRequestPayload) -> (&'static str, bool, bool) {
+    match payload {
+        ControlRequestPayload::Interrupt => ("interrupt", false, true),
+        ControlRequestPayload::CanUseTool { .. } => ("can_use_tool", true, false),
+        ControlRequestPayload::Initialize { .. } => ("initialize", true, true),
+        ControlRequestPayload::SetPermissionMode { .. } => ("set_permission_mode", false, true),
+        ControlRequestPayload::SetModel { .. } => ("set_model", false, true),
+        ControlRequestPayload::SetMaxThinkingTokens { .. } => {
+            ("set_max_thinking_tokens", false, true)
+        }
+        ControlRequestPayload::HookCallback { .. } => ("hook_callback", true, false),
+        ControlRequestPayload::McpMessage { .. } => ("mcp_message", true, false),
+        ControlRequestPayload::McpStatus => ("mcp_status", false, true),
+        ControlRequestPayload::McpToggle { .. } => ("mcp_toggle", false, true),
+        ControlRequestPayload::RewindFiles { .. } => ("rewind_files", false, true),
+    }
+}
+
+/// Name of a content block's type. Exhaustive, like [`control_direction`].
+fn block_type(block: &ContentBlock) -> &'static str {
+    match block {
+        ContentBlock::Text(_) => "text",
+        ContentBlock::Thinking(_) => "thinking",
+        ContentBlock::ToolUse(_) => "tool_use",
+        ContentBlock::ToolResult(_) => "tool_result",
+    }
+}
+
+#[test]
+fn test_schema_covers_every_frame_type() {
+    // A sample of every message type, with every content block type
+    let messages = [
+        json!({"type": "user", "message": {"role": "user", "content": "Hi"}}),
+        json!({
+            "type": "user",
+            "uuid": "u1",
+            "parent_tool_use_id": null,
+            "message": {"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "t1", "content": "ok", "is_error": false}
+            ]}
+        }),
+        json!({
+            "type": "assistant",
+            "parent_tool_use_id": null,
+            "message": {"model": "claude-sonnet-4-5", "content": [
+                {"type": "thinking", "thinking": "hmm", "signature": "sig"},
+                {"type": "text", "text": "Listing"},
+                {"type": "tool_use", "id": "t1", "name": "Bash", "input": {"command": "ls"}}
+            ]}
+        }),
+        json!({"type": "system", "subtype": "init", "session_id": "s", "tools": ["Bash"]}),
+        json!({
+            "type": "result",
+            "subtype": "success",
+            "duration_ms": 10,
+            "duration_api_ms": 8,
+            "is_error": false,
+            "num_turns": 1,
+            "session_id": "s",
+            "total_cost_usd": 0.01,
+            "usage": {"input_tokens": 1},
+            "result": "Done"
+        }),
+        json!({
+            "type": "stream_event",
+            "uuid": "e1",
+            "session_id": "s",
+            "event": {"type": "message_start"},
+            "parent_tool_use_id": null
+        }),
+    ];
+    let mut message_types = Vec::new();
+    let mut block_types = Vec::new();
+    for frame in &messages {
+        assert!(
+            conforms("incoming", frame),
+            "frame not in schema: {}",
+            frame
+        );
+        let message = parse_message(frame.clone()).unwrap();
+        let blocks = match message {
+            Message::User(UserMessage {
+                content: UserMessageContent::Blocks(ref blocks),
+                ..
+            }) => blocks.as_slice(),
+            Message::Assistant(ref assistant) => assistant.content.as_slice(),
+            _ => &[],
+        };
+        block_types.extend(blocks.iter().map(block_type));
+        message_types.push(frame["type"].as_str().unwrap());
+    }
+    for message_type in protocol::wire::MESSAGE_TYPES {
+        assert!(
+            message_types.contains(&message_type),
+            "no sample of {}",
+            message_type
+        );
+    }
+    for block in ["text", "thinking", "tool_use", "tool_result"] {
+        assert!(
+            block_types.contains(&block),
+            "no sample of {} blocks",
+            block
+        );
+    }
+
+    // A sample of every control request, checked in each direction it goes
+    let payloads = [
+        ControlRequestPayload::Interrupt,
+        ControlRequestPayload::CanUseTool {
+            tool_name: "Bash".to_string(),
+            input: json!({"command": "ls"}),
+            permission_suggestions: Some(vec![json!({"type": "addRules"})]),
+            blocked_path: None,
+            tool_use_id: Some("t1".to_string()),
+        },
+        ControlRequestPayload::Initialize {
+            hooks: Some(json!({"PreToolUse": []})),
+        },
+        ControlRequestPayload::SetPermissionMode {
+            mode: "plan".to_string(),
+        },
+        ControlRequestPayload::SetModel {
+            model: "claude-sonnet-4-5".to_string(),
+        },
+        ControlRequestPayload::SetMaxThinkingTokens {
+            max_thinking_tokens: Some(1024),
+        },
+        ControlRequestPayload::HookCallback {
+            callback_id: "hook_0".to_string(),
+            input: json!({"hook_event_name": "PreToolUse"}),
+            tool_use_id: Some("t1".to_string()),
+        },
+        ControlRequestPayload::McpMessage {
+            server_name: "calc".to_string(),
+            message: json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}),
+        },
+        ControlRequestPayload::McpStatus,
+        ControlRequestPayload::McpToggle {
+            server_name: "calc".to_string(),
+            enabled: true,
+        },
+        ControlRequestPayload::RewindFiles {
+            user_message_id: "u1".to_string(),
+        },
+    ];
+    let mut sampled = (Vec::new(), Vec::new());
+    for payload in payloads {
+        let (subtype, incoming, outgoing) = control_direction(&payload);
+        let frame = serde_json::to_value(ControlRequest::new("req_1", payload)).unwrap();
+        assert_eq!(frame["request"]["subtype"], subtype);
+        let frame = strip_nulls(&frame);
+        if incoming {
+            assert!(
+                conforms("incoming", &frame),
+                "frame not in schema: {}",
+                frame
+            );
+            sampled.0.push(subtype);
+        }
+        if outgoing {
+            assert!(
+                conforms("outgoing", &frame),
+                "frame not in schema: {}",
+                frame
+            );
+            sampled.1.push(subtype);
+        }
+    }
+    // ...and the schema has no control requests the SDK doesn't model
+    let schema = protocol::schema();
+    let subtypes = |def: &str| {
+        let mut subtypes: Vec<&str> = schema["$defs"][def]["properties"]["request"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["properties"]["subtype"]["const"].as_str().unwrap())
+            .collect();
+        subtypes.sort_unstable();
+        subtypes
+    };
+    sampled.0.sort_unstable();
+    sampled.1.sort_unstable();
+    assert_eq!(sampled.0, subtypes("incoming_control_request"));
+    assert_eq!(sampled.1, subtypes("outgoing_control_request"));
+
+    // Control responses go both ways
+    for frame in [
+        serde_json::to_value(ControlResponse::success("req_1", json!({}))).unwrap(),
+        serde_json::to_value(ControlResponse::error_response("req_1", "failed")).unwrap(),
+    ] {
+        assert!(
+            conforms("incoming", &frame),
+            "frame not in schema: {}",
+            frame
+        );
+        assert!(
+            conforms("outgoing", &frame),
+            "frame not in schema: {}",
+            frame
+        );
+    }
+}
+
+#[test]
+fn test_codecs_round_trip_fixture_frames() {
+    for name in codec::supported() {
+        let codec = codec::by_name(name).unwrap();
+        for frame in fixture_frames() {
+            let encoded = codec.encode(&frame).unwrap();
+            assert_eq!(codec.decode(&encoded).unwrap(), frame, "codec {}", name);
+        }
+    }
+
+    let line = serde_json::to_string(&UserInputMessage::text("Hello")).unwrap();
+    let codec = codec::negotiate(&["brotli", "json"]);
+    assert_eq!(codec.name(), "json");
+    assert_eq!(codec.encode_line(&line).unwrap(), line.as_bytes());
+    assert!(codec.encode_line("not json").is_err());
+    assert!(codec::by_name("brotli").is_none());
+}
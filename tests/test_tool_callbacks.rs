@@ -82,9 +82,7 @@ fn test_permission_result_deny_with_interrupt() {
 
 #[test]
 fn test_tool_permission_context() {
-    let context = ToolPermissionContext {
-        suggestions: vec![],
-    };
+    let context = ToolPermissionContext::new(vec![]);
 
     assert!(context.suggestions.is_empty());
 }
@@ -93,16 +91,14 @@ fn test_tool_permission_context() {
 fn test_tool_permission_context_with_suggestions() {
     use claude_agents_sdk::PermissionUpdateType;
 
-    let context = ToolPermissionContext {
-        suggestions: vec![PermissionUpdate {
-            update_type: PermissionUpdateType::AddRules,
-            rules: None,
-            behavior: None,
-            mode: None,
-            directories: None,
-            destination: None,
-        }],
-    };
+    let context = ToolPermissionContext::new(vec![PermissionUpdate {
+        update_type: PermissionUpdateType::AddRules,
+        rules: None,
+        behavior: None,
+        mode: None,
+        directories: None,
+        destination: None,
+    }]);
 
     assert_eq!(context.suggestions.len(), 1);
 }
@@ -113,11 +109,9 @@ fn test_hook_matcher_creation() {
         Box::pin(async move { HookOutput::Sync(SyncHookOutput::default()) })
     });
 
-    let matcher = HookMatcher {
-        matcher: Some("Bash".to_string()),
-        hooks: vec![callback],
-        timeout: Some(30.0),
-    };
+    let matcher = HookMatcher::new(vec![callback])
+        .with_matcher("Bash")
+        .with_timeout(30.0);
 
     assert_eq!(matcher.matcher, Some("Bash".to_string()));
     assert_eq!(matcher.hooks.len(), 1);
@@ -130,11 +124,7 @@ fn test_hook_matcher_without_matcher() {
         Box::pin(async move { HookOutput::Sync(SyncHookOutput::default()) })
     });
 
-    let matcher = HookMatcher {
-        matcher: None, // Match all tools
-        hooks: vec![callback],
-        timeout: None,
-    };
+    let matcher = HookMatcher::new(vec![callback]);
 
     assert!(matcher.matcher.is_none());
     assert_eq!(matcher.hooks.len(), 1);
@@ -210,11 +200,7 @@ fn test_options_with_hooks() {
     let mut hooks: HashMap<HookEvent, Vec<HookMatcher>> = HashMap::new();
     hooks.insert(
         HookEvent::PreToolUse,
-        vec![HookMatcher {
-            matcher: Some("Bash".to_string()),
-            hooks: vec![callback],
-            timeout: None,
-        }],
+        vec![HookMatcher::new(vec![callback]).with_matcher("Bash")],
     );
 
     let mut options = ClaudeAgentOptions::new();
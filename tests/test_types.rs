@@ -89,13 +89,28 @@ fn test_permission_result_deny_with_message_includes_message() {
     assert_eq!(json["message"], "Operation not allowed");
 }
 
+#[test]
+fn test_permission_result_deny_with_suggestion_embeds_guidance() {
+    let result = PermissionResult::deny_with_suggestion("Read", "Shell access is disabled.");
+    let json = serde_json::to_value(&result).unwrap();
+
+    assert_eq!(json["behavior"], "deny");
+    assert!(json.get("suggestion").is_none());
+    let message = json["message"].as_str().unwrap();
+    let guidance = message
+        .split_once("<denial_guidance>")
+        .and_then(|(_, rest)| rest.strip_suffix("</denial_guidance>"))
+        .unwrap();
+    let suggestion: DenialSuggestion = serde_json::from_str(guidance).unwrap();
+    assert_eq!(suggestion.alternative_tool, "Read");
+    assert_eq!(suggestion.reason, "Shell access is disabled.");
+}
+
 #[test]
 fn test_permission_result_allow_with_updated_input() {
-    let result = PermissionResult::Allow(PermissionResultAllow {
-        behavior: "allow".to_string(),
-        updated_input: Some(json!({"modified": true, "extra_field": "added"})),
-        updated_permissions: None,
-    });
+    let result = PermissionResult::Allow(PermissionResultAllow::with_updated_input(
+        json!({"modified": true, "extra_field": "added"}),
+    ));
     let json = serde_json::to_value(&result).unwrap();
 
     assert_eq!(json["behavior"], "allow");
@@ -103,6 +118,78 @@ fn test_permission_result_allow_with_updated_input() {
     assert_eq!(json["updatedInput"]["extra_field"], "added");
 }
 
+#[test]
+fn test_permission_update_helpers_serialize_for_cli() {
+    let update = PermissionUpdate::allow_tool("Bash", Some("ls:*")).session();
+    assert_eq!(
+        serde_json::to_value(&update).unwrap(),
+        json!({
+            "type": "addRules",
+            "rules": [{"toolName": "Bash", "ruleContent": "ls:*"}],
+            "behavior": "allow",
+            "destination": "session"
+        })
+    );
+
+    let update = PermissionUpdate::deny_tool("WebFetch", None)
+        .with_rule("WebSearch", None)
+        .user_settings();
+    assert_eq!(
+        serde_json::to_value(&update).unwrap(),
+        json!({
+            "type": "addRules",
+            "rules": [{"toolName": "WebFetch"}, {"toolName": "WebSearch"}],
+            "behavior": "deny",
+            "destination": "userSettings"
+        })
+    );
+
+    let update = PermissionUpdate::deny_directory("/srv/secrets/").project_settings();
+    assert_eq!(
+        serde_json::to_value(&update).unwrap(),
+        json!({
+            "type": "addRules",
+            "rules": [
+                {"toolName": "Read", "ruleContent": "//srv/secrets/**"},
+                {"toolName": "Edit", "ruleContent": "//srv/secrets/**"}
+            ],
+            "behavior": "deny",
+            "destination": "projectSettings"
+        })
+    );
+
+    let update = PermissionUpdate::add_directory("/data")
+        .with_directory("/cache")
+        .local_settings();
+    assert_eq!(
+        serde_json::to_value(&update).unwrap(),
+        json!({
+            "type": "addDirectories",
+            "directories": ["/data", "/cache"],
+            "destination": "localSettings"
+        })
+    );
+
+    let update = PermissionUpdate::set_mode(PermissionMode::AcceptEdits).session();
+    assert_eq!(
+        serde_json::to_value(&update).unwrap(),
+        json!({"type": "setMode", "mode": "acceptEdits", "destination": "session"})
+    );
+
+    let update = PermissionUpdate::remove_rules(
+        PermissionBehavior::Ask,
+        vec![PermissionRuleValue::new("Bash").with_content("rm:*")],
+    );
+    assert_eq!(
+        serde_json::to_value(&update).unwrap(),
+        json!({
+            "type": "removeRules",
+            "rules": [{"toolName": "Bash", "ruleContent": "rm:*"}],
+            "behavior": "ask"
+        })
+    );
+}
+
 // ============================================================================
 // Content Block Tests
 // ============================================================================
@@ -141,21 +228,53 @@ fn test_tool_use_block_is_tool_use_returns_true() {
 
 #[test]
 fn test_tool_result_block_fields() {
-    let block = ContentBlock::ToolResult(ToolResultBlock {
-        tool_use_id: "tool_123".to_string(),
-        content: Some(json!("Command output here")),
-        is_error: Some(false),
-    });
+    let mut result = ToolResultBlock::new("tool_123", "Command output here");
+    result.is_error = Some(false);
+    let block = ContentBlock::ToolResult(result);
 
     if let ContentBlock::ToolResult(result) = block {
         assert_eq!(result.tool_use_id, "tool_123");
-        assert_eq!(result.content, Some(json!("Command output here")));
+        assert_eq!(
+            result.content.unwrap().as_text().as_deref(),
+            Some("Command output here")
+        );
         assert_eq!(result.is_error, Some(false));
     } else {
         panic!("Expected ToolResult block");
     }
 }
 
+#[test]
+fn test_tool_result_content_shapes() {
+    let blocks = json!([
+        {"type": "text", "text": "Screenshot taken"},
+        {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}},
+        {"type": "text", "text": "1280x720"},
+        {"type": "tool_reference", "tool_name": "Bash"}
+    ]);
+    let content = ToolResultContent::from_value(blocks.clone());
+    assert_eq!(
+        content.as_text().as_deref(),
+        Some("Screenshot taken\n1280x720")
+    );
+    let image = content.blocks()[1].as_image().unwrap();
+    assert_eq!(image.media_type.as_deref(), Some("image/png"));
+    assert!(matches!(
+        content.blocks()[3],
+        ToolResultContentBlock::Other(_)
+    ));
+    // Written back as read
+    assert_eq!(serde_json::to_value(&content).unwrap(), blocks);
+
+    let json = ToolResultContent::from(json!({"output": "success"}));
+    assert_eq!(json.as_json(), Some(&json!({"output": "success"})));
+    assert!(json.as_text().is_none());
+    assert!(json.blocks().is_empty());
+
+    let text: ToolResultContent = serde_json::from_value(json!("done")).unwrap();
+    assert_eq!(text, ToolResultContent::Text("done".to_string()));
+}
+
 #[test]
 fn test_thinking_block_fields() {
     let block = ContentBlock::Thinking(ThinkingBlock {
@@ -177,11 +296,7 @@ fn test_thinking_block_fields() {
 
 #[test]
 fn test_user_message_text_content_returns_text() {
-    let msg = UserMessage {
-        content: UserMessageContent::Text("Hello from user".to_string()),
-        uuid: None,
-        parent_tool_use_id: None,
-    };
+    let msg = UserMessage::new(UserMessageContent::Text("Hello from user".to_string()));
 
     assert_eq!(
         msg.text(),
@@ -192,18 +307,14 @@ fn test_user_message_text_content_returns_text() {
 
 #[test]
 fn test_user_message_blocks_content() {
-    let msg = UserMessage {
-        content: UserMessageContent::Blocks(vec![
-            ContentBlock::Text(TextBlock {
-                text: "First block".to_string(),
-            }),
-            ContentBlock::Text(TextBlock {
-                text: "Second block".to_string(),
-            }),
-        ]),
-        uuid: None,
-        parent_tool_use_id: None,
-    };
+    let msg = UserMessage::new(UserMessageContent::Blocks(vec![
+        ContentBlock::Text(TextBlock {
+            text: "First block".to_string(),
+        }),
+        ContentBlock::Text(TextBlock {
+            text: "Second block".to_string(),
+        }),
+    ]));
 
     // text() returns None for block content
     assert!(
@@ -220,11 +331,8 @@ fn test_user_message_blocks_content() {
 
 #[test]
 fn test_user_message_with_uuid() {
-    let msg = UserMessage {
-        content: UserMessageContent::Text("Test".to_string()),
-        uuid: Some("unique-id-12345".to_string()),
-        parent_tool_use_id: None,
-    };
+    let mut msg = UserMessage::new(UserMessageContent::Text("Test".to_string()));
+    msg.uuid = Some("unique-id-12345".to_string());
 
     assert_eq!(msg.uuid, Some("unique-id-12345".to_string()));
 }
@@ -235,8 +343,8 @@ fn test_user_message_with_uuid() {
 
 #[test]
 fn test_assistant_message_text_concatenates_all_text_blocks() {
-    let msg = AssistantMessage {
-        content: vec![
+    let msg = AssistantMessage::new(
+        vec![
             ContentBlock::Text(TextBlock {
                 text: "Hello ".to_string(),
             }),
@@ -247,10 +355,8 @@ fn test_assistant_message_text_concatenates_all_text_blocks() {
                 text: "!".to_string(),
             }),
         ],
-        model: "claude-3-sonnet".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    };
+        "claude-3-sonnet",
+    );
 
     assert_eq!(
         msg.text(),
@@ -261,8 +367,8 @@ fn test_assistant_message_text_concatenates_all_text_blocks() {
 
 #[test]
 fn test_assistant_message_text_skips_non_text_blocks() {
-    let msg = AssistantMessage {
-        content: vec![
+    let msg = AssistantMessage::new(
+        vec![
             ContentBlock::Text(TextBlock {
                 text: "Let me help: ".to_string(),
             }),
@@ -275,10 +381,8 @@ fn test_assistant_message_text_skips_non_text_blocks() {
                 text: "Done.".to_string(),
             }),
         ],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    };
+        "claude-3",
+    );
 
     assert_eq!(
         msg.text(),
@@ -289,8 +393,8 @@ fn test_assistant_message_text_skips_non_text_blocks() {
 
 #[test]
 fn test_assistant_message_tool_uses_extracts_all_tool_uses() {
-    let msg = AssistantMessage {
-        content: vec![
+    let msg = AssistantMessage::new(
+        vec![
             ContentBlock::Text(TextBlock {
                 text: "Running commands...".to_string(),
             }),
@@ -305,10 +409,8 @@ fn test_assistant_message_tool_uses_extracts_all_tool_uses() {
                 input: json!({"path": "/tmp/file.txt"}),
             }),
         ],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    };
+        "claude-3",
+    );
 
     let tool_uses = msg.tool_uses();
     assert_eq!(tool_uses.len(), 2, "Should extract both tool uses");
@@ -318,14 +420,12 @@ fn test_assistant_message_tool_uses_extracts_all_tool_uses() {
 
 #[test]
 fn test_assistant_message_tool_uses_returns_empty_when_no_tools() {
-    let msg = AssistantMessage {
-        content: vec![ContentBlock::Text(TextBlock {
+    let msg = AssistantMessage::new(
+        vec![ContentBlock::Text(TextBlock {
             text: "Just text".to_string(),
         })],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    };
+        "claude-3",
+    );
 
     assert!(
         msg.tool_uses().is_empty(),
@@ -333,27 +433,47 @@ fn test_assistant_message_tool_uses_returns_empty_when_no_tools() {
     );
 }
 
+#[test]
+fn test_assistant_message_code_blocks_reads_each_text_block() {
+    let msg = AssistantMessage::new(
+        vec![
+            ContentBlock::Text(TextBlock {
+                text: "Here is the script:".to_string(),
+            }),
+            ContentBlock::Text(TextBlock {
+                text: "```bash\nset -e\ncargo test\n```".to_string(),
+            }),
+            ContentBlock::ToolUse(ToolUseBlock {
+                id: "tool_1".to_string(),
+                name: "Write".to_string(),
+                input: json!({"content": "```python\nprint(1)\n```"}),
+            }),
+        ],
+        "claude-3",
+    );
+
+    let blocks = msg.code_blocks();
+    assert_eq!(blocks.len(), 1, "tool inputs aren't response text");
+    assert!(blocks[0].is_language("Bash"));
+    assert_eq!(blocks[0].code, "set -e\ncargo test\n");
+}
+
 // ============================================================================
 // Result Message Tests
 // ============================================================================
 
 #[test]
 fn test_result_message_success() {
-    let result = ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 1500,
-        duration_api_ms: 1200,
-        is_error: false,
-        num_turns: 3,
-        session_id: "sess_abc123".to_string(),
-        total_cost_usd: Some(0.0042),
-        usage: Some(json!({
-            "input_tokens": 150,
-            "output_tokens": 75
-        })),
-        result: Some("Task completed successfully".to_string()),
-        structured_output: None,
-    };
+    let mut result = ResultMessage::new("success", "sess_abc123");
+    result.duration_ms = 1500;
+    result.duration_api_ms = 1200;
+    result.num_turns = 3;
+    result.total_cost_usd = Some(0.0042);
+    result.usage = Some(json!({
+        "input_tokens": 150,
+        "output_tokens": 75
+    }));
+    result.result = Some("Task completed successfully".to_string());
 
     assert_eq!(result.subtype, "success");
     assert!(!result.is_error);
@@ -361,39 +481,94 @@ fn test_result_message_success() {
     assert_eq!(result.total_cost_usd, Some(0.0042));
 }
 
+#[test]
+fn test_result_message_extract_code() {
+    let mut result = ResultMessage::new("success", "sess_abc123");
+    result.duration_ms = 1500;
+    result.duration_api_ms = 1200;
+    result.num_turns = 1;
+    result.result =
+        Some("Run:\n```sh\n./build.sh\n```\nThen:\n```python\nprint(1)\n```\n".to_string());
+
+    assert_eq!(result.extract_code(Some("python")), vec!["print(1)\n"]);
+    assert_eq!(
+        result.extract_code(None),
+        vec!["./build.sh\n", "print(1)\n"]
+    );
+
+    result.result = None;
+    assert!(result.extract_code(None).is_empty());
+}
+
 #[test]
 fn test_result_message_error() {
-    let result = ResultMessage {
-        subtype: "error".to_string(),
-        duration_ms: 500,
-        duration_api_ms: 400,
-        is_error: true,
-        num_turns: 1,
-        session_id: "sess_xyz789".to_string(),
-        total_cost_usd: Some(0.001),
-        usage: None,
-        result: Some("API rate limit exceeded".to_string()),
-        structured_output: None,
-    };
+    let mut result = ResultMessage::new("error", "sess_xyz789");
+    result.duration_ms = 500;
+    result.duration_api_ms = 400;
+    result.is_error = true;
+    result.num_turns = 1;
+    result.total_cost_usd = Some(0.001);
+    result.result = Some("API rate limit exceeded".to_string());
 
     assert!(result.is_error);
     assert_eq!(result.subtype, "error");
 }
 
+fn result_with_subtype(subtype: &str, is_error: bool) -> ResultMessage {
+    let mut result = ResultMessage::new(subtype, "sess");
+    result.duration_ms = 100;
+    result.duration_api_ms = 80;
+    result.is_error = is_error;
+    result.num_turns = 1;
+    result
+}
+
+#[test]
+fn test_result_completion_reason_mapping() {
+    assert_eq!(
+        result_with_subtype("success", false).completion_reason(),
+        CompletionReason::Completed
+    );
+    assert_eq!(
+        result_with_subtype("error_max_turns", true).completion_reason(),
+        CompletionReason::MaxTurnsReached
+    );
+    assert_eq!(
+        result_with_subtype("error_max_budget_usd", true).completion_reason(),
+        CompletionReason::BudgetExceeded
+    );
+    assert_eq!(
+        result_with_subtype("interrupted", true).completion_reason(),
+        CompletionReason::Interrupted
+    );
+    assert_eq!(
+        result_with_subtype("error_during_execution", true).completion_reason(),
+        CompletionReason::Error("error_during_execution".to_string())
+    );
+}
+
+#[test]
+fn test_result_completion_reason_success_with_error_flag() {
+    // A "success" subtype flagged as an error is not a normal completion
+    let reason = result_with_subtype("success", true).completion_reason();
+    assert_eq!(reason, CompletionReason::Error("success".to_string()));
+    assert!(!reason.is_limit());
+    assert!(CompletionReason::MaxTurnsReached.is_limit());
+    assert!(CompletionReason::BudgetExceeded.is_limit());
+}
+
 // ============================================================================
 // Message Type Tests
 // ============================================================================
 
 #[test]
 fn test_message_is_assistant_discriminates_correctly() {
-    let assistant = Message::Assistant(AssistantMessage {
-        content: vec![ContentBlock::Text(TextBlock {
+    let assistant = Message::Assistant(AssistantMessage::new(
+        vec![ContentBlock::Text(TextBlock {
             text: "Hi".to_string(),
         })],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    });
+        "claude-3",
+    ));
 
     assert!(assistant.is_assistant());
     assert!(!assistant.is_result());
@@ -401,18 +576,11 @@ fn test_message_is_assistant_discriminates_correctly() {
 
 #[test]
 fn test_message_is_result_discriminates_correctly() {
-    let result = Message::Result(ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 100,
-        duration_api_ms: 80,
-        is_error: false,
-        num_turns: 1,
-        session_id: "test".to_string(),
-        total_cost_usd: None,
-        usage: None,
-        result: None,
-        structured_output: None,
-    });
+    let mut result = ResultMessage::new("success", "test");
+    result.duration_ms = 100;
+    result.duration_api_ms = 80;
+    result.num_turns = 1;
+    let result = Message::Result(result);
 
     assert!(result.is_result());
     assert!(!result.is_assistant());
@@ -420,14 +588,12 @@ fn test_message_is_result_discriminates_correctly() {
 
 #[test]
 fn test_message_as_assistant_returns_reference() {
-    let msg = Message::Assistant(AssistantMessage {
-        content: vec![ContentBlock::Text(TextBlock {
+    let msg = Message::Assistant(AssistantMessage::new(
+        vec![ContentBlock::Text(TextBlock {
             text: "Hello".to_string(),
         })],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    });
+        "claude-3",
+    ));
 
     let asst = msg
         .as_assistant()
@@ -437,18 +603,12 @@ fn test_message_as_assistant_returns_reference() {
 
 #[test]
 fn test_message_as_result_returns_reference() {
-    let msg = Message::Result(ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 100,
-        duration_api_ms: 80,
-        is_error: false,
-        num_turns: 1,
-        session_id: "test-session".to_string(),
-        total_cost_usd: Some(0.005),
-        usage: None,
-        result: None,
-        structured_output: None,
-    });
+    let mut result = ResultMessage::new("success", "test-session");
+    result.duration_ms = 100;
+    result.duration_api_ms = 80;
+    result.num_turns = 1;
+    result.total_cost_usd = Some(0.005);
+    let msg = Message::Result(result);
 
     let result = msg.as_result().expect("Should return Some for Result");
     assert_eq!(result.session_id, "test-session");
@@ -480,6 +640,30 @@ fn test_hook_event_roundtrip() {
     }
 }
 
+#[test]
+fn test_hook_input_roundtrip() {
+    let raw = json!({
+        "session_id": "s",
+        "transcript_path": "/tmp/t.jsonl",
+        "cwd": "/tmp",
+        "hook_event_name": "PreToolUse",
+        "tool_name": "Bash",
+        "tool_input": {"command": "ls"}
+    });
+    let input: HookInput = serde_json::from_value(raw.clone()).unwrap();
+    match &input {
+        HookInput::PreToolUse(input) => {
+            assert_eq!(input.hook_event_name, "PreToolUse");
+            assert_eq!(input.tool_name, "Bash");
+        }
+        other => panic!("unexpected input: {:?}", other),
+    }
+    assert_eq!(serde_json::to_value(&input).unwrap(), raw);
+
+    let unknown = json!({"hook_event_name": "Notification", "message": "hi"});
+    assert!(serde_json::from_value::<HookInput>(unknown).is_err());
+}
+
 // ============================================================================
 // Sync Hook Output Tests
 // ============================================================================
@@ -669,12 +853,7 @@ fn test_setting_source_serialization() {
 
 #[test]
 fn test_empty_assistant_message_returns_empty_string() {
-    let msg = AssistantMessage {
-        content: vec![],
-        model: "claude-3".to_string(),
-        parent_tool_use_id: None,
-        error: None,
-    };
+    let msg = AssistantMessage::new(vec![], "claude-3");
 
     assert_eq!(msg.text(), "", "Empty content should return empty string");
     assert!(msg.tool_uses().is_empty());
@@ -682,18 +861,8 @@ fn test_empty_assistant_message_returns_empty_string() {
 
 #[test]
 fn test_result_message_with_zero_duration() {
-    let result = ResultMessage {
-        subtype: "success".to_string(),
-        duration_ms: 0,
-        duration_api_ms: 0,
-        is_error: false,
-        num_turns: 0,
-        session_id: "test".to_string(),
-        total_cost_usd: Some(0.0),
-        usage: None,
-        result: None,
-        structured_output: None,
-    };
+    let mut result = ResultMessage::new("success", "test");
+    result.total_cost_usd = Some(0.0);
 
     assert_eq!(result.duration_ms, 0);
     assert_eq!(result.num_turns, 0);
@@ -727,11 +896,7 @@ fn test_tool_use_block_with_complex_input() {
 
 #[test]
 fn test_user_message_empty_text() {
-    let msg = UserMessage {
-        content: UserMessageContent::Text("".to_string()),
-        uuid: None,
-        parent_tool_use_id: None,
-    };
+    let msg = UserMessage::new(UserMessageContent::Text("".to_string()));
 
     assert_eq!(msg.text(), Some(""), "Empty text should return Some(\"\")");
 }
@@ -815,3 +980,58 @@ fn test_sync_hook_output_roundtrip() {
     assert_eq!(deserialized.decision, original.decision);
     assert_eq!(deserialized.reason, original.reason);
 }
+
+#[test]
+fn test_python_sdk_field_aliases() {
+    let output: SyncHookOutput = serde_json::from_value(json!({
+        "continue_": false,
+        "stopReason": "Blocked by policy"
+    }))
+    .unwrap();
+    assert_eq!(output.continue_, Some(false));
+    assert_eq!(
+        serde_json::to_value(&output).unwrap()["continue"],
+        json!(false)
+    );
+
+    let output: AsyncHookOutput =
+        serde_json::from_value(json!({"async_": true, "async_timeout": 5000})).unwrap();
+    assert!(output.async_);
+    assert_eq!(output.async_timeout, Some(5000));
+
+    let allow: PermissionResultAllow = serde_json::from_value(json!({
+        "behavior": "allow",
+        "updated_input": {"command": "ls"},
+        "updated_permissions": [{
+            "type": "addRules",
+            "rules": [{"tool_name": "Bash", "rule_content": "ls"}]
+        }]
+    }))
+    .unwrap();
+    assert_eq!(allow.updated_input, Some(json!({"command": "ls"})));
+    let rules = allow.updated_permissions.unwrap()[0].rules.clone().unwrap();
+    assert_eq!(rules[0].tool_name, "Bash");
+    assert_eq!(rules[0].rule_content.as_deref(), Some("ls"));
+
+    let client: ClaudeSDKClient = ClaudeClient::new(None);
+    assert!(!client.is_connected());
+}
+
+#[test]
+fn test_slash_command_catalog_and_invocation() {
+    let commands: Vec<SlashCommand> = serde_json::from_value(json!([
+        {"name": "compact", "description": "Clear history but keep a summary", "argumentHint": "<instructions>"},
+        {"name": "cost", "description": "Show session cost"}
+    ]))
+    .unwrap();
+    assert_eq!(commands[0].args, "<instructions>");
+    assert_eq!(commands[1].args, "");
+
+    assert_eq!(SlashCommand::invocation("/cost", "").unwrap(), "/cost");
+    assert_eq!(
+        SlashCommand::invocation("compact", "  keep notes ").unwrap(),
+        "/compact keep notes"
+    );
+    assert!(SlashCommand::invocation("/", "").is_err());
+    assert!(SlashCommand::invocation("/com pact", "").is_err());
+}